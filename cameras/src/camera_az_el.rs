@@ -5,6 +5,11 @@ use bevy::{
 };
 use std::f32::consts::PI;
 
+use crate::{
+    chase::ChaseCamera, fly::FlyCamera, shake::CameraShake, top_down::TopDownCamera,
+    viewport::CameraViewport,
+};
+
 #[derive(Resource)]
 pub struct PointerOverUi(bool);
 
@@ -41,6 +46,16 @@ pub enum UpDirection {
     Z,
 }
 
+impl UpDirection {
+    pub fn vector(&self) -> Vec3 {
+        match self {
+            UpDirection::X => Vec3::X,
+            UpDirection::Y => Vec3::Y,
+            UpDirection::Z => Vec3::Z,
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct AzElCamera {
     pub focus: Vec3,
@@ -62,12 +77,21 @@ impl Default for AzElCamera {
     }
 }
 
+type OrbitCamera<'a> = (
+    &'a mut AzElCamera,
+    &'a mut Transform,
+    &'a Projection,
+    Option<&'a CameraViewport>,
+    Option<&'a FlyCamera>,
+    Option<&'a TopDownCamera>,
+);
+
 pub fn az_el_camera(
     windows: Query<&mut Window>,
     mut cursor_moved: EventReader<CursorMoved>,
     mut ev_scroll: EventReader<MouseWheel>,
     input_mouse: Res<Input<MouseButton>>,
-    mut query: Query<(&mut AzElCamera, &mut Transform, &Projection)>,
+    mut query: Query<OrbitCamera>,
     pointer_over_ui: Res<PointerOverUi>,
     mut last_position: Local<Vec2>,
 ) {
@@ -105,7 +129,19 @@ pub fn az_el_camera(
     }
 
     // update cameras
-    for (mut az_el, mut transform, projection) in query.iter_mut() {
+    for (mut az_el, mut transform, projection, viewport, fly, top_down) in query.iter_mut() {
+        // the fly camera and the top-down view have their own controls
+        if matches!(fly, Some(fly) if fly.active)
+            || matches!(top_down, Some(top_down) if top_down.active)
+        {
+            continue;
+        }
+        // in split screen only the view under the cursor moves
+        if let Some(viewport) = viewport {
+            if !viewport.contains(current_position, get_primary_window_size(&windows)) {
+                continue;
+            }
+        }
         let mut any_changes = false; // has anything changed?
 
         if rotation_move.length_squared() > 0.0 {
@@ -151,7 +187,7 @@ pub fn az_el_camera(
     }
 }
 
-fn az_el_rotation(az: f32, el: f32, up_direction: &UpDirection) -> Quat {
+pub fn az_el_rotation(az: f32, el: f32, up_direction: &UpDirection) -> Quat {
     match up_direction {
         UpDirection::X => {
             let yaw = Quat::from_rotation_x(az + PI);
@@ -171,7 +207,7 @@ fn az_el_rotation(az: f32, el: f32, up_direction: &UpDirection) -> Quat {
     }
 }
 
-fn az_el_translation(focus: Vec3, rotation: Quat, radius: f32) -> Vec3 {
+pub fn az_el_translation(focus: Vec3, rotation: Quat, radius: f32) -> Vec3 {
     focus + rotation * Vec3::new(0.0, 0.0, radius)
 }
 
@@ -230,7 +266,13 @@ pub fn camera_builder(
                 up_direction: up_direction.clone(),
                 azimuth: az,
                 elevation: el,
-            });
+            })
+            .insert((
+                ChaseCamera::default(),
+                FlyCamera::default(),
+                CameraShake::default(),
+                TopDownCamera::default(),
+            ));
 
         commands.init_resource::<PointerOverUi>()
     };
@@ -0,0 +1,187 @@
+use bevy::prelude::*;
+
+use crate::{
+    camera_az_el::{az_el_rotation, az_el_translation, AzElCamera},
+    control::CameraParentList,
+    fly::FlyCamera,
+    top_down::TopDownCamera,
+};
+
+// Chase mode of the orbit camera. Instead of being parented to the active entry
+// of `CameraParentList`, which passes every suspension jolt on to the view, the
+// camera follows it from behind and above with critically damped lag in both
+// position and aim. It looks ahead along the velocity, and swings into corners
+// with the yaw rate. Only the heading of the target is followed, not its pitch
+// and roll. `V` toggles the mode.
+#[derive(Component)]
+pub struct ChaseCamera {
+    pub active: bool,
+    pub distance: f32,           // behind the target
+    pub height: f32,             // above the target
+    pub position_frequency: f32, // (rad/s) of the position lag, higher follows closer
+    pub aim_frequency: f32,      // (rad/s) of the aim lag
+    pub lookahead_time: f32,     // (s) the aim leads the target by its velocity times this
+    pub yaw_lookahead: f32,      // (s) the camera swings by the yaw rate times this
+    state: Option<ChaseState>,
+}
+
+struct ChaseState {
+    position: Vec3,
+    velocity: Vec3,
+    aim: Vec3,
+    aim_velocity: Vec3,
+    target_position: Vec3, // on the previous frame, for the target velocity
+    heading: f32,
+}
+
+impl Default for ChaseCamera {
+    fn default() -> Self {
+        Self {
+            active: false,
+            distance: 8.,
+            height: 2.5,
+            position_frequency: 4.,
+            aim_frequency: 8.,
+            lookahead_time: 0.3,
+            yaw_lookahead: 0.4,
+            state: None,
+        }
+    }
+}
+
+impl ChaseCamera {
+    // out of the chase mode, e.g. when another camera mode takes over
+    pub fn stop(&mut self) {
+        self.active = false;
+        self.state = None;
+    }
+}
+
+// One step of a critically damped spring pulling `position` towards `target`,
+// exact for a constant target, so it is stable at any frame time.
+pub(crate) fn critically_damped(
+    position: Vec3,
+    velocity: Vec3,
+    target: Vec3,
+    frequency: f32,
+    dt: f32,
+) -> (Vec3, Vec3) {
+    let decay = (-frequency * dt).exp();
+    let offset = position - target;
+    let rate = velocity + frequency * offset;
+    (
+        target + (offset + rate * dt) * decay,
+        (velocity - frequency * rate * dt) * decay,
+    )
+}
+
+// horizontal axes the heading is measured in, about the up axis
+pub(crate) fn heading_frame(up: Vec3) -> (Vec3, Vec3) {
+    let reference = if up.x.abs() < 0.9 { Vec3::X } else { Vec3::Y };
+    let x = (reference - reference.dot(up) * up).normalize();
+    (x, up.cross(x))
+}
+
+type ChasingCamera<'a> = (
+    Entity,
+    &'a mut ChaseCamera,
+    &'a AzElCamera,
+    &'a mut Transform,
+    Option<&'a Parent>,
+    Option<&'a FlyCamera>,
+    Option<&'a TopDownCamera>,
+);
+
+pub fn chase_camera_system(
+    time: Res<Time>,
+    input: Res<Input<KeyCode>>,
+    parent_list: Option<Res<CameraParentList>>,
+    targets: Query<&GlobalTransform, Without<ChaseCamera>>,
+    mut cameras: Query<ChasingCamera>,
+    mut commands: Commands,
+) {
+    let target = match parent_list.and_then(|list| list.list.get(list.active).copied()) {
+        Some(target) => target,
+        None => return,
+    };
+    let dt = time.delta_seconds();
+    for (entity, mut chase, az_el, mut transform, parent, fly, top_down) in cameras.iter_mut() {
+        // the fly camera and the top-down view have their own controls
+        if matches!(fly, Some(fly) if fly.active)
+            || matches!(top_down, Some(top_down) if top_down.active)
+        {
+            continue;
+        }
+        if input.just_pressed(KeyCode::V) {
+            chase.active = !chase.active;
+            if !chase.active {
+                // back to the orbit view, the parent system parents the camera again
+                chase.stop();
+                transform.rotation =
+                    az_el_rotation(az_el.azimuth, az_el.elevation, &az_el.up_direction);
+                transform.translation =
+                    az_el_translation(az_el.focus, transform.rotation, az_el.radius);
+            }
+        }
+        if !chase.active {
+            continue;
+        }
+        let chase = &mut *chase;
+        let target_transform = match targets.get(target) {
+            Ok(target_transform) => target_transform,
+            Err(_) => continue,
+        };
+        if parent.is_some() {
+            commands.entity(entity).remove_parent();
+        }
+
+        let up = az_el.up_direction.vector();
+        let (x_axis, y_axis) = heading_frame(up);
+        let target_position = target_transform.translation();
+        let forward = target_transform.affine().transform_vector3(Vec3::X);
+        let heading = forward.dot(y_axis).atan2(forward.dot(x_axis));
+
+        let behind = |heading: f32| -(heading.cos() * x_axis + heading.sin() * y_axis);
+        let (distance, height) = (chase.distance, chase.height);
+        // start behind the target, at rest
+        let state = chase.state.get_or_insert_with(|| ChaseState {
+            position: target_position + distance * behind(heading) + height * up,
+            velocity: Vec3::ZERO,
+            aim: target_position,
+            aim_velocity: Vec3::ZERO,
+            target_position,
+            heading,
+        });
+        if dt <= 0. {
+            continue;
+        }
+        let target_velocity = (target_position - state.target_position) / dt;
+        // wrapped to the shortest way around
+        let turn = (heading - state.heading + std::f32::consts::PI)
+            .rem_euclid(2. * std::f32::consts::PI)
+            - std::f32::consts::PI;
+        let yaw_rate = turn / dt;
+        state.target_position = target_position;
+        state.heading = heading;
+
+        let swing = heading + yaw_rate * chase.yaw_lookahead;
+        let desired_position = target_position + distance * behind(swing) + height * up;
+        let desired_aim = target_position + target_velocity * chase.lookahead_time;
+
+        (state.position, state.velocity) = critically_damped(
+            state.position,
+            state.velocity,
+            desired_position,
+            chase.position_frequency,
+            dt,
+        );
+        (state.aim, state.aim_velocity) = critically_damped(
+            state.aim,
+            state.aim_velocity,
+            desired_aim,
+            chase.aim_frequency,
+            dt,
+        );
+        *transform = Transform::from_translation(state.position).looking_at(state.aim, up);
+    }
+}
@@ -1,19 +1,31 @@
 use bevy::prelude::*;
 
-use crate::camera_az_el::AzElCamera;
+use crate::{
+    camera_az_el::AzElCamera, chase::ChaseCamera, director::CameraDirector, fly::FlyCamera,
+    top_down::TopDownCamera, viewport::CameraViewport,
+};
 
-#[derive(Resource)]
+#[derive(Resource, Clone)]
 pub struct CameraParentList {
     pub list: Vec<Entity>,
     pub active: usize,
 }
 
+type ParentedCamera<'a> = (
+    Entity,
+    Option<&'a ChaseCamera>,
+    Option<&'a FlyCamera>,
+    Option<&'a TopDownCamera>,
+    Option<&'a CameraViewport>,
+);
+
 pub fn camera_parent_system(
     mut commands: Commands,
     mut parent_list: ResMut<CameraParentList>,
-    mut query: Query<Entity, With<AzElCamera>>,
+    mut query: Query<ParentedCamera, With<AzElCamera>>,
     focused_windows: Query<(Entity, &Window)>,
     input: Res<Input<KeyCode>>,
+    director: Option<Res<CameraDirector>>,
 ) {
     for (_window, focus) in focused_windows.iter() {
         if !focus.focused {
@@ -28,8 +40,17 @@ pub fn camera_parent_system(
             parent_list.active = (parent_list.active + 1) % parent_list.list.len();
         }
 
-        // update the parent on every frame...
-        if let Ok(camera_entity) = query.get_single_mut() {
+        // update the parent on every frame, unless the camera is chasing, flying,
+        // looking top-down, directed or follows the parent list of its viewport
+        for (camera_entity, chase, fly, top_down, viewport) in query.iter_mut() {
+            if matches!(chase, Some(chase) if chase.active)
+                || matches!(fly, Some(fly) if fly.active)
+                || matches!(top_down, Some(top_down) if top_down.active)
+                || matches!(&director, Some(director) if director.is_active())
+                || matches!(viewport, Some(viewport) if viewport.parents.is_some())
+            {
+                continue;
+            }
             let parent_entity = parent_list.list[parent_list.active];
             if commands.get_entity(parent_entity).is_some() {
                 if let Some(mut camera_entity_commands) = commands.get_entity(camera_entity) {
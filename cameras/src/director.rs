@@ -0,0 +1,241 @@
+use bevy::{prelude::*, render::camera::Projection};
+
+use crate::{
+    camera_az_el::{az_el_rotation, az_el_translation, AzElCamera},
+    chase::{critically_damped, ChaseCamera},
+    control::CameraParentList,
+    fly::FlyCamera,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DirectorMode {
+    Off,
+    Trackside,
+    Flythrough,
+}
+
+// Camera director for replay footage of a run, filming the active entry of
+// `CameraParentList`. Trackside cameras stand still and turn to follow the
+// target, zooming to keep it about `framing` meters across the view, and the
+// director cuts to the closest one (to a new one only when it is `switch_margin`
+// closer, so the cuts don't flicker). The flythrough moves the camera along a
+// Catmull-Rom spline through `flythrough` in `flythrough_time` seconds, looping,
+// aimed at the target. `N` cycles off, trackside and flythrough.
+#[derive(Resource)]
+pub struct CameraDirector {
+    pub mode: DirectorMode,
+    pub trackside: Vec<Vec3>,
+    pub flythrough: Vec<Vec3>,
+    pub flythrough_time: f32,
+    pub framing: f32,
+    pub switch_margin: f32,
+    pub aim_frequency: f32, // (rad/s) of the aim lag
+    active_camera: Option<usize>,
+    flythrough_timer: f32,
+    aim: Option<(Vec3, Vec3)>, // smoothed aim point and its velocity
+    fov: Option<f32>,          // of the orbit view, restored when the director stops
+}
+
+impl Default for CameraDirector {
+    fn default() -> Self {
+        Self {
+            mode: DirectorMode::Off,
+            trackside: Vec::new(),
+            flythrough: Vec::new(),
+            flythrough_time: 30.,
+            framing: 12.,
+            switch_margin: 10.,
+            aim_frequency: 6.,
+            active_camera: None,
+            flythrough_timer: 0.,
+            aim: None,
+            fov: None,
+        }
+    }
+}
+
+impl CameraDirector {
+    // Trackside cameras every `spacing` meters along a path (e.g. the centerline
+    // of a circuit, with z up), `offset` to its left and `height` above it, and
+    // a flythrough along the path at twice the height. A negative offset puts
+    // the cameras on the right.
+    pub fn along_path(path: &[Vec3], spacing: f32, offset: f32, height: f32) -> Self {
+        let mut trackside = Vec::new();
+        let mut flythrough = Vec::new();
+        let mut travelled = spacing; // a camera at the start
+        let mut length = 0.;
+        for pair in path.windows(2) {
+            let step = pair[1] - pair[0];
+            length += step.length();
+            travelled += step.length();
+            if travelled >= spacing {
+                travelled = 0.;
+                let left = Vec3::Z.cross(step).normalize_or_zero();
+                trackside.push(pair[0] + offset * left + height * Vec3::Z);
+                flythrough.push(pair[0] + 2. * height * Vec3::Z);
+            }
+        }
+        Self {
+            trackside,
+            flythrough,
+            // about 20 m/s
+            flythrough_time: length / 20.,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_mode(mut self, mode: DirectorMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.mode != DirectorMode::Off
+    }
+
+    // trackside camera closest to the target, keeping the current one unless
+    // another is closer by the switch margin
+    fn trackside_camera(&mut self, target: Vec3) -> Option<Vec3> {
+        let distance = |index: usize| self.trackside[index].distance(target);
+        let closest =
+            (0..self.trackside.len()).min_by(|a, b| distance(*a).total_cmp(&distance(*b)))?;
+        let camera = match self.active_camera {
+            Some(current)
+                if current < self.trackside.len()
+                    && distance(current) < distance(closest) + self.switch_margin =>
+            {
+                current
+            }
+            _ => closest,
+        };
+        if self.active_camera != Some(camera) {
+            // a cut, not a pan to the new target
+            self.aim = None;
+        }
+        self.active_camera = Some(camera);
+        Some(self.trackside[camera])
+    }
+
+    // point on the closed flythrough spline, `t` from 0 to 1 around it
+    fn flythrough_point(&self, t: f32) -> Option<Vec3> {
+        let n = self.flythrough.len();
+        if n < 2 {
+            return None;
+        }
+        let u = t.rem_euclid(1.) * n as f32;
+        let i = (u.floor() as usize).min(n - 1);
+        let s = u - i as f32;
+        let point = |offset: usize| self.flythrough[(i + n + offset - 1) % n];
+        let [p0, p1, p2, p3] = [point(0), point(1), point(2), point(3)];
+        let s2 = s * s;
+        let s3 = s2 * s;
+        Some(
+            0.5 * (2. * p1
+                + (p2 - p0) * s
+                + (2. * p0 - 5. * p1 + 4. * p2 - p3) * s2
+                + (3. * p1 - p0 - 3. * p2 + p3) * s3),
+        )
+    }
+}
+
+type DirectorCamera<'a> = (
+    Entity,
+    &'a mut Transform,
+    &'a mut Projection,
+    &'a mut ChaseCamera,
+    &'a AzElCamera,
+    Option<&'a Parent>,
+    Option<&'a FlyCamera>,
+);
+
+pub fn camera_director_system(
+    time: Res<Time>,
+    input: Res<Input<KeyCode>>,
+    director: Option<ResMut<CameraDirector>>,
+    parent_list: Option<Res<CameraParentList>>,
+    targets: Query<&GlobalTransform, Without<AzElCamera>>,
+    mut cameras: Query<DirectorCamera>,
+    mut commands: Commands,
+) {
+    let (mut director, parent_list) = match (director, parent_list) {
+        (Some(director), Some(parent_list)) => (director, parent_list),
+        _ => return,
+    };
+    if input.just_pressed(KeyCode::N) {
+        director.mode = match director.mode {
+            DirectorMode::Off => DirectorMode::Trackside,
+            DirectorMode::Trackside => DirectorMode::Flythrough,
+            DirectorMode::Flythrough => DirectorMode::Off,
+        };
+        director.active_camera = None;
+        director.aim = None;
+        info!("camera director: {:?}", director.mode);
+    }
+    let dt = time.delta_seconds();
+    for (entity, mut transform, mut projection, mut chase, az_el, parent, fly) in cameras.iter_mut()
+    {
+        // the fly camera takes over from the director
+        if matches!(fly, Some(fly) if fly.active) {
+            continue;
+        }
+        let perspective = match projection.as_mut() {
+            Projection::Perspective(perspective) => perspective,
+            _ => continue,
+        };
+        if !director.is_active() {
+            // back to the orbit view, the parent system parents the camera again
+            if let Some(fov) = director.fov.take() {
+                perspective.fov = fov;
+                transform.rotation =
+                    az_el_rotation(az_el.azimuth, az_el.elevation, &az_el.up_direction);
+                transform.translation =
+                    az_el_translation(az_el.focus, transform.rotation, az_el.radius);
+            }
+            continue;
+        }
+        let target = match parent_list
+            .list
+            .get(parent_list.active)
+            .and_then(|target| targets.get(*target).ok())
+        {
+            Some(target) => target.translation(),
+            None => continue,
+        };
+        if parent.is_some() {
+            commands.entity(entity).remove_parent();
+        }
+        chase.stop();
+        if director.fov.is_none() {
+            director.fov = Some(perspective.fov);
+        }
+
+        let position = match director.mode {
+            DirectorMode::Trackside => director.trackside_camera(target),
+            DirectorMode::Flythrough => {
+                director.flythrough_timer += dt;
+                let t = director.flythrough_timer / director.flythrough_time.max(1.);
+                director.flythrough_point(t)
+            }
+            DirectorMode::Off => None,
+        };
+        let position = match position {
+            Some(position) => position,
+            None => continue,
+        };
+
+        let aim_frequency = director.aim_frequency;
+        let (aim, aim_velocity) = match director.aim {
+            Some((aim, aim_velocity)) => {
+                critically_damped(aim, aim_velocity, target, aim_frequency, dt)
+            }
+            None => (target, Vec3::ZERO),
+        };
+        director.aim = Some((aim, aim_velocity));
+
+        // zoom so the target fills about the framing width
+        let distance = position.distance(aim).max(1.);
+        perspective.fov = (2. * (director.framing / 2.).atan2(distance)).clamp(0.05, 1.2);
+        let up = az_el.up_direction.vector();
+        *transform = Transform::from_translation(position).looking_at(aim, up);
+    }
+}
@@ -0,0 +1,146 @@
+use bevy::{input::mouse::MouseMotion, prelude::*};
+
+use crate::{
+    camera_az_el::{az_el_rotation, az_el_translation, AzElCamera, PointerOverUi},
+    chase::{heading_frame, ChaseCamera},
+    top_down::TopDownCamera,
+};
+
+// Free flying spectator mode of the orbit camera. The camera is unparented and
+// flies from where it is, so the terrain and the car can be looked at from
+// anywhere, also while the simulation is paused. `W`/`S` fly along the view,
+// `A`/`D` sideways and `E`/`Q` up and down, faster with `Left Shift`, and
+// dragging with the left mouse button turns the view. `F` toggles the mode; the
+// keyboard doesn't drive the car while flying.
+#[derive(Component)]
+pub struct FlyCamera {
+    pub active: bool,
+    pub speed: f32,       // (m/s)
+    pub boost: f32,       // speed factor with shift held
+    pub sensitivity: f32, // (rad/pixel) of mouse movement
+    yaw: f32,
+    pitch: f32,
+}
+
+impl Default for FlyCamera {
+    fn default() -> Self {
+        Self {
+            active: false,
+            speed: 10.,
+            boost: 4.,
+            sensitivity: 0.004,
+            yaw: 0.,
+            pitch: 0.,
+        }
+    }
+}
+
+impl FlyCamera {
+    // starts flying from where the camera is
+    pub fn start(
+        &mut self,
+        transform: &mut Transform,
+        global_transform: &GlobalTransform,
+        up: Vec3,
+    ) {
+        let (x_axis, y_axis) = heading_frame(up);
+        self.active = true;
+        *transform = global_transform.compute_transform();
+        let forward = transform.forward();
+        self.yaw = forward.dot(y_axis).atan2(forward.dot(x_axis));
+        self.pitch = forward.dot(up).clamp(-1., 1.).asin();
+    }
+
+    // back to the orbit view, the parent system parents the camera again
+    pub fn stop(&mut self, transform: &mut Transform, az_el: &AzElCamera) {
+        self.active = false;
+        transform.rotation = az_el_rotation(az_el.azimuth, az_el.elevation, &az_el.up_direction);
+        transform.translation = az_el_translation(az_el.focus, transform.rotation, az_el.radius);
+    }
+}
+
+type FlyingCamera<'a> = (
+    Entity,
+    &'a mut FlyCamera,
+    &'a mut Transform,
+    &'a GlobalTransform,
+    &'a AzElCamera,
+    Option<&'a mut ChaseCamera>,
+    Option<&'a Parent>,
+    Option<&'a TopDownCamera>,
+);
+
+pub fn fly_camera_system(
+    time: Res<Time>,
+    input: Res<Input<KeyCode>>,
+    input_mouse: Res<Input<MouseButton>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    pointer_over_ui: Option<Res<PointerOverUi>>,
+    mut cameras: Query<FlyingCamera>,
+    mut commands: Commands,
+) {
+    let mut turn = Vec2::ZERO;
+    for motion in mouse_motion.iter() {
+        turn += motion.delta;
+    }
+    let over_ui = matches!(&pointer_over_ui, Some(pointer_over_ui) if pointer_over_ui.check());
+    if !input_mouse.pressed(MouseButton::Left) || over_ui {
+        turn = Vec2::ZERO;
+    }
+
+    // real time, so the camera flies while the simulation is paused
+    let dt = time.raw_delta_seconds();
+    for (entity, mut fly, mut transform, global_transform, az_el, chase, parent, top_down) in
+        cameras.iter_mut()
+    {
+        // the top-down view has its own controls
+        if matches!(top_down, Some(top_down) if top_down.active) {
+            continue;
+        }
+        let up = az_el.up_direction.vector();
+        let (x_axis, y_axis) = heading_frame(up);
+        if input.just_pressed(KeyCode::F) {
+            if fly.active {
+                fly.stop(&mut transform, az_el);
+            } else {
+                fly.start(&mut transform, global_transform, up);
+            }
+        }
+        if !fly.active {
+            continue;
+        }
+        if parent.is_some() {
+            commands.entity(entity).remove_parent();
+        }
+        if let Some(mut chase) = chase {
+            chase.stop();
+        }
+
+        fly.yaw -= turn.x * fly.sensitivity;
+        fly.pitch = (fly.pitch - turn.y * fly.sensitivity).clamp(-1.5, 1.5);
+        let heading = fly.yaw.cos() * x_axis + fly.yaw.sin() * y_axis;
+        let forward = fly.pitch.cos() * heading + fly.pitch.sin() * up;
+        let right = forward.cross(up).normalize();
+
+        let mut direction = Vec3::ZERO;
+        for (key, axis) in [
+            (KeyCode::W, forward),
+            (KeyCode::S, -forward),
+            (KeyCode::D, right),
+            (KeyCode::A, -right),
+            (KeyCode::E, up),
+            (KeyCode::Q, -up),
+        ] {
+            if input.pressed(key) {
+                direction += axis;
+            }
+        }
+        let speed = if input.pressed(KeyCode::ShiftLeft) {
+            fly.speed * fly.boost
+        } else {
+            fly.speed
+        };
+        let translation = transform.translation + direction.normalize_or_zero() * speed * dt;
+        *transform = Transform::from_translation(translation).looking_to(forward, up);
+    }
+}
@@ -1,2 +1,8 @@
 pub mod camera_az_el;
+pub mod chase;
 pub mod control;
+pub mod director;
+pub mod fly;
+pub mod shake;
+pub mod top_down;
+pub mod viewport;
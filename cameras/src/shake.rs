@@ -0,0 +1,99 @@
+use bevy::prelude::*;
+
+// Procedural shake of a camera, for feeling harsh landings and impacts. Sources
+// add `trauma` (0 to 1), which decays over time, and the camera is offset by
+// smooth noise scaled by the trauma squared, so small knocks stay subtle. The
+// offset is applied just before the transforms are propagated and taken off
+// again at the start of the next frame, so the other camera systems never see
+// it. `K` toggles it.
+#[derive(Component)]
+pub struct CameraShake {
+    pub enabled: bool,
+    pub amplitude: f32, // (m) at full trauma
+    pub angle: f32,     // (rad) at full trauma
+    pub frequency: f32, // (Hz) of the noise
+    pub decay: f32,     // trauma lost per second
+    pub trauma: f32,
+    time: f32,
+    offset: Option<(Vec3, Quat)>, // applied this frame
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            amplitude: 0.15,
+            angle: 0.04,
+            frequency: 12.,
+            decay: 1.5,
+            trauma: 0.,
+            time: 0.,
+            offset: None,
+        }
+    }
+}
+
+impl CameraShake {
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).clamp(0., 1.);
+    }
+
+    // raises the trauma to at least `trauma`, for a continuous source
+    pub fn raise_trauma(&mut self, trauma: f32) {
+        self.trauma = self.trauma.max(trauma.min(1.));
+    }
+}
+
+// smooth noise from -1 to 1, a different pattern for each seed
+fn noise(time: f32, seed: f32) -> f32 {
+    ((time + seed).sin() * 0.5
+        + (time * 2.31 + 1.7 * seed).sin() * 0.3
+        + (time * 4.73 + 2.9 * seed).sin() * 0.2)
+        .clamp(-1., 1.)
+}
+
+// runs in `PostUpdate`, before the transform propagation
+pub fn camera_shake_system(
+    time: Res<Time>,
+    input: Res<Input<KeyCode>>,
+    mut cameras: Query<(&mut CameraShake, &mut Transform)>,
+) {
+    let dt = time.delta_seconds();
+    for (mut shake, mut transform) in cameras.iter_mut() {
+        if input.just_pressed(KeyCode::K) {
+            shake.enabled = !shake.enabled;
+            info!("camera shake: {}", if shake.enabled { "on" } else { "off" });
+        }
+        shake.trauma = (shake.trauma - shake.decay * dt).max(0.);
+        if !shake.enabled || shake.trauma <= 0. {
+            continue;
+        }
+        shake.time += dt * shake.frequency * 2. * std::f32::consts::PI;
+        let strength = shake.trauma * shake.trauma;
+        let t = shake.time;
+        let translation =
+            strength * shake.amplitude * Vec3::new(noise(t, 0.), noise(t, 10.), noise(t, 20.));
+        let rotation = Quat::from_euler(
+            EulerRot::XYZ,
+            strength * shake.angle * noise(t, 30.),
+            strength * shake.angle * noise(t, 40.),
+            strength * shake.angle * noise(t, 50.),
+        );
+        // in the camera frame
+        let offset = transform.rotation * translation;
+        transform.translation += offset;
+        transform.rotation *= rotation;
+        shake.offset = Some((translation, rotation));
+    }
+}
+
+// runs in `PreUpdate`, taking the offset of the last frame off again
+pub fn camera_unshake_system(mut cameras: Query<(&mut CameraShake, &mut Transform)>) {
+    for (mut shake, mut transform) in cameras.iter_mut() {
+        if let Some((translation, rotation)) = shake.offset.take() {
+            transform.rotation *= rotation.inverse();
+            let offset = transform.rotation * translation;
+            transform.translation -= offset;
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use bevy::{
+    input::mouse::{MouseMotion, MouseWheel},
+    prelude::*,
+    render::camera::{Projection, ScalingMode},
+    window::PrimaryWindow,
+};
+
+use crate::camera_az_el::{az_el_rotation, az_el_translation, AzElCamera, PointerOverUi};
+
+// Orthographic top-down map view of the orbit camera, for reviewing racing
+// lines, skid marks and AI paths. The camera is unparented and looks straight
+// down (along -z) on `area`, the x and y extent of the ground, zoomed to fit it
+// in the window with +y up. The scroll wheel zooms and dragging with the left
+// mouse button pans. `O` toggles the view, fitting the area again.
+#[derive(Component)]
+pub struct TopDownCamera {
+    pub active: bool,
+    pub area: Rect,
+    pub height: f32,           // of the camera above the ground
+    saved: Option<Projection>, // of the orbit view, restored when the view is left
+}
+
+impl Default for TopDownCamera {
+    fn default() -> Self {
+        Self {
+            active: false,
+            area: Rect::new(-50., -50., 50., 50.),
+            height: 500.,
+            saved: None,
+        }
+    }
+}
+
+impl TopDownCamera {
+    pub fn start(&mut self, transform: &mut Transform, projection: &mut Projection) {
+        self.active = true;
+        self.saved = Some(projection.clone());
+        self.fit(transform, projection);
+    }
+
+    // back to the orbit view, the parent system parents the camera again
+    pub fn stop(
+        &mut self,
+        transform: &mut Transform,
+        projection: &mut Projection,
+        az_el: &AzElCamera,
+    ) {
+        self.active = false;
+        if let Some(saved) = self.saved.take() {
+            *projection = saved;
+        }
+        transform.rotation = az_el_rotation(az_el.azimuth, az_el.elevation, &az_el.up_direction);
+        transform.translation = az_el_translation(az_el.focus, transform.rotation, az_el.radius);
+    }
+
+    // looking down on all of the area
+    fn fit(&self, transform: &mut Transform, projection: &mut Projection) {
+        *projection = Projection::Orthographic(OrthographicProjection {
+            scaling_mode: ScalingMode::AutoMin {
+                min_width: self.area.width().max(1.),
+                min_height: self.area.height().max(1.),
+            },
+            far: 2. * self.height,
+            ..default()
+        });
+        *transform = Transform::from_translation(self.area.center().extend(self.height))
+            .looking_to(-Vec3::Z, Vec3::Y);
+    }
+}
+
+type MapCamera<'a> = (
+    Entity,
+    &'a mut TopDownCamera,
+    &'a mut Transform,
+    &'a mut Projection,
+    &'a AzElCamera,
+    Option<&'a Parent>,
+);
+
+#[allow(clippy::too_many_arguments)]
+pub fn top_down_camera_system(
+    input: Res<Input<KeyCode>>,
+    input_mouse: Res<Input<MouseButton>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    pointer_over_ui: Option<Res<PointerOverUi>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<MapCamera>,
+    mut commands: Commands,
+) {
+    let mut drag = Vec2::ZERO;
+    for motion in mouse_motion.iter() {
+        drag += motion.delta;
+    }
+    let mut scroll = 0.;
+    for wheel in mouse_wheel.iter() {
+        scroll += wheel.y;
+    }
+    let over_ui = matches!(&pointer_over_ui, Some(pointer_over_ui) if pointer_over_ui.check());
+    if !input_mouse.pressed(MouseButton::Left) || over_ui {
+        drag = Vec2::ZERO;
+    }
+    if over_ui {
+        scroll = 0.;
+    }
+    let window_width = match windows.get_single() {
+        Ok(window) => window.width(),
+        Err(_) => return,
+    };
+
+    for (entity, mut top_down, mut transform, mut projection, az_el, parent) in cameras.iter_mut() {
+        if input.just_pressed(KeyCode::O) {
+            if top_down.active {
+                top_down.stop(&mut transform, &mut projection, az_el);
+            } else {
+                top_down.start(&mut transform, &mut projection);
+            }
+        }
+        if !top_down.active {
+            continue;
+        }
+        if parent.is_some() {
+            commands.entity(entity).remove_parent();
+        }
+        let orthographic = match projection.as_mut() {
+            Projection::Orthographic(orthographic) => orthographic,
+            _ => continue,
+        };
+
+        // the ground moves with the cursor, the window y axis is down
+        let meters_per_pixel = orthographic.area.width() / window_width.max(1.);
+        transform.translation -= Vec3::new(drag.x, -drag.y, 0.) * meters_per_pixel;
+        if scroll != 0. {
+            orthographic.scale = (orthographic.scale * (1. - 0.2 * scroll)).clamp(0.005, 10.);
+        }
+    }
+}
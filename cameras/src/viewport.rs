@@ -0,0 +1,179 @@
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig, prelude::*, render::camera::Viewport,
+    window::PrimaryWindow,
+};
+
+use crate::{
+    camera_az_el::{az_el_rotation, az_el_translation, AzElCamera},
+    chase::ChaseCamera,
+    control::CameraParentList,
+};
+
+// Part of the window a camera renders to, for split screen views. `rect` runs
+// from (0, 0) at the top left of the window to (1, 1) at the bottom right. A
+// viewport with its own `parents` follows the active entry of that list (cycled
+// with `cycle_key`) instead of the `CameraParentList` resource, so each view can
+// follow a different car.
+#[derive(Component, Clone)]
+pub struct CameraViewport {
+    pub rect: Rect,
+    pub parents: Option<CameraParentList>,
+    pub cycle_key: Option<KeyCode>,
+}
+
+impl CameraViewport {
+    pub fn new(rect: Rect) -> Self {
+        Self {
+            rect,
+            parents: None,
+            cycle_key: None,
+        }
+    }
+
+    // column `index` of `count` side by side views, from the left
+    pub fn column(index: usize, count: usize) -> Self {
+        let width = 1. / count.max(1) as f32;
+        Self::new(Rect::new(
+            index as f32 * width,
+            0.,
+            (index + 1) as f32 * width,
+            1.,
+        ))
+    }
+
+    // row `index` of `count` stacked views, from the top
+    pub fn row(index: usize, count: usize) -> Self {
+        let height = 1. / count.max(1) as f32;
+        Self::new(Rect::new(
+            0.,
+            index as f32 * height,
+            1.,
+            (index + 1) as f32 * height,
+        ))
+    }
+
+    pub fn with_parents(mut self, parents: CameraParentList) -> Self {
+        self.parents = Some(parents);
+        self
+    }
+
+    pub fn with_cycle_key(mut self, key: KeyCode) -> Self {
+        self.cycle_key = Some(key);
+        self
+    }
+
+    // a cursor position (logical, from the top left) is in the viewport
+    pub fn contains(&self, cursor: Vec2, window: Vec2) -> bool {
+        self.rect.contains(cursor / window)
+    }
+}
+
+// Spawns another orbit camera for a viewport. Cameras of a higher `order` draw
+// over the lower ones, without clearing the window or drawing the UI again.
+pub fn spawn_viewport_camera(
+    commands: &mut Commands,
+    az_el: AzElCamera,
+    viewport: CameraViewport,
+    order: isize,
+) -> Entity {
+    let rotation = az_el_rotation(az_el.azimuth, az_el.elevation, &az_el.up_direction);
+    let translation = az_el_translation(az_el.focus, rotation, az_el.radius);
+    let clear_color = if order > 0 {
+        ClearColorConfig::None
+    } else {
+        ClearColorConfig::Custom(Color::BLACK)
+    };
+    commands
+        .spawn(Camera3dBundle {
+            transform: Transform {
+                translation,
+                rotation,
+                ..default()
+            },
+            camera: Camera { order, ..default() },
+            camera_3d: Camera3d {
+                clear_color,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(UiCameraConfig {
+            show_ui: order <= 0,
+        })
+        .insert((az_el, viewport))
+        .id()
+}
+
+// Puts the camera spawned by `camera_builder` in a viewport. Run it after that
+// camera is spawned (e.g. in `PostStartup`).
+pub fn main_viewport(
+    viewport: CameraViewport,
+) -> impl Fn(Commands, Query<Entity, With<ChaseCamera>>) {
+    move |mut commands, cameras| {
+        for camera in cameras.iter() {
+            commands.entity(camera).insert(viewport.clone());
+        }
+    }
+}
+
+// keeps the camera viewports on their part of the window as it is resized
+pub fn viewport_system(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<(&CameraViewport, &mut Camera)>,
+) {
+    let window = match windows.get_single() {
+        Ok(window) => window,
+        Err(_) => return,
+    };
+    let size = UVec2::new(window.physical_width(), window.physical_height());
+    // minimized
+    if size.x == 0 || size.y == 0 {
+        return;
+    }
+    for (viewport, mut camera) in cameras.iter_mut() {
+        let corner = |point: Vec2| {
+            (point.clamp(Vec2::ZERO, Vec2::ONE) * size.as_vec2())
+                .round()
+                .as_uvec2()
+        };
+        let position = corner(viewport.rect.min).min(size - UVec2::ONE);
+        let physical_size = (corner(viewport.rect.max).max(position) - position)
+            .max(UVec2::ONE)
+            .min(size - position);
+        if matches!(&camera.viewport, Some(current)
+            if current.physical_position == position && current.physical_size == physical_size)
+        {
+            continue;
+        }
+        camera.viewport = Some(Viewport {
+            physical_position: position,
+            physical_size,
+            ..default()
+        });
+    }
+}
+
+// parents the viewport cameras with their own parent lists, like
+// `camera_parent_system` does with the `CameraParentList` resource
+pub fn viewport_parent_system(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    mut cameras: Query<(Entity, &mut CameraViewport)>,
+) {
+    for (camera_entity, mut viewport) in cameras.iter_mut() {
+        let cycle_key = viewport.cycle_key;
+        let parents = match viewport.parents.as_mut() {
+            Some(parents) if !parents.list.is_empty() => parents,
+            _ => continue,
+        };
+        if matches!(cycle_key, Some(key) if input.just_pressed(key)) {
+            parents.active = (parents.active + 1) % parents.list.len();
+        }
+        let parent_entity = parents.list[parents.active.min(parents.list.len() - 1)];
+        if commands.get_entity(parent_entity).is_some() {
+            commands.entity(camera_entity).set_parent(parent_entity);
+        } else {
+            commands.entity(camera_entity).remove_parent();
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{SimTime, Solver};
+use cameras::control::CameraParentList;
+use car::{
+    ai::AiDriver,
+    audio::CarAudioPlugin,
+    build::{spawn_car, CarDefinition},
+    environment::build_track_environment,
+    hud::hud_setup,
+    particles::tire_particles_setup,
+    presets::Preset,
+    setup::{camera_setup, simulation_setup},
+    skid_marks::skid_marks_setup,
+};
+use grid_terrain::examples::circuit_track;
+use rigid_body::{
+    graphics::GraphicsSettings,
+    joint::{Base, Joint},
+    plugin::RigidBodyPlugin,
+    sva::Motion,
+};
+
+// The AI driver laps the circuit unattended, pass a vehicle preset to change the
+// car: cargo run --example ai_driver -- kart
+fn main() {
+    let preset = match std::env::args().nth(1) {
+        Some(name) => {
+            Preset::from_name(&name).unwrap_or_else(|| panic!("unknown vehicle preset: {}", name))
+        }
+        None => Preset::Car,
+    };
+
+    App::new()
+        .add_plugins(RigidBodyPlugin {
+            time: SimTime::new(0.002, 0.0, None),
+            solver: Solver::RK4,
+            simulation_setup: vec![simulation_setup],
+            environment_setup: vec![
+                camera_setup,
+                hud_setup,
+                skid_marks_setup,
+                tire_particles_setup,
+            ],
+            name: "ai_driver".to_string(),
+            headless: false,
+            graphics: GraphicsSettings::default(),
+            single_threaded_physics: true,
+        })
+        .insert_resource(preset.build())
+        .add_systems(Startup, ai_startup_system)
+        .add_systems(Startup, build_track_environment)
+        .add_plugins(CarAudioPlugin)
+        .run();
+}
+
+fn ai_startup_system(mut commands: Commands, car: Res<CarDefinition>) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    let driver = AiDriver::new(
+        circuit_track().centerline(),
+        car.max_curvature().unwrap_or(0.2),
+        30., // max speed (m/s)
+        7.,  // lateral acceleration (m/s^2)
+        6.,  // braking deceleration (m/s^2)
+    );
+    let [x, y] = driver.path[0];
+    let z = car.initial_position()[2];
+    let entities = spawn_car(
+        &mut commands,
+        &car,
+        base_id,
+        [x, y, z],
+        driver.start_heading(),
+        Color::rgb(0.9, 0.1, 0.2),
+    );
+    commands.entity(entities.chassis).insert(driver);
+
+    let mut camera_parent_list = entities.camera_parents.clone();
+    camera_parent_list.push(base_id);
+    commands.insert_resource(CameraParentList {
+        list: camera_parent_list,
+        active: 0,
+    });
+    commands.insert_resource(entities);
+}
@@ -0,0 +1,132 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use bevy::prelude::*;
+use bevy_integrator::{integrator_schedule, SimSeed, Solver};
+use car::{
+    build::{car_startup_system, CarEntities},
+    control::CarControl,
+    presets::Preset,
+    setup::simulation_setup,
+    terrain::{build_described_terrain, TerrainDescription, TerrainLayout},
+};
+use rigid_body::{cli::SimArgs, joint::Joint};
+
+// counts the allocations of the process
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// allocations of the threaded executor per solver stage, its tasks (20.03 measured)
+const THREADED_STAGE_ALLOCATIONS: f64 = 20.05;
+
+// Counts the allocations of the physics time steps of a car driving a circle on
+// the test terrain, once the buffers of the solver, the joint tree and the tires
+// have grown. A time step (`integrator_schedule`, the solver stages running the
+// physics schedule) should not allocate, so it panics if one does. It runs the
+// physics schedule on one thread (`single_threaded_physics`), `multi_threaded`
+// compares with the tasks of the threaded executor, which allocate, and panics
+// if a step allocates more than those of the measured baseline. Takes a
+// vehicle preset, the solver and time step of `rigid_body::cli::SimArgs` and
+// the number of steps to count:
+// cargo run --example allocations -- truck solver=euler steps=5000
+fn main() {
+    let mut preset = Preset::Car;
+    let mut steps = 2500;
+    let mut sim_args = SimArgs::new(0.002, None);
+    for arg in std::env::args().skip(1) {
+        if let Some(value) = arg.strip_prefix("steps=") {
+            steps = value
+                .parse()
+                .unwrap_or_else(|_| panic!("invalid number of steps: {}", value));
+        } else if !sim_args
+            .parse(&arg)
+            .unwrap_or_else(|error| panic!("{}", error))
+        {
+            preset = Preset::from_name(&arg)
+                .unwrap_or_else(|| panic!("unknown vehicle preset: {}", arg));
+        }
+    }
+    sim_args.headless = true;
+
+    let mut app = App::new();
+    app.add_plugins(sim_args.plugin("allocations", vec![simulation_setup], Vec::new()))
+        .insert_resource(preset.build())
+        .insert_resource(SimSeed(sim_args.seed))
+        .insert_resource(TerrainDescription {
+            layout: TerrainLayout::TestGrid,
+            props: Vec::new(),
+        })
+        .add_systems(Startup, (car_startup_system, build_described_terrain));
+    app.finish();
+    app.cleanup();
+    // the first update runs the startup systems, then the car settles
+    for _ in 0..500 {
+        app.update();
+    }
+
+    let chassis = app.world.resource::<CarEntities>().chassis;
+    if let Some(mut control) = app.world.get_mut::<CarControl>(chassis) {
+        control.throttle = 0.4;
+        control.steering = 0.3;
+        control.steering_input = 0.3;
+    }
+    // the steps alone, as the other systems of an update record and log
+    let mut step = |count: usize| {
+        for _ in 0..count {
+            integrator_schedule::<Joint>(&mut app.world);
+        }
+    };
+    step(500);
+    let start = ALLOCATIONS.load(Ordering::Relaxed);
+    let clock = Instant::now();
+    step(steps);
+    let step_time = clock.elapsed().as_secs_f64() / steps as f64;
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - start;
+    let per_step = allocations as f64 / steps as f64;
+
+    info!(
+        "{} allocations in {} time steps ({:.2} per step), {:.0} µs per step",
+        allocations,
+        steps,
+        per_step,
+        1e6 * step_time
+    );
+    if sim_args.single_threaded_physics && allocations > 0 {
+        panic!("the time steps allocate");
+    }
+    let stages = match sim_args.solver {
+        Solver::Euler => 1.,
+        Solver::Heun | Solver::Midpoint => 2.,
+        Solver::RK4 => 4.,
+    };
+    let baseline = stages * THREADED_STAGE_ALLOCATIONS;
+    if !sim_args.single_threaded_physics && per_step > baseline {
+        panic!(
+            "the time steps allocate more than the threaded executor's {:.2}",
+            baseline
+        );
+    }
+}
@@ -1,27 +1,446 @@
-use bevy::prelude::*;
+use bevy::{
+    diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
+    prelude::*,
+};
 
-use bevy_integrator::{SimTime, Solver};
+use bevy_integrator::{recorder::Recorder, SimSeed};
 use car::{
-    build::{build_car, car_startup_system},
-    environment::build_environment,
-    setup::{camera_setup, simulation_setup},
+    audio::CarAudioPlugin,
+    bicycle::bicycle_model_setup,
+    bindings::InputBindings,
+    broadcast::TelemetryBroadcast,
+    build::{car_startup_system, CarDefinition, ChassisFlex},
+    camera_presets::CameraPresets,
+    camera_sensor::{camera_sensor_setup, roof_camera, CarCameraSensor},
+    config::{CarConfig, CarConfigFile},
+    control::RacingWheel,
+    diagnostics::CarDiagnosticsPlugin,
+    drive_mode::drive_mode_setup,
+    force_feedback::ForceFeedback,
+    headlights::{headlights_setup, Headlights},
+    hud::hud_setup,
+    interior::interior_setup,
+    lidar::lidar_setup,
+    mcap::McapLogger,
+    menu::{menu_setup, Menu},
+    mirror::mirror_setup,
+    motion::MotionOutput,
+    particles::tire_particles_setup,
+    performance::PerformanceOverlayPlugin,
+    plot::TelemetryPlotPlugin,
+    presets::Preset,
+    replay::{input_replay_startup_system, InputReplayFiles},
+    ros::RosBridge,
+    setup::{camera_setup, physics_thread_setup, simulation_setup},
+    skid_marks::skid_marks_setup,
+    snapshot::{WorldSnapshot, WorldSnapshots},
+    telemetry::TelemetryFile,
+    terrain::{
+        build_described_environment, build_described_terrain, TerrainDescription, TerrainLayout,
+    },
+    time_of_day::{time_of_day_setup, TimeOfDay},
+    torque_vectoring::TorqueVectoring,
+    touch::touch_controls_setup,
+    tuning::TuningPanelPlugin,
+    wheel_load::wheel_load_setup,
 };
-use rigid_body::plugin::RigidBodyPlugin;
+use rigid_body::cli::SimArgs;
 
 // Main function
 fn main() {
-    let car_definition = build_car();
+    // optional vehicle preset (car, truck, kart, buggy, 6x6), setup file, telemetry
+    // file and live plots. The setup file is reloaded when it changes, the
+    // telemetry is written at exit: cargo run --example car -- truck setup.toml telemetry.csv plot
+    // (to HDF5 for a .h5 file, each run adds a group to it).
+    // The driver inputs can be recorded (record=inputs.csv) and played back (play=inputs.csv),
+    // and the controls rebound from a file (bindings=bindings.toml). `vectoring` adds torque
+    // vectoring on the driven axle. `loads` shows the tire loads and weight transfer.
+    // `flex` makes the chassis torsionally soft. `bicycle` runs a bicycle model alongside the car.
+    // `mirror` adds a rear view mirror (`G` toggles it). The camera presets are read from a file
+    // (cameras=car/examples/cameras.toml), `Y` cycles through them. `H` keeps the horizon of the
+    // cockpit view level. `tune` shows a panel to tune the car while it drives. The app starts
+    // in a menu, escape pauses it, and another vehicle or terrain can be picked from the menu. `touch` adds on-screen touch controls, always there in the browser.
+    // `motion=127.0.0.1:20777` streams the chassis motion to a motion rig, `broadcast=20778`
+    // sends live telemetry to dashboard tools. `ros=127.0.0.1:9870` sends the car's state to
+    // the ROS 2 bridge node (car/examples/ros2_bridge.py), which sends back drive commands.
+    // `snapshot=moment.json` saves the whole world to the file with F5 and loads it back with
+    // F9. When the file exists the car starts from it, on the terrain saved in it. `lidar` puts
+    // a lidar on the roof and draws its points. `camera` puts a camera sensor on the roof,
+    // `camera=frames` also writes its pictures to the directory. `mcap=run.mcap` logs the
+    // telemetry, joint frames and lidar scans for Foxglove. `diagnostics` logs the physics step
+    // and ABA times, steps per frame, tire contact points and frame rate every second, `F3`
+    // shows them in an overlay. `wheel` drives with a racing wheel and pedals, with force feedback
+    // (the `force_feedback` feature drives the wheel, `wheel=/dev/input/event5` names its device).
+    // `hour=20` starts at that time of day, the sun and the ambient light following it through
+    // a day every 10 minutes, or another day length in seconds (`day=120`, `day=0` keeps the
+    // hour). `J` toggles the headlights, on from the start at night.
+    // `threaded` runs the physics on its own thread, in real time, so a slow time step doesn't
+    // drop a frame and a slow frame doesn't slow the physics (not for headless runs, or with
+    // `tune`).
+    // `car=setup.toml` and `terrain=terrain.toml` give the setup file and a terrain file, and
+    // the simulation options of `rigid_body::cli::SimArgs` set the solver, time step, end time,
+    // headless runs and the seed of the random processes (`solver=euler dt=0.001 end=30
+    // headless seed=7`), `record=` being the input recording.
+    let mut preset = Preset::Car;
+    let mut setup_file = None;
+    let mut telemetry_file = None;
+    let mut plot = false;
+    let mut tune = false;
+    let mut replay_files = InputReplayFiles::default();
+    let mut bindings = None;
+    let mut camera_presets = None;
+    let mut vectoring = false;
+    let mut loads = false;
+    let mut flex = false;
+    let mut bicycle = false;
+    let mut mirror = false;
+    let mut lidar = false;
+    let mut camera = None;
+    let mut touch = cfg!(target_arch = "wasm32");
+    let mut motion = None;
+    let mut broadcast = None;
+    let mut ros = None;
+    let mut snapshots = None;
+    let mut mcap = None;
+    let mut diagnostics = false;
+    let mut terrain_file = None;
+    let mut hour = None;
+    let mut day_length = None;
+    let mut threaded = false;
+    let mut wheel = None;
+    let mut sim_args = SimArgs::new(0.002, None);
+    for arg in std::env::args().skip(1) {
+        if arg == "plot" {
+            plot = true;
+        } else if arg == "tune" {
+            tune = true;
+        } else if arg == "vectoring" {
+            vectoring = true;
+        } else if arg == "loads" {
+            loads = true;
+        } else if arg == "flex" {
+            flex = true;
+        } else if arg == "bicycle" {
+            bicycle = true;
+        } else if arg == "mirror" {
+            mirror = true;
+        } else if arg == "lidar" {
+            lidar = true;
+        } else if arg == "camera" {
+            camera = Some(None);
+        } else if let Some(directory) = arg.strip_prefix("camera=") {
+            camera = Some(Some(directory.to_string()));
+        } else if arg == "threaded" {
+            threaded = true;
+        } else if arg == "diagnostics" {
+            diagnostics = true;
+        } else if arg == "wheel" {
+            wheel = Some(ForceFeedback::default());
+        } else if let Some(device) = arg.strip_prefix("wheel=") {
+            wheel = Some(ForceFeedback {
+                device: Some(device.to_string()),
+                ..default()
+            });
+        } else if arg == "touch" {
+            touch = true;
+        } else if let Some(address) = arg.strip_prefix("motion=") {
+            motion = Some(MotionOutput::new(address));
+        } else if let Some(port) = arg.strip_prefix("broadcast=") {
+            let port = port
+                .parse()
+                .unwrap_or_else(|_| panic!("invalid broadcast port: {}", port));
+            broadcast = Some(TelemetryBroadcast::new(port));
+        } else if let Some(value) = arg.strip_prefix("hour=") {
+            let value = value
+                .parse()
+                .unwrap_or_else(|_| panic!("invalid hour: {}", value));
+            hour = Some(value);
+        } else if let Some(value) = arg.strip_prefix("day=") {
+            let value = value
+                .parse()
+                .unwrap_or_else(|_| panic!("invalid day length: {}", value));
+            day_length = Some(value);
+        } else if let Some(address) = arg.strip_prefix("ros=") {
+            ros = Some(RosBridge::new(address));
+        } else if let Some(path) = arg.strip_prefix("mcap=") {
+            mcap = Some(McapLogger::new(path));
+        } else if let Some(path) = arg.strip_prefix("snapshot=") {
+            snapshots = Some(WorldSnapshots::new(path));
+        } else if let Some(path) = arg.strip_prefix("car=") {
+            setup_file = Some(path.to_string());
+        } else if let Some(path) = arg.strip_prefix("terrain=") {
+            terrain_file = Some(path.to_string());
+        } else if let Some(path) = arg.strip_prefix("play=") {
+            replay_files.playback = Some(path.into());
+        } else if let Some(path) = arg.strip_prefix("bindings=") {
+            let file = InputBindings::from_file(path.as_ref());
+            bindings = Some(file.unwrap_or_else(|error| panic!("{}", error)));
+        } else if let Some(path) = arg.strip_prefix("cameras=") {
+            let file = CameraPresets::from_file(path.as_ref());
+            camera_presets = Some(file.unwrap_or_else(|error| panic!("{}", error)));
+        } else if sim_args
+            .parse(&arg)
+            .unwrap_or_else(|error| panic!("{}", error))
+        {
+            // solver, time step, end time, headless or input recording
+        } else if arg.ends_with(".toml") {
+            setup_file = Some(arg);
+        } else if arg.ends_with(".csv") || arg.ends_with(".h5") {
+            telemetry_file = Some(arg);
+        } else {
+            preset = Preset::from_name(&arg)
+                .unwrap_or_else(|| panic!("unknown vehicle preset: {}", arg));
+        }
+    }
+    replay_files.record = sim_args.record.clone();
+    // headless runs take the time steps as fast as they can already
+    let threaded = threaded && !sim_args.headless;
+    let car_definition = build_car(preset, vectoring, flex, setup_file.as_deref());
+
+    // the terrain of the car demo or of the terrain file, or of the snapshot it starts from
+    let mut terrain = match &terrain_file {
+        Some(path) => {
+            TerrainDescription::from_file(path.as_ref()).unwrap_or_else(|error| panic!("{}", error))
+        }
+        None => TerrainDescription {
+            layout: TerrainLayout::TestGrid,
+            props: Vec::new(),
+        },
+    };
+    let mut start_terrain = terrain_file.is_some();
+    let snapshots = snapshots.map(|snapshots| match snapshots.path.exists() {
+        true => {
+            let snapshot = WorldSnapshot::from_file(&snapshots.path);
+            let snapshot = snapshot.unwrap_or_else(|error| panic!("{}", error));
+            if let Some(snapshot_terrain) = &snapshot.terrain {
+                terrain = snapshot_terrain.clone();
+                start_terrain = true;
+            }
+            sim_args.seed = snapshot.seed;
+            snapshots.with_snapshot(snapshot)
+        }
+        false => snapshots,
+    });
+
+    // the menu swaps the vehicle and the terrain, not with the physics on its own thread,
+    // which has its own car and terrain
+    let mut menu = Menu::default();
+    if !threaded {
+        let vehicles = Preset::ALL.iter().map(|preset| preset.name().to_string());
+        let vehicle = Preset::ALL.iter().position(|p| *p == preset).unwrap_or(0);
+        let menu_setup_file = setup_file.clone();
+        menu = menu.with_vehicles(vehicles.collect(), vehicle, move |name| {
+            let preset = Preset::from_name(name).unwrap_or(Preset::Car);
+            build_car(preset, vectoring, flex, menu_setup_file.as_deref())
+        });
+        let start = start_terrain.then(|| terrain.clone());
+        menu = menu.with_terrains(menu_terrains(start), 0);
+    }
+    if replay_files.playback.is_some() {
+        menu = menu.with_replay();
+    }
+
+    let mut environment_setup: Vec<fn(&mut App)> = vec![
+        camera_setup,
+        hud_setup,
+        skid_marks_setup,
+        tire_particles_setup,
+        drive_mode_setup,
+        interior_setup,
+        menu_setup,
+        headlights_setup,
+    ];
+    if loads {
+        environment_setup.push(wheel_load_setup);
+    }
+    if bicycle {
+        environment_setup.push(bicycle_model_setup);
+    }
+    if mirror {
+        environment_setup.push(mirror_setup);
+    }
+    if lidar {
+        environment_setup.push(lidar_setup);
+    }
+    let camera = camera.map(|directory| {
+        environment_setup.push(camera_sensor_setup);
+        let sensor = roof_camera(&car_definition);
+        match directory {
+            Some(directory) => CarCameraSensor(sensor.with_directory(directory)),
+            None => CarCameraSensor(sensor),
+        }
+    });
+    if touch {
+        environment_setup.push(touch_controls_setup);
+    }
+    let time_of_day = hour.map(|hour| {
+        environment_setup.push(time_of_day_setup);
+        let time_of_day = TimeOfDay::new(hour);
+        match day_length {
+            Some(day_length) => time_of_day.with_day_length(Some(day_length)),
+            None => time_of_day,
+        }
+    });
+
+    // the physics app of the physics thread, which writes the recordings
+    let physics_setup = threaded.then(|| {
+        let args = SimArgs {
+            headless: true,
+            ..sim_args.clone()
+        };
+        let car_definition = build_car(preset, vectoring, flex, setup_file.as_deref());
+        let terrain = terrain.clone();
+        let replay_files = InputReplayFiles {
+            record: replay_files.record.take(),
+            playback: replay_files.playback.clone(),
+        };
+        let setup_file = setup_file.clone();
+        let telemetry_file = telemetry_file.take();
+        let mcap = mcap.take();
+        move |app: &mut App| {
+            app.add_plugins(args.plugin("car_demo", vec![simulation_setup], Vec::new()))
+                .insert_resource(car_definition)
+                .insert_resource(SimSeed(args.seed))
+                .insert_resource(terrain)
+                .insert_resource(replay_files)
+                .add_systems(Startup, (car_startup_system, build_described_terrain))
+                .add_systems(PostStartup, input_replay_startup_system);
+            if let Some(path) = setup_file {
+                app.insert_resource(CarConfigFile::new(path));
+            }
+            add_recording(app, telemetry_file, mcap);
+        }
+    });
+
     // Create App
-    App::new()
-        .add_plugins(RigidBodyPlugin {
-            time: SimTime::new(0.002, 0.0, None),
-            solver: Solver::RK4,
-            simulation_setup: vec![simulation_setup],
-            environment_setup: vec![camera_setup],
-            name: "car_demo".to_string(),
-        })
+    let mut app = App::new();
+    app.add_plugins(sim_args.plugin("car_demo", vec![simulation_setup], environment_setup))
         .insert_resource(car_definition)
+        .insert_resource(SimSeed(sim_args.seed))
+        .insert_resource(terrain)
+        .insert_resource(replay_files)
+        .insert_resource(menu)
         .add_systems(Startup, car_startup_system)
-        .add_systems(Startup, build_environment)
-        .run();
+        .add_systems(PostStartup, input_replay_startup_system); // once the car is spawned
+    if sim_args.headless {
+        app.add_systems(Startup, build_described_terrain);
+    } else {
+        app.add_systems(Startup, build_described_environment)
+            .add_plugins(CarAudioPlugin);
+    }
+    if let Some(physics_setup) = physics_setup {
+        physics_thread_setup(&mut app, physics_setup);
+    }
+    if let Some(path) = setup_file {
+        app.insert_resource(CarConfigFile::new(path));
+    }
+    if let Some(bindings) = bindings {
+        app.insert_resource(bindings);
+    }
+    if let Some(camera_presets) = camera_presets {
+        app.insert_resource(camera_presets);
+    }
+    if let Some(force_feedback) = wheel {
+        app.insert_resource(RacingWheel::default())
+            .insert_resource(force_feedback);
+    }
+    if plot {
+        app.add_plugins(TelemetryPlotPlugin);
+    }
+    if tune && threaded {
+        // the physics would overwrite the tuned car
+        warn!("the tuning panel is not available with the physics on its own thread");
+    } else if tune {
+        app.add_plugins(TuningPanelPlugin);
+    }
+    if let Some(motion) = motion {
+        app.insert_resource(motion);
+    }
+    if let Some(broadcast) = broadcast {
+        app.insert_resource(broadcast);
+    }
+    if let Some(ros) = ros {
+        app.insert_resource(ros);
+    }
+    if let Some(snapshots) = snapshots {
+        app.insert_resource(snapshots);
+    }
+    if let Some(camera) = camera {
+        app.insert_resource(camera);
+    }
+    if let Some(time_of_day) = time_of_day {
+        let headlights = Headlights {
+            on: time_of_day.daylight(time_of_day.start_hour) < 0.5,
+            ..default()
+        };
+        app.insert_resource(time_of_day).insert_resource(headlights);
+    }
+    add_recording(&mut app, telemetry_file, mcap);
+    if diagnostics {
+        app.add_plugins((
+            CarDiagnosticsPlugin,
+            FrameTimeDiagnosticsPlugin,
+            LogDiagnosticsPlugin::default(),
+        ));
+    }
+    if !sim_args.headless {
+        // after the diagnostics, it adds the ones it shows that aren't there
+        app.add_plugins(PerformanceOverlayPlugin);
+    }
+    app.run();
+}
+
+fn build_car(
+    preset: Preset,
+    vectoring: bool,
+    flex: bool,
+    setup_file: Option<&str>,
+) -> CarDefinition {
+    let mut car_definition = preset.build();
+    if vectoring {
+        car_definition = car_definition.with_torque_vectoring(TorqueVectoring::new(2000., 600.));
+    }
+    if flex {
+        // about 1.7 kNm/deg, a fraction of a typical car
+        car_definition = car_definition.with_chassis_flex(ChassisFlex::new(100000.));
+    }
+    if let Some(path) = setup_file {
+        CarConfig::from_file(path.as_ref())
+            .unwrap_or_else(|error| panic!("{}", error))
+            .apply(&mut car_definition);
+    }
+    car_definition
+}
+
+// the terrain of a terrain file or snapshot, then the built in layouts
+fn menu_terrains(start: Option<TerrainDescription>) -> Vec<(String, TerrainDescription)> {
+    let layouts = [
+        ("test grid", TerrainLayout::TestGrid),
+        ("circuit", TerrainLayout::Circuit),
+        ("hill climb", TerrainLayout::HillClimb),
+        ("rock crawl", TerrainLayout::RockCrawl { seed: None }),
+        ("drift arena", TerrainLayout::DriftArena { cells: 6 }),
+        ("flat", TerrainLayout::Flat { cells: [20, 10] }),
+    ];
+    let layouts = layouts.into_iter().map(|(name, layout)| {
+        let terrain = TerrainDescription {
+            layout,
+            props: Vec::new(),
+        };
+        (name.to_string(), terrain)
+    });
+    let start = start.map(|terrain| ("start".to_string(), terrain));
+    start.into_iter().chain(layouts).collect()
+}
+
+fn add_recording(app: &mut App, telemetry_file: Option<String>, mcap: Option<McapLogger>) {
+    if telemetry_file.is_some() || mcap.is_some() {
+        app.insert_resource(Recorder::new(5)); // every 10 ms
+    }
+    if let Some(path) = telemetry_file {
+        app.insert_resource(TelemetryFile(path.into()));
+    }
+    if let Some(mcap) = mcap {
+        app.insert_resource(mcap);
+    }
 }
@@ -0,0 +1,132 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{SimTime, Solver};
+use cameras::control::CameraParentList;
+use car::{
+    audio::CarAudioPlugin,
+    build::{spawn_car, CarDefinition},
+    cone_test::{cone_test_setup, ConeTest, ConeTestRun},
+    control::UserControl,
+    environment::build_track_environment,
+    hud::hud_setup,
+    particles::tire_particles_setup,
+    presets::Preset,
+    setup::{camera_setup, simulation_setup},
+    skid_marks::skid_marks_setup,
+};
+use rigid_body::{
+    graphics::GraphicsSettings,
+    joint::{Base, Joint},
+    plugin::RigidBodyPlugin,
+    sva::Motion,
+};
+
+// A slalom or moose test (slalom or moose) laid out on flat ground away from the
+// circuit, with an optional vehicle preset and entry speed (km/h). The AI drives
+// the ideal line, `drive` to drive it yourself:
+// cargo run --example cone_test -- moose truck 60 drive
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let test = args.next().unwrap_or_else(|| "slalom".to_string());
+    let preset = match args.next() {
+        Some(name) => {
+            Preset::from_name(&name).unwrap_or_else(|| panic!("unknown vehicle preset: {}", name))
+        }
+        None => Preset::Car,
+    };
+    let mut speed = None;
+    let mut drive = false;
+    for arg in args {
+        if arg == "drive" {
+            drive = true;
+        } else {
+            let kmh: f64 = arg
+                .parse()
+                .unwrap_or_else(|_| panic!("unknown option: {}", arg));
+            speed = Some(kmh / 3.6);
+        }
+    }
+    let car = preset.build();
+    let (test, default_speed) = match test.as_str() {
+        "slalom" => (ConeTest::slalom(), 60.),
+        "moose" => (
+            ConeTest::MooseTest {
+                width: car.track_width(),
+            },
+            70.,
+        ),
+        name => panic!("unknown test: {}", name),
+    };
+    let speed = speed.unwrap_or(default_speed / 3.6);
+
+    App::new()
+        .add_plugins(RigidBodyPlugin {
+            time: SimTime::new(0.002, 0.0, None),
+            solver: Solver::RK4,
+            simulation_setup: vec![simulation_setup],
+            environment_setup: vec![
+                camera_setup,
+                hud_setup,
+                skid_marks_setup,
+                tire_particles_setup,
+                cone_test_setup,
+            ],
+            name: "cone_test".to_string(),
+            headless: false,
+            graphics: GraphicsSettings::default(),
+            single_threaded_physics: true,
+        })
+        .insert_resource(car)
+        .insert_resource(Test { test, speed, drive })
+        .add_systems(Startup, cone_test_startup_system)
+        .add_systems(Startup, build_track_environment)
+        .add_plugins(CarAudioPlugin)
+        .run();
+}
+
+#[derive(Resource)]
+struct Test {
+    test: ConeTest,
+    speed: f64,
+    drive: bool,
+}
+
+fn cone_test_startup_system(mut commands: Commands, car: Res<CarDefinition>, test: Res<Test>) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    // flat ground south west of the circuit, heading east, with room to get up to speed
+    let run_up = 150.;
+    let start = [-450., -150.];
+    let run = ConeTestRun::new(
+        test.test.clone(),
+        [start[0] + run_up, start[1]],
+        0.,
+        test.speed,
+    );
+    let z = car.initial_position()[2];
+    let entities = spawn_car(
+        &mut commands,
+        &car,
+        base_id,
+        [start[0], start[1], z],
+        0.,
+        Color::rgb(0.9, 0.1, 0.2),
+    );
+    if test.drive {
+        commands
+            .entity(entities.chassis)
+            .insert((run, UserControl::default()));
+    } else {
+        let driver = run.driver(run_up, car.max_curvature().unwrap_or(0.2));
+        commands.entity(entities.chassis).insert((run, driver));
+    }
+
+    let mut camera_parent_list = entities.camera_parents.clone();
+    camera_parent_list.push(base_id);
+    commands.insert_resource(CameraParentList {
+        list: camera_parent_list,
+        active: 0,
+    });
+    commands.insert_resource(entities);
+}
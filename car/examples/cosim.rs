@@ -0,0 +1,77 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{SimTime, Solver};
+use car::{
+    build::external_car_startup_system,
+    camera_sensor::{camera_sensor_setup, roof_camera, CarCameraSensor},
+    cosim::CoSimPlugin,
+    environment::{build_track_environment, build_track_terrain},
+    hud::hud_setup,
+    particles::tire_particles_setup,
+    presets::Preset,
+    setup::{camera_setup, simulation_setup},
+    skid_marks::skid_marks_setup,
+};
+use rigid_body::{graphics::GraphicsSettings, plugin::RigidBodyPlugin};
+
+// The car on the circuit, driven in lock step by an external controller over
+// ZeroMQ (see `car::cosim`), with an optional vehicle preset. The endpoint is
+// host:port for TCP, or a ZeroMQ endpoint (e.g. ipc:///tmp/cosim). `headless`
+// runs without a window. `camera` puts a camera on the roof, for the pictures of
+// `get_image` (not headless). car/examples/cosim_client.py is an example controller:
+// cargo run --example cosim -- 127.0.0.1:5555 truck headless
+fn main() {
+    let mut address = "tcp://127.0.0.1:5555".to_string();
+    let mut preset = Preset::Car;
+    let mut headless = false;
+    let mut camera = false;
+    for arg in std::env::args().skip(1) {
+        if arg == "headless" {
+            headless = true;
+        } else if arg == "camera" {
+            camera = true;
+        } else if arg.contains("://") {
+            address = arg;
+        } else if arg.contains(':') {
+            address = format!("tcp://{}", arg);
+        } else {
+            preset = Preset::from_name(&arg)
+                .unwrap_or_else(|| panic!("unknown vehicle preset: {}", arg));
+        }
+    }
+
+    let car = preset.build();
+    let mut environment_setup: Vec<fn(&mut App)> = vec![
+        camera_setup,
+        hud_setup,
+        skid_marks_setup,
+        tire_particles_setup,
+    ];
+    if camera {
+        environment_setup.push(camera_sensor_setup);
+    }
+
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, None),
+        solver: Solver::RK4,
+        simulation_setup: vec![simulation_setup],
+        environment_setup,
+        name: "cosim".to_string(),
+        headless,
+        graphics: GraphicsSettings::default(),
+        single_threaded_physics: true,
+    })
+    .add_plugins(CoSimPlugin { address });
+    if camera {
+        app.insert_resource(CarCameraSensor(roof_camera(&car)));
+    }
+    app.insert_resource(car)
+        .add_systems(Startup, external_car_startup_system);
+    if headless {
+        app.add_systems(Startup, build_track_terrain);
+    } else {
+        app.add_systems(Startup, build_track_environment);
+    }
+    app.run();
+}
@@ -0,0 +1,91 @@
+# Example co-simulation client for `cargo run --example cosim`: a speed
+# controller holds the car at a target speed, running every 10 time steps of
+# the simulation, in lock step with it. Needs pyzmq (pip install pyzmq):
+#   python3 car/examples/cosim_client.py --address tcp://127.0.0.1:5555
+# With `--camera` (and `cargo run --example cosim -- camera`) it also gets the
+# pictures of the roof camera, and prints their mean brightness.
+import argparse
+import json
+
+import zmq
+
+
+class CoSimClient:
+    def __init__(self, address):
+        self.context = zmq.Context()
+        self.socket = self.context.socket(zmq.REQ)
+        self.socket.connect(address)
+
+    # the reply, and the message parts after it
+    def request_parts(self, method, **fields):
+        self.socket.send_string(json.dumps({"method": method, **fields}))
+        header, *parts = self.socket.recv_multipart()
+        reply = json.loads(header)
+        if "error" in reply:
+            raise RuntimeError(reply["error"])
+        return reply, parts
+
+    def request(self, method, **fields):
+        return self.request_parts(method, **fields)[0]
+
+    def step(self, steps=1):
+        return self.request("step", steps=steps)
+
+    def set_input(self, **inputs):
+        return self.request("set_input", **inputs)
+
+    # the header of the latest picture, and its RGBA pixels
+    def get_image(self):
+        image, parts = self.request_parts("get_image")
+        return image, parts[0]
+
+    def reset(self):
+        return self.request("reset")
+
+    def close(self):
+        self.request("close")
+        self.socket.close()
+        self.context.term()
+
+
+def main():
+    parser = argparse.ArgumentParser()
+    parser.add_argument("--address", default="tcp://127.0.0.1:5555")
+    parser.add_argument("--speed", type=float, default=15.0, help="target speed (m/s)")
+    parser.add_argument("--time", type=float, default=10.0, help="simulated time (s)")
+    parser.add_argument("--camera", action="store_true", help="get the camera pictures")
+    args = parser.parse_args()
+
+    client = CoSimClient(args.address)
+    state = client.request("get_state")
+    print(f"{len(state['joints'])} joints: {', '.join(state['joints'])}")
+
+    gain, integral, period = 0.2, 0.0, 0.02
+    while state["time"] < args.time:
+        error = args.speed - state["chassis"]["velocity"][0]
+        # no windup while the car is still accelerating
+        if abs(error) < 2.0:
+            integral += error * period
+        command = gain * error + 0.05 * integral
+        client.set_input(throttle=max(command, 0.0), brake=max(-command, 0.0))
+        state = client.step(10)
+        if round(state["time"] / period) % 50 == 0:
+            print(f"{state['time']:5.1f} s  {state['chassis']['velocity'][0]:5.1f} m/s")
+            if args.camera:
+                try:
+                    image, pixels = client.get_image()
+                except RuntimeError as error:
+                    print(f"        {error}")
+                    continue
+                # mean of the color channels, without the alpha
+                color = sum(pixels) - sum(pixels[3::4])
+                brightness = color / (len(pixels) * 3 / 4) / 255
+                print(
+                    f"        picture {image['frame']} at {image['time']:.2f} s, "
+                    f"{image['width']}x{image['height']}, brightness {brightness:.2f}"
+                )
+    client.close()
+
+
+if __name__ == "__main__":
+    main()
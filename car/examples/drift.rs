@@ -0,0 +1,83 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{SimTime, Solver};
+use cameras::control::CameraParentList;
+use car::{
+    audio::CarAudioPlugin,
+    build::{spawn_car, CarDefinition},
+    control::UserControl,
+    drift::{drift_setup, DriftScore},
+    environment::build_drift_environment,
+    hud::hud_setup,
+    particles::tire_particles_setup,
+    presets::Preset,
+    setup::{camera_setup, simulation_setup},
+    skid_marks::skid_marks_setup,
+};
+use rigid_body::{
+    graphics::GraphicsSettings,
+    joint::{Base, Joint},
+    plugin::RigidBodyPlugin,
+    sva::Motion,
+};
+
+// Drift challenge in a walled arena, with an optional vehicle preset:
+// cargo run --example drift -- buggy
+fn main() {
+    let preset = match std::env::args().nth(1) {
+        Some(name) => {
+            Preset::from_name(&name).unwrap_or_else(|| panic!("unknown vehicle preset: {}", name))
+        }
+        None => Preset::Car,
+    };
+
+    App::new()
+        .add_plugins(RigidBodyPlugin {
+            time: SimTime::new(0.002, 0.0, None),
+            solver: Solver::RK4,
+            simulation_setup: vec![simulation_setup],
+            environment_setup: vec![
+                camera_setup,
+                hud_setup,
+                skid_marks_setup,
+                tire_particles_setup,
+                drift_setup,
+            ],
+            name: "drift".to_string(),
+            headless: false,
+            graphics: GraphicsSettings::default(),
+            single_threaded_physics: true,
+        })
+        .insert_resource(preset.build())
+        .add_systems(Startup, drift_startup_system)
+        .add_systems(Startup, build_drift_environment)
+        .add_plugins(CarAudioPlugin)
+        .run();
+}
+
+fn drift_startup_system(mut commands: Commands, car: Res<CarDefinition>) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    // south west corner of the arena, heading east
+    let z = car.initial_position()[2];
+    let entities = spawn_car(
+        &mut commands,
+        &car,
+        base_id,
+        [20., 20., z],
+        0.,
+        Color::rgb(0.9, 0.1, 0.2),
+    );
+    commands
+        .entity(entities.chassis)
+        .insert((UserControl::default(), DriftScore::default()));
+
+    let mut camera_parent_list = entities.camera_parents.clone();
+    camera_parent_list.push(base_id);
+    commands.insert_resource(CameraParentList {
+        list: camera_parent_list,
+        active: 0,
+    });
+    commands.insert_resource(entities);
+}
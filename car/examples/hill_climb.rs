@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{SimTime, Solver};
+use cameras::control::CameraParentList;
+use car::{
+    audio::CarAudioPlugin,
+    build::{spawn_car, CarDefinition},
+    control::UserControl,
+    environment::build_hill_climb_environment,
+    hill_climb::{hill_climb_setup, HillClimb},
+    hud::hud_setup,
+    particles::tire_particles_setup,
+    presets::Preset,
+    setup::{camera_setup, simulation_setup},
+    skid_marks::skid_marks_setup,
+};
+use grid_terrain::examples::hill_climb_road;
+use rigid_body::{
+    graphics::GraphicsSettings,
+    joint::{Base, Joint},
+    plugin::RigidBodyPlugin,
+    sva::Motion,
+};
+
+// Timed run up the switchback hill climb, or back down it where the brakes heat
+// up and fade, with an optional vehicle preset. The AI drives the centerline,
+// `drive` to drive it yourself:
+// cargo run --example hill_climb -- down truck drive
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let descent = match args.next().as_deref() {
+        Some("up") | None => false,
+        Some("down") => true,
+        Some(name) => panic!("unknown run: {}, up or down", name),
+    };
+    let preset = match args.next() {
+        Some(name) => {
+            Preset::from_name(&name).unwrap_or_else(|| panic!("unknown vehicle preset: {}", name))
+        }
+        None => Preset::Car,
+    };
+    let drive = match args.next().as_deref() {
+        Some("drive") => true,
+        Some(arg) => panic!("unknown option: {}", arg),
+        None => false,
+    };
+
+    App::new()
+        .add_plugins(RigidBodyPlugin {
+            time: SimTime::new(0.002, 0.0, None),
+            solver: Solver::RK4,
+            simulation_setup: vec![simulation_setup],
+            environment_setup: vec![
+                camera_setup,
+                hud_setup,
+                skid_marks_setup,
+                tire_particles_setup,
+                hill_climb_setup,
+            ],
+            name: "hill_climb".to_string(),
+            headless: false,
+            graphics: GraphicsSettings::default(),
+            single_threaded_physics: true,
+        })
+        .insert_resource(preset.build())
+        .insert_resource(Run { descent, drive })
+        .add_systems(Startup, hill_climb_startup_system)
+        .add_systems(Startup, build_hill_climb_environment)
+        .add_plugins(CarAudioPlugin)
+        .run();
+}
+
+#[derive(Resource)]
+struct Run {
+    descent: bool,
+    drive: bool,
+}
+
+fn hill_climb_startup_system(mut commands: Commands, car: Res<CarDefinition>, run: Res<Run>) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    let mut centerline = hill_climb_road().centerline();
+    if run.descent {
+        centerline.reverse();
+    }
+    let path: Vec<[f64; 2]> = centerline.iter().map(|(point, _)| *point).collect();
+    let name = if run.descent { "descent" } else { "ascent" };
+    // a short run up to the start line, and room to stop after the finish
+    let hill_climb = HillClimb::new(name, path, 20., 60.);
+
+    let (position, heading) = hill_climb.start_pose(15.);
+    let elevation = centerline
+        .iter()
+        .min_by(|(a, _), (b, _)| {
+            let distance = |p: &[f64; 2]| (p[0] - position[0]).hypot(p[1] - position[1]);
+            distance(a).total_cmp(&distance(b))
+        })
+        .map_or(0., |(_, elevation)| *elevation);
+    let z = elevation + car.initial_position()[2];
+    let entities = spawn_car(
+        &mut commands,
+        &car,
+        base_id,
+        [position[0], position[1], z],
+        heading,
+        Color::rgb(0.1, 0.4, 0.9),
+    );
+    if run.drive {
+        commands
+            .entity(entities.chassis)
+            .insert((hill_climb, UserControl::default()));
+    } else {
+        let driver = hill_climb.driver(car.max_curvature().unwrap_or(0.2), 30.);
+        commands
+            .entity(entities.chassis)
+            .insert((hill_climb, driver));
+    }
+
+    let mut camera_parent_list = entities.camera_parents.clone();
+    camera_parent_list.push(base_id);
+    commands.insert_resource(CameraParentList {
+        list: camera_parent_list,
+        active: 0,
+    });
+    commands.insert_resource(entities);
+}
@@ -0,0 +1,151 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{ExitEvent, SimTime, Solver};
+use cameras::control::CameraParentList;
+use car::{
+    audio::CarAudioPlugin,
+    build::{spawn_car, CarDefinition},
+    environment::{build_track_environment, build_track_terrain},
+    hud::hud_setup,
+    maneuver::{maneuver_cones_setup, ConeCourse, Maneuver, ManeuverRunner},
+    particles::tire_particles_setup,
+    presets::Preset,
+    setup::{camera_setup, simulation_setup},
+    skid_marks::skid_marks_setup,
+    torque_vectoring::TorqueVectoring,
+};
+use rigid_body::{
+    graphics::GraphicsSettings,
+    joint::{Base, Joint},
+    plugin::RigidBodyPlugin,
+    sva::Motion,
+};
+
+// Runs a test maneuver (step, sine, lane_change or radius) on flat ground away
+// from the circuit and logs the metrics, with an optional vehicle preset, and
+// optionally with torque vectoring for comparison. `cones` lays out cone gates
+// along the ideal path and counts the cones hit. `headless` runs without a
+// window, as fast as it can, and exits when the maneuver is complete:
+// cargo run --example maneuver -- sine truck vectoring cones
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let maneuver = args.next().unwrap_or_else(|| "step".to_string());
+    let preset = match args.next() {
+        Some(name) => {
+            Preset::from_name(&name).unwrap_or_else(|| panic!("unknown vehicle preset: {}", name))
+        }
+        None => Preset::Car,
+    };
+    let mut car = preset.build();
+    let mut cones = false;
+    let mut headless = false;
+    for arg in args {
+        match arg.as_str() {
+            "vectoring" => car = car.with_torque_vectoring(TorqueVectoring::new(2000., 600.)),
+            "cones" => cones = true,
+            "headless" => headless = true,
+            name => panic!("unknown option: {}", name),
+        }
+    }
+    let (maneuver, speed) = match maneuver.as_str() {
+        "step" => (
+            Maneuver::StepSteer {
+                steering: 0.3,
+                rate: 2.,
+                hold: 4.,
+            },
+            20.,
+        ),
+        "sine" => (Maneuver::sine_with_dwell(0.4), 22.),
+        "lane_change" => (
+            Maneuver::DoubleLaneChange {
+                amplitude: 0.15,
+                period: 2.,
+                gap: 1.,
+            },
+            20.,
+        ),
+        "radius" => (
+            Maneuver::ConstantRadius {
+                radius: 40.,
+                max_curvature: car.max_curvature().unwrap_or(0.2),
+                acceleration: 0.3,
+                max_speed: 25.,
+            },
+            5.,
+        ),
+        name => panic!("unknown maneuver: {}", name),
+    };
+
+    let mut runner = ManeuverRunner::new(maneuver, speed);
+    if cones {
+        runner = runner.with_cones(ConeCourse {
+            max_curvature: car.lock_curvature(),
+            width: car.track_width() + 1.,
+            spacing: 6.,
+        });
+    }
+
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, None),
+        solver: Solver::RK4,
+        simulation_setup: vec![simulation_setup],
+        environment_setup: vec![
+            camera_setup,
+            hud_setup,
+            skid_marks_setup,
+            tire_particles_setup,
+            maneuver_cones_setup,
+        ],
+        name: "maneuver".to_string(),
+        headless,
+        graphics: GraphicsSettings::default(),
+        single_threaded_physics: true,
+    })
+    .insert_resource(car)
+    .insert_resource(Runner(runner))
+    .add_systems(Startup, maneuver_startup_system);
+    if headless {
+        app.add_systems(Startup, build_track_terrain)
+            .add_systems(Update, maneuver_exit_system);
+    } else {
+        app.add_systems(Startup, build_track_environment)
+            .add_plugins(CarAudioPlugin);
+    }
+    app.run();
+}
+
+fn maneuver_exit_system(runners: Query<&ManeuverRunner>, mut exit: EventWriter<ExitEvent>) {
+    if !runners.is_empty() && runners.iter().all(|runner| runner.is_finished()) {
+        exit.send(ExitEvent);
+    }
+}
+
+#[derive(Resource)]
+struct Runner(ManeuverRunner);
+
+fn maneuver_startup_system(mut commands: Commands, car: Res<CarDefinition>, runner: Res<Runner>) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    // flat ground south west of the circuit, heading east
+    let z = car.initial_position()[2];
+    let entities = spawn_car(
+        &mut commands,
+        &car,
+        base_id,
+        [-400., -150., z],
+        0.,
+        Color::rgb(0.9, 0.1, 0.2),
+    );
+    commands.entity(entities.chassis).insert(runner.0.clone());
+
+    let mut camera_parent_list = entities.camera_parents.clone();
+    camera_parent_list.push(base_id);
+    commands.insert_resource(CameraParentList {
+        list: camera_parent_list,
+        active: 0,
+    });
+    commands.insert_resource(entities);
+}
@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{SimSeed, SimTime, Solver};
+use cameras::control::CameraParentList;
+use car::{
+    audio::CarAudioPlugin,
+    build::{build_car, spawn_car, CarDefinition, Drivetrain},
+    control::UserControl,
+    environment::build_track_environment,
+    hud::hud_setup,
+    mirror::mirror_setup,
+    particles::tire_particles_setup,
+    race::{spawn_opponents, Race, Racer},
+    setup::{camera_setup, simulation_setup},
+    skid_marks::skid_marks_setup,
+};
+use grid_terrain::examples::circuit_track;
+use rigid_body::{
+    graphics::GraphicsSettings,
+    joint::{Base, Joint},
+    plugin::RigidBodyPlugin,
+    sva::Motion,
+};
+
+// Race the AI around the circuit, starting from the back of the grid. Pass the
+// number of opponents: cargo run --example race -- 5
+fn main() {
+    let opponents = match std::env::args().nth(1) {
+        Some(count) => count
+            .parse()
+            .unwrap_or_else(|_| panic!("not a number of opponents: {}", count)),
+        None => 3,
+    };
+
+    App::new()
+        .add_plugins(RigidBodyPlugin {
+            time: SimTime::new(0.002, 0.0, None),
+            solver: Solver::RK4,
+            simulation_setup: vec![simulation_setup],
+            environment_setup: vec![
+                camera_setup,
+                hud_setup,
+                skid_marks_setup,
+                tire_particles_setup,
+                mirror_setup,
+            ],
+            name: "race".to_string(),
+            headless: false,
+            graphics: GraphicsSettings::default(),
+            single_threaded_physics: true,
+        })
+        .insert_resource(build_car(Drivetrain::RearWheelDrive))
+        .insert_resource(Race::new(circuit_track().centerline(), 4.).camera_director())
+        .insert_resource(Race::new(circuit_track().centerline(), 4.))
+        .insert_resource(Opponents(opponents))
+        .add_systems(Startup, race_startup_system)
+        .add_systems(Startup, build_track_environment)
+        .add_systems(Update, position_report_system)
+        .add_plugins(CarAudioPlugin)
+        .run();
+}
+
+#[derive(Resource)]
+struct Opponents(usize);
+
+fn race_startup_system(
+    mut commands: Commands,
+    car: Res<CarDefinition>,
+    race: Res<Race>,
+    opponents: Res<Opponents>,
+    seed: Res<SimSeed>,
+) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    let seed = seed.stream("opponents");
+    spawn_opponents(&mut commands, &car, base_id, &race, opponents.0, 0, seed);
+
+    // the player starts at the back
+    let (position, heading) = race.grid_slot(opponents.0);
+    let player = spawn_car(
+        &mut commands,
+        &car,
+        base_id,
+        [position[0], position[1], car.initial_position()[2]],
+        heading,
+        Color::rgb(0.9, 0.9, 0.9),
+    );
+    commands
+        .entity(player.chassis)
+        .insert((UserControl::default(), Racer::new("player")));
+
+    let mut camera_parent_list = player.camera_parents.clone();
+    camera_parent_list.push(base_id);
+    commands.insert_resource(CameraParentList {
+        list: camera_parent_list,
+        active: 0,
+    });
+    commands.insert_resource(player);
+}
+
+fn position_report_system(
+    players: Query<&Racer, With<UserControl>>,
+    mut last_position: Local<usize>,
+) {
+    for racer in players.iter() {
+        if racer.position != *last_position {
+            *last_position = racer.position;
+            info!("{} is P{}", racer.name, racer.position);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{SimTime, Solver};
+use cameras::control::CameraParentList;
+use car::{
+    audio::CarAudioPlugin,
+    build::{spawn_car, CarDefinition},
+    control::UserControl,
+    environment::build_rock_crawl_environment,
+    hud::hud_setup,
+    particles::tire_particles_setup,
+    presets::Preset,
+    setup::{camera_setup, simulation_setup},
+    skid_marks::skid_marks_setup,
+    wheel_load::wheel_load_setup,
+};
+use rigid_body::{
+    graphics::GraphicsSettings,
+    joint::{Base, Joint},
+    plugin::RigidBodyPlugin,
+    sva::Motion,
+};
+
+// Rock crawling over boulder fields and a ledge in the low range of the
+// transfer case (`L` switches to the high range), with the tire loads shown to
+// follow the articulation, and an optional vehicle preset:
+// cargo run --example rock_crawl -- 6x6
+fn main() {
+    let preset = match std::env::args().nth(1) {
+        Some(name) => {
+            Preset::from_name(&name).unwrap_or_else(|| panic!("unknown vehicle preset: {}", name))
+        }
+        None => Preset::Buggy,
+    };
+    // presets with a transfer case keep their low range
+    let mut car = preset.build();
+    let low_range = car.low_range().unwrap_or(2.7);
+    car = car.with_low_range(low_range);
+
+    App::new()
+        .add_plugins(RigidBodyPlugin {
+            time: SimTime::new(0.002, 0.0, None),
+            solver: Solver::RK4,
+            simulation_setup: vec![simulation_setup],
+            environment_setup: vec![
+                camera_setup,
+                hud_setup,
+                skid_marks_setup,
+                tire_particles_setup,
+                wheel_load_setup,
+            ],
+            name: "rock_crawl".to_string(),
+            headless: false,
+            graphics: GraphicsSettings::default(),
+            single_threaded_physics: true,
+        })
+        .insert_resource(car)
+        .add_systems(Startup, rock_crawl_startup_system)
+        .add_systems(Startup, build_rock_crawl_environment)
+        .add_plugins(CarAudioPlugin)
+        .run();
+}
+
+fn rock_crawl_startup_system(mut commands: Commands, car: Res<CarDefinition>) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    // on flat ground before the course, which runs along y up the middle column
+    let entities = spawn_car(
+        &mut commands,
+        &car,
+        base_id,
+        [30., -12., car.initial_position()[2]],
+        std::f64::consts::FRAC_PI_2,
+        Color::rgb(0.9, 0.6, 0.1),
+    );
+    commands
+        .entity(entities.chassis)
+        .insert(UserControl::default());
+
+    let mut camera_parent_list = entities.camera_parents.clone();
+    camera_parent_list.push(base_id);
+    commands.insert_resource(CameraParentList {
+        list: camera_parent_list,
+        active: 0,
+    });
+    commands.insert_resource(entities);
+}
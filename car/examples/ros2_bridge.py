@@ -0,0 +1,103 @@
+# ROS 2 node for the car demo's ROS bridge (`car::ros::RosBridge`). It receives
+# the car's state as JSON over UDP and publishes it on ROS 2 topics (/clock,
+# /odom, /imu, /wheel_speeds, /tf, and /points with a lidar), and sends the drive commands of /cmd_drive
+# back to the demo. In a sourced ROS 2 environment:
+#   python3 car/examples/ros2_bridge.py --port 9870
+#   cargo run --example car -- ros=127.0.0.1:9870
+# /cmd_drive is a sensor_msgs/Joy with the axes [steering, throttle, brake,
+# handbrake, clutch], steering from -1 (right) to 1 (left) and the pedals from 0
+# to 1, so a joystick (joy_node) can drive the car directly.
+import argparse
+import json
+import socket
+
+import rclpy
+from rclpy.node import Node
+from rosidl_runtime_py.set_message import set_message_fields
+from rosidl_runtime_py.utilities import get_message
+from sensor_msgs.msg import Joy
+from sensor_msgs_py import point_cloud2
+from std_msgs.msg import Header
+
+AXES = ["steering", "throttle", "brake", "handbrake", "clutch"]
+
+
+class CarBridge(Node):
+    def __init__(self, port):
+        super().__init__("car_demo_bridge")
+        self.socket = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+        self.socket.bind(("0.0.0.0", port))
+        self.socket.setblocking(False)
+        self.demo = None  # address the state comes from, for the commands
+        self.publishers_by_topic = {}
+        self.scan = None  # lidar scan being received, and its parts
+        self.scan_parts = {}
+        self.create_subscription(Joy, "/cmd_drive", self.drive_command, 10)
+        self.create_timer(0.002, self.receive)
+        self.get_logger().info(f"waiting for the car demo on UDP port {port}")
+
+    def publisher(self, topic, message_type):
+        if topic not in self.publishers_by_topic:
+            message_class = get_message(message_type)
+            self.publishers_by_topic[topic] = (
+                message_class,
+                self.create_publisher(message_class, topic, 10),
+            )
+        return self.publishers_by_topic[topic]
+
+    def receive(self):
+        while True:
+            try:
+                data, address = self.socket.recvfrom(65536)
+            except BlockingIOError:
+                return
+            if self.demo is None:
+                self.get_logger().info(f"car demo at {address[0]}:{address[1]}")
+            self.demo = address
+            packet = json.loads(data)
+            if "cloud" in packet:
+                self.point_cloud(packet)
+                continue
+            message_class, publisher = self.publisher(packet["topic"], packet["type"])
+            message = message_class()
+            set_message_fields(message, packet["msg"])
+            publisher.publish(message)
+
+    # lidar scans come in parts of points in mm, published once all have come
+    def point_cloud(self, packet):
+        cloud = packet["cloud"]
+        if cloud["scan"] != self.scan:
+            self.scan = cloud["scan"]
+            self.scan_parts = {}
+        self.scan_parts[cloud["part"]] = cloud["points"]
+        if len(self.scan_parts) < cloud["parts"]:
+            return
+        points = [
+            [value / 1000.0 for value in point]
+            for part in sorted(self.scan_parts)
+            for point in self.scan_parts[part]
+        ]
+        header = Header()
+        set_message_fields(header, cloud["header"])
+        _, publisher = self.publisher(packet["topic"], packet["type"])
+        publisher.publish(point_cloud2.create_cloud_xyz32(header, points))
+
+    def drive_command(self, joy):
+        if self.demo is None:
+            return
+        command = {name: float(value) for name, value in zip(AXES, joy.axes)}
+        for name in AXES:
+            command.setdefault(name, 0.0)
+        self.socket.sendto(json.dumps(command).encode(), self.demo)
+
+
+def main():
+    parser = argparse.ArgumentParser()
+    parser.add_argument("--port", type=int, default=9870)
+    args, ros_args = parser.parse_known_args()
+    rclpy.init(args=ros_args)
+    rclpy.spin(CarBridge(args.port))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,86 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+
+use bevy_integrator::{recorder::Recorder, SimSeed, SimTime, Solver};
+use car::{
+    audio::CarAudioPlugin,
+    hud::hud_setup,
+    maneuver::maneuver_cones_setup,
+    mcap::McapLogger,
+    particles::tire_particles_setup,
+    scenario::{scenario_startup_system, Scenario, ScenarioRun},
+    setup::{camera_setup, simulation_setup},
+    skid_marks::skid_marks_setup,
+    telemetry::TelemetryFile,
+    terrain::{build_described_environment, build_described_terrain},
+};
+use rigid_body::{graphics::GraphicsSettings, plugin::RigidBodyPlugin};
+
+// Runs a scenario file (see `car::scenario` and car/examples/scenarios): the car,
+// terrain, start pose, maneuver or input script, end conditions and outputs of a
+// test case. `headless` runs without a window, as fast as it can, e.g. to run
+// scenarios from scripts:
+// cargo run --example scenario -- car/examples/scenarios/sine_with_dwell.toml headless
+fn main() {
+    let mut path = None;
+    let mut headless = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "headless" => headless = true,
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.unwrap_or_else(|| "car/examples/scenarios/sine_with_dwell.toml".to_string());
+    let scenario =
+        Scenario::from_file(Path::new(&path)).unwrap_or_else(|error| panic!("{}", error));
+    let car = scenario.car().unwrap_or_else(|error| panic!("{}", error));
+    let terrain = scenario
+        .terrain()
+        .unwrap_or_else(|error| panic!("{}", error));
+    scenario
+        .create_output_dirs()
+        .unwrap_or_else(|error| panic!("{}", error));
+    let start = scenario.start_pose(&car, &terrain);
+
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(scenario.dt, 0.0, None),
+        solver: Solver::RK4,
+        simulation_setup: vec![simulation_setup],
+        environment_setup: vec![
+            camera_setup,
+            hud_setup,
+            skid_marks_setup,
+            tire_particles_setup,
+            maneuver_cones_setup,
+        ],
+        name: format!("scenario: {}", scenario.name),
+        headless,
+        graphics: GraphicsSettings::default(),
+        single_threaded_physics: true,
+    });
+    let outputs = &scenario.outputs;
+    if outputs.telemetry.is_some() || outputs.mcap.is_some() {
+        app.insert_resource(Recorder::new(outputs.telemetry_interval.max(1)));
+    }
+    if let Some(telemetry) = &outputs.telemetry {
+        app.insert_resource(TelemetryFile(telemetry.clone()));
+    }
+    if let Some(mcap) = &outputs.mcap {
+        app.insert_resource(McapLogger::new(mcap));
+    }
+    app.insert_resource(car)
+        .insert_resource(SimSeed(scenario.seed))
+        .insert_resource(terrain)
+        .insert_resource(ScenarioRun::new(start))
+        .insert_resource(scenario)
+        .add_systems(Startup, scenario_startup_system);
+    if headless {
+        app.add_systems(Startup, build_described_terrain);
+    } else {
+        app.add_systems(Startup, build_described_environment)
+            .add_plugins(CarAudioPlugin);
+    }
+    app.run();
+}
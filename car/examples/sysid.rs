@@ -0,0 +1,114 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+
+use bevy_integrator::{ExitEvent, SimSeed};
+use cameras::control::CameraParentList;
+use car::{
+    audio::CarAudioPlugin,
+    build::{spawn_car, CarDefinition},
+    hud::hud_setup,
+    particles::tire_particles_setup,
+    setup::{camera_setup, simulation_setup},
+    skid_marks::skid_marks_setup,
+    sysid::{SysIdConfig, SysIdRunner},
+    terrain::{
+        build_described_environment, build_described_terrain, TerrainDescription, TerrainLayout,
+    },
+};
+use rigid_body::{
+    cli::SimArgs,
+    joint::{Base, Joint},
+    sva::Motion,
+};
+
+// Excites the car with the input signals of a system identification file (see
+// `car::sysid` and car/examples/sysid.toml), a chirp or PRBS on the steering and
+// throttle at speed, on flat ground, and writes the sampled inputs and response
+// for system identification tools. Exits when the dataset is written. Takes the
+// simulation options of `rigid_body::cli::SimArgs`, e.g. `headless` or `seed=7`:
+// cargo run --example sysid -- car/examples/sysid.toml headless
+fn main() {
+    let mut path = None;
+    let mut sim_args = SimArgs::new(0.002, None);
+    for arg in std::env::args().skip(1) {
+        if !sim_args
+            .parse(&arg)
+            .unwrap_or_else(|error| panic!("{}", error))
+        {
+            path = Some(arg);
+        }
+    }
+    let path = path.unwrap_or_else(|| "car/examples/sysid.toml".to_string());
+    let config =
+        SysIdConfig::from_file(Path::new(&path)).unwrap_or_else(|error| panic!("{}", error));
+    let car = config.car().unwrap_or_else(|error| panic!("{}", error));
+    config
+        .create_output_dir()
+        .unwrap_or_else(|error| panic!("{}", error));
+
+    // 1.6 km of flat ground along x, 800 m across
+    let terrain = TerrainDescription {
+        layout: TerrainLayout::Flat { cells: [80, 40] },
+        props: Vec::new(),
+    };
+
+    let mut app = App::new();
+    app.add_plugins(sim_args.plugin(
+        "system identification",
+        vec![simulation_setup],
+        vec![
+            camera_setup,
+            hud_setup,
+            skid_marks_setup,
+            tire_particles_setup,
+        ],
+    ))
+    .insert_resource(car)
+    .insert_resource(terrain)
+    .insert_resource(SimSeed(sim_args.seed))
+    .insert_resource(Runner(config.runner()))
+    .add_systems(Startup, sysid_startup_system)
+    .add_systems(Update, sysid_exit_system);
+    if sim_args.headless {
+        app.add_systems(Startup, build_described_terrain);
+    } else {
+        app.add_systems(Startup, build_described_environment)
+            .add_plugins(CarAudioPlugin);
+    }
+    app.run();
+}
+
+fn sysid_exit_system(runners: Query<&SysIdRunner>, mut exit: EventWriter<ExitEvent>) {
+    if !runners.is_empty() && runners.iter().all(|runner| runner.is_finished()) {
+        exit.send(ExitEvent);
+    }
+}
+
+#[derive(Resource)]
+struct Runner(SysIdRunner);
+
+fn sysid_startup_system(mut commands: Commands, car: Res<CarDefinition>, runner: Res<Runner>) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    // near the west end of the flat ground, in the middle, heading east
+    let z = car.initial_position()[2];
+    let entities = spawn_car(
+        &mut commands,
+        &car,
+        base_id,
+        [100., 400., z],
+        0.,
+        Color::rgb(0.9, 0.1, 0.2),
+    );
+    commands.entity(entities.chassis).insert(runner.0.clone());
+
+    let mut camera_parent_list = entities.camera_parents.clone();
+    camera_parent_list.push(base_id);
+    commands.insert_resource(CameraParentList {
+        list: camera_parent_list,
+        active: 0,
+    });
+    commands.insert_resource(entities);
+}
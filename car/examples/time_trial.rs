@@ -0,0 +1,86 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{SimTime, Solver};
+use cameras::control::CameraParentList;
+use car::{
+    audio::CarAudioPlugin,
+    build::{spawn_car, CarDefinition},
+    control::UserControl,
+    environment::build_track_environment,
+    hud::hud_setup,
+    particles::tire_particles_setup,
+    presets::Preset,
+    race::{Race, Racer},
+    setup::{camera_setup, simulation_setup},
+    skid_marks::skid_marks_setup,
+    time_trial::{time_trial_setup, TimeTrial},
+};
+use grid_terrain::examples::circuit_track;
+use rigid_body::{
+    graphics::GraphicsSettings,
+    joint::{Base, Joint},
+    plugin::RigidBodyPlugin,
+    sva::Motion,
+};
+
+// Time trial around the circuit, with an optional vehicle preset. The best lap
+// of each car is kept between runs and driven by a ghost:
+// cargo run --example time_trial -- kart
+fn main() {
+    let name = std::env::args().nth(1).unwrap_or("car".to_string());
+    let preset =
+        Preset::from_name(&name).unwrap_or_else(|| panic!("unknown vehicle preset: {}", name));
+
+    App::new()
+        .add_plugins(RigidBodyPlugin {
+            time: SimTime::new(0.002, 0.0, None),
+            solver: Solver::RK4,
+            simulation_setup: vec![simulation_setup],
+            environment_setup: vec![
+                camera_setup,
+                hud_setup,
+                skid_marks_setup,
+                tire_particles_setup,
+                time_trial_setup,
+            ],
+            name: "time_trial".to_string(),
+            headless: false,
+            graphics: GraphicsSettings::default(),
+            single_threaded_physics: true,
+        })
+        .insert_resource(preset.build())
+        .insert_resource(Race::new(circuit_track().centerline(), 4.).camera_director())
+        .insert_resource(Race::new(circuit_track().centerline(), 4.))
+        .insert_resource(TimeTrial::new("circuit", &name))
+        .add_systems(Startup, time_trial_startup_system)
+        .add_systems(Startup, build_track_environment)
+        .add_plugins(CarAudioPlugin)
+        .run();
+}
+
+fn time_trial_startup_system(mut commands: Commands, car: Res<CarDefinition>, race: Res<Race>) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    // the out lap starts just behind the line
+    let (position, heading) = race.grid_slot(0);
+    let player = spawn_car(
+        &mut commands,
+        &car,
+        base_id,
+        [position[0], position[1], car.initial_position()[2]],
+        heading,
+        Color::rgb(0.9, 0.9, 0.9),
+    );
+    commands
+        .entity(player.chassis)
+        .insert((UserControl::default(), Racer::new("player")));
+
+    let mut camera_parent_list = player.camera_parents.clone();
+    camera_parent_list.push(base_id);
+    commands.insert_resource(CameraParentList {
+        list: camera_parent_list,
+        active: 0,
+    });
+    commands.insert_resource(player);
+}
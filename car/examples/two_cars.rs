@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{SimTime, Solver};
+use cameras::{
+    control::CameraParentList,
+    viewport::{main_viewport, spawn_viewport_camera, CameraViewport},
+};
+use car::{
+    audio::CarAudioPlugin,
+    build::{build_car, spawn_car, CarDefinition, Drivetrain},
+    camera_presets::CameraPreset,
+    control::{GamepadInput, UserControl},
+    environment::build_environment,
+    hud::hud_setup,
+    particles::tire_particles_setup,
+    setup::{camera_setup, simulation_setup},
+    skid_marks::skid_marks_setup,
+};
+use rigid_body::{
+    graphics::GraphicsSettings,
+    joint::{Base, Joint},
+    plugin::RigidBodyPlugin,
+    sva::Motion,
+};
+
+// Two cars side by side, the red one is driven with the keyboard and the blue
+// one with a gamepad. With `split` the window is split, the top view follows
+// the red car and the bottom one the blue car:
+// cargo run --example two_cars -- split
+fn main() {
+    let split = matches!(std::env::args().nth(1).as_deref(), Some("split"));
+
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, None),
+        solver: Solver::RK4,
+        simulation_setup: vec![simulation_setup],
+        environment_setup: vec![
+            camera_setup,
+            hud_setup,
+            skid_marks_setup,
+            tire_particles_setup,
+        ],
+        name: "two_cars".to_string(),
+        headless: false,
+        graphics: GraphicsSettings::default(),
+        single_threaded_physics: true,
+    })
+    .insert_resource(build_car(Drivetrain::RearWheelDrive))
+    .add_systems(Startup, two_cars_startup_system)
+    .add_systems(Startup, build_environment)
+    .add_plugins(CarAudioPlugin);
+    if split {
+        app.insert_resource(SplitScreen)
+            .add_systems(PostStartup, main_viewport(CameraViewport::row(0, 2)));
+    }
+    app.run();
+}
+
+// marks the split screen mode
+#[derive(Resource)]
+struct SplitScreen;
+
+fn two_cars_startup_system(
+    mut commands: Commands,
+    car: Res<CarDefinition>,
+    split: Option<Res<SplitScreen>>,
+) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    let [x, y, z] = car.initial_position();
+    let keyboard_car = spawn_car(
+        &mut commands,
+        &car,
+        base_id,
+        [x, y + 2.5, z],
+        0.,
+        Color::rgb(0.9, 0.1, 0.2),
+    );
+    commands.entity(keyboard_car.chassis).insert(UserControl {
+        keyboard: true,
+        gamepad: GamepadInput::None,
+    });
+
+    let gamepad_car = spawn_car(
+        &mut commands,
+        &car,
+        base_id,
+        [x, y - 2.5, z],
+        0.,
+        Color::rgb(0.1, 0.2, 0.9),
+    );
+    commands.entity(gamepad_car.chassis).insert(UserControl {
+        keyboard: false,
+        gamepad: GamepadInput::Any,
+    });
+
+    // the camera follows the keyboard car
+    let mut camera_parent_list = keyboard_car.camera_parents.clone();
+    camera_parent_list.push(base_id);
+    commands.insert_resource(CameraParentList {
+        list: camera_parent_list,
+        active: 0,
+    });
+
+    // the blue car gets its own view, `Tab` cycles its camera parents
+    if split.is_some() {
+        let mut camera_parent_list = gamepad_car.camera_parents.clone();
+        camera_parent_list.push(base_id);
+        spawn_viewport_camera(
+            &mut commands,
+            CameraPreset::default().az_el(),
+            CameraViewport::row(1, 2)
+                .with_parents(CameraParentList {
+                    list: camera_parent_list,
+                    active: 0,
+                })
+                .with_cycle_key(KeyCode::Tab),
+            1,
+        );
+    }
+    commands.insert_resource(keyboard_car);
+}
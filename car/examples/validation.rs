@@ -0,0 +1,47 @@
+use rigid_body::{cli::SimArgs, validation::ValidationCase};
+
+// Runs the validation cases, models with a closed-form solution simulated
+// headless (`rigid_body::validation` and `car::validation`): single and double
+// pendulum energy, a cylinder rolling down a slope, and the car cornering at a
+// steady state. The error norms of each case are logged, and it panics if a case
+// fails, so a solver or algorithm change can be checked quickly. Takes the solver
+// and time step of `rigid_body::cli::SimArgs`, and the names of the cases to run
+// (all by default):
+// cargo run --example validation -- solver=heun dt=0.001 pendulum rolling_cylinder
+fn main() {
+    let mut names = Vec::new();
+    let mut sim_args = SimArgs::new(0.002, None);
+    for arg in std::env::args().skip(1) {
+        if !sim_args
+            .parse(&arg)
+            .unwrap_or_else(|error| panic!("{}", error))
+        {
+            names.push(arg);
+        }
+    }
+
+    let cases: Vec<ValidationCase> = rigid_body::validation::validation_cases()
+        .into_iter()
+        .chain(car::validation::validation_cases())
+        .collect();
+    for name in names.iter() {
+        if !cases.iter().any(|case| case.name == *name) {
+            panic!("unknown validation case: {}", name);
+        }
+    }
+
+    let mut failed = Vec::new();
+    for case in cases
+        .iter()
+        .filter(|case| names.is_empty() || names.contains(&case.name))
+    {
+        let report = case.run(&sim_args);
+        report.log();
+        if !report.passed() {
+            failed.push(case.name.clone());
+        }
+    }
+    if !failed.is_empty() {
+        panic!("validation failed: {}", failed.join(", "));
+    }
+}
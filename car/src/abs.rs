@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::tire::PointTire;
+
+use super::control::{CarControl, CarPart};
+
+// Anti-lock braking on a braked wheel. At a fixed cycle rate, the brake pressure
+// is released when the wheel slips more than the target, and reapplied otherwise.
+#[derive(Component, Clone)]
+pub struct Abs {
+    pub enabled: bool,
+    pub target_slip: f64, // slip ratio magnitude
+    pub cycle_rate: f64,  // pressure updates per second
+    pub release: f64,     // pressure fraction removed per cycle when slipping
+    pub apply: f64,       // pressure fraction added per cycle otherwise
+    pub outputs: HashMap<String, f64>,
+    pressure: f64, // fraction of the requested brake torque
+    timer: f64,
+}
+
+impl Abs {
+    pub fn new(target_slip: f64, cycle_rate: f64) -> Self {
+        Self {
+            enabled: true,
+            target_slip,
+            cycle_rate,
+            release: 0.5,
+            apply: 0.2,
+            outputs: HashMap::new(),
+            pressure: 1.,
+            timer: 0.,
+        }
+    }
+
+    // multiplier on the brake torque
+    pub fn pressure(&self) -> f64 {
+        if self.enabled {
+            self.pressure
+        } else {
+            1.
+        }
+    }
+
+    fn update(&mut self, slip_ratio: f64) {
+        if slip_ratio < -self.target_slip {
+            self.pressure = (self.pressure - self.release).max(0.);
+        } else {
+            self.pressure = (self.pressure + self.apply).min(1.);
+        }
+    }
+}
+
+// runs once per time step (not in the physics schedule), like the transmission
+pub fn abs_system(
+    fixed_time: Res<FixedTime>,
+    mut controls: Query<&mut CarControl>,
+    tires: Query<&PointTire>,
+    mut wheels: Query<(Entity, &mut Abs, &CarPart)>,
+) {
+    let dt = fixed_time.period.as_secs_f64();
+    let slip_ratios: HashMap<Entity, f64> = tires
+        .iter()
+        .map(|tire| (tire.joint_entity(), tire.slip_ratio()))
+        .collect();
+
+    for (entity, mut abs, part) in wheels.iter_mut() {
+        let control = match controls.get(part.0) {
+            Ok(control) => control,
+            Err(_) => continue,
+        };
+        if control.toggle_abs {
+            abs.enabled = !abs.enabled;
+        }
+
+        if control.brake <= 0. {
+            // full pressure is available as soon as the brake is pressed
+            abs.pressure = 1.;
+            abs.timer = 0.;
+        } else {
+            abs.timer += dt;
+            if abs.timer >= 1. / abs.cycle_rate {
+                abs.timer = 0.;
+                abs.update(slip_ratios.get(&entity).copied().unwrap_or(0.));
+            }
+        }
+
+        let pressure = abs.pressure();
+        abs.outputs.insert("pressure".to_string(), pressure);
+    }
+    for mut control in controls.iter_mut() {
+        control.toggle_abs = false;
+    }
+}
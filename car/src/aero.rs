@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+use rigid_body::{
+    joint::Joint,
+    sva::{Force, Vector},
+};
+
+use crate::interpolate::Interpolator1D;
+
+const AIR_DENSITY: f64 = 1.225;
+
+// Aerodynamic element (wing, splitter, body) attached to a joint. The lift and
+// drag coefficients are functions of the forward airspeed (m/s). Positive lift
+// is downforce, along the negative z axis of the joint.
+#[derive(Component)]
+pub struct AeroElement {
+    joint_entity: Entity,
+    position: Vector, // center of pressure, relative to the joint
+    area: f64,
+    lift: Interpolator1D,
+    drag: Interpolator1D,
+}
+
+impl AeroElement {
+    pub fn new(
+        joint_entity: Entity,
+        position: [f64; 3],
+        area: f64,
+        lift: Interpolator1D,
+        drag: Interpolator1D,
+    ) -> Self {
+        Self {
+            joint_entity,
+            position: Vector::new(position[0], position[1], position[2]),
+            area,
+            lift,
+            drag,
+        }
+    }
+
+    pub fn joint_entity(&self) -> Entity {
+        self.joint_entity
+    }
+}
+
+pub fn aero_system(aero_query: Query<&AeroElement>, mut query_joints: Query<&mut Joint>) {
+    for aero in aero_query.iter() {
+        if let Ok(mut joint) = query_joints.get_mut(aero.joint_entity) {
+            let x0i = joint.x.inverse(); // spatial transform from the joint to absolute coordinates
+            let v0 = x0i * joint.v; // spatial velocity of the joint in absolute coordinates
+            let point_abs = x0i.transform_point(aero.position);
+            let forward_abs = x0i * Vector::x();
+            let up_abs = x0i * Vector::z();
+
+            // still air, the airspeed is the velocity of the element
+            let velocity = v0.velocity_point(point_abs).vel;
+            let speed = velocity.dot(&forward_abs);
+            let dynamic_pressure = 0.5 * AIR_DENSITY * aero.area;
+
+            let downforce =
+                -dynamic_pressure * aero.lift.interpolate(speed) * speed * speed * up_abs;
+            let drag =
+                -dynamic_pressure * aero.drag.interpolate(speed) * velocity.norm() * velocity;
+
+            joint.f_ext += Force::force_point(downforce + drag, point_abs);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use rigid_body::{joint::Joint, sva::Vector};
+
+use crate::control::{CarControl, ChassisJoint, SteeringConfig};
+
+// Drives a car around a closed path without a player, on the chassis entity next
+// to its `CarControl`. Steering uses pure pursuit: the car follows the arc through
+// a point on the path a lookahead distance ahead. Throttle and brake follow a
+// speed profile, limited by the lateral acceleration in the corners and the
+// deceleration available to brake for them. `offset` and `speed_limit` let
+// another system (e.g. race avoidance) move the car off the path and hold it
+// behind a slower car.
+#[derive(Component, Clone)]
+pub struct AiDriver {
+    pub path: Vec<[f64; 2]>, // absolute x, y, the last point connects to the first
+    pub speed_profile: Vec<f64>, // target speed at each path point (m/s)
+    pub max_curvature: f64,  // curvature of the car at full steering lock
+    pub lookahead: [f64; 2], // minimum distance (m) and time (s) to the pursued point
+    pub speed_gain: f64,     // pedal per unit speed error
+    pub offset: f64,         // lateral offset from the path, positive to the left
+    pub speed_limit: f64,    // on top of the speed profile
+    pub outputs: HashMap<String, f64>,
+    index: Option<usize>, // closest path point
+}
+
+impl AiDriver {
+    pub fn new(
+        path: Vec<[f64; 2]>,
+        max_curvature: f64,
+        max_speed: f64,
+        max_lateral_acceleration: f64,
+        max_deceleration: f64,
+    ) -> Self {
+        assert!(path.len() >= 3, "the path needs at least three points");
+        let speed_profile =
+            speed_profile(&path, max_speed, max_lateral_acceleration, max_deceleration);
+        Self {
+            path,
+            speed_profile,
+            max_curvature,
+            lookahead: [4., 0.6],
+            speed_gain: 0.5,
+            offset: 0.,
+            speed_limit: f64::INFINITY,
+            outputs: HashMap::new(),
+            index: None,
+        }
+    }
+
+    pub fn with_lookahead(mut self, distance: f64, time: f64) -> Self {
+        self.lookahead = [distance, time];
+        self
+    }
+
+    // heading (radians) of the path at the first point, to line up the car at the start
+    pub fn start_heading(&self) -> f64 {
+        let [a, b] = [self.path[0], self.path[1]];
+        (b[1] - a[1]).atan2(b[0] - a[0])
+    }
+
+    // point on the path, moved sideways by the offset
+    fn point(&self, index: usize) -> [f64; 2] {
+        let n = self.path.len();
+        let [a, b] = [self.path[index % n], self.path[(index + 1) % n]];
+        let length = distance(a, b).max(1e-6);
+        let left = [-(b[1] - a[1]) / length, (b[0] - a[0]) / length];
+        [a[0] + self.offset * left[0], a[1] + self.offset * left[1]]
+    }
+
+    // point on the path at the lookahead distance from the car, walking forward
+    // from the closest point
+    fn target(&self, index: usize, position: [f64; 2], lookahead: f64) -> [f64; 2] {
+        for offset in 0..self.path.len() {
+            let point = self.point(index + offset);
+            if distance(point, position) >= lookahead {
+                return point;
+            }
+        }
+        self.point(index + 1)
+    }
+}
+
+pub fn ai_driver_system(
+    steering_config: Res<SteeringConfig>,
+    mut cars: Query<(&Joint, &mut AiDriver, &mut CarControl), With<ChassisJoint>>,
+) {
+    for (joint, mut driver, mut control) in cars.iter_mut() {
+        let (position, heading) = planar_pose(joint);
+        let speed = joint.v.v.x;
+
+        let index = closest_point(&driver.path, position, driver.index);
+        driver.index = Some(index);
+
+        // pure pursuit, the arc through the target point tangent to the heading
+        let lookahead = driver.lookahead[0] + driver.lookahead[1] * speed.abs();
+        let target = driver.target(index, position, lookahead);
+        let [dx, dy] = [target[0] - position[0], target[1] - position[1]];
+        let lateral = -heading.sin() * dx + heading.cos() * dy;
+        let curvature = 2. * lateral / (dx * dx + dy * dy);
+        // undo the speed scaling of the steering filter, the driver already
+        // asks for the curvature it needs
+        let speed_factor = steering_config.speed_factor(speed.abs() as f32);
+        control.steering_input =
+            (curvature / driver.max_curvature / speed_factor as f64).clamp(-1., 1.) as f32;
+
+        // speed profile
+        let target_speed = driver.speed_profile[index].min(driver.speed_limit);
+        let pedal = driver.speed_gain * (target_speed - speed);
+        control.throttle = pedal.clamp(0., 1.) as f32;
+        control.brake = (-pedal).clamp(0., 1.) as f32;
+        control.reverse = false;
+
+        driver
+            .outputs
+            .insert("target_speed".to_string(), target_speed);
+        driver.outputs.insert("curvature".to_string(), curvature);
+        driver.outputs.insert("index".to_string(), index as f64);
+    }
+}
+
+// Closest path point, searched a short way around the previous one so the car
+// doesn't jump to a different part of the path where it passes close by.
+pub(crate) fn closest_point(
+    path: &[[f64; 2]],
+    position: [f64; 2],
+    previous: Option<usize>,
+) -> usize {
+    let n = path.len();
+    let candidates: Vec<usize> = match previous {
+        Some(index) => (0..30)
+            .map(|offset| (index + 2 * n - 5 + offset) % n)
+            .collect(),
+        None => (0..n).collect(),
+    };
+    candidates
+        .into_iter()
+        .min_by(|&a, &b| distance(path[a], position).total_cmp(&distance(path[b], position)))
+        .unwrap_or(0)
+}
+
+// absolute x, y position and heading (yaw) of the chassis
+pub(crate) fn planar_pose(joint: &Joint) -> ([f64; 2], f64) {
+    // the chassis joint transform is from absolute to chassis coordinates
+    let x0i = joint.x.inverse();
+    let position = x0i.transform_point(Vector::zeros());
+    let forward = x0i * Vector::x();
+    ([position.x, position.y], forward.y.atan2(forward.x))
+}
+
+pub(crate) fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+// curvature of the circle through three points
+fn curvature(a: [f64; 2], b: [f64; 2], c: [f64; 2]) -> f64 {
+    let cross = (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]);
+    let lengths = distance(a, b) * distance(b, c) * distance(c, a);
+    if lengths == 0. {
+        0.
+    } else {
+        2. * cross.abs() / lengths
+    }
+}
+
+// Speed limited by the lateral acceleration in the corners, then reduced ahead of
+// each corner so the car can brake down to it.
+fn speed_profile(
+    path: &[[f64; 2]],
+    max_speed: f64,
+    max_lateral_acceleration: f64,
+    max_deceleration: f64,
+) -> Vec<f64> {
+    let n = path.len();
+    let mut speeds: Vec<f64> = (0..n)
+        .map(|i| {
+            let curvature = curvature(path[(i + n - 1) % n], path[i], path[(i + 1) % n]);
+            (max_lateral_acceleration / curvature.max(1e-6))
+                .sqrt()
+                .min(max_speed)
+        })
+        .collect();
+    // backwards around the closed path, twice so the braking wraps over the start
+    for step in 0..2 * n {
+        let i = (2 * n - 1 - step) % n;
+        let next = (i + 1) % n;
+        let braking =
+            (speeds[next].powi(2) + 2. * max_deceleration * distance(path[i], path[next])).sqrt();
+        speeds[i] = speeds[i].min(braking);
+    }
+    speeds
+}
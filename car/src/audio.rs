@@ -0,0 +1,294 @@
+use std::{f32::consts::PI, time::Duration};
+
+use bevy::{
+    audio::{AddAudioSource, Source, Volume},
+    prelude::*,
+    reflect::{TypePath, TypeUuid},
+};
+use cameras::camera_az_el::AzElCamera;
+use grid_terrain::SurfaceKind;
+use rigid_body::{joint::Joint, sva::Vector};
+
+use crate::{build::CarEntities, engine::Engine, physics::SuspensionComponent, tire::PointTire};
+
+const SAMPLE_RATE: u32 = 44100;
+const ENGINE_FREQUENCY: f32 = 50.; // firing frequency of the engine sound at normal speed
+const EAR_GAP: f32 = 0.3;
+
+// Sounds of the car in `CarEntities`, synthesized and played from the chassis
+// position: engine tone pitched by the engine speed, tire squeal from the slip
+// angle, rumble from the suspension motion over rough terrain, and a thump when
+// a suspension hits its bump stop. Added after the `RigidBodyPlugin` (it needs
+// the audio and asset plugins).
+pub struct CarAudioPlugin;
+
+impl Plugin for CarAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<Synth>()
+            .init_resource::<CarAudio>()
+            .add_systems(Update, car_audio_system);
+    }
+}
+
+#[derive(Resource)]
+pub struct CarAudio {
+    pub volume: f32,
+    pub cylinders: f32, // firing frequency is cylinders / 2 per engine revolution
+    pub squeal_slip: f64, // slip angle (tangent) where the tires start to squeal
+    pub rumble_velocity: f64, // suspension velocity (m/s) for full rumble
+    pub impact_velocity: f64, // compression speed (m/s) at the bump stop for a full thump
+    pub distance_scale: f32, // the sound fades with 1 / distance^2, distances are scaled down
+    pub rumble_filter: f32, // time constant (s)
+    sources: Option<[Entity; 3]>, // engine, squeal and rumble
+    thump: Option<Handle<Synth>>,
+    rumble: f32,          // filtered suspension velocity
+    bump_stop: Vec<bool>, // suspensions on the bump stop at the last update
+}
+
+impl Default for CarAudio {
+    fn default() -> Self {
+        Self {
+            volume: 0.5,
+            cylinders: 4.,
+            squeal_slip: 0.08,
+            rumble_velocity: 0.5,
+            impact_velocity: 1.,
+            distance_scale: 0.1,
+            rumble_filter: 0.1,
+            sources: None,
+            thump: None,
+            rumble: 0.,
+            bump_stop: Vec::new(),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn car_audio_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut audio: ResMut<CarAudio>,
+    mut synths: ResMut<Assets<Synth>>,
+    car: Option<Res<CarEntities>>,
+    cameras: Query<&GlobalTransform, With<AzElCamera>>,
+    joints: Query<&Joint>,
+    engines: Query<&Engine>,
+    suspensions: Query<(&Joint, &SuspensionComponent)>,
+    tires: Query<&PointTire>,
+    sinks: Query<&SpatialAudioSink>,
+) {
+    let car = match car {
+        Some(car) => car,
+        None => return,
+    };
+    let audio = audio.as_mut();
+
+    // looping sources, silent until the first update
+    let sources = *audio.sources.get_or_insert_with(|| {
+        [SynthKind::Engine, SynthKind::Squeal, SynthKind::Rumble].map(|kind| {
+            commands
+                .spawn(SpatialAudioSourceBundle {
+                    source: synths.add(Synth { kind }),
+                    // the sources never end, looping would buffer them forever
+                    settings: PlaybackSettings::ONCE.with_volume(Volume::new_relative(0.)),
+                    spatial: SpatialSettings::new(Transform::IDENTITY, EAR_GAP, Vec3::ZERO),
+                })
+                .id()
+        })
+    });
+    let thump = audio
+        .thump
+        .get_or_insert_with(|| {
+            synths.add(Synth {
+                kind: SynthKind::Thump,
+            })
+        })
+        .clone();
+
+    // the listener is at the orbit camera (not the mirror), distances are scaled relative to it
+    let camera = match cameras.iter().next() {
+        Some(camera) => camera.compute_transform(),
+        None => return,
+    };
+    let chassis = match joints.get(car.chassis) {
+        Ok(chassis) => chassis,
+        Err(_) => return,
+    };
+    let position = chassis.x.inverse().transform_point(Vector::zeros());
+    let position = Vec3::new(position.x as f32, position.y as f32, position.z as f32);
+    let emitter = (position - camera.translation) * audio.distance_scale;
+    let listener = Transform::from_rotation(camera.rotation);
+
+    // engine: pitch from the engine speed, louder with throttle
+    let (rpm, throttle) = match engines.get(car.engine) {
+        Ok(engine) => (
+            engine.outputs.get("rpm").copied().unwrap_or(0.) as f32,
+            engine.outputs.get("throttle").copied().unwrap_or(0.) as f32,
+        ),
+        Err(_) => (0., 0.),
+    };
+    let firing = rpm / 60. * audio.cylinders / 2.;
+    let engine = [
+        (firing / ENGINE_FREQUENCY).clamp(0.1, 10.),
+        audio.volume * (0.3 + 0.7 * throttle.clamp(0., 1.)),
+    ];
+
+    // squeal: the largest slip angle of the loaded tires
+    let slip = tires
+        .iter()
+        .filter(|tire| car.wheels.contains(&tire.joint_entity()) && tire.contact().is_some())
+        .map(|tire| tire.slip_angle().abs())
+        .fold(0., f64::max);
+    let squeal = ((slip - audio.squeal_slip) / audio.squeal_slip).clamp(0., 1.) as f32;
+    let squeal = [1. + 0.2 * squeal, audio.volume * squeal];
+
+    // rumble and bump stop impacts from the suspension motion
+    let mut suspension_ids = car.suspensions.clone();
+    for (_, [heave, _]) in car.solid_axles.iter() {
+        suspension_ids.push(*heave);
+    }
+    audio.bump_stop.resize(suspension_ids.len(), false);
+    let mut velocity = 0.;
+    for (index, id) in suspension_ids.iter().enumerate() {
+        if let Ok((joint, suspension)) = suspensions.get(*id) {
+            velocity += joint.qd.abs() / suspension_ids.len() as f64;
+            let on_bump_stop = suspension.on_bump_stop(joint.q);
+            if on_bump_stop && !audio.bump_stop[index] && joint.qd > 0. {
+                let impact = (joint.qd / audio.impact_velocity).min(1.) as f32;
+                commands.spawn(SpatialAudioSourceBundle {
+                    source: thump.clone(),
+                    settings: PlaybackSettings::DESPAWN
+                        .with_volume(Volume::new_relative(audio.volume * impact)),
+                    spatial: SpatialSettings::new(listener, EAR_GAP, emitter),
+                });
+            }
+            audio.bump_stop[index] = on_bump_stop;
+        }
+    }
+    // loose surfaces rumble with speed, e.g. gravel
+    let loose = tires
+        .iter()
+        .filter(|tire| car.wheels.contains(&tire.joint_entity()) && tire.contact().is_some())
+        .filter(|tire| tire.surface() == SurfaceKind::Loose)
+        .count() as f64
+        / car.wheels.len().max(1) as f64;
+    let speed = (chassis.v.v.x.abs() / 20.).min(1.);
+    let filter = (time.delta_seconds() / audio.rumble_filter).min(1.);
+    let target = (velocity / audio.rumble_velocity + loose * speed).min(1.) as f32;
+    audio.rumble += filter * (target - audio.rumble);
+    let rumble = [1., audio.volume * audio.rumble];
+
+    for (entity, [speed, volume]) in sources.iter().zip([engine, squeal, rumble]) {
+        if let Ok(sink) = sinks.get(*entity) {
+            sink.set_speed(speed);
+            sink.set_volume(volume);
+            sink.set_listener_position(listener, EAR_GAP);
+            sink.set_emitter_position(emitter);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum SynthKind {
+    Engine, // harmonics of the firing frequency
+    Squeal, // high tone with a wobble
+    Rumble, // low frequency noise
+    Thump,  // short decaying low tone, played once
+}
+
+// Procedural sound, played through the audio plugin like a sound file. The
+// looping sounds never end.
+#[derive(TypeUuid, TypePath)]
+#[uuid = "5b3f6f1e-2a51-4c1b-9f63-3f3c2f0a8d17"]
+struct Synth {
+    kind: SynthKind,
+}
+
+impl Decodable for Synth {
+    type DecoderItem = f32;
+    type Decoder = SynthDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        SynthDecoder {
+            kind: self.kind,
+            sample: 0,
+            noise: 0x2545_f491,
+            filtered: 0.,
+        }
+    }
+}
+
+struct SynthDecoder {
+    kind: SynthKind,
+    sample: u32,
+    noise: u32, // xorshift state
+    filtered: f32,
+}
+
+impl SynthDecoder {
+    // white noise from -1 to 1
+    fn noise(&mut self) -> f32 {
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 17;
+        self.noise ^= self.noise << 5;
+        self.noise as f32 / u32::MAX as f32 * 2. - 1.
+    }
+}
+
+impl Iterator for SynthDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let time = self.sample as f32 / SAMPLE_RATE as f32;
+        // the tones are whole numbers of hertz, so the time can wrap every second
+        // (the thump is shorter than that)
+        self.sample = (self.sample + 1) % SAMPLE_RATE;
+        let tone = |frequency: f32| (2. * PI * frequency * time).sin();
+        let value = match self.kind {
+            SynthKind::Engine => {
+                let harmonics: f32 = (1..=6)
+                    .map(|harmonic| tone(ENGINE_FREQUENCY * harmonic as f32) / harmonic as f32)
+                    .sum();
+                0.3 * harmonics + 0.05 * self.noise()
+            }
+            SynthKind::Squeal => {
+                let wobble = 0.8 + 0.2 * tone(7.);
+                0.4 * wobble * (tone(900.) + 0.3 * tone(1800.))
+            }
+            SynthKind::Rumble => {
+                let noise = self.noise();
+                self.filtered += 0.02 * (noise - self.filtered);
+                3. * self.filtered
+            }
+            SynthKind::Thump => {
+                if time > 0.4 {
+                    return None;
+                }
+                let noise = self.noise();
+                tone(60.) * (-time / 0.08).exp() + 0.5 * noise * (-time / 0.02).exp()
+            }
+        };
+        Some(value)
+    }
+}
+
+impl Source for SynthDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        match self.kind {
+            SynthKind::Thump => Some(Duration::from_secs_f32(0.4)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use rigid_body::{joint::Joint, sva::Vector};
+
+use crate::{
+    ai::planar_pose,
+    build::{CarDefinition, CarEntities},
+    control::CarControl,
+};
+
+const GRAVITY: f64 = 9.81;
+
+// Planar bicycle model of a car, on the chassis entity, run alongside the full
+// multibody car from the same steering input and forward speed. Each axle is
+// one wheel with a linear cornering stiffness, and there is no load transfer,
+// roll, or tire saturation, so the two drift apart where those matter (near the
+// grip limit, in transients, on bumps). The model is reset to the car state
+// every `sync_time`, and the paths of both since the last reset are kept to be
+// drawn on top of each other. At low speed the kinematic (no slip) model is used.
+#[derive(Component, Clone)]
+pub struct BicycleModel {
+    pub mass: f64,
+    pub yaw_inertia: f64,
+    pub front: f64,           // center of gravity to the front axle
+    pub rear: f64,            // center of gravity to the rear axle (center of the unsteered axles)
+    pub front_stiffness: f64, // cornering stiffness of the front axle (N/rad)
+    pub rear_stiffness: f64,  // cornering stiffness of the rear axles (N/rad)
+    pub lock_angle: f64,      // road wheel angle at full steering (rad)
+    pub min_speed: f64,       // kinematic model below this speed (m/s)
+    pub sync_time: f64,       // (s) between resets to the car state
+    pub sample_time: f64,     // (s) between path points
+    pub position: [f64; 2],
+    pub heading: f64,
+    pub lateral_velocity: f64,
+    pub yaw_rate: f64,
+    pub path: Vec<Vec3>,     // of the model since the last reset
+    pub car_path: Vec<Vec3>, // of the car since the last reset
+    pub outputs: HashMap<String, f64>,
+    timer: Option<f64>, // since the last reset, none before the first
+    sample_timer: f64,
+}
+
+impl BicycleModel {
+    // mass, center of gravity and cornering stiffness of the car at rest
+    pub fn from_car(car: &CarDefinition) -> Self {
+        // point masses of the chassis, fuel, payload, and each corner
+        let corner_mass = |ind: usize| car.suspension[ind].mass + car.wheel.mass;
+        let mut points = vec![
+            (car.chassis.mass, car.chassis.cg_position[0], 0.),
+            (car.fuel_tank.fuel, car.fuel_tank.position[0], 0.),
+        ];
+        points.extend(
+            car.payload
+                .iter()
+                .map(|item| (item.mass, item.position[0], 0.)),
+        );
+        points.extend(car.suspension.iter().enumerate().map(|(ind, suspension)| {
+            let [x, y, _] = suspension.location;
+            (corner_mass(ind), x, y)
+        }));
+        let mass: f64 = points.iter().map(|(mass, _, _)| mass).sum();
+        let cg = points.iter().map(|(mass, x, _)| mass * x).sum::<f64>() / mass;
+        let yaw_inertia = car.chassis.moi[2]
+            + points
+                .iter()
+                .map(|(mass, x, y)| mass * ((x - cg).powi(2) + y.powi(2)))
+                .sum::<f64>();
+
+        let wheelbase = car.wheelbase();
+        let front = car.suspension[0].location[0] - cg;
+        let rear = wheelbase - front;
+
+        // static axle loads, shared by the wheels of the axles
+        let front_wheels = 2.;
+        let rear_wheels = (car.suspension.len() - 2) as f64;
+        let weight = mass * GRAVITY;
+        let stiffness = |load: f64| {
+            let slip_angle = 0.01;
+            let force = car
+                .wheel
+                .tire_model
+                .normalized_forces(0., slip_angle, load, 1.)[1];
+            force.abs() * load / slip_angle
+        };
+        let front_load = weight * rear / wheelbase / front_wheels;
+        let rear_load = weight * front / wheelbase / rear_wheels;
+
+        Self {
+            mass,
+            yaw_inertia,
+            front,
+            rear,
+            front_stiffness: front_wheels * stiffness(front_load),
+            rear_stiffness: rear_wheels * stiffness(rear_load),
+            lock_angle: (car.lock_curvature() * wheelbase).atan(),
+            min_speed: 3.,
+            sync_time: 5.,
+            sample_time: 0.05,
+            position: [0., 0.],
+            heading: 0.,
+            lateral_velocity: 0.,
+            yaw_rate: 0.,
+            path: Vec::new(),
+            car_path: Vec::new(),
+            outputs: HashMap::new(),
+            timer: None,
+            sample_timer: 0.,
+        }
+    }
+
+    pub fn with_sync_time(mut self, sync_time: f64) -> Self {
+        self.sync_time = sync_time;
+        self
+    }
+
+    fn wheelbase(&self) -> f64 {
+        self.front + self.rear
+    }
+
+    // yaw rate of the car rolling without slip
+    pub fn kinematic_yaw_rate(&self, speed: f64, steer_angle: f64) -> f64 {
+        speed * steer_angle.tan() / self.wheelbase()
+    }
+
+    // steady state yaw rate of the linear model
+    pub fn steady_state_yaw_rate(&self, speed: f64, steer_angle: f64) -> f64 {
+        let understeer_gradient = self.mass / self.wheelbase()
+            * (self.rear / self.front_stiffness - self.front / self.rear_stiffness);
+        speed * steer_angle / (self.wheelbase() + understeer_gradient * speed.powi(2))
+    }
+
+    // lateral velocity and yaw rate derivatives
+    fn derivatives(&self, speed: f64, steer_angle: f64) -> [f64; 2] {
+        let [vy, r] = [self.lateral_velocity, self.yaw_rate];
+        let front_slip = (vy + self.front * r) / speed - steer_angle;
+        let rear_slip = (vy - self.rear * r) / speed;
+        let front_force = -self.front_stiffness * front_slip * steer_angle.cos();
+        let rear_force = -self.rear_stiffness * rear_slip;
+        [
+            (front_force + rear_force) / self.mass - speed * r,
+            (self.front * front_force - self.rear * rear_force) / self.yaw_inertia,
+        ]
+    }
+}
+
+// runs once per time step (not in the physics schedule), like the wheel loads
+pub fn bicycle_model_system(
+    fixed_time: Res<FixedTime>,
+    mut cars: Query<(&Joint, &CarControl, &mut BicycleModel)>,
+) {
+    let dt = fixed_time.period.as_secs_f64();
+    for (joint, control, mut model) in cars.iter_mut() {
+        let (position, heading) = planar_pose(joint);
+        let car_position = joint.x.inverse().transform_point(Vector::zeros());
+        // chassis velocities are in chassis coordinates
+        let speed = joint.v.v.x;
+        let steer_angle = control.steering as f64 * model.lock_angle;
+
+        let timer = model.timer.map_or(f64::INFINITY, |timer| timer + dt);
+        if timer >= model.sync_time {
+            model.position = position;
+            model.heading = heading;
+            model.lateral_velocity = joint.v.v.y;
+            model.yaw_rate = joint.v.w.z;
+            model.path.clear();
+            model.car_path.clear();
+            model.sample_timer = f64::INFINITY;
+            model.timer = Some(0.);
+        } else {
+            model.timer = Some(timer);
+        }
+
+        if speed > model.min_speed {
+            let [vy_dot, r_dot] = model.derivatives(speed, steer_angle);
+            model.lateral_velocity += vy_dot * dt;
+            model.yaw_rate += r_dot * dt;
+        } else {
+            model.yaw_rate = model.kinematic_yaw_rate(speed, steer_angle);
+            model.lateral_velocity = model.rear * model.yaw_rate;
+        }
+        let (sin, cos) = model.heading.sin_cos();
+        let vy = model.lateral_velocity;
+        model.position[0] += (speed * cos - vy * sin) * dt;
+        model.position[1] += (speed * sin + vy * cos) * dt;
+        model.heading += model.yaw_rate * dt;
+
+        // both paths at the height of the car
+        model.sample_timer += dt;
+        if model.sample_timer >= model.sample_time {
+            model.sample_timer = 0.;
+            let z = car_position.z as f32;
+            let [x, y] = model.position.map(|value| value as f32);
+            model.path.push(Vec3::new(x, y, z));
+            let [x, y] = position.map(|value| value as f32);
+            model.car_path.push(Vec3::new(x, y, z));
+        }
+
+        let [dx, dy] = [
+            model.position[0] - position[0],
+            model.position[1] - position[1],
+        ];
+        let outputs = [
+            ("yaw_rate", model.yaw_rate),
+            (
+                "kinematic_yaw_rate",
+                model.kinematic_yaw_rate(speed, steer_angle),
+            ),
+            (
+                "steady_state_yaw_rate",
+                model.steady_state_yaw_rate(speed, steer_angle),
+            ),
+            ("yaw_rate_error", joint.v.w.z - model.yaw_rate),
+            ("sideslip", model.lateral_velocity.atan2(speed.abs())),
+            ("path_error", (dx * dx + dy * dy).sqrt()),
+        ];
+        for (name, value) in outputs {
+            model.outputs.insert(name.to_string(), value);
+        }
+    }
+}
+
+// Runs the bicycle model on the car in `CarEntities` and draws its path (yellow)
+// over the path of the car (white)
+pub fn bicycle_model_setup(app: &mut App) {
+    app.add_systems(
+        Update,
+        (bicycle_model_attach_system, bicycle_overlay_system),
+    );
+}
+
+fn bicycle_model_attach_system(
+    mut commands: Commands,
+    car: Option<Res<CarDefinition>>,
+    car_entities: Option<Res<CarEntities>>,
+    models: Query<&BicycleModel>,
+) {
+    if let (Some(car), Some(car_entities)) = (car, car_entities) {
+        if models.get(car_entities.chassis).is_err() {
+            commands
+                .entity(car_entities.chassis)
+                .insert(BicycleModel::from_car(&car));
+        }
+    }
+}
+
+fn bicycle_overlay_system(mut gizmos: Gizmos, models: Query<&BicycleModel>) {
+    for model in models.iter() {
+        gizmos.linestrip(model.car_path.iter().copied(), Color::WHITE);
+        gizmos.linestrip(model.path.iter().copied(), Color::YELLOW);
+    }
+}
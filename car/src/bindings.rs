@@ -0,0 +1,198 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+// Input bindings of the player controls, read from a TOML file. Every value is
+// optional, missing values keep the default mapping (`WASD` and the gamepad
+// sticks and triggers). Key, axis and button names are the bevy names, e.g.
+// `"W"`, `"Up"`, `"LeftStickX"`, `"RightTrigger2"`.
+#[derive(Resource, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct InputBindings {
+    pub keyboard: KeyBindings,
+    pub gamepad: GamepadBindings,
+    // bindings for a gamepad id (the table key), in place of `gamepad`. This
+    // lets different devices drive at once, e.g. a wheel and a separate pedal box.
+    pub gamepads: HashMap<String, GamepadBindings>,
+}
+
+impl InputBindings {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("reading {}: {}", path.display(), error))?;
+        toml::from_str(&text).map_err(|error| format!("parsing {}: {}", path.display(), error))
+    }
+
+    pub fn gamepad(&self, gamepad: Gamepad) -> &GamepadBindings {
+        self.gamepads
+            .get(&gamepad.id.to_string())
+            .unwrap_or(&self.gamepad)
+    }
+}
+
+// Several keys can be bound to each control
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub throttle: Vec<KeyCode>,
+    pub brake: Vec<KeyCode>,
+    pub steer_left: Vec<KeyCode>,
+    pub steer_right: Vec<KeyCode>,
+    pub clutch: Vec<KeyCode>,
+    pub handbrake: Vec<KeyCode>,
+    pub gear_up: Vec<KeyCode>,
+    pub gear_down: Vec<KeyCode>,
+    pub reverse: Vec<KeyCode>,
+    pub abs: Vec<KeyCode>,
+    pub reset: Vec<KeyCode>,
+    pub drive_mode: Vec<KeyCode>,
+    pub drop_ballast: Vec<KeyCode>,
+    pub low_range: Vec<KeyCode>,
+    pub headlights: Vec<KeyCode>,
+    pub response_time: f32, // time (s) for a held key to move a control fully
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            throttle: vec![KeyCode::W],
+            brake: vec![KeyCode::S],
+            steer_left: vec![KeyCode::A],
+            steer_right: vec![KeyCode::D],
+            clutch: vec![KeyCode::Space],
+            handbrake: vec![KeyCode::ShiftLeft],
+            gear_up: vec![KeyCode::E],
+            gear_down: vec![KeyCode::Q],
+            reverse: vec![KeyCode::R],
+            abs: vec![KeyCode::B],
+            reset: vec![KeyCode::T],
+            drive_mode: vec![KeyCode::M],
+            drop_ballast: vec![KeyCode::X],
+            low_range: vec![KeyCode::L],
+            headlights: vec![KeyCode::J],
+            response_time: 0.25,
+        }
+    }
+}
+
+// Gamepad, racing wheel and pedal controls. Throttle and brake take the
+// largest of their bound inputs, so a stick and a trigger can share a control.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct GamepadBindings {
+    pub steering: Vec<AxisBinding>, // positive steers left
+    pub throttle: Vec<AxisBinding>,
+    pub brake: Vec<AxisBinding>,
+    pub clutch: Vec<AxisBinding>,
+    // the axes always set the controls (wheel and pedals), otherwise only when
+    // moved out of the deadzone, so the keyboard still works
+    pub absolute: bool,
+    pub clutch_button: Option<GamepadButtonType>,
+    pub handbrake: Option<GamepadButtonType>,
+    pub gear_up: Option<GamepadButtonType>,
+    pub gear_down: Option<GamepadButtonType>,
+    pub reverse: Option<GamepadButtonType>,
+    pub reset: Option<GamepadButtonType>,
+    pub drive_mode: Option<GamepadButtonType>,
+    pub drop_ballast: Option<GamepadButtonType>,
+    pub low_range: Option<GamepadButtonType>,
+    pub headlights: Option<GamepadButtonType>,
+}
+
+impl Default for GamepadBindings {
+    fn default() -> Self {
+        Self {
+            steering: vec![AxisBinding {
+                invert: true,
+                ..AxisBinding::new(AxisInput::Axis(GamepadAxisType::LeftStickX))
+            }],
+            throttle: vec![
+                AxisBinding::new(AxisInput::Button(GamepadButtonType::RightTrigger2)),
+                AxisBinding::new(AxisInput::Axis(GamepadAxisType::RightStickY)),
+            ],
+            brake: vec![
+                AxisBinding::new(AxisInput::Button(GamepadButtonType::LeftTrigger2)),
+                AxisBinding {
+                    invert: true,
+                    ..AxisBinding::new(AxisInput::Axis(GamepadAxisType::RightStickY))
+                },
+            ],
+            clutch: Vec::new(),
+            absolute: false,
+            clutch_button: Some(GamepadButtonType::South),
+            handbrake: Some(GamepadButtonType::East),
+            gear_up: Some(GamepadButtonType::RightTrigger),
+            gear_down: Some(GamepadButtonType::LeftTrigger),
+            reverse: Some(GamepadButtonType::North),
+            reset: Some(GamepadButtonType::Select),
+            drive_mode: Some(GamepadButtonType::DPadUp),
+            drop_ballast: Some(GamepadButtonType::DPadDown),
+            low_range: Some(GamepadButtonType::DPadLeft),
+            headlights: Some(GamepadButtonType::DPadRight),
+        }
+    }
+}
+
+// An analog input: a stick or wheel axis, or an analog button (trigger)
+#[derive(Deserialize, Clone, Copy)]
+pub enum AxisInput {
+    Axis(GamepadAxisType),
+    Button(GamepadButtonType),
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct AxisBinding {
+    pub input: AxisInput,
+    #[serde(default = "default_deadzone")]
+    pub deadzone: f32, // fraction of the range ignored around rest
+    #[serde(default = "default_sensitivity")]
+    pub sensitivity: f32, // gain, e.g. 2 reaches full lock at half the wheel rotation
+    #[serde(default)]
+    pub invert: bool,
+    // the axis rests at -1 and reads 1 fully pressed (pedals), it's mapped to 0 to 1
+    #[serde(default)]
+    pub pedal: bool,
+}
+
+fn default_deadzone() -> f32 {
+    0.01
+}
+
+fn default_sensitivity() -> f32 {
+    1.
+}
+
+impl AxisBinding {
+    pub fn new(input: AxisInput) -> Self {
+        Self {
+            input,
+            deadzone: default_deadzone(),
+            sensitivity: default_sensitivity(),
+            invert: false,
+            pedal: false,
+        }
+    }
+
+    // input value, -1 to 1 (0 to 1 for pedals), zero in the deadzone
+    pub fn value(
+        &self,
+        gamepad: Gamepad,
+        axes: &Axis<GamepadAxis>,
+        button_axes: &Axis<GamepadButton>,
+    ) -> f32 {
+        let raw = match self.input {
+            AxisInput::Axis(axis) => axes.get(GamepadAxis::new(gamepad, axis)),
+            AxisInput::Button(button) => button_axes.get(GamepadButton::new(gamepad, button)),
+        }
+        .unwrap_or(0.);
+        let mut value = if self.invert { -raw } else { raw };
+        if self.pedal {
+            value = (value + 1.) / 2.;
+        }
+
+        let deadzone = self.deadzone.clamp(0., 0.99);
+        let magnitude = ((value.abs() - deadzone) / (1. - deadzone)).max(0.);
+        (value.signum() * magnitude * self.sensitivity).clamp(-1., 1.)
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use rigid_body::joint::Joint;
+
+use crate::physics::BrakeWheel;
+
+// Brake temperature and fade, on a braked wheel. The brake work heats a lumped
+// disc and pad mass, which cools to the ambient air faster as the wheel spins,
+// and the brake torque fades linearly from `fade_start` to `fade_end`, down to
+// `min_friction` of the cold torque. Temperatures in degrees Celsius.
+#[derive(Component, Clone)]
+pub struct BrakeHeat {
+    pub temperature: f64,
+    pub ambient: f64,
+    pub heat_capacity: f64, // J/K of the disc surface and pads, which heat ahead of the core
+    pub cooling: f64,       // W/K to the air at standstill
+    pub cooling_speed: f64, // W/K added per rad/s of wheel speed
+    pub fade_start: f64,
+    pub fade_end: f64,
+    pub min_friction: f64, // fraction of the torque left when fully faded
+    pub outputs: HashMap<String, f64>,
+}
+
+impl Default for BrakeHeat {
+    // a ventilated car disc, which fades after a few hard stops from speed
+    fn default() -> Self {
+        Self {
+            temperature: 20.,
+            ambient: 20.,
+            heat_capacity: 1500.,
+            cooling: 4.,
+            cooling_speed: 0.2,
+            fade_start: 400.,
+            fade_end: 700.,
+            min_friction: 0.5,
+            outputs: HashMap::new(),
+        }
+    }
+}
+
+impl BrakeHeat {
+    pub fn with_heat_capacity(mut self, heat_capacity: f64) -> Self {
+        self.heat_capacity = heat_capacity;
+        self
+    }
+
+    pub fn with_fade(mut self, fade_start: f64, fade_end: f64, min_friction: f64) -> Self {
+        self.fade_start = fade_start;
+        self.fade_end = fade_end;
+        self.min_friction = min_friction;
+        self
+    }
+
+    // friction of the hot brake relative to the cold one
+    pub fn friction_factor(&self) -> f64 {
+        let fade = (self.temperature - self.fade_start) / (self.fade_end - self.fade_start);
+        1. - (1. - self.min_friction) * fade.clamp(0., 1.)
+    }
+}
+
+// runs once per time step (not in the physics schedule), like the fuel tank
+pub fn brake_heat_system(
+    fixed_time: Res<FixedTime>,
+    mut brakes: Query<(&mut BrakeHeat, &mut BrakeWheel, &Joint)>,
+) {
+    let dt = fixed_time.period.as_secs_f64();
+    for (mut heat, mut brake, joint) in brakes.iter_mut() {
+        // the brake torque opposes the wheel speed, all its work turns to heat
+        let power = (brake.torque * joint.qd).abs();
+        let cooling = (heat.cooling + heat.cooling_speed * joint.qd.abs())
+            * (heat.temperature - heat.ambient);
+        heat.temperature += (power - cooling) / heat.heat_capacity * dt;
+        brake.fade = heat.friction_factor();
+
+        let temperature = heat.temperature;
+        let fade = brake.fade;
+        for (name, value) in [
+            ("temperature", temperature),
+            ("power", power),
+            ("friction", fade),
+        ] {
+            heat.outputs.insert(name.to_string(), value);
+        }
+    }
+}
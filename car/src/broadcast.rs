@@ -0,0 +1,152 @@
+use std::net::UdpSocket;
+
+use bevy::prelude::*;
+use rigid_body::joint::Joint;
+
+use crate::{
+    build::CarEntities, control::CarControl, engine::Engine, race::Racer, tire::PointTire,
+    transmission::Transmission,
+};
+
+// Live telemetry of the car in `CarEntities` for external dashboards and
+// overlays. Insert the resource to send a JSON object over UDP to `host:port`
+// `rate` times a second. The names follow the SimHub game data properties
+// (SpeedKmh, Rpms, Gear, CurrentLapTime, ...), pedals are in percent, times in
+// seconds, and the slip of each tire is keyed by its corner. The lap times are
+// those of the `Racer` on the chassis, if any.
+#[derive(Resource)]
+pub struct TelemetryBroadcast {
+    pub host: String, // 255.255.255.255 reaches the whole local network
+    pub port: u16,
+    pub rate: f64, // packets per second
+    socket: Option<UdpSocket>,
+    timer: f64,
+}
+
+impl TelemetryBroadcast {
+    pub fn new(port: u16) -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port,
+            rate: 20.,
+            socket: None,
+            timer: 0.,
+        }
+    }
+
+    pub fn with_host(mut self, host: &str) -> Self {
+        self.host = host.to_string();
+        self
+    }
+
+    pub fn with_rate(mut self, rate: f64) -> Self {
+        self.rate = rate;
+        self
+    }
+}
+
+// JSON has no infinity or NaN
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{:.4}", value)
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_object(fields: &[(String, String)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| format!("\"{}\":{}", name, value))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn telemetry_broadcast_system(
+    time: Res<Time>,
+    broadcast: Option<ResMut<TelemetryBroadcast>>,
+    car: Option<Res<CarEntities>>,
+    joints: Query<&Joint>,
+    controls: Query<&CarControl>,
+    engines: Query<(&Engine, &Transmission)>,
+    racers: Query<&Racer>,
+    tires: Query<&PointTire>,
+) {
+    let (mut broadcast, car) = match (broadcast, car) {
+        (Some(broadcast), Some(car)) => (broadcast, car),
+        _ => return,
+    };
+    broadcast.timer += time.delta_seconds_f64();
+    if broadcast.timer < 1. / broadcast.rate {
+        return;
+    }
+    broadcast.timer = 0.;
+
+    let mut fields: Vec<(String, String)> = Vec::new();
+    let mut number = |name: &str, value: f64| fields.push((name.to_string(), json_number(value)));
+    if let Ok(chassis) = joints.get(car.chassis) {
+        number("SpeedKmh", chassis.v.v.x.abs() * 3.6);
+    }
+    if let Ok(control) = controls.get(car.chassis) {
+        number("Throttle", 100. * control.throttle as f64);
+        number("Brake", 100. * control.brake as f64);
+        number("Clutch", 100. * control.clutch as f64);
+        number("Steering", control.steering as f64);
+    }
+    if let Ok((engine, transmission)) = engines.get(car.engine) {
+        let rpm = 30. / std::f64::consts::PI;
+        number("Rpms", engine.outputs.get("rpm").copied().unwrap_or(0.));
+        number("MaxRpm", engine.redline * rpm);
+        number("IdleRpm", engine.idle_speed * rpm);
+        number("MaxGears", transmission.ratios.len() as f64);
+    }
+    if let Ok(racer) = racers.get(car.chassis) {
+        number("CurrentLapTime", racer.lap_time);
+        number("LastLapTime", racer.last_lap.unwrap_or(0.));
+        number("BestLapTime", racer.best_lap.unwrap_or(0.));
+        number("CompletedLaps", racer.lap.max(0) as f64);
+        number("Position", racer.position as f64);
+    }
+
+    // the gear is a string, like in SimHub
+    if let Ok((_, transmission)) = engines.get(car.engine) {
+        let gear = transmission.outputs.get("gear").copied().unwrap_or(0.);
+        let gear = if gear < 0. {
+            "R".to_string()
+        } else {
+            format!("{:.0}", gear)
+        };
+        fields.push(("Gear".to_string(), format!("\"{}\"", gear)));
+    }
+
+    let mut slip_ratios = Vec::new();
+    let mut slip_angles = Vec::new();
+    for (corner, wheel) in car.corners.iter().zip(&car.wheels) {
+        if let Some(tire) = tires.iter().find(|tire| tire.joint_entity() == *wheel) {
+            slip_ratios.push((corner.clone(), json_number(tire.slip_ratio())));
+            slip_angles.push((corner.clone(), json_number(tire.slip_angle())));
+        }
+    }
+    fields.push(("SlipRatio".to_string(), json_object(&slip_ratios)));
+    fields.push(("SlipAngle".to_string(), json_object(&slip_angles)));
+    let packet = json_object(&fields);
+
+    if broadcast.socket.is_none() {
+        match UdpSocket::bind("0.0.0.0:0").and_then(|socket| {
+            socket.set_broadcast(true)?;
+            Ok(socket)
+        }) {
+            Ok(socket) => broadcast.socket = Some(socket),
+            Err(error) => {
+                warn!("telemetry broadcast socket: {}", error);
+                return;
+            }
+        }
+    }
+    if let Some(socket) = &broadcast.socket {
+        // nothing listening is not an error, the dashboard may be started later
+        let address = (broadcast.host.as_str(), broadcast.port);
+        let _ = socket.send_to(packet.as_bytes(), address);
+    }
+}
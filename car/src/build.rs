@@ -1,34 +1,298 @@
 use bevy::prelude::*;
 
-use cameras::control::CameraParentList;
+use bevy_integrator::{initialize_state, SimTime};
+use cameras::{camera_az_el::AzElCamera, control::CameraParentList};
 use rigid_body::{
     definitions::{MeshDef, MeshTypeDef, TransformDef},
     joint::{Base, Joint},
+    physics_thread::PhysicsIdCommands,
+    structure::{joint_tree_system, loop_1},
     sva::{Inertia, Matrix, Motion, Vector, Xform},
 };
 
 use crate::{
+    abs::Abs,
+    aero::AeroElement,
+    brake_heat::BrakeHeat,
+    buoyancy::Buoyancy,
+    control::{CarControl, CarPart, ChassisJoint, UserControl},
+    damage::Damage,
+    differential::{Axle, Differential, DifferentialType},
+    drive_mode::DriveModes,
+    engine::{Driveline, Engine},
+    fuel::FuelTank,
+    interpolate::Interpolator1D,
+    kinematics::{Alignment, SuspensionKinematics},
+    payload::{Payload, PayloadItem},
     physics::{
-        BrakeWheel, DriveType, DrivenWheelLookup, SteeringCurvature, SteeringType,
-        SuspensionComponent,
+        BrakeWheel, DriveType, SteeringCurvature, SteeringType, SuspensionComponent,
+        SuspensionController,
     },
-    tire::PointTire,
+    recovery::CarRecovery,
+    sensors::{Gps, Imu, WheelSpeedSensors},
+    tire::{PointTire, TireModel},
+    torque_vectoring::TorqueVectoring,
+    transmission::Transmission,
+    turbo::Turbo,
+    wheel_load::WheelLoads,
 };
 
 #[derive(Resource)]
 pub struct CarDefinition {
-    chassis: Chassis,
-    suspension: Vec<Suspension>,
-    wheel: Wheel,
-    drives: Vec<DriveType>,
-    brake: Brake,
+    pub(crate) chassis: Chassis,
+    pub(crate) suspension: Vec<Suspension>,
+    pub(crate) wheel: Wheel,
+    pub(crate) axles: Vec<AxleDef>, // front first, two corners each in `suspension`
+    pub(crate) tire_pressures: Vec<f64>, // kPa, one per corner
+    pub(crate) engine: Engine,
+    pub(crate) transmission: Transmission,
+    pub(crate) turbo: Option<Turbo>,
+    pub(crate) driveline: DrivelineDef,
+    pub(crate) fuel_tank: FuelTankDef,
+    pub(crate) aero: Vec<AeroDef>,
+    pub(crate) brake: Brake,
+    pub(crate) torque_vectoring: Option<TorqueVectoring>,
+    pub(crate) payload: Vec<PayloadItem>,
+    pub(crate) chassis_flex: Option<ChassisFlex>,
+}
+
+impl CarDefinition {
+    // where the chassis is spawned, relative to the base
+    pub fn initial_position(&self) -> [f64; 3] {
+        self.chassis.initial_position
+    }
+
+    // initial yaw angle of the chassis
+    pub fn initial_heading(&self) -> f64 {
+        self.chassis.initial_orientation[2]
+    }
+
+    // vehicle curvature at full steering lock, for curvature steering
+    pub fn max_curvature(&self) -> Option<f64> {
+        self.suspension
+            .iter()
+            .find_map(|suspension| match &suspension.steering {
+                SteeringType::Curvature(steering) => Some(steering.max_curvature),
+                _ => None,
+            })
+    }
+
+    // distance from the front axle to the center of the unsteered axles, the
+    // point the car turns about
+    pub fn wheelbase(&self) -> f64 {
+        self.suspension[0].location[0] - self.rear_reference()
+    }
+
+    fn rear_reference(&self) -> f64 {
+        let unsteered: Vec<f64> = self
+            .suspension
+            .iter()
+            .filter(|suspension| matches!(suspension.steering, SteeringType::None))
+            .map(|suspension| suspension.location[0])
+            .collect();
+        if unsteered.is_empty() {
+            self.suspension[self.suspension.len() - 1].location[0]
+        } else {
+            unsteered.iter().sum::<f64>() / unsteered.len() as f64
+        }
+    }
+
+    // vehicle curvature at full steering lock, for either steering type
+    pub fn lock_curvature(&self) -> f64 {
+        let wheelbase = self.wheelbase();
+        self.suspension
+            .iter()
+            .find_map(|suspension| match &suspension.steering {
+                SteeringType::Curvature(steering) => Some(steering.max_curvature),
+                SteeringType::Angle(steering) => Some(steering.max_angle.tan() / wheelbase),
+                SteeringType::None => None,
+            })
+            .unwrap_or(0.)
+    }
+
+    // distance between the outer edges of the front tires
+    pub fn track_width(&self) -> f64 {
+        (self.suspension[0].location[1] - self.suspension[1].location[1]).abs() + self.wheel.width
+    }
+
+    // low range ratio of the transfer case, if the car has one
+    pub fn low_range(&self) -> Option<f64> {
+        self.transmission.low_range
+    }
+
+    // corner names in wheel order, e.g. fl, fr, rl, rr
+    pub fn corners(&self) -> Vec<String> {
+        self.suspension
+            .iter()
+            .map(|suspension| suspension.name.clone())
+            .collect()
+    }
+
+    // Moves the axles to `positions` (x relative to the chassis, front first), adding or
+    // removing axles. The first `steered` axles steer. New axles are copies of the front
+    // (steered) or the rear axle, the drive and brake shares are scaled back to a total of one.
+    pub fn set_axle_positions(&mut self, positions: &[f64], steered: usize) {
+        let names = axle_names(positions.len());
+        let front = 0;
+        let rear = self.axles.len() - 1;
+        let mut suspension = Vec::new();
+        let mut axles = Vec::new();
+        for (ind, (x, name)) in positions.iter().zip(names).enumerate() {
+            let template = if ind < steered { front } else { rear };
+            axles.push(self.axles[template].clone());
+            for (side, corner) in ["l", "r"].iter().enumerate() {
+                let mut corner_suspension = self.suspension[2 * template + side].clone();
+                corner_suspension.name = format!("{}{}", name, corner);
+                corner_suspension.location[0] = *x;
+                suspension.push(corner_suspension);
+            }
+        }
+        let pressures = (0..suspension.len())
+            .map(|ind| {
+                if ind < 2 * steered {
+                    self.tire_pressures[ind % 2]
+                } else {
+                    self.tire_pressures[self.tire_pressures.len() - 2 + ind % 2]
+                }
+            })
+            .collect();
+        self.suspension = suspension;
+        self.axles = axles;
+        self.tire_pressures = pressures;
+
+        // steered wheels are turned about the center of the unsteered axles
+        let rear_reference = self.rear_reference();
+        for (ind, suspension) in self.suspension.iter_mut().enumerate() {
+            let [x, y, _] = suspension.location;
+            match &mut suspension.steering {
+                SteeringType::Curvature(steering) if ind < 2 * steered => {
+                    steering.x = x - rear_reference;
+                    steering.y = y;
+                }
+                _ if ind < 2 * steered => {}
+                _ => suspension.steering = SteeringType::None,
+            }
+        }
+
+        let drive_total: f64 = self.axles.iter().map(|axle| axle.drive_share).sum();
+        let brake_total: f64 = self.axles.iter().map(|axle| axle.brake_share).sum();
+        for axle in self.axles.iter_mut() {
+            axle.drive_share /= drive_total.max(f64::EPSILON);
+            axle.brake_share /= brake_total.max(f64::EPSILON);
+        }
+    }
+
+    // drive torque split over the axles
+    pub fn set_drivetrain(&mut self, drivetrain: &Drivetrain) {
+        let shares = drivetrain.drive_shares(self.axles.len());
+        for (axle, share) in self.axles.iter_mut().zip(shares) {
+            axle.drive_share = share;
+        }
+    }
+
+    // fraction of the brake torque on the front axle, the rest is split evenly over the other axles
+    pub fn set_brake_bias(&mut self, front_bias: f64) {
+        let rear_axles = (self.axles.len() - 1).max(1) as f64;
+        for (ind, axle) in self.axles.iter_mut().enumerate() {
+            axle.brake_share = match ind {
+                0 => front_bias,
+                _ => (1. - front_bias) / rear_axles,
+            };
+        }
+    }
+
+    // brake of each wheel on `axle`
+    pub fn wheel_brake(&self, axle: usize) -> BrakeWheel {
+        let axle = &self.axles[axle];
+        let handbrake_torque = if axle.handbrake {
+            self.brake.handbrake_torque
+        } else {
+            0.
+        };
+        BrakeWheel::new(
+            self.brake.total_torque * axle.brake_share / 2.,
+            handbrake_torque,
+        )
+    }
+
+    // drive torque moved between the wheels of the driven axle (the rear one
+    // with all wheel drive) to follow the steering, e.g. `TorqueVectoring::new(2000., 600.)`
+    pub fn with_torque_vectoring(mut self, vectoring: TorqueVectoring) -> Self {
+        self.torque_vectoring = Some(vectoring);
+        self
+    }
+
+    // torsionally flexible chassis, e.g. `ChassisFlex::new(100000.)`
+    pub fn with_chassis_flex(mut self, flex: ChassisFlex) -> Self {
+        self.chassis_flex = Some(flex);
+        self
+    }
+
+    // transfer case with a low range of `ratio` on top of the gearbox, starting
+    // in the low range (`L` switches ranges)
+    pub fn with_low_range(mut self, ratio: f64) -> Self {
+        self.transmission = self.transmission.with_low_range(ratio).in_low_range();
+        self
+    }
+
+    // mass carried by the chassis, e.g. `PayloadItem::new("load", 300., [-1., 0., 0.2])`
+    pub fn with_payload(mut self, item: PayloadItem) -> Self {
+        self.payload.push(item);
+        self
+    }
+}
+
+// Which wheels the engine drives
+#[derive(Clone)]
+pub enum Drivetrain {
+    FrontWheelDrive,
+    RearWheelDrive,                     // all axles behind the front one
+    AllWheelDrive { front_split: f64 }, // fraction of the torque sent to the front axle
+}
+
+impl Drivetrain {
+    // fraction of the drive torque on each axle (front first)
+    pub fn drive_shares(&self, axles: usize) -> Vec<f64> {
+        let rear_axles = (axles - 1).max(1) as f64;
+        (0..axles)
+            .map(|ind| match (self, ind) {
+                (Drivetrain::FrontWheelDrive, 0) => 1.,
+                (Drivetrain::FrontWheelDrive, _) => 0.,
+                (Drivetrain::RearWheelDrive, 0) => 0.,
+                (Drivetrain::RearWheelDrive, _) => 1. / rear_axles,
+                (Drivetrain::AllWheelDrive { front_split }, 0) => *front_split,
+                (Drivetrain::AllWheelDrive { front_split }, _) => (1. - front_split) / rear_axles,
+            })
+            .collect()
+    }
+}
+
+// An axle of the car, a left and a right corner
+#[derive(Clone)]
+pub struct AxleDef {
+    pub drive_share: f64, // fraction of the drive torque, zero if not driven
+    pub brake_share: f64, // fraction of the brake torque
+    pub handbrake: bool,
+    pub solid: bool, // beam axle instead of independent suspension
+}
+
+// f and r for two axles, f, m and r for three, and numbered middle axles for more
+fn axle_names(axles: usize) -> Vec<String> {
+    (0..axles)
+        .map(|ind| match ind {
+            0 => "f".to_string(),
+            _ if ind == axles - 1 => "r".to_string(),
+            _ if axles == 3 => "m".to_string(),
+            _ => format!("m{}", ind),
+        })
+        .collect()
 }
 
 const CHASSIS_MASS: f64 = 1000.;
 const SUSPENSION_MASS: f64 = 20.;
 const GRAVITY: f64 = 9.81;
 
-pub fn build_car() -> CarDefinition {
+pub fn build_car(drivetrain: Drivetrain) -> CarDefinition {
     // Chassis
     let mass = 1000.;
     let dimensions = [3.0_f64, 1.2, 0.4]; // shape of rectangular chassis
@@ -83,10 +347,33 @@ pub fn build_car() -> CarDefinition {
             } else {
                 SteeringType::None
             };
+            // front wheels have caster, the rear toe in for stability
+            let alignment = if ind < 2 {
+                Alignment {
+                    camber: -1.0_f64.to_radians(),
+                    toe: 0.,
+                    caster: 5.0_f64.to_radians(),
+                    camber_gain: -0.5,
+                    bump_steer: 0.,
+                }
+            } else {
+                Alignment {
+                    camber: -1.5_f64.to_radians(),
+                    toe: 0.2_f64.to_radians(),
+                    caster: 0.,
+                    camber_gain: -0.5,
+                    bump_steer: 0.,
+                }
+            };
             Suspension {
                 name,
                 mass: suspension_mass,
                 steering,
+                alignment,
+                travel: [0.1, 0.12],
+                // passive dampers, semi-active with e.g.
+                // Some(SuspensionController::Skyhook { sky_damping: 2500., min_damping: 500., max_damping: 5000. })
+                controller: None,
                 stiffness: suspension_stiffness,
                 damping: suspension_damping,
                 preload: suspension_preload,
@@ -98,107 +385,527 @@ pub fn build_car() -> CarDefinition {
 
     // Wheel
     let wheel = build_wheel();
+    let tire_pressures = vec![wheel.nominal_pressure; suspension.len()];
 
-    // // Drive and Brake
-    let drive_speeds = vec![0., 25., 50., 75.];
-    let drive_torques = vec![1000., 1000., 600., 250.];
+    // Engine (speeds in rad/s)
+    let rpm = |rpm: f64| rpm * std::f64::consts::PI / 30.;
+    let engine = Engine::new(
+        [0., 1000., 3000., 5000., 6500., 7000.].map(rpm).to_vec(),
+        vec![150., 200., 250., 240., 200., 180.],
+        0.2,
+        rpm(800.),
+        rpm(7000.),
+    )
+    .with_throttle_map(vec![0., 0.5, 1.], vec![0., 0.7, 1.]);
 
-    let rear_drive = DriveType::DrivenWheelLookup(DrivenWheelLookup::new(
-        "fl".to_string(),
-        drive_speeds.clone(),
-        drive_torques.clone(),
-    ));
+    let transmission = Transmission::new(
+        vec![3.2, 2.1, 1.5, 1.15, 0.9],
+        3.9,
+        rpm(6500.),
+        rpm(3000.),
+        0.2,
+    )
+    .with_reverse(3.4);
+
+    let turbo = Some(Turbo::new(0.4, 0.8, rpm(3500.)));
 
-    let drives = vec![
-        DriveType::None,
-        DriveType::None,
-        rear_drive.clone(),
-        rear_drive.clone(),
+    let driveline = DrivelineDef {
+        damping: 10.,
+        differential_damping: 500.,
+        capacity: 400.,
+        engagement: [rpm(1000.), rpm(2000.)],
+        differential: DifferentialType::LimitedSlip {
+            preload: 50.,
+            bias_ratio: 2.5,
+        },
+        center_differential: DifferentialType::LimitedSlip {
+            preload: 100.,
+            bias_ratio: 2.,
+        },
+    };
+
+    // 50 liter tank behind the rear axle, 250 g/kWh
+    let fuel_tank = FuelTankDef {
+        fuel: 37.,
+        position: [-1.4, 0., -0.1],
+        specific_consumption: 0.25 / 3.6e6,
+    };
+
+    // Aero, coefficients against forward speed (m/s). The front splitter loses
+    // downforce at high speed, which moves the aero balance rearward.
+    let aero = vec![
+        AeroDef {
+            position: [1.6, 0., -0.25],
+            area: 0.3,
+            lift: Interpolator1D::new(vec![0., 30., 60.], vec![1.0, 1.0, 0.8]),
+            drag: Interpolator1D::new(vec![0.], vec![0.1]),
+        },
+        AeroDef {
+            position: [-1.45, 0., 0.5],
+            area: 0.6,
+            lift: Interpolator1D::new(vec![0.], vec![1.2]),
+            drag: Interpolator1D::new(vec![0.], vec![0.3]),
+        },
+        // body drag, no lift
+        AeroDef {
+            position: [0., 0., 0.],
+            area: 1.8,
+            lift: Interpolator1D::new(vec![0.], vec![0.]),
+            drag: Interpolator1D::new(vec![0.], vec![0.3]),
+        },
     ];
 
+    // Drive and Brake, the handbrake acts on the rear axle
+    let axles = drivetrain
+        .drive_shares(2)
+        .into_iter()
+        .enumerate()
+        .map(|(ind, drive_share)| AxleDef {
+            drive_share,
+            brake_share: 0.,
+            handbrake: ind > 0,
+            solid: false,
+        })
+        .collect();
+
     let brake = Brake {
-        front_torque: 800.,
-        rear_torque: 400.,
+        total_torque: 2400.,
+        handbrake_torque: 2000.,
+        abs: Some(Abs::new(0.15, 20.)),
     };
 
-    CarDefinition {
+    let mut car = CarDefinition {
         chassis,
         suspension,
         wheel,
-        drives,
+        axles,
+        tire_pressures,
+        engine,
+        transmission,
+        turbo,
+        driveline,
+        fuel_tank,
+        aero,
         brake,
-    }
+        torque_vectoring: None,
+        payload: Vec::new(),
+        chassis_flex: None,
+    };
+    car.set_brake_bias(0.67);
+    car
 }
 
 pub fn build_wheel() -> Wheel {
-    let wheel_mass = 20.;
-    let wheel_radius = 0.325_f64;
-    let wheel_moi_y = wheel_mass * wheel_radius.powi(2);
-    let wheel_moi_xz = 1. / 12. * 10. * (3. * wheel_radius.powi(2));
-    let corner_mass = CHASSIS_MASS / 4. + SUSPENSION_MASS + wheel_mass;
+    sized_wheel(0.325, 0.2, 20., CHASSIS_MASS / 4. + SUSPENSION_MASS)
+}
+
+// wheel of the given size, the tire stiffness is set from the load it carries
+// (sprung mass and suspension mass per corner)
+pub fn sized_wheel(radius: f64, width: f64, mass: f64, corner_mass: f64) -> Wheel {
+    let wheel_moi_y = mass * radius.powi(2);
+    let wheel_moi_xz = 1. / 12. * (mass / 2.) * (3. * radius.powi(2));
+    let corner_mass = corner_mass + mass;
     let wheel_stiffness = corner_mass * GRAVITY / 0.005;
-    let wheel_damping = 0.01 * 2. * (wheel_stiffness * wheel_mass).sqrt();
+    let wheel_damping = 0.01 * 2. * (wheel_stiffness * mass).sqrt();
     Wheel {
-        mass: wheel_mass,
-        radius: wheel_radius,
-        width: 0.2_f64,
+        mass,
+        radius,
+        width,
         moi_y: wheel_moi_y,
         moi_xz: wheel_moi_xz,
         stiffness: [wheel_stiffness, 0.],
         damping: wheel_damping,
-        coefficient_of_friction: 0.8,
-        rolling_radius: 0.315,
+        rolling_radius: radius - 0.01,
+        nominal_pressure: 220.,
         low_speed: 1.0,
-        normalized_slip_stiffness: 20.0,
+        // TireModel::MagicFormula(MagicFormula::default()) for a Pacejka tire,
+        // or TireModel::Brush(Brush::new(60000.)) for a brush tire
+        tire_model: TireModel::Linear {
+            normalized_slip_stiffness: 20.0,
+        },
         filter_time: 0.005,
     }
 }
 
-pub fn car_startup_system(mut commands: Commands, car: ResMut<CarDefinition>) {
+pub fn car_startup_system(mut commands: Commands, car: Res<CarDefinition>) {
+    // the car is driven with the keyboard and any gamepad
+    let entities = spawn_initial_car(&mut commands, &car);
+    commands
+        .entity(entities.chassis)
+        .insert(UserControl::default());
+    commands.insert_resource(entities);
+}
+
+// The car at its initial position, driven only through the `CarControl` on its
+// chassis, by a co-simulation client, an FMU or Python
+pub fn external_car_startup_system(mut commands: Commands, car: Res<CarDefinition>) {
+    let entities = spawn_initial_car(&mut commands, &car);
+    commands.insert_resource(entities);
+}
+
+// the base and the car at its initial position, with the cameras following them
+fn spawn_initial_car(commands: &mut Commands, car: &CarDefinition) -> CarEntities {
     let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
-    let base_id = commands.spawn((base, Base)).id();
+    let base_id = commands.spawn((base, Base)).insert_physics_id().id();
+    let entities = spawn_car(
+        commands,
+        car,
+        base_id,
+        car.initial_position(),
+        car.initial_heading(),
+        Color::rgb(0.9, 0.1, 0.2),
+    );
+
+    let mut camera_parent_list = entities.camera_parents.clone();
+    camera_parent_list.push(base_id); // stationary camera
+    commands.insert_resource(CameraParentList {
+        list: camera_parent_list,
+        active: 0, // start with following x, y, z and yaw of chassis
+    });
+    entities
+}
+
+// Despawns the cars (the joints below the bases, and the tires, aero elements
+// and buoyancy acting on them) and spawns them again with `startup`, from their
+// initial states and the start time. The cameras riding on a car stay, the car
+// attachments (interior, lamps, camera sensor...) are built again by their
+// systems. The joint positions and velocities are those of the initial states.
+pub fn respawn_car<M>(world: &mut World, startup: impl IntoSystem<(), (), M>) {
+    let mut cameras = world.query_filtered::<Entity, (With<AzElCamera>, With<Parent>)>();
+    for camera in cameras.iter(world).collect::<Vec<_>>() {
+        world.entity_mut(camera).remove_parent();
+    }
+    let mut bases = world.query_filtered::<Entity, With<Base>>();
+    for base in bases.iter(world).collect::<Vec<_>>() {
+        world.entity_mut(base).despawn_recursive();
+    }
+    let mut forces =
+        world.query_filtered::<Entity, Or<(With<PointTire>, With<AeroElement>, With<Buoyancy>)>>();
+    for force in forces.iter(world).collect::<Vec<_>>() {
+        world.entity_mut(force).despawn_recursive();
+    }
+
+    run_system(world, startup);
+    run_system(world, initialize_state::<Joint>);
+    run_system(world, joint_tree_system);
+    run_system(world, loop_1);
+    world.resource_mut::<SimTime>().reset();
+}
+
+// runs a startup system again, with its commands applied
+pub(crate) fn run_system<M>(world: &mut World, system: impl IntoSystem<(), (), M>) {
+    let mut system = IntoSystem::into_system(system);
+    system.initialize(world);
+    system.run((), world);
+    system.apply_deferred(world);
+}
 
+// Spawns a car below the base joint, heading is the initial yaw angle. The car is
+// driven through the `CarControl` on its chassis entity, add a control source
+// (e.g. `UserControl` or `AiDriver`) to drive it.
+pub fn spawn_car(
+    commands: &mut Commands,
+    car: &CarDefinition,
+    base_id: Entity,
+    initial_position: [f64; 3],
+    heading: f64,
+    color: Color,
+) -> CarEntities {
     // Chassis
-    let chassis_ids = car
-        .chassis
-        .build(&mut commands, Color::rgb(0.9, 0.1, 0.2), base_id);
+    let mut chassis = car.chassis.clone();
+    chassis.initial_position = initial_position;
+    chassis.initial_orientation[2] = heading;
+    let chassis_ids = chassis.build(commands, color, base_id);
     let chassis_id = chassis_ids[3]; // ids are not ordered by parent child order!!! "3" is rx, the last joint in the chain
 
-    let camera_parent_list = vec![
+    // the chassis holds the car controls, and its speed is used by the steering filter.
+    // The car is respawned a little above its resting height.
+    let recovery = CarRecovery::new(
+        [
+            chassis_ids[0],
+            chassis_ids[1],
+            chassis_ids[2],
+            chassis_ids[3],
+            chassis_ids[4],
+            chassis_ids[5],
+        ],
+        car.chassis.initial_position[2] + 0.2,
+    );
+    commands
+        .entity(chassis_id)
+        .insert((ChassisJoint, CarControl::default(), recovery));
+    let part = CarPart(chassis_id);
+
+    // chassis floats and is slowed down when driving through water
+    commands.spawn(Buoyancy::new(
+        chassis_id,
+        car.chassis.dimensions,
+        car.chassis.position,
+        [6, 3, 2],
+        1.0,
+    ));
+
+    let camera_parents = vec![
         chassis_ids[5], // follow x, y and z and yaw of chassis
         // chassis_ids[0], // only follow x of chassis (why would you do that?)
         chassis_ids[1], // follow x and y of chassis
         chassis_ids[2], // follow x, y and z of chassis
         chassis_ids[3], // follow all motion of chassis
-        base_id,        // stationary camera
                         // chassis_ids[4],
     ];
 
-    commands.insert_resource(CameraParentList {
-        list: camera_parent_list,
-        active: 0, // start with following x, y, z and yaw of chassis
-    });
+    // with a flexible chassis, the corners behind the flex joint are mounted on
+    // the rear half, their location is relative to the joint
+    let flex_id = car
+        .chassis_flex
+        .as_ref()
+        .map(|flex| flex.build(commands, chassis_id));
+    let mounts: Vec<(Entity, Suspension)> = car
+        .suspension
+        .iter()
+        .map(|susp| {
+            let mut susp = susp.clone();
+            match (&car.chassis_flex, flex_id) {
+                (Some(flex), Some(flex_id)) if susp.location[0] < flex.position => {
+                    susp.location[0] -= flex.position;
+                    (flex_id, susp)
+                }
+                _ => (chassis_id, susp),
+            }
+        })
+        .collect();
 
-    for (ind, susp) in car.suspension.iter().enumerate() {
-        let braked_wheel = if ind < 2 {
-            Some(BrakeWheel {
-                max_torque: car.brake.front_torque,
-            })
+    // a solid axle carries both wheels of the axle
+    let solid_axles: Vec<(usize, [Entity; 2])> = car
+        .axles
+        .iter()
+        .enumerate()
+        .filter(|(_, axle)| axle.solid)
+        .map(|(ind, _)| {
+            let [(mount_id, left), (_, right)] = [&mounts[2 * ind], &mounts[2 * ind + 1]];
+            (ind, build_solid_axle(commands, *mount_id, left, right))
+        })
+        .collect();
+
+    let mut suspension_ids = Vec::new();
+    let mut wheel_ids = Vec::new();
+    for (ind, (mount_id, susp)) in mounts.iter().enumerate() {
+        let axle = ind / 2;
+        let braked_wheel = Some(car.wheel_brake(axle));
+        let drive = if car.axles[axle].drive_share > 0. {
+            DriveType::Engine
         } else {
-            Some(BrakeWheel {
-                max_torque: car.brake.rear_torque,
-            })
+            DriveType::None
+        };
+        let solid_axle = solid_axles.iter().find(|(solid, _)| *solid == axle);
+        let (parent_id, xt_wheel, kinematics) = match solid_axle {
+            Some((_, [_, roll_id])) => {
+                // wheel at the end of the axle, the axle roll sets the camber
+                let half_track = (car.suspension[2 * axle].location[1]
+                    - car.suspension[2 * axle + 1].location[1])
+                    / 2.;
+                let y = if ind % 2 == 0 {
+                    half_track
+                } else {
+                    -half_track
+                };
+                let xt = Xform::new(Vector::new(0., y, 0.), Matrix::identity());
+                (*roll_id, xt, None)
+            }
+            _ => {
+                let (id_susp, id_steer) = susp.build(commands, *mount_id, &susp.location);
+                suspension_ids.push(id_susp);
+                if let Some(id_steer) = id_steer {
+                    commands.entity(id_steer).insert(part);
+                }
+                let kinematics = SuspensionKinematics {
+                    alignment: susp.alignment.clone(),
+                    suspension: id_susp,
+                    steering: id_steer,
+                    side: susp.location[1].signum(),
+                };
+                (id_susp, Xform::identity(), Some(kinematics))
+            }
         };
-        let id_susp = susp.build(&mut commands, chassis_id, &susp.location);
-        let _wheel_id = car.wheel.build(
-            &mut commands,
+        let wheel_id = car.wheel.build(
+            commands,
             &susp.name,
-            id_susp,
-            car.drives[ind].clone(),
+            parent_id,
+            xt_wheel,
+            drive,
             braked_wheel,
+            car.tire_pressures[ind],
             0.,
         );
+        commands.entity(wheel_id).insert(part);
+        if let Some(kinematics) = kinematics {
+            commands.entity(wheel_id).insert(kinematics);
+        }
+        wheel_ids.push(wheel_id);
+    }
+    if let Some(abs) = &car.brake.abs {
+        for wheel_id in wheel_ids.iter() {
+            commands.entity(*wheel_id).insert(abs.clone());
+        }
+    }
+
+    // driven axles, front first
+    let driven: Vec<(usize, f64)> = car
+        .axles
+        .iter()
+        .enumerate()
+        .filter(|(_, axle)| axle.drive_share > 0.)
+        .map(|(ind, axle)| (ind, axle.drive_share))
+        .collect();
+
+    // torque vectoring on the last driven axle, the rear one with all wheel drive
+    if let (Some(vectoring), Some((axle, _))) = (&car.torque_vectoring, driven.last()) {
+        let mut vectoring = vectoring.clone();
+        vectoring.wheels = [wheel_ids[2 * axle], wheel_ids[2 * axle + 1]];
+        vectoring.max_curvature = car.lock_curvature();
+        commands.entity(chassis_id).insert(vectoring);
+    }
+
+    let axles = driven
+        .iter()
+        .map(|(axle, _)| Axle {
+            wheels: [wheel_ids[2 * axle], wheel_ids[2 * axle + 1]],
+            differential: Differential::new(car.driveline.differential.clone())
+                .with_damping(car.driveline.differential_damping),
+        })
+        .collect();
+    // each center differential splits between its axle and the axles behind it
+    let centers = (0..driven.len().saturating_sub(1))
+        .map(|ind| {
+            let behind: f64 = driven[ind..].iter().map(|(_, share)| share).sum();
+            Differential::new(car.driveline.center_differential.clone())
+                .with_split(driven[ind].1 / behind)
+        })
+        .collect();
+
+    // engine spins relative to the chassis, so its reaction torque acts on the chassis
+    let engine_inertia = Inertia::new(
+        0.,
+        Vector::zeros(),
+        Matrix::from_diagonal(&Vector::new(0., car.engine.inertia, 0.)),
+    );
+    let mut engine = Joint::ry("engine".to_string(), engine_inertia, Xform::identity());
+    engine.qd = car.engine.idle_speed;
+    let mut engine_e = commands.spawn((
+        engine,
+        car.engine.clone(),
+        car.transmission.clone(),
+        Driveline::new(
+            axles,
+            centers,
+            car.driveline.damping,
+            car.driveline.capacity,
+            car.driveline.engagement,
+        ),
+    ));
+    engine_e.insert(part).insert_physics_id();
+    if let Some(turbo) = &car.turbo {
+        engine_e.insert(turbo.clone());
+    }
+    engine_e.set_parent(chassis_id);
+    let engine_id = engine_e.id();
+
+    // the fuel tank updates the chassis mass as fuel is consumed
+    let [x, y, z] = car.chassis.cg_position;
+    let [ixx, iyy, izz] = car.chassis.moi;
+    let [tx, ty, tz] = car.fuel_tank.position;
+    commands.entity(chassis_id).insert(FuelTank::new(
+        engine_id,
+        car.fuel_tank.fuel,
+        Vector::new(tx, ty, tz),
+        car.fuel_tank.specific_consumption,
+        car.chassis.mass,
+        Vector::new(x, y, z),
+        Matrix::from_diagonal(&Vector::new(ixx, iyy, izz)),
+    ));
+
+    // impacts damage the engine, steering and wheels, and dent the body
+    let wheels = car
+        .suspension
+        .iter()
+        .zip(&wheel_ids)
+        .map(|(suspension, wheel)| {
+            let [x, y, z] = suspension.location;
+            (suspension.name.clone(), *wheel, Vector::new(x, y, z))
+        })
+        .collect();
+    commands.entity(chassis_id).insert(
+        Damage::default()
+            .with_parts(engine_id, wheels)
+            .with_body(car.chassis.dimensions, car.chassis.position),
+    );
+
+    // the payload is added to the chassis inertia with the fuel
+    commands
+        .entity(chassis_id)
+        .insert(Payload::new(car.payload.clone()));
+
+    // downforce and drag act on the chassis
+    for aero in car.aero.iter() {
+        commands.spawn(AeroElement::new(
+            chassis_id,
+            aero.position,
+            aero.area,
+            aero.lift.clone(),
+            aero.drag.clone(),
+        ));
     }
+
+    // drive modes adjust the dampers and the ABS of the car
+    let mut suspensions = suspension_ids.clone();
+    for (_, axle) in solid_axles.iter() {
+        suspensions.extend(axle);
+    }
+    commands
+        .entity(chassis_id)
+        .insert(DriveModes::default().with_parts(suspensions, wheel_ids.clone()));
+
+    commands
+        .entity(chassis_id)
+        .insert(WheelLoads::new(wheel_ids.clone()));
+
+    // IMU at the center of gravity, GPS antenna on the roof of the chassis box
+    let roof = [
+        car.chassis.position[0],
+        0.,
+        car.chassis.position[2] + car.chassis.dimensions[2] / 2.,
+    ];
+    let wheel_sensors = car.corners().into_iter().zip(wheel_ids.clone()).collect();
+    commands.entity(chassis_id).insert((
+        Imu::default().with_mounting(car.chassis.cg_position, [0., 0., 0.]),
+        WheelSpeedSensors::new(wheel_sensors),
+        Gps::default().with_antenna(roof),
+    ));
+
+    CarEntities {
+        chassis: chassis_id,
+        camera_parents,
+        suspensions: suspension_ids,
+        solid_axles,
+        chassis_flex: flex_id,
+        wheels: wheel_ids,
+        corners: car.corners(),
+        engine: engine_id,
+    }
+}
+
+// Entities of the spawned car (wheel order fl, fr, rl, rr for two axles), so
+// the car can be updated after it is built
+#[derive(Resource, Clone)]
+pub struct CarEntities {
+    pub chassis: Entity,
+    pub camera_parents: Vec<Entity>, // chassis joints the camera can follow
+    pub suspensions: Vec<Entity>,    // independent suspensions
+    pub solid_axles: Vec<(usize, [Entity; 2])>, // axle index, heave and roll joints
+    pub chassis_flex: Option<Entity>, // joint between the chassis halves
+    pub wheels: Vec<Entity>,
+    pub corners: Vec<String>, // names of the wheels
+    pub engine: Entity,
 }
 
 #[derive(Clone)]
@@ -219,35 +926,35 @@ impl Chassis {
         let mut px = Joint::px("chassis_px".to_string(), Inertia::zero(), Xform::identity());
         px.q = self.initial_position[0];
         let mut px_e = commands.spawn((px,));
-        px_e.set_parent(parent_id);
+        px_e.set_parent(parent_id).insert_physics_id();
         let px_id = px_e.id();
 
         // y degree of freedom (absolute coordinate system, not relative to car)
         let mut py = Joint::py("chassis_py".to_string(), Inertia::zero(), Xform::identity());
         py.q = self.initial_position[1];
         let mut py_e = commands.spawn((py,));
-        py_e.set_parent(px_id);
+        py_e.set_parent(px_id).insert_physics_id();
         let py_id = py_e.id();
 
         // z degree of freedom (always points "up", relative to absolute coordinate system)
         let mut pz = Joint::pz("chassis_pz".to_string(), Inertia::zero(), Xform::identity());
         pz.q = self.initial_position[2];
         let mut pz_e = commands.spawn((pz,));
-        pz_e.set_parent(py_id);
+        pz_e.set_parent(py_id).insert_physics_id();
         let pz_id = pz_e.id();
 
         // yaw degree of freedom (rotation around z axis)
         let mut rz = Joint::rz("chassis_rz".to_string(), Inertia::zero(), Xform::identity());
         rz.q = self.initial_orientation[2];
         let mut rz_e = commands.spawn((rz,));
-        rz_e.set_parent(pz_id);
+        rz_e.set_parent(pz_id).insert_physics_id();
         let rz_id = rz_e.id();
 
         // pitch degree of freedom (rotation around y axis)
         let mut ry = Joint::ry("chassis_ry".to_string(), Inertia::zero(), Xform::identity());
         ry.q = self.initial_orientation[1];
         let mut ry_e = commands.spawn((ry,));
-        ry_e.set_parent(rz_id);
+        ry_e.set_parent(rz_id).insert_physics_id();
         let ry_id = ry_e.id();
 
         // roll degree of freedom (rotation around x axis)
@@ -266,7 +973,7 @@ impl Chassis {
         let mut rx = Joint::rx("chassis_rx".to_string(), inertia, Xform::identity());
         rx.q = self.initial_orientation[0];
         let mut rx_e = commands.spawn((rx,));
-        rx_e.set_parent(ry_id);
+        rx_e.set_parent(ry_id).insert_physics_id();
         let rx_id = rx_e.id();
         if let Some(chassis_file) = &self.mesh_file {
             rx_e.insert(MeshDef {
@@ -296,11 +1003,57 @@ impl Chassis {
     }
 }
 
+// Torsional flex of the chassis. The chassis is split at `position` into a front
+// half (the chassis joint, with the chassis mass) and a light rear half that
+// twists about the chassis x axis against a torsion spring. The corners behind
+// `position` are on the rear half. A soft chassis moves lateral load transfer
+// to the front axle, the stiffer end of the car.
+#[derive(Clone)]
+pub struct ChassisFlex {
+    pub stiffness: f64, // torsional, Nm/rad
+    pub damping: f64,   // Nms/rad
+    pub position: f64,  // x of the split, relative to the chassis
+    pub moi: f64,       // roll inertia of the rear half structure
+}
+
+impl ChassisFlex {
+    pub fn new(stiffness: f64) -> Self {
+        let moi = 20.;
+        Self {
+            stiffness,
+            damping: 0.1 * 2. * (stiffness * moi).sqrt(),
+            position: 0.,
+            moi,
+        }
+    }
+
+    pub fn component(&self) -> SuspensionComponent {
+        SuspensionComponent::new(self.stiffness, self.damping, 0.)
+    }
+
+    // returns the rear half joint
+    pub fn build(&self, commands: &mut Commands, chassis_id: Entity) -> Entity {
+        let inertia = Inertia::new(
+            0.,
+            Vector::zeros(),
+            Matrix::from_diagonal(&Vector::new(self.moi, 0., 0.)),
+        );
+        let xt = Xform::new(Vector::new(self.position, 0., 0.), Matrix::identity());
+        let flex = Joint::rx("chassis_flex".to_string(), inertia, xt);
+        let mut flex_e = commands.spawn((flex, SpatialBundle::default(), self.component()));
+        flex_e.set_parent(chassis_id).insert_physics_id();
+        flex_e.id()
+    }
+}
+
 #[derive(Clone)]
 pub struct Suspension {
     pub name: String,
     pub mass: f64,
     pub steering: SteeringType,
+    pub alignment: Alignment,
+    pub travel: [f64; 2], // bump and droop travel from the static position
+    pub controller: Option<SuspensionController>,
     pub stiffness: f64,
     pub damping: f64,
     pub preload: f64,
@@ -309,12 +1062,22 @@ pub struct Suspension {
 }
 
 impl Suspension {
+    pub fn component(&self) -> SuspensionComponent {
+        let mut suspension = SuspensionComponent::new(self.stiffness, self.damping, self.preload)
+            .with_travel(self.travel[0], self.travel[1]);
+        if let Some(controller) = self.controller.clone() {
+            suspension = suspension.with_controller(controller);
+        }
+        suspension
+    }
+
+    // returns the suspension and the steering entity (if steered)
     pub fn build(
         &self,
         commands: &mut Commands,
         mut parent_id: Entity,
         location: &[f64; 3],
-    ) -> Entity {
+    ) -> (Entity, Option<Entity>) {
         // suspension transform
         let mut xt_susp = Xform::new(
             Vector::new(location[0], location[1], location[2]), // location of suspension relative to chassis
@@ -328,15 +1091,17 @@ impl Suspension {
             self.moi * Matrix::identity(), // inertia
         );
 
+        let mut steer_id = None;
         match self.steering.clone() {
             SteeringType::None => {}
             SteeringType::Curvature(steering) => {
                 let steer_name = ("steer_".to_owned() + &self.name).to_string();
                 let steer = Joint::rz(steer_name, Inertia::zero(), xt_susp);
                 let mut steer_e = commands.spawn((steer, steering));
-                steer_e.set_parent(parent_id);
+                steer_e.set_parent(parent_id).insert_physics_id();
 
                 parent_id = steer_e.id();
+                steer_id = Some(parent_id);
                 xt_susp = Xform::identity();
             }
             SteeringType::Angle(steering) => {
@@ -344,9 +1109,10 @@ impl Suspension {
                 let steer_name = ("steer_".to_owned() + &self.name).to_string();
                 let steer = Joint::rz(steer_name, Inertia::zero(), xt_susp);
                 let mut steer_e = commands.spawn((steer, steering));
-                steer_e.set_parent(parent_id);
+                steer_e.set_parent(parent_id).insert_physics_id();
 
                 parent_id = steer_e.id();
+                steer_id = Some(parent_id);
                 xt_susp = Xform::identity();
             }
         }
@@ -356,17 +1122,68 @@ impl Suspension {
         let susp = Joint::pz(name, inertia, xt_susp);
 
         // create suspension entity
-        let mut susp_e = commands.spawn((
-            susp,
-            SpatialBundle::default(),
-            SuspensionComponent::new(self.stiffness, self.damping, self.preload),
-        ));
-        susp_e.set_parent(parent_id);
+        let mut susp_e = commands.spawn((susp, SpatialBundle::default(), self.component()));
+        susp_e.set_parent(parent_id).insert_physics_id();
 
-        susp_e.id()
+        (susp_e.id(), steer_id)
     }
 }
 
+// Spring and damper of a solid axle carrying two corners: the corner springs
+// act together in heave (vertical travel) and at half the track apart in roll
+pub fn solid_axle_components(left: &Suspension, right: &Suspension) -> [SuspensionComponent; 2] {
+    let half_track = (left.location[1] - right.location[1]) / 2.;
+    let heave = SuspensionComponent::new(
+        left.stiffness + right.stiffness,
+        left.damping + right.damping,
+        left.preload + right.preload,
+    )
+    .with_travel(left.travel[0], left.travel[1]);
+    let roll = SuspensionComponent::new(
+        (left.stiffness + right.stiffness) * half_track.powi(2),
+        (left.damping + right.damping) * half_track.powi(2),
+        0.,
+    );
+    [heave, roll]
+}
+
+// returns the heave and roll joint of the axle, the wheels are children of the roll joint
+pub fn build_solid_axle(
+    commands: &mut Commands,
+    parent_id: Entity,
+    left: &Suspension,
+    right: &Suspension,
+) -> [Entity; 2] {
+    let center = [0, 1, 2].map(|i| (left.location[i] + right.location[i]) / 2.);
+    let half_track = (left.location[1] - right.location[1]) / 2.;
+    let xt_axle = Xform::new(
+        Vector::new(center[0], center[1], center[2]),
+        Matrix::identity(),
+    );
+    let [heave_component, roll_component] = solid_axle_components(left, right);
+    let name = left.name.trim_end_matches('l');
+
+    // heave degree of freedom, massless
+    let heave = Joint::pz(format!("susp_{}_axle", name), Inertia::zero(), xt_axle);
+    let mut heave_e = commands.spawn((heave, SpatialBundle::default(), heave_component));
+    heave_e.set_parent(parent_id).insert_physics_id();
+    let heave_id = heave_e.id();
+
+    // roll degree of freedom, the axle beam with the suspension mass at each end
+    let mass = left.mass + right.mass;
+    let moi_x = mass * half_track.powi(2);
+    let inertia = Inertia::new(
+        mass,
+        Vector::zeros(),
+        Matrix::from_diagonal(&Vector::new(moi_x, left.moi + right.moi, moi_x)),
+    );
+    let roll = Joint::rx(format!("roll_{}_axle", name), inertia, Xform::identity());
+    let mut roll_e = commands.spawn((roll, SpatialBundle::default(), roll_component));
+    roll_e.set_parent(heave_id).insert_physics_id();
+
+    [heave_id, roll_e.id()]
+}
+
 #[derive(Resource, Clone)]
 pub struct Wheel {
     pub mass: f64,
@@ -376,10 +1193,10 @@ pub struct Wheel {
     pub moi_xz: f64,
     pub stiffness: [f64; 2],
     pub damping: f64,
-    pub coefficient_of_friction: f64,
     pub rolling_radius: f64,
+    pub nominal_pressure: f64, // kPa, pressure the stiffness and rolling radius are given for
     pub low_speed: f64,
-    pub normalized_slip_stiffness: f64,
+    pub tire_model: TireModel,
     pub filter_time: f64,
 }
 
@@ -389,8 +1206,10 @@ impl Wheel {
         commands: &mut Commands,
         corner_name: &String,
         parent_id: Entity,
+        xt: Xform, // wheel center relative to the parent
         driven_wheel: DriveType,
         braked_wheel: Option<BrakeWheel>,
+        pressure: f64,
         initial_speed: f64,
     ) -> Entity {
         // wheel inertia
@@ -402,7 +1221,7 @@ impl Wheel {
 
         // create wheel joint
         let name = ("wheel_".to_owned() + corner_name).to_string();
-        let mut ry = Joint::ry(name, inertia, Xform::identity());
+        let mut ry = Joint::ry(name, inertia, xt);
         ry.qd = initial_speed;
 
         let mut wheel_e = commands.spawn((
@@ -419,46 +1238,72 @@ impl Wheel {
 
         // add driven and braked components
         match driven_wheel {
-            DriveType::None => {}
-            DriveType::DrivenWheelLookup(driven) => {
-                wheel_e.insert(driven);
-            }
+            DriveType::None | DriveType::Engine => {}
             DriveType::DrivenWheel(driven) => {
                 wheel_e.insert(driven);
             }
         }
 
-        if let Some(braked) = braked_wheel {
-            wheel_e.insert(braked);
+        if let Some(mut braked) = braked_wheel {
+            // the standstill hold is scaled with the wheel inertia, so it stays stable
+            braked.hold_stiffness = 5000. * self.moi_y;
+            braked.hold_damping = 100. * self.moi_y;
+            wheel_e.insert((braked, BrakeHeat::default()));
         }
 
         // set parent
-        wheel_e.set_parent(parent_id);
+        wheel_e.set_parent(parent_id).insert_physics_id();
         let wheel_id = wheel_e.id();
 
         // add tire contact model
-        commands.spawn(PointTire::new(
-            wheel_id,
-            parent_id,
-            self.stiffness,
-            self.damping,
-            self.coefficient_of_friction,
-            self.normalized_slip_stiffness,
-            // self.rolling_resistance,
-            self.rolling_radius,
-            self.low_speed,
-            self.radius,
-            self.width,
-            self.filter_time,
-            5,
-            51,
-            0.01,
-        ));
+        commands
+            .spawn(PointTire::new(
+                wheel_id,
+                parent_id,
+                self.stiffness,
+                self.damping,
+                self.tire_model.clone(),
+                // self.rolling_resistance,
+                self.rolling_radius,
+                self.low_speed,
+                self.radius,
+                self.width,
+                pressure / self.nominal_pressure,
+                self.filter_time,
+                5,
+                51,
+                0.01,
+            ))
+            .insert_physics_id();
         wheel_id
     }
 }
 
+pub struct DrivelineDef {
+    pub damping: f64,
+    pub differential_damping: f64, // locking torque per unit speed difference
+    pub capacity: f64,
+    pub engagement: [f64; 2],
+    pub differential: DifferentialType, // axle differentials
+    pub center_differential: DifferentialType, // between the axles (all wheel drive)
+}
+
+pub struct FuelTankDef {
+    pub fuel: f64,          // kg
+    pub position: [f64; 3], // relative to the chassis
+    pub specific_consumption: f64,
+}
+
+pub struct AeroDef {
+    pub position: [f64; 3], // center of pressure, relative to the chassis
+    pub area: f64,
+    pub lift: Interpolator1D, // downforce coefficient
+    pub drag: Interpolator1D,
+}
+
+// The brake torque is split over the axles by their `AxleDef::brake_share`
 pub struct Brake {
-    front_torque: f64,
-    rear_torque: f64,
+    pub total_torque: f64,     // all wheels
+    pub handbrake_torque: f64, // per wheel on a handbrake axle
+    pub abs: Option<Abs>,
 }
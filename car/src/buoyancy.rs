@@ -0,0 +1,106 @@
+use bevy::prelude::*;
+use grid_terrain::GridTerrain;
+use rigid_body::{
+    joint::Joint,
+    sva::{Force, Vector},
+};
+
+const WATER_DENSITY: f64 = 1000.;
+const GRAVITY: f64 = 9.81;
+
+// Buoyancy and hydrodynamic drag for a body that can be submerged in water.
+// The body volume is split into a set of points, each representing a small box.
+#[derive(Component)]
+pub struct Buoyancy {
+    joint_entity: Entity,
+    points: Vec<Vector>,
+    point_volume: f64,
+    point_height: f64,
+    point_area: f64,
+    drag_coefficient: f64,
+}
+
+impl Buoyancy {
+    pub fn new(
+        joint_entity: Entity,
+        dimensions: [f64; 3],
+        position: [f64; 3],
+        num_points: [usize; 3],
+        drag_coefficient: f64,
+    ) -> Self {
+        let step = [0, 1, 2].map(|i| dimensions[i] / num_points[i] as f64);
+        let mut points = Vec::new();
+        for x_ind in 0..num_points[0] {
+            for y_ind in 0..num_points[1] {
+                for z_ind in 0..num_points[2] {
+                    points.push(Vector::new(
+                        position[0] - dimensions[0] / 2. + (x_ind as f64 + 0.5) * step[0],
+                        position[1] - dimensions[1] / 2. + (y_ind as f64 + 0.5) * step[1],
+                        position[2] - dimensions[2] / 2. + (z_ind as f64 + 0.5) * step[2],
+                    ));
+                }
+            }
+        }
+
+        // drag acts on the frontal area of the box, shared between the points
+        let frontal_area = dimensions[1] * dimensions[2];
+        Self {
+            joint_entity,
+            point_volume: step[0] * step[1] * step[2],
+            point_height: step[2],
+            point_area: frontal_area / (num_points[1] * num_points[2]) as f64,
+            points,
+            drag_coefficient,
+        }
+    }
+
+    pub fn joint_entity(&self) -> Entity {
+        self.joint_entity
+    }
+
+    pub fn points(&self) -> &Vec<Vector> {
+        &self.points
+    }
+}
+
+pub fn buoyancy_system(
+    buoyancy_query: Query<&Buoyancy>,
+    mut query_joints: Query<&mut Joint>,
+    grid_terrain: Res<GridTerrain>,
+) {
+    let terrain = grid_terrain.as_ref();
+    for buoyancy in buoyancy_query.iter() {
+        if let Ok(mut joint) = query_joints.get_mut(buoyancy.joint_entity) {
+            let mut f_ext = Force::zero();
+            let x0i = joint.x.inverse(); // spatial transform from the joint to absolute coordinates
+            let v0 = x0i * joint.v; // spatial velocity of the joint in absolute coordinates
+
+            for point in buoyancy.points.iter() {
+                let point_abs = x0i.transform_point(*point); // point in absolute coordinates
+                if let Some(depth) = terrain.water_depth(point_abs) {
+                    // fraction of the point's box below the surface
+                    let submerged = (depth / buoyancy.point_height + 0.5).clamp(0., 1.);
+                    if submerged == 0. {
+                        continue;
+                    }
+
+                    let buoyancy_force =
+                        WATER_DENSITY * GRAVITY * buoyancy.point_volume * submerged * Vector::z();
+
+                    let velocity = v0.velocity_point(point_abs).vel;
+                    let drag_force = -0.5
+                        * WATER_DENSITY
+                        * buoyancy.drag_coefficient
+                        * buoyancy.point_area
+                        * submerged
+                        * velocity.norm()
+                        * velocity;
+
+                    f_ext += Force::force_point(buoyancy_force + drag_force, point_abs);
+                }
+            }
+
+            joint.f_ext += f_ext;
+        }
+    }
+}
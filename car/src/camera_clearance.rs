@@ -0,0 +1,104 @@
+use bevy::prelude::*;
+use cameras::camera_az_el::AzElCamera;
+use grid_terrain::GridTerrain;
+
+// Keeps a camera above the terrain. Where the camera would be less than
+// `clearance` above the ground (the orbit camera behind the car on a slope, or
+// the chase camera coming down a step) it is lifted, quickly when the ground
+// rises and slowly when it falls away, so the view doesn't jump. Like the camera
+// shake, the lift is added just before the transforms are propagated and taken
+// off again at the start of the next frame. It is added to every orbit camera.
+#[derive(Component)]
+pub struct CameraClearance {
+    pub enabled: bool,
+    pub clearance: f32, // (m) above the ground
+    pub radius: f32,    // (m) around the camera the ground is sampled in
+    pub rise_time: f32, // (s) time constant of the lift going up
+    pub fall_time: f32, // (s) time constant of the lift going down
+    lift: f32,
+    applied: Option<Vec3>, // offset added to the transform this frame
+}
+
+impl Default for CameraClearance {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            clearance: 0.5,
+            radius: 0.5,
+            rise_time: 0.05,
+            fall_time: 0.4,
+            lift: 0.,
+            applied: None,
+        }
+    }
+}
+
+pub fn camera_clearance_insert_system(
+    mut commands: Commands,
+    cameras: Query<Entity, Added<AzElCamera>>,
+) {
+    for camera in cameras.iter() {
+        commands.entity(camera).insert(CameraClearance::default());
+    }
+}
+
+// runs in `PostUpdate`, before the camera shake and the transform propagation
+pub fn camera_clearance_system(
+    time: Res<Time>,
+    terrain: Option<Res<GridTerrain>>,
+    mut cameras: Query<(&mut CameraClearance, &mut Transform, Option<&Parent>)>,
+    parents: Query<&GlobalTransform>,
+) {
+    let terrain = match terrain {
+        Some(terrain) => terrain,
+        None => return,
+    };
+    let dt = time.delta_seconds();
+    for (mut clearance, mut transform, parent) in cameras.iter_mut() {
+        if !clearance.enabled {
+            continue;
+        }
+        // the parent moved since its global transform, but not by much
+        let parent_transform = match parent.map(|parent| parents.get(parent.get())) {
+            Some(Ok(parent_transform)) => *parent_transform,
+            Some(Err(_)) => continue,
+            None => GlobalTransform::IDENTITY,
+        };
+        let position = parent_transform.transform_point(transform.translation);
+
+        // highest ground under and around the camera
+        let r = clearance.radius;
+        let ground = [[0., 0.], [r, 0.], [-r, 0.], [0., r], [0., -r]]
+            .iter()
+            .map(|[dx, dy]| terrain.height((position.x + dx) as f64, (position.y + dy) as f64))
+            .fold(f64::NEG_INFINITY, f64::max) as f32;
+        let target = (ground + clearance.clearance - position.z).max(0.);
+        let time_constant = if target > clearance.lift {
+            clearance.rise_time
+        } else {
+            clearance.fall_time
+        };
+        let blend = 1. - (-dt / time_constant.max(1e-3)).exp();
+        clearance.lift += (target - clearance.lift) * blend;
+        if clearance.lift <= 1e-4 {
+            continue;
+        }
+
+        // straight up in the world, in the frame of the parent
+        let offset = parent_transform
+            .affine()
+            .inverse()
+            .transform_vector3(clearance.lift * Vec3::Z);
+        transform.translation += offset;
+        clearance.applied = Some(offset);
+    }
+}
+
+// runs in `PreUpdate`, taking the lift of the last frame off again
+pub fn camera_clearance_reset_system(mut cameras: Query<(&mut CameraClearance, &mut Transform)>) {
+    for (mut clearance, mut transform) in cameras.iter_mut() {
+        if let Some(offset) = clearance.applied.take() {
+            transform.translation -= offset;
+        }
+    }
+}
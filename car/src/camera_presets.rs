@@ -0,0 +1,191 @@
+use std::{fs, path::Path};
+
+use bevy::prelude::*;
+use cameras::{
+    camera_az_el::{az_el_rotation, az_el_translation, AzElCamera, UpDirection},
+    chase::ChaseCamera,
+    control::CameraParentList,
+    fly::FlyCamera,
+    top_down::TopDownCamera,
+};
+use serde::Deserialize;
+
+use crate::photo::PhotoMode;
+
+// Named camera setups read from a TOML file, a `[[preset]]` table each. `Y`
+// cycles through them. The first one is taken when the file is loaded.
+#[derive(Resource, Deserialize, Clone)]
+pub struct CameraPresets {
+    #[serde(rename = "preset")]
+    pub presets: Vec<CameraPreset>,
+    #[serde(skip)]
+    pub active: usize,
+}
+
+impl Default for CameraPresets {
+    fn default() -> Self {
+        Self {
+            presets: vec![CameraPreset::default()],
+            active: 0,
+        }
+    }
+}
+
+impl CameraPresets {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("reading {}: {}", path.display(), error))?;
+        let presets: Self = toml::from_str(&text)
+            .map_err(|error| format!("parsing {}: {}", path.display(), error))?;
+        if presets.presets.is_empty() {
+            return Err(format!("{} has no camera presets", path.display()));
+        }
+        Ok(presets)
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+pub enum CameraMode {
+    Orbit,   // parented to an entry of the camera parent list
+    Chase,   // following the entry of the camera parent list (`ChaseCamera`)
+    Fly,     // free flying from the orbit view (`FlyCamera`)
+    TopDown, // orthographic map view (`TopDownCamera`)
+}
+
+// One camera setup. Every value is optional, missing values are the ones of the
+// default orbit view behind the car.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct CameraPreset {
+    pub name: String,
+    pub mode: CameraMode,
+    pub focus: [f32; 3], // in the frame of the parent
+    pub azimuth: f32,    // (deg)
+    pub elevation: f32,  // (deg)
+    pub radius: f32,     // (m) from the focus
+    pub fov: f32,        // (deg) vertical
+    pub parent: usize,   // entry of the camera parent list
+    pub chase: ChasePreset,
+}
+
+impl Default for CameraPreset {
+    fn default() -> Self {
+        Self {
+            name: "orbit".to_string(),
+            mode: CameraMode::Orbit,
+            focus: [0., 0., 1.],
+            azimuth: -90.,
+            elevation: 10.,
+            radius: 20.,
+            fov: 45.,
+            parent: 0,
+            chase: ChasePreset::default(),
+        }
+    }
+}
+
+// overrides of the `ChaseCamera` values
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct ChasePreset {
+    pub distance: Option<f32>,
+    pub height: Option<f32>,
+    pub position_frequency: Option<f32>, // (rad/s) of the position lag
+    pub aim_frequency: Option<f32>,      // (rad/s) of the aim lag
+}
+
+impl CameraPreset {
+    pub fn az_el(&self) -> AzElCamera {
+        AzElCamera {
+            focus: Vec3::from(self.focus),
+            radius: self.radius,
+            up_direction: UpDirection::Z,
+            azimuth: self.azimuth.to_radians(),
+            elevation: self.elevation.to_radians(),
+        }
+    }
+}
+
+type PresetCamera<'a> = (
+    Entity,
+    &'a mut AzElCamera,
+    &'a mut Transform,
+    &'a mut Projection,
+    &'a mut ChaseCamera,
+    &'a mut FlyCamera,
+    &'a mut TopDownCamera,
+);
+
+// applies the active preset to the main camera when it changes
+pub fn camera_preset_system(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    mut presets: ResMut<CameraPresets>,
+    photo: Option<Res<PhotoMode>>,
+    parents: Option<ResMut<CameraParentList>>,
+    mut cameras: Query<PresetCamera>,
+) {
+    // the photo mode restores its own view
+    if matches!(&photo, Some(photo) if photo.active) || presets.presets.is_empty() {
+        return;
+    }
+    if input.just_pressed(KeyCode::Y) {
+        presets.active = (presets.active + 1) % presets.presets.len();
+    }
+    if !presets.is_changed() {
+        return;
+    }
+    let preset = match presets.presets.get(presets.active) {
+        Some(preset) => preset,
+        None => return,
+    };
+    info!("camera preset: {}", preset.name);
+
+    if let Some(mut parents) = parents {
+        if !parents.list.is_empty() {
+            parents.active = preset.parent.min(parents.list.len() - 1);
+        }
+    }
+    for (entity, mut az_el, mut transform, mut projection, mut chase, mut fly, mut top_down) in
+        cameras.iter_mut()
+    {
+        *az_el = preset.az_el();
+        chase.stop();
+        if fly.active {
+            fly.stop(&mut transform, &az_el);
+        }
+        if top_down.active {
+            top_down.stop(&mut transform, &mut projection, &az_el);
+        }
+        transform.rotation = az_el_rotation(az_el.azimuth, az_el.elevation, &az_el.up_direction);
+        transform.translation = az_el_translation(az_el.focus, transform.rotation, az_el.radius);
+        if let Projection::Perspective(perspective) = projection.as_mut() {
+            perspective.fov = preset.fov.to_radians();
+        }
+
+        let overrides = &preset.chase;
+        let defaults = ChaseCamera::default();
+        chase.distance = overrides.distance.unwrap_or(defaults.distance);
+        chase.height = overrides.height.unwrap_or(defaults.height);
+        chase.position_frequency = overrides
+            .position_frequency
+            .unwrap_or(defaults.position_frequency);
+        chase.aim_frequency = overrides.aim_frequency.unwrap_or(defaults.aim_frequency);
+
+        match preset.mode {
+            CameraMode::Orbit => {}
+            CameraMode::Chase => chase.active = true,
+            CameraMode::Fly => {
+                // from the preset view in the world, off the parent
+                commands.entity(entity).remove_parent();
+                let global_transform = GlobalTransform::from(*transform);
+                fly.start(
+                    &mut transform,
+                    &global_transform,
+                    az_el.up_direction.vector(),
+                );
+            }
+            CameraMode::TopDown => top_down.start(&mut transform, &mut projection),
+        }
+    }
+}
@@ -0,0 +1,458 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        main_graph::node::CAMERA_DRIVER,
+        render_asset::RenderAssets,
+        render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext},
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, Extent3d, ImageCopyBuffer, ImageDataLayout,
+            MapMode, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+        },
+        renderer::{RenderContext, RenderDevice},
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+    },
+};
+use bevy_integrator::SimTime;
+
+use crate::build::{CarDefinition, CarEntities};
+
+// A picture taken by a `CameraSensor`: RGBA, 8 bits a channel, row by row from
+// the top left
+#[derive(Clone)]
+pub struct CameraFrame {
+    pub index: usize, // starting at 1
+    pub time: f64,    // simulation time it was taken at
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl CameraFrame {
+    pub fn save_png(&self, path: &Path) -> Result<(), String> {
+        let image = Image::new(
+            Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            self.pixels.clone(),
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        let image = image
+            .try_into_dynamic()
+            .map_err(|error| error.to_string())?;
+        image
+            .to_rgb8()
+            .save(path)
+            .map_err(|error| error.to_string())
+    }
+}
+
+// Camera on the chassis entity, for vision based driving: it renders the view
+// into an offscreen image every `sample_time` of simulation time, at its own
+// resolution, and copies the picture back from the GPU. The latest picture is
+// in `frame`, a frame or two after it was taken, and with a `directory` every
+// picture is written there as a PNG, with their times in frames.csv. Needs
+// rendering, it takes no pictures in headless runs. See `camera_sensor_setup`.
+#[derive(Component, Clone)]
+pub struct CameraSensor {
+    pub position: Vec3, // chassis coordinates
+    pub rotation: Quat, // of the view, looking forward (x) with z up without one
+    pub width: u32,
+    pub height: u32,
+    pub fov: f32,         // vertical field of view (rad)
+    pub sample_time: f64, // between pictures (s)
+    pub directory: Option<PathBuf>,
+    pub frame: Option<CameraFrame>,
+    camera: Option<Entity>,
+    image: Handle<Image>,
+    taken: usize,                           // pictures requested
+    last_time: Option<f64>,                 // of the last picture requested
+    request: Option<usize>,                 // picture to take this frame
+    received: Arc<Mutex<Vec<CameraFrame>>>, // back from the render world
+}
+
+impl Default for CameraSensor {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(0., 0., 1.2),
+            rotation: Quat::IDENTITY,
+            width: 640,
+            height: 480,
+            fov: 0.8,
+            sample_time: 0.1,
+            directory: None,
+            frame: None,
+            camera: None,
+            image: Handle::default(),
+            taken: 0,
+            last_time: None,
+            request: None,
+            received: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl CameraSensor {
+    // mounting point and angles (roll, pitch, yaw in rad, positive pitch looks
+    // down) on the chassis
+    pub fn with_mounting(mut self, position: [f32; 3], angles: [f32; 3]) -> Self {
+        let [roll, pitch, yaw] = angles;
+        self.position = Vec3::from(position);
+        self.rotation = Quat::from_euler(EulerRot::ZYX, yaw, pitch, roll);
+        self
+    }
+
+    pub fn with_resolution(mut self, width: u32, height: u32) -> Self {
+        self.width = width.max(1);
+        self.height = height.max(1);
+        self
+    }
+
+    pub fn with_fov(mut self, fov: f32) -> Self {
+        self.fov = fov;
+        self
+    }
+
+    pub fn with_sample_time(mut self, sample_time: f64) -> Self {
+        self.sample_time = sample_time;
+        self
+    }
+
+    // writes the pictures to the directory
+    pub fn with_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+
+    // camera transform in the chassis, bevy cameras look along -z with y up
+    fn transform(&self) -> Transform {
+        let forward = Transform::IDENTITY.looking_to(Vec3::X, Vec3::Z).rotation;
+        Transform::from_translation(self.position).with_rotation(self.rotation * forward)
+    }
+}
+
+// camera on the front of the roof, looking ahead and slightly down
+pub fn roof_camera(car: &CarDefinition) -> CameraSensor {
+    let [length, _, height] = car.chassis.dimensions.map(|x| x as f32);
+    let [x, _, z] = car.chassis.position.map(|x| x as f32);
+    CameraSensor::default()
+        .with_mounting([x + length / 4., 0., z + height / 2. + 0.2], [0., 0.1, 0.])
+}
+
+// Put on the car in `CarEntities` (and again on the car that replaces it) by
+// `camera_sensor_setup`
+#[derive(Resource, Clone)]
+pub struct CarCameraSensor(pub CameraSensor);
+
+// Takes the pictures of every `CameraSensor`, and puts the `CarCameraSensor` on
+// the car
+pub fn camera_sensor_setup(app: &mut App) {
+    app.add_plugins(CameraSensorPlugin);
+}
+
+struct CameraSensorPlugin;
+
+impl Plugin for CameraSensorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                camera_sensor_attach_system,
+                camera_sensor_build_system,
+                camera_sensor_system,
+            )
+                .chain(),
+        );
+    }
+
+    // the render app is there once every plugin is built
+    fn finish(&self, app: &mut App) {
+        let render_app = match app.get_sub_app_mut(RenderApp) {
+            Ok(render_app) => render_app,
+            Err(_) => return,
+        };
+        render_app
+            .init_resource::<CameraCopies>()
+            .add_systems(ExtractSchedule, extract_camera_captures)
+            .add_systems(Render, prepare_camera_copies.in_set(RenderSet::Queue))
+            .add_systems(Render, read_camera_copies.in_set(RenderSet::Cleanup));
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node(CAMERA_SENSOR_COPY, CameraSensorCopyNode);
+        graph.add_node_edge(CAMERA_DRIVER, CAMERA_SENSOR_COPY);
+    }
+}
+
+fn camera_sensor_attach_system(
+    mut commands: Commands,
+    sensor: Option<Res<CarCameraSensor>>,
+    car: Option<Res<CarEntities>>,
+    sensors: Query<&CameraSensor>,
+) {
+    if let (Some(sensor), Some(car)) = (sensor, car) {
+        if sensors.get(car.chassis).is_err() {
+            let mut sensor = sensor.0.clone();
+            sensor.received = Arc::default();
+            commands.entity(car.chassis).insert(sensor);
+        }
+    }
+}
+
+// the camera of each sensor, rendering into its image
+fn camera_sensor_build_system(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut sensors: Query<(Entity, &mut CameraSensor)>,
+) {
+    for (chassis, mut sensor) in sensors.iter_mut() {
+        if sensor.camera.is_some() {
+            continue;
+        }
+        let size = Extent3d {
+            width: sensor.width,
+            height: sensor.height,
+            depth_or_array_layers: 1,
+        };
+        let mut image = Image {
+            texture_descriptor: TextureDescriptor {
+                label: Some("camera_sensor"),
+                size,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8UnormSrgb,
+                mip_level_count: 1,
+                sample_count: 1,
+                usage: TextureUsages::TEXTURE_BINDING
+                    | TextureUsages::COPY_SRC
+                    | TextureUsages::COPY_DST
+                    | TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            },
+            ..default()
+        };
+        image.resize(size);
+        sensor.image = images.add(image);
+
+        let camera = commands
+            .spawn(Camera3dBundle {
+                transform: sensor.transform(),
+                camera: Camera {
+                    // before the views of the window
+                    order: -1,
+                    is_active: false,
+                    target: RenderTarget::Image(sensor.image.clone()),
+                    ..default()
+                },
+                projection: Projection::Perspective(PerspectiveProjection {
+                    fov: sensor.fov,
+                    ..default()
+                }),
+                ..default()
+            })
+            .insert(UiCameraConfig { show_ui: false })
+            .set_parent(chassis)
+            .id();
+        sensor.camera = Some(camera);
+    }
+}
+
+// The camera only renders in the frames a picture is taken in. Pictures back
+// from the renderer are kept and written.
+fn camera_sensor_system(
+    time: Res<SimTime>,
+    mut sensors: Query<&mut CameraSensor>,
+    mut cameras: Query<&mut Camera>,
+) {
+    let time = time.time();
+    for mut sensor in sensors.iter_mut() {
+        let received: Vec<CameraFrame> = match sensor.received.lock() {
+            Ok(mut received) => received.drain(..).collect(),
+            Err(_) => Vec::new(),
+        };
+        for frame in received {
+            if let Some(directory) = &sensor.directory {
+                if let Err(error) = write_frame(directory, &frame) {
+                    warn!("camera sensor picture not written, {}", error);
+                }
+            }
+            sensor.frame = Some(frame);
+        }
+
+        // the camera is spawned the frame after the sensor, the time goes back
+        // on a reset or a snapshot loaded
+        sensor.request = None;
+        let mut camera = match sensor
+            .camera
+            .and_then(|camera| cameras.get_mut(camera).ok())
+        {
+            Some(camera) => camera,
+            None => continue,
+        };
+        let due = match sensor.last_time {
+            Some(last_time) => time < last_time || time - last_time >= sensor.sample_time - 1e-9,
+            None => true,
+        };
+        camera.is_active = due;
+        if due {
+            sensor.taken += 1;
+            sensor.request = Some(sensor.taken);
+            sensor.last_time = Some(time);
+        }
+    }
+}
+
+fn write_frame(directory: &Path, frame: &CameraFrame) -> Result<(), String> {
+    fs::create_dir_all(directory).map_err(|error| error.to_string())?;
+    let path = directory.join(format!("frame_{:06}.png", frame.index));
+    frame.save_png(&path)?;
+    let times = directory.join("frames.csv");
+    let new = !times.exists();
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&times)
+        .map_err(|error| format!("{}: {}", times.display(), error))?;
+    if new {
+        writeln!(file, "frame,time").map_err(|error| error.to_string())?;
+    }
+    writeln!(file, "{},{}", frame.index, frame.time).map_err(|error| error.to_string())
+}
+
+const CAMERA_SENSOR_COPY: &str = "camera_sensor_copy";
+
+// a picture to copy back from the GPU, in the render world
+struct CameraCopy {
+    image: Handle<Image>,
+    index: usize,
+    time: f64,
+    size: [u32; 2],
+    received: Arc<Mutex<Vec<CameraFrame>>>,
+    buffer: Option<Buffer>,
+}
+
+#[derive(Resource, Default)]
+struct CameraCopies(Vec<CameraCopy>);
+
+fn extract_camera_captures(
+    time: Extract<Res<SimTime>>,
+    sensors: Extract<Query<&CameraSensor>>,
+    mut copies: ResMut<CameraCopies>,
+) {
+    copies.0.clear();
+    for sensor in sensors.iter() {
+        if let Some(index) = sensor.request {
+            copies.0.push(CameraCopy {
+                image: sensor.image.clone(),
+                index,
+                time: time.time(),
+                size: [sensor.width, sensor.height],
+                received: sensor.received.clone(),
+                buffer: None,
+            });
+        }
+    }
+}
+
+// bytes of a row in the buffer, the rows of a copy are aligned
+fn padded_row(width: u32) -> usize {
+    RenderDevice::align_copy_bytes_per_row(width as usize * 4)
+}
+
+fn prepare_camera_copies(
+    render_device: Res<RenderDevice>,
+    images: Res<RenderAssets<Image>>,
+    mut copies: ResMut<CameraCopies>,
+) {
+    for copy in copies.0.iter_mut() {
+        // the first pictures are lost while the image is prepared
+        if images.get(&copy.image).is_none() {
+            continue;
+        }
+        copy.buffer = Some(render_device.create_buffer(&BufferDescriptor {
+            label: Some("camera_sensor_copy"),
+            size: (padded_row(copy.size[0]) * copy.size[1] as usize) as u64,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+    }
+}
+
+// copies the images the cameras rendered into the buffers
+struct CameraSensorCopyNode;
+
+impl Node for CameraSensorCopyNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let images = world.resource::<RenderAssets<Image>>();
+        for copy in world.resource::<CameraCopies>().0.iter() {
+            if let (Some(image), Some(buffer)) = (images.get(&copy.image), &copy.buffer) {
+                render_context.command_encoder().copy_texture_to_buffer(
+                    image.texture.as_image_copy(),
+                    ImageCopyBuffer {
+                        buffer,
+                        layout: ImageDataLayout {
+                            offset: 0,
+                            bytes_per_row: Some(padded_row(copy.size[0]) as u32),
+                            rows_per_image: None,
+                        },
+                    },
+                    Extent3d {
+                        width: copy.size[0],
+                        height: copy.size[1],
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+// The buffers are read once the copies are done on the GPU, when it is polled
+// in a later frame, and the pictures sent back to their sensors
+fn read_camera_copies(render_device: Res<RenderDevice>, mut copies: ResMut<CameraCopies>) {
+    for copy in copies.0.drain(..) {
+        let buffer = match copy.buffer {
+            Some(buffer) => buffer,
+            None => continue,
+        };
+        let mapped = buffer.clone();
+        let [width, height] = copy.size;
+        let (index, time, received) = (copy.index, copy.time, copy.received);
+        render_device.map_buffer(&buffer.slice(..), MapMode::Read, move |result| {
+            if result.is_err() {
+                return;
+            }
+            let row = width as usize * 4;
+            let padded = padded_row(width);
+            let pixels = {
+                let data = mapped.slice(..).get_mapped_range();
+                (0..height as usize)
+                    .flat_map(|y| data[y * padded..y * padded + row].iter().copied())
+                    .collect()
+            };
+            mapped.unmap();
+            if let Ok(mut received) = received.lock() {
+                received.push(CameraFrame {
+                    index,
+                    time,
+                    width,
+                    height,
+                    pixels,
+                });
+            }
+        });
+    }
+}
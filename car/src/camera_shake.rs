@@ -0,0 +1,87 @@
+use bevy::prelude::*;
+use bevy_integrator::SimTime;
+use cameras::shake::CameraShake;
+use rigid_body::{joint::Joint, sva::Vector};
+
+use crate::{build::CarEntities, cones::ConeStrike, damage::Damage};
+
+// Sources of camera shake from the car in `CarEntities`. The vertical chassis
+// acceleration above `vertical_threshold` shakes the camera in proportion, up to
+// full trauma at `vertical_full` above it, so rough ground rattles the view and a
+// harsh landing jolts it. Collisions (counted by `Damage`) and cone strikes add
+// trauma on top.
+#[derive(Resource)]
+pub struct CameraShakeSources {
+    pub vertical_threshold: f64, // (m/s²) of vertical acceleration before any shake
+    pub vertical_full: f64,      // (m/s²) above the threshold for full trauma
+    pub impact: f32,             // trauma of a collision
+    pub cone_strike: f32,        // trauma of hitting a cone
+    previous: Option<(f64, Vector)>, // time and absolute chassis velocity
+    impacts: u32,                // impacts of the damage seen so far
+}
+
+impl Default for CameraShakeSources {
+    fn default() -> Self {
+        Self {
+            vertical_threshold: 6.,
+            vertical_full: 60.,
+            impact: 0.7,
+            cone_strike: 0.15,
+            previous: None,
+            impacts: 0,
+        }
+    }
+}
+
+pub fn camera_shake_source_system(
+    time: Res<SimTime>,
+    mut sources: ResMut<CameraShakeSources>,
+    car: Option<Res<CarEntities>>,
+    joints: Query<&Joint>,
+    damage: Query<&Damage>,
+    mut cone_strikes: EventReader<ConeStrike>,
+    mut cameras: Query<&mut CameraShake>,
+) {
+    let car = match car {
+        Some(car) => car,
+        None => return,
+    };
+    let chassis = match joints.get(car.chassis) {
+        Ok(chassis) => chassis,
+        Err(_) => return,
+    };
+    let sources = sources.as_mut();
+    let mut trauma = 0.;
+    let mut level: f32 = 0.;
+
+    // acceleration since the last frame, nothing while the simulation is paused
+    let velocity = chassis.x.inverse() * chassis.v.v;
+    let time = time.time();
+    if let Some((previous_time, previous_velocity)) = sources.previous {
+        if time > previous_time {
+            let vertical = (velocity.z - previous_velocity.z) / (time - previous_time);
+            let excess = vertical.abs() - sources.vertical_threshold;
+            level = (excess / sources.vertical_full).clamp(0., 1.) as f32;
+        }
+    }
+    sources.previous = Some((time, velocity));
+
+    if let Ok(damage) = damage.get(car.chassis) {
+        if damage.impacts > sources.impacts {
+            trauma += sources.impact;
+        }
+        sources.impacts = damage.impacts;
+    }
+    for strike in cone_strikes.iter() {
+        if strike.car == car.chassis {
+            trauma += sources.cone_strike;
+        }
+    }
+
+    for mut shake in cameras.iter_mut() {
+        shake.raise_trauma(level);
+        if trauma > 0. {
+            shake.add_trauma(trauma);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use std::{collections::HashMap, f64::consts::PI};
+
+use bevy::prelude::*;
+use bevy_integrator::SimTime;
+use grid_terrain::{props::Prop, GridTerrain};
+use rigid_body::joint::Joint;
+
+use crate::{
+    ai::{ai_driver_system, planar_pose, AiDriver},
+    cones::ConeStrike,
+    control::CarControl,
+};
+
+// Cone course tests on flat ground. Positions are in the course coordinates,
+// x forward from the entry line and y to the left, lengths in meters.
+#[derive(Clone)]
+pub enum ConeTest {
+    // a row of cones on the center line, passed on alternate sides
+    Slalom { cones: usize, spacing: f64 },
+    // the ISO 3888-2 obstacle avoidance lane change (moose test) for a vehicle
+    // of `width`, entered at speed with the throttle released
+    MooseTest { width: f64 },
+}
+
+// length, left and right edge (y) of each lane of the moose test, and where it starts
+struct Lane {
+    start: f64,
+    length: f64,
+    right: f64,
+    left: f64,
+}
+
+impl ConeTest {
+    // 18 m is the usual spacing of a handling slalom
+    pub fn slalom() -> Self {
+        ConeTest::Slalom {
+            cones: 8,
+            spacing: 18.,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConeTest::Slalom { .. } => "slalom",
+            ConeTest::MooseTest { .. } => "moose test",
+        }
+    }
+
+    // from the entry to the exit line
+    pub fn length(&self) -> f64 {
+        match *self {
+            ConeTest::Slalom { cones, spacing } => cones as f64 * spacing,
+            ConeTest::MooseTest { .. } => 61.,
+        }
+    }
+
+    // entry lane, offset lane and exit lane, with the gaps between them
+    fn moose_lanes(width: f64) -> [Lane; 3] {
+        let entry = 1.1 * width + 0.25;
+        let offset = width + 1.;
+        let exit = (1.3 * width + 0.25).min(3.);
+        let right = -entry / 2.;
+        [
+            Lane {
+                start: 0.,
+                length: 12.,
+                right,
+                left: entry / 2.,
+            },
+            Lane {
+                start: 25.5,
+                length: 11.,
+                right: entry / 2. + 1.,
+                left: entry / 2. + 1. + offset,
+            },
+            Lane {
+                start: 49.,
+                length: 12.,
+                right,
+                left: right + exit,
+            },
+        ]
+    }
+
+    pub fn cones(&self) -> Vec<[f64; 2]> {
+        match *self {
+            ConeTest::Slalom { cones, spacing } => (0..cones)
+                .map(|ind| [(ind as f64 + 0.5) * spacing, 0.])
+                .collect(),
+            ConeTest::MooseTest { width } => {
+                // both edges of each lane, about every 3 m
+                let mut cones = Vec::new();
+                for lane in ConeTest::moose_lanes(width) {
+                    let gaps = (lane.length / 3.).round().max(1.);
+                    for ind in 0..=gaps as usize {
+                        let x = lane.start + lane.length * ind as f64 / gaps;
+                        cones.push([x, lane.right]);
+                        cones.push([x, lane.left]);
+                    }
+                }
+                cones
+            }
+        }
+    }
+
+    // ideal line through the course, with `run_up` before the entry and `run_out`
+    // after the exit, every meter
+    pub fn path(&self, run_up: f64, run_out: f64) -> Vec<[f64; 2]> {
+        let length = self.length();
+        let lateral = |x: f64| match *self {
+            ConeTest::Slalom { spacing, .. } => {
+                if x < 0. || x > length {
+                    0.
+                } else {
+                    // 2 m to the side of each cone
+                    2. * (PI * x / spacing).sin()
+                }
+            }
+            ConeTest::MooseTest { width } => {
+                let [entry, offset, exit] = ConeTest::moose_lanes(width);
+                let center = |lane: &Lane| (lane.left + lane.right) / 2.;
+                // smooth steps between the lane centers
+                let blend = |x: f64, from: f64, to: f64, a: f64, b: f64| {
+                    let t = ((x - from) / (to - from)).clamp(0., 1.);
+                    a + (b - a) * (1. - (PI * t).cos()) / 2.
+                };
+                let offset_start = entry.start + entry.length;
+                let exit_start = offset.start + offset.length;
+                if x < offset.start + 0.5 * offset.length {
+                    blend(
+                        x,
+                        offset_start,
+                        offset.start,
+                        center(&entry),
+                        center(&offset),
+                    )
+                } else {
+                    blend(x, exit_start, exit.start, center(&offset), center(&exit))
+                }
+            }
+        };
+        let points = (run_up + length + run_out).ceil() as usize;
+        (0..=points)
+            .map(|ind| {
+                let x = ind as f64 - run_up;
+                [x, lateral(x)]
+            })
+            .collect()
+    }
+}
+
+// Runs a cone test on a car, on the chassis entity: the course is laid out at
+// `origin` facing `heading`, and the car is scored from the entry to the exit
+// line: entry and exit speed, time, and the cones hit. It is driven by the
+// player, or by the AI driver from `driver`, which follows the ideal line at
+// the target speed. The results are logged and kept in `results`.
+#[derive(Component, Clone)]
+pub struct ConeTestRun {
+    pub test: ConeTest,
+    pub origin: [f64; 2],
+    pub heading: f64,
+    pub speed: f64, // target entry speed (m/s)
+    pub cone_strikes: u32,
+    pub results: HashMap<String, f64>,
+    entry: Option<(f64, f64)>, // time and speed at the entry line
+    cones_placed: bool,
+}
+
+impl ConeTestRun {
+    pub fn new(test: ConeTest, origin: [f64; 2], heading: f64, speed: f64) -> Self {
+        Self {
+            test,
+            origin,
+            heading,
+            speed,
+            cone_strikes: 0,
+            results: HashMap::new(),
+            entry: None,
+            cones_placed: false,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.results.is_empty()
+    }
+
+    // absolute coordinates of a point of the course
+    pub fn to_absolute(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+        let (sin, cos) = self.heading.sin_cos();
+        [
+            self.origin[0] + cos * x - sin * y,
+            self.origin[1] + sin * x + cos * y,
+        ]
+    }
+
+    // course coordinates of an absolute point
+    pub fn to_course(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+        let (sin, cos) = self.heading.sin_cos();
+        let [dx, dy] = [x - self.origin[0], y - self.origin[1]];
+        [cos * dx + sin * dy, -sin * dx + cos * dy]
+    }
+
+    // AI driver for the ideal line, starting `run_up` before the entry
+    pub fn driver(&self, run_up: f64, max_curvature: f64) -> AiDriver {
+        let path = self
+            .test
+            .path(run_up, 100.)
+            .into_iter()
+            .map(|point| self.to_absolute(point))
+            .collect();
+        // no slowing down for the course, it is taken at the entry speed
+        AiDriver::new(path, max_curvature, self.speed, 100., 6.).with_lookahead(3., 0.4)
+    }
+}
+
+// Lays out the course and scores the run. Uses the terrain meshes, so add it to
+// the environment setup.
+pub fn cone_test_setup(app: &mut App) {
+    app.add_systems(
+        Update,
+        (
+            cone_test_cones_system,
+            cone_test_system.after(ai_driver_system),
+        ),
+    );
+}
+
+fn cone_test_cones_system(
+    mut commands: Commands,
+    terrain: Option<ResMut<GridTerrain>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut runs: Query<&mut ConeTestRun>,
+) {
+    let mut terrain = match terrain {
+        Some(terrain) => terrain,
+        None => return,
+    };
+    for mut run in runs.iter_mut() {
+        if run.cones_placed {
+            continue;
+        }
+        run.cones_placed = true;
+        let parent = commands.spawn(SpatialBundle::default()).id();
+        for point in run.test.cones() {
+            let [x, y] = run.to_absolute(point);
+            let index = terrain.add_prop(Prop::cone(x, y));
+            terrain.build_prop_mesh(index, &mut commands, &mut meshes, &mut materials, parent);
+        }
+    }
+}
+
+// Runs after the AI driver, so the throttle can be released in the moose test
+fn cone_test_system(
+    time: Res<SimTime>,
+    mut strikes: EventReader<ConeStrike>,
+    mut cars: Query<(
+        &Joint,
+        &mut CarControl,
+        &mut ConeTestRun,
+        Option<&mut AiDriver>,
+    )>,
+) {
+    for strike in strikes.iter() {
+        if let Ok((_, _, mut run, _)) = cars.get_mut(strike.car) {
+            if run.entry.is_some() && !run.is_finished() {
+                run.cone_strikes += 1;
+            }
+        }
+    }
+    for (joint, mut control, mut run, driver) in cars.iter_mut() {
+        let (position, _) = planar_pose(joint);
+        let [x, _] = run.to_course(position);
+        let speed = joint.v.v.x;
+
+        if run.entry.is_none() && x >= 0. {
+            info!("{} entered at {:.1} m/s", run.test.name(), speed);
+            run.entry = Some((time.time(), speed));
+        }
+        let (entry_time, entry_speed) = match run.entry {
+            Some(entry) => entry,
+            None => continue,
+        };
+
+        if run.is_finished() {
+            // the AI driver stops after the course
+            if let Some(mut driver) = driver {
+                driver.speed_limit = 0.;
+            }
+            continue;
+        }
+        // the moose test is coasted through from the entry line
+        if matches!(run.test, ConeTest::MooseTest { .. }) && driver.is_some() {
+            control.throttle = 0.;
+            control.brake = 0.;
+        }
+        if x < run.test.length() {
+            continue;
+        }
+
+        let elapsed = time.time() - entry_time;
+        let results = [
+            ("entry_speed", entry_speed),
+            ("exit_speed", speed),
+            ("time", elapsed),
+            ("average_speed", run.test.length() / elapsed),
+            ("cone_strikes", run.cone_strikes as f64),
+            ("clean", if run.cone_strikes == 0 { 1. } else { 0. }),
+        ];
+        run.results = results
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect();
+        let report: Vec<String> = results
+            .iter()
+            .map(|(name, value)| format!("{}: {:.3}", name, value))
+            .collect();
+        info!("{} complete, {}", run.test.name(), report.join(", "));
+    }
+}
@@ -0,0 +1,128 @@
+use bevy::prelude::*;
+use grid_terrain::{props::PropMesh, GridTerrain};
+use rigid_body::{joint::Joint, sva::Vector};
+
+use crate::{control::CarPart, tire::PointTire};
+
+const GRAVITY: f32 = 9.81;
+
+// A car hit a loose prop (a cone), which was knocked over
+#[derive(Event)]
+pub struct ConeStrike {
+    pub car: Entity, // chassis
+    pub prop: usize, // index in the `GridTerrain` props
+    pub speed: f64,  // of the wheel that hit it (m/s)
+}
+
+// A knocked over cone, on the prop mesh entity. It flies off the wheel that hit
+// it, tumbles, and slides to a stop on the ground. It no longer collides with
+// the cars.
+#[derive(Component)]
+pub struct KnockedCone {
+    pub velocity: Vec3,
+    pub angular_velocity: Vec3,
+    pub friction: f32,    // with the ground
+    pub restitution: f32, // vertical bounce
+    pub rest_height: f32, // of the cone base center, lying on its side
+}
+
+impl KnockedCone {
+    // launched by a wheel moving at `velocity`
+    pub fn new(velocity: Vec3) -> Self {
+        let horizontal = Vec3::new(velocity.x, velocity.y, 0.);
+        // a light cone leaves faster than the wheel, and tips over away from it
+        let tip = Vec3::Z.cross(horizontal.normalize_or_zero()) * 12.;
+        Self {
+            velocity: 1.3 * horizontal + Vec3::Z * (0.15 * horizontal.length()).min(3.),
+            angular_velocity: tip,
+            friction: 0.6,
+            restitution: 0.3,
+            rest_height: 0.15,
+        }
+    }
+}
+
+// Runs once per time step after the integrator. A cone is struck when a tire
+// contact point is inside it, it is taken out of the terrain contact and
+// becomes a `KnockedCone`.
+pub fn cone_strike_system(
+    mut commands: Commands,
+    terrain: Option<ResMut<GridTerrain>>,
+    tires: Query<&PointTire>,
+    wheels: Query<(&Joint, &CarPart)>,
+    prop_meshes: Query<(Entity, &PropMesh)>,
+    mut strikes: EventWriter<ConeStrike>,
+) {
+    let mut terrain = match terrain {
+        Some(terrain) => terrain,
+        None => return,
+    };
+    for tire in tires.iter() {
+        let (joint, part) = match wheels.get(tire.joint_entity()) {
+            Ok(wheel) => wheel,
+            Err(_) => continue,
+        };
+        let x0i = joint.x.inverse();
+        let center = x0i.transform_point(Vector::zeros());
+        for point in tire.points() {
+            let index = match terrain.prop_at(x0i.transform_point(*point)) {
+                Some(index) if terrain.props()[index].is_loose() => index,
+                _ => continue,
+            };
+            terrain.remove_prop(index);
+
+            // wheel center velocity in absolute coordinates
+            let velocity = (x0i * joint.v).velocity_point(center).vel;
+            let launch = Vec3::new(velocity.x as f32, velocity.y as f32, velocity.z as f32);
+            if let Some((entity, _)) = prop_meshes.iter().find(|(_, mesh)| mesh.0 == index) {
+                commands.entity(entity).insert(KnockedCone::new(launch));
+            }
+            strikes.send(ConeStrike {
+                car: part.0,
+                prop: index,
+                speed: velocity.norm(),
+            });
+        }
+    }
+}
+
+// simple ballistic motion with a ground contact, the cones don't push back on the cars
+pub fn knocked_cone_system(
+    mut commands: Commands,
+    fixed_time: Res<FixedTime>,
+    terrain: Option<Res<GridTerrain>>,
+    mut cones: Query<(Entity, &mut KnockedCone, &mut Transform)>,
+) {
+    let dt = fixed_time.period.as_secs_f32();
+    for (entity, mut cone, mut transform) in cones.iter_mut() {
+        let velocity = cone.velocity;
+        let angular_velocity = cone.angular_velocity;
+        transform.translation += velocity * dt;
+        transform.rotation =
+            (Quat::from_scaled_axis(angular_velocity * dt) * transform.rotation).normalize();
+        cone.velocity.z -= GRAVITY * dt;
+
+        let position = transform.translation;
+        let ground = match &terrain {
+            Some(terrain) => terrain.height(position.x as f64, position.y as f64) as f32,
+            None => 0.,
+        };
+        if position.z > ground + cone.rest_height {
+            continue;
+        }
+
+        // on the ground: bounce, slide with friction, and stop tumbling
+        transform.translation.z = ground + cone.rest_height;
+        if cone.velocity.z < 0. {
+            cone.velocity.z *= -cone.restitution;
+        }
+        let horizontal = Vec3::new(cone.velocity.x, cone.velocity.y, 0.);
+        let slowdown = (cone.friction * GRAVITY * dt).min(horizontal.length());
+        cone.velocity -= horizontal.normalize_or_zero() * slowdown;
+        cone.angular_velocity *= (1. - 5. * dt).max(0.);
+
+        if cone.velocity.length() < 0.05 && cone.angular_velocity.length() < 0.1 {
+            commands.entity(entity).remove::<KnockedCone>();
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::Deserialize;
+
+use crate::{
+    build::{
+        build_car, solid_axle_components, CarDefinition, CarEntities, ChassisFlex, Drivetrain,
+    },
+    fuel::FuelTank,
+    payload::{Payload, PayloadItem},
+    physics::{BrakeWheel, SuspensionComponent},
+    transmission::Transmission,
+};
+
+// Car setup read from a TOML file. Every value is optional, the file overrides
+// the matching values of the built in car (`build_car`).
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct CarConfig {
+    pub chassis: ChassisConfig,
+    pub front_suspension: SuspensionConfig,
+    pub rear_suspension: SuspensionConfig, // all axles behind the front one
+    pub tire_pressures: Option<Vec<f64>>,  // kPa, one per corner, fl, fr, rl, rr
+    pub brake: BrakeConfig,
+    pub transmission: TransmissionConfig,
+    pub payload: Option<Vec<PayloadItem>>, // replaces the payload of the car
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct ChassisConfig {
+    pub mass: Option<f64>,                // the inertia is scaled with the mass
+    pub torsional_stiffness: Option<f64>, // Nm/rad, makes the chassis flexible
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct SuspensionConfig {
+    pub stiffness: Option<f64>,
+    pub damping: Option<f64>,
+    pub preload: Option<f64>,
+    pub travel: Option<[f64; 2]>, // bump, droop
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct BrakeConfig {
+    pub total_torque: Option<f64>,
+    pub front_bias: Option<f64>, // the rest is split evenly over the other axles
+    pub handbrake_torque: Option<f64>,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct TransmissionConfig {
+    pub ratios: Option<Vec<f64>>,
+    pub final_drive: Option<f64>,
+    pub reverse_ratio: Option<f64>,
+    pub automatic: Option<bool>,
+    pub low_range: Option<f64>, // adds a transfer case with this low range ratio
+}
+
+impl CarConfig {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("reading {}: {}", path.display(), error))?;
+        toml::from_str(&text).map_err(|error| format!("parsing {}: {}", path.display(), error))
+    }
+
+    pub fn apply(&self, car: &mut CarDefinition) {
+        if let Some(mass) = self.chassis.mass {
+            let scale = mass / car.chassis.mass;
+            car.chassis.mass = mass;
+            car.chassis.moi = car.chassis.moi.map(|moi| moi * scale);
+        }
+        if let Some(stiffness) = self.chassis.torsional_stiffness {
+            let mut flex = car
+                .chassis_flex
+                .clone()
+                .unwrap_or_else(|| ChassisFlex::new(stiffness));
+            flex.stiffness = stiffness;
+            car.chassis_flex = Some(flex);
+        }
+
+        for (ind, suspension) in car.suspension.iter_mut().enumerate() {
+            let config = if ind < 2 {
+                &self.front_suspension
+            } else {
+                &self.rear_suspension
+            };
+            if let Some(stiffness) = config.stiffness {
+                suspension.stiffness = stiffness;
+            }
+            if let Some(damping) = config.damping {
+                suspension.damping = damping;
+            }
+            if let Some(preload) = config.preload {
+                suspension.preload = preload;
+            }
+            if let Some(travel) = config.travel {
+                suspension.travel = travel;
+            }
+        }
+
+        if let Some(tire_pressures) = &self.tire_pressures {
+            for (pressure, config) in car.tire_pressures.iter_mut().zip(tire_pressures) {
+                *pressure = *config;
+            }
+        }
+
+        if let Some(total_torque) = self.brake.total_torque {
+            car.brake.total_torque = total_torque;
+        }
+        if let Some(front_bias) = self.brake.front_bias {
+            car.set_brake_bias(front_bias);
+        }
+        if let Some(handbrake_torque) = self.brake.handbrake_torque {
+            car.brake.handbrake_torque = handbrake_torque;
+        }
+
+        let transmission = &mut car.transmission;
+        if let Some(ratios) = &self.transmission.ratios {
+            if !ratios.is_empty() {
+                transmission.ratios = ratios.clone();
+            }
+        }
+        if let Some(final_drive) = self.transmission.final_drive {
+            transmission.final_drive = final_drive;
+        }
+        if let Some(reverse_ratio) = self.transmission.reverse_ratio {
+            transmission.reverse_ratio = reverse_ratio;
+        }
+        if let Some(automatic) = self.transmission.automatic {
+            transmission.automatic = automatic;
+        }
+        if let Some(low_range) = self.transmission.low_range {
+            transmission.low_range = Some(low_range);
+        }
+
+        if let Some(payload) = &self.payload {
+            car.payload = payload.clone();
+        }
+    }
+}
+
+impl CarDefinition {
+    // the built in car with the setup file applied
+    pub fn from_file(path: &Path, drivetrain: Drivetrain) -> Result<Self, String> {
+        let mut car = build_car(drivetrain);
+        CarConfig::from_file(path)?.apply(&mut car);
+        Ok(car)
+    }
+}
+
+// Watches the setup file, and re-applies it to the running car when it changes.
+// Only parameters that can change without rebuilding the car are updated live
+// (chassis mass and torsional stiffness, payload, suspension, brakes and gear ratios), tire pressures
+// and the number of gears take effect on restart. Values removed from the file keep
+// their current value.
+#[derive(Resource)]
+pub struct CarConfigFile {
+    pub path: PathBuf,
+    pub poll_time: f32,
+    modified: Option<SystemTime>,
+    timer: f32,
+}
+
+impl CarConfigFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+        Self {
+            path,
+            poll_time: 0.5,
+            modified,
+            timer: 0.,
+        }
+    }
+}
+
+pub fn car_config_reload_system(
+    time: Res<Time>,
+    config_file: Option<ResMut<CarConfigFile>>,
+    car_entities: Option<Res<CarEntities>>,
+    car: Option<ResMut<CarDefinition>>,
+    mut running_car: RunningCar,
+) {
+    let (mut config_file, car_entities, mut car) = match (config_file, car_entities, car) {
+        (Some(config_file), Some(car_entities), Some(car)) => (config_file, car_entities, car),
+        _ => return,
+    };
+
+    config_file.timer += time.delta_seconds();
+    if config_file.timer < config_file.poll_time {
+        return;
+    }
+    config_file.timer = 0.;
+
+    let modified = fs::metadata(&config_file.path)
+        .and_then(|meta| meta.modified())
+        .ok();
+    if modified.is_none() || modified == config_file.modified {
+        return;
+    }
+    config_file.modified = modified;
+
+    let config = match CarConfig::from_file(&config_file.path) {
+        Ok(config) => config,
+        Err(error) => {
+            warn!("car setup not reloaded, {}", error);
+            return;
+        }
+    };
+    config.apply(&mut car);
+    running_car.update(&config, &car, &car_entities);
+
+    info!("car setup reloaded from {}", config_file.path.display());
+}
+
+// Components of the running car that a changed `CarDefinition` is written into,
+// the parameters that can change without rebuilding the car
+#[derive(SystemParam)]
+pub struct RunningCar<'w, 's> {
+    fuel_tanks: Query<'w, 's, &'static mut FuelTank>,
+    payloads: Query<'w, 's, &'static mut Payload>,
+    suspensions: Query<'w, 's, &'static mut SuspensionComponent>,
+    brakes: Query<'w, 's, &'static mut BrakeWheel>,
+    transmissions: Query<'w, 's, &'static mut Transmission>,
+}
+
+impl RunningCar<'_, '_> {
+    // `config` is the setup just applied to `car`
+    pub fn update(&mut self, config: &CarConfig, car: &CarDefinition, car_entities: &CarEntities) {
+        if let Ok(mut fuel_tank) = self.fuel_tanks.get_mut(car_entities.chassis) {
+            let scale = car.chassis.mass / fuel_tank.dry_mass;
+            fuel_tank.dry_mass = car.chassis.mass;
+            fuel_tank.dry_moi *= scale;
+        }
+        // dropped ballast is loaded again
+        if config.payload.is_some() {
+            if let Ok(mut payload) = self.payloads.get_mut(car_entities.chassis) {
+                payload.items = car.payload.clone();
+            }
+        }
+
+        let independent = car
+            .suspension
+            .iter()
+            .enumerate()
+            .filter(|(ind, _)| !car.axles[ind / 2].solid)
+            .map(|(_, suspension)| suspension);
+        for (entity, suspension) in car_entities.suspensions.iter().zip(independent) {
+            if let Ok(mut component) = self.suspensions.get_mut(*entity) {
+                *component = suspension.component();
+            }
+        }
+        // a flexible chassis is only stiffened or softened, it isn't added to a running car
+        if let (Some(entity), Some(flex)) = (car_entities.chassis_flex, &car.chassis_flex) {
+            if let Ok(mut component) = self.suspensions.get_mut(entity) {
+                *component = flex.component();
+            }
+        }
+        for (ind, axle) in car_entities.solid_axles.iter() {
+            let components =
+                solid_axle_components(&car.suspension[2 * ind], &car.suspension[2 * ind + 1]);
+            for (entity, axle_component) in axle.iter().zip(components) {
+                if let Ok(mut component) = self.suspensions.get_mut(*entity) {
+                    *component = axle_component;
+                }
+            }
+        }
+
+        for (ind, entity) in car_entities.wheels.iter().enumerate() {
+            if let Ok(mut brake) = self.brakes.get_mut(*entity) {
+                let wheel_brake = car.wheel_brake(ind / 2);
+                brake.max_torque = wheel_brake.max_torque;
+                brake.handbrake_torque = wheel_brake.handbrake_torque;
+            }
+        }
+
+        if let Ok(mut transmission) = self.transmissions.get_mut(car_entities.engine) {
+            // the current gear must stay valid
+            if car.transmission.ratios.len() == transmission.ratios.len() {
+                transmission.ratios = car.transmission.ratios.clone();
+            }
+            transmission.final_drive = car.transmission.final_drive;
+            transmission.reverse_ratio = car.transmission.reverse_ratio;
+            transmission.automatic = car.transmission.automatic;
+            transmission.low_range = car.transmission.low_range;
+        }
+    }
+}
@@ -1,104 +1,412 @@
 use bevy::prelude::*;
 
-#[derive(Resource, Default)]
+use cameras::fly::FlyCamera;
+use rigid_body::{joint::Joint, physics_thread::PhysicsInput};
+
+use crate::{
+    bindings::{AxisBinding, AxisInput, GamepadBindings, InputBindings},
+    drive_mode::DriveModes,
+    maneuver::ManeuverRunner,
+    replay::InputPlayback,
+};
+
+// Driver inputs of a car, on the chassis entity
+#[derive(Component, Default, Clone)]
 pub struct CarControl {
     pub throttle: f32,
-    pub steering: f32,
+    pub steering: f32, // filtered steering command, used by the steering systems
+    pub steering_input: f32, // driver steering input, -1 to 1
     pub brake: f32,
+    pub handbrake: f32,
+    pub clutch: f32,   // clutch pedal, 1 is fully disengaged
+    pub gear_up: bool, // shift requests, cleared when the transmission shifts
+    pub gear_down: bool,
+    pub reverse: bool,           // reverse mode, selected until toggled back
+    pub toggle_abs: bool,        // request, cleared by the ABS system
+    pub toggle_low_range: bool,  // request, cleared by the transmission system
+    pub next_drive_mode: bool,   // request, cleared by the drive mode system
+    pub drop_ballast: bool,      // request, cleared by the payload system
+    pub toggle_headlights: bool, // request, cleared by the headlights system
+}
+
+// The requests cleared by the time steps are handed over to the physics thread.
+// The drive mode goes with `DriveModes`, the ballast is dropped in both worlds.
+impl PhysicsInput for CarControl {
+    type Data = CarControl;
+
+    fn send(&mut self) -> CarControl {
+        let control = self.clone();
+        self.gear_up = false;
+        self.gear_down = false;
+        self.toggle_abs = false;
+        self.toggle_low_range = false;
+        control
+    }
+
+    fn receive(&mut self, control: &CarControl, fresh: bool) {
+        self.throttle = control.throttle;
+        self.steering = control.steering;
+        self.steering_input = control.steering_input;
+        self.brake = control.brake;
+        self.handbrake = control.handbrake;
+        self.clutch = control.clutch;
+        self.reverse = control.reverse;
+        if fresh {
+            self.gear_up |= control.gear_up;
+            self.gear_down |= control.gear_down;
+            self.toggle_abs |= control.toggle_abs;
+            self.toggle_low_range |= control.toggle_low_range;
+            self.drop_ballast |= control.drop_ballast;
+        }
+    }
+}
+
+// Driver steering filter. The steering command is scaled down with vehicle
+// speed, and follows the driver input with a lag and a rate limit, so a full
+// lock keyboard input at speed doesn't instantly spin the car.
+#[derive(Resource, Clone)]
+pub struct SteeringConfig {
+    pub max_rate: f32,        // maximum change of the steering command per second
+    pub lag: f32,             // time constant of the first order lag (s)
+    pub reference_speed: f32, // speed (m/s) where the steering authority is halved
+    pub min_factor: f32,      // lower limit of the speed scaling
+}
+
+impl Default for SteeringConfig {
+    fn default() -> Self {
+        Self {
+            max_rate: 2.0,
+            lag: 0.05,
+            reference_speed: 25.,
+            min_factor: 0.2,
+        }
+    }
+}
+
+impl SteeringConfig {
+    // fraction of the driver input available at the given speed
+    pub fn speed_factor(&self, speed: f32) -> f32 {
+        (1. / (1. + (speed / self.reference_speed).powi(2))).max(self.min_factor)
+    }
+}
+
+// Axis mapping for a racing wheel and pedals (they show up as a gamepad).
+// Insert the resource to use it in place of the gamepad bindings of `InputBindings`.
+#[derive(Resource, Clone)]
+pub struct RacingWheel {
+    pub steering: GamepadAxisType,
+    pub throttle: GamepadAxisType,
+    pub brake: GamepadAxisType,
+    pub clutch: Option<GamepadAxisType>,
+    pub steering_range: f32,   // axis value at full steering lock
+    pub pedals_inverted: bool, // pedals reading 1 when released and -1 when pressed
+}
+
+impl Default for RacingWheel {
+    fn default() -> Self {
+        Self {
+            steering: GamepadAxisType::LeftStickX,
+            throttle: GamepadAxisType::RightZ,
+            brake: GamepadAxisType::LeftZ,
+            clutch: None,
+            steering_range: 1.0,
+            pedals_inverted: true,
+        }
+    }
+}
+
+impl RacingWheel {
+    // pedal position from 0 (released) to 1 (pressed)
+    pub fn pedal(&self, axis: f32) -> f32 {
+        let axis = if self.pedals_inverted { -axis } else { axis };
+        ((axis + 1.) / 2.).clamp(0., 1.)
+    }
+
+    // the axis mapping as gamepad bindings, with the default buttons
+    pub fn bindings(&self) -> GamepadBindings {
+        let pedal = |axis| AxisBinding {
+            deadzone: 0.,
+            invert: self.pedals_inverted,
+            pedal: true,
+            ..AxisBinding::new(AxisInput::Axis(axis))
+        };
+        GamepadBindings {
+            steering: vec![AxisBinding {
+                deadzone: 0.,
+                sensitivity: 1. / self.steering_range,
+                invert: true,
+                ..AxisBinding::new(AxisInput::Axis(self.steering))
+            }],
+            throttle: vec![pedal(self.throttle)],
+            brake: vec![pedal(self.brake)],
+            clutch: self.clutch.map(pedal).into_iter().collect(),
+            absolute: true,
+            ..default()
+        }
+    }
+}
+
+// marks the chassis joint, its forward velocity is the vehicle speed
+#[derive(Component)]
+pub struct ChassisJoint;
+
+// Links a part of a car (steering joint, wheel, engine) to the chassis entity
+// holding the car's `CarControl`
+#[derive(Component, Clone, Copy)]
+pub struct CarPart(pub Entity);
+
+// Which gamepads drive a car
+#[derive(Clone, Copy)]
+pub enum GamepadInput {
+    None,
+    Any,
+    Id(usize),
+}
+
+impl GamepadInput {
+    pub fn accepts(&self, gamepad: Gamepad) -> bool {
+        match self {
+            GamepadInput::None => false,
+            GamepadInput::Any => true,
+            GamepadInput::Id(id) => gamepad.id == *id,
+        }
+    }
+}
+
+// Control source for a car driven by a player. Several cars can be driven at
+// once, for example one with the keyboard and one with a gamepad.
+#[derive(Component, Clone)]
+pub struct UserControl {
+    pub keyboard: bool,
+    pub gamepad: GamepadInput,
+}
+
+impl Default for UserControl {
+    fn default() -> Self {
+        Self {
+            keyboard: true,
+            gamepad: GamepadInput::Any,
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn user_control_system(
     keyboard_input: Res<Input<KeyCode>>,
     gamepads: Res<Gamepads>,
+    button_inputs: Res<Input<GamepadButton>>,
     button_axes: Res<Axis<GamepadButton>>,
     axes: Res<Axis<GamepadAxis>>,
-    mut control: ResMut<CarControl>,
+    bindings: Res<InputBindings>,
+    racing_wheel: Option<Res<RacingWheel>>,
+    mut cars: Query<(&mut CarControl, &UserControl)>,
+    fly_cameras: Query<&FlyCamera>,
 ) {
-    // gamepad controls
-    for gamepad in gamepads.iter() {
-        // trigger controls
-        let throttle = button_axes
-            .get(GamepadButton::new(
-                gamepad,
-                GamepadButtonType::RightTrigger2,
-            ))
-            .unwrap();
+    let wheel_bindings = racing_wheel.map(|wheel| wheel.bindings());
+    let keys = &bindings.keyboard;
+    // the keyboard flies the camera instead
+    let keyboard = !fly_cameras.iter().any(|fly| fly.active);
+    for (mut control, user) in cars.iter_mut() {
+        let keyboard = keyboard && user.keyboard;
+        let pressed =
+            |keys: &[KeyCode]| keyboard && keyboard_input.any_pressed(keys.iter().copied());
+        let just_pressed =
+            |keys: &[KeyCode]| keyboard && keyboard_input.any_just_pressed(keys.iter().copied());
+        let mut handbrake = false;
 
-        if throttle > 0.01 {
-            control.throttle = throttle;
+        // gamepad, racing wheel and pedal controls
+        for gamepad in gamepads
+            .iter()
+            .filter(|gamepad| user.gamepad.accepts(*gamepad))
+        {
+            let pad = match &wheel_bindings {
+                Some(wheel_bindings) => wheel_bindings,
+                None => bindings.gamepad(gamepad),
+            };
+            let value = |axis: &AxisBinding| axis.value(gamepad, &axes, &button_axes);
+            // the largest input of a pedal control, None if nothing is bound
+            let pedal = |bound: &[AxisBinding]| {
+                bound
+                    .iter()
+                    .map(|axis| value(axis).max(0.))
+                    .reduce(f32::max)
+            };
+
+            if let Some(throttle) = pedal(&pad.throttle) {
+                if pad.absolute || throttle > 0. {
+                    control.throttle = throttle;
+                }
+            }
+            if let Some(brake) = pedal(&pad.brake) {
+                if pad.absolute || brake > 0. {
+                    control.brake = brake;
+                }
+            }
+            if let Some(clutch) = pedal(&pad.clutch) {
+                if pad.absolute || clutch > 0. {
+                    control.clutch = clutch;
+                }
+            }
+            let steering =
+                pad.steering
+                    .iter()
+                    .map(value)
+                    .reduce(|a, b| if b.abs() > a.abs() { b } else { a });
+            if let Some(steering) = steering {
+                if pad.absolute || steering != 0. {
+                    control.steering_input = steering;
+                }
+            }
+
+            let button = |button: Option<GamepadButtonType>| {
+                button.map(|button| GamepadButton::new(gamepad, button))
+            };
+            let just_pressed = |binding| match button(binding) {
+                Some(button) => button_inputs.just_pressed(button),
+                None => false,
+            };
+            let pressed = |binding| match button(binding) {
+                Some(button) => button_inputs.pressed(button),
+                None => false,
+            };
+            if just_pressed(pad.gear_up) {
+                control.gear_up = true;
+            }
+            if just_pressed(pad.gear_down) {
+                control.gear_down = true;
+            }
+            if just_pressed(pad.reverse) {
+                control.reverse = !control.reverse;
+            }
+            if just_pressed(pad.drive_mode) {
+                control.next_drive_mode = true;
+            }
+            if just_pressed(pad.drop_ballast) {
+                control.drop_ballast = true;
+            }
+            if just_pressed(pad.low_range) {
+                control.toggle_low_range = true;
+            }
+            if just_pressed(pad.headlights) {
+                control.toggle_headlights = true;
+            }
+            if pressed(pad.clutch_button) {
+                control.clutch = 1.0;
+            }
+            if pressed(pad.handbrake) {
+                handbrake = true;
+            }
         }
 
-        let brake = button_axes
-            .get(GamepadButton::new(gamepad, GamepadButtonType::LeftTrigger2))
-            .unwrap();
+        // Keyboard controls - these are rate controlled to make them feel more natural.
+        // When a key is pressed, the control value is increased at a constant rate.
+        // When a key is released, the control value is decreased at a constant rate.
+        // The control value is clamped between 0 and 1 for throttle and brake, and
+        // between -1 and 1 for steering.
+        let time_constant = 1. / (keys.response_time.max(0.01) * 60.);
+        if pressed(&keys.throttle) {
+            control.throttle += time_constant;
+            control.throttle = control.throttle.min(1.0);
+        } else {
+            control.throttle -= time_constant;
+            control.throttle = control.throttle.max(0.0);
+        }
 
-        if brake > 0.01 {
-            control.brake = brake;
+        if pressed(&keys.brake) {
+            control.brake += time_constant;
+            control.brake = control.brake.min(1.0);
+        } else {
+            control.brake -= time_constant;
+            control.brake = control.brake.max(0.0);
         }
 
-        // right stick throttle/brake
-        let throttle_brake = axes
-            .get(GamepadAxis::new(gamepad, GamepadAxisType::RightStickY))
-            .unwrap();
-        if throttle_brake > 0.01 {
-            control.throttle = throttle_brake;
+        // the handbrake is pulled immediately (no rate control)
+        if pressed(&keys.handbrake) {
+            handbrake = true;
         }
-        if throttle_brake < -0.01 {
-            control.brake = -throttle_brake;
+        control.handbrake = if handbrake { 1.0 } else { 0.0 };
+
+        if pressed(&keys.clutch) {
+            control.clutch += time_constant;
+            control.clutch = control.clutch.min(1.0);
+        } else {
+            control.clutch -= time_constant;
+            control.clutch = control.clutch.max(0.0);
         }
 
-        // left stick steering
-        let steering = -axes
-            .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
-            .unwrap();
-        if steering.abs() > 0.01 {
-            control.steering = steering;
+        if just_pressed(&keys.gear_up) {
+            control.gear_up = true;
+        }
+        if just_pressed(&keys.gear_down) {
+            control.gear_down = true;
+        }
+        if just_pressed(&keys.reverse) {
+            control.reverse = !control.reverse;
+        }
+        if just_pressed(&keys.abs) {
+            control.toggle_abs = true;
+        }
+        if just_pressed(&keys.drive_mode) {
+            control.next_drive_mode = true;
+        }
+        if just_pressed(&keys.drop_ballast) {
+            control.drop_ballast = true;
+        }
+        if just_pressed(&keys.low_range) {
+            control.toggle_low_range = true;
+        }
+        if just_pressed(&keys.headlights) {
+            control.toggle_headlights = true;
         }
-    }
 
-    // Keyboard controls - these are rate controlled to make them feel more natural.
-    // When a key is pressed, the control value is increased at a constant rate.
-    // When a key is released, the control value is decreased at a constant rate.
-    // The control value is clamped between 0 and 1 for throttle and brake, and
-    // between -1 and 1 for steering.
-    let response_time = 0.25;
-    let time_constant = 1. / (response_time * 60.);
-    if keyboard_input.pressed(KeyCode::W) {
-        control.throttle += time_constant;
-        control.throttle = control.throttle.min(1.0);
-    } else {
-        control.throttle -= time_constant;
-        control.throttle = control.throttle.max(0.0);
-    }
+        let mut steer_active = false;
+        if pressed(&keys.steer_left) {
+            control.steering_input += time_constant;
+            control.steering_input = control.steering_input.min(1.0);
+            steer_active = true;
+        }
 
-    if keyboard_input.pressed(KeyCode::S) {
-        control.brake += time_constant;
-        control.brake = control.brake.min(1.0);
-    } else {
-        control.brake -= time_constant;
-        control.brake = control.brake.max(0.0);
-    }
+        if pressed(&keys.steer_right) {
+            control.steering_input -= time_constant;
+            control.steering_input = control.steering_input.max(-1.0);
+            steer_active = true;
+        }
 
-    let mut steer_active = false;
-    if keyboard_input.pressed(KeyCode::A) {
-        control.steering += time_constant;
-        control.steering = control.steering.min(1.0);
-        steer_active = true;
+        if !steer_active {
+            if control.steering_input.abs() < time_constant {
+                control.steering_input = 0.0;
+            } else if control.steering_input > 0.0 {
+                control.steering_input -= time_constant;
+            } else {
+                control.steering_input += time_constant;
+            }
+        }
     }
+}
 
-    if keyboard_input.pressed(KeyCode::D) {
-        control.steering -= time_constant;
-        control.steering = control.steering.max(-1.0);
-        steer_active = true;
-    }
+// cars driven by a player or the AI, playback and maneuvers set the steering directly
+type FilteredCars = (
+    With<ChassisJoint>,
+    Without<InputPlayback>,
+    Without<ManeuverRunner>,
+);
 
-    if !steer_active {
-        if control.steering.abs() < time_constant {
-            control.steering = 0.0;
-        } else if control.steering > 0.0 {
-            control.steering -= time_constant;
-        } else {
-            control.steering += time_constant;
-        }
+pub fn steering_filter_system(
+    time: Res<Time>,
+    config: Res<SteeringConfig>,
+    mut cars: Query<(&Joint, &mut CarControl, Option<&DriveModes>), FilteredCars>,
+) {
+    let dt = time.delta_seconds();
+    for (joint, mut control, modes) in cars.iter_mut() {
+        // chassis velocity is in chassis coordinates, x is forward
+        let speed = joint.v.v.x.abs() as f32;
+        let sensitivity = modes.map_or(1., |modes| modes.profile().steering);
+
+        let input = (control.steering_input * sensitivity).clamp(-1., 1.);
+        let target = input * config.speed_factor(speed);
+        let lag = (dt / config.lag).min(1.);
+        let max_change = config.max_rate * dt;
+        let change = ((target - control.steering) * lag).clamp(-max_change, max_change);
+        control.steering += change;
     }
 }
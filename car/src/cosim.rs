@@ -0,0 +1,385 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use bevy::{ecs::system::SystemState, prelude::*, time::TimeUpdateStrategy};
+use bevy_integrator::{ExitEvent, PhysicsState, SimTime};
+use rigid_body::{
+    joint::{Base, Joint},
+    physics_thread::PhysicsId,
+    sva::Vector,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    build::{external_car_startup_system, respawn_car, CarEntities},
+    camera_sensor::CameraSensor,
+    control::CarControl,
+};
+
+// Co-simulation server for the car in `CarEntities`: an external controller
+// (Simulink, C++, Python) drives the simulation in lock step through a ZeroMQ
+// REP socket. Requests and replies are JSON objects, one per message:
+// - `{"method": "step", "steps": 10}`: takes time steps, replies with the state
+//   (at once for no steps)
+// - `{"method": "set_input", "throttle": 0.5, "steering": 0.1}`: sets the given
+//   driver inputs of the `CarControl`, replies `{"ok": true}`
+// - `{"method": "get_state"}`: replies with the state
+// - `{"method": "get_image"}`: the latest picture of the car's `CameraSensor`,
+//   replies with two message parts: its `frame` number, `time`, `width`,
+//   `height` and `bytes`, then the RGBA pixels, row by row from the top left.
+//   Pictures come back from the renderer a frame or two after they are taken.
+// - `{"method": "reset"}`: spawns the car again (`external_car_startup_system`),
+//   so it starts over from its initial state and time with the controls
+//   released, replies with the state
+// - `{"method": "close"}`: replies `{"ok": true}` and exits
+// The state has the time, the joint names, positions and velocities, and the
+// chassis position (absolute), velocity and angular velocity (chassis axes) of
+// the last time step taken.
+// The socket is served on its own thread, which hands the requests to the app
+// and waits for their replies. The app keeps running its frames meanwhile, the
+// time stopped between step requests, and every update of a step request takes
+// one time step, with or without a window.
+pub struct CoSimPlugin {
+    pub address: String, // ZeroMQ endpoint to bind, e.g. tcp://127.0.0.1:5555
+}
+
+impl Plugin for CoSimPlugin {
+    fn build(&self, app: &mut App) {
+        let dt = app.world.resource::<SimTime>().dt;
+        let server = CoSimServer::start(&self.address).unwrap_or_else(|error| panic!("{}", error));
+        info!("co-simulation server listening on {}", self.address);
+        app.insert_resource(server)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(
+                std::time::Duration::from_secs_f64(dt),
+            ))
+            .add_systems(Startup, cosim_pause_system)
+            .add_systems(Last, (cosim_system, cosim_reset_system).chain());
+    }
+}
+
+#[derive(Resource)]
+pub struct CoSimServer {
+    pub address: String,
+    requests: Mutex<Receiver<Request>>, // from the socket thread
+    replies: Sender<Reply>,             // to the socket thread
+    thread: Option<JoinHandle<()>>,
+    end_step: Option<usize>, // time step a step request ends at
+    reset: bool,             // a reset request waits for its reply
+    joints: Vec<Entity>,     // every joint but the base, in a fixed order
+}
+
+// a reply message, the JSON object and the pixels of a picture
+struct Reply {
+    value: Value,
+    bytes: Option<Vec<u8>>,
+}
+
+impl CoSimServer {
+    // binds the socket and starts serving it on its own thread
+    pub fn start(address: &str) -> Result<Self, String> {
+        let context = zmq::Context::new();
+        let socket = context
+            .socket(zmq::REP)
+            .and_then(|socket| {
+                // a reply to a client gone doesn't hold the exit
+                socket.set_linger(1000)?;
+                socket.bind(address)?;
+                Ok(socket)
+            })
+            .map_err(|error| format!("co-simulation server on {}: {}", address, error))?;
+        let (request_sender, requests) = mpsc::channel();
+        let (replies, reply_receiver) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("cosim".to_string())
+            .spawn(move || serve(socket, request_sender, reply_receiver))
+            .map_err(|error| format!("starting the co-simulation server: {}", error))?;
+        Ok(Self {
+            address: address.to_string(),
+            requests: Mutex::new(requests),
+            replies,
+            thread: Some(thread),
+            end_step: None,
+            reset: false,
+            joints: Vec::new(),
+        })
+    }
+
+    // the next request, if one came in
+    fn request(&self) -> Result<Request, TryRecvError> {
+        let requests = self.requests.lock();
+        requests
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .try_recv()
+    }
+
+    fn reply(&self, value: Value) {
+        // the socket thread is gone when the client broke the connection
+        let _ = self.replies.send(Reply { value, bytes: None });
+    }
+
+    fn reply_with_bytes(&self, value: Value, bytes: Vec<u8>) {
+        let bytes = Some(bytes);
+        let _ = self.replies.send(Reply { value, bytes });
+    }
+}
+
+// Takes the requests off the socket and hands them to the app, sending its reply
+// before taking the next request. Malformed requests are answered here.
+fn serve(socket: zmq::Socket, requests: Sender<Request>, replies: Receiver<Reply>) {
+    loop {
+        let message = match socket.recv_bytes(0) {
+            Ok(message) => message,
+            Err(error) => {
+                warn!("co-simulation server: {}", error);
+                return;
+            }
+        };
+        let (reply, close) = match serde_json::from_slice::<Request>(&message) {
+            Ok(request) => {
+                let close = matches!(request, Request::Close);
+                // the app has quit when its ends of the channels are gone
+                if requests.send(request).is_err() {
+                    return;
+                }
+                match replies.recv() {
+                    Ok(reply) => (reply, close),
+                    Err(_) => return,
+                }
+            }
+            Err(error) => {
+                let value = json!({"error": error.to_string()});
+                (Reply { value, bytes: None }, false)
+            }
+        };
+        let header = reply.value.to_string();
+        let sent = match &reply.bytes {
+            Some(bytes) => socket.send_multipart([header.as_bytes(), bytes.as_slice()], 0),
+            None => socket.send(header.as_bytes(), 0),
+        };
+        if let Err(error) = sent {
+            warn!("co-simulation server: {}", error);
+            return;
+        }
+        if close {
+            return;
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+enum Request {
+    Step {
+        #[serde(default = "one_step")]
+        steps: usize,
+    },
+    SetInput {
+        throttle: Option<f32>,
+        steering: Option<f32>,
+        brake: Option<f32>,
+        handbrake: Option<f32>,
+        clutch: Option<f32>,
+    },
+    GetState,
+    GetImage,
+    Reset,
+    Close,
+}
+
+fn one_step() -> usize {
+    1
+}
+
+type CoSimJoints<'w, 's> =
+    Query<'w, 's, (Entity, &'static Joint, Option<&'static PhysicsId>), Without<Base>>;
+
+// the time stands still until the first step request
+fn cosim_pause_system(mut time: ResMut<Time>) {
+    time.pause();
+}
+
+// At the end of the frame, so the time pauses or goes on from the next frame:
+// answers the step request once its time steps are taken, and the other
+// requests that came in.
+#[allow(clippy::too_many_arguments)]
+fn cosim_system(
+    server: Option<ResMut<CoSimServer>>,
+    car: Option<Res<CarEntities>>,
+    physics_state: Option<Res<PhysicsState<Joint>>>,
+    sim_time: Res<SimTime>,
+    mut time: ResMut<Time>,
+    joints: CoSimJoints,
+    mut controls: Query<&mut CarControl>,
+    cameras: Query<&CameraSensor>,
+    mut exit: EventWriter<ExitEvent>,
+) {
+    // the joint states are initialized after the startup systems
+    let (mut server, car, physics_state) = match (server, car, physics_state) {
+        (Some(server), Some(car), Some(physics_state)) => (server, car, physics_state),
+        _ => return,
+    };
+    if server.joints.is_empty() {
+        server.joints = joint_order(&joints);
+    }
+
+    match server.end_step {
+        Some(end_step) if sim_time.index < end_step => return,
+        Some(_) => {
+            server.end_step = None;
+            time.pause();
+            let state = state(&server, &physics_state, &sim_time, &car, &joints);
+            server.reply(state);
+        }
+        None => (),
+    }
+
+    loop {
+        let request = match server.request() {
+            Ok(request) => request,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => {
+                // without a server, the simulation can't go on
+                exit.send(ExitEvent);
+                return;
+            }
+        };
+        match request {
+            Request::Step { steps: 0 } => {
+                let state = state(&server, &physics_state, &sim_time, &car, &joints);
+                server.reply(state);
+            }
+            Request::Step { steps } => {
+                server.end_step = Some(sim_time.index + steps);
+                time.unpause();
+                return;
+            }
+            Request::SetInput {
+                throttle,
+                steering,
+                brake,
+                handbrake,
+                clutch,
+            } => {
+                if let Ok(mut control) = controls.get_mut(car.chassis) {
+                    let pedal = |value: Option<f32>, current: f32| {
+                        value.map_or(current, |value| value.clamp(0., 1.))
+                    };
+                    control.throttle = pedal(throttle, control.throttle);
+                    control.brake = pedal(brake, control.brake);
+                    control.handbrake = pedal(handbrake, control.handbrake);
+                    control.clutch = pedal(clutch, control.clutch);
+                    if let Some(steering) = steering {
+                        control.steering_input = steering.clamp(-1., 1.);
+                    }
+                }
+                server.reply(json!({"ok": true}));
+            }
+            Request::GetState => {
+                let state = state(&server, &physics_state, &sim_time, &car, &joints);
+                server.reply(state);
+            }
+            Request::GetImage => {
+                let frame = cameras
+                    .get(car.chassis)
+                    .ok()
+                    .and_then(|camera| camera.frame.as_ref());
+                match frame {
+                    Some(frame) => server.reply_with_bytes(
+                        json!({
+                            "frame": frame.index,
+                            "time": frame.time,
+                            "width": frame.width,
+                            "height": frame.height,
+                            "bytes": frame.pixels.len(),
+                        }),
+                        frame.pixels.clone(),
+                    ),
+                    None => server.reply(json!({"error": "no camera picture"})),
+                }
+            }
+            Request::Reset => {
+                // answered by `cosim_reset_system`, with the car spawned again
+                server.reset = true;
+                return;
+            }
+            Request::Close => {
+                server.reply(json!({"ok": true}));
+                // until the reply is sent
+                if let Some(thread) = server.thread.take() {
+                    let _ = thread.join();
+                }
+                exit.send(ExitEvent);
+                return;
+            }
+        }
+    }
+}
+
+// Spawns the car again for a reset request and replies with its initial state.
+// Every component of the car starts over, not only the joint states.
+fn cosim_reset_system(world: &mut World) {
+    match world.get_resource_mut::<CoSimServer>() {
+        Some(mut server) if server.reset => server.reset = false,
+        _ => return,
+    }
+    respawn_car(world, external_car_startup_system);
+
+    let mut joints = SystemState::<CoSimJoints>::new(world);
+    let order = joint_order(&joints.get(world));
+    world.resource_mut::<CoSimServer>().joints = order;
+    let joints = joints.get(world);
+    let server = world.resource::<CoSimServer>();
+    let car = world.resource::<CarEntities>();
+    let state = state(server, world.resource(), world.resource(), car, &joints);
+    server.reply(state);
+}
+
+fn joint_order(joints: &CoSimJoints) -> Vec<Entity> {
+    // in the order they were spawned in, the same again once respawned
+    let mut entities: Vec<_> = joints.iter().map(|(entity, _, id)| (id, entity)).collect();
+    entities.sort();
+    entities.into_iter().map(|(_, entity)| entity).collect()
+}
+
+// the joint states of the integrator, the joints hold those of its last stage
+fn state(
+    server: &CoSimServer,
+    physics_state: &PhysicsState<Joint>,
+    time: &SimTime,
+    car: &CarEntities,
+    joints: &CoSimJoints,
+) -> Value {
+    let mut names = Vec::new();
+    let mut q = Vec::new();
+    let mut qd = Vec::new();
+    for entity in server.joints.iter() {
+        if let (Ok((_, joint, _)), Some(state)) =
+            (joints.get(*entity), physics_state.states.get(entity))
+        {
+            names.push(joint.name.clone());
+            q.push(state.q);
+            qd.push(state.qd);
+        }
+    }
+    let chassis = joints.get(car.chassis).ok().map(|(_, chassis, _)| {
+        let position = chassis.x.inverse().transform_point(Vector::zeros());
+        json!({
+            "position": [position.x, position.y, position.z],
+            "velocity": [chassis.v.v.x, chassis.v.v.y, chassis.v.v.z],
+            "angular_velocity": [chassis.v.w.x, chassis.v.w.y, chassis.v.w.z],
+        })
+    });
+    json!({
+        "time": time.time(),
+        "joints": names,
+        "q": q,
+        "qd": qd,
+        "chassis": chassis,
+    })
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use bevy::{prelude::*, render::mesh::VertexAttributeValues};
+use rigid_body::{joint::Joint, sva::Vector};
+
+use crate::{engine::Engine, tire::PointTire};
+
+// A dent in the body, in chassis coordinates
+#[derive(Clone, Debug)]
+pub struct Dent {
+    pub position: Vector,  // impact point on the body
+    pub direction: Vector, // into the body
+    pub depth: f64,
+    pub radius: f64,
+}
+
+// Collision damage of a car, on the chassis entity. The chassis has no collision
+// shape of its own, impacts are taken from the horizontal chassis acceleration:
+// hitting a wall or a prop gives a short deceleration far above anything the
+// tires can produce on the ground. An impact damages the parts near where it
+// hit, in proportion to how far the peak acceleration went over `threshold`.
+#[derive(Component, Clone)]
+pub struct Damage {
+    pub enabled: bool,
+    pub threshold: f64,      // chassis acceleration (m/s²) of a significant impact
+    pub full_damage: f64,    // acceleration above the threshold that wrecks a part
+    pub filter_time: f64,    // time constant (s) of the acceleration filter
+    pub max_power_loss: f64, // engine torque lost with a wrecked engine (fraction)
+    pub max_steering_offset: f64, // steering offset with wrecked steering (of full lock)
+    pub max_rolling_resistance: f64, // extra rolling resistance (scale) of a wrecked wheel
+    pub max_dent: f64,       // dent depth (m) of a full damage impact
+    pub engine: f64,         // 0 intact to 1 wrecked
+    pub steering: f64,       // -1 to 1, positive pulls to the left
+    pub wheels: Vec<f64>,    // 0 intact to 1 wrecked, in wheel order
+    pub dents: Vec<Dent>,
+    pub impacts: u32,
+    pub outputs: HashMap<String, f64>,
+    engine_entity: Entity,
+    wheel_entities: Vec<(String, Entity, Vector)>, // corner, wheel and its position on the chassis
+    half_size: Vector,                             // of the body
+    center: Vector,                                // of the body, in chassis coordinates
+    previous: Option<(Vector, Vector)>,            // absolute position and velocity
+    acceleration: Vector,                          // filtered, in chassis coordinates
+    peak: Option<Vector>,                          // largest acceleration of the current impact
+    meshed_dents: usize,                           // dents applied to the body mesh
+}
+
+impl Default for Damage {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 80.,
+            full_damage: 500.,
+            filter_time: 0.02,
+            max_power_loss: 0.6,
+            max_steering_offset: 0.08,
+            max_rolling_resistance: 4.,
+            max_dent: 0.15,
+            engine: 0.,
+            steering: 0.,
+            wheels: Vec::new(),
+            dents: Vec::new(),
+            impacts: 0,
+            outputs: HashMap::new(),
+            engine_entity: Entity::PLACEHOLDER,
+            wheel_entities: Vec::new(),
+            half_size: Vector::new(1.5, 0.6, 0.2),
+            center: Vector::zeros(),
+            previous: None,
+            acceleration: Vector::zeros(),
+            peak: None,
+            meshed_dents: 0,
+        }
+    }
+}
+
+impl Damage {
+    // the engine and wheels (corner name, wheel, and its position on the chassis) that are damaged
+    pub fn with_parts(mut self, engine: Entity, wheels: Vec<(String, Entity, Vector)>) -> Self {
+        self.engine_entity = engine;
+        self.wheels = vec![0.; wheels.len()];
+        self.wheel_entities = wheels;
+        self
+    }
+
+    // size and position of the body box, where the dents go
+    pub fn with_body(mut self, dimensions: [f64; 3], position: [f64; 3]) -> Self {
+        let [x, y, z] = dimensions;
+        self.half_size = Vector::new(x, y, z) / 2.;
+        self.center = Vector::new(position[0], position[1], position[2]);
+        self
+    }
+
+    // steering command added by bent steering
+    pub fn steering_offset(&self) -> f64 {
+        self.max_steering_offset * self.steering
+    }
+
+    pub fn repair(&mut self) {
+        self.engine = 0.;
+        self.steering = 0.;
+        self.wheels.iter_mut().for_each(|wheel| *wheel = 0.);
+        self.dents.clear();
+    }
+
+    fn apply_impact(&mut self, acceleration: Vector) {
+        let magnitude = acceleration.norm();
+        let severity = ((magnitude - self.threshold) / self.full_damage).clamp(0., 1.);
+        self.impacts += 1;
+
+        // the body is pushed away from the impact, which is on the side of the
+        // body box opposite to the acceleration
+        let direction = acceleration / magnitude;
+        let extent =
+            (self.half_size.x / direction.x.abs()).min(self.half_size.y / direction.y.abs());
+        let point = self.center - direction * extent;
+        let front = (point.x / self.half_size.x).clamp(0., 1.);
+        let side = (point.y / self.half_size.y).clamp(-1., 1.);
+
+        // frontal hits reach the engine, hits on a front corner bend the steering
+        self.engine = (self.engine + severity * front).min(1.);
+        self.steering = (self.steering + severity * front * side).clamp(-1., 1.);
+        for (damage, (_, _, position)) in self.wheels.iter_mut().zip(&self.wheel_entities) {
+            let distance = ((point.x - position.x).powi(2) + (point.y - position.y).powi(2)).sqrt();
+            *damage = (*damage + severity * (1. - distance).max(0.)).min(1.);
+        }
+        self.dents.push(Dent {
+            position: point,
+            direction,
+            depth: self.max_dent * severity,
+            radius: 0.4 + 0.6 * severity,
+        });
+        info!(
+            "impact {:.0} m/s², damage engine {:.2}, steering {:.2}",
+            magnitude, self.engine, self.steering
+        );
+    }
+}
+
+// runs once per time step (not in the physics schedule), like the ABS
+pub fn damage_system(
+    fixed_time: Res<FixedTime>,
+    mut cars: Query<(&Joint, &mut Damage)>,
+    mut engines: Query<&mut Engine>,
+    mut tires: Query<&mut PointTire>,
+) {
+    let dt = fixed_time.period.as_secs_f64();
+    for (joint, mut damage) in cars.iter_mut() {
+        // chassis velocity is in chassis coordinates
+        let x0i = joint.x.inverse();
+        let position = x0i.transform_point(Vector::zeros());
+        let velocity = x0i * joint.v.v;
+
+        // a respawned car jumps, it is not an impact
+        if let Some((previous_position, previous_velocity)) = damage.previous {
+            if (position - previous_position).norm() < 1. {
+                // landing from a jump is not an impact, only the horizontal part counts
+                let acceleration = (velocity - previous_velocity) / dt;
+                let horizontal = joint.x * Vector::new(acceleration.x, acceleration.y, 0.);
+                let horizontal = Vector::new(horizontal.x, horizontal.y, 0.);
+                let lag = (dt / damage.filter_time).min(1.);
+                let change = (horizontal - damage.acceleration) * lag;
+                damage.acceleration += change;
+            } else {
+                damage.acceleration = Vector::zeros();
+            }
+        }
+        damage.previous = Some((position, velocity));
+
+        // the damage is taken at the peak, when the impact is over
+        let acceleration = damage.acceleration;
+        if damage.enabled && acceleration.norm() > damage.threshold {
+            match damage.peak {
+                Some(peak) if peak.norm() >= acceleration.norm() => {}
+                _ => damage.peak = Some(acceleration),
+            }
+        } else if let Some(peak) = damage.peak.take() {
+            damage.apply_impact(peak);
+        }
+
+        if let Ok(mut engine) = engines.get_mut(damage.engine_entity) {
+            engine.power_factor = 1. - damage.max_power_loss * damage.engine;
+        }
+        for (wheel_damage, (_, wheel, _)) in damage.wheels.iter().zip(&damage.wheel_entities) {
+            if let Some(mut tire) = tires.iter_mut().find(|tire| tire.joint_entity() == *wheel) {
+                tire.set_rolling_resistance_scale(
+                    1. + damage.max_rolling_resistance * wheel_damage,
+                );
+            }
+        }
+
+        let mut outputs = vec![
+            ("engine".to_string(), damage.engine),
+            ("steering".to_string(), damage.steering),
+            ("impacts".to_string(), damage.impacts as f64),
+        ];
+        for (wheel, (corner, _, _)) in damage.wheels.iter().zip(&damage.wheel_entities) {
+            outputs.push((format!("wheel_{}", corner), *wheel));
+        }
+        damage.outputs.extend(outputs);
+    }
+}
+
+// Pushes the body mesh in at the dents. The mesh is copied on the first dent,
+// so cars sharing a mesh file are dented on their own.
+pub fn damage_mesh_system(
+    meshes: Option<ResMut<Assets<Mesh>>>,
+    mut cars: Query<(&mut Damage, &Children)>,
+    mut bodies: Query<(&mut Handle<Mesh>, &Transform)>,
+    mut originals: Local<HashMap<Entity, Vec<[f32; 3]>>>,
+) {
+    let mut meshes = match meshes {
+        Some(meshes) => meshes,
+        None => return,
+    };
+    for (mut damage, children) in cars.iter_mut() {
+        if damage.meshed_dents == damage.dents.len() {
+            continue;
+        }
+        damage.meshed_dents = damage.dents.len();
+        for child in children.iter() {
+            let (mut handle, transform) = match bodies.get_mut(*child) {
+                Ok(body) => body,
+                Err(_) => continue,
+            };
+            let mut mesh = match meshes.get(&handle) {
+                Some(mesh) => mesh.clone(),
+                None => continue,
+            };
+            let positions = match mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION) {
+                Some(VertexAttributeValues::Float32x3(positions)) => positions,
+                _ => continue,
+            };
+            let original = originals.entry(*child).or_insert_with(|| positions.clone());
+
+            for (position, original) in positions.iter_mut().zip(original.iter()) {
+                // vertex in chassis coordinates
+                let vertex = transform.transform_point(Vec3::from(*original));
+                let vertex = Vector::new(vertex.x as f64, vertex.y as f64, vertex.z as f64);
+                let mut offset = Vector::zeros();
+                for dent in damage.dents.iter() {
+                    let falloff = (1. - (vertex - dent.position).norm() / dent.radius).max(0.);
+                    offset += dent.direction * dent.depth * falloff.powi(2);
+                }
+                let offset = Vec3::new(offset.x as f32, offset.y as f32, offset.z as f32);
+                *position = (Vec3::from(*original) + transform.rotation.inverse() * offset).into();
+            }
+            *handle = meshes.add(mesh);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+};
+use rigid_body::diagnostics::PhysicsDiagnosticsPlugin;
+
+use crate::tire::PointTire;
+
+// The physics diagnostics, with the number of tire points touching the terrain
+// (`tire_contact_points`, over all tires). Add it after `RigidBodyPlugin`.
+pub struct CarDiagnosticsPlugin;
+
+impl CarDiagnosticsPlugin {
+    pub const TIRE_CONTACTS: DiagnosticId =
+        DiagnosticId::from_u128(160245380327845561148802457516301865023);
+}
+
+impl Plugin for CarDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(PhysicsDiagnosticsPlugin)
+            .register_diagnostic(Diagnostic::new(
+                Self::TIRE_CONTACTS,
+                "tire_contact_points",
+                20,
+            ))
+            .add_systems(Update, tire_diagnostics_system);
+    }
+}
+
+fn tire_diagnostics_system(mut diagnostics: Diagnostics, tires: Query<&PointTire>) {
+    diagnostics.add_measurement(CarDiagnosticsPlugin::TIRE_CONTACTS, || {
+        tires
+            .iter()
+            .map(|tire| tire.contact_points())
+            .sum::<usize>() as f64
+    });
+}
@@ -0,0 +1,72 @@
+use bevy::prelude::*;
+
+#[derive(Clone)]
+pub enum DifferentialType {
+    Open,
+    LimitedSlip { preload: f64, bias_ratio: f64 }, // bias ratio: max torque ratio between the outputs
+    Locked,
+}
+
+// Splits the input torque between two outputs (left and right wheel, or front
+// and rear axle). The outputs are coupled by a locking torque that opposes their
+// speed difference, limited by the differential type.
+#[derive(Clone)]
+pub struct Differential {
+    pub differential_type: DifferentialType,
+    pub split: f64,   // fraction of the input torque sent to the first output
+    pub damping: f64, // locking torque per unit speed difference
+}
+
+impl Differential {
+    pub fn new(differential_type: DifferentialType) -> Self {
+        Self {
+            differential_type,
+            split: 0.5,
+            damping: 500.,
+        }
+    }
+
+    pub fn with_split(mut self, split: f64) -> Self {
+        self.split = split;
+        self
+    }
+
+    pub fn with_damping(mut self, damping: f64) -> Self {
+        self.damping = damping;
+        self
+    }
+
+    // maximum locking torque for a given input torque
+    pub fn locking_capacity(&self, torque: f64) -> f64 {
+        match self.differential_type {
+            DifferentialType::Open => 0.,
+            DifferentialType::LimitedSlip {
+                preload,
+                bias_ratio,
+            } => preload + torque.abs() * (bias_ratio - 1.) / (bias_ratio + 1.),
+            DifferentialType::Locked => f64::INFINITY,
+        }
+    }
+
+    // speed of the input (weighted by the torque split, so power is conserved)
+    pub fn input_speed(&self, speeds: [f64; 2]) -> f64 {
+        self.split * speeds[0] + (1. - self.split) * speeds[1]
+    }
+
+    // output torques for the input torque, the faster output receives less torque
+    pub fn output_torques(&self, torque: f64, speeds: [f64; 2]) -> [f64; 2] {
+        let capacity = self.locking_capacity(torque);
+        let locking = (self.damping * (speeds[0] - speeds[1])).clamp(-capacity, capacity);
+        [
+            self.split * torque - locking / 2.,
+            (1. - self.split) * torque + locking / 2.,
+        ]
+    }
+}
+
+// A driven axle, the differential splits torque between the left and right wheel
+#[derive(Clone)]
+pub struct Axle {
+    pub wheels: [Entity; 2],
+    pub differential: Differential,
+}
@@ -0,0 +1,220 @@
+use bevy::prelude::*;
+use grid_terrain::{
+    props::{Prop, PropShape},
+    GridTerrain,
+};
+use rigid_body::{joint::Joint, sva::Vector};
+
+use crate::{ai::planar_pose, build::CarEntities};
+
+// Drift challenge scoring, on the chassis entity. Points build up while the car
+// slides with the body slip angle above `min_angle`, faster with a larger angle,
+// more speed and when close to a wall. Each `combo_time` of drifting, and each
+// change of drift direction, raises the multiplier. The combo is banked into the
+// total after `chain_time` without drifting, and lost if the car spins.
+#[derive(Component)]
+pub struct DriftScore {
+    pub min_angle: f64,     // body slip angle (rad) where a drift starts
+    pub max_angle: f64,     // beyond this the car spun out
+    pub min_speed: f64,     // (m/s)
+    pub points_rate: f64,   // points per second per m/s at 1 rad slip angle
+    pub wall_distance: f64, // walls closer than this (m) add a bonus
+    pub wall_bonus: f64,    // extra points scale touching a wall
+    pub combo_time: f64,    // drift time (s) for each multiplier step
+    pub max_combo: u32,
+    pub chain_time: f64, // time (s) between drifts that keeps the combo going
+    pub total: f64,
+    pub best: f64,    // best banked combo
+    pub combo: u32,   // current multiplier
+    pub pending: f64, // points of the current combo, before the multiplier
+    pub angle: f64,   // current body slip angle (rad)
+    pub wall_factor: f64,
+    combo_timer: f64,
+    idle_timer: f64,
+    direction: f64, // sign of the slip angle of the current drift
+}
+
+impl Default for DriftScore {
+    fn default() -> Self {
+        Self {
+            min_angle: 10_f64.to_radians(),
+            max_angle: 100_f64.to_radians(),
+            min_speed: 8.,
+            points_rate: 10.,
+            wall_distance: 3.,
+            wall_bonus: 2.,
+            combo_time: 2.,
+            max_combo: 5,
+            chain_time: 1.5,
+            total: 0.,
+            best: 0.,
+            combo: 1,
+            pending: 0.,
+            angle: 0.,
+            wall_factor: 1.,
+            combo_timer: 0.,
+            idle_timer: 0.,
+            direction: 0.,
+        }
+    }
+}
+
+impl DriftScore {
+    fn bank(&mut self) {
+        let points = self.pending * self.combo as f64;
+        if points > 0. {
+            info!("drift banked: {:.0} x{}", self.pending, self.combo);
+        }
+        self.total += points;
+        self.best = self.best.max(points);
+        self.reset_combo();
+    }
+
+    fn reset_combo(&mut self) {
+        self.pending = 0.;
+        self.combo = 1;
+        self.combo_timer = 0.;
+        self.idle_timer = 0.;
+        self.direction = 0.;
+    }
+}
+
+#[derive(Component)]
+struct DriftText;
+
+pub fn drift_setup(app: &mut App) {
+    app.add_systems(Startup, drift_hud_startup_system)
+        .add_systems(Update, (drift_score_system, drift_hud_system).chain());
+}
+
+fn drift_score_system(
+    time: Res<Time>,
+    terrain: Option<Res<GridTerrain>>,
+    mut cars: Query<(&Joint, &mut DriftScore)>,
+) {
+    let dt = time.delta_seconds_f64();
+    for (joint, mut score) in cars.iter_mut() {
+        // chassis velocity is in chassis coordinates, x is forward
+        let velocity = joint.v.v;
+        let speed = (velocity.x.powi(2) + velocity.y.powi(2)).sqrt();
+        score.angle = velocity.y.atan2(velocity.x);
+        let angle = score.angle.abs();
+
+        if speed > score.min_speed && angle > score.max_angle {
+            if score.pending > 0. {
+                info!("spun out, drift lost");
+            }
+            score.reset_combo();
+            continue;
+        }
+
+        let (position, _) = planar_pose(joint);
+        let wall = match &terrain {
+            Some(terrain) => terrain
+                .props()
+                .iter()
+                .filter_map(|prop| barrier_distance(prop, position))
+                .fold(f64::INFINITY, f64::min),
+            None => f64::INFINITY,
+        };
+        score.wall_factor = 1. + score.wall_bonus * (1. - wall / score.wall_distance).clamp(0., 1.);
+
+        if speed > score.min_speed && angle > score.min_angle {
+            // a change of direction (a transition) raises the combo
+            let direction = score.angle.signum();
+            if score.direction != 0. && direction != score.direction {
+                score.combo = (score.combo + 1).min(score.max_combo);
+                score.combo_timer = 0.;
+            }
+            score.direction = direction;
+            score.idle_timer = 0.;
+
+            score.combo_timer += dt;
+            if score.combo_timer > score.combo_time {
+                score.combo = (score.combo + 1).min(score.max_combo);
+                score.combo_timer = 0.;
+            }
+            score.pending += score.points_rate * angle.min(1.) * speed * score.wall_factor * dt;
+        } else if score.pending > 0. {
+            score.idle_timer += dt;
+            if score.idle_timer > score.chain_time {
+                score.bank();
+            }
+        }
+    }
+}
+
+// distance (m) from a point to the side of a wall or a tire stack
+fn barrier_distance(prop: &Prop, point: [f64; 2]) -> Option<f64> {
+    let relative = Vector::new(point[0] - prop.position.x, point[1] - prop.position.y, 0.);
+    match prop.shape {
+        PropShape::Wall {
+            length, thickness, ..
+        } => {
+            // point in the wall coordinates, x along the wall
+            let (sin, cos) = prop.yaw.sin_cos();
+            let along = (cos * relative.x + sin * relative.y).abs() - length / 2.;
+            let across = (-sin * relative.x + cos * relative.y).abs() - thickness / 2.;
+            Some((along.max(0.).powi(2) + across.max(0.).powi(2)).sqrt())
+        }
+        PropShape::Tire { radius, .. } => Some((relative.norm() - radius).max(0.)),
+        PropShape::Cone { .. } => None,
+    }
+}
+
+fn drift_hud_startup_system(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.),
+                width: Val::Percent(100.),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 28.,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                )
+                .with_background_color(Color::rgba(0., 0., 0., 0.5)),
+                DriftText,
+            ));
+        });
+}
+
+fn drift_hud_system(
+    car: Option<Res<CarEntities>>,
+    scores: Query<&DriftScore>,
+    mut text: Query<&mut Text, With<DriftText>>,
+) {
+    let score = match car.and_then(|car| scores.get(car.chassis).ok()) {
+        Some(score) => score,
+        None => return,
+    };
+    let mut lines = vec![format!("score {:.0}   best {:.0}", score.total, score.best)];
+    if score.pending > 0. {
+        let wall = if score.wall_factor > 1.5 {
+            "  wall bonus"
+        } else {
+            ""
+        };
+        lines.push(format!(
+            "drift {:.0} x{}   {:.0}°{}",
+            score.pending,
+            score.combo,
+            score.angle.abs().to_degrees(),
+            wall
+        ));
+    }
+    if let Ok(mut text) = text.get_single_mut() {
+        text.sections[0].value = lines.join("\n");
+    }
+}
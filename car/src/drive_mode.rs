@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use rigid_body::physics_thread::PhysicsInput;
+
+use crate::{
+    abs::Abs, build::CarEntities, control::CarControl, physics::SuspensionComponent,
+    torque_vectoring::TorqueVectoring,
+};
+
+const BUTTON_COLOR: Color = Color::rgba(0., 0., 0., 0.5);
+const SELECTED_COLOR: Color = Color::rgba(0.9, 0.5, 0.1, 0.8);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DriveMode {
+    Eco,
+    Normal,
+    Sport,
+}
+
+impl DriveMode {
+    pub const ALL: [DriveMode; 3] = [DriveMode::Eco, DriveMode::Normal, DriveMode::Sport];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DriveMode::Eco => "eco",
+            DriveMode::Normal => "normal",
+            DriveMode::Sport => "sport",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            DriveMode::Eco => DriveMode::Normal,
+            DriveMode::Normal => DriveMode::Sport,
+            DriveMode::Sport => DriveMode::Eco,
+        }
+    }
+}
+
+// Settings changed together by a drive mode. The scales are relative to the
+// car as built, so the normal mode leaves the car unchanged. The stability aids
+// of the car are the ABS and torque vectoring, the mode sets their thresholds.
+#[derive(Clone)]
+pub struct DriveModeProfile {
+    pub throttle_exponent: f64, // pedal to throttle curve, above 1 is progressive
+    pub max_throttle: f64,      // throttle at full pedal
+    pub steering: f32,          // scale on the driver steering input
+    pub damping: f64,           // scale on the damper command (passive or active)
+    pub abs_slip: f64,          // scale on the ABS target slip
+    pub vectoring: f64,         // scale on the torque vectoring gain
+}
+
+impl DriveModeProfile {
+    pub fn throttle(&self, pedal: f64) -> f64 {
+        self.max_throttle * pedal.clamp(0., 1.).powf(self.throttle_exponent)
+    }
+}
+
+// throttle pedal of a car, remapped by its drive mode
+pub fn throttle_pedal(control: &CarControl, modes: Option<&DriveModes>) -> f64 {
+    let pedal = control.throttle as f64;
+    match modes {
+        Some(modes) => modes.profile().throttle(pedal),
+        None => pedal,
+    }
+}
+
+// Drive mode of a car, on the chassis entity. The mode is cycled with the drive
+// mode binding (`M` by default) or picked with the buttons of `drive_mode_setup`.
+#[derive(Component, Clone)]
+pub struct DriveModes {
+    pub mode: DriveMode,
+    pub eco: DriveModeProfile,
+    pub normal: DriveModeProfile,
+    pub sport: DriveModeProfile,
+    suspensions: Vec<Entity>,
+    wheels: Vec<Entity>,
+    applied: Option<DriveMode>,
+    base_abs_slip: HashMap<Entity, f64>,
+    base_vectoring: Option<f64>,
+}
+
+impl Default for DriveModes {
+    fn default() -> Self {
+        Self {
+            mode: DriveMode::Normal,
+            eco: DriveModeProfile {
+                throttle_exponent: 1.6,
+                max_throttle: 0.8,
+                steering: 0.9,
+                damping: 0.8,
+                abs_slip: 0.8,
+                vectoring: 0.5,
+            },
+            normal: DriveModeProfile {
+                throttle_exponent: 1.,
+                max_throttle: 1.,
+                steering: 1.,
+                damping: 1.,
+                abs_slip: 1.,
+                vectoring: 1.,
+            },
+            sport: DriveModeProfile {
+                throttle_exponent: 0.7,
+                max_throttle: 1.,
+                steering: 1.15,
+                damping: 1.4,
+                abs_slip: 1.4,
+                vectoring: 1.5,
+            },
+            suspensions: Vec::new(),
+            wheels: Vec::new(),
+            applied: None,
+            base_abs_slip: HashMap::new(),
+            base_vectoring: None,
+        }
+    }
+}
+
+impl DriveModes {
+    // the suspensions and braked wheels the modes adjust
+    pub fn with_parts(mut self, suspensions: Vec<Entity>, wheels: Vec<Entity>) -> Self {
+        self.suspensions = suspensions;
+        self.wheels = wheels;
+        self
+    }
+
+    pub fn profile(&self) -> &DriveModeProfile {
+        match self.mode {
+            DriveMode::Eco => &self.eco,
+            DriveMode::Normal => &self.normal,
+            DriveMode::Sport => &self.sport,
+        }
+    }
+
+    pub fn profile_mut(&mut self) -> &mut DriveModeProfile {
+        match self.mode {
+            DriveMode::Eco => &mut self.eco,
+            DriveMode::Normal => &mut self.normal,
+            DriveMode::Sport => &mut self.sport,
+        }
+    }
+}
+
+// the mode picked in the render world
+impl PhysicsInput for DriveModes {
+    type Data = DriveMode;
+
+    fn send(&mut self) -> DriveMode {
+        self.mode
+    }
+
+    fn receive(&mut self, mode: &DriveMode, _fresh: bool) {
+        self.mode = *mode;
+    }
+}
+
+pub fn drive_mode_system(
+    mut cars: Query<(
+        &mut CarControl,
+        &mut DriveModes,
+        Option<&mut TorqueVectoring>,
+    )>,
+    mut suspensions: Query<&mut SuspensionComponent>,
+    mut abs: Query<&mut Abs>,
+) {
+    for (mut control, mut modes, vectoring) in cars.iter_mut() {
+        if control.next_drive_mode {
+            control.next_drive_mode = false;
+            modes.mode = modes.mode.next();
+        }
+        let modes = modes.as_mut();
+        let profile = modes.profile().clone();
+
+        // the dampers are set every update, a reloaded car setup replaces them
+        for entity in modes.suspensions.iter() {
+            if let Ok(mut suspension) = suspensions.get_mut(*entity) {
+                if suspension.damping_scale() != profile.damping {
+                    suspension.set_damping_scale(profile.damping);
+                }
+            }
+        }
+
+        if modes.applied == Some(modes.mode) {
+            continue;
+        }
+        modes.applied = Some(modes.mode);
+        info!("drive mode {}", modes.mode.name());
+
+        for entity in modes.wheels.iter() {
+            if let Ok(mut abs) = abs.get_mut(*entity) {
+                let base = *modes
+                    .base_abs_slip
+                    .entry(*entity)
+                    .or_insert(abs.target_slip);
+                abs.target_slip = base * profile.abs_slip;
+            }
+        }
+        if let Some(mut vectoring) = vectoring {
+            let base = *modes.base_vectoring.get_or_insert(vectoring.gain);
+            vectoring.gain = base * profile.vectoring;
+        }
+    }
+}
+
+#[derive(Component)]
+struct DriveModeButton(DriveMode);
+
+// Buttons to pick the drive mode of the car in `CarEntities`
+pub fn drive_mode_setup(app: &mut App) {
+    app.add_systems(Startup, drive_mode_button_startup_system)
+        .add_systems(Update, drive_mode_button_system);
+}
+
+fn drive_mode_button_startup_system(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.),
+                bottom: Val::Px(10.),
+                column_gap: Val::Px(4.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|row| {
+            for mode in DriveMode::ALL {
+                row.spawn((
+                    ButtonBundle {
+                        style: Style {
+                            padding: UiRect::axes(Val::Px(10.), Val::Px(6.)),
+                            ..default()
+                        },
+                        background_color: BUTTON_COLOR.into(),
+                        ..default()
+                    },
+                    DriveModeButton(mode),
+                ))
+                .with_children(|button| {
+                    button.spawn(TextBundle::from_section(
+                        mode.name(),
+                        TextStyle {
+                            font_size: 18.,
+                            color: Color::WHITE,
+                            ..default()
+                        },
+                    ));
+                });
+            }
+        });
+}
+
+fn drive_mode_button_system(
+    car: Option<Res<CarEntities>>,
+    mut cars: Query<&mut DriveModes>,
+    mut buttons: Query<(&Interaction, &DriveModeButton, &mut BackgroundColor)>,
+) {
+    let mut modes = match car.and_then(|car| cars.get_mut(car.chassis).ok()) {
+        Some(modes) => modes,
+        None => return,
+    };
+    for (interaction, button, _) in buttons.iter() {
+        if *interaction == Interaction::Pressed && modes.mode != button.0 {
+            modes.mode = button.0;
+        }
+    }
+    for (_, button, mut color) in buttons.iter_mut() {
+        let selected = if button.0 == modes.mode {
+            SELECTED_COLOR
+        } else {
+            BUTTON_COLOR
+        };
+        if color.0 != selected {
+            color.0 = selected;
+        }
+    }
+}
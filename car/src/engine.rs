@@ -0,0 +1,273 @@
+use std::{collections::HashMap, f64::consts::PI};
+
+use bevy::prelude::*;
+
+use rigid_body::{joint::Joint, physics_thread::Mirrored};
+
+use crate::{
+    differential::{Axle, Differential},
+    drive_mode::{throttle_pedal, DriveModes},
+    interpolate::Interpolator1D,
+    transmission::Transmission,
+    turbo::Turbo,
+};
+
+use super::control::{CarControl, CarPart};
+
+// Engine attached to a rotational joint, so the engine speed is the joint speed
+// and is integrated with the rest of the car. All speeds are in rad/s.
+#[derive(Component, Clone)]
+pub struct Engine {
+    pub torque_curve: Interpolator1D, // full throttle torque vs engine speed
+    pub throttle_map: Interpolator1D, // throttle opening vs pedal position
+    pub inertia: f64,
+    pub friction: f64, // internal friction torque per unit speed
+    pub braking: f64,  // additional drag torque per unit speed with the throttle closed
+    pub idle_speed: f64,
+    pub redline: f64,
+    pub out_of_fuel: bool,
+    pub power_factor: f64, // scale on the combustion torque, lowered by damage
+    pub outputs: HashMap<String, f64>,
+}
+
+impl Engine {
+    pub fn new(
+        speeds: Vec<f64>,
+        torques: Vec<f64>,
+        inertia: f64,
+        idle_speed: f64,
+        redline: f64,
+    ) -> Self {
+        Self {
+            torque_curve: Interpolator1D::new(speeds, torques),
+            throttle_map: Interpolator1D::new(vec![0., 1.], vec![0., 1.]),
+            inertia,
+            friction: 0.02,
+            braking: 0.1,
+            idle_speed,
+            redline,
+            out_of_fuel: false,
+            power_factor: 1.,
+            outputs: HashMap::new(),
+        }
+    }
+
+    pub fn with_throttle_map(mut self, pedal: Vec<f64>, throttle: Vec<f64>) -> Self {
+        self.throttle_map = Interpolator1D::new(pedal, throttle);
+        self
+    }
+
+    pub fn throttle(&self, pedal: f64, speed: f64) -> f64 {
+        // rev limiter
+        if speed > self.redline || self.out_of_fuel {
+            return 0.;
+        }
+        // idle governor opens the throttle as the engine drops below idle
+        let governor = ((self.idle_speed - speed) / (0.1 * self.idle_speed)).clamp(0., 1.);
+        self.throttle_map.interpolate(pedal).max(governor)
+    }
+
+    pub fn with_braking(mut self, braking: f64) -> Self {
+        self.braking = braking;
+        self
+    }
+
+    // torque_factor scales the combustion torque (boost)
+    pub fn combustion_torque(&self, throttle: f64, speed: f64, torque_factor: f64) -> f64 {
+        throttle * self.torque_curve.interpolate(speed) * torque_factor * self.power_factor
+    }
+
+    pub fn torque(&self, throttle: f64, speed: f64, torque_factor: f64) -> f64 {
+        // pumping losses (engine braking) fade out as the throttle opens
+        let drag = self.friction + (1. - throttle) * self.braking;
+        self.combustion_torque(throttle, speed, torque_factor) - drag * speed
+    }
+}
+
+impl Mirrored for Engine {
+    type Data = Engine;
+
+    fn extract(&self) -> Engine {
+        self.clone()
+    }
+
+    fn apply(&mut self, data: &Engine) {
+        self.clone_from(data);
+    }
+}
+
+pub fn engine_system(
+    mut joints: Query<(&mut Joint, &mut Engine, Option<&Turbo>, &CarPart)>,
+    controls: Query<(&CarControl, Option<&DriveModes>)>,
+) {
+    for (mut joint, mut engine, turbo, part) in joints.iter_mut() {
+        let (control, modes) = match controls.get(part.0) {
+            Ok(control) => control,
+            Err(_) => continue,
+        };
+        let speed = joint.qd;
+        let throttle = engine.throttle(throttle_pedal(control, modes), speed);
+        let torque_factor = turbo.map_or(1., |turbo| turbo.torque_factor());
+        let torque = engine.torque(throttle, speed, torque_factor);
+        joint.tau += torque;
+        set_output(&mut engine.outputs, "rpm", speed * 30. / PI);
+        set_output(&mut engine.outputs, "throttle", throttle);
+        set_output(&mut engine.outputs, "torque", torque);
+    }
+}
+
+// runs at every solver stage, so the keys are only allocated the first time
+fn set_output(outputs: &mut HashMap<String, f64>, name: &str, value: f64) {
+    match outputs.get_mut(name) {
+        Some(output) => *output = value,
+        None => {
+            outputs.insert(name.to_string(), value);
+        }
+    }
+}
+
+// Couples the engine to the driven axles through the transmission. With more
+// than one axle, center differentials split the torque: the first between the
+// first axle and the ones behind it, the next between the second axle and the
+// ones behind it, and so on. The clutch
+// transmits torque proportional to the slip between engine and wheels, up to its
+// capacity. It engages automatically as the engine speeds up above idle (so the
+// engine doesn't stall), and is released with the clutch pedal.
+#[derive(Component, Clone)]
+pub struct Driveline {
+    pub axles: Vec<Axle>,
+    pub centers: Vec<Differential>, // one less than the axles
+    pub damping: f64,               // clutch torque per unit slip (engine side)
+    pub capacity: f64,              // maximum clutch torque when fully engaged
+    pub engagement: [f64; 2],       // engine speeds where the clutch starts and finishes engaging
+}
+
+impl Driveline {
+    pub fn new(
+        axles: Vec<Axle>,
+        centers: Vec<Differential>,
+        damping: f64,
+        capacity: f64,
+        engagement: [f64; 2],
+    ) -> Self {
+        assert!(!axles.is_empty() && centers.len() == axles.len() - 1);
+        Self {
+            axles,
+            centers,
+            damping,
+            capacity,
+            engagement,
+        }
+    }
+
+    pub fn engagement(&self, engine_speed: f64, clutch_pedal: f64) -> f64 {
+        let automatic = ((engine_speed - self.engagement[0])
+            / (self.engagement[1] - self.engagement[0]))
+            .clamp(0., 1.);
+        automatic * (1. - clutch_pedal).clamp(0., 1.)
+    }
+
+    // torque transmitted by the clutch (engine side)
+    pub fn clutch_torque(&self, slip: f64, engagement: f64) -> f64 {
+        let capacity = engagement * self.capacity;
+        (engagement * self.damping * slip).clamp(-capacity, capacity)
+    }
+}
+
+// speeds and torques of the driveline evaluation, kept between the solver stages
+// so they don't allocate
+#[derive(Default)]
+pub struct DrivelineBuffers {
+    wheel_speeds: Vec<[f64; 2]>,
+    axle_speeds: Vec<f64>,
+    behind_speeds: Vec<f64>,
+    axle_torques: Vec<f64>,
+}
+
+pub fn driveline_system(
+    drivelines: Query<(Entity, &Driveline, &Transmission, &CarPart)>,
+    mut joints: Query<&mut Joint>,
+    controls: Query<&CarControl>,
+    mut buffers: Local<DrivelineBuffers>,
+) {
+    let DrivelineBuffers {
+        wheel_speeds,
+        axle_speeds,
+        behind_speeds,
+        axle_torques,
+    } = &mut *buffers;
+    for (engine_entity, driveline, transmission, part) in drivelines.iter() {
+        let control = match controls.get(part.0) {
+            Ok(control) => control,
+            Err(_) => continue,
+        };
+        // torque is interrupted while shifting
+        if transmission.is_shifting() {
+            continue;
+        }
+        let ratio = transmission.ratio();
+        let engine_speed = match joints.get(engine_entity) {
+            Ok(joint) => joint.qd,
+            Err(_) => continue,
+        };
+        wheel_speeds.clear();
+        for axle in driveline.axles.iter() {
+            match joints.get_many(axle.wheels) {
+                Ok(wheels) => wheel_speeds.push(wheels.map(|joint| joint.qd)),
+                Err(_) => break,
+            }
+        }
+        if wheel_speeds.len() != driveline.axles.len() {
+            continue;
+        }
+        axle_speeds.clear();
+        axle_speeds.extend(
+            driveline
+                .axles
+                .iter()
+                .zip(wheel_speeds.iter())
+                .map(|(axle, speeds)| axle.differential.input_speed(*speeds)),
+        );
+        // input speed of each center differential's rear output, from the back
+        behind_speeds.clear();
+        behind_speeds.push(axle_speeds[axle_speeds.len() - 1]);
+        for (center, speed) in driveline.centers.iter().zip(axle_speeds.iter()).rev() {
+            let behind = behind_speeds[behind_speeds.len() - 1];
+            behind_speeds.push(center.input_speed([*speed, behind]));
+        }
+        behind_speeds.reverse();
+        let wheel_speed = behind_speeds[0];
+
+        let slip = engine_speed - ratio * wheel_speed;
+        let engagement = driveline.engagement(engine_speed, control.clutch as f64);
+        let torque = driveline.clutch_torque(slip, engagement);
+
+        if let Ok(mut engine) = joints.get_mut(engine_entity) {
+            engine.tau -= torque;
+        }
+
+        // torque at the input of each axle differential
+        axle_torques.clear();
+        let mut behind_torque = torque * ratio;
+        for (ind, center) in driveline.centers.iter().enumerate() {
+            let [axle_torque, behind] =
+                center.output_torques(behind_torque, [axle_speeds[ind], behind_speeds[ind + 1]]);
+            axle_torques.push(axle_torque);
+            behind_torque = behind;
+        }
+        axle_torques.push(behind_torque);
+        for ((axle, speeds), axle_torque) in driveline
+            .axles
+            .iter()
+            .zip(wheel_speeds.iter())
+            .zip(axle_torques.iter())
+        {
+            let wheel_torques = axle.differential.output_torques(*axle_torque, *speeds);
+            for (wheel, wheel_torque) in axle.wheels.iter().zip(wheel_torques) {
+                if let Ok(mut joint) = joints.get_mut(*wheel) {
+                    joint.tau += wheel_torque;
+                }
+            }
+        }
+    }
+}
@@ -1,55 +1,86 @@
 use std::f32::consts::PI;
 
-use bevy::{
-    pbr::{CascadeShadowConfigBuilder, DirectionalLightShadowMap},
-    prelude::*,
-};
+use bevy::prelude::*;
+use bevy_integrator::SimSeed;
 
+use cameras::top_down::TopDownCamera;
 use grid_terrain::{
-    examples::{steps, table_top, wave},
-    GridTerrain,
+    coloring::TerrainColoring,
+    examples::{
+        circuit, drift_arena, hill_climb_road, icy_patches, rock_crawl, slalom, steps, stream,
+        table_top, wave,
+    },
+    props::Prop,
+    GridElement, GridTerrain,
 };
 
+use crate::time_of_day::Sun;
+
+// fits the top-down map view to the terrain and its props
+pub fn top_down_area_system(
+    terrain: Option<Res<GridTerrain>>,
+    mut cameras: Query<&mut TopDownCamera>,
+) {
+    let terrain = match terrain {
+        Some(terrain) if terrain.is_changed() => terrain,
+        _ => return,
+    };
+    let [min, max] = terrain.bounds();
+    for mut camera in cameras.iter_mut() {
+        camera.area = Rect::new(min[0] as f32, min[1] as f32, max[0] as f32, max[1] as f32);
+    }
+}
+
+// the distances at which the terrain meshes switch to a coarser level of detail
+const LOD_DISTANCES: [f64; 3] = [60., 120., 240.];
+// the resolution of the minimap
+const MINIMAP_PIXELS_PER_METER: f64 = 2.;
+
+// the meshes of the terrain under a new parent, which is returned, and the
+// terrain and its minimap as resources
+pub(crate) fn spawn_terrain(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    images: &mut ResMut<Assets<Image>>,
+    grid_terrain: GridTerrain,
+) -> Entity {
+    let grid_terrain = grid_terrain
+        .with_coloring(TerrainColoring::default())
+        .with_lod(LOD_DISTANCES.to_vec());
+    let empty_parent = commands.spawn(SpatialBundle::default()).id();
+
+    grid_terrain.build_meshes(commands, meshes, materials, empty_parent);
+    commands.insert_resource(grid_terrain.build_minimap(images, MINIMAP_PIXELS_PER_METER));
+    commands.insert_resource(grid_terrain);
+    empty_parent
+}
+
 pub fn build_environment(
     mut commands: Commands,
+    seed: Res<SimSeed>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
 ) {
-    commands.insert_resource(AmbientLight {
-        color: Color::rgb(0.9, 0.9, 1.0),
-        brightness: 0.4,
-    });
-
-    commands.spawn(DirectionalLightBundle {
-        directional_light: DirectionalLight {
-            shadows_enabled: true,
-            illuminance: 10000.0, // lux
-            shadow_depth_bias: 0.3,
-            shadow_normal_bias: 1.0,
-            ..default()
-        },
-        transform: Transform {
-            translation: Vec3::new(0.0, 0.0, 10.0),
-            rotation: Quat::from_rotation_x(-PI / 4.) * Quat::from_rotation_y(-PI / 4.),
-
-            ..default()
-        },
-        cascade_shadow_config: CascadeShadowConfigBuilder {
-            num_cascades: 4,
-            minimum_distance: 1.,
-            maximum_distance: 300.0,
-            first_cascade_far_bound: 5.0,
-            overlap_proportion: 0.3,
-        }
-        .into(),
-
-        ..default()
-    });
-
-    commands.insert_resource(DirectionalLightShadowMap { size: 4 * 1024 });
+    build_lights(&mut commands);
 
     let size = 20.0; // must be the same for all grid elements
+    let (elements, props) = test_grid(size, &seed);
+    let grid_terrain = GridTerrain::new(elements, [size, size])
+        .with_blend_margin(0.1)
+        .with_props(props);
+    spawn_terrain(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        &mut images,
+        grid_terrain,
+    );
+}
 
+// the table top, waves, steps, stream and icy patches, with a slalom and a barrier
+pub(crate) fn test_grid(size: f64, seed: &SimSeed) -> (Vec<Vec<Box<dyn GridElement>>>, Vec<Prop>) {
     let height = 2.;
     let table_elements = table_top(size, height);
 
@@ -59,14 +90,135 @@ pub fn build_environment(
 
     let step_elements = steps(size, vec![0.2, 0.4, 0.6]);
 
-    // merge the two grid terrains
+    let stream_elements = stream(size, 0.8, 2);
+
+    let icy_elements = icy_patches(size, seed.stream("icy_patches"));
+
+    // merge the grid terrains
     let mut elements = table_elements;
     elements.extend(wave_elements);
     elements.extend(step_elements);
+    elements.extend(stream_elements);
+    elements.extend(icy_elements);
 
-    let grid_terrain = GridTerrain::new(elements, [size, size]);
-    let empty_parent = commands.spawn(SpatialBundle::default()).id();
+    // slalom and a barrier next to the grid
+    let mut props = slalom([-80., -15.], 12., 6);
+    props.push(Prop::wall([-90., -30.], [0., -30.], 1.0));
+    (elements, props)
+}
 
-    grid_terrain.build_meshes(&mut commands, &mut meshes, &mut materials, empty_parent);
-    commands.insert_resource(grid_terrain);
+// the circuit, e.g. for the AI driver to lap
+pub fn build_track_environment(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    build_lights(&mut commands);
+
+    let size = 20.0;
+    let grid_terrain = GridTerrain::new(circuit(size), [size, size]);
+    spawn_terrain(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        &mut images,
+        grid_terrain,
+    );
+}
+
+// the circuit terrain alone, without lights or meshes, for headless runs
+pub fn build_track_terrain(mut commands: Commands) {
+    let size = 20.0;
+    commands.insert_resource(GridTerrain::new(circuit(size), [size, size]));
+}
+
+// the switchback road up a hill, for the hill climb
+pub fn build_hill_climb_environment(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    build_lights(&mut commands);
+
+    let size = 20.0;
+    let grid_terrain = GridTerrain::new(hill_climb_road().grid_elements(size), [size, size]);
+    spawn_terrain(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        &mut images,
+        grid_terrain,
+    );
+}
+
+// boulder fields and a ledge, for rock crawling
+pub fn build_rock_crawl_environment(
+    mut commands: Commands,
+    seed: Res<SimSeed>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    build_lights(&mut commands);
+
+    let size = 20.0;
+    let grid_terrain = GridTerrain::new(rock_crawl(size, seed.stream("rock_crawl")), [size, size])
+        .with_blend_margin(0.1);
+    spawn_terrain(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        &mut images,
+        grid_terrain,
+    );
+}
+
+// flat ground enclosed by walls, for the drift challenge
+pub fn build_drift_environment(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    build_lights(&mut commands);
+
+    let size = 20.0;
+    let (elements, props) = drift_arena(size, 6);
+    let grid_terrain = GridTerrain::new(elements, [size, size]).with_props(props);
+    spawn_terrain(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        &mut images,
+        grid_terrain,
+    );
+}
+
+pub(crate) fn build_lights(commands: &mut Commands) {
+    commands.insert_resource(AmbientLight {
+        color: Color::rgb(0.9, 0.9, 1.0),
+        brightness: 0.4,
+    });
+
+    commands
+        .spawn(DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                shadows_enabled: true,
+                illuminance: 10000.0, // lux
+                shadow_depth_bias: 0.3,
+                shadow_normal_bias: 1.0,
+                ..default()
+            },
+            transform: Transform {
+                translation: Vec3::new(0.0, 0.0, 10.0),
+                rotation: Quat::from_rotation_x(-PI / 4.) * Quat::from_rotation_y(-PI / 4.),
+
+                ..default()
+            },
+            // the shadow cascades and map size are `rigid_body::graphics::GraphicsSettings`
+            ..default()
+        })
+        .insert(Sun);
 }
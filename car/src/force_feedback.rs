@@ -0,0 +1,260 @@
+use bevy::{
+    input::gamepad::{GamepadRumbleIntensity, GamepadRumbleRequest},
+    prelude::*,
+    utils::Duration,
+};
+
+use crate::{
+    control::{CarPart, UserControl},
+    kinematics::SuspensionKinematics,
+    tire::PointTire,
+};
+
+// a rumble lasts this long, it is sent again before it runs out (s)
+const RUMBLE_HOLD: f64 = 5.;
+// steps of the rumble intensity, a change smaller than one sends nothing
+const RUMBLE_STEPS: f32 = 20.;
+
+// Force feedback torque for a steering wheel, from the aligning moments of the
+// steered tires. Insert the resource to enable it. With the `force_feedback`
+// feature on Linux the torque drives the constant force effect of the first
+// input device with one (`/dev/input/event*`, or `device`), the way racing
+// wheels take it. Gamepads only rumble, so `rumble` sends the magnitude as a
+// rumble intensity (a direction-less approximation).
+#[derive(Resource, Clone)]
+pub struct ForceFeedback {
+    pub gain: f64,
+    pub max_moment: f64, // aligning moment (Nm) giving the full feedback torque
+    pub rumble: bool,
+    pub device: Option<String>, // event device of the wheel, found when none
+    pub torque: f32,            // -1 to 1, positive turns the wheel left
+}
+
+impl Default for ForceFeedback {
+    fn default() -> Self {
+        Self {
+            gain: 1.,
+            max_moment: 400.,
+            rumble: true,
+            device: None,
+            torque: 0.,
+        }
+    }
+}
+
+// the rumble intensity last sent, and when (s)
+#[derive(Default)]
+pub struct RumbleSent {
+    intensity: f32,
+    time: f64,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn force_feedback_system(
+    time: Res<Time>,
+    force_feedback: Option<ResMut<ForceFeedback>>,
+    tires: Query<&PointTire>,
+    kinematics: Query<(&SuspensionKinematics, &CarPart)>,
+    users: Query<&UserControl>,
+    gamepads: Res<Gamepads>,
+    mut rumble_requests: EventWriter<GamepadRumbleRequest>,
+    mut rumble_sent: Local<RumbleSent>,
+    #[cfg(all(target_os = "linux", feature = "force_feedback"))] mut wheel: Local<
+        evdev::WheelDevice,
+    >,
+) {
+    let mut force_feedback = match force_feedback {
+        Some(force_feedback) => force_feedback,
+        None => return,
+    };
+
+    // only the steered wheels of the player's car push back on the steering wheel
+    let moment: f64 = tires
+        .iter()
+        .filter(|tire| {
+            kinematics
+                .get(tire.joint_entity())
+                .map_or(false, |(kinematics, part)| {
+                    kinematics.steering.is_some() && users.contains(part.0)
+                })
+        })
+        .map(|tire| tire.aligning_moment())
+        .sum();
+    let torque = (force_feedback.gain * moment / force_feedback.max_moment).clamp(-1., 1.);
+    force_feedback.torque = torque as f32;
+
+    #[cfg(all(target_os = "linux", feature = "force_feedback"))]
+    wheel.set_torque(force_feedback.device.as_deref(), force_feedback.torque);
+
+    if force_feedback.rumble {
+        // a rumble adds to the running one, so a change stops it first
+        let intensity = (force_feedback.torque.abs() * RUMBLE_STEPS).round() / RUMBLE_STEPS;
+        let now = time.elapsed_seconds_f64();
+        let running_out = intensity > 0. && now - rumble_sent.time > RUMBLE_HOLD - 1.;
+        if intensity == rumble_sent.intensity && !running_out {
+            return;
+        }
+        *rumble_sent = RumbleSent {
+            intensity,
+            time: now,
+        };
+        for gamepad in gamepads.iter() {
+            rumble_requests.send(GamepadRumbleRequest::Stop { gamepad });
+            if intensity > 0. {
+                rumble_requests.send(GamepadRumbleRequest::Add {
+                    gamepad,
+                    intensity: GamepadRumbleIntensity {
+                        strong_motor: intensity,
+                        weak_motor: 0.,
+                    },
+                    duration: Duration::from_secs_f64(RUMBLE_HOLD),
+                });
+            }
+        }
+    }
+}
+
+// The constant force effect of a Linux event device (the kernel force feedback
+// interface, `linux/input.h`), which the wheel drivers turn into a torque
+#[cfg(all(target_os = "linux", feature = "force_feedback"))]
+mod evdev {
+    use std::{
+        fs::{self, File, OpenOptions},
+        io::Write,
+        mem,
+        os::fd::AsRawFd,
+    };
+
+    use bevy::prelude::*;
+
+    const EV_FF: u16 = 0x15;
+    const FF_CONSTANT: u16 = 0x52;
+    // the force points left, a negative level to the right
+    const DIRECTION_LEFT: u16 = 0x4000;
+
+    // the ioctl request numbers of the event device interface (`_IOC` of ioctl.h)
+    const fn ioc(write: bool, number: u64, size: usize) -> u64 {
+        let direction: u64 = if write { 1 } else { 2 };
+        (direction << 30) | ((size as u64) << 16) | ((b'E' as u64) << 8) | number
+    }
+    const EVIOCSFF: u64 = ioc(true, 0x80, mem::size_of::<libc::ff_effect>());
+    const FF_BITS: usize = 16; // bytes of the force feedback capability bits
+    const EVIOCGBIT_FF: u64 = ioc(false, 0x20 + EV_FF as u64, FF_BITS);
+
+    // the device, opened once, and its effect
+    #[derive(Default)]
+    pub struct WheelDevice {
+        opened: bool,
+        device: Option<(File, i16)>, // the file and the effect id
+        level: i16,                  // last uploaded
+    }
+
+    impl WheelDevice {
+        pub fn set_torque(&mut self, path: Option<&str>, torque: f32) {
+            if !self.opened {
+                self.opened = true;
+                self.device = open(path);
+            }
+            let (file, id) = match &mut self.device {
+                Some(device) => device,
+                None => return,
+            };
+            let level = (torque.clamp(-1., 1.) * i16::MAX as f32) as i16;
+            if level == self.level {
+                return;
+            }
+            self.level = level;
+            // updates the effect in place, it keeps playing
+            if let Err(error) = upload(file, *id, level) {
+                warn!("force feedback stopped: {}", error);
+                self.device = None;
+            }
+        }
+    }
+
+    // the device of `path`, or the first one with a constant force effect, with
+    // the effect uploaded and playing
+    fn open(path: Option<&str>) -> Option<(File, i16)> {
+        let paths = match path {
+            Some(path) => vec![path.into()],
+            None => {
+                let mut paths: Vec<_> = fs::read_dir("/dev/input")
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| {
+                        let name = path.file_name().and_then(|name| name.to_str());
+                        name.is_some_and(|name| name.starts_with("event"))
+                    })
+                    .collect();
+                paths.sort();
+                paths
+            }
+        };
+        for path in paths {
+            let mut file = match OpenOptions::new().read(true).write(true).open(&path) {
+                Ok(file) => file,
+                Err(_) => continue,
+            };
+            if !constant_force(&file) {
+                continue;
+            }
+            let started = upload(&file, -1, 0).and_then(|id| {
+                play(&mut file, id)?;
+                Ok(id)
+            });
+            match started {
+                Ok(id) => {
+                    info!("force feedback on {}", path.display());
+                    return Some((file, id));
+                }
+                Err(error) => warn!("force feedback on {}: {}", path.display(), error),
+            }
+        }
+        warn!("no input device with a constant force effect for the force feedback");
+        None
+    }
+
+    fn constant_force(file: &File) -> bool {
+        let mut bits = [0_u8; FF_BITS];
+        let result = unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGBIT_FF as _, bits.as_mut_ptr()) };
+        let index = FF_CONSTANT as usize;
+        result >= 0 && bits[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    // uploads the constant force effect, a new one for id -1, and returns its id
+    fn upload(file: &File, id: i16, level: i16) -> std::io::Result<i16> {
+        let mut effect: libc::ff_effect = unsafe { mem::zeroed() };
+        effect.type_ = FF_CONSTANT;
+        effect.id = id;
+        effect.direction = DIRECTION_LEFT;
+        effect.replay.length = 0; // until stopped
+        let constant = libc::ff_constant_effect {
+            level,
+            envelope: unsafe { mem::zeroed() },
+        };
+        unsafe {
+            let effect_data = effect.u.as_mut_ptr() as *mut libc::ff_constant_effect;
+            effect_data.write(constant);
+            if libc::ioctl(file.as_raw_fd(), EVIOCSFF as _, &mut effect) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(effect.id)
+    }
+
+    fn play(file: &mut File, id: i16) -> std::io::Result<()> {
+        let mut event: libc::input_event = unsafe { mem::zeroed() };
+        event.type_ = EV_FF;
+        event.code = id as u16;
+        event.value = 1;
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &event as *const libc::input_event as *const u8,
+                mem::size_of::<libc::input_event>(),
+            )
+        };
+        file.write_all(bytes)
+    }
+}
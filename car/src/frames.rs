@@ -0,0 +1,42 @@
+use bevy::{
+    math::{DMat3, DQuat},
+    prelude::*,
+};
+use rigid_body::{
+    joint::Joint,
+    sva::{Vector, Xform},
+};
+use serde_json::{json, Value};
+
+// The joint frames as JSON, for the ROS 2 bridge and the MCAP log
+
+// translation and rotation (x, y, z, w) of a joint frame in its parent frame,
+// from the spatial transform of the parent into the frame
+pub(crate) fn pose(xform: &Xform) -> (Value, Value) {
+    let position = xform.inverse().transform_point(Vector::zeros());
+    let rotation = DMat3::from_cols_slice(xform.rotation.as_slice()).transpose();
+    let rotation = DQuat::from_mat3(&rotation);
+    (
+        json!({"x": position.x, "y": position.y, "z": position.z}),
+        json!({"x": rotation.x, "y": rotation.y, "z": rotation.z, "w": rotation.w}),
+    )
+}
+
+// the joint tree as (parent frame, joint frame, translation, rotation), the base
+// joint is the world frame
+pub(crate) fn joint_frames(
+    joints: &Query<(&Joint, Option<&Parent>)>,
+    world_frame: &str,
+) -> Vec<(String, String, Value, Value)> {
+    let mut frames = Vec::new();
+    for (joint, parent) in joints.iter() {
+        let parent_frame = match parent.and_then(|parent| joints.get(parent.get()).ok()) {
+            Some((parent, _)) if !parent.name.is_empty() => parent.name.clone(),
+            Some(_) => world_frame.to_string(),
+            None => continue,
+        };
+        let (translation, rotation) = pose(&joint.xl);
+        frames.push((parent_frame, joint.name.clone(), translation, rotation));
+    }
+    frames
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_integrator::PhysicsState;
+
+use rigid_body::{
+    joint::Joint,
+    sva::{Inertia, Matrix, Vector},
+};
+
+use crate::{
+    drive_mode::{throttle_pedal, DriveModes},
+    engine::Engine,
+    payload::Payload,
+    turbo::Turbo,
+};
+
+use super::control::CarControl;
+
+// Fuel tank on the chassis joint. Fuel is consumed in proportion to the engine
+// power, and the chassis mass, center of gravity and inertia are updated with
+// the remaining fuel (modeled as a point mass at the tank position) and the
+// payload of the car.
+#[derive(Component, Clone)]
+pub struct FuelTank {
+    pub engine: Entity,
+    pub fuel: f64,                 // kg
+    pub position: Vector,          // tank position in chassis coordinates
+    pub specific_consumption: f64, // kg of fuel per J of engine work
+    pub dry_mass: f64,             // chassis without fuel
+    pub dry_cg: Vector,
+    pub dry_moi: Matrix, // about the dry center of gravity
+    pub outputs: HashMap<String, f64>,
+}
+
+impl FuelTank {
+    pub fn new(
+        engine: Entity,
+        fuel: f64,
+        position: Vector,
+        specific_consumption: f64,
+        dry_mass: f64,
+        dry_cg: Vector,
+        dry_moi: Matrix,
+    ) -> Self {
+        Self {
+            engine,
+            fuel,
+            position,
+            specific_consumption,
+            dry_mass,
+            dry_cg,
+            dry_moi,
+            outputs: HashMap::new(),
+        }
+    }
+
+    // chassis mass, center of gravity and inertia (about the center of
+    // gravity) including the fuel and the payload
+    pub fn mass_properties(&self, payload: Option<&Payload>) -> (f64, Vector, Matrix) {
+        let mut points = vec![(self.fuel, self.position)];
+        if let Some(payload) = payload {
+            points.extend(payload.point_masses());
+        }
+        let mass = self.dry_mass + points.iter().map(|(mass, _)| mass).sum::<f64>();
+        let cg = points
+            .iter()
+            .fold(self.dry_mass * self.dry_cg, |sum, (mass, position)| {
+                sum + *mass * position
+            })
+            / mass;
+        let moi = points.iter().fold(
+            self.dry_moi + parallel_axis(self.dry_mass, self.dry_cg - cg),
+            |moi, (mass, position)| moi + parallel_axis(*mass, position - cg),
+        );
+        (mass, cg, moi)
+    }
+}
+
+// inertia of a point mass at an offset from the center of gravity
+fn parallel_axis(mass: f64, offset: Vector) -> Matrix {
+    mass * (offset.norm_squared() * Matrix::identity() - offset * offset.transpose())
+}
+
+type FuelTankQuery<'a> = (
+    &'a mut FuelTank,
+    &'a mut Joint,
+    &'a CarControl,
+    Option<&'a DriveModes>,
+    Option<&'a mut Payload>,
+);
+
+// runs once per time step (not in the physics schedule), like the transmission
+pub fn fuel_system(
+    fixed_time: Res<FixedTime>,
+    physics_state: Res<PhysicsState<Joint>>,
+    mut engines: Query<(&mut Engine, Option<&Turbo>)>,
+    mut tanks: Query<FuelTankQuery>,
+) {
+    let dt = fixed_time.period.as_secs_f64();
+    for (mut tank, mut joint, control, modes, payload) in tanks.iter_mut() {
+        if let (Ok((mut engine, turbo)), Some(state)) = (
+            engines.get_mut(tank.engine),
+            physics_state.states.get(&tank.engine),
+        ) {
+            let speed = state.qd;
+            let throttle = engine.throttle(throttle_pedal(control, modes), speed);
+            let torque_factor = turbo.map_or(1., |turbo| turbo.torque_factor());
+            let power = engine.combustion_torque(throttle, speed, torque_factor) * speed;
+            tank.fuel = (tank.fuel - power.max(0.) * tank.specific_consumption * dt).max(0.);
+            engine.out_of_fuel = tank.fuel <= 0.;
+        }
+
+        let (mass, cg, moi) = tank.mass_properties(payload.as_deref());
+        joint.i = Inertia::new(mass, cg, moi);
+        let fuel = tank.fuel;
+        tank.outputs.insert("fuel".to_string(), fuel);
+
+        if let Some(mut payload) = payload {
+            let payload_mass = payload.mass();
+            for (name, value) in [
+                ("mass", payload_mass),
+                ("chassis_mass", mass),
+                ("cg_x", cg.x),
+                ("cg_y", cg.y),
+                ("cg_z", cg.z),
+            ] {
+                payload.outputs.insert(name.to_string(), value);
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use bevy::prelude::*;
+use bevy_integrator::{PhysicsState, SimTime, Solver};
+use rigid_body::{
+    graphics::GraphicsSettings,
+    joint::{Base, Joint, JointState},
+    physics_thread::PhysicsId,
+    plugin::RigidBodyPlugin,
+};
+
+use crate::{
+    build::{external_car_startup_system, respawn_car, CarDefinition, CarEntities},
+    control::CarControl,
+    environment::build_track_terrain,
+    setup::simulation_setup,
+};
+
+// A headless simulation of the car on the circuit, stepped by its owner instead
+// of running the app, for the FMU and the Python bindings. The car is driven
+// only through the `CarControl` on its chassis (`control`).
+pub struct HeadlessCar {
+    pub app: App,
+}
+
+impl HeadlessCar {
+    // the car at its initial position, its state initialized but no time step taken
+    pub fn new(car: CarDefinition, dt: f64, start_time: f64) -> Self {
+        let mut app = App::new();
+        app.add_plugins(RigidBodyPlugin {
+            time: SimTime::new(dt, start_time, None),
+            solver: Solver::RK4,
+            simulation_setup: vec![simulation_setup],
+            environment_setup: Vec::new(),
+            name: "headless_car".to_string(),
+            headless: true,
+            graphics: GraphicsSettings::default(),
+            single_threaded_physics: true,
+        })
+        .insert_resource(car)
+        .add_systems(Startup, (external_car_startup_system, build_track_terrain));
+        app.finish();
+        app.cleanup();
+        // runs the startup systems
+        app.update();
+        Self { app }
+    }
+
+    pub fn time(&self) -> f64 {
+        self.app.world.resource::<SimTime>().time()
+    }
+
+    pub fn dt(&self) -> f64 {
+        self.app.world.resource::<SimTime>().dt
+    }
+
+    pub fn car(&self) -> &CarEntities {
+        self.app.world.resource::<CarEntities>()
+    }
+
+    pub fn control(&mut self) -> Option<Mut<'_, CarControl>> {
+        let chassis = self.car().chassis;
+        self.app.world.get_mut::<CarControl>(chassis)
+    }
+
+    pub fn step(&mut self, steps: usize) {
+        let end = self.time() + steps as f64 * self.dt();
+        self.step_to(end);
+    }
+
+    // takes time steps up to the given time, the nearest a whole number of steps
+    pub fn step_to(&mut self, end: f64) {
+        let dt = self.dt();
+        // an update takes one time step, but the time can run a step behind
+        let steps = ((end - self.time()) / dt).round().max(0.) as usize;
+        for _ in 0..2 * steps + 2 {
+            if self.time() >= end - 0.5 * dt {
+                break;
+            }
+            self.app.update();
+        }
+    }
+
+    // the car spawned again, from its initial state and the start time
+    pub fn reset(&mut self) {
+        respawn_car(&mut self.app.world, external_car_startup_system);
+    }
+
+    // every joint but the base, in the order they were spawned in (the same
+    // again after a reset)
+    pub fn joints(&mut self) -> Vec<Entity> {
+        let mut query = self
+            .app
+            .world
+            .query_filtered::<(Entity, Option<&PhysicsId>), (With<Joint>, Without<Base>)>();
+        let mut joints: Vec<_> = query
+            .iter(&self.app.world)
+            .map(|(entity, id)| (id, entity))
+            .collect();
+        joints.sort();
+        joints.into_iter().map(|(_, entity)| entity).collect()
+    }
+
+    pub fn joint(&mut self, name: &str) -> Option<Entity> {
+        let mut query = self.app.world.query::<(Entity, &Joint)>();
+        query
+            .iter(&self.app.world)
+            .find(|(_, joint)| joint.name == name)
+            .map(|(entity, _)| entity)
+    }
+
+    // the joint state of the integrator, the joints hold those of its last stage
+    pub fn state(&self, joint: Entity) -> Option<&JointState> {
+        let physics_state = self.app.world.get_resource::<PhysicsState<Joint>>()?;
+        physics_state.states.get(&joint)
+    }
+}
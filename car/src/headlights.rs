@@ -0,0 +1,115 @@
+use bevy::prelude::*;
+
+use crate::{
+    build::{CarDefinition, CarEntities},
+    control::CarControl,
+};
+
+// Headlights of the car in `CarEntities`: two spot lights at the front corners
+// of the chassis, aimed a little below the horizon, for driving at night or in
+// low visibility (`time_of_day`). They follow the chassis as its children, and
+// are switched by the driver (`CarControl::toggle_headlights`, `J`).
+#[derive(Resource, Clone)]
+pub struct Headlights {
+    pub on: bool,
+    pub intensity: f32, // of each lamp (lumens)
+    pub range: f32,     // (m)
+    pub angle: f32,     // half angle of the beam (rad)
+    pub pitch: f32,     // aim below the horizon (rad)
+    pub inset: f32,     // of the lamps from the sides of the chassis
+    pub color: Color,
+}
+
+impl Default for Headlights {
+    fn default() -> Self {
+        Self {
+            on: false,
+            intensity: 200000.,
+            range: 80.,
+            angle: 0.45,
+            pitch: 0.04,
+            inset: 0.2,
+            color: Color::rgb(1.0, 0.95, 0.85),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct HeadlightLamp;
+
+pub fn headlights_setup(app: &mut App) {
+    app.init_resource::<Headlights>()
+        .add_systems(Update, (headlights_build_system, headlights_toggle_system));
+}
+
+fn visibility(on: bool) -> Visibility {
+    if on {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    }
+}
+
+// spawns the lamps once the car is spawned
+fn headlights_build_system(
+    mut commands: Commands,
+    headlights: Res<Headlights>,
+    car: Option<Res<CarDefinition>>,
+    car_entities: Option<Res<CarEntities>>,
+    lamps: Query<&HeadlightLamp>,
+) {
+    let (car, car_entities) = match (car, car_entities) {
+        (Some(car), Some(car_entities)) => (car, car_entities),
+        _ => return,
+    };
+    if !lamps.is_empty() {
+        return;
+    }
+
+    let [length, width, _] = car.chassis.dimensions.map(|x| x as f32);
+    let [x, _, z] = car.chassis.position.map(|x| x as f32);
+    let aim = Vec3::new(headlights.pitch.cos(), 0., -headlights.pitch.sin());
+    for side in [-1., 1.] {
+        let position = Vec3::new(x + length / 2., side * (width / 2. - headlights.inset), z);
+        commands
+            .spawn(SpotLightBundle {
+                spot_light: SpotLight {
+                    color: headlights.color,
+                    intensity: headlights.intensity,
+                    range: headlights.range,
+                    shadows_enabled: true,
+                    outer_angle: headlights.angle,
+                    inner_angle: 0.6 * headlights.angle,
+                    ..default()
+                },
+                // spot lights shine along -z
+                transform: Transform::from_translation(position).looking_to(aim, Vec3::Z),
+                visibility: visibility(headlights.on),
+                ..default()
+            })
+            .insert(HeadlightLamp)
+            .set_parent(car_entities.chassis);
+    }
+}
+
+fn headlights_toggle_system(
+    mut headlights: ResMut<Headlights>,
+    car_entities: Option<Res<CarEntities>>,
+    mut controls: Query<&mut CarControl>,
+    mut lamps: Query<&mut Visibility, With<HeadlightLamp>>,
+) {
+    if let Some(car_entities) = car_entities {
+        if let Ok(mut control) = controls.get_mut(car_entities.chassis) {
+            if control.toggle_headlights {
+                control.toggle_headlights = false;
+                headlights.on = !headlights.on;
+            }
+        }
+    }
+    if !headlights.is_changed() {
+        return;
+    }
+    for mut lamp in lamps.iter_mut() {
+        *lamp = visibility(headlights.on);
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_integrator::SimTime;
+use rigid_body::joint::Joint;
+
+use crate::{
+    ai::{ai_driver_system, closest_point, planar_pose, AiDriver},
+    brake_heat::BrakeHeat,
+    build::CarEntities,
+};
+
+// A timed run along a road, on the chassis entity: up a hill climb, or back down
+// it, where the brakes heat up and fade. The clock starts when the car crosses
+// the start line `start` meters along the path and stops at the finish line
+// `finish` meters before its end. The run is driven by the player, or by the AI
+// driver from `driver`, which stops after the finish. The results (time, average
+// speed, the hottest brake and the least brake friction) are logged and kept in
+// `results`.
+#[derive(Component, Clone)]
+pub struct HillClimb {
+    pub name: String,
+    pub path: Vec<[f64; 2]>, // centerline in the direction of the run
+    pub time: Option<f64>,   // since the start line, until the finish
+    pub results: HashMap<String, f64>,
+    start: usize,  // path index of the start line
+    finish: usize, // path index of the finish line
+    start_time: Option<f64>,
+    length: f64, // from the start to the finish line
+    index: Option<usize>,
+    max_brake_temperature: f64,
+    min_brake_friction: f64,
+}
+
+impl HillClimb {
+    pub fn new(name: &str, path: Vec<[f64; 2]>, start: f64, finish: f64) -> Self {
+        let mut distances = vec![0.];
+        for pair in path.windows(2) {
+            let [a, b] = [pair[0], pair[1]];
+            let last = distances[distances.len() - 1];
+            distances.push(last + (b[0] - a[0]).hypot(b[1] - a[1]));
+        }
+        let total = distances[distances.len() - 1];
+        let start = distances.iter().position(|s| *s >= start).unwrap_or(0);
+        let finish = distances
+            .iter()
+            .position(|s| *s >= total - finish)
+            .unwrap_or(path.len() - 1);
+        Self {
+            name: name.to_string(),
+            length: distances[finish] - distances[start],
+            path,
+            time: None,
+            results: HashMap::new(),
+            start,
+            finish,
+            start_time: None,
+            index: None,
+            max_brake_temperature: f64::NEG_INFINITY,
+            min_brake_friction: 1.,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.results.is_empty()
+    }
+
+    // position and heading of the path `distance` before the start line, to
+    // line up the car
+    pub fn start_pose(&self, distance: f64) -> ([f64; 2], f64) {
+        let mut index = self.start;
+        while index > 0 {
+            let [a, b] = [self.path[index], self.path[self.start]];
+            if (b[0] - a[0]).hypot(b[1] - a[1]) >= distance {
+                break;
+            }
+            index -= 1;
+        }
+        let [a, b] = [self.path[index], self.path[index + 1]];
+        (a, (b[1] - a[1]).atan2(b[0] - a[0]))
+    }
+
+    // AI driver for the centerline at up to `max_speed`
+    pub fn driver(&self, max_curvature: f64, max_speed: f64) -> AiDriver {
+        AiDriver::new(self.path.clone(), max_curvature, max_speed, 7., 6.)
+    }
+}
+
+#[derive(Component)]
+struct HillClimbText;
+
+// Times the run and shows it with the brake temperatures. Uses the UI, so add
+// it to the environment setup.
+pub fn hill_climb_setup(app: &mut App) {
+    app.add_systems(Startup, hill_climb_hud_startup_system)
+        .add_systems(
+            Update,
+            (
+                hill_climb_system.after(ai_driver_system),
+                hill_climb_hud_system.after(hill_climb_system),
+            ),
+        );
+}
+
+// Runs after the AI driver, so it can be stopped after the finish
+fn hill_climb_system(
+    time: Res<SimTime>,
+    car: Option<Res<CarEntities>>,
+    brakes: Query<&BrakeHeat>,
+    mut runs: Query<(&Joint, &mut HillClimb, Option<&mut AiDriver>)>,
+) {
+    for (joint, mut run, driver) in runs.iter_mut() {
+        if run.is_finished() {
+            if let Some(mut driver) = driver {
+                driver.speed_limit = 0.;
+            }
+            continue;
+        }
+        let (position, _) = planar_pose(joint);
+        let index = closest_point(&run.path, position, run.index);
+        run.index = Some(index);
+
+        let start_time = match run.start_time {
+            Some(start_time) => start_time,
+            None => {
+                if index < run.start {
+                    continue;
+                }
+                info!("{} started", run.name);
+                run.start_time = Some(time.time());
+                time.time()
+            }
+        };
+        run.time = Some(time.time() - start_time);
+
+        if let Some(car) = &car {
+            for wheel in &car.wheels {
+                if let Ok(brake) = brakes.get(*wheel) {
+                    run.max_brake_temperature = run.max_brake_temperature.max(brake.temperature);
+                    run.min_brake_friction = run.min_brake_friction.min(brake.friction_factor());
+                }
+            }
+        }
+        if index < run.finish {
+            continue;
+        }
+
+        let elapsed = time.time() - start_time;
+        let results = [
+            ("time", elapsed),
+            ("average_speed", run.length / elapsed),
+            ("max_brake_temperature", run.max_brake_temperature),
+            ("min_brake_friction", run.min_brake_friction),
+        ];
+        run.results = results
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect();
+        let report: Vec<String> = results
+            .iter()
+            .map(|(name, value)| format!("{}: {:.3}", name, value))
+            .collect();
+        info!("{} complete, {}", run.name, report.join(", "));
+    }
+}
+
+fn hill_climb_hud_startup_system(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.),
+                width: Val::Percent(100.),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 28.,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                )
+                .with_background_color(Color::rgba(0., 0., 0., 0.5)),
+                HillClimbText,
+            ));
+        });
+}
+
+fn hill_climb_hud_system(
+    car: Option<Res<CarEntities>>,
+    runs: Query<&HillClimb>,
+    brakes: Query<&BrakeHeat>,
+    mut text: Query<&mut Text, With<HillClimbText>>,
+) {
+    let (car, run) = match car {
+        Some(car) => match runs.get(car.chassis) {
+            Ok(run) => (car, run),
+            Err(_) => return,
+        },
+        None => return,
+    };
+    let time = match run.time {
+        Some(time) => format!("{:.2} s", time),
+        None => "-.-- s".to_string(),
+    };
+    let state = if run.is_finished() { "finished" } else { "" };
+    // brake temperatures, front axle first
+    let temperatures: Vec<String> = car
+        .corners
+        .iter()
+        .zip(&car.wheels)
+        .filter_map(|(corner, wheel)| {
+            brakes
+                .get(*wheel)
+                .ok()
+                .map(|brake| format!("{} {:.0}", corner, brake.temperature))
+        })
+        .collect();
+    if let Ok(mut text) = text.get_single_mut() {
+        text.sections[0].value = format!(
+            "{} {} {}\nbrakes (C) {}",
+            run.name,
+            time,
+            state,
+            temperatures.join("  ")
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use bevy::prelude::*;
+use rigid_body::{joint::Joint, sva::Vector};
+
+use crate::{build::CarEntities, control::CarControl, engine::Engine, transmission::Transmission};
+
+const GRAVITY: f64 = 9.81;
+const PANEL_COLOR: Color = Color::rgba(0., 0., 0., 0.5);
+const BAR_BACKGROUND: Color = Color::rgba(1., 1., 1., 0.15);
+
+// Driving HUD for the car in `CarEntities`: speed, engine speed and gear, the
+// driver inputs, and a g-ball of the horizontal chassis acceleration.
+#[derive(Resource)]
+pub struct Hud {
+    pub max_g: f32,             // acceleration at the edge of the g-ball
+    pub filter_time: f64,       // time constant of the acceleration filter (s)
+    pub acceleration: [f64; 2], // filtered, longitudinal and lateral (g)
+    velocity: Option<Vector>,   // previous chassis velocity, absolute coordinates
+}
+
+impl Default for Hud {
+    fn default() -> Self {
+        Self {
+            max_g: 1.5,
+            filter_time: 0.1,
+            acceleration: [0., 0.],
+            velocity: None,
+        }
+    }
+}
+
+#[derive(Component)]
+struct HudText;
+
+#[derive(Component, Clone, Copy)]
+enum HudBar {
+    Throttle,
+    Brake,
+    Clutch,
+    Steering, // fills from the center
+}
+
+#[derive(Component)]
+struct GBallDot;
+
+pub fn hud_setup(app: &mut App) {
+    app.init_resource::<Hud>()
+        .add_systems(Startup, hud_startup_system)
+        .add_systems(Update, hud_system);
+}
+
+fn hud_startup_system(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.),
+                bottom: Val::Px(10.),
+                padding: UiRect::all(Val::Px(8.)),
+                column_gap: Val::Px(12.),
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            ..default()
+        })
+        .with_children(|panel| {
+            panel
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(4.),
+                        width: Val::Px(160.),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|column| {
+                    column.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 20.,
+                                color: Color::WHITE,
+                                ..default()
+                            },
+                        ),
+                        HudText,
+                    ));
+                    spawn_bar(column, HudBar::Throttle, Color::rgb(0.2, 0.8, 0.2));
+                    spawn_bar(column, HudBar::Brake, Color::rgb(0.9, 0.2, 0.2));
+                    spawn_bar(column, HudBar::Clutch, Color::rgb(0.3, 0.5, 0.9));
+                    spawn_bar(column, HudBar::Steering, Color::rgb(0.9, 0.9, 0.9));
+                });
+
+            // g-ball
+            panel
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(100.),
+                        height: Val::Px(100.),
+                        ..default()
+                    },
+                    background_color: BAR_BACKGROUND.into(),
+                    ..default()
+                })
+                .with_children(|ball| {
+                    ball.spawn((
+                        NodeBundle {
+                            style: Style {
+                                position_type: PositionType::Absolute,
+                                width: Val::Px(10.),
+                                height: Val::Px(10.),
+                                ..default()
+                            },
+                            background_color: Color::rgb(1., 0.8, 0.1).into(),
+                            ..default()
+                        },
+                        GBallDot,
+                    ));
+                });
+        });
+}
+
+fn spawn_bar(parent: &mut ChildBuilder, bar: HudBar, color: Color) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                height: Val::Px(8.),
+                ..default()
+            },
+            background_color: BAR_BACKGROUND.into(),
+            ..default()
+        })
+        .with_children(|background| {
+            background.spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        height: Val::Percent(100.),
+                        ..default()
+                    },
+                    background_color: color.into(),
+                    ..default()
+                },
+                bar,
+            ));
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn hud_system(
+    time: Res<Time>,
+    mut hud: ResMut<Hud>,
+    car: Option<Res<CarEntities>>,
+    chassis: Query<(&Joint, &CarControl)>,
+    engines: Query<(&Engine, &Transmission)>,
+    mut text: Query<&mut Text, With<HudText>>,
+    mut bars: Query<(&mut Style, &HudBar), Without<GBallDot>>,
+    mut dot: Query<&mut Style, With<GBallDot>>,
+) {
+    let car = match car {
+        Some(car) => car,
+        None => return,
+    };
+    let (joint, control) = match chassis.get(car.chassis) {
+        Ok(chassis) => chassis,
+        Err(_) => return,
+    };
+
+    // horizontal acceleration in chassis coordinates, from the change in velocity
+    let x0i = joint.x.inverse();
+    let velocity = (x0i * joint.v)
+        .velocity_point(x0i.transform_point(Vector::zeros()))
+        .vel;
+    let dt = time.delta_seconds_f64();
+    if let (Some(previous), true) = (hud.velocity, dt > 0.) {
+        let acceleration = (velocity - previous) / dt;
+        let measured = [
+            acceleration.dot(&(x0i * Vector::x())) / GRAVITY,
+            acceleration.dot(&(x0i * Vector::y())) / GRAVITY,
+        ];
+        let filter = (dt / hud.filter_time).min(1.);
+        for (filtered, measured) in hud.acceleration.iter_mut().zip(measured) {
+            *filtered += filter * (measured - *filtered);
+        }
+    }
+    hud.velocity = Some(velocity);
+
+    let (rpm, gear) = match engines.get(car.engine) {
+        Ok((engine, transmission)) => (
+            engine.outputs.get("rpm").copied().unwrap_or(0.),
+            match (transmission.is_reverse(), transmission.is_low_range()) {
+                (true, false) => "R".to_string(),
+                (true, true) => "R low".to_string(),
+                (false, false) => transmission.gear().to_string(),
+                (false, true) => format!("{} low", transmission.gear()),
+            },
+        ),
+        Err(_) => (0., "-".to_string()),
+    };
+    if let Ok(mut text) = text.get_single_mut() {
+        text.sections[0].value = format!(
+            "{:.0} km/h\n{:.0} rpm\ngear {}",
+            joint.v.v.x.abs() * 3.6,
+            rpm,
+            gear
+        );
+    }
+
+    for (mut style, bar) in bars.iter_mut() {
+        // percent of the bar width
+        let (left, width) = match bar {
+            HudBar::Throttle => (0., 100. * control.throttle),
+            HudBar::Brake => (0., 100. * control.brake),
+            HudBar::Clutch => (0., 100. * control.clutch),
+            // steering left (positive) fills to the left of the center
+            HudBar::Steering => {
+                let steering = control.steering.clamp(-1., 1.);
+                (50. - 50. * steering.max(0.), 50. * steering.abs())
+            }
+        };
+        style.left = Val::Percent(left);
+        style.width = Val::Percent(width.clamp(0., 100.));
+    }
+
+    // the dot points along the acceleration, up when accelerating and left when turning left
+    if let Ok(mut style) = dot.get_single_mut() {
+        let scale = 50. / hud.max_g;
+        let [longitudinal, lateral] = hud.acceleration.map(|g| g as f32);
+        let x = (50. - lateral * scale).clamp(0., 100.);
+        let y = (50. - longitudinal * scale).clamp(0., 100.);
+        style.left = Val::Percent(x - 5.);
+        style.top = Val::Percent(y - 5.);
+    }
+}
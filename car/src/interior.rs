@@ -0,0 +1,356 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use cameras::{camera_az_el::AzElCamera, control::CameraParentList};
+use rigid_body::joint::Joint;
+
+use crate::{
+    build::{CarDefinition, CarEntities},
+    control::CarControl,
+    engine::Engine,
+};
+
+const TRIM_COLOR: Color = Color::rgb(0.12, 0.12, 0.13);
+const DIAL_COLOR: Color = Color::rgb(0.05, 0.05, 0.05);
+const NEEDLE_COLOR: Color = Color::rgb(1.0, 0.3, 0.1);
+
+// Cockpit of the car in `CarEntities`: seat, dashboard, steering wheel, pedals
+// and a speedometer and rev counter, laid out on top of the chassis box. The
+// steering wheel turns with the steering, the pedals move with the driver
+// inputs and the needles follow the speed and engine speed. A driver's eye
+// camera parent is added to the camera list (`C` cycles through the views).
+// With `level_horizon` (`H` toggles it) the eye follows the heading and pitch of
+// the chassis but not its roll, a comfort option keeping the horizon level.
+#[derive(Resource, Clone)]
+pub struct Cockpit {
+    pub steering_ratio: f32, // steering wheel angle at full steering (rad)
+    pub pedal_travel: f32,   // pedal angle when fully pressed (rad)
+    pub gauge_sweep: f32,    // needle angle from zero to full scale (rad)
+    pub max_speed: f64,      // full scale of the speedometer (m/s)
+    pub eye_height: f32,     // above the seat
+    pub level_horizon: bool,
+}
+
+impl Default for Cockpit {
+    fn default() -> Self {
+        Self {
+            steering_ratio: 7.8, // 450° to each side
+            pedal_travel: 0.4,
+            gauge_sweep: 1.5 * PI,
+            max_speed: 70.,
+            eye_height: 0.6,
+            level_horizon: false,
+        }
+    }
+}
+
+#[derive(Component)]
+struct CockpitEye;
+
+#[derive(Component)]
+struct SteeringWheel;
+
+#[derive(Component, Clone, Copy)]
+enum PedalArm {
+    Throttle,
+    Brake,
+    Clutch,
+}
+
+#[derive(Component, Clone, Copy)]
+enum GaugeNeedle {
+    Speed,
+    EngineSpeed,
+}
+
+pub fn interior_setup(app: &mut App) {
+    app.init_resource::<Cockpit>().add_systems(
+        Update,
+        (
+            interior_build_system,
+            interior_animation_system,
+            cockpit_camera_system,
+            cockpit_level_system,
+        ),
+    );
+}
+
+// builds the interior once the car is spawned
+#[allow(clippy::too_many_arguments)]
+fn interior_build_system(
+    mut commands: Commands,
+    cockpit: Res<Cockpit>,
+    car: Option<Res<CarDefinition>>,
+    car_entities: Option<Res<CarEntities>>,
+    camera_parents: Option<ResMut<CameraParentList>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    eyes: Query<&CockpitEye>,
+) {
+    let (car, car_entities) = match (car, car_entities) {
+        (Some(car), Some(car_entities)) => (car, car_entities),
+        _ => return,
+    };
+    if !eyes.is_empty() {
+        return;
+    }
+
+    // the driver sits on the left, on top of the chassis box
+    let [length, width, height] = car.chassis.dimensions.map(|x| x as f32);
+    let [x, _, z] = car.chassis.position.map(|x| x as f32);
+    let seat = Vec3::new(x - 0.1 * length, 0.22 * width, z + height / 2.);
+    let dash_x = seat.x + 0.55;
+
+    let trim = materials.add(StandardMaterial {
+        base_color: TRIM_COLOR,
+        perceptual_roughness: 0.8,
+        ..default()
+    });
+    let dial = materials.add(StandardMaterial {
+        base_color: DIAL_COLOR,
+        unlit: true,
+        ..default()
+    });
+    let needle = materials.add(StandardMaterial {
+        base_color: NEEDLE_COLOR,
+        unlit: true,
+        ..default()
+    });
+    let rim = PbrBundle {
+        mesh: meshes.add(Mesh::from(shape::Torus {
+            radius: 0.18,
+            ring_radius: 0.015,
+            ..default()
+        })),
+        material: trim.clone(),
+        transform: Transform::from_rotation(Quat::from_rotation_z(PI / 2.)),
+        ..default()
+    };
+    let mut part = |size: [f32; 3], translation: Vec3, material: &Handle<StandardMaterial>| {
+        let [x, y, z] = size;
+        PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Box::new(x, y, z))),
+            material: material.clone(),
+            transform: Transform::from_translation(translation),
+            ..default()
+        }
+    };
+
+    // seat, dashboard, gauges and pedals, the needles and pedals turn about a pivot
+    let seat_bundle = part([0.45, 0.45, 0.08], seat + Vec3::Z * 0.04, &trim);
+    let back_bundle = part([0.08, 0.45, 0.55], seat + Vec3::new(-0.22, 0., 0.3), &trim);
+    let dash_bundle = part(
+        [0.2, 0.9 * width, 0.2],
+        Vec3::new(dash_x, 0., seat.z + 0.45),
+        &trim,
+    );
+    let gauges = [GaugeNeedle::Speed, GaugeNeedle::EngineSpeed]
+        .into_iter()
+        .zip([0.08, -0.08])
+        .map(|(gauge, offset)| {
+            let center = Vec3::new(dash_x - 0.101, seat.y + offset, seat.z + 0.47);
+            let face = part([0.005, 0.13, 0.13], center, &dial);
+            let pointer = part([0.005, 0.008, 0.06], Vec3::Z * 0.025, &needle);
+            (gauge, center, face, pointer)
+        })
+        .collect::<Vec<_>>();
+    let pedals = [PedalArm::Clutch, PedalArm::Brake, PedalArm::Throttle]
+        .into_iter()
+        .zip([0.14, 0., -0.14])
+        .map(|(pedal, offset)| {
+            let pivot = Vec3::new(dash_x + 0.25, seat.y + offset, seat.z + 0.25);
+            let arm = part([0.02, 0.07, 0.2], Vec3::Z * -0.1, &trim);
+            (pedal, pivot, arm)
+        })
+        .collect::<Vec<_>>();
+
+    // the wheel is tilted back toward the driver, its hub turns about the column
+    let column = Transform::from_translation(Vec3::new(seat.x + 0.4, seat.y, seat.z + 0.4))
+        .with_rotation(Quat::from_rotation_y(-0.35));
+    let spoke = part([0.02, 0.34, 0.03], Vec3::ZERO, &trim);
+    let top_mark = part([0.025, 0.03, 0.03], Vec3::Z * 0.18, &needle);
+
+    // everything hangs off one entity, so the body dents only touch the chassis
+    let eye = seat + Vec3::new(-0.05, 0., cockpit.eye_height);
+    let mut eye_id = None;
+    commands
+        .spawn(SpatialBundle::default())
+        .set_parent(car_entities.chassis)
+        .with_children(|interior| {
+            interior.spawn(seat_bundle);
+            interior.spawn(back_bundle);
+            interior.spawn(dash_bundle);
+            for (gauge, center, face, pointer) in gauges {
+                interior.spawn(face);
+                interior
+                    .spawn((
+                        SpatialBundle::from_transform(Transform::from_translation(
+                            center - Vec3::X * 0.005,
+                        )),
+                        gauge,
+                    ))
+                    .with_children(|pivot| {
+                        pivot.spawn(pointer);
+                    });
+            }
+            for (pedal, pivot, arm) in pedals {
+                interior
+                    .spawn((
+                        SpatialBundle::from_transform(Transform::from_translation(pivot)),
+                        pedal,
+                    ))
+                    .with_children(|pivot| {
+                        pivot.spawn(arm);
+                    });
+            }
+            interior
+                .spawn(SpatialBundle::from_transform(column))
+                .with_children(|column| {
+                    column
+                        .spawn((SpatialBundle::default(), SteeringWheel))
+                        .with_children(|hub| {
+                            hub.spawn(rim);
+                            hub.spawn(spoke);
+                            hub.spawn(top_mark);
+                        });
+                });
+            eye_id = Some(
+                interior
+                    .spawn((
+                        SpatialBundle::from_transform(Transform::from_translation(eye)),
+                        CockpitEye,
+                    ))
+                    .id(),
+            );
+        });
+
+    if let (Some(mut camera_parents), Some(eye_id)) = (camera_parents, eye_id) {
+        camera_parents.list.push(eye_id);
+    }
+}
+
+type InteriorParts<'w, 's> = (
+    Query<'w, 's, &'static mut Transform, With<SteeringWheel>>,
+    Query<'w, 's, (&'static mut Transform, &'static PedalArm)>,
+    Query<'w, 's, (&'static mut Transform, &'static GaugeNeedle)>,
+);
+
+fn interior_animation_system(
+    cockpit: Res<Cockpit>,
+    car: Option<Res<CarEntities>>,
+    controls: Query<&CarControl>,
+    joints: Query<&Joint>,
+    engines: Query<&Engine>,
+    mut parts: ParamSet<InteriorParts>,
+) {
+    let car = match car {
+        Some(car) => car,
+        None => return,
+    };
+    let control = match controls.get(car.chassis) {
+        Ok(control) => control,
+        Err(_) => return,
+    };
+
+    // turning left turns the top of the wheel to the left, seen from the driver
+    for mut transform in parts.p0().iter_mut() {
+        transform.rotation = Quat::from_rotation_x(-control.steering * cockpit.steering_ratio);
+    }
+    // pressed pedals swing forward about their top
+    for (mut transform, pedal) in parts.p1().iter_mut() {
+        let input = match pedal {
+            PedalArm::Throttle => control.throttle,
+            PedalArm::Brake => control.brake,
+            PedalArm::Clutch => control.clutch,
+        };
+        transform.rotation = Quat::from_rotation_y(-input.clamp(0., 1.) * cockpit.pedal_travel);
+    }
+
+    let speed = match joints.get(car.chassis) {
+        Ok(joint) => joint.v.v.x.abs() / cockpit.max_speed,
+        Err(_) => 0.,
+    };
+    let engine_speed = match (joints.get(car.engine), engines.get(car.engine)) {
+        (Ok(joint), Ok(engine)) => joint.qd / engine.redline,
+        _ => 0.,
+    };
+    // needles sweep clockwise from the lower left
+    for (mut transform, gauge) in parts.p2().iter_mut() {
+        let fraction = match gauge {
+            GaugeNeedle::Speed => speed,
+            GaugeNeedle::EngineSpeed => engine_speed,
+        };
+        let angle = cockpit.gauge_sweep * (fraction.clamp(0., 1.) as f32 - 0.5);
+        transform.rotation = Quat::from_rotation_x(angle);
+    }
+}
+
+// puts the camera at the driver's eye, looking ahead, when the cockpit view is
+// selected, and restores the orbit camera when it is left
+fn cockpit_camera_system(
+    camera_parents: Option<Res<CameraParentList>>,
+    eyes: Query<Entity, With<CockpitEye>>,
+    mut cameras: Query<(&mut AzElCamera, &mut Transform)>,
+    mut orbit: Local<Option<(Vec3, f32)>>,
+) {
+    let camera_parents = match camera_parents {
+        Some(camera_parents) => camera_parents,
+        None => return,
+    };
+    let active = camera_parents.list.get(camera_parents.active);
+    let in_cockpit = matches!(active, Some(entity) if eyes.contains(*entity));
+    for (mut camera, mut transform) in cameras.iter_mut() {
+        match (in_cockpit, *orbit) {
+            (true, None) => {
+                *orbit = Some((camera.focus, camera.radius));
+                camera.focus = Vec3::ZERO;
+                camera.radius = 0.05;
+                camera.azimuth = -PI / 2.;
+                camera.elevation = 0.15;
+                transform.rotation = Quat::from_rotation_z(camera.azimuth)
+                    * Quat::from_rotation_x(PI / 2. - camera.elevation);
+            }
+            (false, Some((focus, radius))) => {
+                *orbit = None;
+                camera.focus = focus;
+                camera.radius = radius;
+            }
+            _ => continue,
+        }
+        transform.translation = camera.focus + transform.rotation * Vec3::Z * camera.radius;
+    }
+}
+
+// takes the roll of the chassis out of the driver's eye with `level_horizon`
+fn cockpit_level_system(
+    input: Res<Input<KeyCode>>,
+    mut cockpit: ResMut<Cockpit>,
+    parents: Query<&GlobalTransform>,
+    mut eyes: Query<(&Parent, &mut Transform), With<CockpitEye>>,
+) {
+    if input.just_pressed(KeyCode::H) {
+        cockpit.level_horizon = !cockpit.level_horizon;
+        info!(
+            "level horizon: {}",
+            if cockpit.level_horizon { "on" } else { "off" }
+        );
+    }
+    for (parent, mut transform) in eyes.iter_mut() {
+        let mut level = Quat::IDENTITY;
+        // the chassis moved since its global transform, but not by much
+        if let (true, Ok(parent)) = (cockpit.level_horizon, parents.get(parent.get())) {
+            let (_, rotation, _) = parent.to_scale_rotation_translation();
+            let forward = rotation * Vec3::X;
+            let left = Vec3::Z.cross(forward);
+            // no heading to keep when looking straight up or down
+            if left.length_squared() > 1e-6 {
+                let left = left.normalize();
+                let frame = Mat3::from_cols(forward, left, forward.cross(left));
+                level = rotation.inverse() * Quat::from_mat3(&frame);
+            }
+        }
+        if transform.rotation != level {
+            transform.rotation = level;
+        }
+    }
+}
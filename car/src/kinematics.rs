@@ -0,0 +1,61 @@
+use bevy::prelude::*;
+
+use rigid_body::{joint::Joint, sva::Xform};
+
+// Wheel alignment, angles in radians. Negative camber tilts the top of the wheel
+// towards the chassis, positive toe points the front of the wheel towards the
+// chassis (toe in). Travel is the suspension joint position (positive in bump).
+#[derive(Clone, Default)]
+pub struct Alignment {
+    pub camber: f64,
+    pub toe: f64,
+    pub caster: f64,      // camber change with steering
+    pub camber_gain: f64, // camber change per unit of travel
+    pub bump_steer: f64,  // toe change per unit of travel
+}
+
+impl Alignment {
+    pub fn camber(&self, travel: f64, steer: f64, side: f64) -> f64 {
+        // with caster the outside wheel in a turn gains negative camber
+        self.camber + self.camber_gain * travel + side * self.caster * steer.sin()
+    }
+
+    pub fn toe(&self, travel: f64) -> f64 {
+        self.toe + self.bump_steer * travel
+    }
+}
+
+// Orients the wheel joint relative to the suspension, based on the suspension
+// travel and the steering angle. The wheel is rotated kinematically (through the
+// joint tree transform), so the tire contact uses the aligned wheel.
+#[derive(Component, Clone)]
+pub struct SuspensionKinematics {
+    pub alignment: Alignment,
+    pub suspension: Entity,
+    pub steering: Option<Entity>,
+    pub side: f64, // 1 for left wheels, -1 for right wheels
+}
+
+pub fn suspension_kinematics_system(
+    wheels: Query<(Entity, &SuspensionKinematics)>,
+    mut joints: Query<&mut Joint>,
+) {
+    for (wheel_entity, kinematics) in wheels.iter() {
+        let travel = match joints.get(kinematics.suspension) {
+            Ok(suspension) => suspension.q,
+            Err(_) => continue,
+        };
+        let steer = kinematics
+            .steering
+            .and_then(|steering| joints.get(steering).ok())
+            .map_or(0., |steering| steering.q);
+
+        let side = kinematics.side;
+        let camber = kinematics.alignment.camber(travel, steer, side);
+        let toe = kinematics.alignment.toe(travel);
+        if let Ok(mut wheel) = joints.get_mut(wheel_entity) {
+            // toe about the vertical axis, then camber about the rolling direction
+            wheel.xt = Xform::rotx(-side * camber) * Xform::rotz(-side * toe);
+        }
+    }
+}
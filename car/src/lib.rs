@@ -1,8 +1,73 @@
+pub mod abs;
+pub mod aero;
+pub mod ai;
+pub mod audio;
+pub mod bicycle;
+pub mod bindings;
+pub mod brake_heat;
+pub mod broadcast;
 pub mod build;
+pub mod buoyancy;
+pub mod camera_clearance;
+pub mod camera_presets;
+pub mod camera_sensor;
+pub mod camera_shake;
+pub mod cone_test;
+pub mod cones;
+pub mod config;
 pub mod control;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cosim;
+pub mod damage;
+pub mod diagnostics;
+pub mod differential;
+pub mod drift;
+pub mod drive_mode;
+pub mod engine;
 pub mod environment;
+pub mod force_feedback;
+pub mod frames;
+pub mod fuel;
+pub mod headless;
+pub mod headlights;
+pub mod hill_climb;
+pub mod hud;
+pub mod interior;
 pub mod interpolate;
+pub mod kinematics;
+pub mod lidar;
+pub mod maneuver;
+pub mod mcap;
+pub mod menu;
 pub mod mesh;
+pub mod mirror;
+pub mod motion;
+pub mod particles;
+pub mod payload;
+pub mod performance;
+pub mod photo;
 pub mod physics;
+pub mod plot;
+pub mod presets;
+pub mod race;
+pub mod recovery;
+pub mod replay;
+pub mod ros;
+pub mod scenario;
+pub mod sensors;
 pub mod setup;
+pub mod skid_marks;
+pub mod snapshot;
+pub mod sysid;
+pub mod telemetry;
+pub mod terrain;
+pub mod time_of_day;
+pub mod time_trial;
 pub mod tire;
+pub mod torque_vectoring;
+pub mod touch;
+pub mod transmission;
+pub mod tuning;
+pub mod turbo;
+pub mod validation;
+pub mod wheel_load;
@@ -0,0 +1,273 @@
+use std::{collections::HashMap, f64::consts::PI};
+
+use bevy::prelude::*;
+use grid_terrain::GridTerrain;
+use rigid_body::{
+    joint::Joint,
+    sva::{rx, ry, rz, Matrix, Vector},
+};
+
+use crate::{
+    build::{CarDefinition, CarEntities},
+    sensors::SensorNoise,
+};
+
+// Directions of the lidar rays in the sensor axes (x forward, y left, z up).
+// Angles are in rad, elevation is positive up.
+#[derive(Clone)]
+pub enum LidarPattern {
+    // a spinning lidar: `channels` beams spread over the elevation range, each
+    // sampled every `azimuth_step` around the full circle
+    Spinning {
+        channels: usize,
+        elevation: [f64; 2],
+        azimuth_step: f64,
+    },
+    // a fixed (solid state) lidar: a grid of rays over the field of view
+    Grid {
+        azimuth: [f64; 2],
+        elevation: [f64; 2],
+        columns: usize,
+        rows: usize,
+    },
+    // any rays, as azimuth and elevation
+    Custom(Vec<[f64; 2]>),
+}
+
+impl Default for LidarPattern {
+    // 16 channels over ±15° every 2°, like a small automotive lidar
+    fn default() -> Self {
+        Self::Spinning {
+            channels: 16,
+            elevation: [-15_f64.to_radians(), 15_f64.to_radians()],
+            azimuth_step: 2_f64.to_radians(),
+        }
+    }
+}
+
+// evenly spread over the range, the middle of it for a single one
+fn spread(range: [f64; 2], count: usize) -> Vec<f64> {
+    match count {
+        0 => Vec::new(),
+        1 => vec![0.5 * (range[0] + range[1])],
+        _ => (0..count)
+            .map(|i| range[0] + (range[1] - range[0]) * i as f64 / (count - 1) as f64)
+            .collect(),
+    }
+}
+
+impl LidarPattern {
+    pub fn rays(&self) -> Vec<Vector> {
+        let angles = match self {
+            LidarPattern::Spinning {
+                channels,
+                elevation,
+                azimuth_step,
+            } => {
+                let steps = (2. * PI / azimuth_step.max(1e-3)).round() as usize;
+                let elevations = spread(*elevation, *channels);
+                (0..steps)
+                    .flat_map(|step| {
+                        let azimuth = step as f64 * 2. * PI / steps as f64;
+                        elevations
+                            .iter()
+                            .map(move |elevation| [azimuth, *elevation])
+                    })
+                    .collect()
+            }
+            LidarPattern::Grid {
+                azimuth,
+                elevation,
+                columns,
+                rows,
+            } => {
+                let elevations = spread(*elevation, *rows);
+                spread(*azimuth, *columns)
+                    .into_iter()
+                    .flat_map(|azimuth| {
+                        elevations
+                            .iter()
+                            .map(move |elevation| [azimuth, *elevation])
+                    })
+                    .collect()
+            }
+            LidarPattern::Custom(angles) => angles.clone(),
+        };
+        angles
+            .iter()
+            .map(|[azimuth, elevation]| {
+                Vector::new(
+                    elevation.cos() * azimuth.cos(),
+                    elevation.cos() * azimuth.sin(),
+                    elevation.sin(),
+                )
+            })
+            .collect()
+    }
+}
+
+// Lidar on the chassis entity. Every `sample_time` it casts its rays against the
+// terrain and the props standing on it (not the cars), and keeps the returns as
+// a point cloud in the sensor axes, with range noise. Rays with nothing within
+// `max_range` give no point. The whole scan is taken at once, without the
+// distortion of a spinning sensor moving during the scan. Scans are costly,
+// check the number of rays and the range.
+#[derive(Component, Clone)]
+pub struct Lidar {
+    pub position: Vector, // mounting point, chassis coordinates
+    pub rotation: Matrix, // from chassis to sensor axes
+    pub max_range: f64,
+    pub range_noise: f64,    // standard deviation (m)
+    pub resolution: f64,     // ray march step, the thinnest feature it sees (m)
+    pub sample_time: f64,    // between scans (s)
+    pub points: Vec<Vector>, // of the last scan
+    pub scan: usize,         // number of the last scan, starting at 1
+    pub outputs: HashMap<String, f64>,
+    rays: Vec<Vector>, // of the pattern, sensor axes
+    pub(crate) noise: SensorNoise,
+    timer: f64,
+}
+
+impl Default for Lidar {
+    fn default() -> Self {
+        Self {
+            position: Vector::new(0., 0., 1.5),
+            rotation: Matrix::identity(),
+            rays: LidarPattern::default().rays(),
+            max_range: 60.,
+            range_noise: 0.02,
+            resolution: 0.1,
+            sample_time: 0.1,
+            points: Vec::new(),
+            scan: 0,
+            outputs: HashMap::new(),
+            noise: SensorNoise::default(),
+            timer: 0.,
+        }
+    }
+}
+
+impl Lidar {
+    // mounting point and angles (roll, pitch, yaw in rad) on the chassis
+    pub fn with_mounting(mut self, position: [f64; 3], angles: [f64; 3]) -> Self {
+        let [x, y, z] = position;
+        let [roll, pitch, yaw] = angles;
+        self.position = Vector::new(x, y, z);
+        self.rotation = rx(roll) * ry(pitch) * rz(yaw);
+        self
+    }
+
+    pub fn with_pattern(mut self, pattern: LidarPattern) -> Self {
+        self.rays = pattern.rays();
+        self
+    }
+
+    pub fn with_range(mut self, max_range: f64, range_noise: f64) -> Self {
+        self.max_range = max_range;
+        self.range_noise = range_noise;
+        self
+    }
+
+    pub fn with_sample_time(mut self, sample_time: f64) -> Self {
+        self.sample_time = sample_time;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.noise = SensorNoise::new(seed);
+        self
+    }
+
+    // ray directions of the pattern, sensor axes
+    pub fn rays(&self) -> &Vec<Vector> {
+        &self.rays
+    }
+}
+
+// runs once per time step (not in the physics schedule), like the other sensors
+pub fn lidar_system(
+    fixed_time: Res<FixedTime>,
+    terrain: Option<Res<GridTerrain>>,
+    mut lidars: Query<(&Joint, &mut Lidar)>,
+) {
+    let terrain = match terrain {
+        Some(terrain) => terrain,
+        None => return,
+    };
+    let dt = fixed_time.period.as_secs_f64();
+    for (joint, mut lidar) in lidars.iter_mut() {
+        lidar.timer += dt;
+        if lidar.timer < lidar.sample_time {
+            continue;
+        }
+        lidar.timer = 0.;
+
+        // sensor origin and axes in absolute coordinates
+        let x0i = joint.x.inverse();
+        let origin = x0i.transform_point(lidar.position);
+        let to_chassis = lidar.rotation.transpose();
+
+        let lidar = &mut *lidar;
+        lidar.points.clear();
+        let mut closest = f64::INFINITY;
+        for ray in lidar.rays.iter() {
+            let direction = x0i * (to_chassis * ray);
+            let range = match terrain.ray_cast(origin, direction, lidar.max_range, lidar.resolution)
+            {
+                Some(range) => range + lidar.noise.gaussian(lidar.range_noise),
+                None => continue,
+            };
+            closest = closest.min(range);
+            lidar.points.push(range * ray);
+        }
+        lidar.scan += 1;
+
+        let returns = lidar.points.len() as f64;
+        let closest = if closest.is_finite() {
+            closest
+        } else {
+            lidar.max_range
+        };
+        lidar.outputs.insert("returns".to_string(), returns);
+        lidar.outputs.insert("closest".to_string(), closest);
+    }
+}
+
+// Puts a lidar (the default pattern) on the roof of the car in `CarEntities` and
+// draws the points of its scans
+pub fn lidar_setup(app: &mut App) {
+    app.add_systems(Update, (lidar_attach_system, lidar_points_system));
+}
+
+fn lidar_attach_system(
+    mut commands: Commands,
+    car: Option<Res<CarDefinition>>,
+    car_entities: Option<Res<CarEntities>>,
+    lidars: Query<&Lidar>,
+) {
+    if let (Some(car), Some(car_entities)) = (car, car_entities) {
+        if lidars.get(car_entities.chassis).is_err() {
+            let roof = [
+                car.chassis.position[0],
+                0.,
+                car.chassis.position[2] + car.chassis.dimensions[2] / 2. + 0.1,
+            ];
+            commands
+                .entity(car_entities.chassis)
+                .insert(Lidar::default().with_mounting(roof, [0., 0., 0.]));
+        }
+    }
+}
+
+// a short vertical line at each point, where the lidar is now
+fn lidar_points_system(mut gizmos: Gizmos, lidars: Query<(&Joint, &Lidar)>) {
+    for (joint, lidar) in lidars.iter() {
+        let x0i = joint.x.inverse();
+        let to_chassis = lidar.rotation.transpose();
+        for point in lidar.points.iter() {
+            let point = x0i.transform_point(lidar.position + to_chassis * point);
+            let point = Vec3::new(point.x as f32, point.y as f32, point.z as f32);
+            gizmos.line(point, point + 0.05 * Vec3::Z, Color::CYAN);
+        }
+    }
+}
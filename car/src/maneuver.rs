@@ -0,0 +1,526 @@
+use std::{collections::HashMap, f64::consts::PI};
+
+use bevy::prelude::*;
+use bevy_integrator::SimTime;
+use grid_terrain::{props::Prop, GridTerrain};
+use rigid_body::{joint::Joint, sva::Vector};
+
+use crate::{ai::planar_pose, cones::ConeStrike, control::CarControl};
+
+const GRAVITY: f64 = 9.81;
+
+// Standard vehicle dynamics tests. Steering is a fraction of full lock, positive
+// to the left, and times are in seconds.
+#[derive(Clone)]
+pub enum Maneuver {
+    // ramp the steering to `steering` at `rate` (per second) and hold it
+    StepSteer {
+        steering: f64,
+        rate: f64,
+        hold: f64,
+    },
+    // one and a half sine periods, with a dwell at the second peak (FMVSS 126)
+    SineWithDwell {
+        amplitude: f64,
+        frequency: f64,
+        dwell: f64,
+    },
+    // one sine period to change lanes, straight for `gap`, and one back
+    DoubleLaneChange {
+        amplitude: f64,
+        period: f64,
+        gap: f64,
+    },
+    // closed loop steering on a circle while the speed slowly increases (SAE J266)
+    ConstantRadius {
+        radius: f64,
+        max_curvature: f64, // curvature of the car at full steering lock
+        acceleration: f64,  // increase of the target speed (m/s^2)
+        max_speed: f64,
+    },
+}
+
+impl Maneuver {
+    // the FMVSS 126 sine with dwell at 0.7 Hz
+    pub fn sine_with_dwell(amplitude: f64) -> Self {
+        Maneuver::SineWithDwell {
+            amplitude,
+            frequency: 0.7,
+            dwell: 0.5,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Maneuver::StepSteer { .. } => "step steer",
+            Maneuver::SineWithDwell { .. } => "sine with dwell",
+            Maneuver::DoubleLaneChange { .. } => "double lane change",
+            Maneuver::ConstantRadius { .. } => "constant radius",
+        }
+    }
+
+    // time from the start of the maneuver to the end of the steering input
+    pub fn duration(&self, speed: f64) -> f64 {
+        match *self {
+            Maneuver::StepSteer {
+                steering,
+                rate,
+                hold,
+            } => steering.abs() / rate + hold,
+            Maneuver::SineWithDwell {
+                frequency, dwell, ..
+            } => 1. / frequency + dwell,
+            Maneuver::DoubleLaneChange { period, gap, .. } => 2. * period + gap,
+            Maneuver::ConstantRadius {
+                acceleration,
+                max_speed,
+                ..
+            } => (max_speed - speed).max(0.) / acceleration,
+        }
+    }
+
+    // open loop steering at the time since the start of the maneuver
+    fn steering(&self, time: f64) -> f64 {
+        match *self {
+            Maneuver::StepSteer { steering, rate, .. } => {
+                (rate * time).min(steering.abs()) * steering.signum()
+            }
+            Maneuver::SineWithDwell {
+                amplitude,
+                frequency,
+                dwell,
+            } => {
+                let peak = 0.75 / frequency;
+                if time < peak {
+                    amplitude * (2. * PI * frequency * time).sin()
+                } else if time < peak + dwell {
+                    -amplitude
+                } else if time < 1. / frequency + dwell {
+                    amplitude * (2. * PI * frequency * (time - dwell)).sin()
+                } else {
+                    0.
+                }
+            }
+            Maneuver::DoubleLaneChange {
+                amplitude,
+                period,
+                gap,
+            } => {
+                let sine = |time: f64| amplitude * (2. * PI * time / period).sin();
+                if time < period {
+                    sine(time)
+                } else if time < period + gap {
+                    0.
+                } else if time < 2. * period + gap {
+                    -sine(time - period - gap)
+                } else {
+                    0.
+                }
+            }
+            Maneuver::ConstantRadius { .. } => 0.,
+        }
+    }
+
+    // Cone gates along the kinematic path of the maneuver (no tire slip), in the
+    // coordinates of the start pose (x forward, y left). A car that slides off
+    // the ideal path hits the cones.
+    pub fn cone_course(&self, speed: f64, course: &ConeCourse) -> Vec<[f64; 2]> {
+        let dt = 0.01;
+        let end = self.duration(speed) + 1.;
+        let [mut x, mut y, mut heading]: [f64; 3] = [0., 0., 0.];
+        let mut distance = 0.;
+        let mut cones = Vec::new();
+        let mut time = 0.;
+        while time < end {
+            if distance >= cones.len() as f64 / 2. * course.spacing {
+                let (sin, cos) = heading.sin_cos();
+                for side in [1., -1.] {
+                    let offset = side * course.width / 2.;
+                    cones.push([x - offset * sin, y + offset * cos]);
+                }
+            }
+            let curvature = match *self {
+                Maneuver::ConstantRadius { radius, .. } => 1. / radius,
+                _ => course.max_curvature * self.steering(time),
+            };
+            heading += speed * curvature * dt;
+            x += speed * heading.cos() * dt;
+            y += speed * heading.sin() * dt;
+            distance += speed * dt;
+            time += dt;
+        }
+        cones
+    }
+}
+
+// Cone gates laid out along a maneuver when it starts
+#[derive(Clone)]
+pub struct ConeCourse {
+    pub max_curvature: f64, // curvature of the car at full steering lock
+    pub width: f64,         // between the cones of a gate
+    pub spacing: f64,       // between the gates, along the path
+}
+
+// Runs a maneuver on a car (on the chassis entity next to its `CarControl`, in
+// place of a player). The car is first brought up to speed and settled, then
+// the steering follows the maneuver with the throttle held, and the response is
+// recorded until `record_after` seconds after the steering ends. The metrics
+// are logged and kept in `metrics` when the run is complete.
+#[derive(Component, Clone)]
+pub struct ManeuverRunner {
+    pub maneuver: Maneuver,
+    pub speed: f64,        // entry speed (m/s)
+    pub settle_time: f64,  // time at speed before the maneuver starts
+    pub record_after: f64, // time recorded after the steering ends
+    pub speed_gain: f64,   // pedal per unit speed error
+    pub metrics: HashMap<String, f64>,
+    pub cones: Option<ConeCourse>,
+    pub cone_strikes: u32, // during the maneuver
+    start: Option<f64>,    // time the maneuver started
+    start_pose: Option<([f64; 2], f64)>,
+    cones_placed: bool,
+    settled: f64,  // time at speed so far
+    throttle: f32, // held during open loop maneuvers
+    steering: f64, // closed loop steering of the constant radius test
+    velocity: Option<Vector>,
+    samples: Vec<ManeuverSample>,
+}
+
+#[derive(Clone, Copy)]
+struct ManeuverSample {
+    time: f64, // since the start of the maneuver
+    steering: f64,
+    yaw_rate: f64,
+    lateral_acceleration: f64,
+}
+
+impl ManeuverRunner {
+    pub fn new(maneuver: Maneuver, speed: f64) -> Self {
+        Self {
+            maneuver,
+            speed,
+            settle_time: 3.,
+            record_after: 3.,
+            speed_gain: 0.5,
+            metrics: HashMap::new(),
+            cones: None,
+            cone_strikes: 0,
+            start: None,
+            start_pose: None,
+            cones_placed: false,
+            settled: 0.,
+            throttle: 0.,
+            steering: 0.,
+            velocity: None,
+            samples: Vec::new(),
+        }
+    }
+
+    // lays out cone gates along the maneuver, the strikes are counted in the metrics
+    pub fn with_cones(mut self, cones: ConeCourse) -> Self {
+        self.cones = Some(cones);
+        self
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.metrics.is_empty()
+    }
+
+    // position and heading of the car when the maneuver started
+    pub fn start_pose(&self) -> Option<([f64; 2], f64)> {
+        self.start_pose
+    }
+
+    fn speed_control(&self, control: &mut CarControl, target: f64, speed: f64) {
+        let pedal = self.speed_gain * (target - speed);
+        control.throttle = pedal.clamp(0., 1.) as f32;
+        control.brake = (-pedal).clamp(0., 1.) as f32;
+    }
+
+    fn compute_metrics(&mut self) {
+        let samples = &self.samples;
+        let mut metrics = HashMap::new();
+        let peak = samples
+            .iter()
+            .copied()
+            .max_by(|a, b| a.yaw_rate.abs().total_cmp(&b.yaw_rate.abs()));
+        if let Some(peak) = peak {
+            metrics.insert("peak_yaw_rate".to_string(), peak.yaw_rate);
+            metrics.insert("peak_yaw_rate_time".to_string(), peak.time);
+        }
+        let peak_lateral = samples
+            .iter()
+            .map(|sample| sample.lateral_acceleration)
+            .fold(0., |peak: f64, value| {
+                if value.abs() > peak.abs() {
+                    value
+                } else {
+                    peak
+                }
+            });
+        metrics.insert("peak_lateral_acceleration".to_string(), peak_lateral);
+        if self.cones.is_some() {
+            metrics.insert("cone_strikes".to_string(), self.cone_strikes as f64);
+        }
+
+        let end = self.maneuver.duration(self.speed);
+        match self.maneuver {
+            Maneuver::StepSteer { steering, rate, .. } => {
+                // steady state over the last half second of the hold
+                let steady = mean(
+                    samples
+                        .iter()
+                        .filter(|sample| sample.time > end - 0.5 && sample.time <= end)
+                        .map(|sample| sample.yaw_rate),
+                );
+                metrics.insert("steady_yaw_rate".to_string(), steady);
+                if let (Some(peak), true) = (peak, steady.abs() > 1e-6) {
+                    metrics.insert("overshoot".to_string(), peak.yaw_rate / steady - 1.);
+                }
+                // from 50% steering to 90% of the steady state yaw rate (ISO 7401)
+                let half_steering = 0.5 * steering.abs() / rate;
+                let rise = samples
+                    .iter()
+                    .find(|sample| sample.yaw_rate.abs() >= 0.9 * steady.abs());
+                if let (Some(rise), true) = (rise, steady.abs() > 1e-6) {
+                    metrics.insert("response_time".to_string(), rise.time - half_steering);
+                }
+            }
+            Maneuver::SineWithDwell { .. } => {
+                // yaw rate 1 s and 1.75 s after the steering ends, relative to the
+                // first peak, the FMVSS 126 lateral stability criteria
+                let first_peak = samples
+                    .iter()
+                    .filter(|sample| sample.time <= end)
+                    .map(|sample| sample.yaw_rate.abs())
+                    .fold(0., f64::max);
+                let at = |time: f64| {
+                    samples
+                        .iter()
+                        .find(|sample| sample.time >= time)
+                        .map(|sample| sample.yaw_rate.abs())
+                };
+                if first_peak > 1e-6 {
+                    for (name, time) in [("yaw_rate_ratio_1s", 1.), ("yaw_rate_ratio_1_75s", 1.75)]
+                    {
+                        if let Some(yaw_rate) = at(end + time) {
+                            metrics.insert(name.to_string(), yaw_rate / first_peak);
+                        }
+                    }
+                }
+            }
+            Maneuver::DoubleLaneChange { .. } => {
+                // residual yaw rate after the second lane change
+                let residual = samples
+                    .iter()
+                    .filter(|sample| sample.time > end)
+                    .map(|sample| sample.yaw_rate.abs())
+                    .fold(0., f64::max);
+                metrics.insert("residual_yaw_rate".to_string(), residual);
+            }
+            Maneuver::ConstantRadius { .. } => {
+                // slope of the steering against the lateral acceleration (per g),
+                // positive when the car understeers
+                let points: Vec<[f64; 2]> = samples
+                    .iter()
+                    .filter(|sample| sample.time <= end)
+                    .map(|sample| {
+                        [
+                            sample.lateral_acceleration.abs() / GRAVITY,
+                            sample.steering.abs(),
+                        ]
+                    })
+                    .collect();
+                if let Some(slope) = slope(&points) {
+                    metrics.insert("understeer_gradient".to_string(), slope);
+                }
+                let max_lateral = points.iter().map(|point| point[0]).fold(0., f64::max);
+                metrics.insert("max_lateral_acceleration_g".to_string(), max_lateral);
+            }
+        }
+        self.metrics = metrics;
+    }
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = values.fold((0., 0), |(sum, count), value| (sum + value, count + 1));
+    if count == 0 {
+        0.
+    } else {
+        sum / count as f64
+    }
+}
+
+// least squares slope of y against x
+fn slope(points: &[[f64; 2]]) -> Option<f64> {
+    let n = points.len() as f64;
+    let x = mean(points.iter().map(|point| point[0]));
+    let y = mean(points.iter().map(|point| point[1]));
+    let sxx: f64 = points.iter().map(|point| (point[0] - x).powi(2)).sum();
+    let sxy: f64 = points
+        .iter()
+        .map(|point| (point[0] - x) * (point[1] - y))
+        .sum();
+    if n < 2. || sxx < 1e-9 {
+        None
+    } else {
+        Some(sxy / sxx)
+    }
+}
+
+// Runs before the integrator on the simulation time, so the inputs change at
+// exact time steps. The steering command is set directly, bypassing the driver
+// steering filter.
+pub fn maneuver_system(
+    time: Res<SimTime>,
+    mut strikes: EventReader<ConeStrike>,
+    mut cars: Query<(Entity, &Joint, &mut CarControl, &mut ManeuverRunner)>,
+) {
+    for strike in strikes.iter() {
+        if let Ok((_, _, _, mut runner)) = cars.get_mut(strike.car) {
+            if runner.start.is_some() && !runner.is_finished() {
+                runner.cone_strikes += 1;
+            }
+        }
+    }
+    for (_, joint, mut control, mut runner) in cars.iter_mut() {
+        // chassis velocities are in chassis coordinates
+        let speed = joint.v.v.x;
+        let yaw_rate = joint.v.w.z;
+
+        // lateral acceleration from the change in the absolute velocity
+        let x0i = joint.x.inverse();
+        let velocity = (x0i * joint.v)
+            .velocity_point(x0i.transform_point(Vector::zeros()))
+            .vel;
+        let lateral_acceleration = match runner.velocity {
+            Some(previous) => ((velocity - previous) / time.dt).dot(&(x0i * Vector::y())),
+            None => 0.,
+        };
+        runner.velocity = Some(velocity);
+
+        control.reverse = false;
+        control.clutch = 0.;
+        control.handbrake = 0.;
+
+        let start = match runner.start {
+            Some(start) => start,
+            None => {
+                // up to speed, then settle before starting
+                let target = runner.speed;
+                runner.speed_control(&mut control, target, speed);
+                control.steering = 0.;
+                control.steering_input = 0.;
+                if (speed - runner.speed).abs() < 0.05 * runner.speed.max(1.) {
+                    runner.settled += time.dt;
+                } else {
+                    runner.settled = 0.;
+                }
+                if runner.settled >= runner.settle_time {
+                    info!("{} started at {:.1} m/s", runner.maneuver.name(), speed);
+                    runner.start = Some(time.time());
+                    runner.start_pose = Some(planar_pose(joint));
+                    runner.throttle = control.throttle;
+                    if let Maneuver::ConstantRadius {
+                        radius,
+                        max_curvature,
+                        ..
+                    } = runner.maneuver
+                    {
+                        // start from the kinematic steering for the radius
+                        runner.steering = (1. / (radius * max_curvature)).clamp(-1., 1.);
+                    }
+                }
+                continue;
+            }
+        };
+        let elapsed = time.time() - start;
+
+        let steering = match runner.maneuver {
+            Maneuver::ConstantRadius {
+                radius,
+                max_curvature,
+                acceleration,
+                max_speed,
+            } => {
+                // speed ramp, and integral steering on the path curvature error
+                let target = (runner.speed + acceleration * elapsed).min(max_speed);
+                runner.speed_control(&mut control, target, speed);
+                let curvature = if speed.abs() > 1. {
+                    yaw_rate / speed
+                } else {
+                    1. / radius
+                };
+                let gain = 2. / max_curvature; // steering per unit curvature error per second
+                runner.steering += gain * (1. / radius - curvature) * time.dt;
+                runner.steering = runner.steering.clamp(-1., 1.);
+                runner.steering
+            }
+            _ => {
+                control.throttle = runner.throttle;
+                control.brake = 0.;
+                runner.maneuver.steering(elapsed)
+            }
+        };
+        control.steering = steering as f32;
+        control.steering_input = steering as f32;
+
+        if runner.is_finished() {
+            continue;
+        }
+        runner.samples.push(ManeuverSample {
+            time: elapsed,
+            steering,
+            yaw_rate,
+            lateral_acceleration,
+        });
+        if elapsed >= runner.maneuver.duration(runner.speed) + runner.record_after {
+            runner.compute_metrics();
+            let mut names: Vec<&String> = runner.metrics.keys().collect();
+            names.sort();
+            let report: Vec<String> = names
+                .iter()
+                .map(|name| format!("{}: {:.3}", name, runner.metrics[*name]))
+                .collect();
+            info!("{} complete, {}", runner.maneuver.name(), report.join(", "));
+        }
+    }
+}
+
+// Lays out the cone course of each maneuver when it starts. Uses the terrain
+// meshes, so add it to the environment setup.
+pub fn maneuver_cones_setup(app: &mut App) {
+    app.add_systems(Update, maneuver_cones_system);
+}
+
+fn maneuver_cones_system(
+    mut commands: Commands,
+    terrain: Option<ResMut<GridTerrain>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut runners: Query<&mut ManeuverRunner>,
+) {
+    let mut terrain = match terrain {
+        Some(terrain) => terrain,
+        None => return,
+    };
+    for mut runner in runners.iter_mut() {
+        let (course, ([x, y], heading)) = match (&runner.cones, runner.start_pose) {
+            (Some(course), Some(pose)) if !runner.cones_placed => (course, pose),
+            _ => continue,
+        };
+        let cones = runner.maneuver.cone_course(runner.speed, course);
+        runner.cones_placed = true;
+
+        let parent = commands.spawn(SpatialBundle::default()).id();
+        let (sin, cos) = heading.sin_cos();
+        for [cone_x, cone_y] in cones {
+            let prop = Prop::cone(
+                x + cos * cone_x - sin * cone_y,
+                y + sin * cone_x + cos * cone_y,
+            );
+            let index = terrain.add_prop(prop);
+            terrain.build_prop_mesh(index, &mut commands, &mut meshes, &mut materials, parent);
+        }
+    }
+}
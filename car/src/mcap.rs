@@ -0,0 +1,304 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, BufWriter},
+    path::PathBuf,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bevy::prelude::*;
+use bevy_integrator::{mcap::McapWriter, recorder::Recorder, ExitEvent};
+use rigid_body::{joint::Joint, sva::Xform};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    build::CarEntities,
+    frames::{joint_frames, pose},
+    lidar::Lidar,
+};
+
+// name and JSON schema of a message type
+type Schema = (&'static str, fn() -> Value);
+const FRAME_TRANSFORMS: Schema = ("foxglove.FrameTransforms", frame_transforms_schema);
+const POINT_CLOUD: Schema = ("foxglove.PointCloud", point_cloud_schema);
+
+// Insert the resource (with a `Recorder`) to log the car in `CarEntities` to an
+// MCAP file (`bevy_integrator::mcap::McapWriter`), to play back and plot the run in Foxglove. Each recorded step of the
+// `Recorder` is logged at its simulation time, as JSON messages:
+// - `/telemetry/<group>`: the telemetry channels of the group, "tire.fl.slip_ratio"
+//   is `fl.slip_ratio` of `/telemetry/tire`. The driver inputs and sensor outputs
+//   are in `/telemetry/control`, `/telemetry/imu`, `/telemetry/gps`, ...
+// - `/tf`: the joint frames (`foxglove.FrameTransforms`), the base joint is the
+//   world frame, and the `lidar` frame if the chassis has a lidar
+// - `/lidar`: the points of each lidar scan (`foxglove.PointCloud`)
+// The file is finished when the simulation exits.
+#[derive(Resource)]
+pub struct McapLogger {
+    pub path: PathBuf,
+    pub world_frame: String,
+    writer: Option<McapWriter<BufWriter<File>>>,
+    opened: bool,                 // the file was created, or failed to be
+    topics: HashMap<String, u16>, // channel ids
+    last_time: Option<f64>,       // of the last step logged
+    lidar_scan: usize,            // last scan logged
+}
+
+impl McapLogger {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            world_frame: "world".to_string(),
+            writer: None,
+            opened: false,
+            topics: HashMap::new(),
+            last_time: None,
+            lidar_scan: 0,
+        }
+    }
+
+    pub fn with_world_frame(mut self, frame: &str) -> Self {
+        self.world_frame = frame.to_string();
+        self
+    }
+
+    // the message on the topic, the channel (with the schema) is added the first time
+    fn log(
+        &mut self,
+        topic: &str,
+        schema: Option<Schema>,
+        time: u64,
+        message: &Value,
+    ) -> io::Result<()> {
+        let writer = match self.writer.as_mut() {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        let channel = match self.topics.get(topic) {
+            Some(channel) => *channel,
+            None => {
+                let schema = match schema {
+                    Some((name, schema)) => {
+                        let schema = schema().to_string();
+                        writer.add_schema(name, "jsonschema", schema.as_bytes())?
+                    }
+                    None => 0,
+                };
+                let channel = writer.add_channel(topic, schema, "json")?;
+                self.topics.insert(topic.to_string(), channel);
+                channel
+            }
+        };
+        writer.write_message(channel, time, message.to_string().as_bytes())
+    }
+}
+
+// "fl.slip_ratio" is {"fl": {"slip_ratio": value}}
+fn insert_field(object: &mut Map<String, Value>, name: &str, value: f64) {
+    if let Some((first, rest)) = name.split_once('.') {
+        if let Value::Object(inner) = object.entry(first).or_insert_with(|| json!({})) {
+            insert_field(inner, rest, value);
+            return;
+        }
+    }
+    object.insert(name.to_string(), json!(value));
+}
+
+// runs after `telemetry_system`, logs the step it recorded
+pub fn mcap_log_system(
+    recorder: Option<Res<Recorder>>,
+    logger: Option<ResMut<McapLogger>>,
+    car: Option<Res<CarEntities>>,
+    joints: Query<(&Joint, Option<&Parent>)>,
+    lidars: Query<&Lidar>,
+) {
+    let (recorder, mut logger) = match (recorder, logger) {
+        (Some(recorder), Some(logger)) => (recorder, logger),
+        _ => return,
+    };
+    let (time, values) = match recorder.last_step() {
+        Some((time, values)) if Some(time) != logger.last_time => (time, values),
+        _ => return,
+    };
+    logger.last_time = Some(time);
+    if !logger.opened {
+        logger.opened = true;
+        match McapWriter::create(&logger.path) {
+            Ok(writer) => logger.writer = Some(writer),
+            Err(error) => warn!("MCAP log {}: {}", logger.path.display(), error),
+        }
+    }
+    if logger.writer.is_none() {
+        return;
+    }
+
+    let nanoseconds = (time.max(0.) * 1e9).round() as u64;
+    let timestamp = json!({
+        "sec": nanoseconds / 1_000_000_000,
+        "nsec": nanoseconds % 1_000_000_000,
+    });
+    let mut messages = Vec::new();
+
+    // the telemetry channels by group, without the ones that have no value yet
+    let mut groups: BTreeMap<&str, Map<String, Value>> = BTreeMap::new();
+    for (channel, value) in recorder.channels().iter().zip(values) {
+        if !value.is_nan() {
+            let (group, name) = channel.split_once('.').unwrap_or((channel, "value"));
+            insert_field(groups.entry(group).or_default(), name, *value);
+        }
+    }
+    for (group, fields) in groups {
+        messages.push((format!("/telemetry/{}", group), None, Value::Object(fields)));
+    }
+
+    let frame = |parent: &str, child: &str, translation: Value, rotation: Value| {
+        json!({
+            "timestamp": timestamp,
+            "parent_frame_id": parent,
+            "child_frame_id": child,
+            "translation": translation,
+            "rotation": rotation,
+        })
+    };
+    let mut transforms: Vec<Value> = joint_frames(&joints, &logger.world_frame)
+        .into_iter()
+        .map(|(parent, child, translation, rotation)| frame(&parent, &child, translation, rotation))
+        .collect();
+    let chassis = car.and_then(|car| {
+        Some((
+            joints.get(car.chassis).ok()?.0,
+            lidars.get(car.chassis).ok(),
+        ))
+    });
+    let lidar = match chassis {
+        Some((chassis, Some(lidar))) => {
+            let (translation, rotation) = pose(&Xform::new(lidar.position, lidar.rotation));
+            transforms.push(frame(&chassis.name, "lidar", translation, rotation));
+            Some(lidar)
+        }
+        _ => None,
+    };
+    let transforms = json!({"transforms": transforms});
+    messages.push(("/tf".to_string(), Some(FRAME_TRANSFORMS), transforms));
+
+    // the points of a new scan, as x, y, z floats
+    if let Some(lidar) = lidar.filter(|lidar| lidar.scan != logger.lidar_scan) {
+        logger.lidar_scan = lidar.scan;
+        let mut data = Vec::with_capacity(12 * lidar.points.len());
+        for point in lidar.points.iter() {
+            for coordinate in [point.x, point.y, point.z] {
+                data.extend((coordinate as f32).to_le_bytes());
+            }
+        }
+        let field = |name: &str, offset: u32| json!({"name": name, "offset": offset, "type": 7});
+        let cloud = json!({
+            "timestamp": timestamp,
+            "frame_id": "lidar",
+            "pose": {
+                "position": {"x": 0., "y": 0., "z": 0.},
+                "orientation": {"x": 0., "y": 0., "z": 0., "w": 1.},
+            },
+            "point_stride": 12,
+            "fields": [field("x", 0), field("y", 4), field("z", 8)],
+            "data": STANDARD.encode(data),
+        });
+        messages.push(("/lidar".to_string(), Some(POINT_CLOUD), cloud));
+    }
+
+    for (topic, schema, message) in messages {
+        if let Err(error) = logger.log(&topic, schema, nanoseconds, &message) {
+            warn!("MCAP log {}: {}", logger.path.display(), error);
+            logger.writer = None;
+            return;
+        }
+    }
+}
+
+pub fn mcap_finish_system(mut exit: EventReader<ExitEvent>, logger: Option<ResMut<McapLogger>>) {
+    if exit.is_empty() {
+        return;
+    }
+    exit.clear();
+    let mut logger = match logger {
+        Some(logger) => logger,
+        None => return,
+    };
+    if let Some(writer) = logger.writer.take() {
+        match writer.finish() {
+            Ok(()) => info!("MCAP log written to {}", logger.path.display()),
+            Err(error) => warn!("MCAP log {}: {}", logger.path.display(), error),
+        }
+    }
+}
+
+// JSON schemas of the Foxglove messages, so Foxglove shows them in the 3D panel
+
+fn time_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "sec": {"type": "integer", "minimum": 0},
+            "nsec": {"type": "integer", "minimum": 0, "maximum": 999_999_999},
+        },
+    })
+}
+
+fn vector_schema(components: &[&str]) -> Value {
+    let properties: Map<String, Value> = components
+        .iter()
+        .map(|component| (component.to_string(), json!({"type": "number"})))
+        .collect();
+    json!({"type": "object", "properties": properties})
+}
+
+fn frame_transforms_schema() -> Value {
+    json!({
+        "title": "foxglove.FrameTransforms",
+        "type": "object",
+        "properties": {
+            "transforms": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "timestamp": time_schema(),
+                        "parent_frame_id": {"type": "string"},
+                        "child_frame_id": {"type": "string"},
+                        "translation": vector_schema(&["x", "y", "z"]),
+                        "rotation": vector_schema(&["x", "y", "z", "w"]),
+                    },
+                },
+            },
+        },
+    })
+}
+
+fn point_cloud_schema() -> Value {
+    json!({
+        "title": "foxglove.PointCloud",
+        "type": "object",
+        "properties": {
+            "timestamp": time_schema(),
+            "frame_id": {"type": "string"},
+            "pose": {
+                "type": "object",
+                "properties": {
+                    "position": vector_schema(&["x", "y", "z"]),
+                    "orientation": vector_schema(&["x", "y", "z", "w"]),
+                },
+            },
+            "point_stride": {"type": "integer", "minimum": 0},
+            "fields": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": "string"},
+                        "offset": {"type": "integer", "minimum": 0},
+                        "type": {"type": "integer"},
+                    },
+                },
+            },
+            "data": {"type": "string", "contentEncoding": "base64"},
+        },
+    })
+}
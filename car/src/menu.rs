@@ -0,0 +1,440 @@
+use bevy::prelude::*;
+use bevy_integrator::{ExitEvent, PhysicsState, SimTime, StateMap};
+use rigid_body::{
+    graphics::{GraphicsQuality, GraphicsSettings},
+    joint::Joint,
+    plugin::EscapeMenu,
+};
+
+use crate::{
+    build::{car_startup_system, respawn_car, run_system, CarDefinition},
+    replay::{input_replay_startup_system, InputPlayback},
+    terrain::{build_described_meshes, build_described_terrain, TerrainDescription, TerrainMeshes},
+};
+
+const BACKGROUND_COLOR: Color = Color::rgba(0., 0., 0., 0.6);
+const BUTTON_COLOR: Color = Color::rgba(0., 0., 0., 0.5);
+const HOVERED_COLOR: Color = Color::rgba(0.9, 0.5, 0.1, 0.8);
+
+// States of the app. It starts in the menu, escape pauses driving or a replay
+// and resumes it again, and quits from the start menu.
+#[derive(States, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AppState {
+    #[default]
+    Menu,
+    Driving,
+    Paused,
+    Replay,
+}
+
+// builds the vehicle of the given name
+type VehicleBuilder = Box<dyn Fn(&str) -> CarDefinition + Send + Sync>;
+
+// Start and pause menu. The simulation time stops in both. The pause menu can
+// resume, restart the scenario from its initial state, or quit. With `vehicles`
+// and `terrains`, another vehicle or terrain can be picked in either menu: drive
+// or restart then despawns the car and the terrain meshes and spawns the picked
+// ones, from the start. Both menus cycle the graphics quality presets.
+#[derive(Resource)]
+pub struct Menu {
+    pub title: String,
+    pub vehicles: Vec<String>, // names of the vehicles to pick from
+    pub vehicle: usize,        // running vehicle
+    pub terrains: Vec<(String, TerrainDescription)>, // terrains to pick from, with their names
+    pub terrain: usize,        // running terrain
+    pub replay: bool,          // a replay can be started from the start menu
+    build_vehicle: Option<VehicleBuilder>,
+    selected: usize,                   // vehicle picked in the menu
+    selected_terrain: usize,           // terrain picked in the menu
+    swap: bool,                        // the picked vehicle and terrain are spawned next
+    resume: AppState,                  // state the pause menu returns to
+    snapshot: Option<StateMap<Joint>>, // joint states at the start
+}
+
+impl Default for Menu {
+    fn default() -> Self {
+        Self {
+            title: "car demo".to_string(),
+            vehicles: Vec::new(),
+            vehicle: 0,
+            terrains: Vec::new(),
+            terrain: 0,
+            replay: false,
+            build_vehicle: None,
+            selected: 0,
+            selected_terrain: 0,
+            swap: false,
+            resume: AppState::Driving,
+            snapshot: None,
+        }
+    }
+}
+
+impl Menu {
+    // the vehicles by name, `vehicle` is the running one and `build` builds one
+    // from its name
+    pub fn with_vehicles(
+        mut self,
+        vehicles: Vec<String>,
+        vehicle: usize,
+        build: impl Fn(&str) -> CarDefinition + Send + Sync + 'static,
+    ) -> Self {
+        self.vehicles = vehicles;
+        self.vehicle = vehicle;
+        self.selected = vehicle;
+        self.build_vehicle = Some(Box::new(build));
+        self
+    }
+
+    // the terrains by name, `terrain` is the running one
+    pub fn with_terrains(
+        mut self,
+        terrains: Vec<(String, TerrainDescription)>,
+        terrain: usize,
+    ) -> Self {
+        self.terrains = terrains;
+        self.terrain = terrain;
+        self.selected_terrain = terrain;
+        self
+    }
+
+    pub fn with_replay(mut self) -> Self {
+        self.replay = true;
+        self
+    }
+
+    fn vehicle_changed(&self) -> bool {
+        self.selected != self.vehicle && self.selected < self.vehicles.len()
+    }
+
+    fn terrain_changed(&self) -> bool {
+        self.selected_terrain != self.terrain && self.selected_terrain < self.terrains.len()
+    }
+
+    fn vehicle_label(&self) -> String {
+        match self.vehicles.get(self.selected) {
+            Some(vehicle) => format!("vehicle: {}", vehicle),
+            None => String::new(),
+        }
+    }
+
+    fn terrain_label(&self) -> String {
+        match self.terrains.get(self.selected_terrain) {
+            Some((terrain, _)) => format!("terrain: {}", terrain),
+            None => String::new(),
+        }
+    }
+}
+
+#[derive(Component)]
+struct MenuRoot;
+
+// text of a button whose label changes when it is pressed
+#[derive(Component)]
+struct ButtonLabel(MenuButton);
+
+#[derive(Component, Clone, Copy, PartialEq)]
+enum MenuButton {
+    Drive,
+    Replay,
+    Resume,
+    Restart,
+    Vehicle,
+    Terrain,
+    Graphics,
+    Quit,
+}
+
+pub fn menu_setup(app: &mut App) {
+    app.add_state::<AppState>()
+        .insert_resource(EscapeMenu)
+        .init_resource::<Menu>()
+        .add_systems(OnEnter(AppState::Menu), (pause_system, menu_spawn_system))
+        .add_systems(OnEnter(AppState::Paused), (pause_system, menu_spawn_system))
+        .add_systems(OnExit(AppState::Menu), menu_despawn_system)
+        .add_systems(OnExit(AppState::Paused), menu_despawn_system)
+        .add_systems(OnEnter(AppState::Driving), unpause_system)
+        .add_systems(OnEnter(AppState::Replay), unpause_system)
+        .add_systems(
+            Update,
+            (menu_snapshot_system, menu_escape_system, menu_button_system),
+        )
+        .add_systems(PostUpdate, menu_swap_system);
+}
+
+fn pause_system(mut time: ResMut<Time>) {
+    time.pause();
+}
+
+fn unpause_system(mut time: ResMut<Time>) {
+    time.unpause();
+}
+
+// keeps the joint states the simulation starts from, for restarting it
+fn menu_snapshot_system(mut menu: ResMut<Menu>, physics_state: Option<Res<PhysicsState<Joint>>>) {
+    if let (None, Some(physics_state)) = (&menu.snapshot, physics_state) {
+        menu.snapshot = Some(physics_state.states.clone());
+    }
+}
+
+fn menu_escape_system(
+    input: Res<Input<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut menu: ResMut<Menu>,
+    mut exit: EventWriter<ExitEvent>,
+) {
+    if !input.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    match state.get() {
+        AppState::Driving | AppState::Replay => {
+            menu.resume = *state.get();
+            next_state.set(AppState::Paused);
+        }
+        AppState::Paused => next_state.set(menu.resume),
+        AppState::Menu => exit.send(ExitEvent),
+    }
+}
+
+fn graphics_label(graphics: &GraphicsSettings) -> String {
+    format!("graphics: {}", graphics.label())
+}
+
+fn menu_spawn_system(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    menu: Res<Menu>,
+    graphics: Option<Res<GraphicsSettings>>,
+) {
+    let (title, buttons) = match state.get() {
+        AppState::Paused => (
+            "paused".to_string(),
+            vec![MenuButton::Resume, MenuButton::Restart],
+        ),
+        _ if menu.replay => (
+            menu.title.clone(),
+            vec![MenuButton::Drive, MenuButton::Replay],
+        ),
+        _ => (menu.title.clone(), vec![MenuButton::Drive]),
+    };
+    let vehicle = (!menu.vehicles.is_empty()).then_some(MenuButton::Vehicle);
+    let terrain = (!menu.terrains.is_empty()).then_some(MenuButton::Terrain);
+    let graphics_button = graphics.is_some().then_some(MenuButton::Graphics);
+    let buttons = buttons
+        .into_iter()
+        .chain(vehicle)
+        .chain(terrain)
+        .chain(graphics_button)
+        .chain([MenuButton::Quit]);
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(8.),
+                    ..default()
+                },
+                background_color: BACKGROUND_COLOR.into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            MenuRoot,
+        ))
+        .with_children(|root| {
+            root.spawn(TextBundle::from_section(
+                title,
+                TextStyle {
+                    font_size: 40.,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ));
+            for button in buttons {
+                let label = match button {
+                    MenuButton::Drive => "drive".to_string(),
+                    MenuButton::Replay => "replay".to_string(),
+                    MenuButton::Resume => "resume".to_string(),
+                    MenuButton::Restart => "restart".to_string(),
+                    MenuButton::Vehicle => menu.vehicle_label(),
+                    MenuButton::Terrain => menu.terrain_label(),
+                    MenuButton::Graphics => {
+                        graphics.as_deref().map(graphics_label).unwrap_or_default()
+                    }
+                    MenuButton::Quit => "quit".to_string(),
+                };
+                root.spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(260.),
+                            padding: UiRect::axes(Val::Px(10.), Val::Px(6.)),
+                            justify_content: JustifyContent::Center,
+                            ..default()
+                        },
+                        background_color: BUTTON_COLOR.into(),
+                        ..default()
+                    },
+                    button,
+                ))
+                .with_children(|parent| {
+                    let mut text = parent.spawn(TextBundle::from_section(
+                        label,
+                        TextStyle {
+                            font_size: 22.,
+                            color: Color::WHITE,
+                            ..default()
+                        },
+                    ));
+                    if let MenuButton::Vehicle | MenuButton::Terrain | MenuButton::Graphics = button
+                    {
+                        text.insert(ButtonLabel(button));
+                    }
+                });
+            }
+        });
+}
+
+fn menu_despawn_system(mut commands: Commands, roots: Query<Entity, With<MenuRoot>>) {
+    for root in roots.iter() {
+        commands.entity(root).despawn_recursive();
+    }
+}
+
+type ButtonInteraction<'a> = (&'a Interaction, &'a MenuButton, &'a mut BackgroundColor);
+
+#[allow(clippy::too_many_arguments)]
+fn menu_button_system(
+    mut next_state: ResMut<NextState<AppState>>,
+    mut menu: ResMut<Menu>,
+    mut buttons: Query<ButtonInteraction, Changed<Interaction>>,
+    mut labels: Query<(&mut Text, &ButtonLabel)>,
+    physics_state: Option<ResMut<PhysicsState<Joint>>>,
+    mut time: ResMut<SimTime>,
+    mut playbacks: Query<&mut InputPlayback>,
+    mut exit: EventWriter<ExitEvent>,
+    graphics: Option<ResMut<GraphicsSettings>>,
+) {
+    let mut pressed = None;
+    for (interaction, button, mut color) in buttons.iter_mut() {
+        color.0 = match interaction {
+            Interaction::None => BUTTON_COLOR,
+            _ => HOVERED_COLOR,
+        };
+        if *interaction == Interaction::Pressed {
+            pressed = Some(*button);
+        }
+    }
+    let button = match pressed {
+        Some(button) => button,
+        None => return,
+    };
+
+    // another vehicle or terrain is spawned from the start, at the end of the frame
+    if matches!(button, MenuButton::Drive | MenuButton::Restart)
+        && (menu.vehicle_changed() || menu.terrain_changed())
+    {
+        menu.swap = true;
+    }
+
+    match button {
+        MenuButton::Drive => next_state.set(AppState::Driving),
+        MenuButton::Replay => next_state.set(AppState::Replay),
+        MenuButton::Resume => next_state.set(menu.resume),
+        MenuButton::Restart if menu.swap => next_state.set(menu.resume),
+        MenuButton::Restart => {
+            if let (Some(mut physics_state), Some(snapshot)) = (physics_state, &menu.snapshot) {
+                physics_state.states = snapshot.clone();
+            }
+            time.reset();
+            for mut playback in playbacks.iter_mut() {
+                playback.rewind();
+            }
+            info!("scenario restarted");
+            next_state.set(menu.resume);
+        }
+        MenuButton::Vehicle => {
+            menu.selected = (menu.selected + 1) % menu.vehicles.len().max(1);
+            let label = menu.vehicle_label();
+            for (mut text, _) in labels.iter_mut().filter(|(_, target)| target.0 == button) {
+                text.sections[0].value = label.clone();
+            }
+        }
+        MenuButton::Terrain => {
+            menu.selected_terrain = (menu.selected_terrain + 1) % menu.terrains.len().max(1);
+            let label = menu.terrain_label();
+            for (mut text, _) in labels.iter_mut().filter(|(_, target)| target.0 == button) {
+                text.sections[0].value = label.clone();
+            }
+        }
+        MenuButton::Graphics => {
+            let mut graphics = match graphics {
+                Some(graphics) => graphics,
+                None => return,
+            };
+            // the next preset, a custom setting goes back to the lowest
+            let next = match graphics.quality() {
+                Some(quality) => {
+                    let index = GraphicsQuality::ALL.iter().position(|q| *q == quality);
+                    GraphicsQuality::ALL[(index.unwrap_or(0) + 1) % GraphicsQuality::ALL.len()]
+                }
+                None => GraphicsQuality::Low,
+            };
+            *graphics = next.settings();
+            info!("graphics quality {}", next.name());
+            let label = graphics_label(&graphics);
+            for (mut text, _) in labels.iter_mut().filter(|(_, target)| target.0 == button) {
+                text.sections[0].value = label.clone();
+            }
+        }
+        MenuButton::Quit => exit.send(ExitEvent),
+    }
+}
+
+// Despawns the car (`respawn_car`) and the terrain meshes and spawns the picked
+// vehicle and terrain, the simulation starting again. The car attachments
+// (interior, lamps, mirror...) are built again by their systems.
+fn menu_swap_system(world: &mut World) {
+    let mut menu = world.resource_mut::<Menu>();
+    if !menu.swap {
+        return;
+    }
+    menu.swap = false;
+    menu.snapshot = None;
+    menu.vehicle = menu.selected.min(menu.vehicles.len().saturating_sub(1));
+    let car = match (&menu.build_vehicle, menu.vehicles.get(menu.vehicle)) {
+        (Some(build), Some(vehicle)) => Some(build(vehicle)),
+        _ => None,
+    };
+    let terrain = match menu.terrain_changed() {
+        true => {
+            menu.terrain = menu.selected_terrain;
+            Some(menu.terrains[menu.terrain].1.clone())
+        }
+        false => None,
+    };
+
+    if let Some(car) = car {
+        world.insert_resource(car);
+    }
+    respawn_car(world, car_startup_system);
+    run_system(world, input_replay_startup_system);
+
+    if let Some(terrain) = terrain {
+        let mut roots = world.query_filtered::<Entity, With<TerrainMeshes>>();
+        for root in roots.iter(world).collect::<Vec<_>>() {
+            world.entity_mut(root).despawn_recursive();
+        }
+        world.insert_resource(terrain);
+        match world.contains_resource::<Assets<Mesh>>() {
+            true => run_system(world, build_described_meshes),
+            false => run_system(world, build_described_terrain),
+        }
+    }
+    info!("vehicle and terrain changed");
+}
@@ -0,0 +1,98 @@
+use bevy::{core_pipeline::clear_color::ClearColorConfig, prelude::*};
+use cameras::viewport::CameraViewport;
+
+use crate::build::{CarDefinition, CarEntities};
+
+// Rear view mirror of the car in `CarEntities`: a camera at the back of the
+// chassis roof looking backwards, drawn into a small viewport at the top of the
+// window, to see the cars behind and to judge reversing. Like a reversing
+// camera the view is not flipped left to right. `G` toggles it.
+#[derive(Resource, Clone)]
+pub struct RearViewMirror {
+    pub visible: bool,
+    pub rect: Rect,  // part of the window, as in `CameraViewport`
+    pub height: f32, // of the camera above the chassis
+    pub fov: f32,    // vertical field of view (rad)
+}
+
+impl Default for RearViewMirror {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            rect: Rect::new(0.35, 0.02, 0.65, 0.16),
+            height: 0.3,
+            fov: 0.35,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct MirrorCamera;
+
+pub fn mirror_setup(app: &mut App) {
+    app.init_resource::<RearViewMirror>()
+        .add_systems(Update, (mirror_build_system, mirror_toggle_system));
+}
+
+// spawns the mirror camera once the car is spawned
+fn mirror_build_system(
+    mut commands: Commands,
+    mirror: Res<RearViewMirror>,
+    car: Option<Res<CarDefinition>>,
+    car_entities: Option<Res<CarEntities>>,
+    cameras: Query<&MirrorCamera>,
+) {
+    let (car, car_entities) = match (car, car_entities) {
+        (Some(car), Some(car_entities)) => (car, car_entities),
+        _ => return,
+    };
+    if !cameras.is_empty() {
+        return;
+    }
+
+    let [length, _, height] = car.chassis.dimensions.map(|x| x as f32);
+    let [x, _, z] = car.chassis.position.map(|x| x as f32);
+    let position = Vec3::new(x - length / 2., 0., z + height / 2. + mirror.height);
+    commands
+        .spawn(Camera3dBundle {
+            transform: Transform::from_translation(position).looking_to(-Vec3::X, Vec3::Z),
+            camera: Camera {
+                // over the main view and any split screen views
+                order: 10,
+                is_active: mirror.visible,
+                ..default()
+            },
+            camera_3d: Camera3d {
+                clear_color: ClearColorConfig::Custom(Color::BLACK),
+                ..default()
+            },
+            projection: Projection::Perspective(PerspectiveProjection {
+                fov: mirror.fov,
+                ..default()
+            }),
+            ..default()
+        })
+        .insert((
+            UiCameraConfig { show_ui: false },
+            CameraViewport::new(mirror.rect),
+            MirrorCamera,
+        ))
+        .set_parent(car_entities.chassis);
+}
+
+fn mirror_toggle_system(
+    input: Res<Input<KeyCode>>,
+    mut mirror: ResMut<RearViewMirror>,
+    mut cameras: Query<(&mut Camera, &mut CameraViewport), With<MirrorCamera>>,
+) {
+    if input.just_pressed(KeyCode::G) {
+        mirror.visible = !mirror.visible;
+    }
+    if !mirror.is_changed() {
+        return;
+    }
+    for (mut camera, mut viewport) in cameras.iter_mut() {
+        camera.is_active = mirror.visible;
+        viewport.rect = mirror.rect;
+    }
+}
@@ -0,0 +1,241 @@
+use std::net::UdpSocket;
+
+use bevy::prelude::*;
+use bevy_integrator::SimTime;
+use rigid_body::{joint::Joint, sva::Vector};
+
+use crate::{
+    build::{CarDefinition, CarEntities},
+    control::CarControl,
+    engine::Engine,
+    transmission::Transmission,
+};
+
+const GRAVITY: f64 = 9.81;
+
+// Layout of the motion packets, all values little endian f32
+#[derive(Clone, Copy, PartialEq)]
+pub enum MotionFormat {
+    // the 66 value "extradata=3" packet of the Codemasters racing games, read by
+    // most motion software (SimTools, FlyPT Mover, SimHub). World vectors are
+    // y up. Fields the demo has no equivalent for are zero.
+    Codemasters,
+    // the motion cues only: time, surge, sway and heave acceleration (g, chassis
+    // axes, without gravity), roll, pitch and yaw rate (rad/s), roll, pitch and
+    // yaw angle (rad) and speed (m/s)
+    Cueing,
+}
+
+// Motion rig output for the car in `CarEntities`. Insert the resource to send a
+// packet with the chassis accelerations and angular rates to `address` over UDP
+// every frame. The accelerations are averaged over the time steps of the frame.
+#[derive(Resource)]
+pub struct MotionOutput {
+    pub address: String, // receiver, host:port
+    pub format: MotionFormat,
+    socket: Option<UdpSocket>,
+    previous: Option<(f64, Vector)>, // time and chassis velocity (absolute coordinates)
+    acceleration: Vector,            // absolute coordinates
+}
+
+impl MotionOutput {
+    // 20777 is the default port of the Codemasters games
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            format: MotionFormat::Codemasters,
+            socket: None,
+            previous: None,
+            acceleration: Vector::zeros(),
+        }
+    }
+
+    pub fn with_format(mut self, format: MotionFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+// state of the car in one packet
+struct MotionSample {
+    time: f64,
+    position: Vector, // absolute coordinates
+    velocity: Vector, // absolute coordinates
+    forward: Vector,  // chassis axes in absolute coordinates
+    left: Vector,
+    acceleration: Vector, // chassis coordinates, without gravity
+    angular_rate: Vector, // chassis coordinates
+    wheel_speeds: Vec<f64>,
+    throttle: f64,
+    steering: f64,
+    brake: f64,
+    clutch: f64,
+    gear: f64,
+    rpm: f64,
+    max_rpm: f64,
+    idle_rpm: f64,
+    gears: f64,
+}
+
+impl MotionSample {
+    fn roll(&self) -> f64 {
+        self.left.z.clamp(-1., 1.).asin()
+    }
+
+    fn pitch(&self) -> f64 {
+        (-self.forward.z).clamp(-1., 1.).asin()
+    }
+
+    fn yaw(&self) -> f64 {
+        self.forward.y.atan2(self.forward.x)
+    }
+
+    fn cueing(&self) -> Vec<f32> {
+        let [surge, sway, heave] = [0, 1, 2].map(|ind| self.acceleration[ind] / GRAVITY);
+        let [roll_rate, pitch_rate, yaw_rate] = [0, 1, 2].map(|ind| self.angular_rate[ind]);
+        [
+            self.time,
+            surge,
+            sway,
+            heave,
+            roll_rate,
+            pitch_rate,
+            yaw_rate,
+            self.roll(),
+            self.pitch(),
+            self.yaw(),
+            self.velocity.norm(),
+        ]
+        .map(|value| value as f32)
+        .to_vec()
+    }
+
+    fn codemasters(&self) -> Vec<f32> {
+        // y up, x to the right, z forward
+        let y_up = |vector: Vector| [vector.x, vector.z, vector.y];
+        let right = -self.left;
+        let mut values = vec![0.; 66];
+        values[0] = self.time;
+        values[1] = self.time;
+        values[4..7].copy_from_slice(&y_up(self.position));
+        values[7] = self.velocity.norm();
+        values[8..11].copy_from_slice(&y_up(self.velocity));
+        values[11..14].copy_from_slice(&y_up(right));
+        values[14..17].copy_from_slice(&y_up(self.forward));
+        // wheel speeds rl, rr, fl, fr
+        let wheels = self.wheel_speeds.len();
+        if wheels >= 4 {
+            for (value, ind) in values[25..29]
+                .iter_mut()
+                .zip([wheels - 2, wheels - 1, 0, 1])
+            {
+                *value = self.wheel_speeds[ind];
+            }
+        }
+        values[29] = self.throttle;
+        values[30] = -self.steering; // positive to the right
+        values[31] = self.brake;
+        values[32] = self.clutch;
+        values[33] = self.gear;
+        values[34] = -self.acceleration.y / GRAVITY;
+        values[35] = self.acceleration.x / GRAVITY;
+        values[37] = self.rpm / 10.;
+        values[63] = self.max_rpm / 10.;
+        values[64] = self.idle_rpm / 10.;
+        values[65] = self.gears;
+        values.into_iter().map(|value| value as f32).collect()
+    }
+}
+
+pub fn motion_output_system(
+    time: Res<SimTime>,
+    motion: Option<ResMut<MotionOutput>>,
+    car: Option<Res<CarEntities>>,
+    car_definition: Option<Res<CarDefinition>>,
+    joints: Query<&Joint>,
+    controls: Query<&CarControl>,
+    engines: Query<(&Engine, &Transmission)>,
+) {
+    let (mut motion, car) = match (motion, car) {
+        (Some(motion), Some(car)) => (motion, car),
+        _ => return,
+    };
+    let chassis = match joints.get(car.chassis) {
+        Ok(chassis) => chassis,
+        Err(_) => return,
+    };
+
+    // acceleration since the last frame, held while the simulation is paused
+    let x0i = chassis.x.inverse();
+    let velocity = x0i * chassis.v.v;
+    let time = time.time();
+    if let Some((previous_time, previous_velocity)) = motion.previous {
+        if time > previous_time {
+            motion.acceleration = (velocity - previous_velocity) / (time - previous_time);
+        }
+    }
+    motion.previous = Some((time, velocity));
+
+    let radius = car_definition.map_or(0., |car| car.wheel.rolling_radius);
+    let wheel_speeds = car
+        .wheels
+        .iter()
+        .map(|wheel| joints.get(*wheel).map_or(0., |joint| joint.qd * radius))
+        .collect();
+    let mut sample = MotionSample {
+        time,
+        position: x0i.transform_point(Vector::zeros()),
+        velocity,
+        forward: x0i * Vector::x(),
+        left: x0i * Vector::y(),
+        acceleration: chassis.x * motion.acceleration,
+        angular_rate: chassis.v.w,
+        wheel_speeds,
+        throttle: 0.,
+        steering: 0.,
+        brake: 0.,
+        clutch: 0.,
+        gear: 0.,
+        rpm: 0.,
+        max_rpm: 0.,
+        idle_rpm: 0.,
+        gears: 0.,
+    };
+    if let Ok(control) = controls.get(car.chassis) {
+        sample.throttle = control.throttle as f64;
+        sample.steering = control.steering as f64;
+        sample.brake = control.brake as f64;
+        sample.clutch = control.clutch as f64;
+    }
+    if let Ok((engine, transmission)) = engines.get(car.engine) {
+        let rpm = 30. / std::f64::consts::PI;
+        sample.rpm = engine.outputs.get("rpm").copied().unwrap_or(0.);
+        sample.max_rpm = engine.redline * rpm;
+        sample.idle_rpm = engine.idle_speed * rpm;
+        sample.gear = transmission.outputs.get("gear").copied().unwrap_or(0.);
+        sample.gears = transmission.ratios.len() as f64;
+    }
+
+    let values = match motion.format {
+        MotionFormat::Codemasters => sample.codemasters(),
+        MotionFormat::Cueing => sample.cueing(),
+    };
+    let packet: Vec<u8> = values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+
+    if motion.socket.is_none() {
+        match UdpSocket::bind("0.0.0.0:0") {
+            Ok(socket) => motion.socket = Some(socket),
+            Err(error) => {
+                warn!("motion output socket: {}", error);
+                return;
+            }
+        }
+    }
+    if let Some(socket) = &motion.socket {
+        // nothing listening is not an error, the rig may be started later
+        let _ = socket.send_to(&packet, &motion.address);
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use grid_terrain::{rng::SplitMix64, SurfaceKind};
+
+use crate::tire::PointTire;
+
+const FADE_STEPS: usize = 8; // materials per effect, from opaque to transparent
+
+// Tire smoke on paved surfaces and dust on loose ones, emitted at the contacts of
+// sliding tires. The emission rate follows the slip power above a threshold.
+#[derive(Resource)]
+pub struct TireParticles {
+    pub smoke_threshold: f64, // slip power (W) where a tire starts to smoke
+    pub dust_threshold: f64,  // slip power (W) where a tire starts to raise dust
+    pub rate: f64,            // particles per second per kW above the threshold
+    pub max_rate: f64,        // particles per second per tire
+    pub lifetime: f32,        // (s)
+    pub max_particles: usize,
+    emitted: HashMap<Entity, f64>, // particles owed to each tire, by wheel joint
+    rng: SplitMix64,
+    assets: Option<ParticleAssets>,
+}
+
+impl Default for TireParticles {
+    fn default() -> Self {
+        Self {
+            smoke_threshold: 8000.,
+            dust_threshold: 1000.,
+            rate: 4.,
+            max_rate: 60.,
+            lifetime: 2.,
+            max_particles: 1500,
+            emitted: HashMap::new(),
+            rng: SplitMix64(7),
+            assets: None,
+        }
+    }
+}
+
+struct ParticleAssets {
+    mesh: Handle<Mesh>,
+    smoke: Vec<Handle<StandardMaterial>>,
+    dust: Vec<Handle<StandardMaterial>>,
+}
+
+#[derive(Component)]
+struct Particle {
+    velocity: Vec3,
+    age: f32,
+    rise: f32,   // upward acceleration, smoke rises and dust settles
+    growth: f32, // scale increase per second
+    dust: bool,
+}
+
+pub fn tire_particles_setup(app: &mut App) {
+    app.init_resource::<TireParticles>()
+        .add_systems(Update, (tire_particle_emit_system, tire_particle_system));
+}
+
+fn tire_particle_emit_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut settings: ResMut<TireParticles>,
+    tires: Query<&PointTire>,
+    particles: Query<(), With<Particle>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let settings = settings.as_mut();
+    let assets = settings.assets.get_or_insert_with(|| {
+        // shared materials, fading out in steps
+        let mut fade = |color: Color| -> Vec<Handle<StandardMaterial>> {
+            (0..FADE_STEPS)
+                .map(|step| {
+                    let alpha = color.a() * (1. - step as f32 / FADE_STEPS as f32);
+                    materials.add(StandardMaterial {
+                        base_color: color.with_a(alpha),
+                        alpha_mode: AlphaMode::Blend,
+                        unlit: true,
+                        ..default()
+                    })
+                })
+                .collect()
+        };
+        ParticleAssets {
+            mesh: meshes.add(Mesh::from(shape::UVSphere {
+                radius: 0.15,
+                sectors: 8,
+                stacks: 6,
+            })),
+            smoke: fade(Color::rgba(0.85, 0.85, 0.85, 0.4)),
+            dust: fade(Color::rgba(0.6, 0.5, 0.35, 0.5)),
+        }
+    });
+
+    let mut count = particles.iter().count();
+    let dt = time.delta_seconds_f64();
+    for tire in tires.iter() {
+        let wheel = tire.joint_entity();
+        let threshold = match tire.surface() {
+            SurfaceKind::Paved => settings.smoke_threshold,
+            SurfaceKind::Loose => settings.dust_threshold,
+            SurfaceKind::Ice => f64::INFINITY, // nothing to raise
+        };
+        let contact = match tire.contact() {
+            Some(contact) if tire.slip_power() > threshold => contact,
+            _ => {
+                settings.emitted.remove(&wheel);
+                continue;
+            }
+        };
+
+        let rate = (settings.rate * (tire.slip_power() - threshold) / 1000.).min(settings.max_rate);
+        let owed = settings.emitted.entry(wheel).or_insert(0.);
+        *owed += rate * dt;
+        let dust = tire.surface() == SurfaceKind::Loose;
+        let [position, normal] = contact.map(|vector| vector.cast::<f32>());
+        let position = Vec3::new(position.x, position.y, position.z);
+        let normal = Vec3::new(normal.x, normal.y, normal.z);
+        while *owed >= 1. && count < settings.max_particles {
+            *owed -= 1.;
+            count += 1;
+            let mut random = |range: [f64; 2]| settings.rng.range(range) as f32;
+            let spread = Vec3::new(random([-1., 1.]), random([-1., 1.]), 0.);
+            let velocity = normal * random([0.5, 1.5]) + spread;
+            commands.spawn((
+                PbrBundle {
+                    mesh: assets.mesh.clone(),
+                    material: if dust {
+                        assets.dust[0].clone()
+                    } else {
+                        assets.smoke[0].clone()
+                    },
+                    transform: Transform::from_translation(position + 0.1 * spread),
+                    ..default()
+                },
+                Particle {
+                    velocity,
+                    age: 0.,
+                    rise: if dust { -2. } else { 0.5 },
+                    growth: if dust { 1.5 } else { 3. },
+                    dust,
+                },
+            ));
+        }
+        *owed = owed.min(1.); // don't catch up after hitting the particle limit
+    }
+}
+
+fn tire_particle_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<TireParticles>,
+    mut particles: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        &mut Handle<StandardMaterial>,
+    )>,
+) {
+    let assets = match &settings.assets {
+        Some(assets) => assets,
+        None => return,
+    };
+    let dt = time.delta_seconds();
+    for (entity, mut particle, mut transform, mut material) in particles.iter_mut() {
+        particle.age += dt;
+        if particle.age >= settings.lifetime {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        // air drag slows the particles down
+        let rise = particle.rise;
+        particle.velocity *= (1. - 2. * dt).max(0.);
+        particle.velocity.z += rise * dt;
+        transform.translation += particle.velocity * dt;
+        transform.scale += Vec3::splat(particle.growth * dt);
+
+        let step = ((particle.age / settings.lifetime) * FADE_STEPS as f32) as usize;
+        let fade = if particle.dust {
+            &assets.dust
+        } else {
+            &assets.smoke
+        };
+        let handle = &fade[step.min(FADE_STEPS - 1)];
+        if *material != *handle {
+            *material = handle.clone();
+        }
+    }
+}
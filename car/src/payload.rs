@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use rigid_body::sva::Vector;
+use serde::Deserialize;
+
+use crate::control::CarControl;
+
+// A point mass carried by the chassis: passengers, luggage, a load or ballast
+#[derive(Deserialize, Clone, Debug)]
+pub struct PayloadItem {
+    pub name: String,
+    pub mass: f64,          // kg
+    pub position: [f64; 3], // chassis coordinates
+    #[serde(default)]
+    pub droppable: bool, // ballast that can be dropped while driving
+}
+
+impl PayloadItem {
+    pub fn new(name: &str, mass: f64, position: [f64; 3]) -> Self {
+        Self {
+            name: name.to_string(),
+            mass,
+            position,
+            droppable: false,
+        }
+    }
+
+    pub fn with_droppable(mut self) -> Self {
+        self.droppable = true;
+        self
+    }
+}
+
+// Payload of a car, on the chassis entity. The fuel system adds the items to
+// the chassis mass, center of gravity and inertia every time step, so items
+// can be added or dropped at any time.
+#[derive(Component, Clone, Default)]
+pub struct Payload {
+    pub items: Vec<PayloadItem>,
+    pub outputs: HashMap<String, f64>,
+}
+
+impl Payload {
+    pub fn new(items: Vec<PayloadItem>) -> Self {
+        Self {
+            items,
+            outputs: HashMap::new(),
+        }
+    }
+
+    pub fn mass(&self) -> f64 {
+        self.items.iter().map(|item| item.mass).sum()
+    }
+
+    // mass and position of each item
+    pub fn point_masses(&self) -> Vec<(f64, Vector)> {
+        self.items
+            .iter()
+            .map(|item| {
+                let [x, y, z] = item.position;
+                (item.mass, Vector::new(x, y, z))
+            })
+            .collect()
+    }
+
+    // drops the first droppable item
+    pub fn drop_ballast(&mut self) -> Option<PayloadItem> {
+        let index = self.items.iter().position(|item| item.droppable)?;
+        Some(self.items.remove(index))
+    }
+}
+
+pub fn payload_system(mut cars: Query<(&mut CarControl, &mut Payload)>) {
+    for (mut control, mut payload) in cars.iter_mut() {
+        if !control.drop_ballast {
+            continue;
+        }
+        control.drop_ballast = false;
+        match payload.drop_ballast() {
+            Some(item) => info!("dropped {} ({:.0} kg)", item.name, item.mass),
+            None => info!("no ballast to drop"),
+        }
+    }
+}
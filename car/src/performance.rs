@@ -0,0 +1,116 @@
+use bevy::{
+    diagnostic::{
+        DiagnosticId, DiagnosticsStore, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin,
+    },
+    prelude::*,
+};
+use rigid_body::diagnostics::PhysicsDiagnosticsPlugin;
+
+use crate::diagnostics::CarDiagnosticsPlugin;
+
+// Performance overlay in the top right corner: frame rate and frame time, the
+// physics step time and steps per frame, tire contact points and the entity
+// count, smoothed over the last frames, to see a performance regression while
+// driving. It adds the diagnostics it shows that aren't there yet, so add it
+// after `RigidBodyPlugin`. `F3` toggles it.
+pub struct PerformanceOverlayPlugin;
+
+#[derive(Resource, Clone)]
+pub struct PerformanceOverlay {
+    pub visible: bool,
+    pub key: KeyCode,
+}
+
+impl Default for PerformanceOverlay {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            key: KeyCode::F3,
+        }
+    }
+}
+
+#[derive(Component)]
+struct PerformanceText;
+
+impl Plugin for PerformanceOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<CarDiagnosticsPlugin>() {
+            app.add_plugins(CarDiagnosticsPlugin);
+        }
+        if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+            app.add_plugins(FrameTimeDiagnosticsPlugin);
+        }
+        if !app.is_plugin_added::<EntityCountDiagnosticsPlugin>() {
+            app.add_plugins(EntityCountDiagnosticsPlugin);
+        }
+        app.init_resource::<PerformanceOverlay>()
+            .add_systems(Startup, performance_startup_system)
+            .add_systems(Update, performance_overlay_system);
+    }
+}
+
+fn performance_startup_system(mut commands: Commands, overlay: Res<PerformanceOverlay>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 18.,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            right: Val::Px(10.),
+            padding: UiRect::all(Val::Px(6.)),
+            ..default()
+        })
+        .with_background_color(Color::rgba(0., 0., 0., 0.5)),
+        visibility(overlay.visible),
+        PerformanceText,
+    ));
+}
+
+fn visibility(visible: bool) -> Visibility {
+    if visible {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    }
+}
+
+fn performance_overlay_system(
+    input: Res<Input<KeyCode>>,
+    mut overlay: ResMut<PerformanceOverlay>,
+    diagnostics: Res<DiagnosticsStore>,
+    mut texts: Query<(&mut Text, &mut Visibility), With<PerformanceText>>,
+) {
+    if input.just_pressed(overlay.key) {
+        overlay.visible = !overlay.visible;
+    }
+    let value = |id: DiagnosticId| {
+        diagnostics
+            .get(id)
+            .and_then(|diagnostic| diagnostic.smoothed())
+            .unwrap_or(0.)
+    };
+    for (mut text, mut text_visibility) in texts.iter_mut() {
+        if overlay.is_changed() {
+            *text_visibility = visibility(overlay.visible);
+        }
+        if !overlay.visible {
+            continue;
+        }
+        text.sections[0].value = format!(
+            "{:.0} fps, frame {:.1} ms\nphysics step {:.3} ms, {:.1} steps per frame\n{:.0} tire contact points\n{:.0} entities",
+            value(FrameTimeDiagnosticsPlugin::FPS),
+            value(FrameTimeDiagnosticsPlugin::FRAME_TIME),
+            value(PhysicsDiagnosticsPlugin::STEP_TIME),
+            value(PhysicsDiagnosticsPlugin::STEPS_PER_FRAME),
+            value(CarDiagnosticsPlugin::TIRE_CONTACTS),
+            value(EntityCountDiagnosticsPlugin::ENTITY_COUNT),
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        camera::{CameraProjection, CameraProjectionPlugin},
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        view::{screenshot::ScreenshotManager, ColorGrading},
+    },
+    window::PrimaryWindow,
+};
+use cameras::{camera_az_el::AzElCamera, chase::ChaseCamera, fly::FlyCamera};
+
+use crate::mirror::RearViewMirror;
+
+// Photo mode: pauses the simulation, hides the UI and the mirror, and flies the
+// orbit camera freely (see `FlyCamera`) to frame a picture. `[`/`]` narrow and
+// widen the field of view, `-`/`=` lower and raise the exposure, and `F12`
+// saves a picture of `scale` times the window resolution as a PNG in
+// `directory`. The picture is rendered in `scale` x `scale` tiles, one per
+// frame, each a part of the view, and stitched together. `P` toggles the mode,
+// restoring the view.
+#[derive(Resource)]
+pub struct PhotoMode {
+    pub active: bool,
+    pub scale: u32,
+    pub directory: PathBuf,
+    pub fov_step: f32,      // (rad) per key press
+    pub exposure_step: f32, // (EV) per key press
+    saved: Option<SavedView>,
+    capture: Option<Capture>,
+}
+
+impl Default for PhotoMode {
+    fn default() -> Self {
+        Self {
+            active: false,
+            scale: 2,
+            directory: PathBuf::from("."),
+            fov_step: 0.05,
+            exposure_step: 0.25,
+            saved: None,
+            capture: None,
+        }
+    }
+}
+
+impl PhotoMode {
+    pub fn with_scale(mut self, scale: u32) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    pub fn with_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.directory = directory.into();
+        self
+    }
+}
+
+// the view before the photo mode, restored after it
+struct SavedView {
+    fov: Option<f32>,
+    exposure: f32,
+    flying: bool,
+    paused: bool,
+    mirror: bool,
+}
+
+struct Capture {
+    path: PathBuf,
+    scale: u32,
+    next_tile: u32,
+    tiles: Arc<Mutex<Vec<Option<Image>>>>,
+}
+
+// A part of a perspective view, for rendering a picture in tiles: tile `tile`
+// (column, row from the top left) of `tiles` x `tiles`. The base projection is
+// scaled up and shifted in clip space, so the tiles fit together exactly.
+#[derive(Component, Clone, Default, Reflect)]
+pub struct TileProjection {
+    pub base: PerspectiveProjection,
+    pub tile: [u32; 2],
+    pub tiles: u32,
+}
+
+impl CameraProjection for TileProjection {
+    fn get_projection_matrix(&self) -> Mat4 {
+        let tiles = self.tiles.max(1) as f32;
+        // center of the tile in normalized device coordinates
+        let x = -1. + (2. * self.tile[0] as f32 + 1.) / tiles;
+        let y = 1. - (2. * self.tile[1] as f32 + 1.) / tiles;
+        let tile = Mat4::from_cols(
+            Vec4::new(tiles, 0., 0., 0.),
+            Vec4::new(0., tiles, 0., 0.),
+            Vec4::Z,
+            Vec4::new(-tiles * x, -tiles * y, 0., 1.),
+        );
+        tile * self.base.get_projection_matrix()
+    }
+
+    fn update(&mut self, width: f32, height: f32) {
+        self.base.update(width, height);
+    }
+
+    fn far(&self) -> f32 {
+        self.base.far
+    }
+}
+
+pub fn photo_mode_setup(app: &mut App) {
+    app.init_resource::<PhotoMode>()
+        .add_plugins(CameraProjectionPlugin::<TileProjection>::default())
+        .add_systems(Update, (photo_mode_system, photo_capture_system).chain());
+}
+
+type PhotoCamera<'a> = (
+    Entity,
+    &'a mut Transform,
+    &'a GlobalTransform,
+    &'a mut Projection,
+    &'a mut ColorGrading,
+    &'a mut FlyCamera,
+    &'a AzElCamera,
+);
+
+fn photo_mode_system(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    mut time: ResMut<Time>,
+    mut photo: ResMut<PhotoMode>,
+    mut mirror: Option<ResMut<RearViewMirror>>,
+    mut cameras: Query<PhotoCamera, With<ChaseCamera>>,
+) {
+    let photo = photo.as_mut();
+    let (entity, mut transform, global_transform, mut projection, mut grading, mut fly, az_el) =
+        match cameras.get_single_mut() {
+            Ok(camera) => camera,
+            Err(_) => return,
+        };
+
+    if input.just_pressed(KeyCode::P) && photo.capture.is_none() {
+        photo.active = !photo.active;
+        if photo.active {
+            photo.saved = Some(SavedView {
+                fov: match projection.as_ref() {
+                    Projection::Perspective(perspective) => Some(perspective.fov),
+                    _ => None,
+                },
+                exposure: grading.exposure,
+                flying: fly.active,
+                paused: time.is_paused(),
+                mirror: matches!(&mirror, Some(mirror) if mirror.visible),
+            });
+            time.pause();
+            if !fly.active {
+                fly.start(
+                    &mut transform,
+                    global_transform,
+                    az_el.up_direction.vector(),
+                );
+            }
+            commands
+                .entity(entity)
+                .insert(UiCameraConfig { show_ui: false });
+            if let Some(mirror) = mirror.as_mut() {
+                mirror.visible = false;
+            }
+        } else if let Some(saved) = photo.saved.take() {
+            if let (Some(fov), Projection::Perspective(perspective)) =
+                (saved.fov, projection.as_mut())
+            {
+                perspective.fov = fov;
+            }
+            grading.exposure = saved.exposure;
+            if !saved.flying {
+                fly.stop(&mut transform, az_el);
+            }
+            if !saved.paused {
+                time.unpause();
+            }
+            commands
+                .entity(entity)
+                .insert(UiCameraConfig { show_ui: true });
+            if let Some(mirror) = mirror.as_mut() {
+                mirror.visible = saved.mirror;
+            }
+        }
+    }
+    if !photo.active || photo.capture.is_some() {
+        return;
+    }
+
+    // the projection is only touched when it changes, a changed projection
+    // would take over from the tiles of a picture
+    let mut zoom = 0.;
+    if input.just_pressed(KeyCode::BracketLeft) {
+        zoom -= photo.fov_step;
+    }
+    if input.just_pressed(KeyCode::BracketRight) {
+        zoom += photo.fov_step;
+    }
+    if zoom != 0. {
+        if let Projection::Perspective(perspective) = projection.as_mut() {
+            perspective.fov = (perspective.fov + zoom).clamp(0.05, 2.5);
+        }
+    }
+    if input.just_pressed(KeyCode::Minus) {
+        grading.exposure -= photo.exposure_step;
+    }
+    if input.just_pressed(KeyCode::Equals) {
+        grading.exposure += photo.exposure_step;
+    }
+
+    if input.just_pressed(KeyCode::F12) {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let scale = photo.scale.max(1);
+        photo.capture = Some(Capture {
+            path: photo.directory.join(format!("photo-{}.png", seconds)),
+            scale,
+            next_tile: 0,
+            tiles: Arc::new(Mutex::new(vec![None; (scale * scale) as usize])),
+        });
+    }
+}
+
+// renders the tiles of a picture, one per frame, and saves the picture once all
+// of them are back from the renderer
+fn photo_capture_system(
+    mut commands: Commands,
+    mut photo: ResMut<PhotoMode>,
+    mut screenshots: ResMut<ScreenshotManager>,
+    windows: Query<Entity, With<PrimaryWindow>>,
+    mut cameras: Query<(Entity, &mut Projection), With<ChaseCamera>>,
+) {
+    let capture = match photo.capture.as_mut() {
+        Some(capture) => capture,
+        None => return,
+    };
+    let (window, (camera, mut projection)) = match (windows.get_single(), cameras.get_single_mut())
+    {
+        (Ok(window), Ok(camera)) => (window, camera),
+        _ => return,
+    };
+    let base = match projection.as_ref() {
+        Projection::Perspective(perspective) => perspective.clone(),
+        _ => {
+            warn!("photo mode needs a perspective camera");
+            photo.capture = None;
+            return;
+        }
+    };
+
+    let count = capture.scale * capture.scale;
+    if capture.next_tile < count {
+        let index = capture.next_tile as usize;
+        let tile = [
+            capture.next_tile % capture.scale,
+            capture.next_tile / capture.scale,
+        ];
+        commands.entity(camera).insert(TileProjection {
+            base,
+            tile,
+            tiles: capture.scale,
+        });
+        let tiles = capture.tiles.clone();
+        let requested = screenshots.take_screenshot(window, move |image| {
+            if let Ok(mut tiles) = tiles.lock() {
+                tiles[index] = Some(image);
+            }
+        });
+        if requested.is_ok() {
+            capture.next_tile += 1;
+        }
+        return;
+    }
+
+    let images: Vec<Image> = match capture.tiles.lock() {
+        Ok(mut tiles) if tiles.iter().all(|tile| tile.is_some()) => {
+            tiles.iter_mut().filter_map(|tile| tile.take()).collect()
+        }
+        _ => return,
+    };
+    match save_picture(images, capture.scale, &capture.path) {
+        Ok(_) => info!("photo saved to {}", capture.path.display()),
+        Err(error) => error!("cannot save the photo: {}", error),
+    }
+    // back to the plain projection
+    commands.entity(camera).remove::<TileProjection>();
+    projection.set_changed();
+    photo.capture = None;
+}
+
+// saves the tiles side by side, row by row from the top left
+fn save_picture(tiles: Vec<Image>, scale: u32, path: &Path) -> Result<(), String> {
+    let mut width = 0;
+    let mut height = 0;
+    let mut pixels: Vec<u8> = Vec::new();
+    for (index, tile) in tiles.into_iter().enumerate() {
+        let tile = tile.try_into_dynamic().map_err(|error| error.to_string())?;
+        let tile = tile.to_rgba8();
+        if index == 0 {
+            width = tile.width();
+            height = tile.height();
+            pixels = vec![0; (width * height * scale * scale * 4) as usize];
+        }
+        if tile.width() != width || tile.height() != height {
+            return Err("the window was resized".to_string());
+        }
+        let [column, row] = [index as u32 % scale, index as u32 / scale];
+        let row_bytes = (width * 4) as usize;
+        let tile = tile.into_raw();
+        for y in 0..height {
+            let start = (((row * height + y) * width * scale + column * width) * 4) as usize;
+            let tile_start = y as usize * row_bytes;
+            pixels[start..start + row_bytes]
+                .copy_from_slice(&tile[tile_start..tile_start + row_bytes]);
+        }
+    }
+    let picture = Image::new(
+        Extent3d {
+            width: width * scale,
+            height: height * scale,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        pixels,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    let picture = picture
+        .try_into_dynamic()
+        .map_err(|error| error.to_string())?;
+    picture
+        .to_rgb8()
+        .save(path)
+        .map_err(|error| error.to_string())
+}
@@ -1,18 +1,81 @@
-use std::collections::HashMap;
+use std::sync::Arc;
 
 use bevy::prelude::*;
 
 use rigid_body::joint::Joint;
 
-use crate::interpolate::Interpolator1D;
+use crate::{abs::Abs, damage::Damage};
 
-use super::control::CarControl;
+use super::control::{CarControl, CarPart};
 
 #[derive(Component)]
 pub struct SuspensionComponent {
     stiffness: f64,
     damping: f64,
     preload: f64,
+    bump_travel: f64,         // compression (positive q) to the hard limit
+    droop_travel: f64,        // extension (negative q) to the hard limit
+    bump_stop_length: f64,    // the bump stop engages this far before the limit
+    bump_stop_stiffness: f64, // progressive, force per squared compression of the bump stop
+    limit_stiffness: f64,     // hard limits at the ends of travel
+    limit_damping: f64,
+    controller: Option<SuspensionController>,
+    damping_scale: f64, // on the damping command, e.g. set by the drive mode
+}
+
+// Velocities along the suspension axis (positive upward, in the chassis frame)
+// passed to the suspension controller
+#[derive(Clone, Copy, Debug)]
+pub struct SuspensionState {
+    pub travel: f64,
+    pub travel_rate: f64, // wheel velocity relative to the body
+    pub body_velocity: f64,
+    pub wheel_velocity: f64,
+}
+
+// Damping and preload commanded by the controller, replacing the passive values
+#[derive(Clone, Copy, Debug)]
+pub struct SuspensionCommand {
+    pub damping: f64,
+    pub preload: f64,
+}
+
+// Semi-active skyhook damping, or a user supplied controller
+#[derive(Clone)]
+pub enum SuspensionController {
+    Skyhook {
+        sky_damping: f64, // damping relative to the inertial frame
+        min_damping: f64,
+        max_damping: f64,
+    },
+    Custom(Arc<dyn Fn(SuspensionState, SuspensionCommand) -> SuspensionCommand + Send + Sync>),
+}
+
+impl SuspensionController {
+    // the passive command is the suspension's own damping and preload
+    pub fn command(&self, state: SuspensionState, passive: SuspensionCommand) -> SuspensionCommand {
+        match self {
+            SuspensionController::Skyhook {
+                sky_damping,
+                min_damping,
+                max_damping,
+            } => {
+                // the damper can only dissipate, so it follows the skyhook force
+                // when the body and relative velocity have the same sign
+                let relative = state.body_velocity - state.wheel_velocity;
+                let damping = if state.body_velocity * relative > 0. {
+                    sky_damping * state.body_velocity / relative
+                } else {
+                    *min_damping
+                };
+                SuspensionCommand {
+                    damping: damping.clamp(*min_damping, *max_damping),
+                    preload: passive.preload,
+                }
+            }
+            SuspensionController::Custom(controller) => controller(state, passive),
+        }
+    }
 }
 
 impl SuspensionComponent {
@@ -21,14 +84,84 @@ impl SuspensionComponent {
             stiffness,
             damping,
             preload,
+            bump_travel: f64::INFINITY,
+            droop_travel: f64::INFINITY,
+            bump_stop_length: 0.03,
+            bump_stop_stiffness: 5e6,
+            limit_stiffness: 2e6,
+            limit_damping: 2e4,
+            controller: None,
+            damping_scale: 1.,
         }
     }
+
+    pub fn with_controller(mut self, controller: SuspensionController) -> Self {
+        self.controller = Some(controller);
+        self
+    }
+
+    pub fn with_travel(mut self, bump_travel: f64, droop_travel: f64) -> Self {
+        self.bump_travel = bump_travel;
+        self.droop_travel = droop_travel;
+        self
+    }
+
+    pub fn damping_scale(&self) -> f64 {
+        self.damping_scale
+    }
+
+    pub fn set_damping_scale(&mut self, scale: f64) {
+        self.damping_scale = scale;
+    }
+
+    // compressed into the bump stop
+    pub fn on_bump_stop(&self, q: f64) -> bool {
+        q > self.bump_travel - self.bump_stop_length
+    }
+
+    // bump stop and travel limit force (positive pushes the wheel down)
+    fn limit_force(&self, q: f64, qd: f64) -> f64 {
+        let mut force = 0.;
+        let bump_stop = q - (self.bump_travel - self.bump_stop_length);
+        if bump_stop > 0. {
+            force += self.bump_stop_stiffness * bump_stop * bump_stop;
+        }
+        let bump = q - self.bump_travel;
+        if bump > 0. {
+            force += self.limit_stiffness * bump + self.limit_damping * qd.max(0.);
+        }
+        let droop = -self.droop_travel - q;
+        if droop > 0. {
+            force -= self.limit_stiffness * droop - self.limit_damping * qd.min(0.);
+        }
+        force
+    }
 }
 
 pub fn suspension_system(mut joints: Query<(&mut Joint, &SuspensionComponent)>) {
     for (mut joint, suspension) in joints.iter_mut() {
-        joint.tau -=
-            suspension.stiffness * joint.q + suspension.damping * joint.qd + suspension.preload;
+        let passive = SuspensionCommand {
+            damping: suspension.damping,
+            preload: suspension.preload,
+        };
+        let command = match &suspension.controller {
+            Some(controller) => {
+                // joint velocity is in suspension coordinates, the wheel moves along z
+                let wheel_velocity = joint.v.v.z;
+                let state = SuspensionState {
+                    travel: joint.q,
+                    travel_rate: joint.qd,
+                    body_velocity: wheel_velocity - joint.qd,
+                    wheel_velocity,
+                };
+                controller.command(state, passive)
+            }
+            None => passive,
+        };
+        joint.tau -= suspension.stiffness * joint.q
+            + command.damping * suspension.damping_scale * joint.qd
+            + command.preload
+            + suspension.limit_force(joint.q, joint.qd);
     }
 }
 
@@ -50,9 +183,23 @@ impl Steering {
     }
 }
 
-pub fn steering_system(mut joints: Query<(&mut Joint, &Steering)>, control: Res<CarControl>) {
-    for (mut joint, steering) in joints.iter_mut() {
-        joint.q = control.steering as f64 * steering.max_angle;
+// steering command of a car, with the offset of bent steering
+fn steering_command(control: &CarControl, damage: Option<&Damage>) -> f64 {
+    let offset = match damage {
+        Some(damage) => damage.steering_offset(),
+        None => 0.,
+    };
+    control.steering as f64 + offset
+}
+
+pub fn steering_system(
+    mut joints: Query<(&mut Joint, &Steering, &CarPart)>,
+    controls: Query<(&CarControl, Option<&Damage>)>,
+) {
+    for (mut joint, steering, part) in joints.iter_mut() {
+        if let Ok((control, damage)) = controls.get(part.0) {
+            joint.q = steering_command(control, damage) * steering.max_angle;
+        }
     }
 }
 
@@ -74,11 +221,15 @@ impl SteeringCurvature {
 }
 
 pub fn steering_curvature_system(
-    mut joints: Query<(&mut Joint, &SteeringCurvature)>,
-    control: Res<CarControl>,
+    mut joints: Query<(&mut Joint, &SteeringCurvature, &CarPart)>,
+    controls: Query<(&CarControl, Option<&Damage>)>,
 ) {
-    for (mut joint, steering) in joints.iter_mut() {
-        let vehicle_curvature_target = steering.max_curvature * control.steering as f64;
+    for (mut joint, steering, part) in joints.iter_mut() {
+        let (control, damage) = match controls.get(part.0) {
+            Ok(control) => control,
+            Err(_) => continue,
+        };
+        let vehicle_curvature_target = steering.max_curvature * steering_command(control, damage);
         let wheel_curvature_target =
             vehicle_curvature_target / (1.0 - vehicle_curvature_target * steering.y);
         joint.q = (wheel_curvature_target * steering.x).atan();
@@ -89,7 +240,7 @@ pub fn steering_curvature_system(
 pub enum DriveType {
     None,
     DrivenWheel(DrivenWheel),
-    DrivenWheelLookup(DrivenWheelLookup),
+    Engine, // driven by the car engine through the driveline
 }
 
 #[derive(Component, Clone)]
@@ -110,10 +261,14 @@ impl DrivenWheel {
 }
 
 pub fn driven_wheel_system(
-    mut joints: Query<(&mut Joint, &DrivenWheel)>,
-    control: Res<CarControl>,
+    mut joints: Query<(&mut Joint, &DrivenWheel, &CarPart)>,
+    controls: Query<&CarControl>,
 ) {
-    for (mut joint, driven_wheel) in joints.iter_mut() {
+    for (mut joint, driven_wheel, part) in joints.iter_mut() {
+        let control = match controls.get(part.0) {
+            Ok(control) => control,
+            Err(_) => continue,
+        };
         let power_limited_torque = (driven_wheel.max_power / joint.qd).abs();
         if joint.qd.abs() < driven_wheel.max_speed {
             joint.tau +=
@@ -122,78 +277,57 @@ pub fn driven_wheel_system(
     }
 }
 
-#[derive(Component, Clone)]
-pub struct DrivenWheelLookup {
-    pub name: String,
-    pub torque_lookup: Interpolator1D,
-    pub max_speed: f64,
-    pub max_speed_power: f64,
-    pub outputs: HashMap<String, f64>,
-}
-
-impl DrivenWheelLookup {
-    pub fn new(name: String, speeds: Vec<f64>, torques: Vec<f64>) -> Self {
-        let max_speed = speeds[speeds.len() - 1];
-        let max_speed_power = torques[torques.len() - 1] * max_speed;
-        Self {
-            name,
-            torque_lookup: Interpolator1D::new(speeds, torques),
-            max_speed,
-            max_speed_power,
-            outputs: HashMap::new(),
-        }
-    }
-
-    pub fn limit_torque(&self, speed: f64) -> f64 {
-        let mut sign = speed.signum();
-        if sign == 0. {
-            sign = 1.;
-        }
-        let abs_speed = speed.abs();
-        if abs_speed > self.max_speed {
-            return 0.0;
-        }
-
-        let limit_torque = if abs_speed < self.max_speed {
-            sign * self.torque_lookup.interpolate(abs_speed)
-        } else {
-            sign * self.max_speed_power / abs_speed
-        };
-        limit_torque
-    }
-}
-
-pub fn driven_wheel_lookup_system(
-    mut joints: Query<(&mut Joint, &mut DrivenWheelLookup)>,
-    control: Res<CarControl>,
-) {
-    for (mut joint, mut driven_wheel) in joints.iter_mut() {
-        let torque_limit = driven_wheel.limit_torque(joint.qd).abs();
-        let commanded_torque = control.throttle as f64 * torque_limit;
-        joint.tau += commanded_torque;
-        driven_wheel
-            .outputs
-            .insert("torque".to_string(), commanded_torque);
-        driven_wheel
-            .outputs
-            .insert("torque_limit".to_string(), torque_limit);
-    }
-}
-
+// Brake torque acts through a stiff spring and damper anchored to the wheel
+// position. The torque is limited to the brake capacity, when it saturates the
+// anchor slides with the wheel (kinetic friction), otherwise the wheel is held
+// still (static friction), so a braked car holds on a slope.
 #[derive(Component)]
 pub struct BrakeWheel {
     pub max_torque: f64,
+    pub handbrake_torque: f64, // 0 for wheels without a handbrake
+    pub hold_stiffness: f64,
+    pub hold_damping: f64,
+    pub fade: f64,   // scale on the brake torque, lowered by `BrakeHeat`
+    pub torque: f64, // applied to the wheel in the last evaluation
+    anchor: f64,     // wheel position the brake holds
 }
 
 impl BrakeWheel {
-    pub fn new(max_torque: f64) -> Self {
-        Self { max_torque }
+    pub fn new(max_torque: f64, handbrake_torque: f64) -> Self {
+        Self {
+            max_torque,
+            handbrake_torque,
+            hold_stiffness: 1e4,
+            hold_damping: 200.,
+            fade: 1.,
+            torque: 0.,
+            anchor: 0.,
+        }
     }
 }
 
-pub fn brake_wheel_system(mut joints: Query<(&mut Joint, &BrakeWheel)>, control: Res<CarControl>) {
-    for (mut joint, brake_wheel) in joints.iter_mut() {
-        // TODO: make better? What to do around zero speed?
-        joint.tau += -control.brake as f64 * brake_wheel.max_torque * joint.qd.min(1.).max(-1.);
+pub fn brake_wheel_system(
+    mut joints: Query<(&mut Joint, &mut BrakeWheel, Option<&Abs>, &CarPart)>,
+    controls: Query<&CarControl>,
+) {
+    for (mut joint, mut brake_wheel, abs, part) in joints.iter_mut() {
+        let control = match controls.get(part.0) {
+            Ok(control) => control,
+            Err(_) => continue,
+        };
+        // ABS only modulates the service brake, not the handbrake
+        let pressure = abs.map_or(1., |abs| abs.pressure());
+        let torque = (control.brake as f64 * pressure * brake_wheel.max_torque
+            + control.handbrake as f64 * brake_wheel.handbrake_torque)
+            * brake_wheel.fade;
+
+        let hold = -brake_wheel.hold_stiffness * (joint.q - brake_wheel.anchor)
+            - brake_wheel.hold_damping * joint.qd;
+        if hold.abs() > torque {
+            // slipping, keep the spring at the torque limit
+            brake_wheel.anchor = joint.q + hold.signum() * torque / brake_wheel.hold_stiffness;
+        }
+        brake_wheel.torque = hold.clamp(-torque, torque);
+        joint.tau += brake_wheel.torque;
     }
 }
@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{
+        self,
+        plot::{Legend, Line, Plot},
+    },
+    EguiContexts, EguiPlugin,
+};
+use bevy_integrator::recorder::Recorder;
+
+const CORNERS: [&str; 4] = ["fl", "fr", "rl", "rr"];
+
+// Scrolling plots of recorded telemetry channels in an egui window. Pausing
+// freezes the plots so they can be zoomed (scroll) and dragged back in time.
+// Adds a `Recorder` if there isn't one, the channels come from `telemetry_system`.
+pub struct TelemetryPlotPlugin;
+
+impl Plugin for TelemetryPlotPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+        if !app.world.contains_resource::<Recorder>() {
+            app.insert_resource(Recorder::new(5));
+        }
+        app.init_resource::<TelemetryPlots>()
+            .add_systems(Update, telemetry_plot_system);
+    }
+}
+
+#[derive(Resource)]
+pub struct TelemetryPlots {
+    pub plots: Vec<(String, Vec<String>)>, // title and channels of each plot
+    pub window: f64,                       // time shown while running (s)
+    paused: Option<f64>,                   // time the plots were paused at
+}
+
+impl Default for TelemetryPlots {
+    fn default() -> Self {
+        let corners = |channel: &str| {
+            CORNERS
+                .iter()
+                .map(|corner| channel.replace('*', corner))
+                .collect()
+        };
+        Self {
+            plots: vec![
+                ("slip ratio".to_string(), corners("tire.*.slip_ratio")),
+                (
+                    "suspension travel (m)".to_string(),
+                    corners("suspension.*.travel"),
+                ),
+                (
+                    "yaw rate (rad/s)".to_string(),
+                    vec![
+                        "chassis.yaw_rate".to_string(),
+                        "bicycle.yaw_rate".to_string(),
+                    ],
+                ),
+            ],
+            window: 10.,
+            paused: None,
+        }
+    }
+}
+
+fn telemetry_plot_system(
+    mut contexts: EguiContexts,
+    mut plots: ResMut<TelemetryPlots>,
+    recorder: Option<Res<Recorder>>,
+) {
+    let recorder = match recorder {
+        Some(recorder) => recorder,
+        None => return,
+    };
+    let now = recorder.time().last().copied().unwrap_or(0.);
+
+    egui::Window::new("Telemetry")
+        .default_width(450.)
+        .show(contexts.ctx_mut(), |ui| {
+            let mut reset = false;
+            ui.horizontal(|ui| {
+                let label = if plots.paused.is_some() {
+                    "Resume"
+                } else {
+                    "Pause"
+                };
+                if ui.button(label).clicked() {
+                    plots.paused = match plots.paused {
+                        Some(_) => None,
+                        None => Some(now),
+                    };
+                    reset = true;
+                }
+                ui.add(egui::Slider::new(&mut plots.window, 1.0..=60.0).text("window (s)"));
+            });
+
+            // the whole run is available while paused
+            let (start, end) = match plots.paused {
+                Some(time) => (0., time),
+                None => (now - plots.window, now),
+            };
+            for (title, channels) in plots.plots.iter() {
+                ui.label(title.as_str());
+                let mut plot = Plot::new(title.as_str())
+                    .height(120.)
+                    .legend(Legend::default());
+                if plots.paused.is_none() {
+                    plot = plot
+                        .allow_zoom(false)
+                        .allow_drag(false)
+                        .allow_scroll(false)
+                        .auto_bounds_x()
+                        .auto_bounds_y();
+                }
+                if reset {
+                    plot = plot.reset();
+                }
+                plot.show(ui, |plot_ui| {
+                    for channel in channels {
+                        let samples = recorder.samples(channel, start, end);
+                        plot_ui.line(Line::new(samples).name(channel));
+                    }
+                });
+            }
+        });
+}
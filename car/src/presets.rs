@@ -0,0 +1,331 @@
+use crate::{
+    build::{build_car, sized_wheel, AeroDef, CarDefinition, Drivetrain},
+    differential::DifferentialType,
+    engine::Engine,
+    interpolate::Interpolator1D,
+    physics::{SteeringCurvature, SteeringType},
+    transmission::Transmission,
+    turbo::Turbo,
+};
+
+const GRAVITY: f64 = 9.81;
+
+// Vehicle presets, selectable by name (e.g. from the command line)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Preset {
+    Car,
+    Truck,
+    Kart,
+    Buggy,
+    SixBySix,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 5] = [
+        Preset::Car,
+        Preset::Truck,
+        Preset::Kart,
+        Preset::Buggy,
+        Preset::SixBySix,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Car => "car",
+            Preset::Truck => "truck",
+            Preset::Kart => "kart",
+            Preset::Buggy => "buggy",
+            Preset::SixBySix => "6x6",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "car" => Some(Preset::Car),
+            "truck" => Some(Preset::Truck),
+            "kart" => Some(Preset::Kart),
+            "buggy" => Some(Preset::Buggy),
+            "6x6" => Some(Preset::SixBySix),
+            _ => None,
+        }
+    }
+
+    pub fn build(&self) -> CarDefinition {
+        match self {
+            Preset::Car => build_car(Drivetrain::RearWheelDrive),
+            Preset::Truck => build_truck(),
+            Preset::Kart => build_kart(),
+            Preset::Buggy => build_buggy(),
+            Preset::SixBySix => build_six_by_six(),
+        }
+    }
+}
+
+// Size and ride of a vehicle. Lengths in meters, the suspension height is the
+// wheel center relative to the chassis.
+struct Layout {
+    mass: f64,
+    dimensions: [f64; 3],
+    axles: Vec<f64>, // x positions, front first
+    steered_axles: usize,
+    track: f64,
+    suspension_height: f64,
+    suspension_mass: f64,
+    wheel_radius: f64,
+    wheel_width: f64,
+    wheel_mass: f64,
+    sag: f64, // static suspension deflection
+    damping_ratio: f64,
+    travel: [f64; 2],
+    max_curvature: f64,
+}
+
+// resizes the chassis, suspension and wheels of the car
+fn apply_layout(car: &mut CarDefinition, layout: &Layout) {
+    let mass = layout.mass;
+    let dimensions = layout.dimensions;
+    car.chassis.mass = mass;
+    car.chassis.dimensions = dimensions;
+    car.chassis.moi = [
+        dimensions[1].powi(2) + dimensions[2].powi(2),
+        dimensions[2].powi(2) + dimensions[0].powi(2),
+        dimensions[0].powi(2) + dimensions[1].powi(2),
+    ]
+    .map(|x| mass * (1. / 12.) * x);
+    car.chassis.initial_position[2] = layout.wheel_radius - layout.suspension_height + 0.05;
+
+    car.set_axle_positions(&layout.axles, layout.steered_axles);
+    let corner_mass = mass / car.suspension.len() as f64;
+    let stiffness = corner_mass * GRAVITY / layout.sag;
+    let damping = layout.damping_ratio * 2. * (stiffness * corner_mass).sqrt();
+    for (ind, suspension) in car.suspension.iter_mut().enumerate() {
+        let y = if ind % 2 == 0 { 1. } else { -1. } * layout.track / 2.;
+        suspension.location[1] = y;
+        suspension.location[2] = layout.suspension_height;
+        suspension.mass = layout.suspension_mass;
+        suspension.stiffness = stiffness;
+        suspension.damping = damping;
+        suspension.preload = corner_mass * GRAVITY;
+        suspension.travel = layout.travel;
+    }
+    let wheelbase = car.wheelbase();
+    for (ind, suspension) in car.suspension.iter_mut().enumerate() {
+        if ind < 2 * layout.steered_axles {
+            let x = suspension.location[0] - layout.axles[0] + wheelbase;
+            suspension.steering = SteeringType::Curvature(SteeringCurvature::new(
+                layout.max_curvature,
+                x,
+                suspension.location[1],
+            ));
+        }
+    }
+
+    let tire_model = car.wheel.tire_model.clone();
+    car.wheel = sized_wheel(
+        layout.wheel_radius,
+        layout.wheel_width,
+        layout.wheel_mass,
+        corner_mass + layout.suspension_mass,
+    );
+    car.wheel.tire_model = tire_model;
+}
+
+fn rpm(rpm: f64) -> f64 {
+    rpm * std::f64::consts::PI / 30.
+}
+
+// only body drag
+fn body_drag(area: f64, drag: f64) -> Vec<AeroDef> {
+    vec![AeroDef {
+        position: [0., 0., 0.],
+        area,
+        lift: Interpolator1D::new(vec![0.], vec![0.]),
+        drag: Interpolator1D::new(vec![0.], vec![drag]),
+    }]
+}
+
+// Heavy, rear wheel drive with a solid rear axle and a low revving turbo diesel
+pub fn build_truck() -> CarDefinition {
+    let mut car = build_car(Drivetrain::RearWheelDrive);
+    apply_layout(
+        &mut car,
+        &Layout {
+            mass: 3000.,
+            dimensions: [5.0, 1.9, 0.8],
+            axles: vec![1.7, -1.7],
+            steered_axles: 1,
+            track: 1.7,
+            suspension_height: -0.35,
+            suspension_mass: 60.,
+            wheel_radius: 0.42,
+            wheel_width: 0.25,
+            wheel_mass: 40.,
+            sag: 0.12,
+            damping_ratio: 0.3,
+            travel: [0.15, 0.15],
+            max_curvature: 1. / 8.,
+        },
+    );
+    car.axles[1].solid = true;
+
+    car.engine = Engine::new(
+        [0., 1000., 1500., 2500., 3500., 4000.].map(rpm).to_vec(),
+        vec![300., 550., 650., 600., 480., 400.],
+        0.8,
+        rpm(700.),
+        rpm(4000.),
+    );
+    car.transmission = Transmission::new(
+        vec![5.0, 3.2, 2.1, 1.4, 1.0, 0.8],
+        4.1,
+        rpm(3600.),
+        rpm(1600.),
+        0.4,
+    )
+    .with_reverse(5.0);
+    car.turbo = Some(Turbo::new(0.6, 1.5, rpm(1800.)));
+    car.driveline.damping = 20.;
+    car.driveline.capacity = 1200.;
+    car.driveline.engagement = [rpm(900.), rpm(1500.)];
+
+    car.brake.total_torque = 9000.;
+    car.set_brake_bias(0.6);
+    car.brake.handbrake_torque = 4000.;
+    car.fuel_tank.fuel = 60.;
+    car.fuel_tank.position = [-1.0, 0.6, 0.];
+    car.aero = body_drag(3.5, 0.6);
+    car
+}
+
+// Light, no real suspension, a single gear and a locked rear axle
+pub fn build_kart() -> CarDefinition {
+    let mut car = build_car(Drivetrain::RearWheelDrive);
+    apply_layout(
+        &mut car,
+        &Layout {
+            mass: 160.,
+            dimensions: [1.5, 1.0, 0.2],
+            axles: vec![0.525, -0.525],
+            steered_axles: 1,
+            track: 1.1,
+            suspension_height: -0.02,
+            suspension_mass: 2.,
+            wheel_radius: 0.14,
+            wheel_width: 0.15,
+            wheel_mass: 3.,
+            sag: 0.01,
+            damping_ratio: 0.3,
+            travel: [0.02, 0.02],
+            max_curvature: 1. / 3.,
+        },
+    );
+
+    car.engine = Engine::new(
+        [0., 4000., 8000., 11000., 13000., 14000.].map(rpm).to_vec(),
+        vec![8., 14., 20., 21., 18., 15.],
+        0.02,
+        rpm(2500.),
+        rpm(14000.),
+    );
+    car.transmission = Transmission::new(vec![1.0], 5.5, f64::INFINITY, 0., 0.2).with_reverse(1.0);
+    car.turbo = None;
+    car.driveline.damping = 1.;
+    car.driveline.capacity = 30.;
+    car.driveline.engagement = [rpm(3000.), rpm(5000.)];
+    car.driveline.differential = DifferentialType::Locked;
+    car.driveline.differential_damping = 20.;
+
+    // rear brakes only
+    car.brake.total_torque = 300.;
+    car.set_brake_bias(0.);
+    car.brake.handbrake_torque = 0.;
+    car.brake.abs = None;
+    car.fuel_tank.fuel = 6.;
+    car.fuel_tank.position = [0.2, 0., 0.];
+    car.aero = body_drag(0.6, 0.8);
+    car
+}
+
+// Long travel, soft suspension and all wheel drive for rough terrain
+pub fn build_buggy() -> CarDefinition {
+    let mut car = build_car(Drivetrain::AllWheelDrive { front_split: 0.4 });
+    apply_layout(
+        &mut car,
+        &Layout {
+            mass: 700.,
+            dimensions: [3.2, 1.6, 0.5],
+            axles: vec![1.3, -1.3],
+            steered_axles: 1,
+            track: 1.6,
+            suspension_height: -0.1,
+            suspension_mass: 25.,
+            wheel_radius: 0.38,
+            wheel_width: 0.28,
+            wheel_mass: 25.,
+            sag: 0.15,
+            damping_ratio: 0.35,
+            travel: [0.2, 0.25],
+            max_curvature: 1. / 5.,
+        },
+    );
+    car.aero = body_drag(2.0, 0.5);
+    car
+}
+
+// Off-road truck with a steered front axle, tandem rear axles on beams and all
+// wheel drive
+pub fn build_six_by_six() -> CarDefinition {
+    let mut car = build_car(Drivetrain::AllWheelDrive { front_split: 0.3 });
+    apply_layout(
+        &mut car,
+        &Layout {
+            mass: 6000.,
+            dimensions: [6.5, 2.3, 1.0],
+            axles: vec![2.3, -1.0, -2.4],
+            steered_axles: 1,
+            track: 1.9,
+            suspension_height: -0.45,
+            suspension_mass: 120.,
+            wheel_radius: 0.55,
+            wheel_width: 0.35,
+            wheel_mass: 90.,
+            sag: 0.12,
+            damping_ratio: 0.35,
+            travel: [0.2, 0.2],
+            max_curvature: 1. / 10.,
+        },
+    );
+    car.set_drivetrain(&Drivetrain::AllWheelDrive { front_split: 0.3 });
+    car.axles[1].solid = true;
+    car.axles[2].solid = true;
+
+    car.engine = Engine::new(
+        [0., 1000., 1400., 2000., 2500., 2800.].map(rpm).to_vec(),
+        vec![800., 1400., 1600., 1500., 1250., 1000.],
+        2.,
+        rpm(650.),
+        rpm(2800.),
+    );
+    car.transmission = Transmission::new(
+        vec![7.0, 4.5, 2.9, 1.9, 1.35, 1.0],
+        5.3,
+        rpm(2500.),
+        rpm(1200.),
+        0.5,
+    )
+    .with_reverse(6.5)
+    .with_low_range(2.7);
+    car.turbo = Some(Turbo::new(0.8, 1.8, rpm(1300.)));
+    car.driveline.damping = 40.;
+    car.driveline.capacity = 3000.;
+    car.driveline.engagement = [rpm(800.), rpm(1300.)];
+    car.driveline.differential = DifferentialType::Locked;
+
+    car.brake.total_torque = 24000.;
+    car.set_brake_bias(0.4);
+    car.brake.handbrake_torque = 6000.;
+    car.fuel_tank.fuel = 200.;
+    car.fuel_tank.position = [0., 0.9, -0.2];
+    car.aero = body_drag(6.0, 0.7);
+    car
+}
@@ -0,0 +1,271 @@
+use bevy::prelude::*;
+use bevy_integrator::SimTime;
+use cameras::director::CameraDirector;
+use grid_terrain::rng::SplitMix64;
+use rigid_body::joint::Joint;
+
+use crate::{
+    ai::{closest_point, distance, planar_pose, AiDriver},
+    build::{spawn_car, CarDefinition, CarEntities},
+};
+
+// Race around a closed path. The start line is at the first path point and the
+// grid lines up behind it. Every car with a `Racer` component is tracked, and the
+// AI drivers move over to pass slower cars in front of them.
+#[derive(Resource)]
+pub struct Race {
+    pub path: Vec<[f64; 2]>,
+    pub half_width: f64,        // room either side of the path for passing
+    pub clearance: f64,         // lateral distance between cars to pass safely
+    pub grid_spacing: f64,      // distance between grid slots along the path
+    pub standings: Vec<Entity>, // chassis entities, leader first
+    distances: Vec<f64>,        // along the path at each point
+    length: f64,
+}
+
+impl Race {
+    pub fn new(path: Vec<[f64; 2]>, half_width: f64) -> Self {
+        let n = path.len();
+        let mut distances = Vec::with_capacity(n);
+        let mut length = 0.;
+        for i in 0..n {
+            distances.push(length);
+            length += distance(path[i], path[(i + 1) % n]);
+        }
+        Self {
+            path,
+            half_width,
+            clearance: 2.5,
+            grid_spacing: 8.,
+            standings: Vec::new(),
+            distances,
+            length,
+        }
+    }
+
+    pub fn length(&self) -> f64 {
+        self.length
+    }
+
+    // position and heading of a grid slot, slot 0 is at the front. The cars line
+    // up in two staggered columns.
+    pub fn grid_slot(&self, slot: usize) -> ([f64; 2], f64) {
+        let back = (slot + 1) as f64 * self.grid_spacing;
+        let along = (self.length - back).rem_euclid(self.length);
+        let n = self.path.len();
+        let i = self
+            .distances
+            .iter()
+            .rposition(|&distance| distance <= along)
+            .unwrap_or(0);
+        let [a, b] = [self.path[i], self.path[(i + 1) % n]];
+        let segment = distance(a, b).max(1e-6);
+        let t = (along - self.distances[i]) / segment;
+        let tangent = [(b[0] - a[0]) / segment, (b[1] - a[1]) / segment];
+        let side = if slot % 2 == 0 { 0.4 } else { -0.4 } * self.half_width;
+        let position = [
+            a[0] + t * (b[0] - a[0]) - side * tangent[1],
+            a[1] + t * (b[1] - a[1]) + side * tangent[0],
+        ];
+        (position, tangent[1].atan2(tangent[0]))
+    }
+
+    // replay cameras around the track, trackside ones outside of the passing room
+    pub fn camera_director(&self) -> CameraDirector {
+        let mut path: Vec<Vec3> = self
+            .path
+            .iter()
+            .map(|point| Vec3::new(point[0] as f32, point[1] as f32, 0.))
+            .collect();
+        // the path is closed
+        if let Some(&first) = path.first() {
+            path.push(first);
+        }
+        CameraDirector::along_path(&path, 40., self.half_width as f32 + 8., 3.)
+    }
+}
+
+// Progress of a car in the race, on the chassis entity
+#[derive(Component)]
+pub struct Racer {
+    pub name: String,
+    pub lap: i32,        // completed laps, -1 on the grid behind the start line
+    pub distance: f64,   // along the path since the start line, laps included
+    pub position: usize, // race position, the leader is 1
+    pub lap_time: f64,   // since the start of the current lap
+    pub last_lap: Option<f64>,
+    pub best_lap: Option<f64>,
+    index: Option<usize>,
+    lap_start: Option<f64>, // time the current lap started
+}
+
+impl Racer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            lap: 0,
+            distance: 0.,
+            position: 0,
+            lap_time: 0.,
+            last_lap: None,
+            best_lap: None,
+            index: None,
+            lap_start: None,
+        }
+    }
+}
+
+pub fn race_progress_system(
+    time: Res<SimTime>,
+    race: Option<ResMut<Race>>,
+    mut racers: Query<(Entity, &Joint, &mut Racer)>,
+) {
+    let mut race = match race {
+        Some(race) => race,
+        None => return,
+    };
+    let n = race.path.len();
+    let now = time.time();
+    for (_, joint, mut racer) in racers.iter_mut() {
+        let lap_start = *racer.lap_start.get_or_insert(now);
+        let (position, _) = planar_pose(joint);
+        let index = closest_point(&race.path, position, racer.index);
+        match racer.index {
+            // crossing the start line, forwards or backwards
+            Some(previous) if previous > 3 * n / 4 && index < n / 4 => {
+                racer.lap += 1;
+                info!("{} completed lap {}", racer.name, racer.lap);
+                // leaving the grid starts the first lap without completing one
+                if racer.lap > 0 {
+                    let lap_time = now - lap_start;
+                    racer.last_lap = Some(lap_time);
+                    racer.best_lap =
+                        Some(racer.best_lap.map_or(lap_time, |best| best.min(lap_time)));
+                }
+                racer.lap_start = Some(now);
+            }
+            Some(previous) if previous < n / 4 && index > 3 * n / 4 => racer.lap -= 1,
+            None if index > n / 2 => racer.lap = -1,
+            _ => {}
+        }
+        racer.index = Some(index);
+        racer.lap_time = now - racer.lap_start.unwrap_or(now);
+        racer.distance = racer.lap as f64 * race.length + race.distances[index];
+    }
+
+    let mut standings: Vec<(Entity, f64)> = racers
+        .iter()
+        .map(|(entity, _, racer)| (entity, racer.distance))
+        .collect();
+    standings.sort_by(|a, b| b.1.total_cmp(&a.1));
+    for (position, (entity, _)) in standings.iter().enumerate() {
+        if let Ok((_, _, mut racer)) = racers.get_mut(*entity) {
+            racer.position = position + 1;
+        }
+    }
+    race.standings = standings.into_iter().map(|(entity, _)| entity).collect();
+}
+
+// AI drivers look for cars ahead of them. They move over to pass on the side
+// with more room, and slow down to follow when there isn't room to pass.
+pub fn race_avoidance_system(
+    time: Res<Time>,
+    race: Option<Res<Race>>,
+    cars: Query<(Entity, &Joint), With<Racer>>,
+    mut drivers: Query<(Entity, &Joint, &mut AiDriver)>,
+) {
+    let race = match race {
+        Some(race) => race,
+        None => return,
+    };
+    let dt = time.delta_seconds() as f64;
+    for (entity, joint, mut driver) in drivers.iter_mut() {
+        let (position, heading) = planar_pose(joint);
+        let speed = joint.v.v.x;
+        let look_ahead = 5. + 1.5 * speed.max(0.);
+
+        // closest car ahead in the driver's lane, lateral is its offset from the path
+        let mut blocking: Option<(f64, f64, f64)> = None; // distance, lateral, speed
+        for (other, other_joint) in cars.iter() {
+            if other == entity {
+                continue;
+            }
+            let (other_position, _) = planar_pose(other_joint);
+            let [dx, dy] = [
+                other_position[0] - position[0],
+                other_position[1] - position[1],
+            ];
+            let ahead = heading.cos() * dx + heading.sin() * dy;
+            let lateral = -heading.sin() * dx + heading.cos() * dy + driver.offset;
+            let in_lane = (lateral - driver.offset).abs() < race.clearance;
+            let closer = blocking.map_or(true, |(closest, _, _)| ahead < closest);
+            if ahead > 0. && ahead < look_ahead && in_lane && closer {
+                blocking = Some((ahead, lateral, other_joint.v.v.x));
+            }
+        }
+
+        let (target_offset, speed_limit) = match blocking {
+            Some((_, lateral, other_speed)) => {
+                // pass on the side with more room
+                let limit = race.half_width - 0.5 * race.clearance;
+                let offset = if lateral > 0. {
+                    lateral - race.clearance
+                } else {
+                    lateral + race.clearance
+                };
+                if offset.abs() <= limit {
+                    (offset, f64::INFINITY)
+                } else {
+                    (driver.offset, other_speed)
+                }
+            }
+            None => (0., f64::INFINITY),
+        };
+        // move over gradually
+        let max_change = 2. * dt;
+        driver.offset += (target_offset - driver.offset).clamp(-max_change, max_change);
+        driver.speed_limit = speed_limit;
+    }
+}
+
+// Spawns AI opponents on the grid, from slot `first_slot` back. The drivers get
+// slightly different speed, grip and lookahead parameters, the same seed always
+// gives the same field.
+pub fn spawn_opponents(
+    commands: &mut Commands,
+    car: &CarDefinition,
+    base_id: Entity,
+    race: &Race,
+    count: usize,
+    first_slot: usize,
+    seed: u64,
+) -> Vec<CarEntities> {
+    let mut rng = SplitMix64(seed);
+    let max_curvature = car.max_curvature().unwrap_or(0.2);
+    (0..count)
+        .map(|ind| {
+            let (position, heading) = race.grid_slot(first_slot + ind);
+            let color = Color::hsl(360. * ind as f32 / count as f32, 0.7, 0.5);
+            let entities = spawn_car(
+                commands,
+                car,
+                base_id,
+                [position[0], position[1], car.initial_position()[2]],
+                heading,
+                color,
+            );
+            let driver = AiDriver::new(
+                race.path.clone(),
+                max_curvature,
+                rng.range([26., 32.]),
+                rng.range([6., 8.]),
+                rng.range([5., 7.]),
+            )
+            .with_lookahead(4., rng.range([0.5, 0.7]));
+            commands
+                .entity(entities.chassis)
+                .insert((driver, Racer::new(format!("AI {}", ind + 1))));
+            entities
+        })
+        .collect()
+}
@@ -0,0 +1,128 @@
+use bevy::prelude::*;
+use bevy_integrator::{PhysicsState, SimTime};
+use grid_terrain::GridTerrain;
+use rigid_body::{
+    joint::{Joint, JointState},
+    sva::Vector,
+};
+
+use crate::{ai::planar_pose, bindings::InputBindings, control::UserControl, engine::Engine};
+
+// Recovery of a car that is flipped or stuck, on the chassis entity. The car is
+// put back upright at its last checkpoint, or where it is when there is none.
+// Checkpoints are taken while the car is upright, or set with `set_checkpoint`.
+#[derive(Component, Clone)]
+pub struct CarRecovery {
+    pub joints: [Entity; 6], // chassis joints px, py, pz, rx, ry, rz
+    pub height: f64,         // of the chassis above the terrain when respawned
+    // time between checkpoints (s), 0 to only set them manually
+    pub checkpoint_interval: f64,
+    checkpoint: Option<([f64; 2], f64)>, // x, y and heading
+    last_checkpoint: f64,                // time of the last checkpoint
+}
+
+impl CarRecovery {
+    pub fn new(joints: [Entity; 6], height: f64) -> Self {
+        Self {
+            joints,
+            height,
+            checkpoint_interval: 2.,
+            checkpoint: None,
+            last_checkpoint: f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn set_checkpoint(&mut self, position: [f64; 2], heading: f64) {
+        self.checkpoint = Some((position, heading));
+    }
+
+    pub fn checkpoint(&self) -> Option<([f64; 2], f64)> {
+        self.checkpoint
+    }
+}
+
+// Takes a checkpoint every `checkpoint_interval` while the chassis is upright
+pub fn checkpoint_system(time: Res<SimTime>, mut cars: Query<(&Joint, &mut CarRecovery)>) {
+    for (joint, mut recovery) in cars.iter_mut() {
+        if recovery.checkpoint_interval <= 0.
+            || time.time() - recovery.last_checkpoint < recovery.checkpoint_interval
+        {
+            continue;
+        }
+        // chassis z axis within about 25 degrees of vertical
+        let up = joint.x.inverse() * Vector::z();
+        if up.z > 0.9 {
+            let (position, heading) = planar_pose(joint);
+            recovery.set_checkpoint(position, heading);
+            recovery.last_checkpoint = time.time();
+        }
+    }
+}
+
+// The reset binding (T key or the gamepad select button by default) respawns the player's car upright,
+// at rest, above the terrain at the last checkpoint. The joint states are set in
+// `PhysicsState`, so the integrator continues from the new state.
+#[allow(clippy::too_many_arguments)]
+pub fn car_recovery_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    button_inputs: Res<Input<GamepadButton>>,
+    bindings: Res<InputBindings>,
+    terrain: Option<Res<GridTerrain>>,
+    mut physics_state: ResMut<PhysicsState<Joint>>,
+    cars: Query<(&UserControl, &CarRecovery)>,
+    mut joints: Query<(&mut Joint, Option<&Engine>)>,
+    children: Query<&Children>,
+) {
+    for (user, recovery) in cars.iter() {
+        let keyboard = user.keyboard
+            && keyboard_input.any_just_pressed(bindings.keyboard.reset.iter().copied());
+        let gamepad = gamepads.iter().any(|gamepad| {
+            let reset = match bindings.gamepad(gamepad).reset {
+                Some(button) => button_inputs.just_pressed(GamepadButton::new(gamepad, button)),
+                None => false,
+            };
+            user.gamepad.accepts(gamepad) && reset
+        });
+        if !keyboard && !gamepad {
+            continue;
+        }
+
+        let [px, py, pz, rx, ry, rz] = recovery.joints;
+        let ([x, y], heading) = match recovery.checkpoint {
+            Some(checkpoint) => checkpoint,
+            None => match joints.get(rx) {
+                Ok((chassis, _)) => planar_pose(chassis),
+                Err(_) => continue,
+            },
+        };
+        let ground = terrain.as_ref().map_or(0., |terrain| terrain.height(x, y));
+        let chassis_states = [
+            (px, x),
+            (py, y),
+            (pz, ground + recovery.height),
+            (rx, 0.),
+            (ry, 0.),
+            (rz, heading),
+        ];
+
+        // the chassis is placed at rest, the joints below it are stopped where
+        // they are, except for the engine which keeps running
+        let mut states: Vec<(Entity, f64)> = chassis_states.to_vec();
+        for entity in children.iter_descendants(rx) {
+            if let Ok((joint, engine)) = joints.get(entity) {
+                if engine.is_none() {
+                    states.push((entity, joint.q));
+                }
+            }
+        }
+        for (entity, q) in states {
+            if let Ok((mut joint, _)) = joints.get_mut(entity) {
+                joint.q = q;
+                joint.qd = 0.;
+                physics_state.states.insert(entity, JointState::new(q, 0.));
+            }
+        }
+        info!("car respawned at x {:.1}, y {:.1}", x, y);
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_integrator::{ExitEvent, SimTime};
+
+use crate::{
+    build::CarEntities,
+    control::{CarControl, UserControl},
+};
+
+pub(crate) const HEADER: &str =
+    "time,throttle,steering,brake,handbrake,clutch,gear_up,gear_down,reverse,toggle_abs";
+
+// Driver inputs at a time step. The steering is the filtered steering command,
+// so playback doesn't depend on the frame rate.
+#[derive(Clone, PartialEq)]
+pub(crate) struct InputSample {
+    pub time: f64,
+    throttle: f32,
+    steering: f32,
+    brake: f32,
+    handbrake: f32,
+    clutch: f32,
+    gear_up: bool,
+    gear_down: bool,
+    reverse: bool,
+    toggle_abs: bool,
+}
+
+impl InputSample {
+    pub fn from_control(time: f64, control: &CarControl) -> Self {
+        Self {
+            time,
+            throttle: control.throttle,
+            steering: control.steering,
+            brake: control.brake,
+            handbrake: control.handbrake,
+            clutch: control.clutch,
+            gear_up: control.gear_up,
+            gear_down: control.gear_down,
+            reverse: control.reverse,
+            toggle_abs: control.toggle_abs,
+        }
+    }
+
+    fn apply(&self, control: &mut CarControl) {
+        control.throttle = self.throttle;
+        control.steering = self.steering;
+        control.steering_input = self.steering;
+        control.brake = self.brake;
+        control.handbrake = self.handbrake;
+        control.clutch = self.clutch;
+        control.gear_up = self.gear_up;
+        control.gear_down = self.gear_down;
+        control.reverse = self.reverse;
+        control.toggle_abs = self.toggle_abs;
+    }
+
+    // same inputs, at any time
+    pub fn same_inputs(&self, other: &Self) -> bool {
+        let mut other = other.clone();
+        other.time = self.time;
+        *self == other
+    }
+
+    pub fn to_line(&self) -> String {
+        let flag = |flag: bool| if flag { "1" } else { "0" };
+        format!(
+            "{},{},{},{},{},{},{},{},{},{}",
+            self.time,
+            self.throttle,
+            self.steering,
+            self.brake,
+            self.handbrake,
+            self.clutch,
+            flag(self.gear_up),
+            flag(self.gear_down),
+            flag(self.reverse),
+            flag(self.toggle_abs)
+        )
+    }
+
+    fn from_line(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        if fields.len() != 10 {
+            return Err(format!("expected 10 fields, found {}", fields.len()));
+        }
+        let number = |ind: usize| {
+            fields[ind]
+                .parse::<f64>()
+                .map_err(|error| format!("field {}: {}", ind + 1, error))
+        };
+        let flag = |ind: usize| number(ind).map(|value| value != 0.);
+        Ok(Self {
+            time: number(0)?,
+            throttle: number(1)? as f32,
+            steering: number(2)? as f32,
+            brake: number(3)? as f32,
+            handbrake: number(4)? as f32,
+            clutch: number(5)? as f32,
+            gear_up: flag(6)?,
+            gear_down: flag(7)?,
+            reverse: flag(8)?,
+            toggle_abs: flag(9)?,
+        })
+    }
+}
+
+// Records the driver inputs of a car (on the chassis entity) every time step,
+// and writes them to a CSV file when the simulation exits. Only changes are
+// stored, an input holds until the next sample.
+#[derive(Component)]
+pub struct InputRecorder {
+    pub path: PathBuf,
+    samples: Vec<InputSample>,
+}
+
+impl InputRecorder {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn write(&self) -> Result<(), String> {
+        let write = || -> std::io::Result<()> {
+            let mut file = BufWriter::new(fs::File::create(&self.path)?);
+            writeln!(file, "{}", HEADER)?;
+            for sample in self.samples.iter() {
+                writeln!(file, "{}", sample.to_line())?;
+            }
+            file.flush()
+        };
+        write().map_err(|error| format!("writing {}: {}", self.path.display(), error))
+    }
+}
+
+// Feeds recorded driver inputs to a car in place of a player, so the same inputs
+// can be re-run after changing the car or the terrain. The car must not have a
+// `UserControl`.
+#[derive(Component)]
+pub struct InputPlayback {
+    samples: Vec<InputSample>,
+    index: usize,
+}
+
+impl InputPlayback {
+    // back to the first sample, when the simulation time starts over
+    pub fn rewind(&mut self) {
+        self.index = 0;
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("reading {}: {}", path.display(), error))?;
+        let samples = text
+            .lines()
+            .enumerate()
+            .skip(1) // header
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(ind, line)| {
+                InputSample::from_line(line)
+                    .map_err(|error| format!("{} line {}: {}", path.display(), ind + 1, error))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { samples, index: 0 })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.index + 1 >= self.samples.len()
+    }
+}
+
+// Runs before the integrator, so the recorded inputs are the ones used for the
+// time step. Shift and ABS requests are recorded before they are cleared.
+pub fn input_record_system(time: Res<SimTime>, mut cars: Query<(&CarControl, &mut InputRecorder)>) {
+    for (control, mut recorder) in cars.iter_mut() {
+        let sample = InputSample::from_control(time.time(), control);
+        let changed = match recorder.samples.last() {
+            Some(last) => !last.same_inputs(&sample),
+            None => true,
+        };
+        if changed {
+            recorder.samples.push(sample);
+        }
+    }
+}
+
+pub fn input_playback_system(
+    time: Res<SimTime>,
+    mut cars: Query<(&mut CarControl, &mut InputPlayback)>,
+) {
+    // the sample at or before the current time, allowing for rounding
+    let now = time.time() + 0.5 * time.dt;
+    for (mut control, mut playback) in cars.iter_mut() {
+        while playback.index + 1 < playback.samples.len()
+            && playback.samples[playback.index + 1].time <= now
+        {
+            playback.index += 1;
+        }
+        if let Some(sample) = playback.samples.get(playback.index) {
+            if sample.time <= now {
+                sample.apply(&mut control);
+            }
+        }
+    }
+}
+
+pub fn input_record_write_system(
+    mut exit: EventReader<ExitEvent>,
+    recorders: Query<&InputRecorder>,
+) {
+    if exit.is_empty() {
+        return;
+    }
+    exit.clear();
+    for recorder in recorders.iter() {
+        match recorder.write() {
+            Ok(()) => info!("driver inputs written to {}", recorder.path.display()),
+            Err(error) => warn!("{}", error),
+        }
+    }
+}
+
+// Files for recording or playing back the driver inputs of the car in `CarEntities`
+#[derive(Resource, Default)]
+pub struct InputReplayFiles {
+    pub record: Option<PathBuf>,
+    pub playback: Option<PathBuf>,
+}
+
+// Run after the car is spawned. Playback replaces the player's control.
+pub fn input_replay_startup_system(
+    mut commands: Commands,
+    files: Option<Res<InputReplayFiles>>,
+    car: Option<Res<CarEntities>>,
+) {
+    let (files, car) = match (files, car) {
+        (Some(files), Some(car)) => (files, car),
+        _ => return,
+    };
+    if let Some(path) = &files.record {
+        commands
+            .entity(car.chassis)
+            .insert(InputRecorder::new(path));
+    }
+    if let Some(path) = &files.playback {
+        match InputPlayback::from_file(path) {
+            Ok(playback) => {
+                commands
+                    .entity(car.chassis)
+                    .remove::<UserControl>()
+                    .insert(playback);
+            }
+            Err(error) => warn!("driver inputs not played back, {}", error),
+        }
+    }
+}
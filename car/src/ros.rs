@@ -0,0 +1,314 @@
+use std::{collections::HashSet, io, net::UdpSocket};
+
+use bevy::prelude::*;
+use bevy_integrator::SimTime;
+use rigid_body::{
+    joint::Joint,
+    sva::{Vector, Xform},
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    build::CarEntities,
+    control::CarControl,
+    frames::{joint_frames, pose},
+    lidar::Lidar,
+    sensors::Imu,
+};
+
+// Bridge to ROS 2 for the car in `CarEntities`. Insert the resource to send the
+// car's state `rate` times a second, as JSON over UDP to `address`, where the
+// `ros2_bridge.py` node (car/examples) publishes it on ROS 2 topics:
+// - `/clock`: simulation time, for nodes with `use_sim_time`
+// - `/odom`: chassis pose in the world frame, twist in the chassis frame
+// - `/imu`: IMU outputs, if the chassis has one, with the chassis orientation
+// - `/wheel_speeds`: angle and speed of the wheels, by corner
+// - `/tf`: every joint frame relative to its parent, named after the joints
+// - `/points`: the point cloud of each lidar scan, if the chassis has a lidar,
+//   in the `lidar` frame. Scans are sent in parts that fit in a datagram.
+// Drive commands sent back by the node (`/cmd_drive`) set the `CarControl`,
+// until none has come for `command_timeout`. Axes follow REP 103: x forward,
+// y left and z up.
+#[derive(Resource)]
+pub struct RosBridge {
+    pub address: String,      // bridge node, host:port
+    pub rate: f64,            // state messages per second
+    pub world_frame: String,  // frame of the base joint
+    pub command_timeout: f64, // seconds without a drive command before it is dropped
+    socket: Option<UdpSocket>,
+    timer: f64,
+    command: Option<DriveCommand>,
+    command_age: f64,
+    lidar_scan: usize,              // last scan sent
+    errors: HashSet<io::ErrorKind>, // kinds of socket errors already warned about
+}
+
+impl RosBridge {
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            rate: 50.,
+            world_frame: "world".to_string(),
+            command_timeout: 0.5,
+            socket: None,
+            timer: 0.,
+            command: None,
+            command_age: 0.,
+            lidar_scan: 0,
+            errors: HashSet::new(),
+        }
+    }
+
+    pub fn with_rate(mut self, rate: f64) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    pub fn with_world_frame(mut self, frame: &str) -> Self {
+        self.world_frame = frame.to_string();
+        self
+    }
+
+    fn socket(&mut self) -> Option<&UdpSocket> {
+        if self.socket.is_none() {
+            // the node sends the commands back to where the state comes from
+            match UdpSocket::bind("0.0.0.0:0").and_then(|socket| {
+                socket.set_nonblocking(true)?;
+                Ok(socket)
+            }) {
+                Ok(socket) => self.socket = Some(socket),
+                Err(error) => self.warn_once("socket", error),
+            }
+        }
+        self.socket.as_ref()
+    }
+
+    fn send(&mut self, topic: &str, message_type: &str, message: Value) {
+        self.send_packet(json!({"topic": topic, "type": message_type, "msg": message}));
+    }
+
+    fn send_packet(&mut self, packet: Value) {
+        let address = self.address.clone();
+        let sent = match self.socket() {
+            Some(socket) => socket.send_to(packet.to_string().as_bytes(), address.as_str()),
+            None => return,
+        };
+        if let Err(error) = sent {
+            self.warn_once("send", error);
+        }
+    }
+
+    // the first error of each kind, the same one would come again every frame
+    fn warn_once(&mut self, context: &str, error: io::Error) {
+        if self.errors.insert(error.kind()) {
+            warn!("ROS bridge {}: {}", context, error);
+        }
+    }
+}
+
+// the largest UDP payload over IPv4
+const MAX_DATAGRAM: usize = 65_507;
+// room for the topic, type, header and part numbers of a lidar packet
+const LIDAR_PACKET_OVERHEAD: usize = 512;
+
+// lidar points in each packet of a scan, so that the packets fit in a datagram
+// with points of at most `digits` characters (sign included) per coordinate
+fn lidar_part(digits: usize) -> usize {
+    // `[x,y,z],`
+    let point = 3 * digits + 5;
+    (MAX_DATAGRAM - LIDAR_PACKET_OVERHEAD) / point
+}
+
+// driver inputs from the `/cmd_drive` topic, in the `CarControl` ranges
+#[derive(Deserialize, Clone, Copy)]
+struct DriveCommand {
+    steering: f32,
+    throttle: f32,
+    brake: f32,
+    #[serde(default)]
+    handbrake: f32,
+    #[serde(default)]
+    clutch: f32,
+}
+
+fn stamp(time: f64) -> Value {
+    let sec = time.floor();
+    json!({"sec": sec as i64, "nanosec": ((time - sec) * 1e9) as u32})
+}
+
+fn vector(vector: Vector) -> Value {
+    json!({"x": vector.x, "y": vector.y, "z": vector.z})
+}
+
+pub fn ros_command_system(
+    time: Res<Time>,
+    bridge: Option<ResMut<RosBridge>>,
+    car: Option<Res<CarEntities>>,
+    mut controls: Query<&mut CarControl>,
+) {
+    let (mut bridge, car) = match (bridge, car) {
+        (Some(bridge), Some(car)) => (bridge, car),
+        _ => return,
+    };
+    // the latest command of the frame
+    let mut buffer = [0; 1024];
+    let mut received = None;
+    if let Some(socket) = bridge.socket() {
+        while let Ok((length, _)) = socket.recv_from(&mut buffer) {
+            match serde_json::from_slice::<DriveCommand>(&buffer[..length]) {
+                Ok(command) => received = Some(command),
+                Err(error) => warn!("ROS bridge drive command: {}", error),
+            }
+        }
+    }
+    if let Some(command) = received {
+        bridge.command = Some(command);
+        bridge.command_age = 0.;
+    }
+    bridge.command_age += time.delta_seconds_f64();
+    if bridge.command_age > bridge.command_timeout {
+        bridge.command = None;
+    }
+
+    if let (Some(command), Ok(mut control)) = (bridge.command, controls.get_mut(car.chassis)) {
+        control.steering_input = command.steering.clamp(-1., 1.);
+        control.throttle = command.throttle.clamp(0., 1.);
+        control.brake = command.brake.clamp(0., 1.);
+        control.handbrake = command.handbrake.clamp(0., 1.);
+        control.clutch = command.clutch.clamp(0., 1.);
+    }
+}
+
+pub fn ros_state_system(
+    time: Res<Time>,
+    sim_time: Res<SimTime>,
+    bridge: Option<ResMut<RosBridge>>,
+    car: Option<Res<CarEntities>>,
+    joints: Query<(&Joint, Option<&Parent>)>,
+    imus: Query<&Imu>,
+    lidars: Query<&Lidar>,
+) {
+    let (mut bridge, car) = match (bridge, car) {
+        (Some(bridge), Some(car)) => (bridge, car),
+        _ => return,
+    };
+    bridge.timer += time.delta_seconds_f64();
+    if bridge.timer < 1. / bridge.rate {
+        return;
+    }
+    bridge.timer = 0.;
+    let stamp = stamp(sim_time.time());
+    let world_frame = bridge.world_frame.clone();
+    bridge.send("/clock", "rosgraph_msgs/msg/Clock", json!({"clock": stamp}));
+
+    let chassis = match joints.get(car.chassis) {
+        Ok((chassis, _)) => chassis,
+        Err(_) => return,
+    };
+    let (position, orientation) = pose(&chassis.x);
+    let header = |frame: &str| json!({"stamp": stamp, "frame_id": frame});
+    bridge.send(
+        "/odom",
+        "nav_msgs/msg/Odometry",
+        json!({
+            "header": header(&world_frame),
+            "child_frame_id": chassis.name,
+            "pose": {"pose": {"position": position, "orientation": orientation}},
+            "twist": {"twist": {"linear": vector(chassis.v.v), "angular": vector(chassis.v.w)}},
+        }),
+    );
+
+    if let Ok(imu) = imus.get(car.chassis) {
+        let output = |name: &str| imu.outputs.get(name).copied().unwrap_or(0.);
+        let accel = Vector::new(output("accel_x"), output("accel_y"), output("accel_z"));
+        let gyro = Vector::new(output("gyro_x"), output("gyro_y"), output("gyro_z"));
+        let accel_variance = imu.accel_noise.powi(2);
+        let gyro_variance = imu.gyro_noise.powi(2);
+        bridge.send(
+            "/imu",
+            "sensor_msgs/msg/Imu",
+            json!({
+                "header": header(&chassis.name),
+                "orientation": orientation,
+                "angular_velocity": vector(gyro),
+                "angular_velocity_covariance":
+                    [gyro_variance, 0., 0., 0., gyro_variance, 0., 0., 0., gyro_variance],
+                "linear_acceleration": vector(accel),
+                "linear_acceleration_covariance":
+                    [accel_variance, 0., 0., 0., accel_variance, 0., 0., 0., accel_variance],
+            }),
+        );
+    }
+
+    let mut names = Vec::new();
+    let mut angles = Vec::new();
+    let mut speeds = Vec::new();
+    for (corner, wheel) in car.corners.iter().zip(&car.wheels) {
+        if let Ok((joint, _)) = joints.get(*wheel) {
+            names.push(corner.clone());
+            angles.push(joint.q);
+            speeds.push(joint.qd);
+        }
+    }
+    bridge.send(
+        "/wheel_speeds",
+        "sensor_msgs/msg/JointState",
+        json!({"header": header(""), "name": names, "position": angles, "velocity": speeds}),
+    );
+
+    let mut transforms = Vec::new();
+    for (parent_frame, frame, translation, rotation) in joint_frames(&joints, &world_frame) {
+        transforms.push(json!({
+            "header": header(&parent_frame),
+            "child_frame_id": frame,
+            "transform": {"translation": translation, "rotation": rotation},
+        }));
+    }
+    let lidar = lidars.get(car.chassis).ok();
+    if let Some(lidar) = lidar {
+        let (translation, rotation) = pose(&Xform::new(lidar.position, lidar.rotation));
+        transforms.push(json!({
+            "header": header(&chassis.name),
+            "child_frame_id": "lidar",
+            "transform": {"translation": translation, "rotation": rotation},
+        }));
+    }
+    bridge.send(
+        "/tf",
+        "tf2_msgs/msg/TFMessage",
+        json!({"transforms": transforms}),
+    );
+
+    // the points of a new scan, in mm, the node puts the parts back together
+    if let Some(lidar) = lidar.filter(|lidar| lidar.scan != bridge.lidar_scan) {
+        bridge.lidar_scan = lidar.scan;
+        let points: Vec<[i64; 3]> = lidar
+            .points
+            .iter()
+            .map(|point| [0, 1, 2].map(|i| (point[i] * 1000.).round() as i64))
+            .collect();
+        let digits = points
+            .iter()
+            .flatten()
+            .map(|coordinate| coordinate.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let chunks: Vec<_> = points.chunks(lidar_part(digits)).collect();
+        let parts = chunks.len().max(1);
+        for part in 0..parts {
+            let points = chunks.get(part).copied().unwrap_or_default();
+            bridge.send_packet(json!({
+                "topic": "/points",
+                "type": "sensor_msgs/msg/PointCloud2",
+                "cloud": {
+                    "header": header("lidar"),
+                    "scan": lidar.scan,
+                    "part": part,
+                    "parts": parts,
+                    "points": points,
+                },
+            }));
+        }
+    }
+}
@@ -0,0 +1,546 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_integrator::{ExitEvent, SimSeed, SimTime};
+use cameras::control::CameraParentList;
+use rigid_body::{
+    joint::{Base, Joint},
+    sva::{Motion, Vector},
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    ai::planar_pose,
+    build::{spawn_car, CarDefinition},
+    config::CarConfig,
+    control::{CarControl, UserControl},
+    maneuver::{ConeCourse, Maneuver, ManeuverRunner},
+    presets::Preset,
+    replay::{InputPlayback, InputRecorder},
+    terrain::TerrainDescription,
+};
+
+// A whole test case in one TOML file: the car (a vehicle preset and a setup
+// file applied to it), the terrain (a terrain file or an inline table), the
+// start pose, what drives the car (a test maneuver, a script of timed driver
+// inputs or a recording to play back, the player when there is none), when the
+// run ends and what it writes. Paths are relative to the scenario file. See
+// car/examples/scenarios.
+#[derive(Resource, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "default_vehicle")]
+    pub vehicle: String,
+    pub car: Option<PathBuf>, // setup file, see car/examples/car_setup.toml
+    #[serde(default)]
+    pub terrain: TerrainSource,
+    #[serde(default = "default_dt")]
+    pub dt: f64,
+    #[serde(default)]
+    pub seed: u64, // `SimSeed` of the run
+    #[serde(default)]
+    pub initial: InitialState,
+    pub maneuver: Option<ManeuverDescription>,
+    #[serde(default)]
+    pub inputs: Vec<ScriptInput>,
+    pub playback: Option<PathBuf>, // driver inputs recorded with `InputRecorder`
+    #[serde(default)]
+    pub end: EndConditions,
+    #[serde(default)]
+    pub outputs: Outputs,
+}
+
+fn default_vehicle() -> String {
+    "car".to_string()
+}
+
+fn default_dt() -> f64 {
+    0.002
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum TerrainSource {
+    File(PathBuf),
+    Inline(TerrainDescription),
+}
+
+impl Default for TerrainSource {
+    fn default() -> Self {
+        TerrainSource::Inline(TerrainDescription::default())
+    }
+}
+
+// The car starts at rest, on the ground at `position` (x, y), by default on the
+// grid of the circuit
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct InitialState {
+    pub position: Option<[f64; 2]>,
+    pub heading: Option<f64>, // yaw angle (rad)
+}
+
+// A `Maneuver` with its entry speed, steering as a fraction of full lock
+#[derive(Deserialize, Clone)]
+pub struct ManeuverDescription {
+    #[serde(flatten)]
+    pub maneuver: ManeuverKind,
+    pub speed: f64, // entry speed (m/s)
+    #[serde(default)]
+    pub cones: bool, // cone gates along the ideal path
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ManeuverKind {
+    StepSteer {
+        steering: f64,
+        rate: f64,
+        hold: f64,
+    },
+    SineWithDwell {
+        amplitude: f64,
+        #[serde(default = "default_frequency")]
+        frequency: f64,
+        #[serde(default = "default_dwell")]
+        dwell: f64,
+    },
+    DoubleLaneChange {
+        amplitude: f64,
+        period: f64,
+        gap: f64,
+    },
+    ConstantRadius {
+        radius: f64,
+        acceleration: f64,
+        max_speed: f64,
+    },
+}
+
+fn default_frequency() -> f64 {
+    0.7
+}
+
+fn default_dwell() -> f64 {
+    0.5
+}
+
+// Driver inputs from `time` (s) on. Inputs that aren't given hold their value,
+// the steering is the steering command, without the driver steering filter.
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptInput {
+    pub time: f64,
+    pub throttle: Option<f32>,
+    pub steering: Option<f32>,
+    pub brake: Option<f32>,
+    pub handbrake: Option<f32>,
+    pub clutch: Option<f32>,
+    pub reverse: Option<bool>,
+}
+
+// The run ends at the first condition met
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct EndConditions {
+    pub time: Option<f64>,     // simulated time (s)
+    pub distance: Option<f64>, // travelled by the chassis (m)
+    pub flipped: bool,         // when the chassis is upside down
+    pub maneuver: bool,        // when the maneuver metrics are computed
+}
+
+impl Default for EndConditions {
+    fn default() -> Self {
+        Self {
+            time: None,
+            distance: None,
+            flipped: false,
+            maneuver: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Outputs {
+    pub telemetry: Option<PathBuf>, // CSV of the `Recorder` channels, HDF5 for .h5
+    pub telemetry_interval: usize,  // time steps between telemetry samples
+    pub inputs: Option<PathBuf>,    // the driver inputs, for playback
+    pub summary: Option<PathBuf>,   // JSON: how the run ended, final state and metrics
+    pub mcap: Option<PathBuf>,      // log of the run for Foxglove, at the telemetry interval
+}
+
+impl Default for Outputs {
+    fn default() -> Self {
+        Self {
+            telemetry: None,
+            telemetry_interval: 5,
+            inputs: None,
+            summary: None,
+            mcap: None,
+        }
+    }
+}
+
+impl Scenario {
+    // reads the file, the paths in it are made relative to the working directory
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("reading {}: {}", path.display(), error))?;
+        let mut scenario: Scenario = toml::from_str(&text)
+            .map_err(|error| format!("parsing {}: {}", path.display(), error))?;
+        if scenario.maneuver.is_some()
+            && (!scenario.inputs.is_empty() || scenario.playback.is_some())
+        {
+            return Err(format!(
+                "{}: a maneuver can't be combined with inputs or playback",
+                path.display()
+            ));
+        }
+        if scenario.name.is_empty() {
+            let stem = path.file_stem().map(|stem| stem.to_string_lossy());
+            scenario.name = stem.unwrap_or_default().to_string();
+        }
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let terrain = match &mut scenario.terrain {
+            TerrainSource::File(path) => Some(path),
+            TerrainSource::Inline(_) => None,
+        };
+        let outputs = &mut scenario.outputs;
+        let paths = [
+            scenario.car.as_mut(),
+            terrain,
+            scenario.playback.as_mut(),
+            outputs.telemetry.as_mut(),
+            outputs.inputs.as_mut(),
+            outputs.summary.as_mut(),
+            outputs.mcap.as_mut(),
+        ];
+        for path in paths.into_iter().flatten() {
+            *path = dir.join(&*path);
+        }
+        Ok(scenario)
+    }
+
+    // the folders the outputs are written to
+    pub fn create_output_dirs(&self) -> Result<(), String> {
+        let outputs = &self.outputs;
+        let paths = [
+            &outputs.telemetry,
+            &outputs.inputs,
+            &outputs.summary,
+            &outputs.mcap,
+        ];
+        for path in paths.into_iter().flatten() {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)
+                    .map_err(|error| format!("creating {}: {}", dir.display(), error))?;
+            }
+        }
+        Ok(())
+    }
+
+    // the vehicle preset with the setup file applied
+    pub fn car(&self) -> Result<CarDefinition, String> {
+        let preset = Preset::from_name(&self.vehicle)
+            .ok_or_else(|| format!("unknown vehicle preset: {}", self.vehicle))?;
+        let mut car = preset.build();
+        if let Some(path) = &self.car {
+            CarConfig::from_file(path)?.apply(&mut car);
+        }
+        Ok(car)
+    }
+
+    pub fn terrain(&self) -> Result<TerrainDescription, String> {
+        match &self.terrain {
+            TerrainSource::File(path) => TerrainDescription::from_file(path),
+            TerrainSource::Inline(description) => Ok(description.clone()),
+        }
+    }
+
+    pub fn maneuver_runner(&self, car: &CarDefinition) -> Option<ManeuverRunner> {
+        let description = self.maneuver.as_ref()?;
+        let maneuver = match description.maneuver {
+            ManeuverKind::StepSteer {
+                steering,
+                rate,
+                hold,
+            } => Maneuver::StepSteer {
+                steering,
+                rate,
+                hold,
+            },
+            ManeuverKind::SineWithDwell {
+                amplitude,
+                frequency,
+                dwell,
+            } => Maneuver::SineWithDwell {
+                amplitude,
+                frequency,
+                dwell,
+            },
+            ManeuverKind::DoubleLaneChange {
+                amplitude,
+                period,
+                gap,
+            } => Maneuver::DoubleLaneChange {
+                amplitude,
+                period,
+                gap,
+            },
+            ManeuverKind::ConstantRadius {
+                radius,
+                acceleration,
+                max_speed,
+            } => Maneuver::ConstantRadius {
+                radius,
+                max_curvature: car.max_curvature().unwrap_or(0.2),
+                acceleration,
+                max_speed,
+            },
+        };
+        let runner = ManeuverRunner::new(maneuver, description.speed);
+        Some(match description.cones {
+            true => runner.with_cones(ConeCourse {
+                max_curvature: car.lock_curvature(),
+                width: car.track_width() + 1.,
+                spacing: 6.,
+            }),
+            false => runner,
+        })
+    }
+
+    // position of the chassis at rest on the terrain, and heading
+    pub fn start_pose(&self, car: &CarDefinition, terrain: &TerrainDescription) -> ([f64; 3], f64) {
+        let heading = self.initial.heading.unwrap_or(car.initial_heading());
+        let [x, y, z] = car.initial_position();
+        match self.initial.position {
+            Some([x, y]) => (
+                [
+                    x,
+                    y,
+                    terrain.grid_terrain(&SimSeed(self.seed)).height(x, y) + z,
+                ],
+                heading,
+            ),
+            None => ([x, y, z], heading),
+        }
+    }
+}
+
+// The state of a running scenario, inserted with the `Scenario`
+#[derive(Resource)]
+pub struct ScenarioRun {
+    pub start: ([f64; 3], f64), // position and heading
+    pub distance: f64,          // travelled by the chassis so far
+    pub end: Option<String>,    // the end condition met
+    chassis: Option<Entity>,
+    last_position: Option<[f64; 2]>,
+}
+
+impl ScenarioRun {
+    pub fn new(start: ([f64; 3], f64)) -> Self {
+        Self {
+            start,
+            distance: 0.,
+            end: None,
+            chassis: None,
+            last_position: None,
+        }
+    }
+}
+
+// the timed driver inputs of the scenario, on the chassis entity
+#[derive(Component)]
+pub struct InputScript {
+    pub inputs: Vec<ScriptInput>,
+    index: usize, // of the next input
+}
+
+impl InputScript {
+    pub fn new(mut inputs: Vec<ScriptInput>) -> Self {
+        inputs.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { inputs, index: 0 }
+    }
+}
+
+// Spawns the car of the scenario at its start pose, with what drives it
+pub fn scenario_startup_system(
+    mut commands: Commands,
+    scenario: Res<Scenario>,
+    car: Res<CarDefinition>,
+    mut run: ResMut<ScenarioRun>,
+) {
+    let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+    let (position, heading) = run.start;
+    let entities = spawn_car(
+        &mut commands,
+        &car,
+        base_id,
+        position,
+        heading,
+        Color::rgb(0.9, 0.1, 0.2),
+    );
+    run.chassis = Some(entities.chassis);
+
+    let mut chassis = commands.entity(entities.chassis);
+    if let Some(runner) = scenario.maneuver_runner(&car) {
+        chassis.insert(runner);
+    } else if let Some(path) = &scenario.playback {
+        match InputPlayback::from_file(path) {
+            Ok(playback) => {
+                chassis.insert(playback);
+            }
+            Err(error) => warn!("driver inputs not played back, {}", error),
+        }
+    } else if !scenario.inputs.is_empty() {
+        chassis.insert(InputScript::new(scenario.inputs.clone()));
+    } else {
+        chassis.insert(UserControl::default());
+    }
+    if let Some(path) = &scenario.outputs.inputs {
+        chassis.insert(InputRecorder::new(path));
+    }
+
+    let mut camera_parent_list = entities.camera_parents.clone();
+    camera_parent_list.push(base_id);
+    commands.insert_resource(CameraParentList {
+        list: camera_parent_list,
+        active: 0,
+    });
+    commands.insert_resource(entities);
+}
+
+// Runs before the integrator on the simulation time, like the input playback
+pub fn input_script_system(
+    time: Res<SimTime>,
+    mut cars: Query<(&mut CarControl, &mut InputScript)>,
+) {
+    // the inputs at or before the current time, allowing for rounding
+    let now = time.time() + 0.5 * time.dt;
+    for (mut control, mut script) in cars.iter_mut() {
+        while let Some(input) = script.inputs.get(script.index) {
+            if input.time > now {
+                break;
+            }
+            let pedal = |value: Option<f32>, current: f32| {
+                value.map_or(current, |value| value.clamp(0., 1.))
+            };
+            control.throttle = pedal(input.throttle, control.throttle);
+            control.brake = pedal(input.brake, control.brake);
+            control.handbrake = pedal(input.handbrake, control.handbrake);
+            control.clutch = pedal(input.clutch, control.clutch);
+            if let Some(steering) = input.steering {
+                control.steering = steering.clamp(-1., 1.);
+                control.steering_input = control.steering;
+            }
+            if let Some(reverse) = input.reverse {
+                control.reverse = reverse;
+            }
+            script.index += 1;
+        }
+    }
+}
+
+// Ends the scenario at the first end condition met, after every time step
+pub fn scenario_end_system(
+    time: Res<SimTime>,
+    scenario: Option<Res<Scenario>>,
+    run: Option<ResMut<ScenarioRun>>,
+    chassis: Query<(&Joint, Option<&ManeuverRunner>)>,
+    mut exit: EventWriter<ExitEvent>,
+) {
+    let (scenario, mut run) = match (scenario, run) {
+        (Some(scenario), Some(run)) if run.end.is_none() => (scenario, run),
+        _ => return,
+    };
+    let (joint, runner) = match run.chassis.and_then(|entity| chassis.get(entity).ok()) {
+        Some(chassis) => chassis,
+        None => return,
+    };
+    let ([x, y], _) = planar_pose(joint);
+    if let Some([last_x, last_y]) = run.last_position {
+        run.distance += (x - last_x).hypot(y - last_y);
+    }
+    run.last_position = Some([x, y]);
+
+    let conditions = &scenario.end;
+    let up = joint.x.inverse() * Vector::z();
+    let end = if conditions
+        .time
+        .is_some_and(|end| time.time() >= end - 0.5 * time.dt)
+    {
+        "time"
+    } else if conditions.distance.is_some_and(|end| run.distance >= end) {
+        "distance"
+    } else if conditions.flipped && up.z < 0. {
+        "flipped"
+    } else if conditions.maneuver && runner.is_some_and(|runner| runner.is_finished()) {
+        "maneuver complete"
+    } else {
+        return;
+    };
+    info!(
+        "scenario {} ended ({}) at {:.2} s",
+        scenario.name,
+        end,
+        time.time()
+    );
+    run.end = Some(end.to_string());
+    exit.send(ExitEvent);
+}
+
+// Writes the summary of the scenario when the simulation exits
+pub fn scenario_summary_system(
+    mut exit: EventReader<ExitEvent>,
+    time: Res<SimTime>,
+    scenario: Option<Res<Scenario>>,
+    run: Option<Res<ScenarioRun>>,
+    chassis: Query<(&Joint, Option<&ManeuverRunner>)>,
+) {
+    if exit.is_empty() {
+        return;
+    }
+    exit.clear();
+    let (scenario, run) = match (scenario, run) {
+        (Some(scenario), Some(run)) => (scenario, run),
+        _ => return,
+    };
+    let path = match &scenario.outputs.summary {
+        Some(path) => path,
+        None => return,
+    };
+    let (joint, runner) = match run.chassis.and_then(|entity| chassis.get(entity).ok()) {
+        Some(chassis) => chassis,
+        None => return,
+    };
+    let (position, heading) = planar_pose(joint);
+    let metrics: BTreeMap<&String, &f64> = runner
+        .map(|runner| runner.metrics.iter().collect())
+        .unwrap_or_default();
+    let summary = json!({
+        "name": scenario.name,
+        "seed": scenario.seed,
+        "end": run.end.as_deref().unwrap_or("exit"),
+        "time": time.time(),
+        "distance": run.distance,
+        "position": position,
+        "heading": heading,
+        "speed": joint.v.v.x,
+        "metrics": metrics,
+    });
+    let text = serde_json::to_string_pretty(&summary).unwrap_or_default();
+    match fs::write(path, text + "\n") {
+        Ok(()) => info!("scenario summary written to {}", path.display()),
+        Err(error) => warn!("writing {}: {}", path.display(), error),
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::prelude::*;
+use bevy_integrator::SimSeed;
+use grid_terrain::rng::SplitMix64;
+use rigid_body::{
+    joint::Joint,
+    sva::{rx, ry, rz, Matrix, Vector},
+};
+
+use crate::{ai::planar_pose, lidar::Lidar};
+
+const GRAVITY: f64 = 9.81;
+
+// Gaussian white noise from a seeded generator, so runs are repeatable. The
+// sensors are seeded from the `SimSeed` (`sensor_seed_system`), unless given a
+// seed of their own.
+#[derive(Clone, Default)]
+pub struct SensorNoise(Option<SplitMix64>);
+
+impl SensorNoise {
+    pub fn new(seed: u64) -> Self {
+        Self(Some(SplitMix64(seed)))
+    }
+
+    // seeds the noise from the `stream` of the run's seed, if it has no seed
+    pub fn seed_from(&mut self, seed: &SimSeed, stream: &str) {
+        if self.0.is_none() {
+            self.0 = Some(SplitMix64(seed.stream(stream)));
+        }
+    }
+
+    // Box-Muller transform of two uniform samples
+    pub fn gaussian(&mut self, standard_deviation: f64) -> f64 {
+        if standard_deviation == 0. {
+            return 0.;
+        }
+        let rng = self.0.get_or_insert(SplitMix64(0));
+        let u1 = rng.range([f64::EPSILON, 1.]);
+        let u2 = rng.range([0., 1.]);
+        standard_deviation * (-2. * u1.ln()).sqrt() * (2. * std::f64::consts::PI * u2).cos()
+    }
+
+    pub fn vector(&mut self, standard_deviation: f64) -> Vector {
+        Vector::new(
+            self.gaussian(standard_deviation),
+            self.gaussian(standard_deviation),
+            self.gaussian(standard_deviation),
+        )
+    }
+}
+
+// Inertial measurement unit on the chassis entity: accelerometer (specific
+// force, reads +g upward at rest) and gyroscope in the sensor axes, at the
+// mounting point, with bias and white noise. Sampled every `sample_time` and
+// held in between.
+#[derive(Component, Clone)]
+pub struct Imu {
+    pub position: Vector, // mounting point, chassis coordinates
+    pub rotation: Matrix, // from chassis to sensor axes (mounting misalignment)
+    pub accel_noise: f64, // standard deviation (m/s²)
+    pub gyro_noise: f64,  // standard deviation (rad/s)
+    pub accel_bias: Vector,
+    pub gyro_bias: Vector,
+    pub sample_time: f64,
+    pub outputs: HashMap<String, f64>,
+    noise: SensorNoise,
+    previous_velocity: Option<Vector>, // of the mounting point, absolute coordinates
+    timer: f64,
+}
+
+impl Default for Imu {
+    fn default() -> Self {
+        Self {
+            position: Vector::zeros(),
+            rotation: Matrix::identity(),
+            accel_noise: 0.05,
+            gyro_noise: 0.002,
+            accel_bias: Vector::zeros(),
+            gyro_bias: Vector::zeros(),
+            sample_time: 0.01,
+            outputs: HashMap::new(),
+            noise: SensorNoise::default(),
+            previous_velocity: None,
+            timer: 0.,
+        }
+    }
+}
+
+impl Imu {
+    // mounting point and misalignment (roll, pitch, yaw in rad) on the chassis
+    pub fn with_mounting(mut self, position: [f64; 3], angles: [f64; 3]) -> Self {
+        let [x, y, z] = position;
+        let [roll, pitch, yaw] = angles;
+        self.position = Vector::new(x, y, z);
+        self.rotation = rx(roll) * ry(pitch) * rz(yaw);
+        self
+    }
+
+    pub fn with_noise(mut self, accel_noise: f64, gyro_noise: f64) -> Self {
+        self.accel_noise = accel_noise;
+        self.gyro_noise = gyro_noise;
+        self
+    }
+
+    pub fn with_bias(mut self, accel_bias: [f64; 3], gyro_bias: [f64; 3]) -> Self {
+        let ([ax, ay, az], [gx, gy, gz]) = (accel_bias, gyro_bias);
+        self.accel_bias = Vector::new(ax, ay, az);
+        self.gyro_bias = Vector::new(gx, gy, gz);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.noise = SensorNoise::new(seed);
+        self
+    }
+}
+
+// Wheel speed sensors of a car, on the chassis entity. Each wheel has a toothed
+// ring, the speed is the number of teeth passing in a sample over the sample
+// time, so it is quantized and reads zero at very low speed like the real sensor.
+#[derive(Component, Clone)]
+pub struct WheelSpeedSensors {
+    pub wheels: Vec<(String, Entity)>, // corner name and wheel
+    pub teeth: u32,
+    pub sample_time: f64,
+    pub outputs: HashMap<String, f64>, // wheel speed (rad/s)
+    angles: Vec<f64>,                  // integrated wheel angle
+    counts: Vec<i64>,                  // teeth passed at the last sample
+    timer: f64,
+}
+
+impl WheelSpeedSensors {
+    pub fn new(wheels: Vec<(String, Entity)>) -> Self {
+        Self {
+            angles: vec![0.; wheels.len()],
+            counts: vec![0; wheels.len()],
+            wheels,
+            teeth: 48,
+            sample_time: 0.02,
+            outputs: HashMap::new(),
+            timer: 0.,
+        }
+    }
+
+    pub fn with_teeth(mut self, teeth: u32) -> Self {
+        self.teeth = teeth;
+        self
+    }
+}
+
+// Satellite position receiver on the chassis entity. Position and velocity at
+// the antenna, with white noise, a slowly wandering position error, a low update
+// rate, and a latency: each fix is published `latency` after it was taken.
+#[derive(Component, Clone)]
+pub struct Gps {
+    pub antenna: Vector,     // chassis coordinates
+    pub position_noise: f64, // standard deviation (m)
+    pub velocity_noise: f64, // standard deviation (m/s)
+    pub drift: f64,          // standard deviation of the position error random walk (m/√s)
+    pub sample_time: f64,    // between fixes (s)
+    pub latency: f64,        // (s)
+    pub outputs: HashMap<String, f64>,
+    noise: SensorNoise,
+    offset: Vector,                               // wandering position error
+    pending: VecDeque<(f64, Vec<(String, f64)>)>, // fixes and the time they are published
+    time: f64,
+    timer: f64,
+}
+
+impl Default for Gps {
+    fn default() -> Self {
+        Self {
+            antenna: Vector::new(0., 0., 0.5),
+            position_noise: 0.5,
+            velocity_noise: 0.05,
+            drift: 0.1,
+            sample_time: 0.1,
+            latency: 0.15,
+            outputs: HashMap::new(),
+            noise: SensorNoise::default(),
+            offset: Vector::zeros(),
+            pending: VecDeque::new(),
+            time: 0.,
+            timer: 0.,
+        }
+    }
+}
+
+impl Gps {
+    pub fn with_antenna(mut self, position: [f64; 3]) -> Self {
+        let [x, y, z] = position;
+        self.antenna = Vector::new(x, y, z);
+        self
+    }
+
+    pub fn with_noise(mut self, position_noise: f64, velocity_noise: f64, drift: f64) -> Self {
+        self.position_noise = position_noise;
+        self.velocity_noise = velocity_noise;
+        self.drift = drift;
+        self
+    }
+
+    pub fn with_timing(mut self, sample_time: f64, latency: f64) -> Self {
+        self.sample_time = sample_time;
+        self.latency = latency;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.noise = SensorNoise::new(seed);
+        self
+    }
+}
+
+// Seeds the noise of new sensors from the `SimSeed`, a stream for each sensor
+// named after its kind and entity, before their first sample
+pub fn sensor_seed_system(
+    seed: Res<SimSeed>,
+    mut imus: Query<(Entity, &mut Imu), Added<Imu>>,
+    mut gps_receivers: Query<(Entity, &mut Gps), Added<Gps>>,
+    mut lidars: Query<(Entity, &mut Lidar), Added<Lidar>>,
+) {
+    for (entity, mut imu) in imus.iter_mut() {
+        imu.noise
+            .seed_from(&seed, &format!("imu {}", entity.index()));
+    }
+    for (entity, mut gps) in gps_receivers.iter_mut() {
+        gps.noise
+            .seed_from(&seed, &format!("gps {}", entity.index()));
+    }
+    for (entity, mut lidar) in lidars.iter_mut() {
+        lidar
+            .noise
+            .seed_from(&seed, &format!("lidar {}", entity.index()));
+    }
+}
+
+// runs once per time step (not in the physics schedule), like the wheel loads
+pub fn imu_system(fixed_time: Res<FixedTime>, mut cars: Query<(&Joint, &mut Imu)>) {
+    let dt = fixed_time.period.as_secs_f64();
+    for (joint, mut imu) in cars.iter_mut() {
+        // mounting point velocity in absolute coordinates
+        let x0i = joint.x.inverse();
+        let point = x0i.transform_point(imu.position);
+        let velocity = (x0i * joint.v).velocity_point(point).vel;
+        let acceleration = match imu.previous_velocity {
+            Some(previous) => (velocity - previous) / dt,
+            None => Vector::zeros(),
+        };
+        imu.previous_velocity = Some(velocity);
+
+        imu.timer += dt;
+        if imu.timer < imu.sample_time {
+            continue;
+        }
+        imu.timer = 0.;
+
+        // specific force, then into chassis and sensor axes
+        let specific_force = joint.x * (acceleration + Vector::new(0., 0., GRAVITY));
+        let rotation = imu.rotation;
+        let (accel_noise, gyro_noise) = (imu.accel_noise, imu.gyro_noise);
+        let accel = rotation * specific_force + imu.accel_bias + imu.noise.vector(accel_noise);
+        let gyro = rotation * joint.v.w + imu.gyro_bias + imu.noise.vector(gyro_noise);
+        for (axis, index) in [("x", 0), ("y", 1), ("z", 2)] {
+            imu.outputs.insert(format!("accel_{}", axis), accel[index]);
+            imu.outputs.insert(format!("gyro_{}", axis), gyro[index]);
+        }
+    }
+}
+
+pub fn wheel_speed_sensor_system(
+    fixed_time: Res<FixedTime>,
+    mut cars: Query<&mut WheelSpeedSensors>,
+    joints: Query<&Joint>,
+) {
+    let dt = fixed_time.period.as_secs_f64();
+    for mut sensors in cars.iter_mut() {
+        let sensors = &mut *sensors;
+        for ((_, wheel), angle) in sensors.wheels.iter().zip(sensors.angles.iter_mut()) {
+            if let Ok(joint) = joints.get(*wheel) {
+                *angle += joint.qd * dt;
+            }
+        }
+
+        sensors.timer += dt;
+        if sensors.timer < sensors.sample_time {
+            continue;
+        }
+        let sample_time = sensors.timer;
+        sensors.timer = 0.;
+
+        let pitch = 2. * std::f64::consts::PI / sensors.teeth as f64;
+        for (((corner, _), angle), count) in sensors
+            .wheels
+            .iter()
+            .zip(&sensors.angles)
+            .zip(sensors.counts.iter_mut())
+        {
+            let teeth = (angle / pitch).floor() as i64;
+            let speed = (teeth - *count) as f64 * pitch / sample_time;
+            *count = teeth;
+            sensors.outputs.insert(corner.clone(), speed);
+        }
+    }
+}
+
+pub fn gps_system(fixed_time: Res<FixedTime>, mut cars: Query<(&Joint, &mut Gps)>) {
+    let dt = fixed_time.period.as_secs_f64();
+    for (joint, mut gps) in cars.iter_mut() {
+        gps.time += dt;
+        gps.timer += dt;
+        if gps.timer >= gps.sample_time {
+            gps.timer = 0.;
+            let x0i = joint.x.inverse();
+            let antenna = x0i.transform_point(gps.antenna);
+            let velocity = (x0i * joint.v).velocity_point(antenna).vel;
+            let (_, heading) = planar_pose(joint);
+
+            let (drift, sample_time) = (gps.drift, gps.sample_time);
+            let step = gps.noise.vector(drift * sample_time.sqrt());
+            gps.offset += step;
+            let position_noise = gps.position_noise;
+            let velocity_noise = gps.velocity_noise;
+            let position = antenna + gps.offset + gps.noise.vector(position_noise);
+            let velocity = velocity + gps.noise.vector(velocity_noise);
+            let speed = (velocity.x.powi(2) + velocity.y.powi(2)).sqrt();
+            // the course over ground is only known when moving
+            let course = if speed > 1. {
+                velocity.y.atan2(velocity.x)
+            } else {
+                heading
+            };
+            let fix = vec![
+                ("x".to_string(), position.x),
+                ("y".to_string(), position.y),
+                ("z".to_string(), position.z),
+                ("vx".to_string(), velocity.x),
+                ("vy".to_string(), velocity.y),
+                ("speed".to_string(), speed),
+                ("course".to_string(), course),
+                ("fix_time".to_string(), gps.time),
+            ];
+            let publish = gps.time + gps.latency;
+            gps.pending.push_back((publish, fix));
+        }
+
+        while let Some((publish, _)) = gps.pending.front() {
+            if *publish > gps.time {
+                break;
+            }
+            if let Some((_, fix)) = gps.pending.pop_front() {
+                gps.outputs.extend(fix);
+            }
+        }
+    }
+}
@@ -1,21 +1,70 @@
 #![allow(dead_code)]
 
-use bevy::prelude::*;
-use bevy_integrator::{PhysicsSchedule, PhysicsSet};
+use bevy::{prelude::*, transform::TransformSystem};
+use bevy_integrator::{integrator_schedule, PhysicsSchedule, PhysicsSet};
+use rigid_body::{
+    joint::Joint,
+    physics_thread::{PhysicsThreadPlugin, PhysicsThreadSet},
+};
 
 use crate::{
-    control::user_control_system,
-    physics::{
-        brake_wheel_system, driven_wheel_lookup_system, steering_curvature_system, steering_system,
-        suspension_system,
+    abs::abs_system,
+    aero::aero_system,
+    ai::ai_driver_system,
+    bicycle::bicycle_model_system,
+    bindings::InputBindings,
+    brake_heat::brake_heat_system,
+    broadcast::telemetry_broadcast_system,
+    buoyancy::buoyancy_system,
+    camera_clearance::{
+        camera_clearance_insert_system, camera_clearance_reset_system, camera_clearance_system,
     },
-    tire::point_tire_system,
+    camera_presets::{camera_preset_system, CameraPreset, CameraPresets},
+    camera_shake::{camera_shake_source_system, CameraShakeSources},
+    cones::{cone_strike_system, knocked_cone_system, ConeStrike},
+    config::car_config_reload_system,
+    control::{steering_filter_system, user_control_system, CarControl, SteeringConfig},
+    damage::{damage_mesh_system, damage_system},
+    drive_mode::{drive_mode_system, DriveModes},
+    engine::{driveline_system, engine_system, Engine},
+    environment::top_down_area_system,
+    force_feedback::force_feedback_system,
+    fuel::fuel_system,
+    kinematics::suspension_kinematics_system,
+    lidar::lidar_system,
+    maneuver::maneuver_system,
+    mcap::{mcap_finish_system, mcap_log_system},
+    motion::motion_output_system,
+    payload::payload_system,
+    photo::photo_mode_setup,
+    physics::{brake_wheel_system, steering_curvature_system, steering_system, suspension_system},
+    race::{race_avoidance_system, race_progress_system},
+    recovery::{car_recovery_system, checkpoint_system},
+    replay::{input_playback_system, input_record_system, input_record_write_system},
+    ros::{ros_command_system, ros_state_system},
+    scenario::{input_script_system, scenario_end_system, scenario_summary_system},
+    sensors::{gps_system, imu_system, sensor_seed_system, wheel_speed_sensor_system},
+    snapshot::world_snapshot_system,
+    sysid::sysid_system,
+    telemetry::{telemetry_system, telemetry_write_system},
+    tire::{point_tire_system, PointTire},
+    torque_vectoring::{torque_vectoring_control_system, torque_vectoring_system},
+    transmission::{transmission_system, Transmission},
+    turbo::turbo_system,
+    wheel_load::{wheel_load_system, WheelLoads},
 };
 
-use super::control::CarControl;
+use grid_terrain::lod::terrain_lod_system;
+
 use cameras::{
     camera_az_el::{self, camera_builder},
+    chase::chase_camera_system,
     control::camera_parent_system,
+    director::camera_director_system,
+    fly::fly_camera_system,
+    shake::{camera_shake_system, camera_unshake_system},
+    top_down::top_down_camera_system,
+    viewport::{viewport_parent_system, viewport_system},
 };
 
 pub fn simulation_setup(app: &mut App) {
@@ -23,34 +72,200 @@ pub fn simulation_setup(app: &mut App) {
         PhysicsSchedule,
         (steering_system, steering_curvature_system).in_set(PhysicsSet::Pre),
     )
+    .add_systems(
+        PhysicsSchedule,
+        suspension_kinematics_system
+            .after(steering_system)
+            .after(steering_curvature_system)
+            .in_set(PhysicsSet::Pre),
+    )
     .add_systems(
         PhysicsSchedule,
         (
             suspension_system,
             point_tire_system,
-            driven_wheel_lookup_system,
+            engine_system,
+            driveline_system,
             brake_wheel_system,
+            buoyancy_system,
+            aero_system,
+            torque_vectoring_system,
         )
             .in_set(PhysicsSet::Evaluate),
     )
-    .add_systems(Update, (user_control_system,))
-    .init_resource::<CarControl>();
+    .add_systems(
+        FixedUpdate,
+        (
+            transmission_system,
+            turbo_system,
+            fuel_system,
+            abs_system,
+            brake_heat_system,
+            torque_vectoring_control_system,
+            wheel_load_system,
+            bicycle_model_system,
+            imu_system,
+            wheel_speed_sensor_system,
+            gps_system,
+            lidar_system,
+            damage_system,
+            cone_strike_system,
+            knocked_cone_system,
+        )
+            .after(integrator_schedule::<Joint>),
+    )
+    .add_systems(
+        FixedUpdate,
+        checkpoint_system.after(integrator_schedule::<Joint>),
+    )
+    .add_systems(
+        FixedUpdate,
+        telemetry_system
+            .after(transmission_system)
+            .after(turbo_system)
+            .after(fuel_system)
+            .after(abs_system)
+            .after(brake_heat_system)
+            .after(torque_vectoring_control_system)
+            .after(wheel_load_system)
+            .after(bicycle_model_system)
+            .after(imu_system)
+            .after(wheel_speed_sensor_system)
+            .after(gps_system)
+            .after(lidar_system)
+            .after(damage_system),
+    )
+    .add_systems(FixedUpdate, mcap_log_system.after(telemetry_system))
+    .add_systems(
+        FixedUpdate,
+        sensor_seed_system.before(integrator_schedule::<Joint>),
+    )
+    .add_systems(
+        FixedUpdate,
+        scenario_end_system.after(integrator_schedule::<Joint>),
+    )
+    .add_systems(
+        Last,
+        (
+            telemetry_write_system,
+            mcap_finish_system,
+            input_record_write_system,
+            scenario_summary_system,
+        ),
+    )
+    .add_systems(
+        FixedUpdate,
+        (
+            input_playback_system,
+            input_script_system,
+            input_record_system
+                .after(input_playback_system)
+                .after(input_script_system),
+            maneuver_system,
+            sysid_system,
+        )
+            .before(integrator_schedule::<Joint>),
+    )
+    .add_systems(
+        Update,
+        (
+            user_control_system,
+            race_progress_system,
+            race_avoidance_system,
+            ai_driver_system.after(race_avoidance_system),
+            steering_filter_system
+                .after(user_control_system)
+                .after(ai_driver_system),
+            force_feedback_system,
+            car_config_reload_system,
+            car_recovery_system,
+            drive_mode_system.after(user_control_system),
+            payload_system.after(user_control_system),
+            damage_mesh_system,
+            motion_output_system,
+            telemetry_broadcast_system.after(race_progress_system),
+            ros_command_system
+                .after(user_control_system)
+                .before(steering_filter_system),
+            ros_state_system,
+            world_snapshot_system,
+        ),
+    )
+    .add_event::<ConeStrike>()
+    .init_resource::<SteeringConfig>()
+    .init_resource::<InputBindings>();
+}
+
+// Runs the car physics on its own thread (`PhysicsThreadPlugin`), `physics_setup`
+// building the physics app. This app renders the car, spawned with
+// `simulation_setup` too, and its driver inputs are sent once set.
+pub fn physics_thread_setup(app: &mut App, physics_setup: impl FnOnce(&mut App) + Send + 'static) {
+    app.add_plugins(
+        PhysicsThreadPlugin::new(physics_setup)
+            .with_mirrored::<PointTire>()
+            .with_mirrored::<WheelLoads>()
+            .with_mirrored::<Engine>()
+            .with_mirrored::<Transmission>()
+            .with_input::<CarControl>()
+            .with_input::<DriveModes>(),
+    )
+    .configure_set(
+        Update,
+        PhysicsThreadSet
+            .after(steering_filter_system)
+            .after(drive_mode_system)
+            .before(payload_system),
+    );
 }
 
 pub fn camera_setup(app: &mut App) {
+    photo_mode_setup(app);
+    // the default preset, the active one of `CameraPresets` is applied once it runs
+    let az_el = CameraPreset::default().az_el();
     app.add_systems(
         Startup,
         camera_builder(
-            Vec3 {
-                x: 0.,
-                y: 0.,
-                z: 1.,
-            },
-            -90.0_f32.to_radians(),
-            10.0_f32.to_radians(),
-            20.,
-            camera_az_el::UpDirection::Z,
+            az_el.focus,
+            az_el.azimuth,
+            az_el.elevation,
+            az_el.radius,
+            az_el.up_direction,
+        ),
+    )
+    .add_systems(
+        Update,
+        (
+            camera_az_el::az_el_camera,
+            camera_parent_system,
+            chase_camera_system
+                .after(camera_az_el::az_el_camera)
+                .after(camera_parent_system),
+            fly_camera_system.after(chase_camera_system),
+            camera_director_system.after(chase_camera_system),
+            viewport_system,
+            viewport_parent_system.after(camera_parent_system),
+            camera_shake_source_system,
+            top_down_camera_system.after(camera_az_el::az_el_camera),
+            top_down_area_system,
+            camera_clearance_insert_system,
+            camera_preset_system.before(camera_az_el::az_el_camera),
+        ),
+    ) // setup the camera
+    .add_systems(
+        PreUpdate,
+        (
+            camera_unshake_system,
+            camera_clearance_reset_system.after(camera_unshake_system),
+        ),
+    )
+    .add_systems(
+        PostUpdate,
+        (
+            camera_clearance_system.before(camera_shake_system),
+            camera_shake_system.before(TransformSystem::TransformPropagate),
         ),
     )
-    .add_systems(Update, (camera_az_el::az_el_camera, camera_parent_system)); // setup the camera
+    .init_resource::<CameraShakeSources>()
+    .init_resource::<CameraPresets>()
+    .add_systems(Update, terrain_lod_system);
 }
@@ -0,0 +1,159 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+
+use crate::tire::PointTire;
+
+// Skid marks left on the terrain by tires sliding under load. Each tire leaves a
+// trail of short quads at its contact, which fade out and are removed.
+#[derive(Resource)]
+pub struct SkidMarks {
+    pub slip_threshold: f64, // combined slip ratio and slip angle where marks start
+    pub load_threshold: f64, // minimum normal force (N)
+    pub spacing: f64,        // length of a mark segment (m)
+    pub fade_time: f32,      // time for a mark to fade out (s)
+    pub max_segments: usize, // the oldest marks fade out early beyond this
+    pub color: Color,
+    trails: HashMap<Entity, Vec3>, // last mark point of each sliding tire, by wheel joint
+    segments: VecDeque<Entity>,
+}
+
+impl Default for SkidMarks {
+    fn default() -> Self {
+        Self {
+            slip_threshold: 0.15,
+            load_threshold: 500.,
+            spacing: 0.2,
+            fade_time: 20.,
+            max_segments: 2000,
+            color: Color::rgba(0.05, 0.05, 0.05, 0.8),
+            trails: HashMap::new(),
+            segments: VecDeque::new(),
+        }
+    }
+}
+
+#[derive(Component)]
+struct SkidMark {
+    age: f32,
+    intensity: f32, // initial alpha
+}
+
+pub fn skid_marks_setup(app: &mut App) {
+    app.init_resource::<SkidMarks>()
+        .add_systems(Update, (skid_mark_system, skid_mark_fade_system));
+}
+
+fn skid_mark_system(
+    mut commands: Commands,
+    mut skid_marks: ResMut<SkidMarks>,
+    tires: Query<&PointTire>,
+    mut marks: Query<&mut SkidMark>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for tire in tires.iter() {
+        let wheel = tire.joint_entity();
+        let slip = (tire.slip_ratio().powi(2) + tire.slip_angle().powi(2)).sqrt();
+        let contact = match tire.contact() {
+            Some(contact)
+                if slip > skid_marks.slip_threshold
+                    && tire.forces()[2] > skid_marks.load_threshold =>
+            {
+                contact
+            }
+            _ => {
+                skid_marks.trails.remove(&wheel);
+                continue;
+            }
+        };
+
+        // just above the surface, so the mark isn't hidden by the terrain
+        let [position, normal] = contact.map(|vector| vector.cast::<f32>());
+        let normal = Vec3::new(normal.x, normal.y, normal.z);
+        let point = Vec3::new(position.x, position.y, position.z) + 0.01 * normal;
+        let last = match skid_marks.trails.get(&wheel) {
+            Some(last) => *last,
+            None => {
+                skid_marks.trails.insert(wheel, point);
+                continue;
+            }
+        };
+        let length = point.distance(last);
+        if length < skid_marks.spacing as f32 {
+            continue;
+        }
+        skid_marks.trails.insert(wheel, point);
+        if length > 5. * skid_marks.spacing as f32 {
+            continue; // the car was moved, start a new trail
+        }
+
+        // darker marks the more the tire slides
+        let excess = (slip / skid_marks.slip_threshold - 1.).clamp(0.3, 1.) as f32;
+        let intensity = skid_marks.color.a() * excess;
+        let side = normal.cross(point - last).normalize() * tire.width() as f32 / 2.;
+        let mesh = quad(
+            [last + side, last - side, point - side, point + side],
+            normal,
+        );
+        let entity = commands
+            .spawn((
+                PbrBundle {
+                    mesh: meshes.add(mesh),
+                    material: materials.add(StandardMaterial {
+                        base_color: skid_marks.color.with_a(intensity),
+                        alpha_mode: AlphaMode::Blend,
+                        unlit: true,
+                        ..default()
+                    }),
+                    ..default()
+                },
+                SkidMark { age: 0., intensity },
+            ))
+            .id();
+        skid_marks.segments.push_back(entity);
+
+        while skid_marks.segments.len() > skid_marks.max_segments {
+            if let Some(oldest) = skid_marks.segments.pop_front() {
+                if let Ok(mut mark) = marks.get_mut(oldest) {
+                    mark.age = mark.age.max(0.9 * skid_marks.fade_time);
+                }
+            }
+        }
+    }
+}
+
+fn skid_mark_fade_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    skid_marks: Res<SkidMarks>,
+    mut marks: Query<(Entity, &mut SkidMark, &Handle<StandardMaterial>)>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, mut mark, material) in marks.iter_mut() {
+        mark.age += time.delta_seconds();
+        if mark.age >= skid_marks.fade_time {
+            commands.entity(entity).despawn();
+        } else if let Some(material) = materials.get_mut(material) {
+            let alpha = mark.intensity * (1. - mark.age / skid_marks.fade_time);
+            material.base_color.set_a(alpha);
+        }
+    }
+}
+
+// quad with the corners counterclockwise around the normal
+fn quad(corners: [Vec3; 4], normal: Vec3) -> Mesh {
+    let positions: Vec<[f32; 3]> = corners.iter().map(|corner| corner.to_array()).collect();
+    let normals = vec![normal.to_array(); 4];
+    let uvs: Vec<[f32; 2]> = vec![[0., 0.], [1., 0.], [1., 1.], [0., 1.]];
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_indices(Some(Indices::U32(vec![0, 1, 2, 2, 3, 0])));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh
+}
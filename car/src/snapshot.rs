@@ -0,0 +1,287 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_integrator::{PhysicsState, SimSeed, SimTime};
+use rigid_body::joint::{Joint, JointState};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    control::{CarControl, CarPart},
+    terrain::TerrainDescription,
+    transmission::Transmission,
+};
+
+// The whole simulated world at a moment, e.g. mid-jump or mid-drift: the
+// simulation time, the position and velocity of every joint, the driver inputs
+// and gear of every car, and the terrain description when the world is built
+// from one. A snapshot is loaded into a world built the same way (the same
+// example, vehicles and options), the joints are matched by their names along
+// the joint tree. The state of the other components (tire filters, damage,
+// brake temperatures) carries on from the world it is loaded into.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WorldSnapshot {
+    pub start_time: f64,
+    pub dt: f64,
+    pub index: usize, // time steps taken
+    pub terrain: Option<TerrainDescription>,
+    #[serde(default)]
+    pub seed: u64, // `SimSeed` the world was built with, a run from the snapshot uses it
+    pub joints: Vec<JointSnapshot>,
+    pub cars: Vec<CarSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JointSnapshot {
+    pub key: String, // see `joint_keys`
+    pub q: f64,
+    pub qd: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CarSnapshot {
+    pub chassis: String, // key of the chassis joint
+    pub throttle: f32,
+    pub steering: f32,
+    pub steering_input: f32,
+    pub brake: f32,
+    pub handbrake: f32,
+    pub clutch: f32,
+    pub reverse: bool,
+    pub gear: Option<usize>, // starting at 1, with the range, of the transmission
+    pub low_range: bool,
+}
+
+impl WorldSnapshot {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("reading {}: {}", path.display(), error))?;
+        serde_json::from_str(&text)
+            .map_err(|error| format!("parsing {}: {}", path.display(), error))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).unwrap_or_default();
+        fs::write(path, text + "\n")
+            .map_err(|error| format!("writing {}: {}", path.display(), error))
+    }
+
+    pub fn time(&self) -> f64 {
+        self.start_time + self.index as f64 * self.dt
+    }
+}
+
+// Saves the world to `path` with `save_key` (F5) and loads it back with
+// `load_key` (F9). A snapshot given at the start (`with_snapshot`) is loaded as
+// soon as the joint states are initialized.
+#[derive(Resource)]
+pub struct WorldSnapshots {
+    pub path: PathBuf,
+    pub save_key: KeyCode,
+    pub load_key: KeyCode,
+    pending: Option<WorldSnapshot>,
+}
+
+impl WorldSnapshots {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            save_key: KeyCode::F5,
+            load_key: KeyCode::F9,
+            pending: None,
+        }
+    }
+
+    // loaded at the start, instead of starting from the initial state
+    pub fn with_snapshot(mut self, snapshot: WorldSnapshot) -> Self {
+        self.pending = Some(snapshot);
+        self
+    }
+}
+
+// A key for every joint that is the same in every world built the same way:
+// the names of the joints from the root of its tree, and the count of joints
+// before it (in spawn order) with the same names, as in "chassis_px/chassis_py#1"
+// for the second car.
+fn joint_keys(
+    joints: &Query<(Entity, &mut Joint)>,
+    parents: &Query<&Parent>,
+) -> HashMap<Entity, String> {
+    let mut paths: Vec<(Entity, String)> = joints
+        .iter()
+        .map(|(entity, joint)| {
+            let mut names = vec![joint.name.clone()];
+            let mut current = entity;
+            while let Ok(parent) = parents.get(current) {
+                current = parent.get();
+                match joints.get(current) {
+                    Ok((_, parent_joint)) => names.push(parent_joint.name.clone()),
+                    Err(_) => break,
+                }
+            }
+            names.retain(|name| !name.is_empty()); // e.g. the base
+            names.reverse();
+            (entity, names.join("/"))
+        })
+        .collect();
+    paths.sort_by_key(|(entity, _)| *entity);
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut keys = HashMap::new();
+    for (entity, path) in paths {
+        let count = counts.entry(path.clone()).or_insert(0);
+        let key = match *count {
+            0 => path,
+            count => format!("{}#{}", path, count),
+        };
+        *count += 1;
+        keys.insert(entity, key);
+    }
+    keys
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn world_snapshot_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    snapshots: Option<ResMut<WorldSnapshots>>,
+    terrain: Option<Res<TerrainDescription>>,
+    seed: Res<SimSeed>,
+    mut time: ResMut<SimTime>,
+    physics_state: Option<ResMut<PhysicsState<Joint>>>,
+    mut joints: Query<(Entity, &mut Joint)>,
+    parents: Query<&Parent>,
+    mut cars: Query<(Entity, &mut CarControl)>,
+    mut transmissions: Query<(&mut Transmission, &CarPart)>,
+) {
+    // the joint states are initialized after the startup systems
+    let (mut snapshots, mut physics_state) = match (snapshots, physics_state) {
+        (Some(snapshots), Some(physics_state)) => (snapshots, physics_state),
+        _ => return,
+    };
+    let snapshot = if let Some(snapshot) = snapshots.pending.take() {
+        snapshot
+    } else if keyboard_input.just_pressed(snapshots.load_key) {
+        match WorldSnapshot::from_file(&snapshots.path) {
+            Ok(snapshot) => snapshot,
+            Err(error) => {
+                warn!("snapshot not loaded, {}", error);
+                return;
+            }
+        }
+    } else if keyboard_input.just_pressed(snapshots.save_key) {
+        let keys = joint_keys(&joints, &parents);
+        let mut joint_snapshots: Vec<JointSnapshot> = physics_state
+            .states
+            .0
+            .iter()
+            .filter_map(|(entity, state)| {
+                keys.get(entity).map(|key| JointSnapshot {
+                    key: key.clone(),
+                    q: state.q,
+                    qd: state.qd,
+                })
+            })
+            .collect();
+        joint_snapshots.sort_by(|a, b| a.key.cmp(&b.key));
+        let mut car_snapshots: Vec<CarSnapshot> = cars
+            .iter()
+            .filter_map(|(entity, control)| {
+                let transmission = transmissions
+                    .iter()
+                    .find(|(_, part)| part.0 == entity)
+                    .map(|(transmission, _)| transmission);
+                Some(CarSnapshot {
+                    chassis: keys.get(&entity)?.clone(),
+                    throttle: control.throttle,
+                    steering: control.steering,
+                    steering_input: control.steering_input,
+                    brake: control.brake,
+                    handbrake: control.handbrake,
+                    clutch: control.clutch,
+                    reverse: control.reverse,
+                    gear: transmission.map(|transmission| transmission.gear()),
+                    low_range: transmission.is_some_and(|t| t.is_low_range()),
+                })
+            })
+            .collect();
+        car_snapshots.sort_by(|a, b| a.chassis.cmp(&b.chassis));
+        let snapshot = WorldSnapshot {
+            start_time: time.start_time,
+            dt: time.dt,
+            index: time.index,
+            terrain: terrain.map(|terrain| terrain.clone()),
+            seed: seed.0,
+            joints: joint_snapshots,
+            cars: car_snapshots,
+        };
+        match snapshot.write(&snapshots.path) {
+            Ok(()) => info!(
+                "snapshot at {:.2} s saved to {}",
+                snapshot.time(),
+                snapshots.path.display()
+            ),
+            Err(error) => warn!("snapshot not saved, {}", error),
+        }
+        return;
+    } else {
+        return;
+    };
+
+    // every joint of the world has to be in the snapshot
+    let keys = joint_keys(&joints, &parents);
+    let entities: HashMap<&String, Entity> = keys.iter().map(|(e, key)| (key, *e)).collect();
+    let states: HashMap<&String, &JointSnapshot> = snapshot
+        .joints
+        .iter()
+        .map(|joint| (&joint.key, joint))
+        .collect();
+    if let Some(key) = keys.values().find(|key| !states.contains_key(key)) {
+        warn!(
+            "snapshot not loaded, it is of another world, without the joint {}",
+            key
+        );
+        return;
+    }
+    if (snapshot.dt - time.dt).abs() > 1e-12 {
+        warn!(
+            "the snapshot was taken with a time step of {} s, it continues with {} s",
+            snapshot.dt, time.dt
+        );
+    }
+    time.start_time = snapshot.start_time;
+    time.index = snapshot.index;
+    for (key, entity) in entities.iter() {
+        let state = states[key];
+        if let Ok((_, mut joint)) = joints.get_mut(*entity) {
+            joint.q = state.q;
+            joint.qd = state.qd;
+        }
+        physics_state
+            .states
+            .insert(*entity, JointState::new(state.q, state.qd));
+    }
+    for car in snapshot.cars.iter() {
+        let entity = match entities.get(&car.chassis) {
+            Some(entity) => *entity,
+            None => continue,
+        };
+        if let Ok((_, mut control)) = cars.get_mut(entity) {
+            control.throttle = car.throttle;
+            control.steering = car.steering;
+            control.steering_input = car.steering_input;
+            control.brake = car.brake;
+            control.handbrake = car.handbrake;
+            control.clutch = car.clutch;
+            control.reverse = car.reverse;
+        }
+        for (mut transmission, part) in transmissions.iter_mut() {
+            if let (true, Some(gear)) = (part.0 == entity, car.gear) {
+                transmission.restore(gear, car.reverse, car.low_range);
+            }
+        }
+    }
+    info!("snapshot at {:.2} s loaded", snapshot.time());
+}
@@ -0,0 +1,360 @@
+use std::{
+    f64::consts::PI,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_integrator::{hdf5, recorder::Recorder, SimSeed, SimTime};
+use rigid_body::{joint::Joint, sva::Vector};
+use serde::Deserialize;
+
+use crate::{build::CarDefinition, config::CarConfig, control::CarControl, presets::Preset};
+
+// Input signal exciting the car for system identification, added to the trim of
+// the input (steering: straight ahead, throttle: what holds the entry speed).
+// Frequencies are in Hz and times in seconds.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Excitation {
+    // a sine sweeping linearly from `start_frequency` to `end_frequency` over
+    // the run, to excite a frequency band
+    Chirp {
+        amplitude: f64,
+        start_frequency: f64,
+        end_frequency: f64,
+    },
+    // pseudo random binary sequence: +-`amplitude`, held for `bit_time` per bit,
+    // from a maximum length 10 bit shift register seeded from the `SimSeed`. It
+    // has a flat spectrum up to about 0.4 / `bit_time`.
+    Prbs {
+        amplitude: f64,
+        bit_time: f64,
+    },
+}
+
+impl Excitation {
+    fn value(&self, time: f64, duration: f64, register: &mut ShiftRegister) -> f64 {
+        match *self {
+            Excitation::Chirp {
+                amplitude,
+                start_frequency,
+                end_frequency,
+            } => {
+                let rate = (end_frequency - start_frequency) / duration.max(f64::EPSILON);
+                let phase = 2. * PI * (start_frequency * time + 0.5 * rate * time.powi(2));
+                amplitude * phase.sin()
+            }
+            Excitation::Prbs {
+                amplitude,
+                bit_time,
+            } => {
+                let bit = (time / bit_time.max(f64::EPSILON)).floor() as u64;
+                while register.bit < bit {
+                    register.shift();
+                }
+                match register.state & 1 {
+                    1 => amplitude,
+                    _ => -amplitude,
+                }
+            }
+        }
+    }
+}
+
+// x^10 + x^7 + 1 Fibonacci shift register, 1023 bits before it repeats
+#[derive(Clone, Copy)]
+struct ShiftRegister {
+    state: u16,
+    bit: u64, // bits shifted so far
+}
+
+impl ShiftRegister {
+    fn new(seed: u64) -> Self {
+        // any state but all zeros
+        let state = (seed % 1023) as u16 + 1;
+        Self { state, bit: 0 }
+    }
+
+    fn shift(&mut self) {
+        let feedback = ((self.state >> 9) ^ (self.state >> 6)) & 1;
+        self.state = ((self.state << 1) | feedback) & 0x3FF;
+        self.bit += 1;
+    }
+}
+
+// Runs a system identification test on a car (on the chassis entity next to its
+// `CarControl`, in place of a player). The car is brought up to `speed` and
+// settled, then the steering and throttle follow their excitations for
+// `duration`, and the inputs and the response are sampled together every
+// `sample_time`: inputs as applied over the next time step (`input.*`) and
+// outputs at the start of it (`output.*`, chassis coordinates). The dataset is
+// written to `path` when the run ends, as CSV, or HDF5 for a .h5 file (a group
+// per run with `input` and `output` groups, with units).
+#[derive(Component, Clone)]
+pub struct SysIdRunner {
+    pub speed: f64,       // entry speed (m/s)
+    pub settle_time: f64, // time at speed before the excitation starts
+    pub duration: f64,    // of the excitation
+    pub sample_time: f64, // of the dataset, a multiple of the time step
+    pub speed_gain: f64,  // pedal per unit speed error, while settling
+    pub path: PathBuf,    // dataset file
+    pub steering: Option<Excitation>,
+    pub throttle: Option<Excitation>,
+    start: Option<usize>,          // time step the excitation started
+    settled: f64,                  // time at speed so far
+    trim_throttle: f32,            // pedal holding the entry speed
+    registers: [ShiftRegister; 2], // steering and throttle PRBS
+    velocity: Option<Vector>,
+    recorder: Option<Recorder>,
+    finished: bool,
+}
+
+impl SysIdRunner {
+    pub fn new(speed: f64, duration: f64, path: impl Into<PathBuf>) -> Self {
+        Self {
+            speed,
+            settle_time: 3.,
+            duration,
+            sample_time: 0.01,
+            speed_gain: 0.5,
+            path: path.into(),
+            steering: None,
+            throttle: None,
+            start: None,
+            settled: 0.,
+            trim_throttle: 0.,
+            registers: [ShiftRegister::new(0); 2],
+            velocity: None,
+            recorder: None,
+            finished: false,
+        }
+    }
+
+    pub fn with_steering(mut self, excitation: Excitation) -> Self {
+        self.steering = Some(excitation);
+        self
+    }
+
+    pub fn with_throttle(mut self, excitation: Excitation) -> Self {
+        self.throttle = Some(excitation);
+        self
+    }
+
+    pub fn with_sample_time(mut self, sample_time: f64) -> Self {
+        self.sample_time = sample_time;
+        self
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn write(&self, recorder: &mut Recorder) -> Result<(), String> {
+        let units = [
+            ("input.steering", "-"),
+            ("input.throttle", "-"),
+            ("input.brake", "-"),
+            ("output.speed", "m/s"),
+            ("output.lateral_velocity", "m/s"),
+            ("output.yaw_rate", "rad/s"),
+            ("output.roll_rate", "rad/s"),
+            ("output.pitch_rate", "rad/s"),
+            ("output.longitudinal_acceleration", "m/s^2"),
+            ("output.lateral_acceleration", "m/s^2"),
+        ];
+        for (channel, units) in units {
+            recorder.set_units(channel, units);
+        }
+        let path = &self.path;
+        let hdf5 = path.extension().is_some_and(|extension| extension == "h5");
+        let result = if hdf5 {
+            // the next run of the file
+            hdf5::root_names(path).and_then(|runs| {
+                let run = (1..)
+                    .map(|index| format!("run_{}", index))
+                    .find(|run| !runs.contains(run))
+                    .unwrap_or_default();
+                recorder.write_hdf5(path, &run)
+            })
+        } else {
+            recorder.write_csv(path)
+        };
+        result.map_err(|error| format!("writing {}: {}", path.display(), error))
+    }
+}
+
+// A system identification test from a TOML file, see car/examples/sysid.toml
+#[derive(Deserialize, Clone)]
+pub struct SysIdConfig {
+    #[serde(default = "default_vehicle")]
+    pub vehicle: String,
+    pub car: Option<PathBuf>, // setup file
+    pub speed: f64,
+    pub duration: f64,
+    #[serde(default = "default_sample_time")]
+    pub sample_time: f64,
+    pub output: PathBuf,
+    pub steering: Option<Excitation>,
+    pub throttle: Option<Excitation>,
+}
+
+fn default_vehicle() -> String {
+    "car".to_string()
+}
+
+fn default_sample_time() -> f64 {
+    0.01
+}
+
+impl SysIdConfig {
+    // paths in the file are relative to it
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("reading {}: {}", path.display(), error))?;
+        let mut config: SysIdConfig = toml::from_str(&text)
+            .map_err(|error| format!("parsing {}: {}", path.display(), error))?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+        config.car = config.car.map(|car| directory.join(car));
+        config.output = directory.join(&config.output);
+        Ok(config)
+    }
+
+    pub fn create_output_dir(&self) -> Result<(), String> {
+        match self.output.parent() {
+            Some(dir) => fs::create_dir_all(dir)
+                .map_err(|error| format!("creating {}: {}", dir.display(), error)),
+            None => Ok(()),
+        }
+    }
+
+    // the vehicle preset with the setup file applied
+    pub fn car(&self) -> Result<CarDefinition, String> {
+        let preset = Preset::from_name(&self.vehicle)
+            .ok_or_else(|| format!("unknown vehicle preset: {}", self.vehicle))?;
+        let mut car = preset.build();
+        if let Some(path) = &self.car {
+            CarConfig::from_file(path)?.apply(&mut car);
+        }
+        Ok(car)
+    }
+
+    pub fn runner(&self) -> SysIdRunner {
+        let mut runner = SysIdRunner::new(self.speed, self.duration, &self.output)
+            .with_sample_time(self.sample_time);
+        runner.steering = self.steering;
+        runner.throttle = self.throttle;
+        runner
+    }
+}
+
+// Runs before the integrator on the simulation time, like `maneuver_system`, so
+// the inputs change at exact time steps and the samples are aligned with them
+pub fn sysid_system(
+    time: Res<SimTime>,
+    seed: Res<SimSeed>,
+    mut cars: Query<(&Joint, &mut CarControl, &mut SysIdRunner)>,
+) {
+    for (joint, mut control, mut runner) in cars.iter_mut() {
+        if runner.finished {
+            continue;
+        }
+        // chassis velocities are in chassis coordinates
+        let speed = joint.v.v.x;
+
+        // acceleration from the change in the absolute velocity, in chassis coordinates
+        let x0i = joint.x.inverse();
+        let velocity = (x0i * joint.v)
+            .velocity_point(x0i.transform_point(Vector::zeros()))
+            .vel;
+        let acceleration = match runner.velocity {
+            Some(previous) => joint.x * ((velocity - previous) / time.dt),
+            None => Vector::zeros(),
+        };
+        runner.velocity = Some(velocity);
+
+        control.reverse = false;
+        control.clutch = 0.;
+        control.handbrake = 0.;
+
+        let start = match runner.start {
+            Some(start) => start,
+            None => {
+                // up to speed, then settle before starting
+                let pedal = runner.speed_gain * (runner.speed - speed);
+                control.throttle = pedal.clamp(0., 1.) as f32;
+                control.brake = (-pedal).clamp(0., 1.) as f32;
+                control.steering = 0.;
+                control.steering_input = 0.;
+                if (speed - runner.speed).abs() < 0.05 * runner.speed.max(1.) {
+                    runner.settled += time.dt;
+                } else {
+                    runner.settled = 0.;
+                }
+                if runner.settled >= runner.settle_time {
+                    info!("system identification started at {:.1} m/s", speed);
+                    runner.start = Some(time.index);
+                    runner.trim_throttle = control.throttle;
+                    runner.registers = [
+                        ShiftRegister::new(seed.stream("sysid steering")),
+                        ShiftRegister::new(seed.stream("sysid throttle")),
+                    ];
+                    let decimation = (runner.sample_time / time.dt).round().max(1.) as usize;
+                    runner.recorder = Some(Recorder::new(decimation));
+                    time.index
+                } else {
+                    continue;
+                }
+            }
+        };
+        // counted in steps, so the sample times are exact multiples of the time step
+        let elapsed = (time.index - start) as f64 * time.dt;
+        let duration = runner.duration;
+        let runner = &mut *runner;
+
+        let [steering_register, throttle_register] = &mut runner.registers;
+        let steering = runner
+            .steering
+            .map(|excitation| excitation.value(elapsed, duration, steering_register))
+            .unwrap_or(0.)
+            .clamp(-1., 1.);
+        let pedal = runner.trim_throttle as f64
+            + runner
+                .throttle
+                .map(|excitation| excitation.value(elapsed, duration, throttle_register))
+                .unwrap_or(0.);
+        control.steering = steering as f32;
+        control.steering_input = steering as f32;
+        control.throttle = pedal.clamp(0., 1.) as f32;
+        control.brake = (-pedal).clamp(0., 1.) as f32;
+
+        let recorder = match &mut runner.recorder {
+            Some(recorder) => recorder,
+            None => continue,
+        };
+        if recorder.begin_step(elapsed) {
+            recorder.record("input.steering", steering);
+            recorder.record("input.throttle", control.throttle as f64);
+            recorder.record("input.brake", control.brake as f64);
+            recorder.record("output.speed", speed);
+            recorder.record("output.lateral_velocity", joint.v.v.y);
+            recorder.record("output.yaw_rate", joint.v.w.z);
+            recorder.record("output.roll_rate", joint.v.w.x);
+            recorder.record("output.pitch_rate", joint.v.w.y);
+            recorder.record("output.longitudinal_acceleration", acceleration.x);
+            recorder.record("output.lateral_acceleration", acceleration.y);
+        }
+
+        if elapsed >= duration {
+            runner.finished = true;
+            let mut recorder = runner.recorder.take().unwrap_or_else(|| Recorder::new(1));
+            match runner.write(&mut recorder) {
+                Ok(()) => info!(
+                    "system identification dataset written to {}",
+                    runner.path.display()
+                ),
+                Err(error) => warn!("{}", error),
+            }
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use bevy::prelude::*;
+use bevy_integrator::{hdf5, recorder::Recorder, ExitEvent, SimTime};
+use rigid_body::{joint::Joint, sva::Vector};
+
+use crate::{
+    bicycle::BicycleModel,
+    brake_heat::BrakeHeat,
+    build::CarEntities,
+    control::CarControl,
+    damage::Damage,
+    engine::Engine,
+    fuel::FuelTank,
+    lidar::Lidar,
+    payload::Payload,
+    sensors::{Gps, Imu, WheelSpeedSensors},
+    tire::PointTire,
+    torque_vectoring::TorqueVectoring,
+    transmission::Transmission,
+    turbo::Turbo,
+    wheel_load::WheelLoads,
+};
+
+// Insert the resource (with a `Recorder`) to write the recorded telemetry to a
+// file when the simulation exits: CSV, or HDF5 for a ".h5" file, where each run
+// adds a group ("run_1", "run_2", ...) with the channels and their units
+#[derive(Resource)]
+pub struct TelemetryFile(pub PathBuf);
+
+// Records the car in `CarEntities` into the `Recorder` after every time step:
+// chassis states, driver inputs, engine and transmission outputs, and per wheel
+// speed, suspension travel, slip and tire forces, with the weight transfer and
+// the loaded chassis mass and center of gravity, the collision damage, the
+// bicycle model if it runs, the IMU, wheel speed and GPS sensor signals (and
+// the lidar returns and closest range, with a lidar), and the brake temperatures.
+#[allow(clippy::too_many_arguments)]
+pub fn telemetry_system(
+    time: Res<SimTime>,
+    recorder: Option<ResMut<Recorder>>,
+    car: Option<Res<CarEntities>>,
+    joints: Query<&Joint>,
+    controls: Query<&CarControl>,
+    engines: Query<(&Engine, &Transmission, Option<&Turbo>)>,
+    fuel_tanks: Query<&FuelTank>,
+    payloads: Query<&Payload>,
+    damages: Query<&Damage>,
+    vectorings: Query<&TorqueVectoring>,
+    wheel_loads: Query<&WheelLoads>,
+    bicycle_models: Query<&BicycleModel>,
+    sensors: Query<(&Imu, &WheelSpeedSensors, &Gps, Option<&Lidar>)>,
+    brakes: Query<&BrakeHeat>,
+    tires: Query<&PointTire>,
+) {
+    let (mut recorder, car) = match (recorder, car) {
+        (Some(recorder), Some(car)) => (recorder, car),
+        _ => return,
+    };
+    if !recorder.begin_step(time.time()) {
+        return;
+    }
+
+    if let Ok(chassis) = joints.get(car.chassis) {
+        // position in absolute coordinates, velocities in chassis coordinates
+        let position = chassis.x.inverse().transform_point(Vector::zeros());
+        let [vx, vy, vz] = [chassis.v.v.x, chassis.v.v.y, chassis.v.v.z];
+        let [wx, wy, wz] = [chassis.v.w.x, chassis.v.w.y, chassis.v.w.z];
+        for (name, value) in [
+            ("x", position.x),
+            ("y", position.y),
+            ("z", position.z),
+            ("vx", vx),
+            ("vy", vy),
+            ("vz", vz),
+            ("roll_rate", wx),
+            ("pitch_rate", wy),
+            ("yaw_rate", wz),
+        ] {
+            recorder.record(&format!("chassis.{}", name), value);
+        }
+    }
+    // twist of the rear half against the front
+    if let Some(Ok(flex)) = car.chassis_flex.map(|flex| joints.get(flex)) {
+        recorder.record("chassis.flex", flex.q);
+        recorder.record("chassis.flex_rate", flex.qd);
+    }
+
+    if let Ok(control) = controls.get(car.chassis) {
+        for (name, value) in [
+            ("throttle", control.throttle),
+            ("brake", control.brake),
+            ("steering", control.steering),
+            ("clutch", control.clutch),
+            ("handbrake", control.handbrake),
+        ] {
+            recorder.record(&format!("control.{}", name), value as f64);
+        }
+    }
+
+    if let Ok((engine, transmission, turbo)) = engines.get(car.engine) {
+        record_outputs(&mut recorder, "engine", &engine.outputs);
+        record_outputs(&mut recorder, "transmission", &transmission.outputs);
+        if let Some(turbo) = turbo {
+            record_outputs(&mut recorder, "turbo", &turbo.outputs);
+        }
+    }
+    if let Ok(fuel_tank) = fuel_tanks.get(car.chassis) {
+        record_outputs(&mut recorder, "fuel", &fuel_tank.outputs);
+    }
+    if let Ok(payload) = payloads.get(car.chassis) {
+        record_outputs(&mut recorder, "payload", &payload.outputs);
+    }
+    if let Ok(damage) = damages.get(car.chassis) {
+        record_outputs(&mut recorder, "damage", &damage.outputs);
+    }
+    if let Ok(model) = bicycle_models.get(car.chassis) {
+        record_outputs(&mut recorder, "bicycle", &model.outputs);
+    }
+    if let Ok((imu, wheel_speed, gps, lidar)) = sensors.get(car.chassis) {
+        record_outputs(&mut recorder, "imu", &imu.outputs);
+        record_outputs(&mut recorder, "wheel_speed", &wheel_speed.outputs);
+        record_outputs(&mut recorder, "gps", &gps.outputs);
+        if let Some(lidar) = lidar {
+            record_outputs(&mut recorder, "lidar", &lidar.outputs);
+        }
+    }
+    if let Ok(vectoring) = vectorings.get(car.chassis) {
+        record_outputs(&mut recorder, "torque_vectoring", &vectoring.outputs);
+    }
+    if let Ok(loads) = wheel_loads.get(car.chassis) {
+        for (name, value) in [
+            ("longitudinal_transfer", loads.longitudinal_transfer),
+            ("lateral_transfer", loads.lateral_transfer),
+            ("roll", loads.roll),
+            ("pitch", loads.pitch),
+        ] {
+            recorder.record(&format!("load.{}", name), value);
+        }
+    }
+
+    // corners on a solid axle have no suspension of their own
+    let independent = car
+        .corners
+        .iter()
+        .enumerate()
+        .filter(|(ind, _)| !car.solid_axles.iter().any(|(axle, _)| *axle == ind / 2))
+        .map(|(_, corner)| corner);
+    for (corner, suspension) in independent.zip(&car.suspensions) {
+        if let Ok(joint) = joints.get(*suspension) {
+            recorder.record(&format!("suspension.{}.travel", corner), joint.q);
+        }
+    }
+    for (axle, [heave, roll]) in car.solid_axles.iter() {
+        if let Ok([heave, roll]) = joints.get_many([*heave, *roll]) {
+            let corner = car.corners[2 * axle].trim_end_matches('l');
+            recorder.record(&format!("suspension.{}_axle.heave", corner), heave.q);
+            recorder.record(&format!("suspension.{}_axle.roll", corner), roll.q);
+        }
+    }
+
+    for (corner, wheel) in car.corners.iter().zip(&car.wheels) {
+        if let Ok(joint) = joints.get(*wheel) {
+            recorder.record(&format!("wheel.{}.speed", corner), joint.qd);
+        }
+        if let Ok(brake) = brakes.get(*wheel) {
+            record_outputs(&mut recorder, &format!("brake.{}", corner), &brake.outputs);
+        }
+        if let Some(tire) = tires.iter().find(|tire| tire.joint_entity() == *wheel) {
+            let [longitudinal, lateral, normal] = tire.forces();
+            for (name, value) in [
+                ("slip_ratio", tire.slip_ratio()),
+                ("slip_angle", tire.slip_angle()),
+                ("force_longitudinal", longitudinal),
+                ("force_lateral", lateral),
+                ("force_normal", normal),
+            ] {
+                recorder.record(&format!("tire.{}.{}", corner, name), value);
+            }
+        }
+    }
+}
+
+fn record_outputs(recorder: &mut Recorder, prefix: &str, outputs: &HashMap<String, f64>) {
+    // sorted, so the channels are in the same order every run
+    let mut names: Vec<&String> = outputs.keys().collect();
+    names.sort();
+    for name in names {
+        recorder.record(&format!("{}.{}", prefix, name), outputs[name]);
+    }
+}
+
+// units of the channels of `telemetry_system`, "1" when without units
+fn channel_units(channel: &str) -> Option<&'static str> {
+    let group = channel.split('.').next()?;
+    let name = channel.rsplit('.').next()?;
+    let units = match (group, name) {
+        ("chassis" | "gps", "x" | "y" | "z") => "m",
+        ("chassis" | "gps", "vx" | "vy" | "vz" | "speed") => "m/s",
+        ("chassis", "flex") => "rad",
+        ("gps", "course") => "rad",
+        ("gps", "fix_time") => "s",
+        ("control" | "transmission" | "turbo" | "damage", _) => "1",
+        ("engine", "rpm") => "rpm",
+        ("engine", "throttle") => "1",
+        ("engine", "torque") => "N m",
+        ("fuel", _) => "kg",
+        ("payload", "mass" | "chassis_mass") => "kg",
+        ("payload", _) => "m",
+        ("bicycle", "sideslip") => "rad",
+        ("bicycle", "path_error") => "m",
+        ("imu", "accel_x" | "accel_y" | "accel_z") => "m/s^2",
+        ("imu", _) => "rad/s",
+        ("wheel_speed" | "wheel", _) => "rad/s",
+        ("lidar", "returns") => "1",
+        ("lidar", "closest") => "m",
+        ("torque_vectoring", "transfer") => "N m",
+        ("load", "longitudinal_transfer" | "lateral_transfer") => "N",
+        ("load" | "suspension", "roll" | "pitch") => "rad",
+        ("suspension", _) => "m",
+        ("brake", "temperature") => "degC",
+        ("brake", "power") => "W",
+        ("brake", "friction") => "1",
+        ("tire", "slip_ratio") => "1",
+        ("tire", "slip_angle") => "rad",
+        ("tire", _) => "N",
+        (_, name) if name.ends_with("_rate") || name.ends_with("_rate_error") => "rad/s",
+        _ => return None,
+    };
+    Some(units)
+}
+
+pub fn telemetry_write_system(
+    mut exit: EventReader<ExitEvent>,
+    recorder: Option<ResMut<Recorder>>,
+    file: Option<Res<TelemetryFile>>,
+) {
+    if exit.is_empty() {
+        return;
+    }
+    exit.clear();
+    let (mut recorder, file) = match (recorder, file) {
+        (Some(recorder), Some(file)) => (recorder, file),
+        _ => return,
+    };
+    let hdf5 = file
+        .0
+        .extension()
+        .is_some_and(|extension| extension == "h5");
+    let result = if hdf5 {
+        let channels = recorder.channels().to_vec();
+        for channel in channels.iter() {
+            if let (None, Some(units)) = (recorder.units(channel), channel_units(channel)) {
+                recorder.set_units(channel, units);
+            }
+        }
+        // the next run of the file
+        hdf5::root_names(&file.0).and_then(|runs| {
+            let run = (1..)
+                .map(|index| format!("run_{}", index))
+                .find(|run| !runs.contains(run))
+                .unwrap_or_default();
+            recorder.write_hdf5(&file.0, &run)
+        })
+    } else {
+        recorder.write_csv(&file.0)
+    };
+    match result {
+        Ok(()) => info!("telemetry written to {}", file.0.display()),
+        Err(error) => warn!("writing telemetry to {}: {}", file.0.display(), error),
+    }
+}
@@ -0,0 +1,192 @@
+use std::{fs, path::Path};
+
+use bevy::prelude::*;
+use bevy_integrator::SimSeed;
+use grid_terrain::{
+    examples::{circuit, drift_arena, hill_climb_road, rock_crawl, slalom},
+    plane::Plane,
+    props::Prop,
+    GridElement, GridTerrain,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::environment::{build_lights, spawn_terrain, test_grid};
+
+const SIZE: f64 = 20.; // of the grid elements
+
+// Terrain read from a TOML file (or a table of a scenario file): one of the
+// built in layouts, with props added to it. See car/examples/scenarios/flat.toml.
+#[derive(Resource, Serialize, Deserialize, Clone, Default)]
+pub struct TerrainDescription {
+    #[serde(flatten)]
+    pub layout: TerrainLayout,
+    #[serde(default)]
+    pub props: Vec<PropDescription>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(tag = "layout", rename_all = "snake_case")]
+pub enum TerrainLayout {
+    #[default]
+    Circuit,
+    // the table top, waves, steps, stream and icy patches of the car example
+    TestGrid,
+    HillClimb,
+    // the boulders from `seed`, or from the `SimSeed` without one
+    RockCrawl {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seed: Option<u64>,
+    },
+    // flat ground enclosed by walls, `cells` grid elements on a side
+    DriftArena {
+        #[serde(default = "default_arena_cells")]
+        cells: usize,
+    },
+    // flat ground, `cells` grid elements along x and y from the origin
+    Flat {
+        #[serde(default = "default_flat_cells")]
+        cells: [usize; 2],
+    },
+}
+
+fn default_arena_cells() -> usize {
+    6
+}
+
+fn default_flat_cells() -> [usize; 2] {
+    [20, 10]
+}
+
+// Props standing on the terrain, positions are x, y
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PropDescription {
+    Cone {
+        position: [f64; 2],
+    },
+    Tire {
+        position: [f64; 2],
+    },
+    Wall {
+        start: [f64; 2],
+        end: [f64; 2],
+        #[serde(default = "default_wall_height")]
+        height: f64,
+    },
+    // a line of cones along x, with a tire stack at each end
+    Slalom {
+        start: [f64; 2],
+        spacing: f64,
+        count: usize,
+    },
+}
+
+fn default_wall_height() -> f64 {
+    1.
+}
+
+impl TerrainDescription {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("reading {}: {}", path.display(), error))?;
+        toml::from_str(&text).map_err(|error| format!("parsing {}: {}", path.display(), error))
+    }
+
+    // the terrain for the contact, without coloring or LOD. Random elements
+    // come from the `seed` streams.
+    pub fn grid_terrain(&self, seed: &SimSeed) -> GridTerrain {
+        let (elements, mut props) = match &self.layout {
+            TerrainLayout::Circuit => (circuit(SIZE), Vec::new()),
+            TerrainLayout::TestGrid => test_grid(SIZE, seed),
+            TerrainLayout::HillClimb => (hill_climb_road().grid_elements(SIZE), Vec::new()),
+            TerrainLayout::RockCrawl { seed: layout_seed } => {
+                let layout_seed = layout_seed.unwrap_or_else(|| seed.stream("rock_crawl"));
+                (rock_crawl(SIZE, layout_seed), Vec::new())
+            }
+            TerrainLayout::DriftArena { cells } => drift_arena(SIZE, *cells),
+            TerrainLayout::Flat { cells } => (flat(cells), Vec::new()),
+        };
+        for prop in self.props.iter() {
+            match *prop {
+                PropDescription::Cone { position: [x, y] } => props.push(Prop::cone(x, y)),
+                PropDescription::Tire { position: [x, y] } => props.push(Prop::tire(x, y)),
+                PropDescription::Wall { start, end, height } => {
+                    props.push(Prop::wall(start, end, height))
+                }
+                PropDescription::Slalom {
+                    start,
+                    spacing,
+                    count,
+                } => props.extend(slalom(start, spacing, count)),
+            }
+        }
+        let blend_margin = match self.layout {
+            TerrainLayout::TestGrid | TerrainLayout::RockCrawl { .. } => 0.1,
+            _ => 0.,
+        };
+        GridTerrain::new(elements, [SIZE, SIZE])
+            .with_blend_margin(blend_margin)
+            .with_props(props)
+    }
+}
+
+fn flat(cells: &[usize; 2]) -> Vec<Vec<Box<dyn GridElement>>> {
+    (0..cells[0])
+        .map(|_| {
+            (0..cells[1])
+                .map(|_| {
+                    Box::new(Plane {
+                        size: [SIZE, SIZE],
+                        subdivisions: 10,
+                    }) as Box<dyn GridElement>
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// parent of the terrain meshes
+#[derive(Component)]
+pub struct TerrainMeshes;
+
+// the terrain of the `TerrainDescription` resource, with lights and meshes
+pub fn build_described_environment(
+    mut commands: Commands,
+    description: Res<TerrainDescription>,
+    seed: Res<SimSeed>,
+    meshes: ResMut<Assets<Mesh>>,
+    materials: ResMut<Assets<StandardMaterial>>,
+    images: ResMut<Assets<Image>>,
+) {
+    build_lights(&mut commands);
+    build_described_meshes(commands, description, seed, meshes, materials, images);
+}
+
+// the terrain of the `TerrainDescription` resource with its meshes, without the
+// lights, e.g. to change the terrain of a running app
+pub fn build_described_meshes(
+    mut commands: Commands,
+    description: Res<TerrainDescription>,
+    seed: Res<SimSeed>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let empty_parent = spawn_terrain(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        &mut images,
+        description.grid_terrain(&seed),
+    );
+    commands.entity(empty_parent).insert(TerrainMeshes);
+}
+
+// the terrain of the `TerrainDescription` resource alone, for headless runs
+pub fn build_described_terrain(
+    mut commands: Commands,
+    description: Res<TerrainDescription>,
+    seed: Res<SimSeed>,
+) {
+    commands.insert_resource(description.grid_terrain(&seed));
+}
@@ -0,0 +1,126 @@
+use std::f64::consts::PI;
+
+use bevy::prelude::*;
+use bevy_integrator::SimTime;
+
+// The directional light of the environment, moved by the time of day
+#[derive(Component)]
+pub struct Sun;
+
+// Time of day: the sun moves across the sky and the ambient light follows it,
+// from daylight through the warm low light of dawn and dusk to a dim blue night,
+// where the headlights are needed. The sun rises in the east (+x) at 6, is
+// highest in the south (-y) at noon and sets at 18. The hour advances with the
+// simulation time, a full day in `day_length` seconds, or stays at `start_hour`
+// without one.
+#[derive(Resource, Clone)]
+pub struct TimeOfDay {
+    pub start_hour: f64,         // 0 to 24, at the start of the simulation
+    pub day_length: Option<f64>, // simulated seconds per day
+    pub max_elevation: f64,      // of the sun at noon (rad)
+    pub illuminance: f32,        // of the sun high in the sky (lux)
+    pub ambient: f32,            // ambient brightness in daylight
+    pub night_ambient: f32,      // ambient brightness at night (moon and stars)
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            start_hour: 12.,
+            day_length: Some(600.),
+            max_elevation: 60_f64.to_radians(),
+            illuminance: 10000.,
+            ambient: 0.4,
+            night_ambient: 0.02,
+        }
+    }
+}
+
+impl TimeOfDay {
+    pub fn new(start_hour: f64) -> Self {
+        Self {
+            start_hour,
+            ..default()
+        }
+    }
+
+    pub fn with_day_length(mut self, day_length: Option<f64>) -> Self {
+        self.day_length = day_length;
+        self
+    }
+
+    // hour of the day at the simulation time
+    pub fn hour(&self, time: f64) -> f64 {
+        let elapsed = match self.day_length {
+            Some(day_length) if day_length > 0. => 24. * time / day_length,
+            _ => 0.,
+        };
+        (self.start_hour + elapsed).rem_euclid(24.)
+    }
+
+    // azimuth from east towards south and elevation of the sun (rad), the
+    // elevation is negative at night
+    pub fn sun_position(&self, hour: f64) -> (f64, f64) {
+        let angle = PI * (hour - 6.) / 12.;
+        (angle, self.max_elevation * angle.sin())
+    }
+
+    // 0 at night to 1 in daylight, through the twilight around sunrise and sunset
+    pub fn daylight(&self, hour: f64) -> f32 {
+        let (_, elevation) = self.sun_position(hour);
+        let t = ((elevation.sin() + 0.1) / 0.35).clamp(0., 1.) as f32;
+        t * t * (3. - 2. * t)
+    }
+}
+
+fn mix(a: Color, b: Color, t: f32) -> Color {
+    let [ar, ag, ab, _] = a.as_rgba_f32();
+    let [br, bg, bb, _] = b.as_rgba_f32();
+    Color::rgb(ar + (br - ar) * t, ag + (bg - ag) * t, ab + (bb - ab) * t)
+}
+
+pub fn time_of_day_setup(app: &mut App) {
+    app.init_resource::<TimeOfDay>()
+        .add_systems(Update, time_of_day_system);
+}
+
+fn time_of_day_system(
+    time: Res<SimTime>,
+    time_of_day: Res<TimeOfDay>,
+    ambient: Option<ResMut<AmbientLight>>,
+    mut suns: Query<(&mut DirectionalLight, &mut Transform), With<Sun>>,
+) {
+    let hour = time_of_day.hour(time.time());
+    let (azimuth, elevation) = time_of_day.sun_position(hour);
+    let daylight = time_of_day.daylight(hour);
+
+    // the sun turns orange low in the sky, and lights nothing below the horizon
+    let height = (elevation.sin() / 0.5).clamp(0., 1.) as f32;
+    let sun_color = mix(
+        Color::rgb(1.0, 0.5, 0.25),
+        Color::rgb(1.0, 0.97, 0.92),
+        height,
+    );
+    let elevation = elevation.max(0.05); // keeps the shadows sane around sunrise and sunset
+    let to_sun = Vec3::new(
+        (azimuth.cos() * elevation.cos()) as f32,
+        (-azimuth.sin() * elevation.cos()) as f32,
+        elevation.sin() as f32,
+    );
+    for (mut light, mut transform) in suns.iter_mut() {
+        light.illuminance = time_of_day.illuminance * daylight * height.max(0.2);
+        light.color = sun_color;
+        *transform =
+            Transform::from_translation(transform.translation).looking_to(-to_sun, Vec3::Z);
+    }
+
+    if let Some(mut ambient) = ambient {
+        let night = time_of_day.night_ambient;
+        ambient.brightness = night + (time_of_day.ambient - night) * daylight;
+        ambient.color = mix(
+            Color::rgb(0.4, 0.5, 0.9),
+            Color::rgb(0.9, 0.9, 1.0),
+            daylight,
+        );
+    }
+}
@@ -0,0 +1,462 @@
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use bevy::prelude::*;
+use bevy_integrator::{integrator_schedule, SimTime};
+use rigid_body::{joint::Joint, sva::Vector};
+
+use crate::{
+    ai::planar_pose,
+    build::{CarDefinition, CarEntities},
+    control::CarControl,
+    race::{race_progress_system, Racer},
+    replay::{InputSample, HEADER},
+};
+
+const GHOST_HEADER: &str = "time,x,y,z,heading";
+const AHEAD_COLOR: Color = Color::rgb(0.3, 0.9, 0.3);
+const BEHIND_COLOR: Color = Color::rgb(1.0, 0.35, 0.3);
+
+// Pose of the chassis during a lap, the time is from the start of the lap
+#[derive(Clone)]
+pub struct GhostSample {
+    pub time: f64,
+    pub position: [f64; 3],
+    pub heading: f64,
+}
+
+impl GhostSample {
+    fn to_line(&self) -> String {
+        let [x, y, z] = self.position;
+        format!("{},{},{},{},{}", self.time, x, y, z, self.heading)
+    }
+
+    fn from_line(line: &str) -> Result<Self, String> {
+        let fields = line
+            .split(',')
+            .map(|field| {
+                field
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|error| error.to_string())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if fields.len() != 5 {
+            return Err(format!("expected 5 fields, found {}", fields.len()));
+        }
+        Ok(Self {
+            time: fields[0],
+            position: [fields[1], fields[2], fields[3]],
+            heading: fields[4],
+        })
+    }
+}
+
+// Time trial of the car in `CarEntities` (with a `Racer`) around the `Race`
+// track. The best lap of each track and car combination is kept in `dir`: the
+// path of the car, driven by a ghost car on the following laps, and the driver
+// inputs of the lap (the replay format). While driving, `delta` is the time
+// against the best lap at the same point of the track, negative when ahead.
+#[derive(Resource)]
+pub struct TimeTrial {
+    pub track: String,
+    pub car: String,
+    pub dir: PathBuf,
+    pub sample_time: f64,       // between ghost samples (s)
+    pub best: Vec<GhostSample>, // empty without a best lap
+    pub delta: Option<f64>,     // none on the out lap
+    lap: Option<i32>,           // laps completed when last checked
+    poses: Vec<GhostSample>,    // since the start of the lap, at the simulation time
+    inputs: Vec<InputSample>,   // since the start of the lap, at the simulation time
+    timer: f64,
+    ghost_index: usize, // closest ghost sample to the car
+}
+
+impl TimeTrial {
+    pub fn new(track: &str, car: &str) -> Self {
+        let mut time_trial = Self {
+            track: track.to_string(),
+            car: car.to_string(),
+            dir: config_dir().join("time_trial"),
+            sample_time: 0.05,
+            best: Vec::new(),
+            delta: None,
+            lap: None,
+            poses: Vec::new(),
+            inputs: Vec::new(),
+            timer: 0.,
+            ghost_index: 0,
+        };
+        time_trial.load();
+        time_trial
+    }
+
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self.load();
+        self
+    }
+
+    pub fn best_time(&self) -> Option<f64> {
+        self.best.last().map(|sample| sample.time)
+    }
+
+    fn path(&self, kind: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}_{}_{}.csv", self.track, self.car, kind))
+    }
+
+    // a missing file is no best lap yet
+    fn load(&mut self) {
+        let path = self.path("ghost");
+        self.best = match fs::read_to_string(&path) {
+            Ok(text) => text
+                .lines()
+                .enumerate()
+                .skip(1) // header
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(ind, line)| {
+                    GhostSample::from_line(line)
+                        .map_err(|error| format!("{} line {}: {}", path.display(), ind + 1, error))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap_or_else(|error| {
+                    warn!("best lap not loaded, {}", error);
+                    Vec::new()
+                }),
+            Err(_) => Vec::new(),
+        };
+    }
+
+    fn save(&self, inputs: &[InputSample]) -> Result<(), String> {
+        let write = |path: &PathBuf, header: &str, lines: Vec<String>| -> std::io::Result<()> {
+            let mut file = BufWriter::new(fs::File::create(path)?);
+            writeln!(file, "{}", header)?;
+            for line in lines {
+                writeln!(file, "{}", line)?;
+            }
+            file.flush()
+        };
+        fs::create_dir_all(&self.dir)
+            .map_err(|error| format!("creating {}: {}", self.dir.display(), error))?;
+        let ghost = self.path("ghost");
+        let lines = self.best.iter().map(|sample| sample.to_line()).collect();
+        write(&ghost, GHOST_HEADER, lines)
+            .map_err(|error| format!("writing {}: {}", ghost.display(), error))?;
+        let path = self.path("inputs");
+        let lines = inputs.iter().map(|sample| sample.to_line()).collect();
+        write(&path, HEADER, lines)
+            .map_err(|error| format!("writing {}: {}", path.display(), error))
+    }
+
+    // pose of the ghost at a lap time, between the samples
+    fn ghost_pose(&self, lap_time: f64) -> Option<GhostSample> {
+        let next = self.best.iter().position(|sample| sample.time > lap_time)?;
+        let previous = &self.best[next.saturating_sub(1)];
+        let next = &self.best[next];
+        let span = (next.time - previous.time).max(1e-9);
+        let t = ((lap_time - previous.time) / span).clamp(0., 1.);
+        let mut position = previous.position;
+        for (value, next) in position.iter_mut().zip(next.position) {
+            *value += t * (next - *value);
+        }
+        let turn = (next.heading - previous.heading + std::f64::consts::PI)
+            .rem_euclid(2. * std::f64::consts::PI)
+            - std::f64::consts::PI;
+        Some(GhostSample {
+            time: lap_time,
+            position,
+            heading: previous.heading + t * turn,
+        })
+    }
+}
+
+// user config directory (XDG on Linux, the roaming app data on Windows)
+pub fn config_dir() -> PathBuf {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("bevy_car_demo")
+}
+
+fn lap_time_text(time: f64) -> String {
+    let minutes = (time / 60.).floor();
+    format!("{:.0}:{:06.3}", minutes, time - 60. * minutes)
+}
+
+#[derive(Component)]
+struct Ghost;
+
+#[derive(Component)]
+struct TimeTrialText;
+
+pub fn time_trial_setup(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        time_trial_record_system.before(integrator_schedule::<Joint>),
+    )
+    .add_systems(Startup, time_trial_hud_startup_system)
+    .add_systems(
+        Update,
+        (
+            time_trial_system.after(race_progress_system),
+            ghost_system.after(time_trial_system),
+            time_trial_hud_system.after(time_trial_system),
+        ),
+    );
+}
+
+// Runs before the integrator like the input recorder, the poses are sampled
+// every `sample_time` and the inputs when they change
+fn time_trial_record_system(
+    time: Res<SimTime>,
+    time_trial: Option<ResMut<TimeTrial>>,
+    car: Option<Res<CarEntities>>,
+    cars: Query<(&Joint, &CarControl)>,
+) {
+    let (mut time_trial, car) = match (time_trial, car) {
+        (Some(time_trial), Some(car)) => (time_trial, car),
+        _ => return,
+    };
+    let (joint, control) = match cars.get(car.chassis) {
+        Ok(car) => car,
+        Err(_) => return,
+    };
+    let now = time.time();
+    let sample = InputSample::from_control(now, control);
+    let changed = match time_trial.inputs.last() {
+        Some(last) => !last.same_inputs(&sample),
+        None => true,
+    };
+    if changed {
+        time_trial.inputs.push(sample);
+    }
+
+    time_trial.timer += time.dt;
+    if time_trial.timer >= time_trial.sample_time {
+        time_trial.timer = 0.;
+        let (_, heading) = planar_pose(joint);
+        let position = joint.x.inverse().transform_point(Vector::zeros());
+        time_trial.poses.push(GhostSample {
+            time: now,
+            position: [position.x, position.y, position.z],
+            heading,
+        });
+    }
+}
+
+// Completes the laps of the player's `Racer`, keeps the fastest, and finds the
+// delta to it
+fn time_trial_system(
+    time: Res<SimTime>,
+    time_trial: Option<ResMut<TimeTrial>>,
+    car: Option<Res<CarEntities>>,
+    racers: Query<(&Joint, &Racer)>,
+) {
+    let (mut time_trial, car) = match (time_trial, car) {
+        (Some(time_trial), Some(car)) => (time_trial, car),
+        _ => return,
+    };
+    let (joint, racer) = match racers.get(car.chassis) {
+        Ok(racer) => racer,
+        Err(_) => return,
+    };
+    let lap_start = time.time() - racer.lap_time;
+
+    if time_trial.lap != Some(racer.lap) {
+        let completed = time_trial.lap == Some(racer.lap - 1) && racer.lap > 0;
+        let best = time_trial.best_time().unwrap_or(f64::INFINITY);
+        if let Some(lap_time) = racer
+            .last_lap
+            .filter(|lap_time| completed && *lap_time < best)
+        {
+            // the lap that just ended, from its start
+            let start = lap_start - lap_time;
+            time_trial.best = time_trial
+                .poses
+                .iter()
+                .map(|pose| GhostSample {
+                    time: pose.time - start,
+                    ..pose.clone()
+                })
+                .filter(|pose| (0. ..=lap_time).contains(&pose.time))
+                .collect();
+            let inputs: Vec<InputSample> = time_trial
+                .inputs
+                .iter()
+                .filter(|input| input.time >= start && input.time <= lap_start)
+                .map(|input| {
+                    let mut input = input.clone();
+                    input.time -= start;
+                    input
+                })
+                .collect();
+            info!("new best lap {}", lap_time_text(lap_time));
+            if let Err(error) = time_trial.save(&inputs) {
+                warn!("best lap not saved, {}", error);
+            }
+        }
+        // keep the inputs in effect at the start of the new lap
+        let held = time_trial
+            .inputs
+            .iter()
+            .rposition(|input| input.time <= lap_start)
+            .unwrap_or(0);
+        time_trial.inputs.drain(..held);
+        time_trial.poses.retain(|pose| pose.time >= lap_start);
+        time_trial.ghost_index = 0;
+        time_trial.lap = Some(racer.lap);
+    }
+
+    // the out lap from the grid is not timed
+    if racer.lap < 0 || time_trial.best.is_empty() {
+        time_trial.delta = None;
+        return;
+    }
+    // closest ghost sample, searched a little ahead of the last one
+    let (position, _) = planar_pose(joint);
+    let start = time_trial.ghost_index;
+    let end = (start + 100).min(time_trial.best.len());
+    let closest = time_trial.best[start..end]
+        .iter()
+        .enumerate()
+        .map(|(ind, sample)| {
+            let [x, y, _] = sample.position;
+            let distance = (x - position[0]).powi(2) + (y - position[1]).powi(2);
+            (start + ind, distance)
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+    if let Some((index, _)) = closest {
+        time_trial.ghost_index = index;
+        time_trial.delta = Some(racer.lap_time - time_trial.best[index].time);
+    }
+}
+
+// A see-through chassis box driving the best lap, hidden without one
+#[allow(clippy::too_many_arguments)]
+fn ghost_system(
+    mut commands: Commands,
+    time_trial: Option<Res<TimeTrial>>,
+    car: Option<Res<CarDefinition>>,
+    car_entities: Option<Res<CarEntities>>,
+    racers: Query<&Racer>,
+    mut ghosts: Query<(&mut Transform, &mut Visibility), With<Ghost>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let (time_trial, car, car_entities) = match (time_trial, car, car_entities) {
+        (Some(time_trial), Some(car), Some(car_entities)) => (time_trial, car, car_entities),
+        _ => return,
+    };
+    if ghosts.is_empty() {
+        let [x, y, z] = car.chassis.dimensions.map(|x| x as f32);
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(Mesh::from(shape::Box::new(x, y, z))),
+                material: materials.add(StandardMaterial {
+                    base_color: Color::rgba(0.6, 0.8, 1.0, 0.35),
+                    alpha_mode: AlphaMode::Blend,
+                    unlit: true,
+                    ..default()
+                }),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            Ghost,
+        ));
+        return;
+    }
+
+    let pose = match racers.get(car_entities.chassis) {
+        Ok(racer) if racer.lap >= 0 => time_trial.ghost_pose(racer.lap_time),
+        _ => None,
+    };
+    for (mut transform, mut visibility) in ghosts.iter_mut() {
+        match &pose {
+            Some(pose) => {
+                let [x, y, z] = pose.position.map(|x| x as f32);
+                let rotation = Quat::from_rotation_z(pose.heading as f32);
+                let offset = Vec3::from_array(car.chassis.position.map(|x| x as f32));
+                transform.translation = Vec3::new(x, y, z) + rotation * offset;
+                transform.rotation = rotation;
+                *visibility = Visibility::Visible;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+}
+
+fn time_trial_hud_startup_system(mut commands: Commands) {
+    let style = |color: Color| TextStyle {
+        font_size: 28.,
+        color,
+        ..default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.),
+                width: Val::Percent(100.),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_sections([
+                    TextSection::new("", style(Color::WHITE)),
+                    TextSection::new("", style(AHEAD_COLOR)),
+                ])
+                .with_background_color(Color::rgba(0., 0., 0., 0.5)),
+                TimeTrialText,
+            ));
+        });
+}
+
+fn time_trial_hud_system(
+    time_trial: Option<Res<TimeTrial>>,
+    car: Option<Res<CarEntities>>,
+    racers: Query<&Racer>,
+    mut text: Query<&mut Text, With<TimeTrialText>>,
+) {
+    let (time_trial, racer) = match (time_trial, car) {
+        (Some(time_trial), Some(car)) => match racers.get(car.chassis) {
+            Ok(racer) => (time_trial, racer),
+            Err(_) => return,
+        },
+        _ => return,
+    };
+    let optional = |time: Option<f64>| time.map_or("-:--.---".to_string(), lap_time_text);
+    let lap = if racer.lap < 0 {
+        "out lap".to_string()
+    } else {
+        lap_time_text(racer.lap_time)
+    };
+    let times = format!(
+        "{}   last {}   best {}",
+        lap,
+        optional(racer.last_lap),
+        optional(time_trial.best_time())
+    );
+    if let Ok(mut text) = text.get_single_mut() {
+        text.sections[0].value = times;
+        text.sections[1].value = match time_trial.delta {
+            Some(delta) => {
+                text.sections[1].style.color = if delta < 0. {
+                    AHEAD_COLOR
+                } else {
+                    BEHIND_COLOR
+                };
+                format!("\n{:+.2}", delta)
+            }
+            None => String::new(),
+        };
+    }
+}
@@ -1,10 +1,149 @@
 use bevy::prelude::*;
-use grid_terrain::GridTerrain;
+use grid_terrain::{cache::TerrainCache, GridTerrain, Interference, SurfaceKind};
 use rigid_body::{
     joint::Joint,
+    physics_thread::Mirrored,
     sva::{Force, Vector},
 };
 
+// Relationship between slip and the in-plane tire forces. Forces are normalized
+// by the normal force and the coefficient of friction.
+#[derive(Clone)]
+pub enum TireModel {
+    Linear { normalized_slip_stiffness: f64 }, // linear up to the friction circle
+    MagicFormula(MagicFormula),
+    Brush(Brush),
+}
+
+impl TireModel {
+    // normalized [longitudinal, lateral] force, load is the total normal force on the tire
+    pub fn normalized_forces(
+        &self,
+        slip_ratio: f64,
+        slip_angle: f64,
+        load: f64,
+        friction: f64,
+    ) -> [f64; 2] {
+        match self {
+            TireModel::Linear {
+                normalized_slip_stiffness,
+            } => {
+                let long = slip_ratio * normalized_slip_stiffness;
+                let lat = slip_angle * normalized_slip_stiffness;
+                // combined slip: clamp the resultant (not each direction) to the friction circle
+                let magnitude = (long * long + lat * lat).sqrt();
+                if magnitude > 1. {
+                    [long / magnitude, lat / magnitude]
+                } else {
+                    [long, lat]
+                }
+            }
+            TireModel::MagicFormula(magic_formula) => {
+                magic_formula.normalized_forces(slip_ratio, slip_angle, load)
+            }
+            TireModel::Brush(brush) => {
+                brush.normalized_forces(slip_ratio, slip_angle, load, friction)
+            }
+        }
+    }
+}
+
+// Pacejka Magic Formula coefficients for one direction
+#[derive(Clone)]
+pub struct MagicFormulaCoefficients {
+    pub b: f64, // stiffness factor
+    pub c: f64, // shape factor
+    pub d: f64, // peak factor (normalized)
+    pub e: f64, // curvature factor
+}
+
+impl MagicFormulaCoefficients {
+    pub fn new(b: f64, c: f64, d: f64, e: f64) -> Self {
+        Self { b, c, d, e }
+    }
+
+    pub fn evaluate(&self, slip: f64) -> f64 {
+        let bx = self.b * slip;
+        self.d * (self.c * (bx - self.e * (bx - bx.atan())).atan()).sin()
+    }
+}
+
+#[derive(Clone)]
+pub struct MagicFormula {
+    pub longitudinal: MagicFormulaCoefficients,
+    pub lateral: MagicFormulaCoefficients,
+    pub nominal_load: f64,
+    pub load_sensitivity: f64, // change of the peak factor per unit of relative load change
+}
+
+impl Default for MagicFormula {
+    fn default() -> Self {
+        Self {
+            longitudinal: MagicFormulaCoefficients::new(10., 1.9, 1., 0.97),
+            lateral: MagicFormulaCoefficients::new(10., 1.3, 1., 0.97),
+            nominal_load: 3000.,
+            load_sensitivity: -0.1,
+        }
+    }
+}
+
+impl MagicFormula {
+    pub fn normalized_forces(&self, slip_ratio: f64, slip_angle: f64, load: f64) -> [f64; 2] {
+        // peak friction drops as the load increases
+        let load_factor =
+            (1. + self.load_sensitivity * (load - self.nominal_load) / self.nominal_load).max(0.);
+        let long = self.longitudinal.evaluate(slip_ratio) * load_factor;
+        let lat = self.lateral.evaluate(slip_angle.atan()) * load_factor;
+
+        // combined slip: limit the resultant to the larger peak
+        let peak = self.longitudinal.d.max(self.lateral.d) * load_factor;
+        let magnitude = (long * long + lat * lat).sqrt();
+        if magnitude > peak && magnitude > 0. {
+            [long * peak / magnitude, lat * peak / magnitude]
+        } else {
+            [long, lat]
+        }
+    }
+}
+
+// Brush model with a parabolic pressure distribution over the contact patch. The
+// force builds up as more of the patch slides, with a smooth transition to full
+// sliding. Lightly loaded tires saturate at smaller slip.
+#[derive(Clone)]
+pub struct Brush {
+    pub stiffness: f64, // force per unit slip (same longitudinally and laterally)
+}
+
+impl Brush {
+    pub fn new(stiffness: f64) -> Self {
+        Self { stiffness }
+    }
+
+    pub fn normalized_forces(
+        &self,
+        slip_ratio: f64,
+        slip_angle: f64,
+        load: f64,
+        friction: f64,
+    ) -> [f64; 2] {
+        let limit = load * friction;
+        let slip = (slip_ratio * slip_ratio + slip_angle * slip_angle).sqrt();
+        if limit <= 0. || slip == 0. {
+            return [0., 0.];
+        }
+
+        // the whole patch slides beyond 1 / theta
+        let theta = self.stiffness / (3. * limit);
+        let ts = theta * slip;
+        let magnitude = if ts < 1. {
+            3. * ts - 3. * ts * ts + ts * ts * ts
+        } else {
+            1.
+        };
+        [magnitude * slip_ratio / slip, magnitude * slip_angle / slip]
+    }
+}
+
 #[derive(Component)]
 pub struct PointTire {
     joint_entity: Entity,
@@ -12,13 +151,26 @@ pub struct PointTire {
     points: Vec<Vector>,
     stiffness: [f64; 2],
     damping: f64,
-    coefficient_of_friction: f64,
-    normalized_slip_stiffness: f64,
+    model: TireModel,
     rolling_radius: f64,
     low_speed: f64,
     filter_time: f64,
     my_filtered: f64,
+    slip_ratio: f64,              // contact weighted average of the last evaluation
+    slip_angle: f64,              // contact weighted average of the last evaluation
+    forces: [f64; 3],             // longitudinal, lateral and normal, from the last evaluation
+    aligning_moment: f64, // moment about the suspension vertical axis, from the last evaluation
+    contact: Option<[Vector; 2]>, // contact weighted position and normal, from the last evaluation
+    contact_points: usize, // points touching the terrain, from the last evaluation
+    slip_power: f64,      // friction force times sliding speed (W), from the last evaluation
+    surface: SurfaceKind, // under the most active contact point
     activation_length: f64,
+    radius: f64,
+    width: f64,
+    terrain_cache: TerrainCache,
+    contacts: Vec<(Interference, Vector, f64)>, // scratch of the evaluation: contact, point, activation
+    rolling_resistance_scale: f64, // on the surface rolling resistance, e.g. a bent wheel
+    friction_scale: f64,           // on the surface friction, e.g. set by the tuning panel
 }
 
 impl PointTire {
@@ -27,17 +179,25 @@ impl PointTire {
         joint_parent: Entity,
         stiffness: [f64; 2],
         damping: f64,
-        coefficient_of_friction: f64,
-        normalized_slip_stiffness: f64,
+        model: TireModel,
         rolling_radius: f64,
         low_speed: f64,
         radius: f64,
         width: f64,
+        pressure_ratio: f64, // inflation pressure / nominal pressure
         filter_time: f64,
         num_points_width: usize,
         num_points_radius: usize,
         activation_length: f64,
     ) -> Self {
+        // the tire gets stiffer with pressure, and deflects less under load
+        let stiffness = stiffness.map(|stiffness| stiffness * pressure_ratio);
+        let rolling_radius = radius - (radius - rolling_radius) / pressure_ratio;
+
+        // over inflated tires bulge in the center of the tread, under inflated
+        // tires carry the load on the edges
+        let crown = 0.005 * (pressure_ratio - 1.).clamp(-1., 1.);
+
         let mut points = Vec::new();
         let mut theta: f64 = 0.;
         let d_theta = 2. * std::f64::consts::PI / num_points_radius as f64;
@@ -55,10 +215,16 @@ impl PointTire {
             let mut y_pos = -half_width;
             for width_ind in 0..num_points_width {
                 let theta_point = theta + (width_ind as f64 / num_points_width as f64) * d_theta;
+                let edge = if half_width > 0. {
+                    y_pos / half_width
+                } else {
+                    0.
+                };
+                let point_radius = radius + crown * (1. - 2. * edge * edge);
                 let point = Vector::new(
-                    radius * theta_point.sin(),
+                    point_radius * theta_point.sin(),
                     y_pos,
-                    radius * theta_point.cos(),
+                    point_radius * theta_point.cos(),
                 );
                 points.push(point);
                 y_pos += y_step;
@@ -73,13 +239,26 @@ impl PointTire {
             points,
             stiffness,
             damping,
-            coefficient_of_friction,
-            normalized_slip_stiffness,
+            model,
             rolling_radius,
             low_speed,
             filter_time,
             my_filtered: 0.,
+            slip_ratio: 0.,
+            slip_angle: 0.,
+            forces: [0.; 3],
+            aligning_moment: 0.,
+            contact: None,
+            contact_points: 0,
+            slip_power: 0.,
+            surface: SurfaceKind::Paved,
             activation_length,
+            radius,
+            width,
+            terrain_cache: TerrainCache::default(),
+            contacts: Vec::new(),
+            rolling_resistance_scale: 1.,
+            friction_scale: 1.,
         }
     }
 
@@ -90,6 +269,105 @@ impl PointTire {
     pub fn points(&self) -> &Vec<Vector> {
         &self.points
     }
+
+    // longitudinal slip ratio, negative when braking (0 when not in contact)
+    pub fn slip_ratio(&self) -> f64 {
+        self.slip_ratio
+    }
+
+    // slip angle (tangent), positive when the contact slides to the right
+    pub fn slip_angle(&self) -> f64 {
+        self.slip_angle
+    }
+
+    // longitudinal, lateral and normal contact forces, summed over the contact points
+    pub fn forces(&self) -> [f64; 3] {
+        self.forces
+    }
+
+    // aligning moment of the contact forces about the suspension vertical axis
+    pub fn aligning_moment(&self) -> f64 {
+        self.aligning_moment
+    }
+
+    // absolute position and normal of the contact (None when not in contact)
+    pub fn contact(&self) -> Option<[Vector; 2]> {
+        self.contact
+    }
+
+    // number of tire points touching the terrain
+    pub fn contact_points(&self) -> usize {
+        self.contact_points
+    }
+
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    // power dissipated by the sliding contact, e.g. to heat and wear the tire
+    pub fn slip_power(&self) -> f64 {
+        self.slip_power
+    }
+
+    pub fn surface(&self) -> SurfaceKind {
+        self.surface
+    }
+
+    pub fn rolling_resistance_scale(&self) -> f64 {
+        self.rolling_resistance_scale
+    }
+
+    pub fn set_rolling_resistance_scale(&mut self, scale: f64) {
+        self.rolling_resistance_scale = scale;
+    }
+
+    pub fn friction_scale(&self) -> f64 {
+        self.friction_scale
+    }
+
+    pub fn set_friction_scale(&mut self, scale: f64) {
+        self.friction_scale = scale;
+    }
+}
+
+// the outputs of the last evaluation
+pub struct TireFrame {
+    slip_ratio: f64,
+    slip_angle: f64,
+    forces: [f64; 3],
+    aligning_moment: f64,
+    contact: Option<[Vector; 2]>,
+    contact_points: usize,
+    slip_power: f64,
+    surface: SurfaceKind,
+}
+
+impl Mirrored for PointTire {
+    type Data = TireFrame;
+
+    fn extract(&self) -> TireFrame {
+        TireFrame {
+            slip_ratio: self.slip_ratio,
+            slip_angle: self.slip_angle,
+            forces: self.forces,
+            aligning_moment: self.aligning_moment,
+            contact: self.contact,
+            contact_points: self.contact_points,
+            slip_power: self.slip_power,
+            surface: self.surface,
+        }
+    }
+
+    fn apply(&mut self, frame: &TireFrame) {
+        self.slip_ratio = frame.slip_ratio;
+        self.slip_angle = frame.slip_angle;
+        self.forces = frame.forces;
+        self.aligning_moment = frame.aligning_moment;
+        self.contact = frame.contact;
+        self.contact_points = frame.contact_points;
+        self.slip_power = frame.slip_power;
+        self.surface = frame.surface;
+    }
 }
 
 pub fn point_tire_system(
@@ -99,6 +377,7 @@ pub fn point_tire_system(
 ) {
     let terrain = grid_terrain.as_ref();
     for mut tire in tire_query.iter_mut() {
+        let tire = tire.as_mut();
         if let Ok([mut joint, parent]) =
             query_joints.get_many_mut([tire.joint_entity, tire.joint_parent])
         {
@@ -107,23 +386,42 @@ pub fn point_tire_system(
             let v0 = x0i * joint.v; // spatial velocity of the wheel joint in absolute coordinates
             let xp0 = parent.x.inverse(); // spatial transform from the parent joint to absolute coordinates
             let vp0 = xp0 * parent.v; // spatial velocity of the parent joint in absolute coordinates
-            let center_abs = xp0.transform_point(Vector::zeros()); // center of the tire in absolute coordinates
+            let center_abs = x0i.transform_point(Vector::zeros()); // center of the tire in absolute coordinates
             let lateral_abs = x0i * Vector::y(); // tire lateral direction in absolute coordinates
 
             // identify points in contact with the terrain
-            let mut contacts = Vec::new();
+            // at most every point, so it grows once instead of as more points touch
+            tire.contacts.clear();
+            tire.contacts.reserve(tire.points.len());
             let mut active_points = 0.0;
-            for point in tire.points.iter() {
-                let point_abs = x0i.transform_point(*point); // point in absolute coordinates
-                if let Some(contact) = terrain.interference(point_abs) {
-                    let active = (contact.magnitude / tire.activation_length).clamp(0.0, 1.0);
-                    contacts.push((contact, point_abs, active));
-                    active_points += active;
+
+            // skip the terrain queries if the tire is clearly above the terrain
+            let radius = tire.radius;
+            tire.terrain_cache.update(terrain, center_abs, radius);
+            if tire.terrain_cache.may_contact(center_abs.z - radius) {
+                for point in tire.points.iter() {
+                    let point_abs = x0i.transform_point(*point); // point in absolute coordinates
+                    if let Some(contact) = tire.terrain_cache.interference(terrain, point_abs) {
+                        let active = (contact.magnitude / tire.activation_length).clamp(0.0, 1.0);
+                        tire.contacts.push((contact, point_abs, active));
+                        active_points += active;
+                    }
                 }
             }
 
             // calculate forces for each contact point
-            for (contact, point_abs, active) in contacts {
+            let mut slip_ratio = 0.;
+            let mut slip_angle = 0.;
+            let mut forces = [0.; 3];
+            let mut contact_position = Vector::zeros();
+            let mut contact_normal = Vector::zeros();
+            let mut slip_power = 0.;
+            let mut surface_kind = SurfaceKind::Paved;
+            let mut max_active = 0.;
+            let in_contact = active_points > 0.;
+            tire.contact_points = tire.contacts.len();
+            for (contact, point_abs, active) in tire.contacts.iter() {
+                let (point_abs, active) = (*point_abs, *active);
                 // critical directions - all in absolute coordinates
                 let contact_lateral =
                     (lateral_abs - contact.normal.dot(&lateral_abs) * contact.normal).normalize();
@@ -160,6 +458,10 @@ pub fn point_tire_system(
 
                 let slip_ratio_point = -ground_speed_long / ground_speed_parent_long_abs;
                 let slip_angle_point = -ground_speed_lat / ground_speed_parent_long_abs;
+                slip_ratio += slip_ratio_point * active / active_points;
+                slip_angle += slip_angle_point * active / active_points;
+                contact_position += contact.position * active / active_points;
+                contact_normal += contact.normal * active / active_points;
 
                 // Calculate forces
 
@@ -175,26 +477,62 @@ pub fn point_tire_system(
                 let normal_force_magnitude = stiffness_force_magnitude + damping_force_magnitude;
                 let normal_force = normal_force_magnitude * contact.normal;
 
+                // friction and rolling resistance of the surface this point touches
+                let surface = terrain.surface(contact.position);
+                let coefficient_of_friction = surface.friction * tire.friction_scale;
+
                 // in plane forces
-                let normalized_long_force =
-                    (slip_ratio_point * tire.normalized_slip_stiffness).clamp(-1., 1.);
-                let normalized_lat_force =
-                    (slip_angle_point * tire.normalized_slip_stiffness).clamp(-1., 1.);
+                let [normalized_long_force, normalized_lat_force] = tire.model.normalized_forces(
+                    slip_ratio_point,
+                    slip_angle_point,
+                    normal_force_magnitude * active_points,
+                    coefficient_of_friction,
+                );
+
+                // rolling resistance opposes the motion of the wheel (smoothed around zero speed)
+                let rolling_resistance = -surface.rolling_resistance
+                    * tire.rolling_resistance_scale
+                    * normal_force_magnitude
+                    * (ground_speed_parent_long / tire.low_speed).clamp(-1., 1.);
 
                 let long_force =
-                    normalized_long_force * normal_force_magnitude * tire.coefficient_of_friction;
+                    normalized_long_force * normal_force_magnitude * coefficient_of_friction
+                        + rolling_resistance;
 
                 let lat_force =
-                    normalized_lat_force * normal_force_magnitude * tire.coefficient_of_friction;
+                    normalized_lat_force * normal_force_magnitude * coefficient_of_friction;
 
                 let plane_force = lat_force * contact_lateral + long_force * contact_longitudinal;
 
+                forces[0] += active * long_force;
+                forces[1] += active * lat_force;
+                forces[2] += active * normal_force_magnitude;
+                slip_power += active
+                    * ((long_force - rolling_resistance) * ground_speed_long).abs()
+                    + active * (lat_force * ground_speed_lat).abs();
+                if active > max_active {
+                    max_active = active;
+                    surface_kind = surface.kind;
+                }
+
                 let force = active * (normal_force + plane_force);
                 f_ext += Force::force_point(force, contact.position);
             }
 
+            tire.slip_ratio = slip_ratio;
+            tire.slip_angle = slip_angle;
+            tire.forces = forces;
+            tire.slip_power = slip_power;
+            tire.surface = surface_kind;
+            tire.contact = if in_contact {
+                Some([contact_position, contact_normal.normalize()])
+            } else {
+                None
+            };
+
             // Y Moment Filter (otherwise the wheel oscillates, it is too stiff for the solver)
             let mut f_ext_parent = parent.x * f_ext; // resolve the force about the axle
+            tire.aligning_moment = f_ext_parent.m.z;
             let weight = 0.5_f64.powf(1. / (tire.filter_time / (0.002 / 4.))); // hard coded time step
             tire.my_filtered = tire.my_filtered * weight + f_ext_parent.m.y * (1. - weight);
             f_ext_parent.m.y = tire.my_filtered;
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use rigid_body::joint::Joint;
+
+use crate::control::CarControl;
+
+const GRAVITY: f64 = 9.81;
+
+// Torque vectoring on a driven axle, on the chassis entity. Drive torque is
+// moved from one wheel to the other to correct the yaw rate error, the sum of
+// the wheel torques is unchanged. The reference yaw rate is the one of the
+// steering curvature at the current speed, limited by the available friction.
+// This stands for independent motors (EV) or clutch packs on the axle.
+#[derive(Component, Clone)]
+pub struct TorqueVectoring {
+    pub enabled: bool,
+    pub gain: f64,           // transfer (Nm at the wheel) per rad/s of yaw rate error
+    pub max_transfer: f64,   // (Nm)
+    pub min_speed: f64,      // no vectoring below this speed (m/s)
+    pub friction: f64,       // friction coefficient limiting the reference yaw rate
+    pub max_curvature: f64,  // vehicle curvature at full steering lock
+    pub wheels: [Entity; 2], // left and right wheel, set when the car is spawned
+    pub outputs: HashMap<String, f64>,
+    transfer: f64, // added to the right wheel, removed from the left
+}
+
+impl TorqueVectoring {
+    pub fn new(gain: f64, max_transfer: f64) -> Self {
+        Self {
+            enabled: true,
+            gain,
+            max_transfer,
+            min_speed: 3.,
+            friction: 0.9,
+            max_curvature: 0.2,
+            wheels: [Entity::PLACEHOLDER; 2],
+            outputs: HashMap::new(),
+            transfer: 0.,
+        }
+    }
+
+    // yaw rate (rad/s) the driver asks for
+    pub fn reference_yaw_rate(&self, speed: f64, steering: f64) -> f64 {
+        let max = self.friction * GRAVITY / speed.abs().max(self.min_speed);
+        (speed * steering * self.max_curvature).clamp(-max, max)
+    }
+}
+
+// runs once per time step (not in the physics schedule), like the ABS
+pub fn torque_vectoring_control_system(
+    mut cars: Query<(&Joint, &CarControl, &mut TorqueVectoring)>,
+) {
+    for (joint, control, mut vectoring) in cars.iter_mut() {
+        // chassis velocities are in chassis coordinates, x forward and z up
+        let speed = joint.v.v.x;
+        let yaw_rate = joint.v.w.z;
+        let reference = vectoring.reference_yaw_rate(speed, control.steering as f64);
+        let error = reference - yaw_rate;
+
+        vectoring.transfer = if vectoring.enabled && speed > vectoring.min_speed {
+            (vectoring.gain * error).clamp(-vectoring.max_transfer, vectoring.max_transfer)
+        } else {
+            0.
+        };
+
+        let transfer = vectoring.transfer;
+        for (name, value) in [
+            ("reference_yaw_rate", reference),
+            ("yaw_rate_error", error),
+            ("transfer", transfer),
+        ] {
+            vectoring.outputs.insert(name.to_string(), value);
+        }
+    }
+}
+
+// more torque on the right wheel pushes the car to the left (positive yaw)
+pub fn torque_vectoring_system(vectorings: Query<&TorqueVectoring>, mut joints: Query<&mut Joint>) {
+    for vectoring in vectorings.iter() {
+        let [left, right] = vectoring.wheels;
+        if let Ok(mut joint) = joints.get_mut(left) {
+            joint.tau -= vectoring.transfer;
+        }
+        if let Ok(mut joint) = joints.get_mut(right) {
+            joint.tau += vectoring.transfer;
+        }
+    }
+}
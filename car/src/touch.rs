@@ -0,0 +1,172 @@
+use bevy::{input::touch::Touch, prelude::*};
+
+use crate::control::{steering_filter_system, user_control_system, CarControl, UserControl};
+
+const BASE_COLOR: Color = Color::rgba(1., 1., 1., 0.15);
+const KNOB_COLOR: Color = Color::rgba(1., 1., 1., 0.5);
+const BASE_SIZE: f32 = 120.;
+const KNOB_SIZE: f32 = 50.;
+
+// On-screen touch controls for the cars driven by a player, e.g. on a phone in
+// the browser. A finger put down on the left half of the screen steers by
+// dragging sideways from where it touched, one on the right half drives by
+// dragging up (throttle) or down (brake). A stick is shown under each finger.
+// The touch inputs override the keyboard and gamepad while a finger is down.
+#[derive(Resource)]
+pub struct TouchControls {
+    pub steering_range: f32, // drag (logical pixels) for full steering lock
+    pub pedal_range: f32,    // drag (logical pixels) for full throttle or brake
+    steering: Option<u64>,   // id of the touch steering
+    pedals: Option<u64>,     // id of the touch on the pedals
+}
+
+impl Default for TouchControls {
+    fn default() -> Self {
+        Self {
+            steering_range: 100.,
+            pedal_range: 100.,
+            steering: None,
+            pedals: None,
+        }
+    }
+}
+
+impl TouchControls {
+    // steering input of a touch, -1 (right) to 1 (left) like the keyboard
+    fn steering_input(&self, touch: &Touch) -> f32 {
+        let drag = touch.position().x - touch.start_position().x;
+        (-drag / self.steering_range).clamp(-1., 1.)
+    }
+
+    // throttle (positive) or brake (negative) of a touch, -1 to 1
+    fn pedal_input(&self, touch: &Touch) -> f32 {
+        let drag = touch.start_position().y - touch.position().y;
+        (drag / self.pedal_range).clamp(-1., 1.)
+    }
+}
+
+#[derive(Component, Clone, Copy, PartialEq)]
+enum TouchStick {
+    Steering,
+    Pedals,
+}
+
+#[derive(Component)]
+struct TouchBase;
+
+#[derive(Component)]
+struct TouchKnob;
+
+pub fn touch_controls_setup(app: &mut App) {
+    app.init_resource::<TouchControls>()
+        .add_systems(Startup, touch_startup_system)
+        .add_systems(
+            Update,
+            (
+                touch_control_system
+                    .after(user_control_system)
+                    .before(steering_filter_system),
+                touch_stick_system,
+            ),
+        );
+}
+
+fn touch_startup_system(mut commands: Commands) {
+    for stick in [TouchStick::Steering, TouchStick::Pedals] {
+        let node = |size: f32, color: Color| NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Px(size),
+                height: Val::Px(size),
+                ..default()
+            },
+            background_color: color.into(),
+            visibility: Visibility::Hidden,
+            z_index: ZIndex::Global(5),
+            ..default()
+        };
+        commands.spawn((node(BASE_SIZE, BASE_COLOR), TouchBase, stick));
+        commands.spawn((node(KNOB_SIZE, KNOB_COLOR), TouchKnob, stick));
+    }
+}
+
+fn touch_control_system(
+    touches: Res<Touches>,
+    windows: Query<&Window>,
+    mut controls: ResMut<TouchControls>,
+    mut cars: Query<&mut CarControl, With<UserControl>>,
+) {
+    let width = match windows.iter().next() {
+        Some(window) => window.width(),
+        None => return,
+    };
+    // a new finger takes a free control, by the side of the screen it touched
+    for touch in touches.iter_just_pressed() {
+        if touch.start_position().x < width / 2. {
+            controls.steering = controls.steering.or(Some(touch.id()));
+        } else {
+            controls.pedals = controls.pedals.or(Some(touch.id()));
+        }
+    }
+    let pressed = |id: Option<u64>| id.and_then(|id| touches.get_pressed(id));
+    let steering = pressed(controls.steering).map(|touch| controls.steering_input(touch));
+    let pedals = pressed(controls.pedals).map(|touch| controls.pedal_input(touch));
+    if steering.is_none() {
+        controls.steering = None;
+    }
+    if pedals.is_none() {
+        controls.pedals = None;
+    }
+
+    for mut control in cars.iter_mut() {
+        if let Some(steering) = steering {
+            control.steering_input = steering;
+        }
+        if let Some(pedals) = pedals {
+            control.throttle = pedals.max(0.);
+            control.brake = (-pedals).max(0.);
+        }
+    }
+}
+
+// a stick under each finger: the base where it touched and the knob following it
+type StickNode<'a> = (
+    &'a TouchStick,
+    &'a mut Style,
+    &'a mut Visibility,
+    Option<&'a TouchKnob>,
+);
+
+fn touch_stick_system(
+    touches: Res<Touches>,
+    controls: Res<TouchControls>,
+    mut nodes: Query<StickNode>,
+) {
+    for (stick, mut style, mut visibility, knob) in nodes.iter_mut() {
+        let id = match stick {
+            TouchStick::Steering => controls.steering,
+            TouchStick::Pedals => controls.pedals,
+        };
+        let touch = match id.and_then(|id| touches.get_pressed(id)) {
+            Some(touch) => touch,
+            None => {
+                *visibility = Visibility::Hidden;
+                continue;
+            }
+        };
+        *visibility = Visibility::Visible;
+        let (center, size) = match (knob, stick) {
+            (None, _) => (touch.start_position(), BASE_SIZE),
+            (Some(_), TouchStick::Steering) => {
+                let offset = -controls.steering_input(touch) * BASE_SIZE / 2.;
+                (touch.start_position() + Vec2::new(offset, 0.), KNOB_SIZE)
+            }
+            (Some(_), TouchStick::Pedals) => {
+                let offset = -controls.pedal_input(touch) * BASE_SIZE / 2.;
+                (touch.start_position() + Vec2::new(0., offset), KNOB_SIZE)
+            }
+        };
+        style.left = Val::Px(center.x - size / 2.);
+        style.top = Val::Px(center.y - size / 2.);
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_integrator::PhysicsState;
+
+use rigid_body::{joint::Joint, physics_thread::Mirrored};
+
+use super::control::{CarControl, CarPart};
+
+// Gearbox between the engine and the driven wheels, on the engine entity. Shifts
+// on driver request, and automatically based on engine speed (rad/s) when
+// `automatic` is set. No torque is transmitted while a shift is in progress.
+// Reverse is selected with the driver reverse mode, it has a single ratio. An
+// optional transfer case adds a low range, multiplying every ratio for crawling.
+#[derive(Component, Clone)]
+pub struct Transmission {
+    pub ratios: Vec<f64>,
+    pub final_drive: f64,
+    pub reverse_ratio: f64,
+    pub low_range: Option<f64>, // transfer case low range ratio, None without one
+    pub upshift_speed: f64,
+    pub downshift_speed: f64,
+    pub shift_time: f64,
+    pub automatic: bool,
+    pub outputs: HashMap<String, f64>,
+    gear: usize, // index into ratios
+    reverse: bool,
+    low: bool, // in the low range
+    shift_timer: f64,
+}
+
+impl Transmission {
+    pub fn new(
+        ratios: Vec<f64>,
+        final_drive: f64,
+        upshift_speed: f64,
+        downshift_speed: f64,
+        shift_time: f64,
+    ) -> Self {
+        assert!(!ratios.is_empty());
+        Self {
+            reverse_ratio: ratios[0],
+            low_range: None,
+            ratios,
+            final_drive,
+            upshift_speed,
+            downshift_speed,
+            shift_time,
+            automatic: true,
+            outputs: HashMap::new(),
+            gear: 0,
+            reverse: false,
+            low: false,
+            shift_timer: 0.,
+        }
+    }
+
+    // only shift on driver request
+    pub fn manual(mut self) -> Self {
+        self.automatic = false;
+        self
+    }
+
+    pub fn with_reverse(mut self, reverse_ratio: f64) -> Self {
+        self.reverse_ratio = reverse_ratio;
+        self
+    }
+
+    // transfer case with a low range of `ratio`, starting in the high range
+    pub fn with_low_range(mut self, ratio: f64) -> Self {
+        self.low_range = Some(ratio);
+        self
+    }
+
+    // start in the low range, if there is one
+    pub fn in_low_range(mut self) -> Self {
+        self.low = self.low_range.is_some();
+        self
+    }
+
+    // current gear, starting at 1
+    pub fn gear(&self) -> usize {
+        self.gear + 1
+    }
+
+    pub fn is_shifting(&self) -> bool {
+        self.shift_timer > 0.
+    }
+
+    pub fn is_reverse(&self) -> bool {
+        self.reverse
+    }
+
+    pub fn is_low_range(&self) -> bool {
+        self.low
+    }
+
+    // engine speed / wheel speed, negative in reverse
+    pub fn ratio(&self) -> f64 {
+        let transfer = match self.low_range {
+            Some(ratio) if self.low => ratio,
+            _ => 1.,
+        };
+        if self.reverse {
+            -self.reverse_ratio * self.final_drive * transfer
+        } else {
+            self.ratios[self.gear] * self.final_drive * transfer
+        }
+    }
+
+    // Into the low or high range, and back to first gear. The shift takes as
+    // long as a gear change.
+    pub fn shift_range(&mut self, low: bool) {
+        if low != self.low && self.low_range.is_some() {
+            self.low = low;
+            self.gear = 0;
+            self.shift_timer = self.shift_time;
+        }
+    }
+
+    // into reverse, or back into first gear
+    pub fn shift_reverse(&mut self, reverse: bool) {
+        if reverse != self.reverse {
+            self.reverse = reverse;
+            self.gear = 0;
+            self.shift_timer = self.shift_time;
+        }
+    }
+
+    pub fn shift(&mut self, gear: usize) {
+        if gear != self.gear && gear < self.ratios.len() {
+            self.gear = gear;
+            self.shift_timer = self.shift_time;
+        }
+    }
+
+    // Puts the transmission in a gear (starting at 1), direction and range at
+    // once, without a shift, e.g. from a snapshot
+    pub fn restore(&mut self, gear: usize, reverse: bool, low: bool) {
+        self.gear = gear.clamp(1, self.ratios.len()) - 1;
+        self.reverse = reverse;
+        self.low = low && self.low_range.is_some();
+        self.shift_timer = 0.;
+    }
+}
+
+impl Mirrored for Transmission {
+    type Data = Transmission;
+
+    fn extract(&self) -> Transmission {
+        self.clone()
+    }
+
+    fn apply(&mut self, data: &Transmission) {
+        self.clone_from(data);
+    }
+}
+
+// runs once per time step (not in the physics schedule), so the gear is constant
+// during the solver stages
+pub fn transmission_system(
+    fixed_time: Res<FixedTime>,
+    physics_state: Res<PhysicsState<Joint>>,
+    mut controls: Query<&mut CarControl>,
+    mut transmissions: Query<(Entity, &mut Transmission, &CarPart)>,
+) {
+    let dt = fixed_time.period.as_secs_f64();
+    for (entity, mut transmission, part) in transmissions.iter_mut() {
+        let mut control = match controls.get_mut(part.0) {
+            Ok(control) => control,
+            Err(_) => continue,
+        };
+        let gear = transmission.gear;
+        if transmission.is_shifting() {
+            transmission.shift_timer = (transmission.shift_timer - dt).max(0.);
+        } else if control.reverse != transmission.reverse {
+            transmission.shift_reverse(control.reverse);
+        } else if control.toggle_low_range {
+            let low = !transmission.low;
+            transmission.shift_range(low);
+        } else if transmission.reverse {
+            // single reverse ratio, no shifting
+        } else if control.gear_up {
+            transmission.shift(gear + 1);
+        } else if control.gear_down && gear > 0 {
+            transmission.shift(gear - 1);
+        } else if transmission.automatic {
+            if let Some(state) = physics_state.states.get(&entity) {
+                let engine_speed = state.qd;
+                if engine_speed > transmission.upshift_speed {
+                    transmission.shift(gear + 1);
+                } else if engine_speed < transmission.downshift_speed && gear > 0 {
+                    transmission.shift(gear - 1);
+                }
+            }
+        }
+
+        let gear = if transmission.reverse {
+            -1.
+        } else {
+            transmission.gear() as f64
+        };
+        let low = if transmission.low { 1. } else { 0. };
+        transmission.outputs.insert("gear".to_string(), gear);
+        transmission.outputs.insert("low_range".to_string(), low);
+        control.gear_up = false;
+        control.gear_down = false;
+        control.toggle_low_range = false;
+    }
+}
@@ -0,0 +1,159 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPlugin, EguiSet};
+use cameras::camera_az_el::PointerOverUi;
+
+use crate::{
+    build::{CarDefinition, CarEntities},
+    config::{CarConfig, RunningCar},
+    drive_mode::DriveModes,
+    tire::PointTire,
+};
+
+// Side panel for tuning the car in `CarEntities` while it drives: suspension
+// stiffness and damping of the front and rear axles, brake torque and balance,
+// tire friction and the throttle map of the drive mode. Changes are written into
+// the running components like a reloaded setup file (`CarConfigFile`). The
+// cameras leave the mouse alone while it is over an egui window.
+pub struct TuningPanelPlugin;
+
+impl Plugin for TuningPanelPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+        app.add_systems(PreUpdate, egui_pointer_system.after(EguiSet::BeginFrame))
+            .add_systems(Update, tuning_panel_system);
+    }
+}
+
+// the pointer is over the UI when egui uses it, for the camera controls
+fn egui_pointer_system(mut contexts: EguiContexts, pointer_over_ui: Option<ResMut<PointerOverUi>>) {
+    if let Some(mut pointer_over_ui) = pointer_over_ui {
+        let ctx = contexts.ctx_mut();
+        pointer_over_ui.set(ctx.is_pointer_over_area() || ctx.wants_pointer_input());
+    }
+}
+
+fn tuning_panel_system(
+    mut contexts: EguiContexts,
+    car_entities: Option<Res<CarEntities>>,
+    car: Option<ResMut<CarDefinition>>,
+    mut running_car: RunningCar,
+    mut tires: Query<&mut PointTire>,
+    mut drive_modes: Query<&mut DriveModes>,
+) {
+    let (car_entities, mut car) = match (car_entities, car) {
+        (Some(car_entities), Some(car)) => (car_entities, car),
+        _ => return,
+    };
+    // only the changed values are set
+    let mut config = CarConfig::default();
+    let mut friction = tires
+        .iter()
+        .find(|tire| car_entities.wheels.contains(&tire.joint_entity()))
+        .map(|tire| tire.friction_scale());
+    let mut friction_changed = false;
+    let mut throttle_map = drive_modes.get(car_entities.chassis).ok().map(|modes| {
+        let profile = modes.profile();
+        (
+            modes.mode.name(),
+            profile.max_throttle,
+            profile.throttle_exponent,
+        )
+    });
+    let mut throttle_map_changed = false;
+
+    egui::SidePanel::right("tuning")
+        .default_width(280.)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.heading("Tuning");
+
+            // the rear values are the ones of the second axle, set on all rear axles
+            let axles = [
+                ("front", 0, &mut config.front_suspension),
+                ("rear", 2, &mut config.rear_suspension),
+            ];
+            for (name, corner, suspension_config) in axles {
+                let suspension = match car.suspension.get(corner) {
+                    Some(suspension) => suspension,
+                    None => continue,
+                };
+                let mut stiffness = suspension.stiffness;
+                let mut damping = suspension.damping;
+                ui.label(format!("{} suspension", name));
+                let slider = egui::Slider::new(&mut stiffness, 1e3..=1e6)
+                    .logarithmic(true)
+                    .text("stiffness (N/m)");
+                if ui.add(slider).changed() {
+                    suspension_config.stiffness = Some(stiffness);
+                }
+                let slider = egui::Slider::new(&mut damping, 10.0..=1e5)
+                    .logarithmic(true)
+                    .text("damping (Ns/m)");
+                if ui.add(slider).changed() {
+                    suspension_config.damping = Some(damping);
+                }
+            }
+
+            ui.separator();
+            ui.label("brakes");
+            let mut total_torque = car.brake.total_torque;
+            let slider = egui::Slider::new(&mut total_torque, 100.0..=5e4)
+                .logarithmic(true)
+                .text("total torque (Nm)");
+            if ui.add(slider).changed() {
+                config.brake.total_torque = Some(total_torque);
+            }
+            let mut front_bias = car.axles[0].brake_share;
+            if ui
+                .add(egui::Slider::new(&mut front_bias, 0.0..=1.0).text("front bias"))
+                .changed()
+            {
+                config.brake.front_bias = Some(front_bias);
+            }
+
+            if let Some(friction) = friction.as_mut() {
+                ui.separator();
+                ui.label("tires");
+                friction_changed = ui
+                    .add(egui::Slider::new(friction, 0.2..=2.0).text("friction scale"))
+                    .changed();
+            }
+
+            if let Some((mode, max_throttle, exponent)) = throttle_map.as_mut() {
+                ui.separator();
+                ui.label(format!("throttle map ({} mode)", mode));
+                throttle_map_changed |= ui
+                    .add(egui::Slider::new(max_throttle, 0.1..=1.0).text("max throttle"))
+                    .changed();
+                throttle_map_changed |= ui
+                    .add(egui::Slider::new(exponent, 0.3..=3.0).text("exponent"))
+                    .changed();
+            }
+        });
+
+    let suspension = [&config.front_suspension, &config.rear_suspension];
+    if suspension
+        .iter()
+        .any(|config| config.stiffness.is_some() || config.damping.is_some())
+        || config.brake.total_torque.is_some()
+        || config.brake.front_bias.is_some()
+    {
+        config.apply(&mut car);
+        running_car.update(&config, &car, &car_entities);
+    }
+    if let (true, Some(friction)) = (friction_changed, friction) {
+        for mut tire in tires.iter_mut() {
+            if car_entities.wheels.contains(&tire.joint_entity()) {
+                tire.set_friction_scale(friction);
+            }
+        }
+    }
+    if let (true, Some((_, max_throttle, exponent))) = (throttle_map_changed, throttle_map) {
+        if let Ok(mut modes) = drive_modes.get_mut(car_entities.chassis) {
+            let profile = modes.profile_mut();
+            profile.max_throttle = max_throttle;
+            profile.throttle_exponent = exponent;
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_integrator::PhysicsState;
+
+use rigid_body::joint::Joint;
+
+use crate::{
+    drive_mode::{throttle_pedal, DriveModes},
+    engine::Engine,
+};
+
+use super::control::{CarControl, CarPart};
+
+// Turbocharger on the engine entity. Boost multiplies the engine torque, and
+// follows the throttle with a first order lag (spool time), so torque response
+// lags the throttle. Boost is only available once the engine speed (rad/s) is
+// high enough to drive the turbine.
+#[derive(Component, Clone)]
+pub struct Turbo {
+    pub max_boost: f64,        // wastegate limit, as a fraction of the engine torque
+    pub spool_time: f64,       // time constant of the boost response
+    pub full_boost_speed: f64, // engine speed where the full boost is available
+    pub boost: f64,
+    pub outputs: HashMap<String, f64>,
+}
+
+impl Turbo {
+    pub fn new(max_boost: f64, spool_time: f64, full_boost_speed: f64) -> Self {
+        Self {
+            max_boost,
+            spool_time,
+            full_boost_speed,
+            boost: 0.,
+            outputs: HashMap::new(),
+        }
+    }
+
+    // boost the turbo spools towards
+    pub fn target_boost(&self, throttle: f64, engine_speed: f64) -> f64 {
+        let available = (engine_speed / self.full_boost_speed).clamp(0., 1.);
+        throttle * available * self.max_boost
+    }
+
+    // multiplier on the engine torque
+    pub fn torque_factor(&self) -> f64 {
+        1. + self.boost
+    }
+}
+
+// runs once per time step (not in the physics schedule), like the transmission
+pub fn turbo_system(
+    fixed_time: Res<FixedTime>,
+    physics_state: Res<PhysicsState<Joint>>,
+    controls: Query<(&CarControl, Option<&DriveModes>)>,
+    mut turbos: Query<(Entity, &Engine, &mut Turbo, &CarPart)>,
+) {
+    let dt = fixed_time.period.as_secs_f64();
+    for (entity, engine, mut turbo, part) in turbos.iter_mut() {
+        if let (Some(state), Ok((control, modes))) =
+            (physics_state.states.get(&entity), controls.get(part.0))
+        {
+            let throttle = engine.throttle(throttle_pedal(control, modes), state.qd);
+            let target = turbo.target_boost(throttle, state.qd);
+            let rate = (dt / turbo.spool_time).min(1.);
+            turbo.boost += (target - turbo.boost) * rate;
+        }
+        let boost = turbo.boost;
+        turbo.outputs.insert("boost".to_string(), boost);
+    }
+}
@@ -0,0 +1,218 @@
+use bevy::prelude::*;
+use bevy_integrator::{integrator_schedule, PhysicsSchedule, PhysicsSet, PhysicsState, SimTime};
+use rigid_body::{
+    joint::{Base, Joint},
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+    validation::{joint_state, ValidationCase, ValidationSamples},
+};
+
+use crate::{
+    bicycle::BicycleModel,
+    build::{sized_wheel, spawn_car, Wheel},
+    control::CarControl,
+    presets::Preset,
+    setup::simulation_setup,
+    terrain::{build_described_terrain, TerrainDescription, TerrainLayout},
+    tire::{point_tire_system, PointTire},
+};
+
+const GRAVITY: f64 = 9.81;
+
+// the cases of the tire contact and the car
+pub fn validation_cases() -> Vec<ValidationCase> {
+    vec![
+        ValidationCase::new("rolling_cylinder", 10., 0.01, rolling_cylinder_setup),
+        ValidationCase::new("steady_cornering", 25., 0.1, steady_cornering_setup),
+    ]
+}
+
+fn flat_terrain_setup(app: &mut App) {
+    app.insert_resource(TerrainDescription {
+        layout: TerrainLayout::Flat { cells: [40, 40] },
+        props: Vec::new(),
+    })
+    .add_systems(Startup, build_described_terrain);
+}
+
+// Solid cylinder (a wheel with its tire) rolling down a slope without slipping:
+// a = g sin(slope) / (1 + I / (m r^2)), with r the rolling radius. The slope is
+// flat ground with gravity tilted back along x.
+const SLOPE: f64 = 0.1; // rad
+const CYLINDER_SPEED: f64 = 10.; // initial, down the slope
+const CYLINDER_MASS: f64 = 100.;
+
+// the tire of the cylinder, with the rolling radius at the static deflection so
+// it rolls from the start
+fn cylinder_wheel() -> Wheel {
+    let mut wheel = sized_wheel(0.325, 0.2, CYLINDER_MASS, 0.);
+    let deflection = CYLINDER_MASS * GRAVITY * SLOPE.cos() / wheel.stiffness[0];
+    wheel.rolling_radius = wheel.radius - deflection;
+    wheel
+}
+
+fn cylinder_acceleration() -> f64 {
+    let wheel = cylinder_wheel();
+    let moi_ratio = 0.5 * wheel.radius.powi(2) / wheel.rolling_radius.powi(2);
+    GRAVITY * SLOPE.sin() / (1. + moi_ratio)
+}
+
+fn rolling_cylinder_setup(app: &mut App) {
+    flat_terrain_setup(app);
+    app.add_systems(Startup, rolling_cylinder_startup_system)
+        .add_systems(
+            PhysicsSchedule,
+            point_tire_system.in_set(PhysicsSet::Evaluate),
+        )
+        .add_systems(
+            FixedUpdate,
+            rolling_cylinder_system.after(integrator_schedule::<Joint>),
+        );
+}
+
+fn rolling_cylinder_startup_system(mut commands: Commands) {
+    let base = Joint::base(Motion::new(
+        [-GRAVITY * SLOPE.sin(), 0., GRAVITY * SLOPE.cos()],
+        [0., 0., 0.],
+    ));
+    let base_id = commands.spawn((base, Base)).id();
+    let wheel = cylinder_wheel();
+
+    // down the slope, then up from the ground, then the spin of the cylinder
+    let mut x = Joint::px(
+        "cylinder_x".to_string(),
+        Inertia::zero(),
+        Xform::pos(100., 400., 0.),
+    );
+    x.qd = CYLINDER_SPEED;
+    let x_id = commands.spawn(x).set_parent(base_id).id();
+
+    let mut z = Joint::pz("cylinder_z".to_string(), Inertia::zero(), Xform::identity());
+    z.q = wheel.rolling_radius;
+    let z_id = commands.spawn(z).set_parent(x_id).id();
+
+    let moi_y = 0.5 * CYLINDER_MASS * wheel.radius.powi(2);
+    let moi_xz = CYLINDER_MASS * (3. * wheel.radius.powi(2) + wheel.width.powi(2)) / 12.;
+    let inertia = Inertia::new(
+        CYLINDER_MASS,
+        Vector::zeros(),
+        Matrix::from_diagonal(&Vector::new(moi_xz, moi_y, moi_xz)),
+    );
+    let mut cylinder = Joint::ry("cylinder".to_string(), inertia, Xform::identity());
+    cylinder.qd = CYLINDER_SPEED / wheel.rolling_radius;
+    let cylinder_id = commands.spawn(cylinder).set_parent(z_id).id();
+
+    commands.spawn(PointTire::new(
+        cylinder_id,
+        z_id,
+        wheel.stiffness,
+        wheel.damping,
+        wheel.tire_model.clone(),
+        wheel.rolling_radius,
+        wheel.low_speed,
+        wheel.radius,
+        wheel.width,
+        1.,
+        wheel.filter_time,
+        5,
+        51,
+        0.01,
+    ));
+}
+
+fn rolling_cylinder_system(
+    time: Res<SimTime>,
+    states: Res<PhysicsState<Joint>>,
+    joints: Query<(Entity, &Joint)>,
+    mut samples: ResMut<ValidationSamples>,
+) {
+    let state = match joint_state(&states, &joints, "cylinder_x") {
+        Some(state) => state,
+        None => return,
+    };
+    let (t, acceleration) = (time.time(), cylinder_acceleration());
+    let distance = CYLINDER_SPEED * t + 0.5 * acceleration * t.powi(2);
+    samples.record("distance", state.q, distance);
+    samples.record("speed", state.qd, CYLINDER_SPEED + acceleration * t);
+}
+
+// The car on a constant steering at constant speed, once it settles, against the
+// steady state of the linear bicycle model of it (`BicycleModel`): yaw rate
+// r = v delta / (L + K v^2), with K the understeer gradient, and lateral
+// acceleration v r. Kept to a low lateral acceleration, where the model holds.
+const CORNERING_SPEED: f64 = 10.;
+const CORNERING_STEERING: f32 = 0.1;
+const CORNERING_SETTLE_TIME: f64 = 15.;
+
+#[derive(Component)]
+struct SteadyCornering {
+    model: BicycleModel,
+    velocity: Option<Vector>, // absolute, at the last time step
+}
+
+fn steady_cornering_setup(app: &mut App) {
+    simulation_setup(app);
+    flat_terrain_setup(app);
+    app.add_systems(Startup, steady_cornering_startup_system)
+        .add_systems(
+            FixedUpdate,
+            steady_cornering_system.before(integrator_schedule::<Joint>),
+        );
+}
+
+fn steady_cornering_startup_system(mut commands: Commands) {
+    let base = Joint::base(Motion::new([0., 0., GRAVITY], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    let car = Preset::Car.build();
+    let z = car.initial_position()[2];
+    let entities = spawn_car(
+        &mut commands,
+        &car,
+        base_id,
+        [400., 300., z],
+        0.,
+        Color::rgb(0.9, 0.1, 0.2),
+    );
+    commands.entity(entities.chassis).insert(SteadyCornering {
+        model: BicycleModel::from_car(&car),
+        velocity: None,
+    });
+    commands.insert_resource(entities);
+}
+
+// runs before the integrator, like `sysid_system`, on the state at the start of the step
+fn steady_cornering_system(
+    time: Res<SimTime>,
+    mut samples: ResMut<ValidationSamples>,
+    mut cars: Query<(&Joint, &mut CarControl, &mut SteadyCornering)>,
+) {
+    for (joint, mut control, mut cornering) in cars.iter_mut() {
+        // chassis velocities are in chassis coordinates
+        let speed = joint.v.v.x;
+
+        // acceleration from the change in the absolute velocity, in chassis coordinates
+        let x0i = joint.x.inverse();
+        let velocity = (x0i * joint.v)
+            .velocity_point(x0i.transform_point(Vector::zeros()))
+            .vel;
+        let acceleration = cornering
+            .velocity
+            .map(|previous| joint.x * ((velocity - previous) / time.dt));
+        cornering.velocity = Some(velocity);
+
+        let pedal = 0.5 * (CORNERING_SPEED - speed);
+        control.throttle = pedal.clamp(0., 1.) as f32;
+        control.brake = (-pedal).clamp(0., 1.) as f32;
+        control.steering = CORNERING_STEERING;
+        control.steering_input = CORNERING_STEERING;
+
+        let acceleration = match acceleration {
+            Some(acceleration) if time.time() >= CORNERING_SETTLE_TIME => acceleration,
+            _ => continue,
+        };
+        let steer_angle = CORNERING_STEERING as f64 * cornering.model.lock_angle;
+        let yaw_rate = cornering.model.steady_state_yaw_rate(speed, steer_angle);
+        samples.record("yaw_rate", joint.v.w.z, yaw_rate);
+        samples.record("lateral_acceleration", acceleration.y, speed * yaw_rate);
+    }
+}
@@ -0,0 +1,249 @@
+use bevy::prelude::*;
+use rigid_body::{joint::Joint, physics_thread::Mirrored, sva::Vector};
+
+use crate::{build::CarEntities, tire::PointTire};
+
+const BAR_HEIGHT: f32 = 80.; // pixels at `max_load`
+
+// Tire loads and weight transfer of a car, on the chassis entity, updated after
+// every time step. The transfers include the static weight distribution.
+#[derive(Component, Clone)]
+pub struct WheelLoads {
+    pub wheels: Vec<Entity>,        // left and right of each axle, front first
+    pub loads: Vec<f64>,            // tire normal force (N)
+    pub longitudinal_transfer: f64, // half the rear axles minus front axle load (N)
+    pub lateral_transfer: f64,      // half the left minus right side load (N)
+    pub roll: f64,                  // (rad) positive with the left side up
+    pub pitch: f64,                 // (rad) positive nose down
+}
+
+impl WheelLoads {
+    pub fn new(wheels: Vec<Entity>) -> Self {
+        Self {
+            loads: vec![0.; wheels.len()],
+            wheels,
+            longitudinal_transfer: 0.,
+            lateral_transfer: 0.,
+            roll: 0.,
+            pitch: 0.,
+        }
+    }
+}
+
+// the wheels are entities of each world
+impl Mirrored for WheelLoads {
+    type Data = WheelLoads;
+
+    fn extract(&self) -> WheelLoads {
+        self.clone()
+    }
+
+    fn apply(&mut self, data: &WheelLoads) {
+        let wheels = std::mem::take(&mut self.wheels);
+        *self = data.clone();
+        self.wheels = wheels;
+    }
+}
+
+// runs once per time step (not in the physics schedule), like the checkpoints
+pub fn wheel_load_system(mut cars: Query<(&Joint, &mut WheelLoads)>, tires: Query<&PointTire>) {
+    for (joint, mut loads) in cars.iter_mut() {
+        let mut wheel_loads = vec![0.; loads.wheels.len()];
+        for (load, wheel) in wheel_loads.iter_mut().zip(&loads.wheels) {
+            if let Some(tire) = tires.iter().find(|tire| tire.joint_entity() == *wheel) {
+                *load = tire.forces()[2];
+            }
+        }
+        let front: f64 = wheel_loads.iter().take(2).sum();
+        let rear: f64 = wheel_loads.iter().skip(2).sum();
+        let left: f64 = wheel_loads.iter().step_by(2).sum();
+        let right: f64 = wheel_loads.iter().skip(1).step_by(2).sum();
+        loads.loads = wheel_loads;
+        loads.longitudinal_transfer = (rear - front) / 2.;
+        loads.lateral_transfer = (left - right) / 2.;
+
+        // chassis axes in absolute coordinates
+        let x0i = joint.x.inverse();
+        let forward = x0i * Vector::x();
+        let left = x0i * Vector::y();
+        loads.roll = left.z.clamp(-1., 1.).asin();
+        loads.pitch = (-forward.z).clamp(-1., 1.).asin();
+    }
+}
+
+// Live bar chart of the tire loads of the car in `CarEntities`, laid out like
+// the wheels, with the weight transfer and the body angles
+#[derive(Resource)]
+pub struct WheelLoadOverlay {
+    pub max_load: f64, // load (N) at the top of the bars
+}
+
+impl Default for WheelLoadOverlay {
+    fn default() -> Self {
+        Self { max_load: 8000. }
+    }
+}
+
+#[derive(Component)]
+struct LoadBar(usize);
+
+// holds a row of bars per axle, built for the wheels of the car
+#[derive(Component)]
+struct LoadBars;
+
+#[derive(Component)]
+struct LoadText;
+
+pub fn wheel_load_setup(app: &mut App) {
+    app.init_resource::<WheelLoadOverlay>()
+        .add_systems(Startup, wheel_load_overlay_startup_system)
+        .add_systems(Update, wheel_load_overlay_system);
+}
+
+fn wheel_load_overlay_startup_system(mut commands: Commands) {
+    let text_style = TextStyle {
+        font_size: 16.,
+        color: Color::WHITE,
+        ..default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.),
+                top: Val::Px(10.),
+                padding: UiRect::all(Val::Px(8.)),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(6.),
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.5).into(),
+            ..default()
+        })
+        .with_children(|panel| {
+            panel.spawn((
+                NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(6.),
+                        ..default()
+                    },
+                    ..default()
+                },
+                LoadBars,
+            ));
+            panel.spawn((TextBundle::from_section("", text_style), LoadText));
+        });
+}
+
+// a row per axle, front axle on top
+fn spawn_load_bars(parent: &mut ChildBuilder, corners: &[String]) {
+    let text_style = TextStyle {
+        font_size: 16.,
+        color: Color::WHITE,
+        ..default()
+    };
+    for (axle, names) in corners.chunks(2).enumerate() {
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    column_gap: Val::Px(30.),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|row| {
+                for (side, name) in names.iter().enumerate() {
+                    spawn_load_bar(row, 2 * axle + side, name, text_style.clone());
+                }
+            });
+    }
+}
+
+fn spawn_load_bar(parent: &mut ChildBuilder, corner: usize, name: &str, text_style: TextStyle) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|column| {
+            column
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(24.),
+                        height: Val::Px(BAR_HEIGHT),
+                        align_items: AlignItems::FlexEnd,
+                        ..default()
+                    },
+                    background_color: Color::rgba(1., 1., 1., 0.15).into(),
+                    ..default()
+                })
+                .with_children(|background| {
+                    background.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(100.),
+                                height: Val::Px(0.),
+                                ..default()
+                            },
+                            background_color: Color::rgb(0.3, 0.7, 0.9).into(),
+                            ..default()
+                        },
+                        LoadBar(corner),
+                    ));
+                });
+            column.spawn(TextBundle::from_section(name, text_style));
+        });
+}
+
+fn wheel_load_overlay_system(
+    mut commands: Commands,
+    overlay: Res<WheelLoadOverlay>,
+    car: Option<Res<CarEntities>>,
+    cars: Query<&WheelLoads>,
+    containers: Query<Entity, With<LoadBars>>,
+    mut bars: Query<(&mut Style, &mut BackgroundColor, &LoadBar)>,
+    mut text: Query<&mut Text, With<LoadText>>,
+) {
+    let (car, loads) = match car {
+        Some(car) => match cars.get(car.chassis) {
+            Ok(loads) => (car, loads),
+            Err(_) => return,
+        },
+        None => return,
+    };
+    // the bars are built once the car is spawned, and again if its wheels change
+    if bars.iter().count() != loads.loads.len() {
+        if let Ok(container) = containers.get_single() {
+            commands
+                .entity(container)
+                .despawn_descendants()
+                .with_children(|parent| spawn_load_bars(parent, &car.corners));
+        }
+        return;
+    }
+    for (mut style, mut color, bar) in bars.iter_mut() {
+        let fraction = (loads.loads[bar.0] / overlay.max_load) as f32;
+        style.height = Val::Px(BAR_HEIGHT * fraction.clamp(0., 1.));
+        // red when the tire is unloaded or off the ground
+        color.0 = if fraction < 0.1 {
+            Color::rgb(0.9, 0.3, 0.2)
+        } else {
+            Color::rgb(0.3, 0.7, 0.9)
+        };
+    }
+    if let Ok(mut text) = text.get_single_mut() {
+        text.sections[0].value = format!(
+            "long {:+.2} kN\nlat {:+.2} kN\nroll {:+.1}°\npitch {:+.1}°",
+            loads.longitudinal_transfer / 1000.,
+            loads.lateral_transfer / 1000.,
+            loads.roll.to_degrees(),
+            loads.pitch.to_degrees()
+        );
+    }
+}
@@ -0,0 +1,31 @@
+# Builds the car FMU (FMI 2.0 co-simulation) for this platform into fmu/car.fmu,
+# the release library of the `car_fmu` crate with modelDescription.xml:
+#   python3 fmu/package.py
+import platform
+import subprocess
+import zipfile
+from pathlib import Path
+
+FMU = Path(__file__).parent
+ROOT = FMU.parent
+
+# FMI platform folder and library name of the cargo build
+PLATFORMS = {
+    "Linux": ("linux64", "libcar_fmu.so", ".so"),
+    "Darwin": ("darwin64", "libcar_fmu.dylib", ".dylib"),
+    "Windows": ("win64", "car_fmu.dll", ".dll"),
+}
+
+
+def main():
+    subprocess.run(["cargo", "build", "--release", "-p", "car_fmu"], cwd=ROOT, check=True)
+    folder, library, extension = PLATFORMS[platform.system()]
+    with zipfile.ZipFile(FMU / "car.fmu", "w", zipfile.ZIP_DEFLATED) as fmu:
+        fmu.write(FMU / "modelDescription.xml", "modelDescription.xml")
+        # the library is named after the model identifier
+        fmu.write(ROOT / "target" / "release" / library, f"binaries/{folder}/car{extension}")
+    print(f"written {FMU / 'car.fmu'}")
+
+
+if __name__ == "__main__":
+    main()
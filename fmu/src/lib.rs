@@ -0,0 +1,747 @@
+// the importing tool calls these as the FMI 2.0 standard describes
+#![allow(clippy::missing_safety_doc)]
+
+use std::{
+    ffi::{c_char, c_int, c_uint, c_void, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+};
+
+use bevy::prelude::*;
+use car::{headless::HeadlessCar, presets::Preset};
+use rigid_body::joint::Joint;
+
+// FMI 2.0 co-simulation FMU of the car on the circuit, see modelDescription.xml
+// for the variables. Every instance is a headless simulation, created when the
+// initialization mode is entered, with the vehicle preset parameter, and stepped
+// in fixed time steps of `DT` up to each communication point.
+
+const GUID: &str = "{7f3c9a52-6e1b-4d1a-9a43-2c5e8b0f6d11}";
+const DT: f64 = 0.002;
+
+// value references
+const THROTTLE: c_uint = 0;
+const BRAKE: c_uint = 1;
+const STEERING: c_uint = 2;
+const CHASSIS: c_uint = 10; // x, y, z, roll, pitch, yaw, vx, vy, vz, roll, pitch and yaw rates
+const WHEEL_SPEEDS: c_uint = 30; // fl, fr, rl, rr
+const VEHICLE: c_uint = 100;
+
+const OK: c_int = 0;
+const WARNING: c_int = 1;
+const DISCARD: c_int = 2;
+const ERROR: c_int = 3;
+
+const CO_SIMULATION: c_int = 1;
+const LAST_SUCCESSFUL_TIME: c_int = 2;
+
+type Logger =
+    unsafe extern "C" fn(*mut c_void, *const c_char, c_int, *const c_char, *const c_char, ...);
+
+// fmi2CallbackFunctions, only the logger is used
+#[repr(C)]
+#[allow(dead_code)]
+pub struct CallbackFunctions {
+    logger: Option<Logger>,
+    allocate_memory: Option<unsafe extern "C" fn(usize, usize) -> *mut c_void>,
+    free_memory: Option<unsafe extern "C" fn(*mut c_void)>,
+    step_finished: Option<unsafe extern "C" fn(*mut c_void, c_int)>,
+    component_environment: *mut c_void,
+}
+
+struct Instance {
+    name: CString,
+    logger: Option<Logger>,
+    environment: *mut c_void,
+    vehicle: CString,
+    start_time: f64,
+    inputs: [f64; 3], // throttle, brake, steering
+    simulation: Option<Simulation>,
+}
+
+impl Instance {
+    fn log_error(&self, message: &str) {
+        let (logger, message) = match (self.logger, CString::new(message)) {
+            (Some(logger), Ok(message)) => (logger, message),
+            _ => return,
+        };
+        unsafe {
+            logger(
+                self.environment,
+                self.name.as_ptr(),
+                ERROR,
+                c"logError".as_ptr(),
+                c"%s".as_ptr(),
+                message.as_ptr(),
+            );
+        }
+    }
+
+    fn time(&self) -> f64 {
+        match &self.simulation {
+            Some(simulation) => simulation.time(),
+            None => self.start_time,
+        }
+    }
+}
+
+struct Simulation {
+    car: HeadlessCar,
+    chassis_joints: Vec<Option<Entity>>, // chassis_px, py, pz, rx, ry, rz
+}
+
+impl Simulation {
+    fn new(preset: Preset, start_time: f64) -> Self {
+        let mut car = HeadlessCar::new(preset.build(), DT, start_time);
+        let chassis_joints = ["px", "py", "pz", "rx", "ry", "rz"]
+            .iter()
+            .map(|axis| car.joint(&format!("chassis_{}", axis)))
+            .collect();
+        Self {
+            car,
+            chassis_joints,
+        }
+    }
+
+    fn time(&self) -> f64 {
+        self.car.time()
+    }
+
+    // takes time steps up to the given time, with the inputs held
+    fn step_to(&mut self, end: f64, inputs: [f64; 3]) {
+        if let Some(mut control) = self.car.control() {
+            control.throttle = (inputs[0] as f32).clamp(0., 1.);
+            control.brake = (inputs[1] as f32).clamp(0., 1.);
+            control.steering_input = (inputs[2] as f32).clamp(-1., 1.);
+        }
+        self.car.step_to(end);
+    }
+
+    // position and angles from the chassis joint states of the integrator, the
+    // velocities (chassis axes) from the chassis joint, of the last stage of the
+    // time step
+    fn chassis(&self) -> [f64; 12] {
+        let mut values = [f64::NAN; 12];
+        for (value, joint) in values.iter_mut().zip(self.chassis_joints.iter()) {
+            if let Some(state) = joint.and_then(|joint| self.car.state(joint)) {
+                *value = state.q;
+            }
+        }
+        if let Some(chassis) = self.car.app.world.get::<Joint>(self.car.car().chassis) {
+            let (v, w) = (chassis.v.v, chassis.v.w);
+            values[6..].copy_from_slice(&[v.x, v.y, v.z, w.x, w.y, w.z]);
+        }
+        values
+    }
+
+    fn wheel_speed(&self, index: usize) -> f64 {
+        let wheel = self.car.car().wheels.get(index).copied();
+        let state = wheel.and_then(|wheel| self.car.state(wheel));
+        state.map_or(f64::NAN, |state| state.qd)
+    }
+}
+
+// Runs an entry point, a panic doesn't unwind into the importing tool: it is
+// logged and returns the error value, and the simulation of the instance is
+// dropped, as it can't go on. Only the constant getters go without.
+unsafe fn guarded<T>(
+    component: *mut c_void,
+    function: &str,
+    error: T,
+    body: impl FnOnce() -> T,
+) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(value) => value,
+        Err(payload) => {
+            let message = match (
+                payload.downcast_ref::<&str>(),
+                payload.downcast_ref::<String>(),
+            ) {
+                (Some(message), _) => message,
+                (_, Some(message)) => message.as_str(),
+                _ => "panic",
+            };
+            if let Some(instance) = instance(component) {
+                instance.simulation = None;
+                instance.log_error(&format!("{}: {}", function, message));
+            }
+            error
+        }
+    }
+}
+
+unsafe fn instance<'a>(component: *mut c_void) -> Option<&'a mut Instance> {
+    (component as *mut Instance).as_mut()
+}
+
+unsafe fn string(value: *const c_char) -> Option<String> {
+    match value.is_null() {
+        true => None,
+        false => Some(CStr::from_ptr(value).to_string_lossy().into_owned()),
+    }
+}
+
+unsafe fn slice<'a, T>(values: *const T, length: usize) -> &'a [T] {
+    match length {
+        0 => &[],
+        _ => std::slice::from_raw_parts(values, length),
+    }
+}
+
+unsafe fn slice_mut<'a, T>(values: *mut T, length: usize) -> &'a mut [T] {
+    match length {
+        0 => &mut [],
+        _ => std::slice::from_raw_parts_mut(values, length),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn fmi2GetTypesPlatform() -> *const c_char {
+    c"default".as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn fmi2GetVersion() -> *const c_char {
+    c"2.0".as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2SetDebugLogging(
+    component: *mut c_void,
+    _logging_on: c_int,
+    _categories: usize,
+    _category: *const *const c_char,
+) -> c_int {
+    guarded(component, "fmi2SetDebugLogging", ERROR, || {
+        match instance(component) {
+            Some(_) => OK,
+            None => ERROR,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2Instantiate(
+    instance_name: *const c_char,
+    fmu_type: c_int,
+    guid: *const c_char,
+    _resource_location: *const c_char,
+    functions: *const CallbackFunctions,
+    _visible: c_int,
+    _logging_on: c_int,
+) -> *mut c_void {
+    guarded(
+        std::ptr::null_mut(),
+        "fmi2Instantiate",
+        std::ptr::null_mut(),
+        || {
+            let functions = functions.as_ref();
+            let instance = Instance {
+                name: CString::new(string(instance_name).unwrap_or_default()).unwrap_or_default(),
+                logger: functions.and_then(|functions| functions.logger),
+                environment: functions.map_or(std::ptr::null_mut(), |f| f.component_environment),
+                vehicle: CString::new("car").unwrap_or_default(),
+                start_time: 0.,
+                inputs: [0.; 3],
+                simulation: None,
+            };
+            if fmu_type != CO_SIMULATION {
+                instance.log_error("the car FMU is for co-simulation only");
+                return std::ptr::null_mut();
+            }
+            if string(guid).as_deref() != Some(GUID) {
+                instance.log_error("the GUID doesn't match the one of modelDescription.xml");
+                return std::ptr::null_mut();
+            }
+            Box::into_raw(Box::new(instance)) as *mut c_void
+        },
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2FreeInstance(component: *mut c_void) {
+    guarded(std::ptr::null_mut(), "fmi2FreeInstance", (), || {
+        if !component.is_null() {
+            drop(Box::from_raw(component as *mut Instance));
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2SetupExperiment(
+    component: *mut c_void,
+    _tolerance_defined: c_int,
+    _tolerance: f64,
+    start_time: f64,
+    _stop_time_defined: c_int,
+    _stop_time: f64,
+) -> c_int {
+    guarded(component, "fmi2SetupExperiment", ERROR, || {
+        match instance(component) {
+            Some(instance) => {
+                instance.start_time = start_time;
+                OK
+            }
+            None => ERROR,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2EnterInitializationMode(component: *mut c_void) -> c_int {
+    guarded(component, "fmi2EnterInitializationMode", ERROR, || {
+        let instance = match instance(component) {
+            Some(instance) => instance,
+            None => return ERROR,
+        };
+        let vehicle = instance.vehicle.to_string_lossy().into_owned();
+        match Preset::from_name(&vehicle) {
+            Some(preset) => {
+                instance.simulation = Some(Simulation::new(preset, instance.start_time));
+                OK
+            }
+            None => {
+                instance.log_error(&format!("unknown vehicle preset: {}", vehicle));
+                ERROR
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2ExitInitializationMode(component: *mut c_void) -> c_int {
+    guarded(
+        component,
+        "fmi2ExitInitializationMode",
+        ERROR,
+        || match instance(component) {
+            Some(instance) if instance.simulation.is_some() => OK,
+            _ => ERROR,
+        },
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2Terminate(component: *mut c_void) -> c_int {
+    guarded(component, "fmi2Terminate", ERROR, || {
+        match instance(component) {
+            Some(_) => OK,
+            None => ERROR,
+        }
+    })
+}
+
+// back to the instantiated state, the simulation is created again on initialization
+#[no_mangle]
+pub unsafe extern "C" fn fmi2Reset(component: *mut c_void) -> c_int {
+    guarded(component, "fmi2Reset", ERROR, || {
+        match instance(component) {
+            Some(instance) => {
+                instance.simulation = None;
+                instance.start_time = 0.;
+                instance.inputs = [0.; 3];
+                OK
+            }
+            None => ERROR,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2GetReal(
+    component: *mut c_void,
+    vr: *const c_uint,
+    nvr: usize,
+    value: *mut f64,
+) -> c_int {
+    guarded(component, "fmi2GetReal", ERROR, || {
+        let instance = match instance(component) {
+            Some(instance) => instance,
+            None => return ERROR,
+        };
+        let (chassis, wheels) = match &instance.simulation {
+            Some(simulation) => (
+                simulation.chassis(),
+                [0, 1, 2, 3].map(|index| simulation.wheel_speed(index)),
+            ),
+            None => ([0.; 12], [0.; 4]),
+        };
+        let values = slice_mut(value, nvr);
+        for (reference, value) in slice(vr, nvr).iter().zip(values.iter_mut()) {
+            *value = match *reference {
+                THROTTLE | BRAKE | STEERING => instance.inputs[*reference as usize],
+                r if (CHASSIS..CHASSIS + 12).contains(&r) => chassis[(r - CHASSIS) as usize],
+                r if (WHEEL_SPEEDS..WHEEL_SPEEDS + 4).contains(&r) => {
+                    wheels[(r - WHEEL_SPEEDS) as usize]
+                }
+                r => {
+                    instance.log_error(&format!("no real variable with value reference {}", r));
+                    return ERROR;
+                }
+            };
+        }
+        OK
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2SetReal(
+    component: *mut c_void,
+    vr: *const c_uint,
+    nvr: usize,
+    value: *const f64,
+) -> c_int {
+    guarded(component, "fmi2SetReal", ERROR, || {
+        let instance = match instance(component) {
+            Some(instance) => instance,
+            None => return ERROR,
+        };
+        for (reference, value) in slice(vr, nvr).iter().zip(slice(value, nvr)) {
+            match *reference {
+                THROTTLE | BRAKE | STEERING => instance.inputs[*reference as usize] = *value,
+                r => {
+                    instance.log_error(&format!("no real input with value reference {}", r));
+                    return ERROR;
+                }
+            }
+        }
+        OK
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2GetString(
+    component: *mut c_void,
+    vr: *const c_uint,
+    nvr: usize,
+    value: *mut *const c_char,
+) -> c_int {
+    guarded(component, "fmi2GetString", ERROR, || {
+        let instance = match instance(component) {
+            Some(instance) => instance,
+            None => return ERROR,
+        };
+        let values = slice_mut(value, nvr);
+        for (reference, value) in slice(vr, nvr).iter().zip(values.iter_mut()) {
+            match *reference {
+                VEHICLE => *value = instance.vehicle.as_ptr(),
+                r => {
+                    instance.log_error(&format!("no string variable with value reference {}", r));
+                    return ERROR;
+                }
+            }
+        }
+        OK
+    })
+}
+
+// the vehicle preset is fixed once the simulation is initialized
+#[no_mangle]
+pub unsafe extern "C" fn fmi2SetString(
+    component: *mut c_void,
+    vr: *const c_uint,
+    nvr: usize,
+    value: *const *const c_char,
+) -> c_int {
+    guarded(component, "fmi2SetString", ERROR, || {
+        let instance = match instance(component) {
+            Some(instance) => instance,
+            None => return ERROR,
+        };
+        for (reference, value) in slice(vr, nvr).iter().zip(slice(value, nvr)) {
+            match (*reference, instance.simulation.is_some()) {
+                (VEHICLE, false) => {
+                    instance.vehicle =
+                        CString::new(string(*value).unwrap_or_default()).unwrap_or_default();
+                }
+                (VEHICLE, true) => {
+                    instance.log_error("the vehicle can only be set before initialization");
+                    return ERROR;
+                }
+                (r, _) => {
+                    instance.log_error(&format!("no string variable with value reference {}", r));
+                    return ERROR;
+                }
+            }
+        }
+        OK
+    })
+}
+
+// the model has no integer or boolean variables
+#[no_mangle]
+pub unsafe extern "C" fn fmi2GetInteger(
+    component: *mut c_void,
+    _vr: *const c_uint,
+    nvr: usize,
+    _value: *mut c_int,
+) -> c_int {
+    guarded(component, "fmi2GetInteger", ERROR, || {
+        no_variables(component, nvr)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2SetInteger(
+    component: *mut c_void,
+    _vr: *const c_uint,
+    nvr: usize,
+    _value: *const c_int,
+) -> c_int {
+    guarded(component, "fmi2SetInteger", ERROR, || {
+        no_variables(component, nvr)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2GetBoolean(
+    component: *mut c_void,
+    _vr: *const c_uint,
+    nvr: usize,
+    _value: *mut c_int,
+) -> c_int {
+    guarded(component, "fmi2GetBoolean", ERROR, || {
+        no_variables(component, nvr)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2SetBoolean(
+    component: *mut c_void,
+    _vr: *const c_uint,
+    nvr: usize,
+    _value: *const c_int,
+) -> c_int {
+    guarded(component, "fmi2SetBoolean", ERROR, || {
+        no_variables(component, nvr)
+    })
+}
+
+unsafe fn no_variables(component: *mut c_void, nvr: usize) -> c_int {
+    match (instance(component), nvr) {
+        (Some(_), 0) => OK,
+        (Some(instance), _) => {
+            instance.log_error("the car FMU has no integer or boolean variables");
+            ERROR
+        }
+        (None, _) => ERROR,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2DoStep(
+    component: *mut c_void,
+    current_communication_point: f64,
+    communication_step_size: f64,
+    _no_set_fmu_state_prior_to_current_point: c_int,
+) -> c_int {
+    guarded(component, "fmi2DoStep", ERROR, || {
+        let instance = match instance(component) {
+            Some(instance) => instance,
+            None => return ERROR,
+        };
+        let inputs = instance.inputs;
+        let simulation = match instance.simulation.as_mut() {
+            Some(simulation) => simulation,
+            None => {
+                instance.log_error("fmi2DoStep before initialization");
+                return ERROR;
+            }
+        };
+        simulation.step_to(
+            current_communication_point + communication_step_size,
+            inputs,
+        );
+        // communication steps that aren't a multiple of the time step end off the point
+        let time = simulation.time();
+        match (time - current_communication_point - communication_step_size).abs() < 1e-9 {
+            true => OK,
+            false => WARNING,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2CancelStep(component: *mut c_void) -> c_int {
+    guarded(component, "fmi2CancelStep", ERROR, || {
+        unsupported(component, "fmi2CancelStep")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2GetStatus(
+    component: *mut c_void,
+    _kind: c_int,
+    _value: *mut c_int,
+) -> c_int {
+    guarded(component, "fmi2GetStatus", ERROR, || {
+        match instance(component) {
+            Some(_) => DISCARD,
+            None => ERROR,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2GetRealStatus(
+    component: *mut c_void,
+    kind: c_int,
+    value: *mut f64,
+) -> c_int {
+    guarded(component, "fmi2GetRealStatus", ERROR, || {
+        match (instance(component), kind) {
+            (Some(instance), LAST_SUCCESSFUL_TIME) if !value.is_null() => {
+                *value = instance.time();
+                OK
+            }
+            (Some(_), _) => DISCARD,
+            (None, _) => ERROR,
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2GetIntegerStatus(
+    component: *mut c_void,
+    kind: c_int,
+    value: *mut c_int,
+) -> c_int {
+    guarded(component, "fmi2GetIntegerStatus", ERROR, || {
+        fmi2GetStatus(component, kind, value)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2GetBooleanStatus(
+    component: *mut c_void,
+    kind: c_int,
+    value: *mut c_int,
+) -> c_int {
+    guarded(component, "fmi2GetBooleanStatus", ERROR, || {
+        fmi2GetStatus(component, kind, value)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2GetStringStatus(
+    component: *mut c_void,
+    _kind: c_int,
+    _value: *mut *const c_char,
+) -> c_int {
+    guarded(component, "fmi2GetStringStatus", ERROR, || {
+        match instance(component) {
+            Some(_) => DISCARD,
+            None => ERROR,
+        }
+    })
+}
+
+// not supported, as declared in modelDescription.xml
+#[no_mangle]
+pub unsafe extern "C" fn fmi2GetFMUstate(
+    component: *mut c_void,
+    _state: *mut *mut c_void,
+) -> c_int {
+    guarded(component, "fmi2GetFMUstate", ERROR, || {
+        unsupported(component, "fmi2GetFMUstate")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2SetFMUstate(component: *mut c_void, _state: *mut c_void) -> c_int {
+    guarded(component, "fmi2SetFMUstate", ERROR, || {
+        unsupported(component, "fmi2SetFMUstate")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2FreeFMUstate(
+    component: *mut c_void,
+    _state: *mut *mut c_void,
+) -> c_int {
+    guarded(component, "fmi2FreeFMUstate", ERROR, || {
+        unsupported(component, "fmi2FreeFMUstate")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2SerializedFMUstateSize(
+    component: *mut c_void,
+    _state: *mut c_void,
+    _size: *mut usize,
+) -> c_int {
+    guarded(component, "fmi2SerializedFMUstateSize", ERROR, || {
+        unsupported(component, "fmi2SerializedFMUstateSize")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2SerializeFMUstate(
+    component: *mut c_void,
+    _state: *mut c_void,
+    _serialized_state: *mut c_char,
+    _size: usize,
+) -> c_int {
+    guarded(component, "fmi2SerializeFMUstate", ERROR, || {
+        unsupported(component, "fmi2SerializeFMUstate")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2DeSerializeFMUstate(
+    component: *mut c_void,
+    _serialized_state: *const c_char,
+    _size: usize,
+    _state: *mut *mut c_void,
+) -> c_int {
+    guarded(component, "fmi2DeSerializeFMUstate", ERROR, || {
+        unsupported(component, "fmi2DeSerializeFMUstate")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2GetDirectionalDerivative(
+    component: *mut c_void,
+    _unknown_vr: *const c_uint,
+    _unknowns: usize,
+    _known_vr: *const c_uint,
+    _knowns: usize,
+    _known_delta: *const f64,
+    _unknown_delta: *mut f64,
+) -> c_int {
+    guarded(component, "fmi2GetDirectionalDerivative", ERROR, || {
+        unsupported(component, "fmi2GetDirectionalDerivative")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2SetRealInputDerivatives(
+    component: *mut c_void,
+    _vr: *const c_uint,
+    _nvr: usize,
+    _order: *const c_int,
+    _value: *const f64,
+) -> c_int {
+    guarded(component, "fmi2SetRealInputDerivatives", ERROR, || {
+        unsupported(component, "fmi2SetRealInputDerivatives")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn fmi2GetRealOutputDerivatives(
+    component: *mut c_void,
+    _vr: *const c_uint,
+    _nvr: usize,
+    _order: *const c_int,
+    _value: *mut f64,
+) -> c_int {
+    guarded(component, "fmi2GetRealOutputDerivatives", ERROR, || {
+        unsupported(component, "fmi2GetRealOutputDerivatives")
+    })
+}
+
+unsafe fn unsupported(component: *mut c_void, function: &str) -> c_int {
+    if let Some(instance) = instance(component) {
+        instance.log_error(&format!("{} isn't supported by the car FMU", function));
+    }
+    ERROR
+}
@@ -0,0 +1,189 @@
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use rigid_body::sva::Vector;
+
+use crate::{rng::SplitMix64, GridElement, Interference, Surface, SurfaceKind};
+
+// A half ellipsoid rock on the ground. Its sides get steep near the ground, so
+// a large one is a ledge the tire has to climb rather than roll over.
+pub struct Boulder {
+    pub center: [f64; 2],
+    pub radius: f64,
+    pub height: f64,
+}
+
+impl Boulder {
+    // height and gradient of the rock surface, None outside of it
+    fn evaluate(&self, x: f64, y: f64) -> Option<(f64, f64, f64)> {
+        let [dx, dy] = [x - self.center[0], y - self.center[1]];
+        let r2 = (dx * dx + dy * dy) / (self.radius * self.radius);
+        if r2 >= 1. {
+            return None;
+        }
+        // the slope is limited right at the edge, where it is vertical
+        let root = (1. - r2).max(1e-4).sqrt();
+        let slope = -self.height / (self.radius * self.radius * root);
+        Some((self.height * root, slope * dx, slope * dy))
+    }
+}
+
+// Flat dirt ground with boulders, for rock crawling. Where boulders overlap the
+// highest one is the surface.
+pub struct Boulders {
+    pub size: f64,
+    pub boulders: Vec<Boulder>,
+}
+
+impl Boulders {
+    // `count` boulders placed randomly in a `size` x `size` element. The same
+    // seed always gives the same boulders.
+    pub fn random(size: f64, count: usize, radius: [f64; 2], height: [f64; 2], seed: u64) -> Self {
+        let mut rng = SplitMix64(seed);
+        let boulders = (0..count)
+            .map(|_| {
+                let radius = rng.range(radius);
+                Boulder {
+                    // keep the boulders inside of the element
+                    center: [
+                        rng.range([radius, (size - radius).max(radius)]),
+                        rng.range([radius, (size - radius).max(radius)]),
+                    ],
+                    radius,
+                    height: rng.range(height).min(radius),
+                }
+            })
+            .collect();
+        Self { size, boulders }
+    }
+
+    // height and gradient of the surface at a point
+    fn evaluate(&self, x: f64, y: f64) -> (f64, f64, f64) {
+        self.boulders
+            .iter()
+            .filter_map(|boulder| boulder.evaluate(x, y))
+            .fold((0., 0., 0.), |highest, surface| {
+                if surface.0 > highest.0 {
+                    surface
+                } else {
+                    highest
+                }
+            })
+    }
+    // mesh at level of detail `lod`, 0 is full detail
+    fn lod_mesh(&self, lod: usize) -> Mesh {
+        let size = self.size as f32;
+        // halve the resolution for each level of detail
+        let x_vertex_count = (100_u32 >> lod.min(31)).max(4);
+        let y_vertex_count = (100_u32 >> lod.min(31)).max(4);
+
+        let num_vertices = (y_vertex_count * x_vertex_count) as usize;
+        let num_indices = ((y_vertex_count - 1) * (x_vertex_count - 1) * 6) as usize;
+
+        let mut positions: Vec<[f32; 3]> = Vec::with_capacity(num_vertices);
+        let mut normals: Vec<[f32; 3]> = Vec::with_capacity(num_vertices);
+        let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(num_vertices);
+        let mut colors: Vec<[f32; 4]> = Vec::with_capacity(num_vertices);
+        let mut indices: Vec<u32> = Vec::with_capacity(num_indices);
+
+        for y_vert in 0..y_vertex_count {
+            for x_vert in 0..x_vertex_count {
+                let x_normalized = x_vert as f32 / (x_vertex_count - 1) as f32;
+                let y_normalized = y_vert as f32 / (y_vertex_count - 1) as f32;
+
+                let x = x_normalized * size;
+                let y = y_normalized * size;
+                let (height, dx, dy) = self.evaluate(x as f64, y as f64);
+                // grey rock on brown dirt
+                let color = if height > 0. {
+                    [0.55, 0.55, 0.52, 1.]
+                } else {
+                    [0.75, 0.6, 0.45, 1.]
+                };
+
+                let normal = Vec3::new(-dx as f32, -dy as f32, 1.).normalize().to_array();
+                positions.push([x, y, height as f32]);
+                normals.push(normal);
+                uvs.push([x_normalized, 1. - y_normalized]);
+                colors.push(color);
+            }
+        }
+
+        for y in 0..y_vertex_count - 1 {
+            for x in 0..x_vertex_count - 1 {
+                let quad = y * x_vertex_count + x;
+                indices.push(quad);
+                indices.push(quad + 1);
+                indices.push(quad + x_vertex_count);
+                indices.push(quad + x_vertex_count + 1);
+                indices.push(quad + x_vertex_count);
+                indices.push(quad + 1);
+            }
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh
+    }
+}
+
+impl GridElement for Boulders {
+    fn interference(&self, point: Vector) -> Option<Interference> {
+        let size = self.size;
+
+        // point is outside of area
+        if point.x < 0.0 || point.x > size || point.y < 0.0 || point.y > size {
+            return None;
+        }
+
+        let (height, dx, dy) = self.evaluate(point.x, point.y);
+        if point.z > height {
+            return None;
+        }
+
+        Some(Interference {
+            magnitude: height - point.z,
+            position: Vector::new(point.x, point.y, height),
+            normal: Vector::new(-dx, -dy, 1.).normalize(),
+        })
+    }
+
+    fn mesh(&self) -> Mesh {
+        self.lod_mesh(0)
+    }
+
+    fn mesh_lod(&self, lod: usize) -> Option<Mesh> {
+        Some(self.lod_mesh(lod))
+    }
+
+    // the ground between the rocks is dirt
+    fn surface(&self, point: Vector) -> Surface {
+        let kind = if self.evaluate(point.x, point.y).0 > 0. {
+            SurfaceKind::Paved
+        } else {
+            SurfaceKind::Loose
+        };
+        Surface {
+            kind,
+            ..Default::default()
+        }
+    }
+
+    fn height(&self, x: f64, y: f64) -> f64 {
+        self.evaluate(x, y).0
+    }
+
+    fn height_bounds(&self) -> [f64; 2] {
+        let max = self
+            .boulders
+            .iter()
+            .map(|boulder| boulder.height)
+            .fold(0., f64::max);
+        [0., max]
+    }
+}
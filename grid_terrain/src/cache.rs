@@ -0,0 +1,55 @@
+use rigid_body::sva::Vector;
+
+use crate::{GridTerrain, Interference};
+
+// Per-body cache of the terrain around a body (e.g. a wheel). The cell of the body
+// and the highest point of the terrain near it are only looked up again when the
+// body moves to a new cell. The height is used to skip terrain queries while the
+// body is clearly above the terrain, and points in the cached cell are queried on
+// its element directly.
+#[derive(Default, Clone)]
+pub struct TerrainCache {
+    cell: Option<[isize; 2]>,
+    cell_bounds: [[f64; 2]; 2], // x and y extent of the cell
+    max_height: f64,
+}
+
+impl TerrainCache {
+    pub fn update(&mut self, terrain: &GridTerrain, center: Vector, radius: f64) {
+        if self.cell.is_some() && self.contains(center) {
+            return;
+        }
+        let cell = terrain.cell_index(center);
+        // the region is large enough to stay valid while the center is in this cell
+        let step = terrain.step();
+        let reach = radius + step[0].max(step[1]);
+        self.max_height = terrain.max_height(center, reach);
+        self.cell = Some(cell);
+        self.cell_bounds = [
+            [cell[0] as f64 * step[0], (cell[0] + 1) as f64 * step[0]],
+            [cell[1] as f64 * step[1], (cell[1] + 1) as f64 * step[1]],
+        ];
+    }
+
+    // can any point below `min_z` be in contact with the terrain?
+    pub fn may_contact(&self, min_z: f64) -> bool {
+        self.cell.is_none() || min_z <= self.max_height
+    }
+
+    // Interference of a point of the body, from the cached cell while the point is
+    // in it, otherwise with a lookup of its cell
+    pub fn interference(&self, terrain: &GridTerrain, point: Vector) -> Option<Interference> {
+        match self.cell {
+            Some(cell) if self.contains(point) => terrain.cell_point_interference(cell, point),
+            _ => terrain.interference(point),
+        }
+    }
+
+    fn contains(&self, point: Vector) -> bool {
+        let [x_bounds, y_bounds] = self.cell_bounds;
+        point.x >= x_bounds[0]
+            && point.x < x_bounds[1]
+            && point.y >= y_bounds[0]
+            && point.y < y_bounds[1]
+    }
+}
@@ -0,0 +1,88 @@
+use bevy::{prelude::*, render::mesh::VertexAttributeValues};
+
+// Vertex coloring of the terrain meshes based on height and slope, so the
+// relief is readable without textures. Flat ground is shaded from `low` to
+// `high` over the height range of the terrain, steep faces fade to `steep`.
+#[derive(Clone)]
+pub struct TerrainColoring {
+    pub low: Color,
+    pub high: Color,
+    pub steep: Color,
+    pub steep_slope: f32, // slope (rise over run) at which faces are fully `steep`
+}
+
+impl Default for TerrainColoring {
+    fn default() -> Self {
+        Self {
+            low: Color::rgb_u8(95, 110, 70),
+            high: Color::rgb_u8(205, 190, 150),
+            steep: Color::rgb_u8(120, 115, 110),
+            steep_slope: 1.0,
+        }
+    }
+}
+
+impl TerrainColoring {
+    pub fn apply(&self, mesh: &mut Mesh, height_range: [f32; 2]) {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            return;
+        };
+        let Some(VertexAttributeValues::Float32x3(normals)) =
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+        else {
+            return;
+        };
+
+        let low = Vec4::from(self.low.as_rgba_f32());
+        let high = Vec4::from(self.high.as_rgba_f32());
+        let steep = Vec4::from(self.steep.as_rgba_f32());
+        let height_span = (height_range[1] - height_range[0]).max(f32::EPSILON);
+
+        let mut colors: Vec<[f32; 4]> = positions
+            .iter()
+            .zip(normals.iter())
+            .map(|(position, normal)| {
+                let height = ((position[2] - height_range[0]) / height_span).clamp(0., 1.);
+                let flat = low.lerp(high, height);
+
+                let vertical = normal[2].abs().max(f32::EPSILON);
+                let slope = (1. - vertical * vertical).sqrt() / vertical;
+                let steepness = (slope / self.steep_slope).clamp(0., 1.);
+                flat.lerp(steep, steepness).to_array()
+            })
+            .collect();
+
+        // keep any existing vertex colors (e.g. track curbs) by multiplying
+        if let Some(VertexAttributeValues::Float32x4(existing)) =
+            mesh.attribute(Mesh::ATTRIBUTE_COLOR)
+        {
+            for (color, existing) in colors.iter_mut().zip(existing.iter()) {
+                for i in 0..4 {
+                    color[i] *= existing[i];
+                }
+            }
+        }
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    }
+}
+
+// lowest and highest vertex of the meshes
+pub fn height_range<'a>(meshes: impl Iterator<Item = &'a Mesh>) -> [f32; 2] {
+    let mut range = [f32::INFINITY, f32::NEG_INFINITY];
+    for mesh in meshes {
+        if let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        {
+            for position in positions {
+                range[0] = range[0].min(position[2]);
+                range[1] = range[1].max(position[2]);
+            }
+        }
+    }
+    if range[0] > range[1] {
+        return [0., 0.];
+    }
+    range
+}
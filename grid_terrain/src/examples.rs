@@ -1,8 +1,9 @@
-use std::f64::consts::PI as PI64;
+use std::{f64::consts::PI as PI64, sync::Arc};
 
 use crate::{
-    function::Function, mirror::Mirror, plane::Plane, rotate::Rotate, step::Step,
-    step_slope::StepSlope, GridElement,
+    boulders::Boulders, function::Function, hill_road::HillRoad, mirror::Mirror, patches::Patches,
+    plane::Plane, props::Prop, rotate::Rotate, step::Step, step_slope::StepSlope, track::Track,
+    water::Water, GridElement,
 };
 
 pub fn table_top(size: f64, height: f64) -> Vec<Vec<Box<dyn GridElement + 'static>>> {
@@ -75,26 +76,188 @@ pub fn steps(size: f64, heights: Vec<f64>) -> Vec<Vec<Box<dyn GridElement + 'sta
     grid_elements
 }
 
+pub fn stream(size: f64, depth: f64, length: usize) -> Vec<Vec<Box<dyn GridElement + 'static>>> {
+    let mut grid_elements: Vec<Vec<Box<dyn GridElement + 'static>>> = Vec::new();
+    for _ in 0..length {
+        grid_elements.push(vec![
+            Box::new(Plane {
+                size: [size, size],
+                subdivisions: 1,
+            }),
+            Box::new(Water {
+                size: [size, size],
+                depth,
+            }),
+            Box::new(Plane {
+                size: [size, size],
+                subdivisions: 1,
+            }),
+        ]);
+    }
+    grid_elements
+}
+
+pub fn circuit(size: f64) -> Vec<Vec<Box<dyn GridElement + 'static>>> {
+    circuit_track().grid_elements(size)
+}
+
+// a small closed circuit with a banked hairpin
+pub fn circuit_track() -> Track {
+    Track {
+        waypoints: vec![
+            [20., 20.],
+            [80., 15.],
+            [110., 40.],
+            [90., 70.],
+            [50., 55.],
+            [20., 75.],
+        ],
+        widths: vec![10., 10., 12., 12., 8., 10.],
+        banking: vec![0., 0., 0.15, 0.15, 0., 0.],
+        ..Default::default()
+    }
+}
+
+// about a kilometer of road climbing 80 m, at up to 9 % on the straights, with
+// three hairpins between four legs
+pub fn hill_climb_road() -> HillRoad {
+    HillRoad {
+        waypoints: vec![
+            [70., 70.],
+            [170., 70.],
+            [270., 70.],
+            [300., 90.],
+            [270., 110.],
+            [170., 110.],
+            [90., 110.],
+            [60., 130.],
+            [90., 150.],
+            [190., 150.],
+            [290., 150.],
+            [320., 170.],
+            [290., 190.],
+            [190., 190.],
+            [90., 190.],
+        ],
+        elevations: vec![
+            0., 6., 14., 17., 20., 28., 35., 38., 41., 50., 59., 62., 65., 74., 80.,
+        ],
+        ..Default::default()
+    }
+}
+
+// A rock crawling course along y up the middle column, between flat ground:
+// boulder fields that get larger, a 0.5 m ledge up and back down (the table
+// top), and the largest boulders at the end
+pub fn rock_crawl(size: f64, seed: u64) -> Vec<Vec<Box<dyn GridElement + 'static>>> {
+    let plane = || {
+        Box::new(Plane {
+            size: [size, size],
+            subdivisions: 1,
+        }) as Box<dyn GridElement>
+    };
+    // count, radius and height range of the boulders in each field
+    let field = |count: usize, radius: [f64; 2], height: [f64; 2], seed: u64| {
+        vec![
+            plane(),
+            Box::new(Boulders::random(size, count, radius, height, seed)) as Box<dyn GridElement>,
+            plane(),
+        ]
+    };
+    let mut grid_elements = vec![
+        field(25, [0.6, 1.2], [0.2, 0.4], seed),
+        field(30, [0.8, 1.5], [0.3, 0.6], seed.wrapping_add(1)),
+    ];
+    grid_elements.extend(table_top(size, 0.5));
+    grid_elements.push(field(25, [1., 1.8], [0.5, 0.9], seed.wrapping_add(2)));
+    grid_elements
+}
+
+// flat ground with randomly placed icy patches
+pub fn icy_patches(size: f64, seed: u64) -> Vec<Vec<Box<dyn GridElement + 'static>>> {
+    let row: Vec<Box<dyn GridElement + 'static>> = (0..3)
+        .map(|ind| {
+            let plane = Box::new(Plane {
+                size: [size, size],
+                subdivisions: 40,
+            });
+            Box::new(Patches::random(
+                plane,
+                size,
+                3,
+                [2., 5.],
+                [0.08, 0.25],
+                seed.wrapping_add(ind),
+            )) as Box<dyn GridElement>
+        })
+        .collect();
+    vec![row]
+}
+
+// flat ground enclosed by walls, with a wall in the middle to drift around and
+// tire stacks at its ends
+pub fn drift_arena(
+    size: f64,
+    count: usize,
+) -> (Vec<Vec<Box<dyn GridElement + 'static>>>, Vec<Prop>) {
+    let elements = (0..count)
+        .map(|_| {
+            (0..count)
+                .map(|_| {
+                    Box::new(Plane {
+                        size: [size, size],
+                        subdivisions: 10,
+                    }) as Box<dyn GridElement>
+                })
+                .collect()
+        })
+        .collect();
+
+    let [min, max] = [5., count as f64 * size - 5.];
+    let center = count as f64 * size / 2.;
+    let island = [center - size, center + size];
+    let props = vec![
+        Prop::wall([min, min], [max, min], 1.),
+        Prop::wall([max, min], [max, max], 1.),
+        Prop::wall([max, max], [min, max], 1.),
+        Prop::wall([min, max], [min, min], 1.),
+        Prop::wall([island[0], center], [island[1], center], 1.),
+        Prop::tire(island[0] - 0.6, center),
+        Prop::tire(island[1] + 0.6, center),
+    ];
+    (elements, props)
+}
+
+// a line of cones along x, with a tire stack at each end
+pub fn slalom(start: [f64; 2], spacing: f64, count: usize) -> Vec<Prop> {
+    let mut props: Vec<Prop> = (0..count)
+        .map(|ind| Prop::cone(start[0] + ind as f64 * spacing, start[1]))
+        .collect();
+    props.push(Prop::tire(start[0] - spacing, start[1]));
+    props.push(Prop::tire(start[0] + count as f64 * spacing, start[1]));
+    props
+}
+
 const TAU64: f64 = 2. * PI64;
 pub fn wave(size: f64, height: f64, wave_length: f64) -> Vec<Vec<Box<dyn GridElement + 'static>>> {
-    let x_start = Box::new(move |x: f64, _y: f64| x / size);
-    let x_end = Box::new(move |x: f64, _y: f64| 1.0 - x / size);
-    let y_start = Box::new(move |_x: f64, y: f64| y / size);
-    let y_end = Box::new(move |_x: f64, y: f64| 1.0 - y / size);
-
-    let dx_start = Box::new(move |_x: f64, _y: f64| (1.0 / size, 0.));
-    let dx_end = Box::new(move |_x: f64, _y: f64| (-1.0 / size, 0.));
-    let dy_start = Box::new(move |_x: f64, _y: f64| (0., 1.0 / size));
-    let dy_end = Box::new(move |_x: f64, _y: f64| (0., -1.0 / size));
-
-    let z_fun = Box::new(move |x: f64, _y: f64| height * (TAU64 / wave_length * x).cos());
-    let z_der = Box::new(move |x: f64, _y: f64| {
+    let x_start = Arc::new(move |x: f64, _y: f64| x / size);
+    let x_end = Arc::new(move |x: f64, _y: f64| 1.0 - x / size);
+    let y_start = Arc::new(move |_x: f64, y: f64| y / size);
+    let y_end = Arc::new(move |_x: f64, y: f64| 1.0 - y / size);
+
+    let dx_start = Arc::new(move |_x: f64, _y: f64| (1.0 / size, 0.));
+    let dx_end = Arc::new(move |_x: f64, _y: f64| (-1.0 / size, 0.));
+    let dy_start = Arc::new(move |_x: f64, _y: f64| (0., 1.0 / size));
+    let dy_end = Arc::new(move |_x: f64, _y: f64| (0., -1.0 / size));
+
+    let z_fun = Arc::new(move |x: f64, _y: f64| height * (TAU64 / wave_length * x).cos());
+    let z_der = Arc::new(move |x: f64, _y: f64| {
         (
             -height * TAU64 / wave_length * (TAU64 / wave_length * x).sin(),
             0.,
         )
     });
-
+    // the ramps are between 0 and 1, so the surface stays within the wave height
     let size = [size, size];
 
     let grid_elements: Vec<Vec<Box<dyn GridElement + 'static>>> = vec![
@@ -104,16 +267,19 @@ pub fn wave(size: f64, height: f64, wave_length: f64) -> Vec<Vec<Box<dyn GridEle
                 size,
                 functions: vec![z_fun.clone(), x_start.clone(), y_start.clone()],
                 derivatives: vec![z_der.clone(), dx_start.clone(), dy_start.clone()],
+                amplitude: height,
             }),
             Box::new(Function {
                 size,
                 functions: vec![z_fun.clone(), y_start.clone()],
                 derivatives: vec![z_der.clone(), dy_start.clone()],
+                amplitude: height,
             }),
             Box::new(Function {
                 size,
                 functions: vec![z_fun.clone(), x_end.clone(), y_start.clone()],
                 derivatives: vec![z_der.clone(), dx_end.clone(), dy_start.clone()],
+                amplitude: height,
             }),
         ],
         // y_middle
@@ -122,16 +288,19 @@ pub fn wave(size: f64, height: f64, wave_length: f64) -> Vec<Vec<Box<dyn GridEle
                 size,
                 functions: vec![z_fun.clone(), x_start.clone()],
                 derivatives: vec![z_der.clone(), dx_start.clone()],
+                amplitude: height,
             }),
             Box::new(Function {
                 size,
                 functions: vec![z_fun.clone()],
                 derivatives: vec![z_der.clone()],
+                amplitude: height,
             }),
             Box::new(Function {
                 size,
                 functions: vec![z_fun.clone(), x_end.clone()],
                 derivatives: vec![z_der.clone(), dx_end.clone()],
+                amplitude: height,
             }),
         ],
         // y_end
@@ -140,16 +309,19 @@ pub fn wave(size: f64, height: f64, wave_length: f64) -> Vec<Vec<Box<dyn GridEle
                 size,
                 functions: vec![z_fun.clone(), x_start.clone(), y_end.clone()],
                 derivatives: vec![z_der.clone(), dx_start.clone(), dy_end.clone()],
+                amplitude: height,
             }),
             Box::new(Function {
                 size,
                 functions: vec![z_fun.clone(), y_end.clone()],
                 derivatives: vec![z_der.clone(), dy_end.clone()],
+                amplitude: height,
             }),
             Box::new(Function {
                 size,
                 functions: vec![z_fun.clone(), x_end.clone(), y_end.clone()],
                 derivatives: vec![z_der.clone(), dx_end.clone(), dy_end.clone()],
+                amplitude: height,
             }),
         ],
     ];
@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use bevy::{
     prelude::*,
     render::{mesh::Indices, render_resource::PrimitiveTopology},
@@ -6,94 +8,56 @@ use rigid_body::sva::Vector;
 
 use crate::{GridElement, Interference};
 
+pub type HeightFunction = Arc<dyn Fn(f64, f64) -> f64 + Send + Sync>;
+pub type HeightDerivative = Arc<dyn Fn(f64, f64) -> (f64, f64) + Send + Sync>;
+
 pub struct Function {
     pub size: [f64; 2],
-    pub functions: Vec<Box<dyn Fn(f64, f64) -> f64>>,
-    pub derivatives: Vec<Box<dyn Fn(f64, f64) -> (f64, f64)>>,
+    pub functions: Vec<HeightFunction>,
+    pub derivatives: Vec<HeightDerivative>,
+    // bound on the magnitude of the height, so tires clear of it skip the queries.
+    // Infinite when unknown.
+    pub amplitude: f64,
 }
 
 impl Default for Function {
     fn default() -> Self {
         Self {
             size: [10.0, 10.],
-            functions: vec![Box::new(|x, _y| x.cos())],
-            derivatives: vec![Box::new(|x, _y| (-x.sin(), 0.))],
+            functions: vec![Arc::new(|x, _y| x.cos())],
+            derivatives: vec![Arc::new(|x, _y| (-x.sin(), 0.))],
+            amplitude: f64::INFINITY,
         }
     }
 }
 
+// height of the product of the functions and its derivatives, by the product rule
 fn evaluate(
-    functions: &Vec<Box<dyn Fn(f64, f64) -> f64>>,
-    derivatives: &Vec<Box<dyn Fn(f64, f64) -> (f64, f64)>>,
+    functions: &[HeightFunction],
+    derivatives: &[HeightDerivative],
     point: Vector,
 ) -> (f64, f64, f64) {
     let mut height = 1.0;
-    let mut derivative_parts_x: Vec<f64> = functions.iter().map(|_x| 1.0_f64).collect();
-    let mut derivative_parts_y: Vec<f64> = functions.iter().map(|_y| 1.0_f64).collect();
-
-    for (i_fun, (fun, der)) in functions.iter().zip(derivatives.iter()).enumerate() {
+    let mut derivative_x = 0.0;
+    let mut derivative_y = 0.0;
+    for (fun, der) in functions.iter().zip(derivatives.iter()) {
         let fun_val = (fun)(point.x, point.y);
         let der_val = (der)(point.x, point.y);
 
+        derivative_x = derivative_x * fun_val + height * der_val.0;
+        derivative_y = derivative_y * fun_val + height * der_val.1;
         height *= fun_val;
-        for i in 0..functions.len() {
-            if i == i_fun {
-                derivative_parts_x[i] *= der_val.0;
-                derivative_parts_y[i] *= der_val.1;
-            } else {
-                derivative_parts_x[i] *= fun_val;
-                derivative_parts_y[i] *= fun_val;
-            }
-        }
     }
-
-    let derivative_x: f64 = derivative_parts_x.iter().sum();
-    let derivative_y: f64 = derivative_parts_y.iter().sum();
     (height, derivative_x, derivative_y)
 }
 
-impl GridElement for Function {
-    fn interference(&self, point: Vector) -> Option<Interference> {
-        let size = self.size;
-
-        // point is outside of area
-        if point.x < 0.0 || point.x > size[0] || point.y < 0.0 || point.y > size[1] {
-            // should be unreachable. Not great that it fails silently...
-            return None;
-        }
-
-        let (height, dx, dy) = evaluate(&self.functions, &self.derivatives, point);
-
-        if point.z > height {
-            // return immediately if point is above surface
-            return None;
-        }
-
-        let interference_magnitude = height - point.z;
-        let contact_point = Vector::new(point.x, point.y, height);
-        let normal = Vector::new(-dx, -dy, 1.).normalize();
-
-        // // iterate to improve contact_point and normal (no significant improvement)
-        // for _ in 0..0 {
-        //     let (height, dx, dy) = evaluate(&self.functions, &self.derivatives, contact_point);
-        //     normal = Vector::new(-dx, -dy, 1.).normalize();
-        //     let function_point = Vector::new(contact_point.x, contact_point.y, height);
-        //     let separation = function_point - point;
-        //     interference_magnitude = separation.dot(&normal);
-        //     contact_point = point + normal * interference_magnitude;
-        // }
-
-        Some(Interference {
-            magnitude: interference_magnitude,
-            position: contact_point,
-            normal,
-        })
-    }
-
-    fn mesh(&self) -> Mesh {
+impl Function {
+    // mesh at level of detail `lod`, 0 is full detail
+    fn lod_mesh(&self, lod: usize) -> Mesh {
         let size = [self.size[0] as f32, self.size[1] as f32];
-        let x_vertex_count = 100;
-        let y_vertex_count = 100;
+        // halve the resolution for each level of detail
+        let x_vertex_count = (100_u32 >> lod.min(31)).max(4);
+        let y_vertex_count = (100_u32 >> lod.min(31)).max(4);
 
         let num_vertices = (y_vertex_count * x_vertex_count) as usize;
         let num_indices = ((y_vertex_count - 1) * (x_vertex_count - 1) * 6) as usize;
@@ -143,3 +107,58 @@ impl GridElement for Function {
         mesh
     }
 }
+
+impl GridElement for Function {
+    fn interference(&self, point: Vector) -> Option<Interference> {
+        let size = self.size;
+
+        // point is outside of area
+        if point.x < 0.0 || point.x > size[0] || point.y < 0.0 || point.y > size[1] {
+            // should be unreachable. Not great that it fails silently...
+            return None;
+        }
+
+        let (height, dx, dy) = evaluate(&self.functions, &self.derivatives, point);
+
+        if point.z > height {
+            // return immediately if point is above surface
+            return None;
+        }
+
+        let interference_magnitude = height - point.z;
+        let contact_point = Vector::new(point.x, point.y, height);
+        let normal = Vector::new(-dx, -dy, 1.).normalize();
+
+        // // iterate to improve contact_point and normal (no significant improvement)
+        // for _ in 0..0 {
+        //     let (height, dx, dy) = evaluate(&self.functions, &self.derivatives, contact_point);
+        //     normal = Vector::new(-dx, -dy, 1.).normalize();
+        //     let function_point = Vector::new(contact_point.x, contact_point.y, height);
+        //     let separation = function_point - point;
+        //     interference_magnitude = separation.dot(&normal);
+        //     contact_point = point + normal * interference_magnitude;
+        // }
+
+        Some(Interference {
+            magnitude: interference_magnitude,
+            position: contact_point,
+            normal,
+        })
+    }
+
+    fn mesh(&self) -> Mesh {
+        self.lod_mesh(0)
+    }
+
+    fn mesh_lod(&self, lod: usize) -> Option<Mesh> {
+        Some(self.lod_mesh(lod))
+    }
+
+    fn height(&self, x: f64, y: f64) -> f64 {
+        evaluate(&self.functions, &self.derivatives, Vector::new(x, y, 0.)).0
+    }
+
+    fn height_bounds(&self) -> [f64; 2] {
+        [-self.amplitude, self.amplitude]
+    }
+}
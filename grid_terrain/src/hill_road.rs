@@ -0,0 +1,392 @@
+use std::sync::Arc;
+
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use rigid_body::sva::Vector;
+
+use crate::{
+    plane::Plane,
+    track::{catmull_rom, length},
+    GridElement, Interference, Surface, SurfaceKind,
+};
+
+// An open road up a hill along a Catmull-Rom spline through the waypoints, e.g.
+// a hill climb with switchbacks. Elevations are given per waypoint and
+// interpolated linearly along the spline. The road is level across, and the
+// hillside around it is a smooth blend of the road elevations nearby, so the
+// legs of a switchback are joined by a slope that falls away to the flat ground
+// `hillside` meters beyond the road. Waypoints must be further than that from
+// the axes, the grid starts at the origin.
+pub struct HillRoad {
+    pub waypoints: Vec<[f64; 2]>,
+    pub elevations: Vec<f64>,
+    pub width: f64,
+    pub shoulder: f64, // width of the blend from the road to the hillside
+    pub hillside: f64, // length scale of the hillside blend
+    pub samples_per_segment: usize,
+}
+
+impl Default for HillRoad {
+    fn default() -> Self {
+        Self {
+            waypoints: vec![],
+            elevations: vec![],
+            width: 7.,
+            shoulder: 3.,
+            hillside: 12.,
+            samples_per_segment: 20,
+        }
+    }
+}
+
+impl HillRoad {
+    pub fn grid_elements(&self, size: f64) -> Vec<Vec<Box<dyn GridElement + 'static>>> {
+        let geometry = Arc::new(RoadGeometry::new(self));
+
+        let [x_max, y_max] = [0, 1].map(|i| {
+            geometry
+                .samples
+                .iter()
+                .map(|s| s.position[i])
+                .fold(0., f64::max)
+        });
+        let x_count = ((x_max + geometry.reach) / size).ceil() as usize;
+        let y_count = ((y_max + geometry.reach) / size).ceil() as usize;
+
+        let mut grid_elements: Vec<Vec<Box<dyn GridElement + 'static>>> = Vec::new();
+        for y_index in 0..y_count {
+            let mut row: Vec<Box<dyn GridElement + 'static>> = Vec::new();
+            for x_index in 0..x_count {
+                let offset = [x_index as f64 * size, y_index as f64 * size];
+                let segments = geometry.segments_near(offset, size);
+                if segments.is_empty() {
+                    row.push(Box::new(Plane {
+                        size: [size, size],
+                        subdivisions: 1,
+                    }));
+                } else {
+                    row.push(Box::new(HillRoadElement {
+                        size,
+                        offset,
+                        segments,
+                        geometry: geometry.clone(),
+                    }));
+                }
+            }
+            grid_elements.push(row);
+        }
+        grid_elements
+    }
+
+    // points along the centerline from the first to the last waypoint, with
+    // the road elevation at each
+    pub fn centerline(&self) -> Vec<([f64; 2], f64)> {
+        RoadGeometry::new(self)
+            .samples
+            .iter()
+            .map(|s| (s.position, s.elevation))
+            .collect()
+    }
+}
+
+struct RoadSample {
+    position: [f64; 2],
+    elevation: f64,
+    distance: f64, // distance along the centerline
+}
+
+struct RoadGeometry {
+    samples: Vec<RoadSample>,
+    width: f64,
+    shoulder: f64,
+    hillside: f64,
+    reach: f64, // furthest distance from the centerline that is not flat ground
+    max_height: f64,
+}
+
+// position relative to the centerline
+struct RoadPoint {
+    separation: f64, // from the centerline, or its ends
+    elevation: f64,
+    distance: f64,
+}
+
+impl RoadGeometry {
+    fn new(road: &HillRoad) -> Self {
+        let n = road.waypoints.len();
+        assert!(n >= 2, "a road needs at least two waypoints");
+        assert_eq!(n, road.elevations.len());
+
+        let mut samples: Vec<RoadSample> = Vec::with_capacity((n - 1) * road.samples_per_segment);
+        let mut distance = 0.;
+        for i in 0..n - 1 {
+            // the end points are repeated to close the spline at the ends
+            let p0 = road.waypoints[i.saturating_sub(1)];
+            let p1 = road.waypoints[i];
+            let p2 = road.waypoints[i + 1];
+            let p3 = road.waypoints[(i + 2).min(n - 1)];
+            // the last segment includes the end of the road
+            let count = road.samples_per_segment + if i == n - 2 { 1 } else { 0 };
+            for j in 0..count {
+                let t = j as f64 / road.samples_per_segment as f64;
+                let position = catmull_rom(p0, p1, p2, p3, t);
+                if let Some(last) = samples.last() {
+                    distance += length([
+                        position[0] - last.position[0],
+                        position[1] - last.position[1],
+                    ]);
+                }
+                samples.push(RoadSample {
+                    position,
+                    elevation: road.elevations[i]
+                        + t * (road.elevations[i + 1] - road.elevations[i]),
+                    distance,
+                });
+            }
+        }
+
+        let max_height = samples.iter().map(|s| s.elevation).fold(0., f64::max);
+        Self {
+            samples,
+            width: road.width,
+            shoulder: road.shoulder,
+            hillside: road.hillside,
+            reach: road.width / 2. + road.shoulder + 3. * road.hillside,
+            max_height,
+        }
+    }
+
+    // indices of the centerline segments that can affect the cell
+    fn segments_near(&self, offset: [f64; 2], size: f64) -> Vec<usize> {
+        let min = [offset[0] - self.reach, offset[1] - self.reach];
+        let max = [offset[0] + size + self.reach, offset[1] + size + self.reach];
+        (0..self.samples.len() - 1)
+            .filter(|&i| {
+                let a = self.samples[i].position;
+                let b = self.samples[i + 1].position;
+                a[0].max(b[0]) >= min[0]
+                    && a[0].min(b[0]) <= max[0]
+                    && a[1].max(b[1]) >= min[1]
+                    && a[1].min(b[1]) <= max[1]
+            })
+            .collect()
+    }
+
+    fn closest(&self, segments: &[usize], point: [f64; 2]) -> Option<RoadPoint> {
+        let mut best: Option<(f64, RoadPoint)> = None;
+        for &i in segments {
+            let a = &self.samples[i];
+            let b = &self.samples[i + 1];
+            let ab = [b.position[0] - a.position[0], b.position[1] - a.position[1]];
+            let ap = [point[0] - a.position[0], point[1] - a.position[1]];
+            let ab_length = length(ab);
+            if ab_length == 0. {
+                continue;
+            }
+            let tangent = [ab[0] / ab_length, ab[1] / ab_length];
+            let t = ((ap[0] * tangent[0] + ap[1] * tangent[1]) / ab_length).clamp(0., 1.);
+            let closest = [
+                a.position[0] + t * ab[0] - point[0],
+                a.position[1] + t * ab[1] - point[1],
+            ];
+            let separation = length(closest);
+            if let Some((best_separation, _)) = &best {
+                if separation >= *best_separation {
+                    continue;
+                }
+            }
+            best = Some((
+                separation,
+                RoadPoint {
+                    separation,
+                    elevation: a.elevation + t * (b.elevation - a.elevation),
+                    distance: a.distance + t * (b.distance - a.distance),
+                },
+            ));
+        }
+        best.map(|(_, road_point)| road_point)
+    }
+
+    // Gaussian weighted average of the nearby road elevations, falling to the
+    // flat ground where there is no road within a few `hillside` lengths
+    fn hillside_height(&self, segments: &[usize], point: [f64; 2]) -> f64 {
+        let ground_weight = (-4_f64).exp();
+        let (mut weights, mut heights) = (ground_weight, 0.);
+        for &i in segments {
+            let sample = &self.samples[i];
+            let separation = length([point[0] - sample.position[0], point[1] - sample.position[1]]);
+            let weight = (-(separation / self.hillside).powi(2)).exp();
+            weights += weight;
+            heights += weight * sample.elevation;
+        }
+        heights / weights
+    }
+
+    fn height(&self, segments: &[usize], point: [f64; 2]) -> f64 {
+        match self.closest(segments, point) {
+            Some(road_point) => {
+                // level across the road, then smoothly onto the hillside
+                let beyond = road_point.separation - self.width / 2.;
+                let t = (beyond / self.shoulder).clamp(0., 1.);
+                let blend = t * t * (3. - 2. * t);
+                if blend == 0. {
+                    road_point.elevation
+                } else {
+                    let hillside = self.hillside_height(segments, point);
+                    road_point.elevation + blend * (hillside - road_point.elevation)
+                }
+            }
+            None => 0.,
+        }
+    }
+
+    fn surface_color(&self, road_point: &RoadPoint) -> [f32; 4] {
+        let separation = road_point.separation;
+        if separation <= 0.1 && (road_point.distance / 3.) as i64 % 2 == 0 {
+            // dashed center line
+            [1., 1., 1., 1.]
+        } else if separation <= self.width / 2. {
+            [0.35, 0.35, 0.35, 1.]
+        } else {
+            [1., 1., 1., 1.]
+        }
+    }
+}
+
+pub struct HillRoadElement {
+    size: f64,
+    offset: [f64; 2],
+    segments: Vec<usize>,
+    geometry: Arc<RoadGeometry>,
+}
+
+impl HillRoadElement {
+    // height and gradient of the surface at a point in local coordinates. The
+    // hillside has no closed form gradient, it is taken by central differences.
+    fn evaluate(&self, x: f64, y: f64) -> (f64, f64, f64) {
+        let height = |dx: f64, dy: f64| {
+            let point = [x + dx + self.offset[0], y + dy + self.offset[1]];
+            self.geometry.height(&self.segments, point)
+        };
+        let step = 0.05;
+        (
+            height(0., 0.),
+            (height(step, 0.) - height(-step, 0.)) / (2. * step),
+            (height(0., step) - height(0., -step)) / (2. * step),
+        )
+    }
+    // mesh at level of detail `lod`, 0 is full detail
+    fn lod_mesh(&self, lod: usize) -> Mesh {
+        let size = self.size as f32;
+        // halve the resolution for each level of detail
+        let x_vertex_count = (60_u32 >> lod.min(31)).max(4);
+        let y_vertex_count = (60_u32 >> lod.min(31)).max(4);
+
+        let num_vertices = (y_vertex_count * x_vertex_count) as usize;
+        let num_indices = ((y_vertex_count - 1) * (x_vertex_count - 1) * 6) as usize;
+
+        let mut positions: Vec<[f32; 3]> = Vec::with_capacity(num_vertices);
+        let mut normals: Vec<[f32; 3]> = Vec::with_capacity(num_vertices);
+        let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(num_vertices);
+        let mut colors: Vec<[f32; 4]> = Vec::with_capacity(num_vertices);
+        let mut indices: Vec<u32> = Vec::with_capacity(num_indices);
+
+        for y_vert in 0..y_vertex_count {
+            for x_vert in 0..x_vertex_count {
+                let x_normalized = x_vert as f32 / (x_vertex_count - 1) as f32;
+                let y_normalized = y_vert as f32 / (y_vertex_count - 1) as f32;
+
+                let x = x_normalized * size;
+                let y = y_normalized * size;
+                let (height, dx, dy) = self.evaluate(x as f64, y as f64);
+                let point = [x as f64 + self.offset[0], y as f64 + self.offset[1]];
+                let color = match self.geometry.closest(&self.segments, point) {
+                    Some(road_point) => self.geometry.surface_color(&road_point),
+                    None => [1., 1., 1., 1.],
+                };
+
+                let normal = Vec3::new(-dx as f32, -dy as f32, 1.).normalize().to_array();
+                positions.push([x, y, height as f32]);
+                normals.push(normal);
+                uvs.push([x_normalized, 1. - y_normalized]);
+                colors.push(color);
+            }
+        }
+
+        for y in 0..y_vertex_count - 1 {
+            for x in 0..x_vertex_count - 1 {
+                let quad = y * x_vertex_count + x;
+                indices.push(quad);
+                indices.push(quad + 1);
+                indices.push(quad + x_vertex_count);
+                indices.push(quad + x_vertex_count + 1);
+                indices.push(quad + x_vertex_count);
+                indices.push(quad + 1);
+            }
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh
+    }
+}
+
+impl GridElement for HillRoadElement {
+    fn interference(&self, point: Vector) -> Option<Interference> {
+        let size = self.size;
+
+        // point is outside of area
+        if point.x < 0.0 || point.x > size || point.y < 0.0 || point.y > size {
+            return None;
+        }
+
+        let (height, dx, dy) = self.evaluate(point.x, point.y);
+        if point.z > height {
+            return None;
+        }
+
+        Some(Interference {
+            magnitude: height - point.z,
+            position: Vector::new(point.x, point.y, height),
+            normal: Vector::new(-dx, -dy, 1.).normalize(),
+        })
+    }
+
+    fn mesh(&self) -> Mesh {
+        self.lod_mesh(0)
+    }
+
+    fn mesh_lod(&self, lod: usize) -> Option<Mesh> {
+        Some(self.lod_mesh(lod))
+    }
+
+    // everything off the road is loose
+    fn surface(&self, point: Vector) -> Surface {
+        let road_point = [point.x + self.offset[0], point.y + self.offset[1]];
+        let kind = match self.geometry.closest(&self.segments, road_point) {
+            Some(road_point) if road_point.separation <= self.geometry.width / 2. => {
+                SurfaceKind::Paved
+            }
+            _ => SurfaceKind::Loose,
+        };
+        Surface {
+            kind,
+            ..Default::default()
+        }
+    }
+
+    fn height(&self, x: f64, y: f64) -> f64 {
+        self.geometry
+            .height(&self.segments, [x + self.offset[0], y + self.offset[1]])
+    }
+
+    fn height_bounds(&self) -> [f64; 2] {
+        [0., self.geometry.max_height]
+    }
+}
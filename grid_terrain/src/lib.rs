@@ -1,14 +1,30 @@
+pub mod boulders;
+pub mod cache;
+pub mod coloring;
 pub mod examples;
 pub mod function;
+pub mod hill_road;
+pub mod lod;
+pub mod minimap;
 pub mod mirror;
+pub mod patches;
 pub mod plane;
+pub mod props;
+pub mod rng;
 pub mod rotate;
 pub mod slope;
 pub mod step;
 pub mod step_slope;
+pub mod track;
+pub mod water;
+
+use std::collections::HashMap;
 
 use bevy::prelude::*;
+use coloring::{height_range, TerrainColoring};
+use lod::TerrainLod;
 use mirror::Mirror;
+use props::{Prop, PropMesh};
 use rigid_body::sva::Vector;
 use rotate::{Rotate, RotationDirection};
 
@@ -18,6 +34,33 @@ pub struct Interference {
     pub normal: Vector,
 }
 
+// What the surface is made of, e.g. for the effects of a sliding tire
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceKind {
+    Paved,
+    Loose, // dirt, gravel or grass
+    Ice,
+}
+
+// Properties of the surface at a contact point
+#[derive(Clone, Copy, Debug)]
+pub struct Surface {
+    pub friction: f64,           // tire coefficient of friction
+    pub rolling_resistance: f64, // rolling resistance coefficient
+    pub kind: SurfaceKind,
+}
+
+impl Default for Surface {
+    // dry asphalt
+    fn default() -> Self {
+        Self {
+            friction: 0.8,
+            rolling_resistance: 0.015,
+            kind: SurfaceKind::Paved,
+        }
+    }
+}
+
 impl Interference {
     fn mirror(&mut self, size: f64, mirror: &Mirror) {
         match mirror {
@@ -70,54 +113,473 @@ impl Interference {
     }
 }
 
-pub trait GridElement {
+// how far up and down the default `GridElement::height` probes for the surface
+pub const PROBE_RANGE: f64 = 1000.;
+
+pub trait GridElement: Send + Sync {
     fn interference(&self, point: Vector) -> Option<Interference>;
     fn mesh(&self) -> Mesh;
+    // Coarser mesh for level of detail `lod` (1 and up, `mesh` is level 0). None
+    // for elements without coarser meshes, their cells keep the one mesh.
+    fn mesh_lod(&self, _lod: usize) -> Option<Mesh> {
+        None
+    }
+    // Height of the (top) surface at x, y. By default a vertical probe: bisection
+    // between the height bounds (within `PROBE_RANGE`) for where the point leaves
+    // the element, flat ground if it is never in it.
+    fn height(&self, x: f64, y: f64) -> f64 {
+        let [mut low, mut high] = self
+            .height_bounds()
+            .map(|bound| bound.clamp(-PROBE_RANGE, PROBE_RANGE));
+        if self.interference(Vector::new(x, y, low)).is_none() {
+            return 0.;
+        }
+        for _ in 0..50 {
+            let middle = 0.5 * (low + high);
+            match self.interference(Vector::new(x, y, middle)) {
+                Some(_) => low = middle,
+                None => high = middle,
+            }
+        }
+        low
+    }
+
+    // depth of the point below the water surface (negative above the surface).
+    // None if the element has no water at this location.
+    fn water_depth(&self, _point: Vector) -> Option<f64> {
+        None
+    }
+    fn water_mesh(&self) -> Option<Mesh> {
+        None
+    }
+
+    // surface properties (friction, rolling resistance) at the point
+    fn surface(&self, _point: Vector) -> Surface {
+        Surface::default()
+    }
+
+    // lowest and highest point of the element surface. Used to skip terrain queries
+    // for bodies that are clearly above the terrain.
+    fn height_bounds(&self) -> [f64; 2] {
+        [f64::NEG_INFINITY, f64::INFINITY]
+    }
 }
 
 #[derive(Resource)]
 pub struct GridTerrain {
     elements: Vec<Vec<Box<dyn GridElement + 'static>>>,
     step: [f64; 2],
+    blend_margin: f64,
+    height_bounds: Vec<Vec<[f64; 2]>>,
+    coloring: Option<TerrainColoring>,
+    props: Vec<Prop>,
+    prop_cells: HashMap<[isize; 2], Vec<usize>>, // props touching each cell
+    lod_distances: Vec<f64>,
 }
 
-unsafe impl Sync for GridTerrain {}
-unsafe impl Send for GridTerrain {}
-
 impl GridTerrain {
     pub fn new(elements: Vec<Vec<Box<dyn GridElement>>>, step: [f64; 2]) -> Self {
-        Self { elements, step }
+        let height_bounds = elements
+            .iter()
+            .map(|y_elements| {
+                y_elements
+                    .iter()
+                    .map(|element| element.height_bounds())
+                    .collect()
+            })
+            .collect();
+        Self {
+            elements,
+            step,
+            blend_margin: 0.,
+            height_bounds,
+            coloring: None,
+            props: Vec::new(),
+            prop_cells: HashMap::new(),
+            lod_distances: Vec::new(),
+        }
+    }
+
+    // Blend the surface with the neighbouring element within `margin` of a cell
+    // boundary: the contact there is with a ramp between the heights of the two
+    // elements. This removes the jump in height and normal at seams between
+    // elements that would otherwise kick the tire. Zero disables blending.
+    pub fn with_blend_margin(mut self, margin: f64) -> Self {
+        self.blend_margin = margin;
+        self
+    }
+
+    // color the element meshes by height and slope
+    pub fn with_coloring(mut self, coloring: TerrainColoring) -> Self {
+        self.coloring = Some(coloring);
+        self
+    }
+
+    // Swap element meshes for coarser ones when the camera is further than each
+    // distance from the cell (requires `terrain_lod_system`). Empty disables LOD.
+    pub fn with_lod(mut self, distances: Vec<f64>) -> Self {
+        self.lod_distances = distances;
+        self
+    }
+
+    // static props (cones, walls, etc.) that collide with the tires
+    pub fn with_props(mut self, props: Vec<Prop>) -> Self {
+        for prop in props {
+            self.add_prop(prop);
+        }
+        self
+    }
+
+    // adds a prop to the contact, returns its index (see `build_prop_mesh`)
+    pub fn add_prop(&mut self, prop: Prop) -> usize {
+        let index = self.props.len();
+        for cell in self.prop_footprint_cells(&prop) {
+            self.prop_cells.entry(cell).or_default().push(index);
+        }
+        self.props.push(prop);
+        index
+    }
+
+    // Takes a prop out of the contact, e.g. a cone that was knocked over. The
+    // prop keeps its index.
+    pub fn remove_prop(&mut self, index: usize) {
+        let cells = match self.props.get(index) {
+            Some(prop) => self.prop_footprint_cells(prop),
+            None => return,
+        };
+        for cell in cells {
+            if let Some(indices) = self.prop_cells.get_mut(&cell) {
+                indices.retain(|prop_index| *prop_index != index);
+            }
+        }
+    }
+
+    // props that are still in the contact
+    pub fn is_prop_active(&self, index: usize) -> bool {
+        match self.props.get(index) {
+            Some(prop) => match self.prop_cells.get(&self.cell_index(prop.position)) {
+                Some(indices) => indices.contains(&index),
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    fn prop_footprint_cells(&self, prop: &Prop) -> Vec<[isize; 2]> {
+        let footprint = prop.footprint();
+        let min_cell = self.cell_index(prop.position - Vector::new(footprint, footprint, 0.));
+        let max_cell = self.cell_index(prop.position + Vector::new(footprint, footprint, 0.));
+        let mut cells = Vec::new();
+        for y_index in min_cell[1]..=max_cell[1] {
+            for x_index in min_cell[0]..=max_cell[0] {
+                cells.push([x_index, y_index]);
+            }
+        }
+        cells
+    }
+
+    pub fn props(&self) -> &Vec<Prop> {
+        &self.props
+    }
+
+    // index of an active prop the point is in
+    pub fn prop_at(&self, point: Vector) -> Option<usize> {
+        let prop_indices = self.prop_cells.get(&self.cell_index(point))?;
+        prop_indices
+            .iter()
+            .copied()
+            .find(|index| self.props[*index].interference(point).is_some())
     }
 
     pub fn interference(&self, point: Vector) -> Option<Interference> {
-        if point.x < 0. || point.y < 0. {
-            if point.z < 0. {
-                return Some(Interference {
-                    magnitude: -point.z,
-                    position: Vector::new(point.x, point.y, 0.),
-                    normal: Vector::z(),
-                });
+        self.cell_point_interference(self.cell_index(point), point)
+    }
+
+    // interference of a point in the given cell (see `cache::TerrainCache`)
+    pub(crate) fn cell_point_interference(
+        &self,
+        cell: [isize; 2],
+        point: Vector,
+    ) -> Option<Interference> {
+        let terrain_interference = self.terrain_interference(cell, point);
+
+        // the deepest contact wins if the point is in a prop
+        let mut interference = terrain_interference;
+        if let Some(prop_indices) = self.prop_cells.get(&cell) {
+            for prop_index in prop_indices {
+                if let Some(prop_interference) = self.props[*prop_index].interference(point) {
+                    match &interference {
+                        Some(current) if current.magnitude >= prop_interference.magnitude => {}
+                        _ => interference = Some(prop_interference),
+                    }
+                }
             }
+        }
+        interference
+    }
+
+    fn terrain_interference(&self, cell: [isize; 2], point: Vector) -> Option<Interference> {
+        if self.blend_margin <= 0. || !self.in_blend_margin(cell, point) {
+            return self.cell_interference(cell, point);
+        }
+
+        // Within the margin the contact is with the blended surface, so a point
+        // above the lower side of a step still touches the ramp between the two
+        let height = self.blended_height(cell, point.x, point.y);
+        if point.z >= height {
             return None;
         }
+        // normal from the slope of the blended surface, central differences
+        let delta = 0.01 * self.blend_margin;
+        let slope = |axis: usize| {
+            let mut offset = [point.x, point.y];
+            offset[axis] += delta;
+            let forward = self.blended_height(cell, offset[0], offset[1]);
+            offset[axis] -= 2. * delta;
+            let backward = self.blended_height(cell, offset[0], offset[1]);
+            (forward - backward) / (2. * delta)
+        };
+        let normal = Vector::new(-slope(0), -slope(1), 1.).normalize();
+        let magnitude = (height - point.z) * normal.z;
+        Some(Interference {
+            magnitude,
+            position: point + magnitude * normal,
+            normal,
+        })
+    }
 
-        let x_index = (point.x / self.step[0]) as usize;
-        let y_index = (point.y / self.step[1]) as usize;
+    // distance along the axis to the closest boundary of the cell, the boundary
+    // and the direction of the neighbour across it
+    fn closest_boundary(
+        &self,
+        cell: [isize; 2],
+        axis: usize,
+        coordinate: f64,
+    ) -> (f64, f64, isize) {
+        let step = self.step[axis];
+        let cell_start = cell[axis] as f64 * step;
+        let local = coordinate - cell_start;
+        if local < step / 2. {
+            (local, cell_start, -1)
+        } else {
+            (step - local, cell_start + step, 1)
+        }
+    }
 
+    fn in_blend_margin(&self, cell: [isize; 2], point: Vector) -> bool {
+        (0..2).any(|axis| self.closest_boundary(cell, axis, point[axis]).0 < self.blend_margin)
+    }
+
+    // Height of the cell surface blended with the neighbours, evaluated on the
+    // shared boundaries. The weight of a neighbour goes smoothly from 1/2 on the
+    // boundary to 0 at the edge of the margin, so the surface and its normal are
+    // continuous across the seam and the margin. Near a corner the blend is
+    // bilinear over the 2x2 cells around it, the diagonal one evaluated at the
+    // corner, so the four cells meet at their average there.
+    fn blended_height(&self, cell: [isize; 2], x: f64, y: f64) -> f64 {
+        // weight and direction of the neighbour along each axis, and the point
+        // moved onto the boundary with it
+        let mut weights = [0.; 2];
+        let mut directions = [0; 2];
+        let mut boundary_point = [x, y];
+        for axis in 0..2 {
+            let (distance, boundary, direction) =
+                self.closest_boundary(cell, axis, boundary_point[axis]);
+            if distance >= self.blend_margin {
+                continue;
+            }
+            let t = 0.5 * (1. - distance / self.blend_margin);
+            weights[axis] = t * t * (3. - 2. * t);
+            directions[axis] = direction;
+            boundary_point[axis] = boundary;
+        }
+
+        let mut height = 0.;
+        for (across_x, across_y) in [(false, false), (true, false), (false, true), (true, true)] {
+            let across = [across_x, across_y];
+            let mut weight = 1.;
+            let mut neighbour = cell;
+            let mut point = [x, y];
+            for axis in 0..2 {
+                if across[axis] {
+                    weight *= weights[axis];
+                    neighbour[axis] += directions[axis];
+                    point[axis] = boundary_point[axis];
+                } else {
+                    weight *= 1. - weights[axis];
+                }
+            }
+            if weight > 0. {
+                height += weight * self.cell_height(neighbour, point[0], point[1]);
+            }
+        }
+        height
+    }
+
+    pub fn step(&self) -> [f64; 2] {
+        self.step
+    }
+
+    // highest point of the terrain in the cells within `radius` of the point (in x and y)
+    pub fn max_height(&self, point: Vector, radius: f64) -> f64 {
+        let radius = radius + self.blend_margin;
+        let min_cell = self.cell_index(point - Vector::new(radius, radius, 0.));
+        let max_cell = self.cell_index(point + Vector::new(radius, radius, 0.));
+        let mut max_height = f64::NEG_INFINITY;
+        for y_index in min_cell[1]..=max_cell[1] {
+            for x_index in min_cell[0]..=max_cell[0] {
+                let bounds = self.cell_height_bounds([x_index, y_index]);
+                max_height = max_height.max(bounds[1]);
+            }
+        }
+        max_height
+    }
+
+    // Distance along a ray from `origin` in the (unit) `direction` to the first
+    // point in the terrain or a prop, None if there is none within `max_range`.
+    // Cells the ray passes over are skipped, in the others it steps `resolution`
+    // at a time (thinner features can be missed) and bisects the crossing.
+    pub fn ray_cast(
+        &self,
+        origin: Vector,
+        direction: Vector,
+        max_range: f64,
+        resolution: f64,
+    ) -> Option<f64> {
+        if self.interference(origin).is_some() {
+            return Some(0.);
+        }
+        let mut distance = 0.;
+        while distance < max_range {
+            let cell = self.cell_index(origin + distance * direction);
+            let exit = self
+                .cell_exit(cell, origin, direction)
+                .clamp(distance, max_range);
+
+            // the highest point the ray passes in the cell is at its entry or exit
+            let top = self.blended_top(cell);
+            let low_point = f64::min(
+                origin.z + distance * direction.z,
+                origin.z + exit * direction.z,
+            );
+            if low_point > top {
+                distance = exit + 1e-6;
+                continue;
+            }
+
+            let mut previous = distance;
+            while previous < exit {
+                let next = f64::min(previous + resolution, exit);
+                if self.interference(origin + next * direction).is_some() {
+                    let [mut outside, mut inside] = [previous, next];
+                    for _ in 0..12 {
+                        let middle = 0.5 * (outside + inside);
+                        match self.interference(origin + middle * direction) {
+                            Some(_) => inside = middle,
+                            None => outside = middle,
+                        }
+                    }
+                    return Some(inside);
+                }
+                previous = next;
+            }
+            distance = exit + 1e-6;
+        }
+        None
+    }
+
+    // distance along the ray to where it leaves the cell in x or y
+    fn cell_exit(&self, cell: [isize; 2], origin: Vector, direction: Vector) -> f64 {
+        let mut exit = f64::INFINITY;
+        for axis in 0..2 {
+            let boundary = match direction[axis] {
+                d if d > 0. => (cell[axis] + 1) as f64 * self.step[axis],
+                d if d < 0. => cell[axis] as f64 * self.step[axis],
+                _ => continue,
+            };
+            exit = exit.min((boundary - origin[axis]) / direction[axis]);
+        }
+        exit
+    }
+
+    // highest point in the cell, with the neighbours it blends with
+    fn blended_top(&self, cell: [isize; 2]) -> f64 {
+        let reach = if self.blend_margin > 0. { 1 } else { 0 };
+        let mut top = f64::NEG_INFINITY;
+        for y_index in cell[1] - reach..=cell[1] + reach {
+            for x_index in cell[0] - reach..=cell[0] + reach {
+                top = top.max(self.cell_height_bounds([x_index, y_index])[1]);
+            }
+        }
+        top
+    }
+
+    fn cell_height_bounds(&self, cell: [isize; 2]) -> [f64; 2] {
+        // flat ground outside of the grid
+        let mut bounds = [0., 0.];
+        if cell[0] >= 0 && cell[1] >= 0 {
+            if let Some(y_bounds) = self.height_bounds.get(cell[1] as usize) {
+                if let Some(cell_bounds) = y_bounds.get(cell[0] as usize) {
+                    bounds = *cell_bounds;
+                }
+            }
+        }
+        if let Some(prop_indices) = self.prop_cells.get(&cell) {
+            for prop_index in prop_indices {
+                bounds[1] = bounds[1].max(self.props[*prop_index].top());
+            }
+        }
+        bounds
+    }
+
+    pub fn cell_index(&self, point: Vector) -> [isize; 2] {
+        [
+            (point.x / self.step[0]).floor() as isize,
+            (point.y / self.step[1]).floor() as isize,
+        ]
+    }
+
+    // element in the given cell and the offset of the cell, None outside of the grid
+    fn cell_element(&self, cell: [isize; 2]) -> Option<(&dyn GridElement, Vector)> {
+        if cell[0] < 0 || cell[1] < 0 {
+            return None;
+        }
+        let x_index = cell[0] as usize;
+        let y_index = cell[1] as usize;
+        let element = self.elements.get(y_index)?.get(x_index)?;
         let local_offset = Vector::new(
             x_index as f64 * self.step[0],
             y_index as f64 * self.step[1],
             0.,
         );
-        let local_point = point - local_offset;
-        if let Some(y_elements) = self.elements.get(y_index) {
-            if let Some(element) = y_elements.get(x_index) {
-                if let Some(mut interference) = element.interference(local_point) {
-                    interference.position += local_offset;
-                    return Some(interference);
-                }
-                return None;
+        Some((element.as_ref(), local_offset))
+    }
+
+    // keep the point in the element area (it may be on the boundary of the cell)
+    fn local_point(&self, point: Vector, local_offset: Vector) -> Vector {
+        let mut local_point = point - local_offset;
+        local_point.x = local_point.x.clamp(0., self.step[0]);
+        local_point.y = local_point.y.clamp(0., self.step[1]);
+        local_point
+    }
+
+    // height of the element surface in the given cell, flat ground outside of the grid
+    fn cell_height(&self, cell: [isize; 2], x: f64, y: f64) -> f64 {
+        match self.cell_element(cell) {
+            Some((element, local_offset)) => {
+                let local_point = self.local_point(Vector::new(x, y, 0.), local_offset);
+                element.height(local_point.x, local_point.y)
             }
+            None => 0.,
+        }
+    }
+
+    // interference with the element in the given cell. Cells outside of the grid are flat ground.
+    fn cell_interference(&self, cell: [isize; 2], point: Vector) -> Option<Interference> {
+        if let Some((element, local_offset)) = self.cell_element(cell) {
+            let mut interference = element.interference(self.local_point(point, local_offset))?;
+            interference.position += local_offset;
+            return Some(interference);
         }
         if point.z < 0. {
             return Some(Interference {
@@ -128,6 +590,72 @@ impl GridTerrain {
         }
         return None;
     }
+
+    pub fn water_depth(&self, point: Vector) -> Option<f64> {
+        let (element, local_point) = self.element_at(point)?;
+        element.water_depth(local_point)
+    }
+
+    // height of the terrain surface (props not included), flat ground outside of the grid
+    pub fn height(&self, x: f64, y: f64) -> f64 {
+        match self.element_at(Vector::new(x, y, 0.)) {
+            Some((element, local_point)) => element.height(local_point.x, local_point.y),
+            None => 0.,
+        }
+    }
+
+    // size of the area covered by the grid elements
+    pub fn grid_size(&self) -> [f64; 2] {
+        [
+            self.elements.iter().map(|y| y.len()).max().unwrap_or(0) as f64 * self.step[0],
+            self.elements.len() as f64 * self.step[1],
+        ]
+    }
+
+    // corners (x and y) of the area covered by the grid and all of the props
+    pub fn bounds(&self) -> [[f64; 2]; 2] {
+        let mut min = [0., 0.];
+        let mut max = self.grid_size();
+        for prop in self.props() {
+            let footprint = prop.footprint();
+            min[0] = f64::min(min[0], prop.position.x - footprint);
+            min[1] = f64::min(min[1], prop.position.y - footprint);
+            max[0] = f64::max(max[0], prop.position.x + footprint);
+            max[1] = f64::max(max[1], prop.position.y + footprint);
+        }
+        [min, max]
+    }
+
+    pub fn coloring(&self) -> Option<&TerrainColoring> {
+        self.coloring.as_ref()
+    }
+
+    // surface properties at a contact point, the default surface outside of the grid
+    pub fn surface(&self, point: Vector) -> Surface {
+        match self.element_at(point) {
+            Some((element, local_point)) => element.surface(local_point),
+            None => Surface::default(),
+        }
+    }
+
+    // element containing the point, and the point in the element coordinates
+    fn element_at(&self, point: Vector) -> Option<(&dyn GridElement, Vector)> {
+        if point.x < 0. || point.y < 0. {
+            return None;
+        }
+
+        let x_index = (point.x / self.step[0]) as usize;
+        let y_index = (point.y / self.step[1]) as usize;
+
+        let local_offset = Vector::new(
+            x_index as f64 * self.step[0],
+            y_index as f64 * self.step[1],
+            0.,
+        );
+        let element = self.elements.get(y_index)?.get(x_index)?;
+        Some((element.as_ref(), point - local_offset))
+    }
+
     pub fn build_meshes(
         &self,
         commands: &mut Commands,
@@ -175,13 +703,52 @@ impl GridTerrain {
             }
         }
 
+        // vertex colors are multiplied with the base color
+        let base_color = match self.coloring {
+            Some(_) => Color::WHITE,
+            None => Color::rgb_u8(100, 100, 100),
+        };
         let material = materials.add(StandardMaterial {
-            base_color: Color::rgb_u8(100, 100, 100),
+            base_color,
             perceptual_roughness: 1.0,
             ..default()
         });
-        for (y_index, y_elements) in self.elements.iter().enumerate() {
-            for (x_index, element) in y_elements.iter().enumerate() {
+        let water_material = materials.add(StandardMaterial {
+            base_color: Color::rgba_u8(40, 90, 160, 150),
+            perceptual_roughness: 0.1,
+            alpha_mode: AlphaMode::Blend,
+            ..default()
+        });
+
+        // build all element meshes first, coloring needs the height range of the whole terrain
+        let lod_levels = self.lod_distances.len() + 1;
+        let element_lods = |element: &dyn GridElement| {
+            let mut lods = vec![element.mesh()];
+            lods.extend((1..lod_levels).map_while(|lod| element.mesh_lod(lod)));
+            lods
+        };
+        let mut element_meshes: Vec<Vec<Vec<Mesh>>> = self
+            .elements
+            .iter()
+            .map(|y_elements| {
+                y_elements
+                    .iter()
+                    .map(|element| element_lods(element.as_ref()))
+                    .collect()
+            })
+            .collect();
+        if let Some(coloring) = &self.coloring {
+            // full detail meshes define the height range
+            let range = height_range(element_meshes.iter().flatten().map(|lods| &lods[0]));
+            for mesh in element_meshes.iter_mut().flatten().flatten() {
+                coloring.apply(mesh, range);
+            }
+        }
+
+        for (y_index, (y_elements, y_meshes)) in
+            self.elements.iter().zip(element_meshes).enumerate()
+        {
+            for (x_index, (element, lod_meshes)) in y_elements.iter().zip(y_meshes).enumerate() {
                 let x_offset = x_index as f32 * self.step[0] as f32;
                 let y_offset = y_index as f32 * self.step[1] as f32;
 
@@ -190,14 +757,70 @@ impl GridTerrain {
                     y: y_offset,
                     z: 0.,
                 });
+                let lod_handles: Vec<Handle<Mesh>> = lod_meshes
+                    .into_iter()
+                    .map(|mesh| meshes.add(mesh))
+                    .collect();
                 let mut entity = commands.spawn(PbrBundle {
-                    mesh: meshes.add(element.mesh()),
+                    mesh: lod_handles[0].clone(),
                     material: material.clone(),
                     transform,
                     ..default()
                 });
+                if lod_handles.len() > 1 {
+                    entity.insert(TerrainLod {
+                        center: Vec3::new(
+                            x_offset + self.step[0] as f32 / 2.,
+                            y_offset + self.step[1] as f32 / 2.,
+                            0.,
+                        ),
+                        distances: self.lod_distances.iter().map(|d| *d as f32).collect(),
+                        meshes: lod_handles,
+                        active: 0,
+                    });
+                }
                 entity.set_parent(parent);
+
+                if let Some(water_mesh) = element.water_mesh() {
+                    let mut entity = commands.spawn(PbrBundle {
+                        mesh: meshes.add(water_mesh),
+                        material: water_material.clone(),
+                        transform,
+                        ..default()
+                    });
+                    entity.set_parent(parent);
+                }
             }
         }
+
+        for index in 0..self.props.len() {
+            self.build_prop_mesh(index, commands, meshes, materials, parent);
+        }
+    }
+
+    // mesh of a prop, e.g. one added after the terrain meshes were built
+    pub fn build_prop_mesh(
+        &self,
+        index: usize,
+        commands: &mut Commands,
+        meshes: &mut ResMut<Assets<Mesh>>,
+        materials: &mut ResMut<Assets<StandardMaterial>>,
+        parent: Entity,
+    ) {
+        let prop = &self.props[index];
+        let mut entity = commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(prop.mesh()),
+                material: materials.add(StandardMaterial {
+                    base_color: prop.color(),
+                    perceptual_roughness: 0.8,
+                    ..default()
+                }),
+                transform: prop.transform(),
+                ..default()
+            },
+            PropMesh(index),
+        ));
+        entity.set_parent(parent);
     }
 }
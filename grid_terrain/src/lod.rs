@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+
+// Level of detail meshes of a terrain cell. The mesh is swapped based on the
+// horizontal distance from the camera to the center of the cell.
+#[derive(Component)]
+pub struct TerrainLod {
+    pub center: Vec3,
+    pub distances: Vec<f32>, // distance at which each coarser level is used
+    pub meshes: Vec<Handle<Mesh>>,
+    pub active: usize,
+}
+
+impl TerrainLod {
+    fn level(&self, distance: f32) -> usize {
+        self.distances
+            .iter()
+            .take_while(|lod_distance| distance > **lod_distance)
+            .count()
+            .min(self.meshes.len() - 1)
+    }
+}
+
+pub fn terrain_lod_system(
+    cameras: Query<&GlobalTransform, With<Camera3d>>,
+    mut cells: Query<(&mut TerrainLod, &mut Handle<Mesh>)>,
+) {
+    // use the closest camera (there may be more than one view)
+    let camera_positions: Vec<Vec3> = cameras
+        .iter()
+        .map(|transform| transform.translation())
+        .collect();
+    if camera_positions.is_empty() {
+        return;
+    }
+
+    for (mut lod, mut mesh) in cells.iter_mut() {
+        let distance = camera_positions
+            .iter()
+            .map(|position| (*position - lod.center).truncate().length())
+            .fold(f32::INFINITY, f32::min);
+        let level = lod.level(distance);
+        if level != lod.active {
+            lod.active = level;
+            *mesh = lod.meshes[level].clone();
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use rigid_body::sva::Vector;
+
+use crate::{coloring::TerrainColoring, GridTerrain};
+
+// Top down image of the terrain, rendered once at startup. The image is an
+// asset, so it can be used in the UI (e.g. a HUD minimap) with the car position
+// from `Minimap::uv`.
+#[derive(Resource, Clone)]
+pub struct Minimap {
+    pub image: Handle<Image>,
+    pub origin: [f64; 2], // world position of the bottom left corner of the image
+    pub size: [f64; 2],   // world size covered by the image
+}
+
+impl Minimap {
+    // image coordinates (0 to 1, v down) of a world position
+    pub fn uv(&self, x: f64, y: f64) -> Vec2 {
+        Vec2::new(
+            ((x - self.origin[0]) / self.size[0]) as f32,
+            (1. - (y - self.origin[1]) / self.size[1]) as f32,
+        )
+    }
+}
+
+impl GridTerrain {
+    // render the terrain (and props) top down, colored by height
+    pub fn build_minimap(&self, images: &mut Assets<Image>, pixels_per_meter: f64) -> Minimap {
+        // cover the grid and all of the props
+        let [min, max] = self.bounds();
+        let size = [max[0] - min[0], max[1] - min[1]];
+        let width = ((size[0] * pixels_per_meter).ceil() as u32).max(1);
+        let height = ((size[1] * pixels_per_meter).ceil() as u32).max(1);
+
+        // sample the terrain height at the center of each pixel
+        let mut heights = Vec::with_capacity((width * height) as usize);
+        let mut height_range = [0_f64, 0_f64];
+        for row in 0..height {
+            for column in 0..width {
+                let x = min[0] + (column as f64 + 0.5) / width as f64 * size[0];
+                let y = max[1] - (row as f64 + 0.5) / height as f64 * size[1];
+                let terrain_height = self.height(x, y);
+                height_range[0] = height_range[0].min(terrain_height);
+                height_range[1] = height_range[1].max(terrain_height);
+                heights.push((x, y, terrain_height));
+            }
+        }
+
+        let default_coloring = TerrainColoring::default();
+        let coloring = self.coloring().unwrap_or(&default_coloring);
+        let low = Vec4::from(coloring.low.as_rgba_f32());
+        let high = Vec4::from(coloring.high.as_rgba_f32());
+        let water = Vec4::from(Color::rgb_u8(40, 90, 160).as_rgba_f32());
+        let span = (height_range[1] - height_range[0]).max(f64::EPSILON);
+
+        let pixel = |color: Vec4| color.to_array().map(|c| (c.clamp(0., 1.) * 255.) as u8);
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+        for (x, y, terrain_height) in heights.iter() {
+            let color = if self.water_depth(Vector::new(*x, *y, 0.)).is_some() {
+                water
+            } else {
+                low.lerp(high, ((terrain_height - height_range[0]) / span) as f32)
+            };
+            data.extend(pixel(color));
+        }
+
+        // then the props over it, each in the pixels of its footprint
+        let black = pixel(Vec4::from(Color::BLACK.as_rgba_f32()));
+        let pixel_range = |start: f64, end: f64, count: u32| {
+            let first = (start * count as f64).floor().max(0.) as u32;
+            let last = ((end * count as f64).ceil() as u32).min(count);
+            first..last
+        };
+        for prop in self.props() {
+            let footprint = prop.footprint();
+            let columns = pixel_range(
+                (prop.position.x - footprint - min[0]) / size[0],
+                (prop.position.x + footprint - min[0]) / size[0],
+                width,
+            );
+            let rows = pixel_range(
+                (max[1] - prop.position.y - footprint) / size[1],
+                (max[1] - prop.position.y + footprint) / size[1],
+                height,
+            );
+            for row in rows {
+                for column in columns.clone() {
+                    let index = (row * width + column) as usize;
+                    let (x, y, terrain_height) = heights[index];
+                    let point = Vector::new(x, y, terrain_height + 0.01);
+                    if prop.interference(point).is_some() {
+                        data[4 * index..4 * index + 4].copy_from_slice(&black);
+                    }
+                }
+            }
+        }
+
+        let image = Image::new(
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+
+        Minimap {
+            image: images.add(image),
+            origin: min,
+            size,
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use bevy::{prelude::*, render::mesh::VertexAttributeValues};
+use rigid_body::sva::Vector;
+
+use crate::{rng::SplitMix64, GridElement, Interference, Surface, SurfaceKind};
+
+// A circular patch of reduced grip (ice, standing water, etc.).
+pub struct Patch {
+    pub center: [f64; 2],
+    pub radius: f64,
+    pub friction: f64, // tire coefficient of friction in the patch
+}
+
+// Decorator that overlays low friction patches on any element. The geometry of
+// the underlying element is unchanged, only the friction and the mesh tint.
+// The tint is applied per vertex, so the underlying mesh needs enough vertices
+// (e.g. a `Plane` with subdivisions) for the patches to be visible.
+pub struct Patches {
+    pub element: Box<dyn GridElement>,
+    pub patches: Vec<Patch>,
+    pub tint: Color,
+}
+
+impl Patches {
+    // `count` patches placed randomly in a `size` x `size` element. The same seed
+    // always gives the same patches.
+    pub fn random(
+        element: Box<dyn GridElement>,
+        size: f64,
+        count: usize,
+        radius: [f64; 2],
+        friction: [f64; 2],
+        seed: u64,
+    ) -> Self {
+        let mut rng = SplitMix64(seed);
+        let patches = (0..count)
+            .map(|_| {
+                let radius = rng.range(radius);
+                Patch {
+                    // keep the patches inside of the element
+                    center: [
+                        rng.range([radius, (size - radius).max(radius)]),
+                        rng.range([radius, (size - radius).max(radius)]),
+                    ],
+                    radius,
+                    friction: rng.range(friction),
+                }
+            })
+            .collect();
+        Self {
+            element,
+            patches,
+            tint: Color::rgb(0.75, 0.85, 1.0),
+        }
+    }
+
+    // how much the point is inside of a patch (0 outside, 1 in the middle), and the patch friction
+    fn coverage(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        self.patches
+            .iter()
+            .map(|patch| {
+                let distance =
+                    ((x - patch.center[0]).powi(2) + (y - patch.center[1]).powi(2)).sqrt();
+                // fade out over the outer 20% of the radius
+                let coverage = ((patch.radius - distance) / (0.2 * patch.radius)).clamp(0., 1.);
+                (coverage, patch.friction)
+            })
+            .filter(|(coverage, _)| *coverage > 0.)
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    // tints the mesh of the element over the patches
+    fn tint_mesh(&self, mut mesh: Mesh) -> Mesh {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            return mesh;
+        };
+
+        let tint = Vec4::from(self.tint.as_rgba_f32());
+        let mut colors: Vec<[f32; 4]> = positions
+            .iter()
+            .map(
+                |position| match self.coverage(position[0] as f64, position[1] as f64) {
+                    Some((coverage, _)) => Vec4::ONE.lerp(tint, coverage as f32).to_array(),
+                    None => [1., 1., 1., 1.],
+                },
+            )
+            .collect();
+
+        // keep any existing vertex colors
+        if let Some(VertexAttributeValues::Float32x4(existing)) =
+            mesh.attribute(Mesh::ATTRIBUTE_COLOR)
+        {
+            for (color, existing) in colors.iter_mut().zip(existing.iter()) {
+                for i in 0..4 {
+                    color[i] *= existing[i];
+                }
+            }
+        }
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh
+    }
+}
+
+impl GridElement for Patches {
+    fn interference(&self, point: Vector) -> Option<Interference> {
+        self.element.interference(point)
+    }
+
+    fn mesh(&self) -> Mesh {
+        self.tint_mesh(self.element.mesh())
+    }
+
+    fn mesh_lod(&self, lod: usize) -> Option<Mesh> {
+        self.element.mesh_lod(lod).map(|mesh| self.tint_mesh(mesh))
+    }
+
+    fn water_depth(&self, point: Vector) -> Option<f64> {
+        self.element.water_depth(point)
+    }
+
+    fn water_mesh(&self) -> Option<Mesh> {
+        self.element.water_mesh()
+    }
+
+    fn surface(&self, point: Vector) -> Surface {
+        let mut surface = self.element.surface(point);
+        if let Some((coverage, patch_friction)) = self.coverage(point.x, point.y) {
+            surface.friction += coverage * (patch_friction - surface.friction);
+            if coverage > 0.5 {
+                surface.kind = SurfaceKind::Ice;
+            }
+        }
+        surface
+    }
+
+    fn height(&self, x: f64, y: f64) -> f64 {
+        self.element.height(x, y)
+    }
+
+    fn height_bounds(&self) -> [f64; 2] {
+        self.element.height_bounds()
+    }
+}
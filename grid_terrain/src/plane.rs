@@ -65,4 +65,12 @@ impl GridElement for Plane {
         mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
         mesh
     }
+
+    fn height(&self, _x: f64, _y: f64) -> f64 {
+        0.
+    }
+
+    fn height_bounds(&self) -> [f64; 2] {
+        [0., 0.]
+    }
 }
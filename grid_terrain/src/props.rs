@@ -0,0 +1,294 @@
+use std::f32::consts::PI;
+
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        render_resource::PrimitiveTopology,
+    },
+};
+use rigid_body::sva::Vector;
+
+use crate::Interference;
+
+#[derive(Clone)]
+pub enum PropShape {
+    Cone {
+        radius: f64,
+        height: f64,
+    },
+    Tire {
+        radius: f64,
+        height: f64,
+    }, // a stack of tires, modeled as a cylinder
+    Wall {
+        length: f64,
+        thickness: f64,
+        height: f64,
+    },
+}
+
+// marks the mesh of the prop with this index in the `GridTerrain`
+#[derive(Component)]
+pub struct PropMesh(pub usize);
+
+// A static object standing on the ground. Props are part of the terrain contact,
+// so tires collide with them like with any other terrain surface.
+#[derive(Clone)]
+pub struct Prop {
+    pub shape: PropShape,
+    pub position: Vector, // center of the base
+    pub yaw: f64,         // rotation around z (only matters for walls)
+}
+
+impl Prop {
+    pub fn cone(x: f64, y: f64) -> Self {
+        Self {
+            shape: PropShape::Cone {
+                radius: 0.15,
+                height: 0.5,
+            },
+            position: Vector::new(x, y, 0.),
+            yaw: 0.,
+        }
+    }
+
+    pub fn tire(x: f64, y: f64) -> Self {
+        Self {
+            shape: PropShape::Tire {
+                radius: 0.3,
+                height: 0.8,
+            },
+            position: Vector::new(x, y, 0.),
+            yaw: 0.,
+        }
+    }
+
+    // wall between two points
+    pub fn wall(start: [f64; 2], end: [f64; 2], height: f64) -> Self {
+        let dx = end[0] - start[0];
+        let dy = end[1] - start[1];
+        Self {
+            shape: PropShape::Wall {
+                length: (dx * dx + dy * dy).sqrt(),
+                thickness: 0.3,
+                height,
+            },
+            position: Vector::new((start[0] + end[0]) / 2., (start[1] + end[1]) / 2., 0.),
+            yaw: dy.atan2(dx),
+        }
+    }
+
+    // cones are light enough to be knocked over, walls and tire stacks stay put
+    pub fn is_loose(&self) -> bool {
+        matches!(self.shape, PropShape::Cone { .. })
+    }
+
+    // radius of a circle (in x and y) around the position that contains the prop
+    pub fn footprint(&self) -> f64 {
+        match self.shape {
+            PropShape::Cone { radius, .. } | PropShape::Tire { radius, .. } => radius,
+            PropShape::Wall {
+                length, thickness, ..
+            } => 0.5 * (length * length + thickness * thickness).sqrt(),
+        }
+    }
+
+    pub fn top(&self) -> f64 {
+        match self.shape {
+            PropShape::Cone { height, .. }
+            | PropShape::Tire { height, .. }
+            | PropShape::Wall { height, .. } => self.position.z + height,
+        }
+    }
+
+    pub fn interference(&self, point: Vector) -> Option<Interference> {
+        // point relative to the base of the prop, rotated into the prop coordinates
+        let relative = point - self.position;
+        let (sin, cos) = self.yaw.sin_cos();
+        let local = Vector::new(
+            cos * relative.x + sin * relative.y,
+            -sin * relative.x + cos * relative.y,
+            relative.z,
+        );
+
+        let (magnitude, local_normal) = match self.shape {
+            PropShape::Cone { radius, height } => {
+                if local.z < 0. || local.z > height {
+                    return None;
+                }
+                let distance = (local.x * local.x + local.y * local.y).sqrt();
+                let surface_radius = radius * (1. - local.z / height);
+                if distance >= surface_radius {
+                    return None;
+                }
+                // push out normal to the cone surface
+                let slant = (height * height + radius * radius).sqrt();
+                let radial = radial_direction(local);
+                let normal = (height * radial + radius * Vector::z()) / slant;
+                ((surface_radius - distance) * height / slant, normal)
+            }
+            PropShape::Tire { radius, height } => {
+                if local.z < 0. || local.z > height {
+                    return None;
+                }
+                let distance = (local.x * local.x + local.y * local.y).sqrt();
+                if distance >= radius {
+                    return None;
+                }
+                let side = radius - distance;
+                let top = height - local.z;
+                if top < side {
+                    (top, Vector::z())
+                } else {
+                    (side, radial_direction(local))
+                }
+            }
+            PropShape::Wall {
+                length,
+                thickness,
+                height,
+            } => {
+                let x = length / 2. - local.x.abs();
+                let y = thickness / 2. - local.y.abs();
+                let z = height - local.z;
+                if x <= 0. || y <= 0. || z <= 0. || local.z < 0. {
+                    return None;
+                }
+                // push out through the closest face
+                if z < x && z < y {
+                    (z, Vector::z())
+                } else if x < y {
+                    (x, local.x.signum() * Vector::x())
+                } else {
+                    (y, local.y.signum() * Vector::y())
+                }
+            }
+        };
+
+        let normal = Vector::new(
+            cos * local_normal.x - sin * local_normal.y,
+            sin * local_normal.x + cos * local_normal.y,
+            local_normal.z,
+        );
+        Some(Interference {
+            magnitude,
+            position: point + magnitude * normal,
+            normal,
+        })
+    }
+
+    pub fn mesh(&self) -> Mesh {
+        match self.shape {
+            PropShape::Cone { radius, height } => cone_mesh(radius as f32, height as f32, 24),
+            PropShape::Tire { radius, height } => {
+                let mut mesh = Mesh::from(shape::Cylinder {
+                    radius: radius as f32,
+                    height: height as f32,
+                    resolution: 24,
+                    segments: 1,
+                });
+                // bevy cylinders are y-up and centered, props are z-up and stand on their base
+                let transform = Transform::from_xyz(0., 0., height as f32 / 2.)
+                    * Transform::from_rotation(Quat::from_rotation_x(PI / 2.));
+                transform_mesh(&mut mesh, transform);
+                mesh
+            }
+            PropShape::Wall {
+                length,
+                thickness,
+                height,
+            } => Mesh::from(shape::Box {
+                min_x: -length as f32 / 2.,
+                max_x: length as f32 / 2.,
+                min_y: -thickness as f32 / 2.,
+                max_y: thickness as f32 / 2.,
+                min_z: 0.,
+                max_z: height as f32,
+            }),
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self.shape {
+            PropShape::Cone { .. } => Color::rgb(1.0, 0.4, 0.0),
+            PropShape::Tire { .. } => Color::rgb(0.1, 0.1, 0.1),
+            PropShape::Wall { .. } => Color::rgb(0.7, 0.7, 0.7),
+        }
+    }
+
+    pub fn transform(&self) -> Transform {
+        Transform::from_xyz(
+            self.position.x as f32,
+            self.position.y as f32,
+            self.position.z as f32,
+        )
+        .with_rotation(Quat::from_rotation_z(self.yaw as f32))
+    }
+}
+
+fn radial_direction(local: Vector) -> Vector {
+    let radial = Vector::new(local.x, local.y, 0.);
+    if radial.norm() > 0. {
+        radial.normalize()
+    } else {
+        Vector::x()
+    }
+}
+
+fn cone_mesh(radius: f32, height: f32, resolution: u32) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+
+    let slant = (height * height + radius * radius).sqrt();
+    for i in 0..resolution {
+        let angle_0 = i as f32 / resolution as f32 * 2. * PI;
+        let angle_1 = (i + 1) as f32 / resolution as f32 * 2. * PI;
+        let angle_mid = (angle_0 + angle_1) / 2.;
+        let ind0 = positions.len() as u32;
+
+        // side (one triangle to the tip)
+        for angle in [angle_0, angle_1] {
+            positions.push([radius * angle.cos(), radius * angle.sin(), 0.]);
+            normals.push([
+                height / slant * angle.cos(),
+                height / slant * angle.sin(),
+                radius / slant,
+            ]);
+        }
+        positions.push([0., 0., height]);
+        normals.push([
+            height / slant * angle_mid.cos(),
+            height / slant * angle_mid.sin(),
+            radius / slant,
+        ]);
+        uvs.extend([[0., 0.], [1., 0.], [0.5, 1.]]);
+        indices.extend([ind0, ind0 + 1, ind0 + 2]);
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh
+}
+
+fn transform_mesh(mesh: &mut Mesh, transform: Transform) {
+    if let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION)
+    {
+        for position in positions.iter_mut() {
+            *position = transform.transform_point(Vec3::from(*position)).to_array();
+        }
+    }
+    if let Some(VertexAttributeValues::Float32x3(normals)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_NORMAL)
+    {
+        for normal in normals.iter_mut() {
+            *normal = (transform.rotation * Vec3::from(*normal)).to_array();
+        }
+    }
+}
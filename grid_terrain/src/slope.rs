@@ -101,4 +101,19 @@ impl GridElement for Slope {
         mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
         mesh
     }
+
+    fn height(&self, x: f64, y: f64) -> f64 {
+        let mut point = Vector::new(x, y, 0.);
+        rotate_point(
+            &mut point,
+            self.size,
+            &self.rotate,
+            RotationDirection::Reverse,
+        );
+        self.height * (1. - point.y / self.size)
+    }
+
+    fn height_bounds(&self) -> [f64; 2] {
+        [self.height.min(0.), self.height.max(0.)]
+    }
 }
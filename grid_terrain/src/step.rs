@@ -217,4 +217,24 @@ impl GridElement for Step {
         mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
         mesh
     }
+
+    fn height(&self, x: f64, y: f64) -> f64 {
+        let mut point = Vector::new(x, y, 0.);
+        rotate_point(
+            &mut point,
+            self.size,
+            &self.rotate,
+            RotationDirection::Reverse,
+        );
+        mirror_point(&mut point, self.size, &self.mirror);
+        if point.x < self.size / 2.0 {
+            0.
+        } else {
+            self.height
+        }
+    }
+
+    fn height_bounds(&self) -> [f64; 2] {
+        [self.height.min(0.), self.height.max(0.)]
+    }
 }
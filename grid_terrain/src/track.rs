@@ -0,0 +1,439 @@
+use std::sync::Arc;
+
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use rigid_body::sva::Vector;
+
+use crate::{plane::Plane, GridElement, Interference, Surface, SurfaceKind};
+
+// A closed circuit defined by a Catmull-Rom spline through the waypoints.
+// Widths and banking (radians, positive raises the left side of the track)
+// are given per waypoint and interpolated linearly along the spline.
+// Waypoints must be in the positive quadrant, the grid starts at the origin.
+pub struct Track {
+    pub waypoints: Vec<[f64; 2]>,
+    pub widths: Vec<f64>,
+    pub banking: Vec<f64>,
+    pub curb_width: f64,
+    pub curb_height: f64,
+    pub samples_per_segment: usize,
+}
+
+impl Default for Track {
+    fn default() -> Self {
+        Self {
+            waypoints: vec![],
+            widths: vec![],
+            banking: vec![],
+            curb_width: 0.75,
+            curb_height: 0.05,
+            samples_per_segment: 20,
+        }
+    }
+}
+
+impl Track {
+    pub fn grid_elements(&self, size: f64) -> Vec<Vec<Box<dyn GridElement + 'static>>> {
+        let geometry = Arc::new(TrackGeometry::new(self));
+
+        let x_max = geometry
+            .samples
+            .iter()
+            .map(|s| s.position[0])
+            .fold(0., f64::max);
+        let y_max = geometry
+            .samples
+            .iter()
+            .map(|s| s.position[1])
+            .fold(0., f64::max);
+        let x_count = ((x_max + geometry.reach) / size).ceil() as usize;
+        let y_count = ((y_max + geometry.reach) / size).ceil() as usize;
+
+        let mut grid_elements: Vec<Vec<Box<dyn GridElement + 'static>>> = Vec::new();
+        for y_index in 0..y_count {
+            let mut row: Vec<Box<dyn GridElement + 'static>> = Vec::new();
+            for x_index in 0..x_count {
+                let offset = [x_index as f64 * size, y_index as f64 * size];
+                let segments = geometry.segments_near(offset, size);
+                if segments.is_empty() {
+                    row.push(Box::new(Plane {
+                        size: [size, size],
+                        subdivisions: 1,
+                    }));
+                } else {
+                    row.push(Box::new(TrackElement {
+                        size,
+                        offset,
+                        segments,
+                        geometry: geometry.clone(),
+                    }));
+                }
+            }
+            grid_elements.push(row);
+        }
+        grid_elements
+    }
+
+    // points along the centerline of the closed spline (the last point connects
+    // back to the first), e.g. as a path for a driver to follow
+    pub fn centerline(&self) -> Vec<[f64; 2]> {
+        let geometry = TrackGeometry::new(self);
+        let n = geometry.samples.len() - 1; // the last sample closes the loop
+        geometry.samples[..n].iter().map(|s| s.position).collect()
+    }
+}
+
+struct TrackSample {
+    position: [f64; 2],
+    width: f64,
+    banking: f64,
+    distance: f64, // distance along the centerline
+}
+
+struct TrackGeometry {
+    samples: Vec<TrackSample>,
+    curb_width: f64,
+    curb_height: f64,
+    reach: f64, // furthest distance from the centerline that is not flat ground
+    max_height: f64,
+}
+
+// position on the track relative to the centerline
+struct TrackPoint {
+    lateral: f64,
+    left: [f64; 2],
+    width: f64,
+    banking: f64,
+    distance: f64,
+}
+
+impl TrackGeometry {
+    fn new(track: &Track) -> Self {
+        let n = track.waypoints.len();
+        assert!(n >= 3, "a closed track needs at least three waypoints");
+        assert_eq!(n, track.widths.len());
+        assert_eq!(n, track.banking.len());
+
+        let mut samples: Vec<TrackSample> = Vec::with_capacity(n * track.samples_per_segment + 1);
+        let mut distance = 0.;
+        for i in 0..n {
+            let p0 = track.waypoints[(i + n - 1) % n];
+            let p1 = track.waypoints[i];
+            let p2 = track.waypoints[(i + 1) % n];
+            let p3 = track.waypoints[(i + 2) % n];
+            for j in 0..track.samples_per_segment {
+                let t = j as f64 / track.samples_per_segment as f64;
+                let position = catmull_rom(p0, p1, p2, p3, t);
+                if let Some(last) = samples.last() {
+                    distance += length([
+                        position[0] - last.position[0],
+                        position[1] - last.position[1],
+                    ]);
+                }
+                samples.push(TrackSample {
+                    position,
+                    width: track.widths[i] + t * (track.widths[(i + 1) % n] - track.widths[i]),
+                    banking: track.banking[i] + t * (track.banking[(i + 1) % n] - track.banking[i]),
+                    distance,
+                });
+            }
+        }
+        // close the loop
+        let first = &samples[0];
+        let last = &samples[samples.len() - 1];
+        distance += length([
+            first.position[0] - last.position[0],
+            first.position[1] - last.position[1],
+        ]);
+        samples.push(TrackSample {
+            position: first.position,
+            width: first.width,
+            banking: first.banking,
+            distance,
+        });
+
+        let max_height = samples
+            .iter()
+            .map(|s| s.width * s.banking.tan().abs() + track.curb_height)
+            .fold(0., f64::max);
+        let reach = samples
+            .iter()
+            .map(|s| s.width / 2. + track.curb_width)
+            .fold(0., f64::max)
+            + max_height;
+
+        Self {
+            samples,
+            curb_width: track.curb_width,
+            curb_height: track.curb_height,
+            reach,
+            max_height,
+        }
+    }
+
+    // indices of the centerline segments that can affect the cell
+    fn segments_near(&self, offset: [f64; 2], size: f64) -> Vec<usize> {
+        let min = [offset[0] - self.reach, offset[1] - self.reach];
+        let max = [offset[0] + size + self.reach, offset[1] + size + self.reach];
+        (0..self.samples.len() - 1)
+            .filter(|&i| {
+                let a = self.samples[i].position;
+                let b = self.samples[i + 1].position;
+                a[0].max(b[0]) >= min[0]
+                    && a[0].min(b[0]) <= max[0]
+                    && a[1].max(b[1]) >= min[1]
+                    && a[1].min(b[1]) <= max[1]
+            })
+            .collect()
+    }
+
+    fn closest(&self, segments: &[usize], point: [f64; 2]) -> Option<TrackPoint> {
+        let mut best: Option<(f64, TrackPoint)> = None;
+        for &i in segments {
+            let a = &self.samples[i];
+            let b = &self.samples[i + 1];
+            let ab = [b.position[0] - a.position[0], b.position[1] - a.position[1]];
+            let ap = [point[0] - a.position[0], point[1] - a.position[1]];
+            let ab_length = length(ab);
+            if ab_length == 0. {
+                continue;
+            }
+            let tangent = [ab[0] / ab_length, ab[1] / ab_length];
+            let t = ((ap[0] * tangent[0] + ap[1] * tangent[1]) / ab_length).clamp(0., 1.);
+            let closest = [
+                a.position[0] + t * ab[0] - point[0],
+                a.position[1] + t * ab[1] - point[1],
+            ];
+            let separation = length(closest);
+            if let Some((best_separation, _)) = &best {
+                if separation >= *best_separation {
+                    continue;
+                }
+            }
+            let left = [-tangent[1], tangent[0]];
+            best = Some((
+                separation,
+                TrackPoint {
+                    lateral: -(closest[0] * left[0] + closest[1] * left[1]),
+                    left,
+                    width: a.width + t * (b.width - a.width),
+                    banking: a.banking + t * (b.banking - a.banking),
+                    distance: a.distance + t * (b.distance - a.distance),
+                },
+            ));
+        }
+        best.map(|(_, track_point)| track_point)
+    }
+
+    // height of the surface and its slope in the lateral direction
+    fn profile(&self, track_point: &TrackPoint) -> (f64, f64) {
+        let half_width = track_point.width / 2.;
+        let tan_bank = track_point.banking.tan();
+        let base = half_width * tan_bank.abs(); // lowest edge of the track is at ground level
+        let lateral = track_point.lateral;
+        let side = lateral.signum();
+        let edge_height = base + side * half_width * tan_bank;
+
+        if lateral.abs() <= half_width {
+            // track surface
+            (base + lateral * tan_bank, tan_bank)
+        } else if lateral.abs() <= half_width + self.curb_width {
+            // curb
+            (edge_height + self.curb_height, 0.)
+        } else {
+            // embankment down to ground level
+            let height = edge_height - (lateral.abs() - half_width - self.curb_width);
+            if height > 0. {
+                (height, -side)
+            } else {
+                (0., 0.)
+            }
+        }
+    }
+
+    fn surface_color(&self, track_point: &TrackPoint) -> [f32; 4] {
+        let half_width = track_point.width / 2.;
+        let lateral = track_point.lateral.abs();
+        if lateral <= half_width {
+            [0.45, 0.45, 0.45, 1.]
+        } else if lateral <= half_width + self.curb_width {
+            // alternating red and white curb stripes
+            if (track_point.distance / 2.) as i64 % 2 == 0 {
+                [1., 0.1, 0.1, 1.]
+            } else {
+                [1., 1., 1., 1.]
+            }
+        } else {
+            [1., 1., 1., 1.]
+        }
+    }
+}
+
+pub struct TrackElement {
+    size: f64,
+    offset: [f64; 2],
+    segments: Vec<usize>,
+    geometry: Arc<TrackGeometry>,
+}
+
+impl TrackElement {
+    // height and gradient of the surface at a point in local coordinates
+    fn evaluate(&self, x: f64, y: f64) -> (f64, f64, f64) {
+        let point = [x + self.offset[0], y + self.offset[1]];
+        match self.geometry.closest(&self.segments, point) {
+            Some(track_point) => {
+                let (height, slope) = self.geometry.profile(&track_point);
+                (
+                    height,
+                    slope * track_point.left[0],
+                    slope * track_point.left[1],
+                )
+            }
+            None => (0., 0., 0.),
+        }
+    }
+    // mesh at level of detail `lod`, 0 is full detail
+    fn lod_mesh(&self, lod: usize) -> Mesh {
+        let size = self.size as f32;
+        // halve the resolution for each level of detail
+        let x_vertex_count = (80_u32 >> lod.min(31)).max(4);
+        let y_vertex_count = (80_u32 >> lod.min(31)).max(4);
+
+        let num_vertices = (y_vertex_count * x_vertex_count) as usize;
+        let num_indices = ((y_vertex_count - 1) * (x_vertex_count - 1) * 6) as usize;
+
+        let mut positions: Vec<[f32; 3]> = Vec::with_capacity(num_vertices);
+        let mut normals: Vec<[f32; 3]> = Vec::with_capacity(num_vertices);
+        let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(num_vertices);
+        let mut colors: Vec<[f32; 4]> = Vec::with_capacity(num_vertices);
+        let mut indices: Vec<u32> = Vec::with_capacity(num_indices);
+
+        for y_vert in 0..y_vertex_count {
+            for x_vert in 0..x_vertex_count {
+                let x_normalized = x_vert as f32 / (x_vertex_count - 1) as f32;
+                let y_normalized = y_vert as f32 / (y_vertex_count - 1) as f32;
+
+                let x = x_normalized * size;
+                let y = y_normalized * size;
+                let point = [x as f64 + self.offset[0], y as f64 + self.offset[1]];
+                let (height, dx, dy, color) = match self.geometry.closest(&self.segments, point) {
+                    Some(track_point) => {
+                        let (height, slope) = self.geometry.profile(&track_point);
+                        (
+                            height,
+                            slope * track_point.left[0],
+                            slope * track_point.left[1],
+                            self.geometry.surface_color(&track_point),
+                        )
+                    }
+                    None => (0., 0., 0., [1., 1., 1., 1.]),
+                };
+
+                let normal = Vec3::new(-dx as f32, -dy as f32, 1.).normalize().to_array();
+                positions.push([x, y, height as f32]);
+                normals.push(normal);
+                uvs.push([x_normalized, 1. - y_normalized]);
+                colors.push(color);
+            }
+        }
+
+        for y in 0..y_vertex_count - 1 {
+            for x in 0..x_vertex_count - 1 {
+                let quad = y * x_vertex_count + x;
+                indices.push(quad);
+                indices.push(quad + 1);
+                indices.push(quad + x_vertex_count);
+                indices.push(quad + x_vertex_count + 1);
+                indices.push(quad + x_vertex_count);
+                indices.push(quad + 1);
+            }
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh
+    }
+}
+
+impl GridElement for TrackElement {
+    fn interference(&self, point: Vector) -> Option<Interference> {
+        let size = self.size;
+
+        // point is outside of area
+        if point.x < 0.0 || point.x > size || point.y < 0.0 || point.y > size {
+            return None;
+        }
+
+        let (height, dx, dy) = self.evaluate(point.x, point.y);
+        if point.z > height {
+            return None;
+        }
+
+        Some(Interference {
+            magnitude: height - point.z,
+            position: Vector::new(point.x, point.y, height),
+            normal: Vector::new(-dx, -dy, 1.).normalize(),
+        })
+    }
+
+    fn mesh(&self) -> Mesh {
+        self.lod_mesh(0)
+    }
+
+    fn mesh_lod(&self, lod: usize) -> Option<Mesh> {
+        Some(self.lod_mesh(lod))
+    }
+
+    // the runoff beyond the curbs is loose
+    fn surface(&self, point: Vector) -> Surface {
+        let track_point = [point.x + self.offset[0], point.y + self.offset[1]];
+        let kind = match self.geometry.closest(&self.segments, track_point) {
+            Some(track_point)
+                if track_point.lateral.abs()
+                    > track_point.width / 2. + self.geometry.curb_width =>
+            {
+                SurfaceKind::Loose
+            }
+            _ => SurfaceKind::Paved,
+        };
+        Surface {
+            kind,
+            ..Default::default()
+        }
+    }
+
+    fn height(&self, x: f64, y: f64) -> f64 {
+        self.evaluate(x, y).0
+    }
+
+    fn height_bounds(&self) -> [f64; 2] {
+        [0., self.geometry.max_height]
+    }
+}
+
+pub(crate) fn catmull_rom(
+    p0: [f64; 2],
+    p1: [f64; 2],
+    p2: [f64; 2],
+    p3: [f64; 2],
+    t: f64,
+) -> [f64; 2] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [0, 1].map(|i| {
+        0.5 * (2. * p1[i]
+            + (-p0[i] + p2[i]) * t
+            + (2. * p0[i] - 5. * p1[i] + 4. * p2[i] - p3[i]) * t2
+            + (-p0[i] + 3. * p1[i] - 3. * p2[i] + p3[i]) * t3)
+    })
+}
+
+pub(crate) fn length(v: [f64; 2]) -> f64 {
+    (v[0] * v[0] + v[1] * v[1]).sqrt()
+}
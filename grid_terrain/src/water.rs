@@ -0,0 +1,140 @@
+use std::f64::consts::TAU;
+
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use rigid_body::sva::Vector;
+
+use crate::{plane::Plane, GridElement, Interference};
+
+// A stream crossing the element in the y direction. The bed is a smooth trough
+// (solid, reported through `interference`) and the water surface sits at z = 0
+// (reported as a depth through `water_depth`, so it can be driven through).
+pub struct Water {
+    pub size: [f64; 2],
+    pub depth: f64,
+}
+
+impl Default for Water {
+    fn default() -> Self {
+        Self {
+            size: [20., 20.],
+            depth: 0.5,
+        }
+    }
+}
+
+impl Water {
+    // height of the bed and its slope in x
+    fn bed(&self, x: f64) -> (f64, f64) {
+        let k = TAU / self.size[0];
+        let height = -self.depth * 0.5 * (1. - (k * x).cos());
+        let dx = -self.depth * 0.5 * k * (k * x).sin();
+        (height, dx)
+    }
+
+    fn in_area(&self, point: Vector) -> bool {
+        point.x >= 0.0 && point.x <= self.size[0] && point.y >= 0.0 && point.y <= self.size[1]
+    }
+}
+
+impl GridElement for Water {
+    fn interference(&self, point: Vector) -> Option<Interference> {
+        // point is outside of area
+        if !self.in_area(point) {
+            return None;
+        }
+
+        let (height, dx) = self.bed(point.x);
+        if point.z > height {
+            // point is above the bed
+            return None;
+        }
+
+        Some(Interference {
+            magnitude: height - point.z,
+            position: Vector::new(point.x, point.y, height),
+            normal: Vector::new(-dx, 0., 1.).normalize(),
+        })
+    }
+
+    fn water_depth(&self, point: Vector) -> Option<f64> {
+        if !self.in_area(point) {
+            return None;
+        }
+        let (height, _dx) = self.bed(point.x);
+        if height >= 0. {
+            // no water at the banks
+            return None;
+        }
+        Some(-point.z)
+    }
+
+    fn mesh(&self) -> Mesh {
+        let size = [self.size[0] as f32, self.size[1] as f32];
+        let x_vertex_count = 50;
+        let y_vertex_count = 2;
+
+        let num_vertices = (y_vertex_count * x_vertex_count) as usize;
+        let num_indices = ((y_vertex_count - 1) * (x_vertex_count - 1) * 6) as usize;
+
+        let mut positions: Vec<[f32; 3]> = Vec::with_capacity(num_vertices);
+        let mut normals: Vec<[f32; 3]> = Vec::with_capacity(num_vertices);
+        let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(num_vertices);
+        let mut indices: Vec<u32> = Vec::with_capacity(num_indices);
+
+        for y_vert in 0..y_vertex_count {
+            for x_vert in 0..x_vertex_count {
+                let x_normalized = x_vert as f32 / (x_vertex_count - 1) as f32;
+                let y_normalized = y_vert as f32 / (y_vertex_count - 1) as f32;
+
+                let x = x_normalized * size[0];
+                let y = y_normalized * size[1];
+                let (height, dx) = self.bed(x as f64);
+
+                let normal = Vec3::new(-dx as f32, 0., 1.).normalize().to_array();
+                positions.push([x, y, height as f32]);
+                normals.push(normal);
+                uvs.push([x_normalized, 1. - y_normalized]);
+            }
+        }
+
+        for y in 0..y_vertex_count - 1 {
+            for x in 0..x_vertex_count - 1 {
+                let quad = y * x_vertex_count + x;
+                indices.push(quad);
+                indices.push(quad + 1);
+                indices.push(quad + x_vertex_count);
+                indices.push(quad + x_vertex_count + 1);
+                indices.push(quad + x_vertex_count);
+                indices.push(quad + 1);
+            }
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh
+    }
+
+    fn height(&self, x: f64, _y: f64) -> f64 {
+        self.bed(x).0
+    }
+
+    fn height_bounds(&self) -> [f64; 2] {
+        [-self.depth, 0.]
+    }
+
+    fn water_mesh(&self) -> Option<Mesh> {
+        Some(
+            Plane {
+                size: self.size,
+                subdivisions: 1,
+            }
+            .mesh(),
+        )
+    }
+}
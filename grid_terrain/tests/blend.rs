@@ -0,0 +1,132 @@
+use grid_terrain::{plane::Plane, step::Step, GridElement, GridTerrain};
+use rigid_body::sva::Vector;
+
+const SIZE: f64 = 20.;
+const STEP_HEIGHT: f64 = 0.1;
+
+// the top of a step next to flat ground, with the seam between them at x = SIZE
+fn step_seam(blend_margin: f64) -> GridTerrain {
+    let elements: Vec<Vec<Box<dyn GridElement>>> = vec![vec![
+        Box::new(Step {
+            size: SIZE,
+            height: STEP_HEIGHT,
+            ..Default::default()
+        }),
+        Box::new(Plane {
+            size: [SIZE, SIZE],
+            subdivisions: 1,
+        }),
+    ]];
+    GridTerrain::new(elements, [SIZE, SIZE]).with_blend_margin(blend_margin)
+}
+
+// Sum of the contact (magnitude along the normal) of the points around a tire
+// with its hub at x, the tire rolling along x in the middle of the cells
+fn tire_contact(terrain: &GridTerrain, x: f64, radius: f64, hub_height: f64) -> Vector {
+    let mut contact = Vector::zeros();
+    for index in 0..72 {
+        let angle = index as f64 * std::f64::consts::PI / 36.;
+        let point = Vector::new(
+            x + radius * angle.cos(),
+            SIZE / 2.,
+            hub_height + radius * angle.sin(),
+        );
+        if let Some(interference) = terrain.interference(point) {
+            contact += interference.magnitude * interference.normal;
+        }
+    }
+    contact
+}
+
+// largest change of the tire contact between hub positions 1 mm apart, rolling
+// from the flat ground onto the step
+fn largest_jump(terrain: &GridTerrain) -> f64 {
+    let radius = 0.3;
+    let hub_height = radius - 0.005;
+    let mut previous = tire_contact(terrain, SIZE + 1., radius, hub_height);
+    let mut largest: f64 = 0.;
+    for index in 1..=1500 {
+        let x = SIZE + 1. - index as f64 * 0.001;
+        let contact = tire_contact(terrain, x, radius, hub_height);
+        largest = largest.max((contact - previous).norm());
+        previous = contact;
+    }
+    largest
+}
+
+#[test]
+fn tire_crosses_step_seam_smoothly() {
+    let blended = largest_jump(&step_seam(0.2));
+    let sharp = largest_jump(&step_seam(0.));
+    assert!(
+        sharp > 0.02,
+        "the unblended seam should kick the tire (jump {})",
+        sharp
+    );
+    assert!(
+        blended < 0.25 * sharp && blended < 0.005,
+        "the blended seam jumps by {} (unblended {})",
+        blended,
+        sharp
+    );
+}
+
+#[test]
+fn point_above_lower_side_touches_ramp() {
+    let terrain = step_seam(0.2);
+    // halfway through the margin the ramp is 5/32 of the step above the ground
+    let point = Vector::new(SIZE + 0.1, SIZE / 2., 0.1 * STEP_HEIGHT);
+    let interference = terrain
+        .interference(point)
+        .expect("the point is below the blended surface");
+    let depth = interference.magnitude / interference.normal.z;
+    assert!((depth - (5. / 32. - 0.1) * STEP_HEIGHT).abs() < 1e-3 * STEP_HEIGHT);
+    // the ramp goes down towards the flat ground
+    assert!(interference.normal.x > 0. && interference.normal.z > 0.9);
+
+    // and clear of it beyond the margin
+    let point = Vector::new(SIZE + 0.3, SIZE / 2., 0.1 * STEP_HEIGHT);
+    assert!(terrain.interference(point).is_none());
+}
+
+#[test]
+fn cells_meet_at_corner() {
+    // the top of a step in one cell, flat ground in the three others around
+    // the corner at (SIZE, SIZE)
+    let flat = || -> Box<dyn GridElement> {
+        Box::new(Plane {
+            size: [SIZE, SIZE],
+            subdivisions: 1,
+        })
+    };
+    let step: Box<dyn GridElement> = Box::new(Step {
+        size: SIZE,
+        height: STEP_HEIGHT,
+        ..Default::default()
+    });
+    let elements = vec![vec![step, flat()], vec![flat(), flat()]];
+    let terrain = GridTerrain::new(elements, [SIZE, SIZE]).with_blend_margin(0.2);
+
+    // from each of the four cells, the corner is at the average of their heights
+    let height = |x: f64, y: f64| {
+        let point = Vector::new(x, y, -STEP_HEIGHT);
+        let interference = terrain
+            .interference(point)
+            .expect("the point is below the blended surface");
+        point.z + interference.magnitude / interference.normal.z
+    };
+    let offset = 1e-6;
+    for [x, y] in [[-1., -1.], [1., -1.], [-1., 1.], [1., 1.]] {
+        let corner = height(SIZE + x * offset, SIZE + y * offset);
+        assert!(
+            (corner - 0.25 * STEP_HEIGHT).abs() < 1e-3 * STEP_HEIGHT,
+            "the corner is at {} from the cell at ({}, {})",
+            corner,
+            x,
+            y
+        );
+    }
+    // and the blend is over beyond the margin
+    assert!(height(SIZE + 0.3, SIZE + 0.3).abs() < 1e-9);
+    assert!((height(SIZE - 0.3, SIZE - 0.3) - STEP_HEIGHT).abs() < 1e-9);
+}
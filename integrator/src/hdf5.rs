@@ -0,0 +1,613 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+// A small HDF5 writer, without the HDF5 library: groups of 1-d f64 datasets with
+// text and number attributes, e.g. recorded runs to read with h5py, MATLAB or
+// HDFView. The file has a version 2 superblock and version 2 object headers
+// (HDF5 1.8 or later), the datasets are contiguous, without chunks or filters.
+// `write` adds a group to the root of the file, so one file collects many runs:
+// the group is appended to the file, and only the root group header and the end
+// of file address are written over.
+
+#[derive(Clone, Debug)]
+pub enum Attribute {
+    Text(String),
+    Number(f64),
+    Integer(i64),
+}
+
+#[derive(Clone, Default)]
+pub struct Dataset {
+    pub values: Vec<f64>,
+    pub attributes: Vec<(String, Attribute)>,
+}
+
+impl Dataset {
+    pub fn new(values: Vec<f64>) -> Self {
+        Self {
+            values,
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn with_attribute(mut self, name: &str, value: Attribute) -> Self {
+        self.attributes.push((name.to_string(), value));
+        self
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Group {
+    pub attributes: Vec<(String, Attribute)>,
+    pub members: Vec<(String, Member)>,
+}
+
+#[derive(Clone)]
+pub enum Member {
+    Group(Group),
+    Dataset(Dataset),
+}
+
+impl Group {
+    pub fn with_attribute(mut self, name: &str, value: Attribute) -> Self {
+        self.attributes.push((name.to_string(), value));
+        self
+    }
+
+    // adds the member at the path of names below the group, creating the groups
+    // on the way. The member is given back when a name on the way is a dataset,
+    // or the last one is taken.
+    pub fn insert(&mut self, path: &[&str], member: Member) -> Result<(), Member> {
+        let (name, groups) = match path.split_last() {
+            Some(split) => split,
+            None => return Err(member),
+        };
+        if path.iter().any(|name| !valid_name(name)) {
+            return Err(member);
+        }
+        let mut group = self;
+        for name in groups {
+            let index = match group.members.iter().position(|(other, _)| other == name) {
+                Some(index) => index,
+                None => {
+                    let new_group = Member::Group(Group::default());
+                    group.members.push((name.to_string(), new_group));
+                    group.members.len() - 1
+                }
+            };
+            group = match &mut group.members[index].1 {
+                Member::Group(group) => group,
+                Member::Dataset(_) => return Err(member),
+            };
+        }
+        if group.members.iter().any(|(other, _)| other == name) {
+            return Err(member);
+        }
+        group.members.push((name.to_string(), member));
+        Ok(())
+    }
+}
+
+// Adds the group to the root of the file, appended at its end. The file is created
+// if there is none, an existing file must have been written here, without a
+// member of that name.
+pub fn write(path: &Path, name: &str, group: &Group) -> io::Result<()> {
+    if !valid_name(name) {
+        return Err(invalid_name(name));
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    if file.metadata()?.len() == 0 {
+        // the superblock, the group and the root
+        let mut end = Appended::new(SUPERBLOCK_SIZE as u64);
+        let address = write_group(&mut end, group, Vec::new())?;
+        let root = end.address();
+        let links = [(name.to_string(), address)];
+        end.bytes.extend(object_header(&root_messages(&links)?)?);
+        file.write_all(&superblock(end.address(), root))?;
+        file.write_all(&end.bytes)?;
+        return file.sync_all();
+    }
+
+    let mut root = read_root(&mut file)?;
+    if root.links.iter().any(|(link, _)| link == name) {
+        return Err(io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("HDF5 file already has {:?}", name),
+        ));
+    }
+    let mut end = Appended::new(root.end_of_file);
+    let address = write_group(&mut end, group, Vec::new())?;
+    let link = message(LINK, &link(name, address))?;
+    match root.chunk.as_mut() {
+        // in the free space at the end of the root header
+        Some(chunk) if chunk.fits(link.len()) => chunk.insert(&link),
+        // in a continuation chunk at the end of the file, twice the size of the last
+        Some(chunk) => {
+            let size = (2 * chunk.bytes.len()).max(4 + link.len() + 4 + 16 + 4);
+            let continuation = Chunk::continuation(&link, size);
+            let mut address = end.address().to_le_bytes().to_vec();
+            address.extend((continuation.len() as u64).to_le_bytes());
+            chunk.insert(&message(CONTINUATION, &address)?);
+            end.bytes.extend(continuation);
+        }
+        // a root header without free space, from an earlier version: a new one
+        None => {
+            root.links.push((name.to_string(), address));
+            root.address = end.address();
+            let header = object_header(&root_messages(&root.links)?)?;
+            end.bytes.extend(header);
+        }
+    }
+
+    // the new objects, then the root header and the superblock pointing to them
+    file.seek(SeekFrom::Start(end.start))?;
+    file.write_all(&end.bytes)?;
+    if let Some(chunk) = root.chunk {
+        file.seek(SeekFrom::Start(chunk.address))?;
+        file.write_all(&chunk.bytes)?;
+    }
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&superblock(end.address(), root.address))?;
+    file.sync_all()
+}
+
+// names in the root group of a file written here, none if there is no file
+pub fn root_names(path: &Path) -> io::Result<Vec<String>> {
+    match File::open(path) {
+        Ok(mut file) => {
+            let root = read_root(&mut file)?;
+            Ok(root.links.into_iter().map(|(name, _)| name).collect())
+        }
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(error) => Err(error),
+    }
+}
+
+const SIGNATURE: [u8; 8] = [0x89, b'H', b'D', b'F', b'\r', b'\n', 0x1a, b'\n'];
+const SUPERBLOCK_SIZE: usize = 48;
+const UNDEFINED: u64 = u64::MAX; // address of nothing
+const ROOT_SPACE: usize = 1024; // free space of a new root header, for the links to come
+
+// header message types
+const NIL: u8 = 0x00;
+const DATASPACE: u8 = 0x01;
+const LINK_INFO: u8 = 0x02;
+const DATATYPE: u8 = 0x03;
+const FILL_VALUE: u8 = 0x05;
+const LINK: u8 = 0x06;
+const LAYOUT: u8 = 0x08;
+const GROUP_INFO: u8 = 0x0a;
+const ATTRIBUTE: u8 = 0x0c;
+const CONTINUATION: u8 = 0x10;
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && !name.contains('/') && name.len() <= u16::MAX as usize
+}
+
+fn invalid_name(name: &str) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidInput,
+        format!("invalid HDF5 name: {:?}", name),
+    )
+}
+
+fn not_written_here() -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, "not an HDF5 file of this writer")
+}
+
+// version 2, with 8 byte addresses and lengths
+fn superblock(end_of_file: u64, root: u64) -> Vec<u8> {
+    let mut superblock = SIGNATURE.to_vec();
+    superblock.extend([2, 8, 8, 0]);
+    // base address, superblock extension, end of file and root group object header
+    for address in [0, UNDEFINED, end_of_file, root] {
+        superblock.extend(address.to_le_bytes());
+    }
+    let checksum = lookup3(&superblock);
+    superblock.extend(checksum.to_le_bytes());
+    superblock
+}
+
+// version 2 object header with the messages (type, data) in a single chunk
+fn object_header(messages: &[(u8, Vec<u8>)]) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    for (message_type, data) in messages {
+        body.extend(message(*message_type, data)?);
+    }
+    let mut header = b"OHDR".to_vec();
+    header.extend([2, 2]); // version, flags: 4 byte chunk size
+    header.extend((body.len() as u32).to_le_bytes());
+    header.extend(body);
+    let checksum = lookup3(&header);
+    header.extend(checksum.to_le_bytes());
+    Ok(header)
+}
+
+// a header message: type, length, flags and data
+fn message(message_type: u8, data: &[u8]) -> io::Result<Vec<u8>> {
+    let length = u16::try_from(data.len())
+        .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "HDF5 header message too long"))?;
+    let mut message = vec![message_type];
+    message.extend(length.to_le_bytes());
+    message.push(0); // message flags
+    message.extend(data);
+    Ok(message)
+}
+
+// a nil message taking `size` bytes, header included, the free space of a header
+fn nil(size: usize) -> Vec<u8> {
+    let mut nil = vec![NIL];
+    nil.extend(((size - 4) as u16).to_le_bytes());
+    nil.resize(size, 0);
+    nil
+}
+
+// the messages of a root group with the links, and free space for more
+fn root_messages(links: &[(String, u64)]) -> io::Result<Vec<(u8, Vec<u8>)>> {
+    let mut messages = group_messages(links)?;
+    messages.push((NIL, vec![0; ROOT_SPACE - 4]));
+    Ok(messages)
+}
+
+// bytes appended to a file, from the address `start`
+struct Appended {
+    start: u64,
+    bytes: Vec<u8>,
+}
+
+impl Appended {
+    fn new(start: u64) -> Self {
+        Self {
+            start,
+            bytes: Vec::new(),
+        }
+    }
+
+    // of the next byte
+    fn address(&self) -> u64 {
+        self.start + self.bytes.len() as u64
+    }
+}
+
+// The last chunk of the root header, with its checksum, and the nil message
+// filling its end. The nil message always keeps room for a continuation message
+// (4 + 16 bytes), and is either that size or leaves room for another nil message.
+struct Chunk {
+    address: u64,
+    bytes: Vec<u8>,
+    free: usize, // offset of the nil message
+}
+
+impl Chunk {
+    // a continuation chunk with the message, free space filling it to `size`
+    fn continuation(message: &[u8], size: usize) -> Vec<u8> {
+        let mut chunk = b"OCHK".to_vec();
+        chunk.extend(message);
+        chunk.extend(nil(size - chunk.len() - 4));
+        let checksum = lookup3(&chunk);
+        chunk.extend(checksum.to_le_bytes());
+        chunk
+    }
+
+    fn free_size(&self) -> usize {
+        self.bytes.len() - 4 - self.free
+    }
+
+    fn fits(&self, size: usize) -> bool {
+        let rest = self.free_size().saturating_sub(size);
+        self.free_size() >= size && (rest == 20 || rest >= 24)
+    }
+
+    // the message in the free space, what is left of it a nil message again
+    fn insert(&mut self, message: &[u8]) {
+        let rest = self.free_size() - message.len();
+        let end = self.bytes.len() - 4;
+        let mut free = message.to_vec();
+        match rest {
+            0 => (),
+            // a continuation in a free space of 20 to 23 bytes leaves a gap
+            1..=3 => free.resize(message.len() + rest, 0),
+            _ => free.extend(nil(rest)),
+        }
+        self.bytes.splice(self.free..end, free);
+        self.free += message.len();
+        let checksum = lookup3(&self.bytes[..end]);
+        self.bytes[end..].copy_from_slice(&checksum.to_le_bytes());
+    }
+}
+
+// writes the members, then the group (with the links given), returns its address
+fn write_group(
+    file: &mut Appended,
+    group: &Group,
+    mut links: Vec<(String, u64)>,
+) -> io::Result<u64> {
+    for (name, member) in group.members.iter() {
+        let address = match member {
+            Member::Group(group) => write_group(file, group, Vec::new())?,
+            Member::Dataset(dataset) => write_dataset(file, dataset)?,
+        };
+        links.push((name.clone(), address));
+    }
+    let mut messages = group_messages(&links)?;
+    messages.extend(attributes(&group.attributes)?);
+    let address = file.address();
+    file.bytes.extend(object_header(&messages)?);
+    Ok(address)
+}
+
+// compact links, without a heap: link info, group info and the links
+fn group_messages(links: &[(String, u64)]) -> io::Result<Vec<(u8, Vec<u8>)>> {
+    let mut link_info = vec![0, 0];
+    link_info.extend(UNDEFINED.to_le_bytes());
+    link_info.extend(UNDEFINED.to_le_bytes());
+    let mut messages = vec![(LINK_INFO, link_info), (GROUP_INFO, vec![0, 0])];
+    for (name, address) in links.iter() {
+        if !valid_name(name) {
+            return Err(invalid_name(name));
+        }
+        messages.push((LINK, link(name, *address)));
+    }
+    Ok(messages)
+}
+
+fn write_dataset(file: &mut Appended, dataset: &Dataset) -> io::Result<u64> {
+    // contiguous data, nowhere when empty
+    let data = match dataset.values.is_empty() {
+        true => UNDEFINED,
+        false => file.address(),
+    };
+    for value in dataset.values.iter() {
+        file.bytes.extend(value.to_le_bytes());
+    }
+    let mut layout = vec![3, 1]; // version, contiguous
+    layout.extend(data.to_le_bytes());
+    layout.extend((8 * dataset.values.len() as u64).to_le_bytes());
+
+    let mut messages = vec![
+        (DATASPACE, dataspace(&[dataset.values.len() as u64])),
+        (DATATYPE, f64_type()),
+        (FILL_VALUE, vec![3, 0x09]), // version, allocated early, written if set
+        (LAYOUT, layout),
+    ];
+    messages.extend(attributes(&dataset.attributes)?);
+    let address = file.address();
+    file.bytes.extend(object_header(&messages)?);
+    Ok(address)
+}
+
+// hard link, with a 2 byte UTF-8 name length
+fn link(name: &str, address: u64) -> Vec<u8> {
+    let mut link = vec![1, 0x11, 1];
+    link.extend((name.len() as u16).to_le_bytes());
+    link.extend(name.as_bytes());
+    link.extend(address.to_le_bytes());
+    link
+}
+
+fn attributes(attributes: &[(String, Attribute)]) -> io::Result<Vec<(u8, Vec<u8>)>> {
+    let mut messages = Vec::new();
+    for (name, value) in attributes.iter() {
+        if !valid_name(name) {
+            return Err(invalid_name(name));
+        }
+        let (datatype, data) = match value {
+            Attribute::Text(text) => {
+                let mut data = text.as_bytes().to_vec();
+                data.push(0);
+                (text_type(data.len()), data)
+            }
+            Attribute::Number(value) => (f64_type(), value.to_le_bytes().to_vec()),
+            Attribute::Integer(value) => (i64_type(), value.to_le_bytes().to_vec()),
+        };
+        let dataspace = dataspace(&[]);
+        let mut message = vec![3, 0]; // version, flags
+        message.extend((name.len() as u16 + 1).to_le_bytes());
+        message.extend((datatype.len() as u16).to_le_bytes());
+        message.extend((dataspace.len() as u16).to_le_bytes());
+        message.push(1); // UTF-8 name
+        message.extend(name.as_bytes());
+        message.push(0);
+        message.extend(datatype);
+        message.extend(dataspace);
+        message.extend(data);
+        messages.push((ATTRIBUTE, message));
+    }
+    Ok(messages)
+}
+
+// version 2, scalar without dimensions
+fn dataspace(dimensions: &[u64]) -> Vec<u8> {
+    let simple = !dimensions.is_empty() as u8;
+    let mut dataspace = vec![2, dimensions.len() as u8, 0, simple];
+    for dimension in dimensions {
+        dataspace.extend(dimension.to_le_bytes());
+    }
+    dataspace
+}
+
+// little endian IEEE double
+fn f64_type() -> Vec<u8> {
+    // floating point class, version 1: implied mantissa msb, sign at bit 63
+    let mut datatype = vec![0x11, 0x20, 63, 0];
+    datatype.extend(8_u32.to_le_bytes());
+    datatype.extend(0_u16.to_le_bytes()); // bit offset
+    datatype.extend(64_u16.to_le_bytes()); // precision
+    datatype.extend([52, 11, 0, 52]); // exponent and mantissa location and size
+    datatype.extend(1023_u32.to_le_bytes()); // exponent bias
+    datatype
+}
+
+// little endian signed 64 bit integer
+fn i64_type() -> Vec<u8> {
+    let mut datatype = vec![0x10, 0x08, 0, 0];
+    datatype.extend(8_u32.to_le_bytes());
+    datatype.extend(0_u16.to_le_bytes());
+    datatype.extend(64_u16.to_le_bytes());
+    datatype
+}
+
+// fixed length, null terminated UTF-8 string
+fn text_type(size: usize) -> Vec<u8> {
+    let mut datatype = vec![0x13, 0x10, 0, 0];
+    datatype.extend((size as u32).to_le_bytes());
+    datatype
+}
+
+fn read_at(file: &mut File, address: u64, length: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; length];
+    file.seek(SeekFrom::Start(address))?;
+    file.read_exact(&mut bytes)
+        .map_err(|_| not_written_here())?;
+    Ok(bytes)
+}
+
+// the root group of a file written here
+struct Root {
+    end_of_file: u64,
+    address: u64, // of the root group header
+    links: Vec<(String, u64)>,
+    chunk: Option<Chunk>, // last chunk of the header, None without free space
+}
+
+fn read_root(file: &mut File) -> io::Result<Root> {
+    let superblock = read_at(file, 0, SUPERBLOCK_SIZE)?;
+    if superblock[..12] != [&SIGNATURE[..], &[2, 8, 8, 0]].concat() {
+        return Err(not_written_here());
+    }
+    let end_of_file = u64::from_le_bytes(superblock[28..36].try_into().unwrap());
+    let root = u64::from_le_bytes(superblock[36..44].try_into().unwrap());
+    let mut address = root;
+    let prefix = read_at(file, address, 10)?;
+    if prefix[..6] != *b"OHDR\x02\x02" {
+        return Err(not_written_here());
+    }
+    let size = u32::from_le_bytes(prefix[6..10].try_into().unwrap()) as usize;
+    let mut bytes = read_at(file, address, 10 + size + 4)?;
+    let mut start = 10;
+    let mut links = Vec::new();
+    loop {
+        let end = chunk_links(&bytes, start, &mut links).ok_or_else(not_written_here)?;
+        match end.next {
+            Some((next_address, length)) => {
+                address = next_address;
+                bytes = read_at(file, address, length as usize)?;
+                if bytes.get(..4) != Some(b"OCHK") {
+                    return Err(not_written_here());
+                }
+                start = 4;
+            }
+            None => {
+                let chunk = end.free.map(|free| Chunk {
+                    address,
+                    bytes,
+                    free,
+                });
+                return Ok(Root {
+                    end_of_file,
+                    address: root,
+                    links,
+                    chunk,
+                });
+            }
+        }
+    }
+}
+
+// what follows the links of a header chunk
+struct ChunkEnd {
+    next: Option<(u64, u64)>, // continuation chunk address and length
+    free: Option<usize>,      // offset of a nil message ending the chunk, room for a continuation
+}
+
+// Adds the links of the header chunk (messages from `start` to the checksum).
+// None for any other file.
+fn chunk_links(chunk: &[u8], start: usize, links: &mut Vec<(String, u64)>) -> Option<ChunkEnd> {
+    let end = chunk.len().checked_sub(4)?;
+    if lookup3(&chunk[..end]).to_le_bytes() != chunk[end..] {
+        return None;
+    }
+    let mut at = start;
+    let mut next = None;
+    let mut free = None;
+    // messages down to a gap of less than a message header
+    while at + 4 <= end {
+        let message_type = chunk[at];
+        let length = u16::from_le_bytes(chunk[at + 1..at + 3].try_into().ok()?) as usize;
+        let data = chunk.get(at + 4..at + 4 + length)?;
+        free = None;
+        match message_type {
+            LINK_INFO | GROUP_INFO => (),
+            NIL if length >= 16 => free = Some(at),
+            NIL => (),
+            CONTINUATION => {
+                let address = u64::from_le_bytes(data.get(..8)?.try_into().ok()?);
+                let length = u64::from_le_bytes(data.get(8..16)?.try_into().ok()?);
+                next = Some((address, length));
+            }
+            LINK if data.get(..3)? == [1, 0x11, 1] => {
+                let name_length = u16::from_le_bytes(data.get(3..5)?.try_into().ok()?) as usize;
+                let name = String::from_utf8(data.get(5..5 + name_length)?.to_vec()).ok()?;
+                let address = data.get(5 + name_length..13 + name_length)?;
+                links.push((name, u64::from_le_bytes(address.try_into().ok()?)));
+            }
+            _ => return None,
+        }
+        at += 4 + length;
+    }
+    Some(ChunkEnd { next, free })
+}
+
+// Bob Jenkins' lookup3 hash (hashlittle, initial value 0), the checksum of the
+// HDF5 metadata
+fn lookup3(data: &[u8]) -> u32 {
+    let word = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let start = 0xdeadbeef_u32.wrapping_add(data.len() as u32);
+    let [mut a, mut b, mut c] = [start; 3];
+    let mut rest = data;
+    while rest.len() > 12 {
+        a = a.wrapping_add(word(&rest[0..]));
+        b = b.wrapping_add(word(&rest[4..]));
+        c = c.wrapping_add(word(&rest[8..]));
+        // mix
+        a = a.wrapping_sub(c) ^ c.rotate_left(4);
+        c = c.wrapping_add(b);
+        b = b.wrapping_sub(a) ^ a.rotate_left(6);
+        a = a.wrapping_add(c);
+        c = c.wrapping_sub(b) ^ b.rotate_left(8);
+        b = b.wrapping_add(a);
+        a = a.wrapping_sub(c) ^ c.rotate_left(16);
+        c = c.wrapping_add(b);
+        b = b.wrapping_sub(a) ^ a.rotate_left(19);
+        a = a.wrapping_add(c);
+        c = c.wrapping_sub(b) ^ b.rotate_left(4);
+        b = b.wrapping_add(a);
+        rest = &rest[12..];
+    }
+    if rest.is_empty() {
+        return c;
+    }
+    // the last 1 to 12 bytes, padded with zeros
+    let mut last = [0; 12];
+    last[..rest.len()].copy_from_slice(rest);
+    a = a.wrapping_add(word(&last[0..]));
+    b = b.wrapping_add(word(&last[4..]));
+    c = c.wrapping_add(word(&last[8..]));
+    // final
+    c = (c ^ b).wrapping_sub(b.rotate_left(14));
+    a = (a ^ c).wrapping_sub(c.rotate_left(11));
+    b = (b ^ a).wrapping_sub(a.rotate_left(25));
+    c = (c ^ b).wrapping_sub(b.rotate_left(16));
+    a = (a ^ c).wrapping_sub(c.rotate_left(4));
+    b = (b ^ a).wrapping_sub(a.rotate_left(14));
+    c = (c ^ b).wrapping_sub(b.rotate_left(24));
+    c
+}
@@ -1,5 +1,7 @@
 // pub mod integrator;
-// pub mod recorder;
+pub mod hdf5;
+pub mod mcap;
+pub mod recorder;
 
 use bevy::{ecs::schedule::ScheduleLabel, prelude::*};
 use std::{
@@ -70,6 +72,27 @@ impl SimTime {
     }
 }
 
+// Seed of every random process of a run (terrain, sensor noise, AI drivers...),
+// so a run is repeated exactly from its seed. Each process draws from its own
+// stream of the seed, so adding one doesn't change the others.
+#[derive(Resource, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct SimSeed(pub u64);
+
+impl SimSeed {
+    // seed of the named stream, e.g. "imu" (FNV-1a of the name, mixed with the
+    // seed by the SplitMix64 finalizer)
+    pub fn stream(&self, name: &str) -> u64 {
+        let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+        for byte in name.bytes() {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3);
+        }
+        let mut z = self.0 ^ hash;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
 // wrapper over HashMap<Entity, T::State> to implement Add and Mul
 impl<T: Stateful> StateMap<T> {
     pub fn new() -> Self {
@@ -82,6 +105,39 @@ impl<T: Stateful> StateMap<T> {
     pub fn insert(&mut self, entity: Entity, state: T::State) {
         self.0.insert(entity, state);
     }
+
+    // the in place operations below keep the capacity of the map, so the
+    // solver doesn't allocate once its buffers have grown
+
+    pub fn copy_from(&mut self, other: &Self) {
+        self.0.clear();
+        for (entity, state) in other.0.iter() {
+            self.0.insert(*entity, state.clone());
+        }
+    }
+
+    // a + b * scale, as `a + &(b * scale)`
+    pub fn set_scaled_sum(&mut self, a: &Self, b: &Self, scale: f64) {
+        self.0.clear();
+        for (entity, state) in a.0.iter() {
+            let scaled = b.0.get(entity).unwrap().clone() * scale;
+            self.0.insert(*entity, state.clone() + scaled);
+        }
+    }
+
+    // self + b * scale, as `self + &(b * scale)`
+    pub fn add_scaled(&mut self, b: &Self, scale: f64) {
+        for (entity, state) in self.0.iter_mut() {
+            let scaled = b.0.get(entity).unwrap().clone() * scale;
+            *state = state.clone() + scaled;
+        }
+    }
+}
+
+impl<T: Stateful> Default for StateMap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: Stateful> Clone for StateMap<T> {
@@ -114,32 +170,62 @@ impl<T: Stateful> Add for &StateMap<T> {
     }
 }
 
-fn evaluate_state<T: Stateful>(world: &mut World, state: &StateMap<T>, _t: f64) -> StateMap<T> {
+// The states of the solver stages, kept between the time steps so a step
+// doesn't allocate
+#[derive(Resource)]
+struct SolverBuffers<T: Stateful> {
+    state_0: StateMap<T>, // at the start of the step
+    stage: StateMap<T>,   // evaluated by a stage
+    k: [StateMap<T>; 4],  // state derivatives of the stages
+    sum: StateMap<T>,     // weighted sum of the derivatives
+    state: StateMap<T>,   // at the end of the step
+}
+
+impl<T: Stateful> Default for SolverBuffers<T> {
+    fn default() -> Self {
+        Self {
+            state_0: StateMap::new(),
+            stage: StateMap::new(),
+            k: Default::default(),
+            sum: StateMap::new(),
+            state: StateMap::new(),
+        }
+    }
+}
+
+fn evaluate_state<T: Stateful>(
+    world: &mut World,
+    state: &StateMap<T>,
+    dstates: &mut StateMap<T>,
+    _t: f64,
+) {
     // assign the state
-    world.resource_scope(
-        |_world: &mut World, mut physics_state: Mut<PhysicsState<T>>| {
-            physics_state.states = state.clone();
-        },
-    );
+    world
+        .resource_mut::<PhysicsState<T>>()
+        .states
+        .copy_from(state);
 
     // run the physics
     world.run_schedule(PhysicsSchedule);
 
     // return the state derivative
-    let mut dstates = StateMap(HashMap::new());
-    world.resource_scope(|_world: &mut World, physics_state: Mut<PhysicsState<T>>| {
-        dstates = physics_state.dstates.clone();
-    });
-    dstates
+    dstates.copy_from(&world.resource::<PhysicsState<T>>().dstates);
 }
 
 pub fn integrator_schedule<T: Stateful>(world: &mut World) {
+    if !world.contains_resource::<SolverBuffers<T>>() {
+        world.insert_resource(SolverBuffers::<T>::default());
+    }
+    world.resource_scope(|world, mut buffers: Mut<SolverBuffers<T>>| {
+        integrator_step(world, buffers.bypass_change_detection());
+    });
+}
+
+fn integrator_step<T: Stateful>(world: &mut World, buffers: &mut SolverBuffers<T>) {
     // get the initial state
-    let state_0 = world
-        .get_resource::<PhysicsState<T>>()
-        .unwrap()
-        .states
-        .clone();
+    buffers
+        .state_0
+        .copy_from(&world.resource::<PhysicsState<T>>().states);
 
     // get step size
     let time_step = world
@@ -156,15 +242,15 @@ pub fn integrator_schedule<T: Stateful>(world: &mut World) {
     // get Solver resource from world
     let solver = world.get_resource::<Solver>().unwrap();
 
-    let state = match solver {
-        Solver::Euler => euler::<T>(world, &state_0, time, time_step),
-        Solver::Heun => heun::<T>(world, &state_0, time, time_step),
-        Solver::Midpoint => midpoint::<T>(world, &state_0, time, time_step),
-        Solver::RK4 => rk4::<T>(world, &state_0, time, time_step),
+    match solver {
+        Solver::Euler => euler::<T>(world, buffers, time, time_step),
+        Solver::Heun => heun::<T>(world, buffers, time, time_step),
+        Solver::Midpoint => midpoint::<T>(world, buffers, time, time_step),
+        Solver::RK4 => rk4::<T>(world, buffers, time, time_step),
     };
 
     let mut physics_state = world.get_resource_mut::<PhysicsState<T>>().unwrap();
-    physics_state.states = state;
+    physics_state.states.copy_from(&buffers.state);
 }
 
 pub trait Stateful: std::fmt::Debug + 'static {
@@ -274,44 +360,75 @@ pub enum Solver {
     RK4,
 }
 
-fn euler<T: Stateful>(world: &mut World, state: &StateMap<T>, t: f64, dt: f64) -> StateMap<T> {
-    let state_derivative = evaluate_state(world, &mut state.clone(), t);
-    let updated_state = state + &(&state_derivative * dt);
-    updated_state
+impl Solver {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "euler" => Some(Solver::Euler),
+            "heun" => Some(Solver::Heun),
+            "midpoint" => Some(Solver::Midpoint),
+            "rk4" => Some(Solver::RK4),
+            _ => None,
+        }
+    }
+}
+
+// Each solver sets `buffers.state` from `buffers.state_0`, with the same
+// arithmetic as the `StateMap` operators
+
+fn euler<T: Stateful>(world: &mut World, buffers: &mut SolverBuffers<T>, t: f64, dt: f64) {
+    let SolverBuffers {
+        state_0, k, state, ..
+    } = buffers;
+    evaluate_state(world, state_0, &mut k[0], t);
+    state.set_scaled_sum(state_0, &k[0], dt);
 }
 
-fn heun<T: Stateful>(world: &mut World, state: &StateMap<T>, t: f64, dt: f64) -> StateMap<T> {
-    let state_derivative = evaluate_state(world, &mut state.clone(), t);
-    let state_derivative2 = evaluate_state(world, &mut (state + &(&state_derivative * dt)), t + dt);
-    state + &(&(&state_derivative + &state_derivative2) * (dt * 0.5))
+fn heun<T: Stateful>(world: &mut World, buffers: &mut SolverBuffers<T>, t: f64, dt: f64) {
+    let SolverBuffers {
+        state_0,
+        stage,
+        k,
+        sum,
+        state,
+    } = buffers;
+    evaluate_state(world, state_0, &mut k[0], t);
+    stage.set_scaled_sum(state_0, &k[0], dt);
+    evaluate_state(world, stage, &mut k[1], t + dt);
+    sum.set_scaled_sum(&k[0], &k[1], 1.);
+    state.set_scaled_sum(state_0, sum, dt * 0.5);
 }
 
-fn midpoint<T: Stateful>(world: &mut World, state: &StateMap<T>, t: f64, dt: f64) -> StateMap<T> {
-    let state_derivative = evaluate_state(world, &mut state.clone(), t);
-    let state_derivative2 = evaluate_state(
-        world,
-        &mut (state + &(&state_derivative * (dt * 0.5))),
-        t + dt * 0.5,
-    );
-    state + &(&state_derivative2 * dt)
+fn midpoint<T: Stateful>(world: &mut World, buffers: &mut SolverBuffers<T>, t: f64, dt: f64) {
+    let SolverBuffers {
+        state_0,
+        stage,
+        k,
+        state,
+        ..
+    } = buffers;
+    evaluate_state(world, state_0, &mut k[0], t);
+    stage.set_scaled_sum(state_0, &k[0], dt * 0.5);
+    evaluate_state(world, stage, &mut k[1], t + dt * 0.5);
+    state.set_scaled_sum(state_0, &k[1], dt);
 }
 
-fn rk4<T: Stateful>(world: &mut World, state: &StateMap<T>, t: f64, dt: f64) -> StateMap<T> {
-    let state_derivative = evaluate_state(world, &mut state.clone(), t);
-    let state_derivative2 = evaluate_state(
-        world,
-        &mut (state + &(&state_derivative * (dt * 0.5))),
-        t + dt * 0.5,
-    );
-    let state_derivative3 = evaluate_state(
-        world,
-        &mut (state + &(&state_derivative2 * (dt * 0.5))),
-        t + dt * 0.5,
-    );
-    let state_derivative4 =
-        evaluate_state(world, &mut (state + &(&state_derivative3 * dt)), t + dt);
-    let state_change = &(&(&state_derivative + &(&state_derivative2 * 2.))
-        + &(&state_derivative3 * 2.))
-        + &state_derivative4;
-    state + &(&state_change * (dt / 6.))
+fn rk4<T: Stateful>(world: &mut World, buffers: &mut SolverBuffers<T>, t: f64, dt: f64) {
+    let SolverBuffers {
+        state_0,
+        stage,
+        k,
+        sum,
+        state,
+    } = buffers;
+    evaluate_state(world, state_0, &mut k[0], t);
+    stage.set_scaled_sum(state_0, &k[0], dt * 0.5);
+    evaluate_state(world, stage, &mut k[1], t + dt * 0.5);
+    stage.set_scaled_sum(state_0, &k[1], dt * 0.5);
+    evaluate_state(world, stage, &mut k[2], t + dt * 0.5);
+    stage.set_scaled_sum(state_0, &k[2], dt);
+    evaluate_state(world, stage, &mut k[3], t + dt);
+    sum.set_scaled_sum(&k[0], &k[1], 2.);
+    sum.add_scaled(&k[2], 2.);
+    sum.add_scaled(&k[3], 1.);
+    state.set_scaled_sum(state_0, sum, dt / 6.);
 }
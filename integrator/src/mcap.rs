@@ -0,0 +1,255 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+const MAGIC: [u8; 8] = [0x89, b'M', b'C', b'A', b'P', b'0', b'\r', b'\n'];
+const CHUNK_SIZE: usize = 1 << 20; // bytes of records before a chunk is written
+
+// record opcodes
+const HEADER: u8 = 0x01;
+const FOOTER: u8 = 0x02;
+const SCHEMA: u8 = 0x03;
+const CHANNEL: u8 = 0x04;
+const MESSAGE: u8 = 0x05;
+const CHUNK: u8 = 0x06;
+const MESSAGE_INDEX: u8 = 0x07;
+const CHUNK_INDEX: u8 = 0x08;
+const STATISTICS: u8 = 0x0b;
+const SUMMARY_OFFSET: u8 = 0x0e;
+const DATA_END: u8 = 0x0f;
+
+// Writer of MCAP files (mcap.dev), the log container of Foxglove and ROS 2 bags,
+// without the mcap crate.
+// Messages go in uncompressed chunks as they come, each chunk followed by the
+// index of its messages. `finish` adds the summary (schemas, channels,
+// statistics and chunk indexes) that readers seek with; a file that isn't
+// finished can still be read from the start. Times are in ns.
+pub struct McapWriter<W: Write> {
+    output: W,
+    position: u64,
+    schemas: Vec<Vec<u8>>, // records, repeated in the summary
+    channels: Vec<Vec<u8>>,
+    message_counts: BTreeMap<u16, u64>, // by channel
+    times: Option<[u64; 2]>,            // of the first and last message
+    chunk: Vec<u8>,
+    chunk_times: Option<[u64; 2]>,
+    chunk_messages: BTreeMap<u16, Vec<(u64, u64)>>, // time and offset in the chunk
+    chunk_indexes: Vec<Vec<u8>>,
+}
+
+fn put_string(buffer: &mut Vec<u8>, text: &str) {
+    buffer.extend((text.len() as u32).to_le_bytes());
+    buffer.extend(text.as_bytes());
+}
+
+fn record(opcode: u8, content: &[u8]) -> Vec<u8> {
+    let mut record = vec![opcode];
+    record.extend((content.len() as u64).to_le_bytes());
+    record.extend(content);
+    record
+}
+
+impl McapWriter<BufWriter<File>> {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> McapWriter<W> {
+    pub fn new(output: W) -> io::Result<Self> {
+        let mut writer = Self {
+            output,
+            position: 0,
+            schemas: Vec::new(),
+            channels: Vec::new(),
+            message_counts: BTreeMap::new(),
+            times: None,
+            chunk: Vec::new(),
+            chunk_times: None,
+            chunk_messages: BTreeMap::new(),
+            chunk_indexes: Vec::new(),
+        };
+        let mut header = Vec::new();
+        put_string(&mut header, ""); // profile
+        put_string(&mut header, "bevy_car_demo"); // library
+        writer.write(&MAGIC)?;
+        writer.write(&record(HEADER, &header))?;
+        Ok(writer)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    // schema of the messages of a channel, e.g. a JSON schema ("jsonschema"),
+    // returns its id
+    pub fn add_schema(&mut self, name: &str, encoding: &str, data: &[u8]) -> io::Result<u16> {
+        let id = self.schemas.len() as u16 + 1; // 0 is no schema
+        let mut content = id.to_le_bytes().to_vec();
+        put_string(&mut content, name);
+        put_string(&mut content, encoding);
+        content.extend((data.len() as u32).to_le_bytes());
+        content.extend(data);
+        let record = record(SCHEMA, &content);
+        // before the messages, which wait in the chunk
+        self.write(&record)?;
+        self.schemas.push(record);
+        Ok(id)
+    }
+
+    // topic with the message encoding, e.g. "json", and schema (0 for none),
+    // returns its id
+    pub fn add_channel(&mut self, topic: &str, schema: u16, encoding: &str) -> io::Result<u16> {
+        let id = self.channels.len() as u16;
+        let mut content = id.to_le_bytes().to_vec();
+        content.extend(schema.to_le_bytes());
+        put_string(&mut content, topic);
+        put_string(&mut content, encoding);
+        content.extend(0_u32.to_le_bytes()); // no metadata
+        let record = record(CHANNEL, &content);
+        self.write(&record)?;
+        self.channels.push(record);
+        Ok(id)
+    }
+
+    pub fn write_message(&mut self, channel: u16, time: u64, data: &[u8]) -> io::Result<()> {
+        let count = self.message_counts.entry(channel).or_insert(0);
+        let mut content = channel.to_le_bytes().to_vec();
+        content.extend((*count as u32).to_le_bytes()); // sequence
+        content.extend(time.to_le_bytes()); // log time
+        content.extend(time.to_le_bytes()); // publish time
+        content.extend(data);
+        *count += 1;
+
+        let offset = self.chunk.len() as u64;
+        self.chunk_messages
+            .entry(channel)
+            .or_default()
+            .push((time, offset));
+        self.chunk.extend(record(MESSAGE, &content));
+        self.chunk_times = Some(match self.chunk_times {
+            Some([start, end]) => [start.min(time), end.max(time)],
+            None => [time, time],
+        });
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.write_chunk()?;
+        }
+        Ok(())
+    }
+
+    fn write_chunk(&mut self) -> io::Result<()> {
+        let [start, end] = match self.chunk_times.take() {
+            Some(times) => times,
+            None => return Ok(()),
+        };
+        self.times = Some(match self.times {
+            Some([first, last]) => [first.min(start), last.max(end)],
+            None => [start, end],
+        });
+        let records = std::mem::take(&mut self.chunk);
+        let size = (records.len() as u64).to_le_bytes();
+
+        let chunk_start = self.position;
+        let mut chunk = Vec::new();
+        chunk.extend(start.to_le_bytes());
+        chunk.extend(end.to_le_bytes());
+        chunk.extend(size);
+        chunk.extend(crc32fast::hash(&records).to_le_bytes());
+        put_string(&mut chunk, ""); // not compressed
+        chunk.extend(size);
+        chunk.extend(&records);
+        self.write(&record(CHUNK, &chunk))?;
+        let chunk_length = self.position - chunk_start;
+
+        // the message index of each channel in the chunk
+        let index_start = self.position;
+        let mut index_offsets = Vec::new();
+        for (channel, messages) in std::mem::take(&mut self.chunk_messages) {
+            index_offsets.extend(channel.to_le_bytes());
+            index_offsets.extend(self.position.to_le_bytes());
+            let mut index = channel.to_le_bytes().to_vec();
+            index.extend((16 * messages.len() as u32).to_le_bytes());
+            for (time, offset) in messages {
+                index.extend(time.to_le_bytes());
+                index.extend(offset.to_le_bytes());
+            }
+            self.write(&record(MESSAGE_INDEX, &index))?;
+        }
+
+        let mut chunk_index = Vec::new();
+        chunk_index.extend(start.to_le_bytes());
+        chunk_index.extend(end.to_le_bytes());
+        chunk_index.extend(chunk_start.to_le_bytes());
+        chunk_index.extend(chunk_length.to_le_bytes());
+        chunk_index.extend((index_offsets.len() as u32).to_le_bytes());
+        chunk_index.extend(index_offsets);
+        chunk_index.extend((self.position - index_start).to_le_bytes());
+        put_string(&mut chunk_index, "");
+        chunk_index.extend(size); // compressed
+        chunk_index.extend(size); // uncompressed
+        self.chunk_indexes.push(record(CHUNK_INDEX, &chunk_index));
+        Ok(())
+    }
+
+    // writes the last chunk, the summary and the footer
+    pub fn finish(mut self) -> io::Result<()> {
+        self.write_chunk()?;
+        self.write(&record(DATA_END, &0_u32.to_le_bytes()))?; // no CRC
+
+        let [start, end] = self.times.unwrap_or([0, 0]);
+        let mut statistics = Vec::new();
+        let messages: u64 = self.message_counts.values().sum();
+        statistics.extend(messages.to_le_bytes());
+        statistics.extend((self.schemas.len() as u16).to_le_bytes());
+        statistics.extend((self.channels.len() as u32).to_le_bytes());
+        statistics.extend(0_u32.to_le_bytes()); // attachments
+        statistics.extend(0_u32.to_le_bytes()); // metadata
+        statistics.extend((self.chunk_indexes.len() as u32).to_le_bytes());
+        statistics.extend(start.to_le_bytes());
+        statistics.extend(end.to_le_bytes());
+        statistics.extend((10 * self.message_counts.len() as u32).to_le_bytes());
+        for (channel, count) in self.message_counts.iter() {
+            statistics.extend(channel.to_le_bytes());
+            statistics.extend(count.to_le_bytes());
+        }
+
+        // the summary, in groups of records of one kind
+        let summary_start = self.position;
+        let groups = [
+            (SCHEMA, std::mem::take(&mut self.schemas)),
+            (CHANNEL, std::mem::take(&mut self.channels)),
+            (STATISTICS, vec![record(STATISTICS, &statistics)]),
+            (CHUNK_INDEX, std::mem::take(&mut self.chunk_indexes)),
+        ];
+        let mut offsets = Vec::new();
+        for (opcode, records) in groups {
+            if records.is_empty() {
+                continue;
+            }
+            let group_start = self.position;
+            for record in records {
+                self.write(&record)?;
+            }
+            offsets.push((opcode, group_start, self.position - group_start));
+        }
+        let offsets_start = self.position;
+        for (opcode, group_start, group_length) in offsets {
+            let mut offset = vec![opcode];
+            offset.extend(group_start.to_le_bytes());
+            offset.extend(group_length.to_le_bytes());
+            self.write(&record(SUMMARY_OFFSET, &offset))?;
+        }
+
+        let mut footer = summary_start.to_le_bytes().to_vec();
+        footer.extend(offsets_start.to_le_bytes());
+        footer.extend(0_u32.to_le_bytes()); // no CRC
+        self.write(&record(FOOTER, &footer))?;
+        self.write(&MAGIC)?;
+        self.output.flush()
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use bevy::prelude::*;
+
+use crate::hdf5::{self, Attribute, Dataset, Group, Member};
+
+// Named channels recorded once every `decimation` time steps, e.g. telemetry to
+// write to a file after the run. Each recorded step starts with `begin_step`, then
+// values are added by channel name. A channel is created the first time it is
+// recorded, steps before that have no value (NaN).
+#[derive(Resource, Clone)]
+pub struct Recorder {
+    pub decimation: usize,
+    channels: Vec<String>,
+    index: HashMap<String, usize>,
+    units: HashMap<String, String>,
+    time: Vec<f64>,
+    rows: Vec<Vec<f64>>,
+    step: usize,
+}
+
+impl Recorder {
+    pub fn new(decimation: usize) -> Self {
+        Self {
+            decimation: decimation.max(1),
+            channels: Vec::new(),
+            index: HashMap::new(),
+            units: HashMap::new(),
+            time: Vec::new(),
+            rows: Vec::new(),
+            step: 0,
+        }
+    }
+
+    // starts a new time step, returns true if it is recorded
+    pub fn begin_step(&mut self, time: f64) -> bool {
+        let active = self.step % self.decimation == 0;
+        self.step += 1;
+        if active {
+            self.time.push(time);
+            self.rows.push(vec![f64::NAN; self.channels.len()]);
+        }
+        active
+    }
+
+    // value of the channel at the current step
+    pub fn record(&mut self, channel: &str, value: f64) {
+        let index = match self.index.get(channel) {
+            Some(index) => *index,
+            None => {
+                self.channels.push(channel.to_string());
+                self.index
+                    .insert(channel.to_string(), self.channels.len() - 1);
+                self.channels.len() - 1
+            }
+        };
+        if let Some(row) = self.rows.last_mut() {
+            if row.len() <= index {
+                row.resize(index + 1, f64::NAN);
+            }
+            row[index] = value;
+        }
+    }
+
+    // drops the recorded steps, the channels are kept
+    pub fn clear(&mut self) {
+        self.time.clear();
+        self.rows.clear();
+    }
+
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    // units of a channel, e.g. "m/s", kept with it in HDF5 files
+    pub fn set_units(&mut self, channel: &str, units: &str) {
+        self.units.insert(channel.to_string(), units.to_string());
+    }
+
+    pub fn units(&self, channel: &str) -> Option<&str> {
+        self.units.get(channel).map(|units| units.as_str())
+    }
+
+    pub fn time(&self) -> &[f64] {
+        &self.time
+    }
+
+    // time and values of the last recorded step, in the order of the channels.
+    // Channels after the end of the values have none.
+    pub fn last_step(&self) -> Option<(f64, &[f64])> {
+        Some((*self.time.last()?, self.rows.last()?))
+    }
+
+    // all recorded values of a channel, one per recorded step
+    pub fn channel(&self, channel: &str) -> Option<Vec<f64>> {
+        let index = *self.index.get(channel)?;
+        Some(
+            self.rows
+                .iter()
+                .map(|row| row.get(index).copied().unwrap_or(f64::NAN))
+                .collect(),
+        )
+    }
+
+    // [time, value] of a channel between the start and end times, without the
+    // steps that have no value
+    pub fn samples(&self, channel: &str, start: f64, end: f64) -> Vec<[f64; 2]> {
+        let index = match self.index.get(channel) {
+            Some(index) => *index,
+            None => return Vec::new(),
+        };
+        let first = self.time.partition_point(|&time| time < start);
+        let last = self.time.partition_point(|&time| time <= end);
+        (first..last)
+            .filter_map(|step| {
+                let value = self.rows[step].get(index).copied()?;
+                (!value.is_nan()).then_some([self.time[step], value])
+            })
+            .collect()
+    }
+
+    // one column per channel, time first. Missing values are left empty.
+    pub fn write_csv(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "time,{}", self.channels.join(","))?;
+        for (time, row) in self.time.iter().zip(&self.rows) {
+            write!(file, "{}", time)?;
+            for index in 0..self.channels.len() {
+                match row.get(index) {
+                    Some(value) if !value.is_nan() => write!(file, ",{}", value)?,
+                    _ => write!(file, ",")?,
+                }
+            }
+            writeln!(file)?;
+        }
+        file.flush()
+    }
+
+    // Adds the recorded steps to an HDF5 file (see `hdf5::write`) as a group named
+    // after the run, with the decimation and the number of steps. Each channel is
+    // a dataset with its name and units, in groups split at the dots of the name:
+    // "tire.fl.slip_ratio" is in "tire/fl". "time" is in the run group.
+    pub fn write_hdf5(&self, path: &Path, run: &str) -> std::io::Result<()> {
+        let text = |text: &str| Attribute::Text(text.to_string());
+        let mut group = Group::default()
+            .with_attribute("decimation", Attribute::Integer(self.decimation as i64))
+            .with_attribute("steps", Attribute::Integer(self.time.len() as i64));
+        let time = Dataset::new(self.time.clone()).with_attribute("units", text("s"));
+        let _ = group.insert(&["time"], Member::Dataset(time));
+        for channel in self.channels.iter() {
+            let values = self.channel(channel).unwrap_or_default();
+            let mut dataset = Dataset::new(values).with_attribute("channel", text(channel));
+            if let Some(units) = self.units(channel) {
+                dataset = dataset.with_attribute("units", text(units));
+            }
+            // the whole name when it doesn't split into groups, e.g. "a.b" beside "a.b.c"
+            let path: Vec<&str> = channel.split('.').collect();
+            if let Err(dataset) = group.insert(&path, Member::Dataset(dataset)) {
+                if group.insert(&[channel], dataset).is_err() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("channel {} can't be written to HDF5", channel),
+                    ));
+                }
+            }
+        }
+        hdf5::write(path, run, &group)
+    }
+}
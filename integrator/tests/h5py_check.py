@@ -0,0 +1,30 @@
+# Reads a file of the `runs_read_by_h5py` test with h5py, the HDF5 library
+# itself, and checks every run against the ones the test writes:
+# python3 integrator/tests/h5py_check.py runs.h5 200
+import sys
+
+import h5py
+import numpy as np
+
+path, count = sys.argv[1], int(sys.argv[2])
+with h5py.File(path, "r") as file:
+    names = [f"run_{index}" for index in range(count)]
+    assert sorted(file.keys()) == sorted(names), list(file.keys())
+    for index, name in enumerate(names):
+        run = file[name]
+        assert run.attrs["steps"] == index
+        driver = run.attrs["driver"]
+        assert (driver.decode() if isinstance(driver, bytes) else driver) == "test"
+
+        time = run["time"]
+        assert time.dtype == np.float64 and time.shape == (10,)
+        assert np.array_equal(time[()], 0.01 * np.arange(10))
+        assert time.attrs["units"] in ("s", b"s")
+
+        speed = run["chassis/speed"]
+        assert np.array_equal(speed[()], [index, -1.5, np.finfo(np.float64).max])
+        assert speed.attrs["gain"] == 0.25
+        assert speed.attrs["units"] in ("m/s", b"m/s")
+
+        assert run["empty"].shape == (0,)
+print(f"h5py {h5py.__version__} (HDF5 {h5py.version.hdf5_version}) read {count} runs")
@@ -0,0 +1,348 @@
+use std::{fs, io::ErrorKind, path::PathBuf};
+
+use bevy_integrator::hdf5::{self, Attribute, Dataset, Group, Member};
+
+// A reader of the files of the writer, following the HDF5 file format
+// specification on its own: version 2 superblock and object headers (checksums
+// checked, continuation chunks followed), compact links, contiguous datasets.
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Text(String),
+    Number(f64),
+    Integer(i64),
+}
+
+#[derive(Debug, PartialEq)]
+enum Object {
+    Group(Vec<(String, Value)>, Vec<(String, Object)>),
+    Dataset(Vec<(String, Value)>, Vec<f64>),
+}
+
+const UNDEFINED: u64 = u64::MAX;
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn read_file(path: &PathBuf) -> Object {
+    let file = fs::read(path).unwrap();
+    assert_eq!(
+        file[..8],
+        [0x89, b'H', b'D', b'F', b'\r', b'\n', 0x1a, b'\n']
+    );
+    assert_eq!(file[8], 2, "superblock version");
+    assert_eq!(
+        lookup3(&file[..44]),
+        u32_at(&file, 44),
+        "superblock checksum"
+    );
+    assert_eq!(u64_at(&file, 28), file.len() as u64, "end of file address");
+    read_object(&file, u64_at(&file, 36))
+}
+
+// the messages (type, data) of the object header, nil and continuation messages left out
+fn messages(file: &[u8], address: u64) -> Vec<(u8, &[u8])> {
+    let at = address as usize;
+    assert_eq!(&file[at..at + 6], b"OHDR\x02\x02");
+    let length = 10 + u32_at(file, at + 6) as usize + 4;
+    let mut chunks = vec![(at, length, 10)];
+    let mut messages = Vec::new();
+    while let Some((at, length, start)) = chunks.pop() {
+        let chunk = &file[at..at + length];
+        let end = length - 4;
+        assert_eq!(
+            lookup3(&chunk[..end]),
+            u32_at(chunk, end),
+            "header checksum"
+        );
+        let mut offset = start;
+        // a gap smaller than a message header may end the chunk
+        while offset + 4 <= end {
+            let message_type = chunk[offset];
+            let size = u16_at(chunk, offset + 1) as usize;
+            let data = &chunk[offset + 4..offset + 4 + size];
+            match message_type {
+                0x00 => (),
+                0x10 => {
+                    let next = u64_at(data, 0) as usize;
+                    assert_eq!(&file[next..next + 4], b"OCHK");
+                    chunks.push((next, u64_at(data, 8) as usize, 4));
+                }
+                _ => messages.push((message_type, data)),
+            }
+            offset += 4 + size;
+        }
+    }
+    messages
+}
+
+fn read_object(file: &[u8], address: u64) -> Object {
+    let messages = messages(file, address);
+    let attributes = messages
+        .iter()
+        .filter(|(message_type, _)| *message_type == 0x0c)
+        .map(|(_, data)| read_attribute(data))
+        .collect();
+    let find = |wanted: u8| {
+        let message = messages
+            .iter()
+            .find(|(message_type, _)| *message_type == wanted);
+        message.map(|(_, data)| *data)
+    };
+    match find(0x08) {
+        Some(layout) => {
+            assert_eq!(layout[..2], [3, 1], "contiguous layout");
+            let dataspace = find(0x01).unwrap();
+            let count = match dataspace[1] {
+                0 => 1,
+                _ => u64_at(dataspace, 4) as usize,
+            };
+            let data = u64_at(layout, 2);
+            assert_eq!(u64_at(layout, 10), 8 * count as u64);
+            let values = match data {
+                UNDEFINED => Vec::new(),
+                data => (0..count)
+                    .map(|index| f64::from_le_bytes(file_bytes(file, data, index)))
+                    .collect(),
+            };
+            Object::Dataset(attributes, values)
+        }
+        None => {
+            let members = messages
+                .iter()
+                .filter(|(message_type, _)| *message_type == 0x06)
+                .map(|(_, data)| {
+                    assert_eq!(data[..3], [1, 0x11, 1], "hard link, UTF-8 name");
+                    let length = u16_at(data, 3) as usize;
+                    let name = String::from_utf8(data[5..5 + length].to_vec()).unwrap();
+                    (name, read_object(file, u64_at(data, 5 + length)))
+                })
+                .collect();
+            Object::Group(attributes, members)
+        }
+    }
+}
+
+fn file_bytes(file: &[u8], data: u64, index: usize) -> [u8; 8] {
+    let at = data as usize + 8 * index;
+    file[at..at + 8].try_into().unwrap()
+}
+
+fn read_attribute(data: &[u8]) -> (String, Value) {
+    assert_eq!(data[0], 3, "attribute version");
+    let name_length = u16_at(data, 2) as usize;
+    let datatype_length = u16_at(data, 4) as usize;
+    let dataspace_length = u16_at(data, 6) as usize;
+    let name = &data[9..9 + name_length];
+    let name = String::from_utf8(name[..name_length - 1].to_vec()).unwrap();
+    let datatype = &data[9 + name_length..9 + name_length + datatype_length];
+    let value = &data[9 + name_length + datatype_length + dataspace_length..];
+    let size = u32_at(datatype, 4) as usize;
+    let value = match datatype[0] & 0x0f {
+        0 => Value::Integer(i64::from_le_bytes(value[..8].try_into().unwrap())),
+        1 => Value::Number(f64::from_le_bytes(value[..8].try_into().unwrap())),
+        3 => {
+            let text = value[..size].split(|byte| *byte == 0).next().unwrap();
+            Value::Text(String::from_utf8(text.to_vec()).unwrap())
+        }
+        class => panic!("unexpected datatype class {}", class),
+    };
+    (name, value)
+}
+
+// Bob Jenkins' lookup3 hashlittle, initial value 0
+fn lookup3(data: &[u8]) -> u32 {
+    let word = |bytes: &[u8]| u32::from_le_bytes(bytes[..4].try_into().unwrap());
+    let mut state = [0xdeadbeef_u32.wrapping_add(data.len() as u32); 3];
+    let mut blocks: Vec<[u8; 12]> = data
+        .chunks(12)
+        .map(|block| {
+            let mut padded = [0; 12];
+            padded[..block.len()].copy_from_slice(block);
+            padded
+        })
+        .collect();
+    let last = match blocks.pop() {
+        Some(last) => last,
+        None => return state[2],
+    };
+    for block in blocks {
+        for (index, value) in state.iter_mut().enumerate() {
+            *value = value.wrapping_add(word(&block[4 * index..]));
+        }
+        // mix: a, b and c are 0, 1 and 2
+        for (x, y, z, rotation) in [
+            (0, 2, 1, 4),
+            (1, 0, 2, 6),
+            (2, 1, 0, 8),
+            (0, 2, 1, 16),
+            (1, 0, 2, 19),
+            (2, 1, 0, 4),
+        ] {
+            state[x] = state[x].wrapping_sub(state[y]) ^ state[y].rotate_left(rotation);
+            state[y] = state[y].wrapping_add(state[z]);
+        }
+    }
+    for (index, value) in state.iter_mut().enumerate() {
+        *value = value.wrapping_add(word(&last[4 * index..]));
+    }
+    // final
+    for (x, y, rotation) in [
+        (2, 1, 14),
+        (0, 2, 11),
+        (1, 0, 25),
+        (2, 1, 16),
+        (0, 2, 4),
+        (1, 0, 14),
+        (2, 1, 24),
+    ] {
+        state[x] = (state[x] ^ state[y]).wrapping_sub(state[y].rotate_left(rotation));
+    }
+    state[2]
+}
+
+// what the reader should find for the group
+fn expected(group: &Group) -> Object {
+    let members = group.members.iter().map(|(name, member)| {
+        let object = match member {
+            Member::Group(group) => expected(group),
+            Member::Dataset(dataset) => {
+                Object::Dataset(values(&dataset.attributes), dataset.values.clone())
+            }
+        };
+        (name.clone(), object)
+    });
+    Object::Group(values(&group.attributes), members.collect())
+}
+
+fn values(attributes: &[(String, Attribute)]) -> Vec<(String, Value)> {
+    let value = |attribute: &Attribute| match attribute {
+        Attribute::Text(text) => Value::Text(text.clone()),
+        Attribute::Number(number) => Value::Number(*number),
+        Attribute::Integer(integer) => Value::Integer(*integer),
+    };
+    let attributes = attributes.iter();
+    attributes
+        .map(|(name, attribute)| (name.clone(), value(attribute)))
+        .collect()
+}
+
+fn run(index: usize) -> Group {
+    let text = |text: &str| Attribute::Text(text.to_string());
+    let mut group = Group::default()
+        .with_attribute("steps", Attribute::Integer(index as i64))
+        .with_attribute("driver", text("test"));
+    let time = (0..10).map(|step| 0.01 * step as f64).collect();
+    let time = Dataset::new(time).with_attribute("units", text("s"));
+    let speed = Dataset::new(vec![index as f64, -1.5, f64::MAX])
+        .with_attribute("gain", Attribute::Number(0.25))
+        .with_attribute("units", text("m/s"));
+    let members = [
+        (vec!["time"], time),
+        (vec!["chassis", "speed"], speed),
+        (vec!["empty"], Dataset::default()),
+    ];
+    for (path, dataset) in members {
+        assert!(group.insert(&path, Member::Dataset(dataset)).is_ok());
+    }
+    group
+}
+
+fn temporary(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("{}_{}.h5", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn lookup3_known_value() {
+    assert_eq!(lookup3(b""), 0xdeadbeef);
+    assert_eq!(lookup3(b"Four score and seven years ago"), 0x17770551);
+}
+
+#[test]
+fn runs_read_back() {
+    let path = temporary("hdf5_runs_read_back");
+    let runs: Vec<(String, Group)> = (1..=3)
+        .map(|index| (format!("run_{}", index), run(index)))
+        .collect();
+    for (name, group) in runs.iter() {
+        hdf5::write(&path, name, group).unwrap();
+    }
+    let root = read_file(&path);
+    let members = runs
+        .iter()
+        .map(|(name, group)| (name.clone(), expected(group)));
+    assert_eq!(root, Object::Group(Vec::new(), members.collect()));
+    let names: Vec<String> = runs.iter().map(|(name, _)| name.clone()).collect();
+    assert_eq!(hdf5::root_names(&path).unwrap(), names);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn runs_are_appended() {
+    let path = temporary("hdf5_runs_are_appended");
+    hdf5::write(&path, "run_0", &run(0)).unwrap();
+    let mut length = fs::metadata(&path).unwrap().len();
+    // many runs, past the free space of the root header
+    let mut growth = Vec::new();
+    for index in 1..200 {
+        hdf5::write(&path, &format!("run_{}", index), &run(index)).unwrap();
+        let new_length = fs::metadata(&path).unwrap().len();
+        growth.push(new_length - length);
+        length = new_length;
+    }
+    // each run adds its own objects, and now and then a root header chunk
+    let run_size = *growth.iter().min().unwrap();
+    let chunks = growth.iter().filter(|size| **size > run_size).count();
+    assert!((1..=4).contains(&chunks), "{} chunks added", chunks);
+    assert!(growth.iter().all(|size| *size < run_size + 8192));
+
+    let root = read_file(&path);
+    let members = (0..200).map(|index| (format!("run_{}", index), expected(&run(index))));
+    assert_eq!(root, Object::Group(Vec::new(), members.collect()));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn existing_name_is_kept() {
+    let path = temporary("hdf5_existing_name_is_kept");
+    hdf5::write(&path, "run_1", &run(1)).unwrap();
+    let before = fs::read(&path).unwrap();
+    let error = hdf5::write(&path, "run_1", &run(2)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+    assert_eq!(fs::read(&path).unwrap(), before);
+    fs::remove_file(&path).unwrap();
+}
+
+// The HDF5 library reads the file too, through h5py: tests/h5py_check.py
+#[test]
+#[ignore = "needs python3 with h5py: cargo test -p bevy_integrator -- --ignored"]
+fn runs_read_by_h5py() {
+    let path = temporary("hdf5_runs_read_by_h5py");
+    // past the free space of the root header, so it has continuation chunks
+    let count = 200;
+    for index in 0..count {
+        hdf5::write(&path, &format!("run_{}", index), &run(index)).unwrap();
+    }
+    let script = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/h5py_check.py");
+    let output = std::process::Command::new("python3")
+        .arg(script)
+        .arg(&path)
+        .arg(count.to_string())
+        .output()
+        .unwrap_or_else(|error| panic!("python3: {}", error));
+    let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+    assert!(output.status.success(), "{}", text(&output.stderr));
+    println!("{}", text(&output.stdout));
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+
+use bevy_integrator::mcap::McapWriter;
+
+// A reader of the files of the writer, following the MCAP specification on its
+// own: the records of the data section, the chunks and their message indexes,
+// and the summary that the footer and the summary offsets point to.
+
+const MAGIC: [u8; 8] = [0x89, b'M', b'C', b'A', b'P', b'0', b'\r', b'\n'];
+
+const HEADER: u8 = 0x01;
+const FOOTER: u8 = 0x02;
+const SCHEMA: u8 = 0x03;
+const CHANNEL: u8 = 0x04;
+const MESSAGE: u8 = 0x05;
+const CHUNK: u8 = 0x06;
+const MESSAGE_INDEX: u8 = 0x07;
+const CHUNK_INDEX: u8 = 0x08;
+const STATISTICS: u8 = 0x0b;
+const SUMMARY_OFFSET: u8 = 0x0e;
+const DATA_END: u8 = 0x0f;
+
+// reads the fields of a record in order
+struct Fields<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, at: 0 }
+    }
+
+    fn take(&mut self, length: usize) -> &'a [u8] {
+        let bytes = &self.bytes[self.at..self.at + length];
+        self.at += length;
+        bytes
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take(2).try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take(4).try_into().unwrap())
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take(8).try_into().unwrap())
+    }
+
+    fn string(&mut self) -> String {
+        let length = self.u32() as usize;
+        String::from_utf8(self.take(length).to_vec()).unwrap()
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        self.take(self.bytes.len() - self.at)
+    }
+
+    fn done(&self) -> bool {
+        self.at == self.bytes.len()
+    }
+}
+
+// the records (offset, opcode, content) from the offset to the end of the bytes
+fn records(bytes: &[u8], mut at: usize) -> Vec<(usize, u8, &[u8])> {
+    let mut records = Vec::new();
+    while at < bytes.len() {
+        let mut fields = Fields::new(&bytes[at..]);
+        let opcode = fields.u8();
+        let length = fields.u64() as usize;
+        records.push((at, opcode, fields.take(length)));
+        at += 9 + length;
+    }
+    records
+}
+
+#[derive(Debug, PartialEq)]
+struct Message {
+    channel: u16,
+    sequence: u32,
+    time: u64,
+    data: Vec<u8>,
+}
+
+fn message(content: &[u8]) -> Message {
+    let mut fields = Fields::new(content);
+    let channel = fields.u16();
+    let sequence = fields.u32();
+    let time = fields.u64();
+    assert_eq!(fields.u64(), time, "publish time");
+    let data = fields.rest().to_vec();
+    Message {
+        channel,
+        sequence,
+        time,
+        data,
+    }
+}
+
+// the messages of a chunk, checking its times, sizes and CRC, by offset in the chunk
+fn chunk_messages(content: &[u8]) -> BTreeMap<u64, Message> {
+    let mut fields = Fields::new(content);
+    let (start, end) = (fields.u64(), fields.u64());
+    let size = fields.u64();
+    let crc = fields.u32();
+    assert_eq!(fields.string(), "", "compression");
+    assert_eq!(fields.u64(), size);
+    let records_bytes = fields.rest();
+    assert_eq!(records_bytes.len() as u64, size);
+    assert_eq!(crc32fast::hash(records_bytes), crc, "chunk CRC");
+    let mut messages = BTreeMap::new();
+    for (offset, opcode, content) in records(records_bytes, 0) {
+        assert_eq!(opcode, MESSAGE);
+        let message = message(content);
+        assert!((start..=end).contains(&message.time));
+        messages.insert(offset as u64, message);
+    }
+    messages
+}
+
+// what a file has, read through its summary
+#[derive(Debug, Default)]
+struct File {
+    schemas: Vec<(u16, String, String, Vec<u8>)>,
+    channels: Vec<(u16, u16, String, String)>,
+    messages: Vec<Message>, // in the order of the chunk indexes and message offsets
+    statistics: Vec<u64>,   // message, schema, channel and chunk counts, start and end times
+    channel_counts: BTreeMap<u16, u64>,
+}
+
+fn read(bytes: &[u8]) -> File {
+    assert_eq!(bytes[..8], MAGIC);
+    assert_eq!(bytes[bytes.len() - 8..], MAGIC);
+    let all = records(&bytes[..bytes.len() - 8], 8);
+    let at = |offset: u64| {
+        let index = all.iter().position(|(at, _, _)| *at as u64 == offset);
+        all[index.unwrap_or_else(|| panic!("no record at {}", offset))]
+    };
+    let (_, opcode, header) = all[0];
+    assert_eq!(opcode, HEADER);
+    let mut header = Fields::new(header);
+    assert_eq!(header.string(), "");
+    assert_eq!(header.string(), "bevy_car_demo");
+
+    let (footer_at, opcode, footer) = *all.last().unwrap();
+    assert_eq!(opcode, FOOTER);
+    let mut footer = Fields::new(footer);
+    let (summary_start, offsets_start) = (footer.u64(), footer.u64());
+    assert_eq!(footer.u32(), 0, "summary CRC");
+    let data_end = all.iter().position(|(_, opcode, _)| *opcode == DATA_END);
+    let summary = &all[data_end.unwrap() + 1..all.len() - 1];
+    assert_eq!(summary[0].0 as u64, summary_start, "summary start");
+
+    // the summary offsets cover the groups of the summary, one opcode each
+    let offsets: Vec<_> = summary
+        .iter()
+        .filter(|(_, opcode, _)| *opcode == SUMMARY_OFFSET)
+        .collect();
+    assert_eq!(offsets[0].0 as u64, offsets_start, "summary offset start");
+    let mut group_end = summary_start;
+    let mut file = File::default();
+    let mut chunk_count = 0;
+    for (_, _, offset) in offsets {
+        let mut offset = Fields::new(offset);
+        let (opcode, group_start, group_length) = (offset.u8(), offset.u64(), offset.u64());
+        assert_eq!(group_start, group_end, "groups follow each other");
+        group_end = group_start + group_length;
+        let group = summary
+            .iter()
+            .filter(|(at, _, _)| (group_start..group_end).contains(&(*at as u64)));
+        for (_, record_opcode, content) in group {
+            assert_eq!(*record_opcode, opcode);
+            let mut fields = Fields::new(content);
+            match opcode {
+                SCHEMA => {
+                    let (id, name, encoding) = (fields.u16(), fields.string(), fields.string());
+                    let length = fields.u32() as usize;
+                    let data = fields.take(length).to_vec();
+                    file.schemas.push((id, name, encoding, data));
+                }
+                CHANNEL => {
+                    let (id, schema) = (fields.u16(), fields.u16());
+                    let (topic, encoding) = (fields.string(), fields.string());
+                    assert_eq!(fields.u32(), 0, "metadata");
+                    file.channels.push((id, schema, topic, encoding));
+                }
+                STATISTICS => {
+                    file.statistics.push(fields.u64());
+                    file.statistics.push(fields.u16() as u64);
+                    file.statistics.push(fields.u32() as u64);
+                    assert_eq!(
+                        (fields.u32(), fields.u32()),
+                        (0, 0),
+                        "attachments, metadata"
+                    );
+                    file.statistics.push(fields.u32() as u64);
+                    file.statistics.push(fields.u64());
+                    file.statistics.push(fields.u64());
+                    let length = fields.u32() as usize;
+                    let mut counts = Fields::new(fields.take(length));
+                    while !counts.done() {
+                        file.channel_counts.insert(counts.u16(), counts.u64());
+                    }
+                }
+                CHUNK_INDEX => {
+                    chunk_count += 1;
+                    let (start, end) = (fields.u64(), fields.u64());
+                    let (chunk_start, chunk_length) = (fields.u64(), fields.u64());
+                    let (chunk_at, opcode, chunk) = at(chunk_start);
+                    assert_eq!(opcode, CHUNK);
+                    assert_eq!(9 + chunk.len() as u64, chunk_length, "chunk length");
+                    let mut times = Fields::new(chunk);
+                    assert_eq!((times.u64(), times.u64()), (start, end), "chunk times");
+                    let mut messages = chunk_messages(chunk);
+
+                    // the message indexes follow the chunk, one per channel
+                    let length = fields.u32() as usize;
+                    let mut index_offsets = Fields::new(fields.take(length));
+                    let mut index_end = (chunk_at + 9 + chunk.len()) as u64;
+                    let mut indexed = Vec::new();
+                    while !index_offsets.done() {
+                        let (channel, index_at) = (index_offsets.u16(), index_offsets.u64());
+                        assert_eq!(index_at, index_end, "message index offset");
+                        let (_, opcode, index) = at(index_at);
+                        assert_eq!(opcode, MESSAGE_INDEX);
+                        index_end += 9 + index.len() as u64;
+                        let mut index = Fields::new(index);
+                        assert_eq!(index.u16(), channel);
+                        let length = index.u32() as usize;
+                        let mut entries = Fields::new(index.take(length));
+                        while !entries.done() {
+                            let (time, offset) = (entries.u64(), entries.u64());
+                            let message = messages.remove(&offset).expect("indexed message");
+                            assert_eq!((message.channel, message.time), (channel, time));
+                            indexed.push(message);
+                        }
+                    }
+                    assert!(messages.is_empty(), "every message indexed");
+                    let message_indexes_length = fields.u64();
+                    assert_eq!(
+                        message_indexes_length,
+                        index_end - (chunk_at + 9 + chunk.len()) as u64
+                    );
+                    assert_eq!(fields.string(), "", "compression");
+                    let (compressed, uncompressed) = (fields.u64(), fields.u64());
+                    assert_eq!(compressed, uncompressed);
+                    // in the order they were written
+                    indexed.sort_by_key(|message| (message.time, message.channel));
+                    file.messages.extend(indexed);
+                }
+                opcode => panic!("opcode {:#x} in the summary", opcode),
+            }
+            assert!(fields.done(), "record length");
+        }
+    }
+    assert_eq!(
+        group_end, offsets_start,
+        "the groups end at the summary offsets"
+    );
+    assert_eq!(
+        footer_at as u64,
+        offsets_start
+            + 26 * summary
+                .iter()
+                .filter(|(_, opcode, _)| *opcode == SUMMARY_OFFSET)
+                .count() as u64
+    );
+    assert_eq!(file.statistics[3], chunk_count, "chunk count");
+    file
+}
+
+#[test]
+fn messages_read_back() {
+    let mut bytes = Vec::new();
+    let mut writer = McapWriter::new(&mut bytes).unwrap();
+    let schema = writer
+        .add_schema("test.Sample", "jsonschema", b"{\"type\": \"object\"}")
+        .unwrap();
+    let samples = writer.add_channel("/samples", schema, "json").unwrap();
+    let raw = writer.add_channel("/raw", 0, "").unwrap();
+    // past the chunk size, so there are a few chunks
+    let mut written = Vec::new();
+    for step in 0..3000_u64 {
+        let time = 1_000_000 * step;
+        let sample = format!("{{\"step\": {}}}", step).into_bytes();
+        writer.write_message(samples, time, &sample).unwrap();
+        written.push((samples, step, time, sample));
+        let data = vec![step as u8; 1000];
+        writer.write_message(raw, time, &data).unwrap();
+        written.push((raw, step, time, data));
+    }
+    writer.finish().unwrap();
+
+    let file = read(&bytes);
+    assert_eq!(
+        file.schemas,
+        vec![(
+            schema,
+            "test.Sample".to_string(),
+            "jsonschema".to_string(),
+            b"{\"type\": \"object\"}".to_vec()
+        )]
+    );
+    assert_eq!(
+        file.channels,
+        vec![
+            (samples, schema, "/samples".to_string(), "json".to_string()),
+            (raw, 0, "/raw".to_string(), "".to_string()),
+        ]
+    );
+    let expected: Vec<Message> = written
+        .into_iter()
+        .map(|(channel, sequence, time, data)| Message {
+            channel,
+            sequence: sequence as u32,
+            time,
+            data,
+        })
+        .collect();
+    let chunks = file.statistics[3];
+    assert!((3..=5).contains(&chunks), "{} chunks", chunks);
+    assert_eq!(
+        file.statistics,
+        vec![6000, 1, 2, chunks, 0, 2999 * 1_000_000]
+    );
+    assert_eq!(
+        file.channel_counts,
+        BTreeMap::from([(samples, 3000), (raw, 3000)])
+    );
+    assert_eq!(file.messages, expected);
+}
+
+#[test]
+fn empty_file() {
+    let mut bytes = Vec::new();
+    McapWriter::new(&mut bytes).unwrap().finish().unwrap();
+    let file = read(&bytes);
+    assert!(file.schemas.is_empty() && file.channels.is_empty() && file.messages.is_empty());
+    assert_eq!(file.statistics, vec![0, 0, 0, 0, 0, 0]);
+}
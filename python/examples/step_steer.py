@@ -0,0 +1,28 @@
+# Step steer of the car from Python: drives straight, then steers and records
+# the yaw rate. Build the module first: `maturin develop --release` in python/
+import numpy as np
+
+import bevy_car
+
+sim = bevy_car.Simulation("car", dt=0.002)
+print("joints:", ", ".join(sim.joint_names()))
+
+# settle on the suspension, then accelerate
+sim.step(500)
+sim.set_control(throttle=0.6)
+sim.step(2500)
+
+# hold the speed and steer left
+sim.set_control(throttle=0.3, steering=0.3)
+yaw_rate = []
+for _ in range(200):
+    sim.step(10)
+    yaw_rate.append(sim.telemetry()["chassis.yaw_rate"])
+
+q, qd = sim.joint_states()
+print(f"time {sim.time:.2f} s, speed {sim.telemetry()['chassis.vx']:.1f} m/s")
+print(f"peak yaw rate {np.max(yaw_rate):.3f} rad/s")
+print(f"largest joint velocity {np.max(np.abs(qd)):.1f}")
+
+sim.reset()
+print(f"reset to {sim.time:.2f} s")
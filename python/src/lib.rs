@@ -0,0 +1,140 @@
+use bevy::prelude::*;
+use bevy_integrator::{recorder::Recorder, PhysicsState};
+use car::{headless::HeadlessCar, presets::Preset};
+use numpy::{IntoPyArray, PyArray1};
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
+use rigid_body::joint::Joint;
+
+// A headless car simulation on the circuit, stepped from Python. The car is
+// driven through its `CarControl` (`set_control`), the joint states and the
+// telemetry channels are read back as NumPy arrays.
+#[pyclass(unsendable)]
+struct Simulation {
+    car: HeadlessCar,
+    joints: Vec<Entity>, // every joint but the base, in a fixed order
+}
+
+#[pymethods]
+impl Simulation {
+    // vehicle preset (car, truck, kart, buggy, 6x6) and time step (s)
+    #[new]
+    #[pyo3(signature = (vehicle = "car", dt = 0.002))]
+    fn new(vehicle: &str, dt: f64) -> PyResult<Self> {
+        let preset = Preset::from_name(vehicle)
+            .ok_or_else(|| PyValueError::new_err(format!("unknown vehicle preset: {}", vehicle)))?;
+
+        let mut car = HeadlessCar::new(preset.build(), dt, 0.0);
+        car.app.insert_resource(Recorder::new(1));
+        let joints = car.joints();
+        Ok(Self { car, joints })
+    }
+
+    // simulation time (s)
+    #[getter]
+    fn time(&self) -> f64 {
+        self.car.time()
+    }
+
+    #[getter]
+    fn dt(&self) -> f64 {
+        self.car.dt()
+    }
+
+    // takes time steps, the telemetry then holds the recorded steps
+    #[pyo3(signature = (steps = 1))]
+    fn step(&mut self, steps: usize) {
+        self.car.app.world.resource_mut::<Recorder>().clear();
+        self.car.step(steps);
+    }
+
+    // spawns the car again, so it starts over from its initial state and time
+    // with the controls released
+    fn reset(&mut self) {
+        self.car.reset();
+        self.car.app.world.resource_mut::<Recorder>().clear();
+        // the respawned joints, in the same order
+        self.joints = self.car.joints();
+    }
+
+    // driver inputs, throttle, brake, handbrake and clutch from 0 to 1, steering
+    // from -1 (right) to 1 (left). The steering goes through the driver steering
+    // filter like a player's.
+    #[pyo3(signature = (throttle = 0., steering = 0., brake = 0., handbrake = 0., clutch = 0.))]
+    fn set_control(
+        &mut self,
+        throttle: f32,
+        steering: f32,
+        brake: f32,
+        handbrake: f32,
+        clutch: f32,
+    ) {
+        if let Some(mut control) = self.car.control() {
+            control.throttle = throttle.clamp(0., 1.);
+            control.steering_input = steering.clamp(-1., 1.);
+            control.brake = brake.clamp(0., 1.);
+            control.handbrake = handbrake.clamp(0., 1.);
+            control.clutch = clutch.clamp(0., 1.);
+        }
+    }
+
+    // names of the joints, in the order of `joint_states`
+    fn joint_names(&self) -> Vec<String> {
+        self.joints
+            .iter()
+            .map(|entity| match self.car.app.world.get::<Joint>(*entity) {
+                Some(joint) => joint.name.clone(),
+                None => String::new(),
+            })
+            .collect()
+    }
+
+    // positions and velocities of the joints
+    fn joint_states<'py>(&self, py: Python<'py>) -> (&'py PyArray1<f64>, &'py PyArray1<f64>) {
+        let states = self.car.app.world.get_resource::<PhysicsState<Joint>>();
+        let state = |entity| states.and_then(|states| states.states.get(entity));
+        let q = self
+            .joints
+            .iter()
+            .map(|entity| state(entity).map_or(f64::NAN, |s| s.q));
+        let qd = self
+            .joints
+            .iter()
+            .map(|entity| state(entity).map_or(f64::NAN, |s| s.qd));
+        (
+            q.collect::<Vec<_>>().into_pyarray(py),
+            qd.collect::<Vec<_>>().into_pyarray(py),
+        )
+    }
+
+    // telemetry channels at the end of the last `step`, e.g. "chassis.vx"
+    fn telemetry<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let recorder = self.car.app.world.resource::<Recorder>();
+        let telemetry = PyDict::new(py);
+        for channel in recorder.channels() {
+            let value = recorder
+                .channel(channel)
+                .and_then(|values| values.last().copied())
+                .unwrap_or(f64::NAN);
+            telemetry.set_item(channel, value)?;
+        }
+        Ok(telemetry)
+    }
+
+    // every telemetry channel over the steps of the last `step`, with "time"
+    fn telemetry_history<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let recorder = self.car.app.world.resource::<Recorder>();
+        let history = PyDict::new(py);
+        history.set_item("time", PyArray1::from_slice(py, recorder.time()))?;
+        for channel in recorder.channels() {
+            let values = recorder.channel(channel).unwrap_or_default();
+            history.set_item(channel, values.into_pyarray(py))?;
+        }
+        Ok(history)
+    }
+}
+
+#[pymodule]
+fn bevy_car(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<Simulation>()?;
+    Ok(())
+}
@@ -2,33 +2,34 @@ use std::f32::consts::PI;
 
 use bevy::prelude::*;
 
-use bevy_integrator::{PhysicsSchedule, PhysicsSet, SimTime, Solver};
+use bevy_integrator::{PhysicsSchedule, PhysicsSet};
 use cameras::camera_az_el::{self, camera_builder};
 use rigid_body::{
+    cli::{JointRecordPlugin, SimArgs},
     definitions::{MeshDef, MeshTypeDef, TransformDef},
     joint::{Base, Joint},
-    plugin::RigidBodyPlugin,
     sva::{Inertia, Matrix, Motion, Vector, Xform},
 };
 
 // Main function
 fn main() {
+    // simulation options from the command line (`rigid_body::cli::SimArgs`), e.g.
+    // cargo run --example 00_1dof -- solver=euler dt=0.01 end=5 headless record=00_1dof.csv
+    let args = SimArgs::from_args(0.002, Some(10.));
+
     // Create App
-    App::new()
-        .add_plugins(RigidBodyPlugin {
-            time: SimTime::new(0.002, 0.0, Some(10.)),
-            solver: Solver::RK4,
-            simulation_setup: vec![],
-            environment_setup: vec![camera_setup],
-            name: "example 00_1dof".to_string(),
-        })
+    let mut app = App::new();
+    app.add_plugins(args.plugin("example 00_1dof", vec![], vec![camera_setup]))
         .add_systems(
             PhysicsSchedule,
             (spring_damper_system,).in_set(PhysicsSet::Evaluate),
         )
         .add_systems(Startup, startup_system)
-        .add_systems(Startup, environment_startup_system)
-        .run();
+        .add_systems(Startup, environment_startup_system);
+    if let Some(path) = args.record {
+        app.add_plugins(JointRecordPlugin(path));
+    }
+    app.run();
 }
 
 pub fn camera_setup(app: &mut App) {
@@ -3,30 +3,30 @@ use std::f64::consts::PI as PI64;
 
 use bevy::prelude::*;
 
-use bevy_integrator::{SimTime, Solver};
 use cameras::camera_az_el::{self, camera_builder};
 use rigid_body::{
+    cli::{JointRecordPlugin, SimArgs},
     definitions::{MeshDef, MeshTypeDef, TransformDef},
     // forces::spring_damper_system,
     joint::{Base, Joint},
-    plugin::RigidBodyPlugin,
     sva::{Inertia, Matrix, Motion, Vector, Xform},
 };
 
 // Main function
 fn main() {
+    // simulation options from the command line (`rigid_body::cli::SimArgs`), e.g.
+    // cargo run --example 02_double_pendulum -- solver=euler dt=0.01 end=5 headless record=02_double_pendulum.csv
+    let args = SimArgs::from_args(0.002, Some(60.));
+
     // Create App
-    App::new()
-        .add_plugins(RigidBodyPlugin {
-            time: SimTime::new(0.002, 0.0, Some(60.)),
-            solver: Solver::RK4,
-            simulation_setup: vec![],
-            environment_setup: vec![camera_setup],
-            name: "example 02_double_pendulum".to_string(),
-        })
+    let mut app = App::new();
+    app.add_plugins(args.plugin("example 02_double_pendulum", vec![], vec![camera_setup]))
         .add_systems(Startup, startup_system)
-        .add_systems(Startup, environment_startup_system)
-        .run();
+        .add_systems(Startup, environment_startup_system);
+    if let Some(path) = args.record {
+        app.add_plugins(JointRecordPlugin(path));
+    }
+    app.run();
 }
 
 pub fn camera_setup(app: &mut App) {
@@ -0,0 +1,166 @@
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use bevy_integrator::{
+    integrator_schedule, recorder::Recorder, ExitEvent, SimSeed, SimTime, Solver,
+};
+
+use crate::{
+    graphics::{GraphicsQuality, GraphicsSettings},
+    joint::{Base, Joint},
+    plugin::RigidBodyPlugin,
+};
+
+// Simulation options of an example from its command line, to try a change
+// without editing `main()`:
+// - `solver=rk4`: `euler`, `heun`, `midpoint` or `rk4`
+// - `dt=0.001`: time step (s)
+// - `end=10`: end time (s), the app exits there
+// - `headless`: no window, the time steps run as fast as they can
+// - `multi_threaded`: the physics schedule runs a task per system, not on one
+//   thread (see `RigidBodyPlugin`)
+// - `record=run.csv`: where the example records its run (see `JointRecordPlugin`)
+// - `seed=42`: the `SimSeed` of the run's random processes, for the example to insert
+// - `graphics=low`: graphics quality, `low`, `medium`, `high` or a settings file
+//   (`graphics=graphics.toml`, see `GraphicsSettings`)
+// The defaults are the example's own.
+#[derive(Clone)]
+pub struct SimArgs {
+    pub solver: Solver,
+    pub dt: f64,
+    pub end_time: Option<f64>,
+    pub headless: bool,
+    pub single_threaded_physics: bool,
+    pub record: Option<PathBuf>,
+    pub seed: u64,
+    pub graphics: GraphicsSettings,
+}
+
+impl SimArgs {
+    pub fn new(dt: f64, end_time: Option<f64>) -> Self {
+        Self {
+            solver: Solver::RK4,
+            dt,
+            end_time,
+            headless: false,
+            single_threaded_physics: true,
+            record: None,
+            seed: SimSeed::default().0,
+            graphics: GraphicsSettings::default(),
+        }
+    }
+
+    // reads the option in `arg`, false if it is not a simulation option
+    pub fn parse(&mut self, arg: &str) -> Result<bool, String> {
+        let number = |value: &str| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite() && *value > 0.)
+                .ok_or_else(|| format!("invalid {}", arg))
+        };
+        if arg == "headless" {
+            self.headless = true;
+        } else if arg == "multi_threaded" {
+            self.single_threaded_physics = false;
+        } else if let Some(name) = arg.strip_prefix("solver=") {
+            self.solver =
+                Solver::from_name(name).ok_or_else(|| format!("unknown solver: {}", name))?;
+        } else if let Some(dt) = arg.strip_prefix("dt=") {
+            self.dt = number(dt)?;
+        } else if let Some(end_time) = arg.strip_prefix("end=") {
+            self.end_time = Some(number(end_time)?);
+        } else if let Some(path) = arg.strip_prefix("record=") {
+            self.record = Some(path.into());
+        } else if let Some(seed) = arg.strip_prefix("seed=") {
+            self.seed = seed.parse().map_err(|_| format!("invalid {}", arg))?;
+        } else if let Some(graphics) = arg.strip_prefix("graphics=") {
+            self.graphics = match GraphicsQuality::from_name(graphics) {
+                Some(quality) => quality.settings(),
+                None => GraphicsSettings::from_file(graphics.as_ref())?,
+            };
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    // the options in the command line arguments, panics on any other argument
+    pub fn from_args(dt: f64, end_time: Option<f64>) -> Self {
+        let mut args = Self::new(dt, end_time);
+        for arg in std::env::args().skip(1) {
+            match args.parse(&arg) {
+                Ok(true) => {}
+                Ok(false) => panic!("unknown argument: {}", arg),
+                Err(error) => panic!("{}", error),
+            }
+        }
+        args
+    }
+
+    pub fn plugin(
+        &self,
+        name: &str,
+        simulation_setup: Vec<fn(&mut App)>,
+        environment_setup: Vec<fn(&mut App)>,
+    ) -> RigidBodyPlugin {
+        RigidBodyPlugin {
+            time: SimTime::new(self.dt, 0.0, self.end_time),
+            solver: self.solver,
+            simulation_setup,
+            environment_setup,
+            name: name.to_string(),
+            headless: self.headless,
+            graphics: self.graphics.clone(),
+            single_threaded_physics: self.single_threaded_physics,
+        }
+    }
+}
+
+// Records the position and speed of every named joint but the base (`<joint>.q`, `<joint>.qd`)
+// each time step, and writes them to a CSV file at exit
+pub struct JointRecordPlugin(pub PathBuf);
+
+#[derive(Resource)]
+struct JointRecordFile(PathBuf);
+
+impl Plugin for JointRecordPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Recorder::new(1))
+            .insert_resource(JointRecordFile(self.0.clone()))
+            .add_systems(
+                FixedUpdate,
+                joint_record_system.after(integrator_schedule::<Joint>),
+            )
+            .add_systems(Last, joint_record_write_system);
+    }
+}
+
+fn joint_record_system(
+    time: Res<SimTime>,
+    mut recorder: ResMut<Recorder>,
+    joints: Query<&Joint, Without<Base>>,
+) {
+    if !recorder.begin_step(time.time()) {
+        return;
+    }
+    for joint in joints.iter().filter(|joint| !joint.name.is_empty()) {
+        recorder.record(&format!("{}.q", joint.name), joint.q);
+        recorder.record(&format!("{}.qd", joint.name), joint.qd);
+    }
+}
+
+fn joint_record_write_system(
+    mut exit: EventReader<ExitEvent>,
+    recorder: Res<Recorder>,
+    file: Res<JointRecordFile>,
+) {
+    if exit.is_empty() {
+        return;
+    }
+    exit.clear();
+    match recorder.write_csv(&file.0) {
+        Ok(()) => info!("joint states written to {}", file.0.display()),
+        Err(error) => warn!("writing joint states to {}: {}", file.0.display(), error),
+    }
+}
@@ -0,0 +1,115 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+    utils::Instant,
+};
+use bevy_integrator::{integrator_schedule, PhysicsSchedule, PhysicsSet};
+
+use crate::{
+    joint::Joint,
+    structure::{apply_external_forces, loop_1, loop_23},
+};
+
+// Physics timings as Bevy diagnostics, for the diagnostic overlays and
+// `LogDiagnosticsPlugin`:
+// - `physics_step`: wall time of a time step, all solver stages (ms)
+// - `aba_traversal`: wall time of the articulated body algorithm loops over the
+//   joint tree in a time step, the rest of the step is the force systems (ms)
+// - `physics_steps_per_frame`: time steps taken in the frame
+// Add it after `RigidBodyPlugin`, whose physics schedule it times.
+pub struct PhysicsDiagnosticsPlugin;
+
+impl PhysicsDiagnosticsPlugin {
+    pub const STEP_TIME: DiagnosticId =
+        DiagnosticId::from_u128(218837459066183204775423761935522046107);
+    pub const ABA_TIME: DiagnosticId =
+        DiagnosticId::from_u128(105927453807729015046251627712530198440);
+    pub const STEPS_PER_FRAME: DiagnosticId =
+        DiagnosticId::from_u128(297544108862493655401780436470731629985);
+}
+
+impl Plugin for PhysicsDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_diagnostic(
+            Diagnostic::new(Self::STEP_TIME, "physics_step", 20).with_suffix("ms"),
+        )
+        .register_diagnostic(Diagnostic::new(Self::ABA_TIME, "aba_traversal", 20).with_suffix("ms"))
+        .register_diagnostic(Diagnostic::new(
+            Self::STEPS_PER_FRAME,
+            "physics_steps_per_frame",
+            20,
+        ))
+        .init_resource::<PhysicsTimings>()
+        .add_systems(
+            FixedUpdate,
+            (
+                step_start_system.before(integrator_schedule::<Joint>),
+                step_end_system.after(integrator_schedule::<Joint>),
+            ),
+        )
+        .add_systems(
+            PhysicsSchedule,
+            (
+                (
+                    aba_start_system.before(loop_1),
+                    aba_end_system.after(loop_1),
+                )
+                    .in_set(PhysicsSet::Initialize),
+                (
+                    aba_start_system.before(apply_external_forces),
+                    aba_end_system.after(loop_23),
+                )
+                    .in_set(PhysicsSet::Finalize),
+            ),
+        )
+        .add_systems(Update, physics_diagnostics_system);
+    }
+}
+
+// timings accumulated over the steps of a frame, or taken on the physics
+// thread (`physics_thread`)
+#[derive(Resource, Default)]
+pub(crate) struct PhysicsTimings {
+    step_start: Option<Instant>,
+    aba_start: Option<Instant>,
+    pub(crate) steps: usize,
+    pub(crate) step_time: f64, // seconds
+    aba_time: f64,             // seconds
+}
+
+fn step_start_system(mut timings: ResMut<PhysicsTimings>) {
+    timings.step_start = Some(Instant::now());
+}
+
+fn step_end_system(mut timings: ResMut<PhysicsTimings>) {
+    if let Some(start) = timings.step_start.take() {
+        timings.step_time += start.elapsed().as_secs_f64();
+        timings.steps += 1;
+    }
+}
+
+fn aba_start_system(mut timings: ResMut<PhysicsTimings>) {
+    timings.aba_start = Some(Instant::now());
+}
+
+fn aba_end_system(mut timings: ResMut<PhysicsTimings>) {
+    if let Some(start) = timings.aba_start.take() {
+        timings.aba_time += start.elapsed().as_secs_f64();
+    }
+}
+
+fn physics_diagnostics_system(mut diagnostics: Diagnostics, mut timings: ResMut<PhysicsTimings>) {
+    let steps = timings.steps;
+    diagnostics.add_measurement(PhysicsDiagnosticsPlugin::STEPS_PER_FRAME, || steps as f64);
+    // frames without a step, e.g. paused, keep the last step timings
+    if steps > 0 {
+        let step_time = timings.step_time / steps as f64;
+        let aba_time = timings.aba_time / steps as f64;
+        diagnostics.add_measurement(PhysicsDiagnosticsPlugin::STEP_TIME, || step_time * 1000.);
+        // not timed on the physics thread
+        if timings.aba_time > 0. {
+            diagnostics.add_measurement(PhysicsDiagnosticsPlugin::ABA_TIME, || aba_time * 1000.);
+        }
+    }
+    *timings = PhysicsTimings::default();
+}
@@ -0,0 +1,198 @@
+use std::{fs, path::Path};
+
+use bevy::{
+    pbr::{CascadeShadowConfig, CascadeShadowConfigBuilder, DirectionalLightShadowMap},
+    prelude::*,
+    window::{PresentMode, PrimaryWindow},
+};
+use serde::Deserialize;
+
+// Quality presets of the graphics settings, `High` being the default
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GraphicsQuality {
+    Low,
+    Medium,
+    High,
+}
+
+impl GraphicsQuality {
+    pub const ALL: [GraphicsQuality; 3] = [
+        GraphicsQuality::Low,
+        GraphicsQuality::Medium,
+        GraphicsQuality::High,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GraphicsQuality::Low => "low",
+            GraphicsQuality::Medium => "medium",
+            GraphicsQuality::High => "high",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|quality| quality.name() == name)
+    }
+
+    pub fn settings(&self) -> GraphicsSettings {
+        match self {
+            GraphicsQuality::Low => GraphicsSettings {
+                shadow_map_size: 1024,
+                shadow_cascades: 1,
+                shadow_distance: 100.,
+                msaa: 1,
+                vsync: true,
+                resolution_scale: 2. / 3.,
+            },
+            GraphicsQuality::Medium => GraphicsSettings {
+                shadow_map_size: 2048,
+                shadow_cascades: 2,
+                shadow_distance: 200.,
+                msaa: 2,
+                vsync: true,
+                resolution_scale: 1.,
+            },
+            GraphicsQuality::High => GraphicsSettings {
+                shadow_map_size: 4096,
+                shadow_cascades: 4,
+                shadow_distance: 300.,
+                msaa: 4,
+                vsync: true,
+                resolution_scale: 1.,
+            },
+        }
+    }
+}
+
+// Rendering settings, to trade the look of the demo for frame rate on a weak
+// laptop. They are applied when they change (e.g. from the menu), to the
+// shadows of every directional light, the MSAA and the primary window. Read
+// from a TOML file, every value is optional and missing values keep the `High`
+// preset.
+#[derive(Resource, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    pub shadow_map_size: usize, // of each cascade (texels), a power of 2
+    pub shadow_cascades: usize, // more keeps the shadows sharp near and far
+    pub shadow_distance: f32,   // from the camera to the last shadow (m)
+    pub msaa: u32,              // samples per pixel: 1 (off), 2, 4 or 8
+    pub vsync: bool,            // caps the frame rate at the display's
+    pub resolution_scale: f32,  // window size, as a fraction of 1920x1080
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        GraphicsQuality::High.settings()
+    }
+}
+
+impl GraphicsSettings {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("reading {}: {}", path.display(), error))?;
+        let settings: Self = toml::from_str(&text)
+            .map_err(|error| format!("parsing {}: {}", path.display(), error))?;
+        settings
+            .validate()
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+        Ok(settings)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !self.shadow_map_size.is_power_of_two() {
+            return Err(format!(
+                "shadow_map_size {} is not a power of 2",
+                self.shadow_map_size
+            ));
+        }
+        if self.shadow_cascades == 0 {
+            return Err("shadow_cascades must be at least 1".to_string());
+        }
+        if !matches!(self.msaa, 1 | 2 | 4 | 8) {
+            return Err(format!("msaa {} is not 1, 2, 4 or 8", self.msaa));
+        }
+        let positive = |value: f32| value.is_finite() && value > 0.;
+        if !positive(self.resolution_scale) || !positive(self.shadow_distance) {
+            return Err("resolution_scale and shadow_distance must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    // the preset these settings are, if any
+    pub fn quality(&self) -> Option<GraphicsQuality> {
+        GraphicsQuality::ALL
+            .into_iter()
+            .find(|quality| quality.settings() == *self)
+    }
+
+    pub fn label(&self) -> &'static str {
+        self.quality().map_or("custom", |quality| quality.name())
+    }
+
+    pub fn msaa(&self) -> Msaa {
+        match self.msaa {
+            0 | 1 => Msaa::Off,
+            2 => Msaa::Sample2,
+            8 => Msaa::Sample8,
+            _ => Msaa::Sample4,
+        }
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        match self.vsync {
+            true => PresentMode::AutoVsync,
+            false => PresentMode::AutoNoVsync,
+        }
+    }
+
+    pub fn window_size(&self) -> Vec2 {
+        Vec2::new(1920., 1080.) * self.resolution_scale
+    }
+
+    pub fn cascade_shadow_config(&self) -> CascadeShadowConfig {
+        CascadeShadowConfigBuilder {
+            num_cascades: self.shadow_cascades.max(1),
+            minimum_distance: 1.,
+            maximum_distance: self.shadow_distance,
+            // a single cascade spans the whole distance
+            first_cascade_far_bound: match self.shadow_cascades {
+                0 | 1 => self.shadow_distance,
+                _ => (self.shadow_distance / 60.).max(1.5),
+            },
+            overlap_proportion: 0.3,
+        }
+        .into()
+    }
+}
+
+// applies the settings when they change, and to the lights spawned since. The
+// window is only resized by a change of the scale, so it keeps a size set by hand.
+pub fn graphics_settings_system(
+    settings: Res<GraphicsSettings>,
+    mut scale: Local<Option<f32>>,
+    mut msaa: ResMut<Msaa>,
+    mut shadow_map: ResMut<DirectionalLightShadowMap>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut lights: Query<(Ref<DirectionalLight>, &mut CascadeShadowConfig)>,
+) {
+    for (light, mut config) in lights.iter_mut() {
+        if settings.is_changed() || light.is_added() {
+            *config = settings.cascade_shadow_config();
+        }
+    }
+    if !settings.is_changed() {
+        return;
+    }
+    *msaa = settings.msaa();
+    shadow_map.size = settings.shadow_map_size;
+    // the window is created at the first scale
+    let resize = matches!(*scale, Some(previous) if previous != settings.resolution_scale);
+    *scale = Some(settings.resolution_scale);
+    for mut window in windows.iter_mut() {
+        window.present_mode = settings.present_mode();
+        if resize {
+            let size = settings.window_size();
+            window.resolution.set(size.x, size.y);
+        }
+    }
+}
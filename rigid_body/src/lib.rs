@@ -1,8 +1,13 @@
 pub mod algorithms;
+pub mod cli;
 pub mod definitions;
+pub mod diagnostics;
+pub mod graphics;
 pub mod joint;
 pub mod mesh;
+pub mod physics_thread;
 pub mod plugin;
 pub mod rendering;
 pub mod structure;
 pub mod sva;
+pub mod validation;
@@ -0,0 +1,514 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    app::{AppExit, MainScheduleOrder, RunFixedUpdateLoop},
+    ecs::system::EntityCommands,
+    prelude::*,
+};
+use bevy_integrator::{ExitEvent, PhysicsState, SimTime};
+
+use crate::{
+    diagnostics::PhysicsTimings,
+    joint::{Joint, JointState},
+    sva::{Force, Motion, Xform},
+};
+
+// simulated time the physics catches up at most, beyond it falls behind real time
+const MAX_LAG: f64 = 0.25;
+
+// The identity of an entity of the model in both worlds, numbered as the model
+// spawns, as entities differ between the worlds. Only the entities spawned with
+// one (`insert_physics_id`) are shared.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PhysicsId(pub u32);
+
+// the ids spawned in a world, and their entities
+#[derive(Resource, Default)]
+struct PhysicsIds {
+    next: u32,
+    entities: HashMap<PhysicsId, Entity>,
+}
+
+impl PhysicsIds {
+    fn entity(world: &World, id: PhysicsId) -> Option<Entity> {
+        let ids = world.get_resource::<PhysicsIds>()?;
+        ids.entities.get(&id).copied()
+    }
+}
+
+pub trait PhysicsIdCommands {
+    // gives the entity the next id of the world, once the commands apply, so
+    // the model has to spawn in the same order in both worlds
+    fn insert_physics_id(&mut self) -> &mut Self;
+}
+
+impl PhysicsIdCommands for EntityCommands<'_, '_, '_> {
+    fn insert_physics_id(&mut self) -> &mut Self {
+        let entity = self.id();
+        self.commands().add(move |world: &mut World| {
+            let mut ids = world.get_resource_or_insert_with(PhysicsIds::default);
+            let id = PhysicsId(ids.next);
+            ids.next += 1;
+            ids.entities.insert(id, entity);
+            if let Some(mut entity) = world.get_entity_mut(entity) {
+                entity.insert(id);
+            }
+        });
+        self
+    }
+}
+
+// A component of the physics world shown in the render world. After the time
+// steps of a frame its data is copied to the component of the entity with the
+// same `PhysicsId` in the render world.
+pub trait Mirrored: Component {
+    type Data: Send + 'static;
+    fn extract(&self) -> Self::Data;
+    fn apply(&mut self, data: &Self::Data);
+}
+
+// A component of the render world driving the physics world, e.g. the driver
+// inputs. It is sent every frame, and received before every time step up to
+// the next one, `fresh` on the first so requests are acted on once.
+pub trait PhysicsInput: Component {
+    type Data: Send + 'static;
+    fn send(&mut self) -> Self::Data;
+    fn receive(&mut self, data: &Self::Data, fresh: bool);
+}
+
+// the kinematics of a joint from its last time step
+pub struct JointFrame {
+    q: f64,
+    qd: f64,
+    qdd: f64,
+    xl: Xform,
+    xj: Xform,
+    x: Xform,
+    v: Motion,
+    vj: Motion,
+    c: Motion,
+    a: Motion,
+    tau: f64,
+    f_ext: Force,
+}
+
+impl Mirrored for Joint {
+    type Data = JointFrame;
+
+    fn extract(&self) -> JointFrame {
+        JointFrame {
+            q: self.q,
+            qd: self.qd,
+            qdd: self.qdd,
+            xl: self.xl,
+            xj: self.xj,
+            x: self.x,
+            v: self.v,
+            vj: self.vj,
+            c: self.c,
+            a: self.a,
+            tau: self.tau,
+            f_ext: self.f_ext,
+        }
+    }
+
+    fn apply(&mut self, frame: &JointFrame) {
+        self.q = frame.q;
+        self.qd = frame.qd;
+        self.qdd = frame.qdd;
+        self.xl = frame.xl;
+        self.xj = frame.xj;
+        self.x = frame.x;
+        self.v = frame.v;
+        self.vj = frame.vj;
+        self.c = frame.c;
+        self.a = frame.a;
+        self.tau = frame.tau;
+        self.f_ext = frame.f_ext;
+    }
+}
+
+type Data = Box<dyn Any + Send>;
+type Extract = fn(&mut World) -> Data;
+type Apply = fn(&mut World, &Data);
+type SendInput = fn(&mut World) -> Data;
+type ReceiveInput = fn(&mut World, &Data, bool);
+type Setup = Box<dyn FnOnce(&mut App) + Send>;
+
+// Runs the physics of the app on its own thread, pipelined with rendering, so a
+// slow time step doesn't drop a frame and a slow frame doesn't hold the physics
+// back. `setup` builds the physics app on the thread (a headless
+// `RigidBodyPlugin` and the model), which takes time steps in real time. This
+// app renders the same model without integrating it: it spawns it the same way,
+// and each frame its joints, and the `Mirrored` components added, take the
+// state of the latest time step, matched by their `PhysicsId`. The `PhysicsInput` components go the other
+// way. A change of the joint states or of the time in this app, e.g. a restart
+// from a menu, is sent to the physics, and pausing the time pauses it.
+pub struct PhysicsThreadPlugin {
+    setup: Mutex<Option<Setup>>,
+    mirrored: Vec<(Extract, Apply)>,
+    inputs: Vec<(SendInput, ReceiveInput)>,
+}
+
+impl PhysicsThreadPlugin {
+    pub fn new(setup: impl FnOnce(&mut App) + Send + 'static) -> Self {
+        Self {
+            setup: Mutex::new(Some(Box::new(setup))),
+            mirrored: vec![(extract::<Joint>, apply::<Joint>)],
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn with_mirrored<T: Mirrored>(mut self) -> Self {
+        self.mirrored.push((extract::<T>, apply::<T>));
+        self
+    }
+
+    pub fn with_input<T: PhysicsInput>(mut self) -> Self {
+        self.inputs.push((send_input::<T>, receive_input::<T>));
+        self
+    }
+}
+
+// The inputs are sent in this set of `Update`, for the app to order it after the
+// systems setting them
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct PhysicsThreadSet;
+
+// The physics thread, in the render app
+#[derive(Resource)]
+pub struct PhysicsThread {
+    messages: Sender<ToPhysics>,
+    frame: Arc<Mutex<PhysicsFrame>>,
+    thread: Option<JoinHandle<()>>,
+    mirrored: Vec<Apply>,
+    inputs: Vec<SendInput>,
+    paused: Option<bool>, // last sent
+}
+
+enum ToPhysics {
+    Inputs(Vec<Data>),
+    States(Vec<(PhysicsId, JointState)>, usize), // joint states, time step index
+    Pause(bool),
+    Stop,
+}
+
+// The state published after the time steps, swapped between the back buffer of
+// the physics thread and this front buffer, read by the render app
+#[derive(Default)]
+struct PhysicsFrame {
+    fresh: bool,           // not read yet
+    index: usize,          // time step
+    steps: usize,          // time steps since the last read
+    step_time: f64,        // wall time of those steps (s)
+    components: Vec<Data>, // of the mirrored components, in order, by id
+}
+
+impl Plugin for PhysicsThreadPlugin {
+    fn build(&self, app: &mut App) {
+        let setup = self
+            .setup
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take()
+            .expect("the physics thread is already started");
+        // the fixed time steps run on the thread, not in this app
+        app.world
+            .resource_mut::<MainScheduleOrder>()
+            .labels
+            .retain(|label| !(**label).eq(&RunFixedUpdateLoop));
+
+        let frame = Arc::new(Mutex::new(PhysicsFrame::default()));
+        let (messages, receiver) = mpsc::channel();
+        let extract: Vec<Extract> = self.mirrored.iter().map(|(extract, _)| *extract).collect();
+        let receive: Vec<ReceiveInput> = self.inputs.iter().map(|(_, receive)| *receive).collect();
+        let front = frame.clone();
+        let thread = thread::Builder::new()
+            .name("physics".to_string())
+            .spawn(move || {
+                let mut physics = App::new();
+                setup(&mut physics);
+                physics_loop(physics, &extract, &receive, &front, receiver);
+            })
+            .unwrap_or_else(|error| panic!("starting the physics thread: {}", error));
+
+        app.insert_resource(PhysicsThread {
+            messages,
+            frame,
+            thread: Some(thread),
+            mirrored: self.mirrored.iter().map(|(_, apply)| *apply).collect(),
+            inputs: self.inputs.iter().map(|(send, _)| *send).collect(),
+            paused: None,
+        })
+        .add_systems(PreUpdate, physics_frame_system)
+        .add_systems(Update, physics_input_system.in_set(PhysicsThreadSet))
+        .add_systems(Last, physics_stop_system);
+    }
+}
+
+fn extract<T: Mirrored>(world: &mut World) -> Data {
+    let data: Vec<(PhysicsId, T::Data)> = world
+        .query::<(&PhysicsId, &T)>()
+        .iter(world)
+        .map(|(id, component)| (*id, component.extract()))
+        .collect();
+    Box::new(data)
+}
+
+fn apply<T: Mirrored>(world: &mut World, data: &Data) {
+    let data = match data.downcast_ref::<Vec<(PhysicsId, T::Data)>>() {
+        Some(data) => data,
+        None => return,
+    };
+    // an id not spawned in this world yet is left out
+    for (id, data) in data {
+        let entity = PhysicsIds::entity(world, *id);
+        if let Some(mut component) = entity.and_then(|entity| world.get_mut::<T>(entity)) {
+            component.apply(data);
+        }
+    }
+}
+
+fn send_input<T: PhysicsInput>(world: &mut World) -> Data {
+    let data: Vec<(PhysicsId, T::Data)> = world
+        .query::<(&PhysicsId, &mut T)>()
+        .iter_mut(world)
+        .map(|(id, mut input)| (*id, input.send()))
+        .collect();
+    Box::new(data)
+}
+
+fn receive_input<T: PhysicsInput>(world: &mut World, data: &Data, fresh: bool) {
+    let data = match data.downcast_ref::<Vec<(PhysicsId, T::Data)>>() {
+        Some(data) => data,
+        None => return,
+    };
+    for (id, data) in data {
+        let entity = PhysicsIds::entity(world, *id);
+        if let Some(mut input) = entity.and_then(|entity| world.get_mut::<T>(entity)) {
+            input.receive(data, fresh);
+        }
+    }
+}
+
+// Takes the time steps due in real time, between the messages of the render
+// app, and publishes the state after them. It starts paused, until the render
+// app runs.
+fn physics_loop(
+    mut app: App,
+    extract: &[Extract],
+    receive: &[ReceiveInput],
+    front: &Mutex<PhysicsFrame>,
+    messages: Receiver<ToPhysics>,
+) {
+    app.finish();
+    app.cleanup();
+    // the first update runs the startup systems, then each update takes a time step
+    app.update();
+    let dt = app.world.resource::<SimTime>().dt;
+    let max_lag = (MAX_LAG / dt).ceil() as u64;
+
+    let mut clock = Instant::now(); // real time of step 0
+    let mut steps: u64 = 0; // taken since `clock`
+    let mut paused = true;
+    let mut inputs: Option<Vec<Data>> = None;
+    let mut fresh = false;
+    let mut back = PhysicsFrame::default();
+    loop {
+        let timeout = match paused {
+            true => Duration::from_millis(100),
+            false => (clock + Duration::from_secs_f64(dt * (steps + 1) as f64))
+                .saturating_duration_since(Instant::now()),
+        };
+        match messages.recv_timeout(timeout) {
+            Ok(ToPhysics::Inputs(data)) => {
+                inputs = Some(data);
+                fresh = true;
+            }
+            Ok(ToPhysics::States(states, index)) => set_states(&mut app.world, &states, index),
+            Ok(ToPhysics::Pause(pause)) => {
+                paused = pause;
+                clock = Instant::now();
+                steps = 0;
+            }
+            Ok(ToPhysics::Stop) | Err(RecvTimeoutError::Disconnected) => {
+                // writes what is kept for the exit, e.g. recordings
+                app.world.send_event(ExitEvent);
+                app.world.run_schedule(Last);
+                return;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        if paused {
+            continue;
+        }
+
+        let due = (clock.elapsed().as_secs_f64() / dt) as u64;
+        steps = steps.max(due.saturating_sub(max_lag));
+        if steps >= due {
+            continue;
+        }
+        let start = Instant::now();
+        let taken = (due - steps) as usize;
+        let mut exited = false;
+        while steps < due && !exited {
+            if let Some(inputs) = &inputs {
+                for (receive, data) in receive.iter().zip(inputs) {
+                    receive(&mut app.world, data, fresh);
+                }
+                fresh = false;
+            }
+            app.update();
+            steps += 1;
+            exited = !app.world.resource::<Events<AppExit>>().is_empty();
+        }
+
+        back.index = app.world.resource::<SimTime>().index;
+        back.steps = taken;
+        back.step_time = start.elapsed().as_secs_f64();
+        back.components.clear();
+        back.components
+            .extend(extract.iter().map(|extract| extract(&mut app.world)));
+        {
+            let mut front = front
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            // the steps of a frame the render app didn't read
+            if front.fresh {
+                back.steps += front.steps;
+                back.step_time += front.step_time;
+            }
+            back.fresh = true;
+            std::mem::swap(&mut *front, &mut back);
+        }
+        // the end time, the render app follows its time
+        if exited {
+            return;
+        }
+    }
+}
+
+fn set_states(world: &mut World, states: &[(PhysicsId, JointState)], index: usize) {
+    let entities: Vec<Option<Entity>> = states
+        .iter()
+        .map(|(id, _)| PhysicsIds::entity(world, *id))
+        .collect();
+    let mut physics_state = world.resource_mut::<PhysicsState<Joint>>();
+    for (entity, (_, state)) in entities.into_iter().zip(states) {
+        if let Some(entity) = entity {
+            physics_state.states.insert(entity, state.clone());
+        }
+    }
+    world.resource_mut::<SimTime>().index = index;
+}
+
+// takes the latest published state, without flagging the time and joint states
+// as changed, which are sent back to the physics when changed in this app
+fn physics_frame_system(world: &mut World) {
+    let finished = world.resource_scope(|world, mut thread: Mut<PhysicsThread>| {
+        {
+            let mut frame = thread
+                .frame
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if frame.fresh {
+                frame.fresh = false;
+                for (apply, data) in thread.mirrored.iter().zip(frame.components.iter()) {
+                    apply(world, data);
+                }
+                world
+                    .resource_mut::<SimTime>()
+                    .bypass_change_detection()
+                    .index = frame.index;
+                if let Some(mut timings) = world.get_resource_mut::<PhysicsTimings>() {
+                    timings.steps += frame.steps;
+                    timings.step_time += frame.step_time;
+                }
+            }
+        }
+        // the states of the joints changed since the last frame, in place
+        if world.contains_resource::<PhysicsState<Joint>>() {
+            world.resource_scope(|world, mut physics_state: Mut<PhysicsState<Joint>>| {
+                let states = &mut physics_state.bypass_change_detection().states;
+                let mut joints = world.query_filtered::<(Entity, &Joint), Changed<Joint>>();
+                for (entity, joint) in joints.iter(world) {
+                    states.insert(entity, JointState::new(joint.q, joint.qd));
+                }
+            });
+        }
+
+        let finished = thread
+            .thread
+            .as_ref()
+            .is_some_and(|thread| thread.is_finished());
+        if finished {
+            thread.thread = None;
+        }
+        finished
+    });
+    // at the end time or on an error, the app exits with the physics
+    if finished {
+        world.send_event(ExitEvent);
+    }
+}
+
+fn physics_input_system(world: &mut World) {
+    // e.g. a restart, a recovery or a loaded snapshot, and the initial states
+    let restart = world.is_resource_changed::<SimTime>()
+        || world.is_resource_changed::<PhysicsState<Joint>>();
+    let states = match restart {
+        true => {
+            let ids: Vec<(Entity, PhysicsId)> = world
+                .query_filtered::<(Entity, &PhysicsId), With<Joint>>()
+                .iter(world)
+                .map(|(entity, id)| (entity, *id))
+                .collect();
+            let physics_state = world.resource::<PhysicsState<Joint>>();
+            ids.into_iter()
+                .map(|(entity, id)| Some((id, physics_state.states.get(&entity)?.clone())))
+                .collect::<Option<Vec<(PhysicsId, JointState)>>>()
+        }
+        false => None,
+    };
+    let index = world.resource::<SimTime>().index;
+    let paused = world.resource::<Time>().is_paused();
+
+    world.resource_scope(|world, mut thread: Mut<PhysicsThread>| {
+        let inputs = thread.inputs.iter().map(|send| send(world)).collect();
+        let mut messages = vec![ToPhysics::Inputs(inputs)];
+        if let Some(states) = states {
+            messages.push(ToPhysics::States(states, index));
+        }
+        if thread.paused != Some(paused) {
+            thread.paused = Some(paused);
+            messages.push(ToPhysics::Pause(paused));
+        }
+        for message in messages {
+            // a stopped thread exits the app in `physics_frame_system`
+            let _ = thread.messages.send(message);
+        }
+    });
+}
+
+// stops the physics with the app, once it wrote what it keeps for the exit
+fn physics_stop_system(world: &mut World) {
+    if world.resource::<Events<ExitEvent>>().is_empty() {
+        return;
+    }
+    let mut thread = world.resource_mut::<PhysicsThread>();
+    let _ = thread.messages.send(ToPhysics::Stop);
+    if let Some(thread) = thread.thread.take() {
+        if thread.join().is_err() {
+            warn!("the physics thread panicked");
+        }
+    }
+}
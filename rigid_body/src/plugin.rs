@@ -1,17 +1,28 @@
 #![allow(dead_code)]
 
 use crate::{
+    graphics::{graphics_settings_system, GraphicsSettings},
     joint::{bevy_joint_positions, Joint},
     rendering::startup_rendering,
-    structure::{apply_external_forces, loop_1, loop_23},
+    structure::{apply_external_forces, joint_tree_system, loop_1, loop_23, JointTree},
+};
+use std::time::Duration;
+
+use bevy::{
+    app::AppExit, ecs::schedule::ExecutorKind, input::InputPlugin, log::LogPlugin, prelude::*,
+    time::TimeUpdateStrategy, utils::tracing::dispatcher,
 };
-use bevy::{app::AppExit, prelude::*};
 use bevy_integrator::{
-    initialize_state, integrator_schedule, ExitEvent, PhysicsSchedule, PhysicsScheduleExt, SimTime,
-    Solver,
+    initialize_state, integrator_schedule, ExitEvent, PhysicsSchedule, PhysicsScheduleExt, SimSeed,
+    SimTime, Solver,
 };
 use bevy_obj::ObjPlugin;
 
+// Inserted by an app that uses the escape key itself, e.g. for a pause menu,
+// instead of exiting on escape
+#[derive(Resource)]
+pub struct EscapeMenu;
+
 #[derive(Clone)]
 pub struct RigidBodyPlugin {
     pub time: SimTime,
@@ -19,14 +30,30 @@ pub struct RigidBodyPlugin {
     pub environment_setup: Vec<fn(&mut App)>,
     pub solver: Solver,
     pub name: String,
+    // no window, meshes or environment: the simulation takes one time step per
+    // update, as fast as it can, e.g. for batch runs and CI
+    pub headless: bool,
+    // shadows, MSAA, vsync and window size, when not headless
+    pub graphics: GraphicsSettings,
+    // Runs the physics schedule, which runs at every solver stage, on this
+    // thread instead of spawning a task per system, as the examples, the FMU and
+    // the Python bindings do. A time step of the car with RK4 then doesn't
+    // allocate (80 allocations otherwise) and takes about 550 instead of 670 µs
+    // (car/examples/allocations.rs, dev build, one core). Only measured on one
+    // core: the threaded executor (false) runs the systems of a stage in
+    // parallel on more, which may make up for its tasks with heavy systems,
+    // e.g. many tires on a mesh.
+    pub single_threaded_physics: bool,
 }
 
 impl RigidBodyPlugin {
     pub fn setup_physics_simulation(&self, app: &mut App) {
-        let schedule = create_physics_schedule();
+        let schedule = create_physics_schedule(self.single_threaded_physics);
         app.add_schedule(PhysicsSchedule, schedule)
             .insert_resource(self.time.clone())
             .insert_resource(self.solver)
+            .init_resource::<SimSeed>()
+            .init_resource::<JointTree>()
             .insert_resource(FixedTime::new_from_secs(self.time.dt as f32))
             .add_systems(FixedUpdate, integrator_schedule::<Joint>);
     }
@@ -39,39 +66,71 @@ impl Plugin for RigidBodyPlugin {
 
         app.add_systems(
             Update,
-            (time_exit_system, esc_exit_system, exit_system).chain(),
+            (
+                time_exit_system,
+                esc_exit_system.run_if(not(resource_exists::<EscapeMenu>())),
+                exit_system,
+            )
+                .chain(),
         );
 
         for setup in self.simulation_setup.iter() {
             setup(app);
         }
-        for setup in self.environment_setup.iter() {
-            setup(app);
-        }
+        if self.headless {
+            app.add_plugins((MinimalPlugins, InputPlugin))
+                .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+                    self.time.dt as f32,
+                )));
+            // unless the process logs already, e.g. the physics app of a physics thread
+            if !dispatcher::has_been_set() {
+                app.add_plugins(LogPlugin::default());
+            }
+        } else {
+            for setup in self.environment_setup.iter() {
+                setup(app);
+            }
+            let size = self.graphics.window_size();
 
-        app.add_plugins((
-            DefaultPlugins.build().set(WindowPlugin {
-                primary_window: Some(Window {
-                    resolution: (1920., 1080.).into(),
-                    title: self.name.clone(),
-                    resizable: true,
+            app.add_plugins((
+                DefaultPlugins.build().set(WindowPlugin {
+                    primary_window: Some(Window {
+                        resolution: (size.x, size.y).into(),
+                        present_mode: self.graphics.present_mode(),
+                        title: self.name.clone(),
+                        resizable: true,
+                        // in the browser the canvas fills the page element it is in
+                        #[cfg(target_arch = "wasm32")]
+                        fit_canvas_to_parent: true,
+                        ..default()
+                    }),
                     ..default()
                 }),
-                ..default()
-            }),
-            ObjPlugin,
-        ));
-        app.add_systems(PostStartup, startup_rendering)
-            .add_systems(Update, bevy_joint_positions);
-
-        app.add_systems(PostStartup, initialize_state::<Joint>);
+                ObjPlugin,
+            ));
+            app.insert_resource(self.graphics.clone())
+                .insert_resource(self.graphics.msaa())
+                .add_systems(PostStartup, startup_rendering)
+                .add_systems(Update, (bevy_joint_positions, graphics_settings_system));
+        }
+
+        // the joint positions and velocities hold from the start, before a time step
+        app.add_systems(
+            PostStartup,
+            (initialize_state::<Joint>, joint_tree_system, loop_1).chain(),
+        );
     }
 }
 
-fn create_physics_schedule() -> Schedule {
+fn create_physics_schedule(single_threaded: bool) -> Schedule {
     let mut physics_schedule = Schedule::new();
-    physics_schedule
-        .add_physics_systems::<Joint, _, _>((loop_1,), (apply_external_forces, loop_23).chain());
+    if single_threaded {
+        physics_schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+    }
+    physics_schedule.add_physics_systems::<Joint, _, _>(
+        (joint_tree_system, loop_1).chain(),
+        (apply_external_forces, loop_23).chain(),
+    );
 
     physics_schedule
 }
@@ -3,129 +3,96 @@ use bevy::prelude::*;
 
 use crate::algorithms::{apply_external_update, loop_1_update, loop_2_update, loop_3_update};
 
-pub fn loop_1(
-    base_query: Query<Entity, With<Base>>,
-    joint_children_query: Query<&Children, With<Joint>>,
-    mut joint_query: Query<&mut Joint>,
-) {
-    base_loop(
-        &base_query,
-        &joint_children_query,
-        &mut joint_query,
-        Some(loop_1_update),
-        None,
-    );
+// The joint tree as (parent, joint) pairs in the order of a depth first walk
+// from the bases: `outward` with each joint after its parent, `inward` with each
+// joint after its children. Rebuilt when the hierarchy changes, so the passes of
+// the time steps walk the pairs instead of looking up the children of every joint.
+#[derive(Resource, Default)]
+pub struct JointTree {
+    outward: Vec<(Entity, Entity)>,
+    inward: Vec<(Entity, Entity)>,
 }
 
-pub fn apply_external_forces(
-    base_query: Query<Entity, With<Base>>,
-    joint_children_query: Query<&Children, With<Joint>>,
-    mut joint_query: Query<&mut Joint>,
-) {
-    base_loop(
-        &base_query,
-        &joint_children_query,
-        &mut joint_query,
-        Some(apply_external_update),
-        None,
-    );
+impl JointTree {
+    fn walk(
+        &mut self,
+        parent_entity: Entity,
+        joint_entity: Entity,
+        joint_children_query: &Query<&Children, With<Joint>>,
+    ) {
+        self.outward.push((parent_entity, joint_entity));
+        if let Ok(children) = joint_children_query.get(joint_entity) {
+            for child_entity in children.iter() {
+                self.walk(joint_entity, *child_entity, joint_children_query);
+            }
+        }
+        self.inward.push((parent_entity, joint_entity));
+    }
 }
 
-pub fn loop_23(
+pub fn joint_tree_system(
+    mut tree: ResMut<JointTree>,
     base_query: Query<Entity, With<Base>>,
     joint_children_query: Query<&Children, With<Joint>>,
-    mut joint_query: Query<&mut Joint>,
+    added_query: Query<(), Added<Joint>>,
+    changed_query: Query<(), (With<Joint>, Changed<Children>)>,
+    mut removed_joints: RemovedComponents<Joint>,
+    mut removed_children: RemovedComponents<Children>,
 ) {
-    base_loop(
-        &base_query,
-        &joint_children_query,
-        &mut joint_query,
-        None,
-        Some(loop_2_update),
-    );
-
-    base_loop(
-        &base_query,
-        &joint_children_query,
-        &mut joint_query,
-        Some(loop_3_update),
-        None,
-    );
-}
+    let removed = removed_joints.iter().count() + removed_children.iter().count();
+    if added_query.is_empty() && changed_query.is_empty() && removed == 0 {
+        return;
+    }
 
-pub fn base_loop(
-    base_query: &Query<Entity, With<Base>>,
-    joint_children_query: &Query<&Children, With<Joint>>,
-    mut joint_query: &mut Query<&mut Joint>,
-    fn_out: Option<fn(&mut Joint, &Joint)>,
-    fn_in: Option<fn(&mut Joint, Option<&mut Joint>)>,
-) {
+    let tree = tree.as_mut();
+    tree.outward.clear();
+    tree.inward.clear();
     for base_entity in base_query.iter() {
         if let Ok(children) = joint_children_query.get(base_entity) {
             for child_entity in children.iter() {
-                recursive_loop(
-                    base_entity,
-                    &child_entity,
-                    &joint_children_query,
-                    &mut joint_query,
-                    fn_out,
-                    fn_in,
-                );
+                tree.walk(base_entity, *child_entity, &joint_children_query);
             }
         }
     }
 }
 
-pub fn recursive_loop(
-    parent_entity: Entity,
-    joint_entity: &Entity,
-    joint_children_query: &Query<&Children, With<Joint>>,
+pub fn loop_1(tree: Res<JointTree>, mut joint_query: Query<&mut Joint>) {
+    outward_pass(&tree, &mut joint_query, loop_1_update);
+}
+
+pub fn apply_external_forces(tree: Res<JointTree>, mut joint_query: Query<&mut Joint>) {
+    outward_pass(&tree, &mut joint_query, apply_external_update);
+}
+
+pub fn loop_23(tree: Res<JointTree>, mut joint_query: Query<&mut Joint>) {
+    inward_pass(&tree, &mut joint_query, loop_2_update);
+    outward_pass(&tree, &mut joint_query, loop_3_update);
+}
+
+// ordered from parent to child
+pub fn outward_pass(
+    tree: &JointTree,
     joint_query: &mut Query<&mut Joint>,
-    fn_out: Option<fn(&mut Joint, &Joint)>,
-    fn_in: Option<fn(&mut Joint, Option<&mut Joint>)>,
+    f: fn(&mut Joint, &Joint),
 ) {
-    match fn_out {
-        Some(f) => {
-            // get parent and joint
-            if let Ok([parent, mut joint]) =
-                joint_query.get_many_mut([parent_entity, *joint_entity])
-            {
-                // call fn_out - outward pass, ordered from parent to child
-                f(&mut joint, &parent);
-            }
-        }
-        None => (),
-    }
-
-    match joint_children_query.get(*joint_entity) {
-        Ok(children) => {
-            // joint has children. loop through them.
-            for child_entity in children.iter() {
-                recursive_loop(
-                    *joint_entity,
-                    child_entity,
-                    joint_children_query,
-                    joint_query,
-                    fn_out,
-                    fn_in,
-                );
-            }
-        }
-        Err(_e) => {
-            // joint has no children. This is fine. Do nothing.
+    for (parent_entity, joint_entity) in tree.outward.iter() {
+        if let Ok([parent, mut joint]) = joint_query.get_many_mut([*parent_entity, *joint_entity]) {
+            f(&mut joint, &parent);
         }
     }
+}
 
-    // get parent and joint
-    match fn_in {
-        Some(f) => {
-            if let Ok([mut parent, mut joint]) =
-                joint_query.get_many_mut([parent_entity, *joint_entity])
-            {
-                // call fn_in - inward pass, ordered from child to parent
-                f(&mut joint, Some(&mut parent));
-            }
+// ordered from child to parent
+pub fn inward_pass(
+    tree: &JointTree,
+    joint_query: &mut Query<&mut Joint>,
+    f: fn(&mut Joint, Option<&mut Joint>),
+) {
+    for (parent_entity, joint_entity) in tree.inward.iter() {
+        if let Ok([mut parent, mut joint]) =
+            joint_query.get_many_mut([*parent_entity, *joint_entity])
+        {
+            f(&mut joint, Some(&mut parent));
         }
-        None => (),
     }
 }
@@ -0,0 +1,289 @@
+use std::{collections::BTreeMap, f64::consts::PI};
+
+use bevy::prelude::*;
+use bevy_integrator::{integrator_schedule, PhysicsState, SimTime};
+
+use crate::{
+    cli::SimArgs,
+    joint::{Base, Joint, JointState},
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+const GRAVITY: f64 = 9.81;
+
+// A check of the simulation against a closed-form solution. `setup` adds the
+// model to the app, and systems recording the simulated and reference values of
+// the checked quantities to `ValidationSamples`. The case runs headless up to
+// `end_time`, and passes if the largest error of each quantity, relative to the
+// largest reference value, is within `tolerance`.
+#[derive(Clone)]
+pub struct ValidationCase {
+    pub name: String,
+    pub end_time: f64,
+    pub tolerance: f64,
+    pub setup: fn(&mut App),
+}
+
+impl ValidationCase {
+    pub fn new(name: &str, end_time: f64, tolerance: f64, setup: fn(&mut App)) -> Self {
+        Self {
+            name: name.to_string(),
+            end_time,
+            tolerance,
+            setup,
+        }
+    }
+
+    // runs the case with the solver and time step of `args` (the end time is the case's)
+    pub fn run(&self, args: &SimArgs) -> ValidationReport {
+        let mut args = args.clone();
+        args.end_time = Some(self.end_time);
+        args.headless = true;
+
+        let mut app = App::new();
+        app.add_plugins(args.plugin(&self.name, vec![self.setup], Vec::new()))
+            .init_resource::<ValidationSamples>();
+        app.finish();
+        app.cleanup();
+        // the first update runs the startup systems, then each update takes a time step
+        while !app.world.resource::<SimTime>().is_complete() {
+            app.update();
+        }
+
+        let samples = app
+            .world
+            .remove_resource::<ValidationSamples>()
+            .unwrap_or_default();
+        ValidationReport {
+            name: self.name.clone(),
+            tolerance: self.tolerance,
+            errors: samples.errors.into_iter().collect(),
+        }
+    }
+}
+
+// Error norms of the quantities of a validation case, by name
+#[derive(Resource, Default)]
+pub struct ValidationSamples {
+    errors: BTreeMap<String, ErrorNorms>,
+}
+
+impl ValidationSamples {
+    pub fn record(&mut self, quantity: &str, simulated: f64, reference: f64) {
+        let norms = match self.errors.get_mut(quantity) {
+            Some(norms) => norms,
+            None => self.errors.entry(quantity.to_string()).or_default(),
+        };
+        norms.add(simulated, reference);
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct ErrorNorms {
+    pub samples: usize,
+    pub max: f64,           // largest absolute error
+    pub max_reference: f64, // largest absolute reference value, the scale of the errors
+    sum_squared: f64,
+}
+
+impl ErrorNorms {
+    fn add(&mut self, simulated: f64, reference: f64) {
+        // a simulation that blew up fails
+        let error = match (simulated - reference).abs() {
+            error if error.is_nan() => f64::INFINITY,
+            error => error,
+        };
+        self.samples += 1;
+        self.max = self.max.max(error);
+        self.max_reference = self.max_reference.max(reference.abs());
+        self.sum_squared += error.powi(2);
+    }
+
+    pub fn rms(&self) -> f64 {
+        (self.sum_squared / self.samples.max(1) as f64).sqrt()
+    }
+
+    // largest error relative to the largest reference value
+    pub fn relative(&self) -> f64 {
+        self.max / self.max_reference.max(f64::EPSILON)
+    }
+}
+
+pub struct ValidationReport {
+    pub name: String,
+    pub tolerance: f64,
+    pub errors: Vec<(String, ErrorNorms)>,
+}
+
+impl ValidationReport {
+    // a case that recorded nothing fails
+    pub fn passed(&self) -> bool {
+        !self.errors.is_empty()
+            && self
+                .errors
+                .iter()
+                .all(|(_, norms)| norms.relative() <= self.tolerance)
+    }
+
+    pub fn log(&self) {
+        if self.errors.is_empty() {
+            warn!("{}: no samples recorded", self.name);
+        }
+        for (quantity, norms) in self.errors.iter() {
+            let message = format!(
+                "{} {}: max error {:.3e}, rms error {:.3e}, relative {:.3e} (tolerance {:.0e}, {} samples)",
+                self.name,
+                quantity,
+                norms.max,
+                norms.rms(),
+                norms.relative(),
+                self.tolerance,
+                norms.samples
+            );
+            if norms.relative() <= self.tolerance {
+                info!("{}", message);
+            } else {
+                warn!("{}", message);
+            }
+        }
+    }
+}
+
+// the cases of the multibody dynamics alone
+pub fn validation_cases() -> Vec<ValidationCase> {
+    vec![
+        ValidationCase::new("pendulum", 20., 1e-4, pendulum_setup),
+        ValidationCase::new("double_pendulum", 20., 1e-3, double_pendulum_setup),
+    ]
+}
+
+// the integrator state of the named joint
+pub fn joint_state(
+    states: &PhysicsState<Joint>,
+    joints: &Query<(Entity, &Joint)>,
+    name: &str,
+) -> Option<JointState> {
+    let (entity, _) = joints.iter().find(|(_, joint)| joint.name == name)?;
+    states.states.0.get(&entity).cloned()
+}
+
+// Uniform bar hinged at one end about the y axis, hanging along -z at zero angle
+struct Bar {
+    mass: f64,
+    length: f64,
+    width: f64,
+}
+
+const BAR: Bar = Bar {
+    mass: 1.,
+    length: 1.,
+    width: 0.05,
+};
+
+impl Bar {
+    // about the center, y axis
+    fn moi(&self) -> f64 {
+        self.mass * (self.width.powi(2) + self.length.powi(2)) / 12.
+    }
+
+    fn center(&self) -> f64 {
+        self.length / 2.
+    }
+
+    // center of mass and inertia about it, in the frame of the hinge
+    fn inertia(&self) -> Inertia {
+        let moi_z = self.mass * self.width.powi(2) / 6.;
+        Inertia::new(
+            self.mass,
+            Vector::new(0., 0., -self.center()),
+            Matrix::from_diagonal(&Vector::new(self.moi(), self.moi(), moi_z)),
+        )
+    }
+}
+
+// single pendulum released from horizontal: its energy stays the initial one
+fn pendulum_setup(app: &mut App) {
+    app.add_systems(Startup, pendulum_startup_system)
+        .add_systems(
+            FixedUpdate,
+            pendulum_energy_system.after(integrator_schedule::<Joint>),
+        );
+}
+
+fn pendulum_startup_system(mut commands: Commands) {
+    let base = Joint::base(Motion::new([0., 0., GRAVITY], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    let mut pendulum = Joint::ry("pendulum".to_string(), BAR.inertia(), Xform::identity());
+    pendulum.q = 0.5 * PI;
+    commands.spawn(pendulum).set_parent(base_id);
+}
+
+fn pendulum_energy_system(
+    states: Res<PhysicsState<Joint>>,
+    joints: Query<(Entity, &Joint)>,
+    mut samples: ResMut<ValidationSamples>,
+) {
+    let state = match joint_state(&states, &joints, "pendulum") {
+        Some(state) => state,
+        None => return,
+    };
+    // potential energy from the hanging position
+    let c = BAR.center();
+    let pivot_moi = BAR.moi() + BAR.mass * c.powi(2);
+    let energy =
+        |q: f64, qd: f64| 0.5 * pivot_moi * qd.powi(2) + BAR.mass * GRAVITY * c * (1. - q.cos());
+    samples.record("energy", energy(state.q, state.qd), energy(0.5 * PI, 0.));
+}
+
+// double pendulum released with both bars horizontal, chaotic but its energy stays
+// the initial one
+fn double_pendulum_setup(app: &mut App) {
+    app.add_systems(Startup, double_pendulum_startup_system)
+        .add_systems(
+            FixedUpdate,
+            double_pendulum_energy_system.after(integrator_schedule::<Joint>),
+        );
+}
+
+fn double_pendulum_startup_system(mut commands: Commands) {
+    let base = Joint::base(Motion::new([0., 0., GRAVITY], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    let mut upper = Joint::ry("upper".to_string(), BAR.inertia(), Xform::identity());
+    upper.q = 0.5 * PI;
+    let upper_id = commands.spawn(upper).set_parent(base_id).id();
+
+    let lower = Joint::ry("lower".to_string(), BAR.inertia(), Xform::posz(-BAR.length));
+    commands.spawn(lower).set_parent(upper_id);
+}
+
+fn double_pendulum_energy_system(
+    states: Res<PhysicsState<Joint>>,
+    joints: Query<(Entity, &Joint)>,
+    mut samples: ResMut<ValidationSamples>,
+) {
+    let (upper, lower) = match (
+        joint_state(&states, &joints, "upper"),
+        joint_state(&states, &joints, "lower"),
+    ) {
+        (Some(upper), Some(lower)) => (upper, lower),
+        _ => return,
+    };
+    // absolute angles and rates of the bars, potential energy from hanging down
+    let energy = |upper: &JointState, lower: &JointState| {
+        let (a1, w1) = (upper.q, upper.qd);
+        let (a2, w2) = (upper.q + lower.q, upper.qd + lower.qd);
+        let (m, l, c) = (BAR.mass, BAR.length, BAR.center());
+        let upper_speed_squared = (c * w1).powi(2);
+        let lower_speed_squared =
+            (l * w1).powi(2) + (c * w2).powi(2) + 2. * l * c * w1 * w2 * (a1 - a2).cos();
+        let kinetic = 0.5 * m * (upper_speed_squared + lower_speed_squared)
+            + 0.5 * BAR.moi() * (w1.powi(2) + w2.powi(2));
+        let potential =
+            m * GRAVITY * (c * (1. - a1.cos()) + l * (1. - a1.cos()) + c * (1. - a2.cos()));
+        kinetic + potential
+    };
+    let initial = energy(&JointState::new(0.5 * PI, 0.), &JointState::zero());
+    samples.record("energy", energy(&upper, &lower), initial);
+}